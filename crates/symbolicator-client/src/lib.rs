@@ -0,0 +1,454 @@
+//! A typed client for the HTTP API of Symbolicator.
+//!
+//! The [`Client`] submits requests, and long-polls Symbolicator until they complete. Requests
+//! which fail because Symbolicator is unreachable, overloaded, or draining are retried with an
+//! exponential backoff.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), symbolicator_client::Error> {
+//! use symbolicator_client::{Client, Scope, SymbolicateStacktraces};
+//!
+//! let client = Client::new("http://localhost:3021".parse().unwrap());
+//! let request = SymbolicateStacktraces::default();
+//! let response = client.symbolicate(&Scope::Global, &request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+use std::time::Duration;
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{multipart, RequestBuilder, StatusCode};
+use thiserror::Error;
+
+mod types;
+
+pub use symbolicator_service::types::{CompletedResponse, Scope};
+pub use symbolicator_sources::SourceConfig;
+pub use types::*;
+pub use url::Url;
+
+/// Errors returned by the [`Client`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The request could not be sent, or its response could not be read.
+    #[error("failed to send request to symbolicator")]
+    Http(#[from] reqwest::Error),
+    /// Symbolicator rejected the request.
+    #[error("symbolicator responded with status {0}")]
+    Status(StatusCode),
+    /// The polled request is not known to Symbolicator, or its response expired.
+    #[error("request {0} not found")]
+    NotFound(RequestId),
+    /// Symbolicator failed to process the request.
+    #[error("symbolication failed: {0}")]
+    Failed(String),
+    /// Symbolicator took too long to process the request.
+    #[error("symbolication took too long")]
+    Timeout,
+    /// The request was cancelled before it finished.
+    #[error("symbolication was cancelled")]
+    Cancelled,
+    /// Symbolicator ran into an internal error while processing the request.
+    #[error("symbolicator ran into an internal error")]
+    InternalError {
+        /// The ID of the error in the logs of Symbolicator.
+        reference_id: Option<String>,
+    },
+}
+
+/// Options of the [`Client`].
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// How often a request is retried after retryable failures.
+    ///
+    /// Submissions of new requests are only retried if they could not be sent, or if Symbolicator
+    /// rejected them with `503 Service Unavailable` and a `Retry-After` header, so that they are
+    /// never processed twice. Polls are also retried after timeouts and other server errors.
+    pub max_retries: u32,
+    /// The delay before the first retry, which doubles with each further retry.
+    ///
+    /// A `Retry-After` header sent by Symbolicator takes precedence.
+    pub retry_delay: Duration,
+    /// For how long Symbolicator holds on to a request or poll before responding that the request
+    /// is still pending.
+    pub poll_timeout: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+            poll_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A client of the Symbolicator HTTP API.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    url: Url,
+    options: ClientOptions,
+}
+
+impl Client {
+    /// Creates a client of the Symbolicator running at `url`, with the default options.
+    pub fn new(url: Url) -> Self {
+        Self::with_options(url, ClientOptions::default())
+    }
+
+    /// Creates a client of the Symbolicator running at `url`.
+    pub fn with_options(url: Url, options: ClientOptions) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url,
+            options,
+        }
+    }
+
+    /// Symbolicates stack traces, and waits for the response.
+    pub async fn symbolicate(
+        &self,
+        scope: &Scope,
+        request: &SymbolicateStacktraces,
+    ) -> Result<CompletedResponse, Error> {
+        let url = self.url("symbolicate");
+        let response = self
+            .send(false, || {
+                self.http
+                    .post(url.clone())
+                    .query(&self.query(scope))
+                    .json(request)
+            })
+            .await?;
+
+        self.wait(response).await
+    }
+
+    /// Uploads and processes a minidump, and waits for the response.
+    ///
+    /// If no `sources` are given, the sources configured in Symbolicator are used.
+    pub async fn upload_minidump(
+        &self,
+        scope: &Scope,
+        minidump: Vec<u8>,
+        sources: Option<&[SourceConfig]>,
+        options: &RequestOptions,
+    ) -> Result<CompletedResponse, Error> {
+        let sources = sources.map(serde_json::to_string).transpose();
+        let sources = sources.expect("source configs serialize to JSON");
+        let options = serde_json::to_string(options).expect("options serialize to JSON");
+
+        let url = self.url("minidump");
+        let response = self
+            .send(false, || {
+                let minidump = multipart::Part::bytes(minidump.clone()).file_name("minidump.dmp");
+                let mut form = multipart::Form::new()
+                    .part("upload_file_minidump", minidump)
+                    .text("options", options.clone());
+                if let Some(ref sources) = sources {
+                    form = form.text("sources", sources.clone());
+                }
+
+                self.http
+                    .post(url.clone())
+                    .query(&self.query(scope))
+                    .multipart(form)
+            })
+            .await?;
+
+        self.wait(response).await
+    }
+
+    /// Polls the response of a request once.
+    ///
+    /// This waits up to the configured `poll_timeout` for the request to complete. The `affinity`
+    /// token of the pending response routes the poll to the instance processing the request.
+    pub async fn poll(
+        &self,
+        request_id: RequestId,
+        affinity: Option<&str>,
+    ) -> Result<SymbolicationResponse, Error> {
+        let url = self.url(&format!("requests/{request_id}"));
+        let timeout = self.options.poll_timeout.as_secs().to_string();
+        let response = self
+            .send(true, || {
+                let mut query = vec![("timeout", timeout.as_str())];
+                query.extend(affinity.map(|affinity| ("affinity", affinity)));
+                self.http.get(url.clone()).query(&query)
+            })
+            .await;
+
+        match response {
+            Err(Error::Status(StatusCode::NOT_FOUND)) => Err(Error::NotFound(request_id)),
+            response => response,
+        }
+    }
+
+    /// Cancels a request which has not finished yet.
+    ///
+    /// Anyone polling the request receives a [`SymbolicationResponse::Cancelled`].
+    pub async fn cancel(&self, request_id: RequestId) -> Result<(), Error> {
+        let url = self.url(&format!("requests/{request_id}"));
+        let response = self.http.delete(url).send().await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND => Err(Error::NotFound(request_id)),
+            status => Err(Error::Status(status)),
+        }
+    }
+
+    /// Polls a pending request until it finishes.
+    async fn wait(&self, mut response: SymbolicationResponse) -> Result<CompletedResponse, Error> {
+        loop {
+            response = match response {
+                SymbolicationResponse::Pending {
+                    request_id,
+                    affinity,
+                    ..
+                } => self.poll(request_id, affinity.as_deref()).await?,
+                SymbolicationResponse::Completed(response) => return Ok(*response),
+                SymbolicationResponse::Failed { message } => return Err(Error::Failed(message)),
+                SymbolicationResponse::Timeout => return Err(Error::Timeout),
+                SymbolicationResponse::Cancelled => return Err(Error::Cancelled),
+                SymbolicationResponse::InternalError { reference_id } => {
+                    return Err(Error::InternalError { reference_id })
+                }
+            };
+        }
+    }
+
+    /// Sends the request built by `make_request`, retrying it after retryable failures.
+    ///
+    /// Unless the request is `idempotent`, it is only retried if Symbolicator has certainly not
+    /// processed it. The request is built anew for every attempt, as multipart bodies can not be
+    /// cloned.
+    async fn send<F>(
+        &self,
+        idempotent: bool,
+        make_request: F,
+    ) -> Result<SymbolicationResponse, Error>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let retry_after = match make_request().send().await {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response.json().await?);
+                }
+                Ok(response)
+                    if attempt < self.options.max_retries
+                        && is_retryable(&response, idempotent) =>
+                {
+                    let status = response.status();
+                    tracing::debug!(%status, attempt, "Retrying symbolicator request");
+                    retry_after(&response)
+                }
+                Ok(response) => return Err(Error::Status(response.status())),
+                // A request which timed out may still be processed, while a connection error
+                // means that it was never sent.
+                Err(err)
+                    if attempt < self.options.max_retries
+                        && (err.is_connect() || (idempotent && err.is_timeout())) =>
+                {
+                    tracing::debug!(error = %err, attempt, "Retrying symbolicator request");
+                    None
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let backoff = self.options.retry_delay * 2u32.pow(attempt);
+            tokio::time::sleep(retry_after.unwrap_or(backoff)).await;
+            attempt += 1;
+        }
+    }
+
+    fn url(&self, path: &str) -> Url {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("symbolicator URL can be a base")
+            .pop_if_empty()
+            .extend(path.split('/'));
+        url
+    }
+
+    fn query(&self, scope: &Scope) -> [(&'static str, String); 2] {
+        let timeout = self.options.poll_timeout.as_secs().to_string();
+        [("scope", scope.as_ref().to_owned()), ("timeout", timeout)]
+    }
+}
+
+/// Returns the delay requested by the `Retry-After` header of the response.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Whether a request rejected with this response can succeed when it is sent again.
+///
+/// Draining instances reject new requests with a `Retry-After` header before processing them, so
+/// these are retried for all requests. `idempotent` requests are also retried on other server
+/// errors, on rate limits, and if they were sent to the wrong instance.
+fn is_retryable(response: &reqwest::Response, idempotent: bool) -> bool {
+    let status = response.status();
+    if status == StatusCode::SERVICE_UNAVAILABLE && retry_after(response).is_some() {
+        return true;
+    }
+
+    idempotent
+        && (status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::MISDIRECTED_REQUEST)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use axum::extract::State;
+    use axum::http::StatusCode as AxumStatusCode;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::json;
+    use symbolicator_test::Server;
+
+    use super::*;
+
+    const REQUEST_ID: &str = "f3b2ee6a-a4b5-4b9a-9a8c-8e8b5c4f8b16";
+
+    fn client(server: &Server) -> Client {
+        let options = ClientOptions {
+            retry_delay: Duration::from_millis(10),
+            ..ClientOptions::default()
+        };
+        Client::with_options(server.url("/"), options)
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_polls_until_completed() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/symbolicate",
+                post(|| async {
+                    Json(json!({
+                        "status": "pending",
+                        "request_id": REQUEST_ID,
+                        "retry_after": 1,
+                    }))
+                }),
+            )
+            .route(
+                "/requests/:request_id",
+                get(|State(polls): State<Arc<AtomicUsize>>| async move {
+                    // The first poll fails as if the instance was overloaded.
+                    if polls.fetch_add(1, Ordering::Relaxed) == 0 {
+                        return Err(AxumStatusCode::SERVICE_UNAVAILABLE);
+                    }
+                    Ok(Json(
+                        json!({"status": "completed", "stacktraces": [], "modules": []}),
+                    ))
+                }),
+            )
+            .with_state(polls.clone());
+        let server = Server::with_router(router);
+
+        let response = client(&server)
+            .symbolicate(&Scope::Global, &SymbolicateStacktraces::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            response,
+            CompletedResponse::NativeSymbolication(_)
+        ));
+        assert_eq!(polls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_submission_retries() {
+        let submissions = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/symbolicate",
+                post(|State(submissions): State<Arc<AtomicUsize>>| async move {
+                    // The first submission is rejected as if the instance was draining.
+                    if submissions.fetch_add(1, Ordering::Relaxed) == 0 {
+                        return Err((AxumStatusCode::SERVICE_UNAVAILABLE, [("retry-after", "0")]));
+                    }
+                    Ok(Json(
+                        json!({"status": "completed", "stacktraces": [], "modules": []}),
+                    ))
+                }),
+            )
+            .route(
+                "/minidump",
+                post(|State(submissions): State<Arc<AtomicUsize>>| async move {
+                    submissions.fetch_add(1, Ordering::Relaxed);
+                    AxumStatusCode::INTERNAL_SERVER_ERROR
+                }),
+            )
+            .with_state(submissions.clone());
+        let server = Server::with_router(router);
+        let client = client(&server);
+
+        let response = client
+            .symbolicate(&Scope::Global, &SymbolicateStacktraces::default())
+            .await;
+        assert!(response.is_ok());
+        assert_eq!(submissions.swap(0, Ordering::Relaxed), 2);
+
+        // Symbolicator may have started processing the minidump, so it is not sent again.
+        let response = client
+            .upload_minidump(
+                &Scope::Global,
+                b"MDMP".to_vec(),
+                None,
+                &RequestOptions::default(),
+            )
+            .await;
+        assert!(matches!(
+            response,
+            Err(Error::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        ));
+        assert_eq!(submissions.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_request() {
+        let router = Router::new()
+            .route(
+                "/minidump",
+                post(|| async { Json(json!({"status": "failed", "message": "invalid minidump"})) }),
+            )
+            .route(
+                "/requests/:request_id",
+                get(|| async { AxumStatusCode::NOT_FOUND }),
+            );
+        let server = Server::with_router(router);
+        let client = client(&server);
+
+        let response = client
+            .upload_minidump(
+                &Scope::Global,
+                b"MDMP".to_vec(),
+                None,
+                &RequestOptions::default(),
+            )
+            .await;
+        assert!(matches!(response, Err(Error::Failed(message)) if message == "invalid minidump"));
+
+        let request_id = RequestId::new(REQUEST_ID.parse().unwrap());
+        let response = client.poll(request_id, None).await;
+        assert!(matches!(response, Err(Error::NotFound(id)) if id == request_id));
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
+
+use symbolicator_service::services::symbolication::SymbolicationProgress;
+use symbolicator_service::types::{
+    CompletedResponse, RawObjectInfo, RawStacktrace, Signal, SymbolicationOptions,
+};
+use symbolicator_sources::SourceConfig;
+
+/// The ID of a request accepted by Symbolicator, used to poll for its response.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[serde(transparent)]
+pub struct RequestId(Uuid);
+
+impl RequestId {
+    /// Creates a request ID from its UUID.
+    pub fn new(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The response of a symbolication or poll request.
+///
+/// See <https://getsentry.github.io/symbolicator/api/response/> for the documentation of the
+/// individual states.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SymbolicationResponse {
+    /// The request is still being processed.
+    Pending {
+        /// The ID with which the response can be polled.
+        request_id: RequestId,
+        /// The number of seconds after which the next poll would be suitable.
+        retry_after: usize,
+        /// The progress of the request, if it is reported by the endpoint.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        progress: Option<SymbolicationProgress>,
+        /// A token which routes polls to the instance processing the request.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        affinity: Option<String>,
+    },
+    /// The request completed.
+    Completed(Box<CompletedResponse>),
+    /// The request failed.
+    Failed {
+        /// A description of the failure.
+        message: String,
+    },
+    /// The request took too long to process.
+    Timeout,
+    /// The request was cancelled before it finished.
+    Cancelled,
+    /// Symbolicator ran into an internal error while processing the request.
+    InternalError {
+        /// The ID of the error in the logs of Symbolicator.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reference_id: Option<String>,
+    },
+}
+
+/// Common options of all requests, see [the API documentation] for their meaning.
+///
+/// This mirrors the options accepted by the server, which tests of the server check by
+/// round-tripping them through this type.
+///
+/// [the API documentation]: https://getsentry.github.io/symbolicator/api/symbolication/
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestOptions {
+    /// Whether to return detailed information on the candidates for each module's debug files.
+    #[serde(default)]
+    pub dif_candidates: bool,
+
+    /// Whether to return a trace of the decisions made while processing the request.
+    #[serde(default)]
+    pub capture_trace: bool,
+
+    /// For how many seconds the response can be polled after the request finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_ttl: Option<u64>,
+
+    /// The format of the response to completed native symbolication requests.
+    #[serde(default)]
+    pub output: OutputFormat,
+
+    /// A URL to which the response is sent once the request has finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<Url>,
+
+    /// The priority with which the request is started if it has to wait for others.
+    #[serde(default)]
+    pub priority: RequestPriority,
+
+    /// The URL of another Symbolicator to compare the response to native stack traces with.
+    ///
+    /// This must be one of the `comparison_targets` configured on the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compare_with: Option<Url>,
+
+    /// Options which control how frames are symbolicated.
+    #[serde(flatten)]
+    pub symbolication: SymbolicationOptions,
+}
+
+/// The format in which native symbolication responses are returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// The symbolication response.
+    #[default]
+    Symbolication,
+    /// A Sentry event payload.
+    SentryEvent,
+}
+
+/// The priority of a request, see [`RequestOptions::priority`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    /// For requests nobody is waiting for, such as reprocessing.
+    Low,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// For requests someone is actively waiting for.
+    High,
+}
+
+/// The body of a request to `/symbolicate`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SymbolicateStacktraces {
+    /// The signal thrown on certain operating systems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<Signal>,
+    /// The sources to load debug files from, instead of the ones configured in Symbolicator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The stack traces of the threads to symbolicate.
+    #[serde(default)]
+    pub stacktraces: Vec<RawStacktrace>,
+    /// The modules which were loaded into the process.
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    /// The options of the request.
+    #[serde(default)]
+    pub options: RequestOptions,
+}
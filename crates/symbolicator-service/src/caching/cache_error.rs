@@ -25,6 +25,11 @@ pub enum CacheError {
     /// The object could not be fetched from the remote source due to a timeout.
     #[error("download timed out after {0:?}")]
     Timeout(Duration),
+    /// The object could not be fetched because the remote source is rate limiting requests.
+    ///
+    /// The attached duration is how long the source asked to wait before it is tried again.
+    #[error("rate limited, retry after {0:?}")]
+    RateLimited(Duration),
     /// The object could not be fetched from the remote source due to another problem,
     /// like connection loss, DNS resolution, or a 5xx server response.
     ///
@@ -37,6 +42,24 @@ pub enum CacheError {
     /// during symcache conversion
     #[error("malformed: {0}")]
     Malformed(String),
+    /// The object was fetched successfully, but does not match the requested debug or code id.
+    ///
+    /// This usually happens with misconfigured sources, which serve a file of a different build
+    /// than the one that was requested. The attached string describes the mismatch.
+    #[error("id mismatch: {0}")]
+    IdMismatch(String),
+    /// The object was fetched successfully, but its contents do not match the requested checksum.
+    ///
+    /// This points to a truncated or otherwise corrupted download. The attached string describes
+    /// the mismatch.
+    #[error("checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+    /// The object was fetched successfully, but its source requires a valid signature, which is
+    /// missing or does not verify.
+    ///
+    /// The attached string describes why the signature was rejected.
+    #[error("invalid signature: {0}")]
+    SignatureInvalid(String),
     /// An unexpected error in symbolicator itself.
     ///
     /// This variant is not intended to be persisted to or read from caches.
@@ -62,7 +85,11 @@ impl CacheError {
     pub(super) const MALFORMED_MARKER: &[u8] = b"malformed";
     pub(super) const PERMISSION_DENIED_MARKER: &[u8] = b"permissiondenied";
     pub(super) const TIMEOUT_MARKER: &[u8] = b"timeout";
+    pub(super) const RATE_LIMITED_MARKER: &[u8] = b"ratelimited";
     pub(super) const DOWNLOAD_ERROR_MARKER: &[u8] = b"downloaderror";
+    pub(super) const ID_MISMATCH_MARKER: &[u8] = b"idmismatch";
+    pub(super) const CHECKSUM_MISMATCH_MARKER: &[u8] = b"checksummismatch";
+    pub(super) const SIGNATURE_INVALID_MARKER: &[u8] = b"signatureinvalid";
 
     /// Writes error markers and details to a file.
     ///
@@ -94,10 +121,27 @@ impl CacheError {
                 file.write_all(format_duration(*duration).to_string().as_bytes())
                     .await?;
             }
+            CacheError::RateLimited(duration) => {
+                file.write_all(Self::RATE_LIMITED_MARKER).await?;
+                file.write_all(format_duration(*duration).to_string().as_bytes())
+                    .await?;
+            }
             CacheError::DownloadError(details) => {
                 file.write_all(Self::DOWNLOAD_ERROR_MARKER).await?;
                 file.write_all(details.as_bytes()).await?;
             }
+            CacheError::IdMismatch(details) => {
+                file.write_all(Self::ID_MISMATCH_MARKER).await?;
+                file.write_all(details.as_bytes()).await?;
+            }
+            CacheError::ChecksumMismatch(details) => {
+                file.write_all(Self::CHECKSUM_MISMATCH_MARKER).await?;
+                file.write_all(details.as_bytes()).await?;
+            }
+            CacheError::SignatureInvalid(details) => {
+                file.write_all(Self::SIGNATURE_INVALID_MARKER).await?;
+                file.write_all(details.as_bytes()).await?;
+            }
             CacheError::InternalError => {
                 unreachable!("this was already handled above");
             }
@@ -127,9 +171,27 @@ impl CacheError {
                     Some(Self::InternalError)
                 }
             }
+        } else if let Some(raw_duration) = bytes.strip_prefix(Self::RATE_LIMITED_MARKER) {
+            let raw_duration = String::from_utf8_lossy(raw_duration);
+            match parse_duration(&raw_duration) {
+                Ok(duration) => Some(Self::RateLimited(duration)),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to read rate limit duration");
+                    Some(Self::InternalError)
+                }
+            }
         } else if let Some(raw_message) = bytes.strip_prefix(Self::DOWNLOAD_ERROR_MARKER) {
             let err_msg = String::from_utf8_lossy(raw_message);
             Some(Self::DownloadError(err_msg.into_owned()))
+        } else if let Some(raw_message) = bytes.strip_prefix(Self::ID_MISMATCH_MARKER) {
+            let err_msg = String::from_utf8_lossy(raw_message);
+            Some(Self::IdMismatch(err_msg.into_owned()))
+        } else if let Some(raw_message) = bytes.strip_prefix(Self::CHECKSUM_MISMATCH_MARKER) {
+            let err_msg = String::from_utf8_lossy(raw_message);
+            Some(Self::ChecksumMismatch(err_msg.into_owned()))
+        } else if let Some(raw_message) = bytes.strip_prefix(Self::SIGNATURE_INVALID_MARKER) {
+            let err_msg = String::from_utf8_lossy(raw_message);
+            Some(Self::SignatureInvalid(err_msg.into_owned()))
         } else if let Some(raw_message) = bytes.strip_prefix(Self::MALFORMED_MARKER) {
             let err_msg = String::from_utf8_lossy(raw_message);
             Some(Self::Malformed(err_msg.into_owned()))
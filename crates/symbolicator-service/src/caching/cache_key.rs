@@ -130,6 +130,7 @@ mod tests {
         let source = Arc::new(FilesystemSourceConfig {
             id: SourceId::new("foo"),
             path: PathBuf::new(),
+            index_ttl: None,
             files: Default::default(),
         });
         let location = SourceLocation::new("bar.baz");
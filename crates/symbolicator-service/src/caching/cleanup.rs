@@ -1,19 +1,117 @@
-use std::fs::{read_dir, remove_dir, remove_file};
 use std::io;
-use std::path::Path;
+use std::ops::AddAssign;
+use std::time::SystemTime;
 
 use anyhow::{anyhow, Result};
 
 use crate::config::Config;
 
-use super::fs::catch_not_found;
-use super::{Cache, Caches};
+use super::cache_error::cache_entry_from_bytes;
+use super::fs::{catch_not_found, expiration_strategy, ExpirationStrategy};
+use super::shared_cache::cleanup_results;
+use super::{Cache, CacheStorage, Caches, StoredFile};
 
 /// Entry function for the cleanup command.
 ///
-/// This will clean up all caches based on configured cache retention.
-pub fn cleanup(config: Config) -> Result<()> {
-    Caches::from_config(&config)?.cleanup()
+/// This will clean up all caches based on configured cache retention, as well as the expired
+/// responses in a filesystem `result_storage`.
+///
+/// If `dry_run` is set, no files are removed. Instead, a report of what would have been removed
+/// is logged for each cache.
+pub fn cleanup(config: Config, dry_run: bool) -> Result<()> {
+    let caches = Caches::from_config(&config)?.cleanup(dry_run);
+
+    if let Some(ref result_storage) = config.result_storage {
+        let removed = cleanup_results(result_storage, dry_run)?;
+        let verb = if dry_run { "Would remove" } else { "Removed" };
+        tracing::info!(
+            "{} {} expired results ({} bytes)",
+            verb,
+            removed.files,
+            removed.bytes
+        );
+    }
+
+    caches
+}
+
+/// The number of files and their accumulated size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileStats {
+    /// The number of files.
+    pub files: u64,
+    /// The total size of all files in bytes.
+    pub bytes: u64,
+}
+
+impl FileStats {
+    pub(super) fn add_file(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+impl AddAssign for FileStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.bytes += rhs.bytes;
+    }
+}
+
+/// Statistics about the files visited during the cleanup of a single [`Cache`].
+///
+/// In dry-run mode, the `expired_*` and `evicted` stats refer to files that *would* have been
+/// removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CleanupStats {
+    /// Files that are still valid and are kept around.
+    pub retained: FileStats,
+    /// Positive entries that were unused for longer than `max_unused_for`.
+    pub expired_unused: FileStats,
+    /// Negative entries that are older than `retry_misses_after`.
+    pub expired_negative: FileStats,
+    /// Malformed entries that are older than `retry_malformed_after`, or were
+    /// created before the cleanup process was started.
+    pub expired_malformed: FileStats,
+    /// Files that did not expire, but were the least recently used ones when the cache exceeded
+    /// its `max_size`.
+    pub evicted: FileStats,
+}
+
+impl CleanupStats {
+    /// The total of all the expired files.
+    pub fn expired(&self) -> FileStats {
+        let mut total = self.expired_unused;
+        total += self.expired_negative;
+        total += self.expired_malformed;
+        total
+    }
+
+    /// The total of all the removed files, whether they expired or were evicted.
+    pub fn removed(&self) -> FileStats {
+        let mut total = self.expired();
+        total += self.evicted;
+        total
+    }
+
+    fn add_expired(&mut self, strategy: ExpirationStrategy, bytes: u64) {
+        let stats = match strategy {
+            ExpirationStrategy::None => &mut self.expired_unused,
+            ExpirationStrategy::Negative => &mut self.expired_negative,
+            ExpirationStrategy::Malformed => &mut self.expired_malformed,
+        };
+        stats.add_file(bytes);
+    }
+}
+
+impl AddAssign for CleanupStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.retained += rhs.retained;
+        self.expired_unused += rhs.expired_unused;
+        self.expired_negative += rhs.expired_negative;
+        self.expired_malformed += rhs.expired_malformed;
+        self.evicted += rhs.evicted;
+    }
 }
 
 impl Caches {
@@ -31,13 +129,17 @@ impl Caches {
         Ok(())
     }
 
-    pub fn cleanup(&self) -> Result<()> {
+    /// Cleans up all the caches.
+    ///
+    /// If `dry_run` is set, no files are removed and only a report is logged.
+    pub fn cleanup(&self, dry_run: bool) -> Result<()> {
         // Destructure so we do not accidentally forget to cleanup one of our members.
         let Self {
             objects,
             object_meta,
             auxdifs,
             il2cpp,
+            proguard,
             symcaches,
             cficaches,
             ppdb_caches,
@@ -49,28 +151,34 @@ impl Caches {
         // Collect results so we can fail the entire function.  But we do not want to early
         // return since we should at least attempt to clean up all caches.
         let results = vec![
-            objects.cleanup(),
-            object_meta.cleanup(),
-            symcaches.cleanup(),
-            cficaches.cleanup(),
-            diagnostics.cleanup(),
-            auxdifs.cleanup(),
-            il2cpp.cleanup(),
-            ppdb_caches.cleanup(),
-            sourcemap_caches.cleanup(),
-            sourcefiles.cleanup(),
+            objects.cleanup(dry_run),
+            object_meta.cleanup(dry_run),
+            symcaches.cleanup(dry_run),
+            cficaches.cleanup(dry_run),
+            diagnostics.cleanup(dry_run),
+            auxdifs.cleanup(dry_run),
+            il2cpp.cleanup(dry_run),
+            proguard.cleanup(dry_run),
+            ppdb_caches.cleanup(dry_run),
+            sourcemap_caches.cleanup(dry_run),
+            sourcefiles.cleanup(dry_run),
         ];
 
         let mut first_error = None;
+        let mut total = CleanupStats::default();
         for result in results {
-            if let Err(err) = result {
-                let stderr: &dyn std::error::Error = &*err;
-                tracing::error!(stderr, "Failed to cleanup cache");
-                if first_error.is_none() {
-                    first_error = Some(err);
+            match result {
+                Ok(stats) => total += stats,
+                Err(err) => {
+                    let stderr: &dyn std::error::Error = &*err;
+                    tracing::error!(stderr, "Failed to cleanup cache");
+                    if first_error.is_none() {
+                        first_error = Some(err);
+                    }
                 }
             }
         }
+        log_stats("all caches", &total, dry_run);
         match first_error {
             Some(err) => Err(err),
             None => Ok(()),
@@ -78,70 +186,157 @@ impl Caches {
     }
 }
 
+/// Logs a human readable report of the given [`CleanupStats`].
+fn log_stats(name: &str, stats: &CleanupStats, dry_run: bool) {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    let removed = stats.removed();
+    tracing::info!(
+        "{} {} files ({} bytes) from {}: {} unused ({} bytes), {} negative ({} bytes), {} malformed ({} bytes), {} evicted ({} bytes); retained {} files ({} bytes)",
+        verb,
+        removed.files,
+        removed.bytes,
+        name,
+        stats.expired_unused.files,
+        stats.expired_unused.bytes,
+        stats.expired_negative.files,
+        stats.expired_negative.bytes,
+        stats.expired_malformed.files,
+        stats.expired_malformed.bytes,
+        stats.evicted.files,
+        stats.evicted.bytes,
+        stats.retained.files,
+        stats.retained.bytes,
+    );
+}
+
 impl Cache {
-    pub fn cleanup(&self) -> Result<()> {
+    /// Cleans up this cache, removing all the expired files.
+    ///
+    /// If the cache has a `max_size` and the remaining files exceed it, the least recently used
+    /// of them are evicted as well, until the cache fits.
+    ///
+    /// If `dry_run` is set, no files are removed, and the returned [`CleanupStats`] rather
+    /// describe the files that would have been removed.
+    pub fn cleanup(&self, dry_run: bool) -> Result<CleanupStats> {
         tracing::info!("Cleaning up cache: {}", self.name);
-        let cache_dir = self.cache_dir.as_ref().ok_or_else(|| {
+        let storage = self.storage.as_ref().ok_or_else(|| {
             anyhow!("no caching configured! Did you provide a path to your config file?")
         })?;
 
-        self.cleanup_directory_recursive(cache_dir)?;
+        let mut stats = CleanupStats::default();
+        let mut retained = RetainedFiles {
+            files: vec![],
+            collect: self.cache_config.max_size().is_some(),
+        };
+        storage.iterate("", &mut |file: StoredFile| {
+            let result =
+                self.try_cleanup_file(storage.as_ref(), &file, dry_run, &mut stats, &mut retained);
+            if let Err(e) = result {
+                sentry::with_scope(
+                    |scope| scope.set_extra("path", file.key.clone().into()),
+                    || tracing::error!("Failed to clean cache file: {:?}", e),
+                );
+            }
+            Ok(())
+        })?;
 
-        Ok(())
+        if let Some(max_size) = self.cache_config.max_size() {
+            self.evict_least_recently_used(
+                storage.as_ref(),
+                retained.files,
+                max_size,
+                dry_run,
+                &mut stats,
+            )?;
+        }
+
+        if !dry_run {
+            storage.sweep()?;
+        }
+
+        log_stats(self.name.as_ref(), &stats, dry_run);
+
+        Ok(stats)
     }
 
-    /// Cleans up the directory recursively, returning `true` if the directory is left empty after cleanup.
-    fn cleanup_directory_recursive(&self, directory: &Path) -> Result<bool> {
-        let entries = match catch_not_found(|| read_dir(directory))? {
-            Some(x) => x,
-            None => {
-                tracing::warn!("Directory not found: {}", directory.display());
-                return Ok(true);
-            }
-        };
+    /// Tries to clean up the given `file`, returning `true` if it was removed.
+    ///
+    /// In `dry_run` mode, this returns `true` if the file would have been removed.
+    fn try_cleanup_file(
+        &self,
+        storage: &dyn CacheStorage,
+        file: &StoredFile,
+        dry_run: bool,
+        stats: &mut CleanupStats,
+        retained: &mut RetainedFiles,
+    ) -> Result<bool> {
+        let key = &file.key;
+        tracing::trace!("Checking {}", key);
+        if catch_not_found(|| self.check_expiry(key))?.is_none() {
+            // `check_expiry` does not tell us *why* the file expired, so look at its contents again
+            // to categorize it. A file that vanished in the meantime counts as a negative entry.
+            let strategy = storage
+                .get(key)?
+                .map(|(bv, _)| expiration_strategy(&cache_entry_from_bytes(bv)))
+                .unwrap_or(ExpirationStrategy::Negative);
+            stats.add_expired(strategy, file.size);
 
-        let mut is_empty = true;
-        for entry in entries {
-            let path = entry?.path();
-            if path.is_dir() {
-                let mut dir_is_empty = self.cleanup_directory_recursive(&path)?;
-                if dir_is_empty {
-                    if let Err(e) = remove_dir(&path) {
-                        sentry::with_scope(
-                            |scope| scope.set_extra("path", path.display().to_string().into()),
-                            || tracing::error!("Failed to clean cache directory: {:?}", e),
-                        );
-                        dir_is_empty = false;
-                    }
-                }
-                is_empty &= dir_is_empty;
+            if dry_run {
+                tracing::debug!("Would remove {}", key);
             } else {
-                match self.try_cleanup_path(&path) {
-                    Err(e) => {
-                        sentry::with_scope(
-                            |scope| scope.set_extra("path", path.display().to_string().into()),
-                            || tracing::error!("Failed to clean cache file: {:?}", e),
-                        );
-                    }
-                    Ok(file_removed) => is_empty &= file_removed,
-                }
+                tracing::debug!("Removing {}", key);
+                storage.delete(key)?;
             }
+
+            return Ok(true);
         }
 
-        Ok(is_empty)
+        stats.retained.add_file(file.size);
+        if retained.collect {
+            retained.files.push((file.mtime, file.size, key.clone()));
+        }
+        Ok(false)
     }
 
-    /// Tries to clean up the file at `path`, returning `true` if it was removed.
-    fn try_cleanup_path(&self, path: &Path) -> Result<bool> {
-        tracing::trace!("Checking {}", path.display());
-        anyhow::ensure!(path.is_file(), "not a file");
-        if catch_not_found(|| self.check_expiry(path))?.is_none() {
-            tracing::debug!("Removing {}", path.display());
-            catch_not_found(|| remove_file(path))?;
+    /// Removes the least recently used of the `retained` files, until the retained files fit into
+    /// `max_size` bytes.
+    ///
+    /// Files are ordered by their `mtime`, which is regularly touched for files in use.
+    fn evict_least_recently_used(
+        &self,
+        storage: &dyn CacheStorage,
+        mut retained: Vec<(SystemTime, u64, String)>,
+        max_size: u64,
+        dry_run: bool,
+        stats: &mut CleanupStats,
+    ) -> Result<()> {
+        retained.sort_unstable();
 
-            return Ok(true);
+        for (_, bytes, key) in retained {
+            if stats.retained.bytes <= max_size {
+                break;
+            }
+
+            if dry_run {
+                tracing::debug!("Would evict {}", key);
+            } else {
+                tracing::debug!("Evicting {}", key);
+                storage.delete(&key)?;
+            }
+
+            stats.retained.files -= 1;
+            stats.retained.bytes -= bytes;
+            stats.evicted.add_file(bytes);
         }
 
-        Ok(false)
+        Ok(())
     }
 }
+
+/// The files kept during cleanup, with their `mtime`, size and key.
+///
+/// These are only collected if the cache has a `max_size`, as they are needed for eviction only.
+struct RetainedFiles {
+    files: Vec<(SystemTime, u64, String)>,
+    collect: bool,
+}
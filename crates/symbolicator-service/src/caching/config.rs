@@ -1,17 +1,33 @@
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
 /// All known cache names.
-#[derive(Debug, Clone, Copy)]
+///
+/// These (de)serialize from the same names as are used for the cache directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum CacheName {
+    #[serde(rename = "objects")]
     Objects,
+    #[serde(rename = "object_meta")]
     ObjectMeta,
+    #[serde(rename = "auxdifs")]
     Auxdifs,
+    #[serde(rename = "il2cpp")]
     Il2cpp,
+    #[serde(rename = "proguard")]
+    Proguard,
+    #[serde(rename = "symcaches")]
     Symcaches,
+    #[serde(rename = "cficaches")]
     Cficaches,
+    #[serde(rename = "ppdb_caches")]
     PpdbCaches,
+    #[serde(rename = "sourcemap_caches")]
     SourceMapCaches,
+    #[serde(rename = "sourcefiles")]
     SourceFiles,
+    #[serde(rename = "diagnostics")]
     Diagnostics,
 }
 
@@ -22,6 +38,7 @@ impl AsRef<str> for CacheName {
             Self::ObjectMeta => "object_meta",
             Self::Auxdifs => "auxdifs",
             Self::Il2cpp => "il2cpp",
+            Self::Proguard => "proguard",
             Self::Symcaches => "symcaches",
             Self::Cficaches => "cficaches",
             Self::PpdbCaches => "ppdb_caches",
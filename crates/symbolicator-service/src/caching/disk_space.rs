@@ -0,0 +1,176 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::DiskSpaceConfig;
+
+use super::cleanup::FileStats;
+use super::{Cache, Caches, StoredFile};
+
+/// Tells caches of downloaded files whether the cache volume is running low on free space.
+///
+/// This is updated by the disk space watchdog, see [`Caches::spawn_disk_space_watchdog`].
+#[derive(Debug, Clone, Default)]
+pub struct DiskPressure {
+    low_space: Arc<AtomicBool>,
+    large_file_size: u64,
+}
+
+impl DiskPressure {
+    /// Creates the state for files larger than `large_file_size` bytes, with enough free space.
+    pub fn new(large_file_size: u64) -> Self {
+        Self {
+            low_space: Default::default(),
+            large_file_size,
+        }
+    }
+
+    /// Returns `true` if the free space is below the low watermark.
+    pub fn is_low(&self) -> bool {
+        self.low_space.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_low(&self, low: bool) {
+        self.low_space.store(low, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if a file of the given size in bytes should be stored in the cache.
+    pub fn allows_storing(&self, bytes: u64) -> bool {
+        !self.is_low() || bytes <= self.large_file_size
+    }
+}
+
+/// Returns the space in bytes which is available to unprivileged users on the volume of `path`.
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string, and `stat` is only read if the call succeeded.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free disk space cannot be determined on this platform",
+    ))
+}
+
+impl Cache {
+    /// Removes the least recently used files from this cache, until at least `bytes` are freed.
+    ///
+    /// Files are ordered by their `mtime`, which is regularly touched for files in use.
+    pub fn evict_oldest(&self, bytes: u64) -> io::Result<FileStats> {
+        let mut stats = FileStats::default();
+        let Some(storage) = self.storage.as_ref() else {
+            return Ok(stats);
+        };
+
+        let mut files = vec![];
+        storage.iterate("", &mut |file: StoredFile| {
+            files.push((file.mtime, file.size, file.key));
+            Ok(())
+        })?;
+        files.sort_unstable();
+
+        for (_, size, key) in files {
+            if stats.bytes >= bytes {
+                break;
+            }
+            tracing::debug!("Evicting {}", key);
+            if storage.delete(&key)? {
+                stats.add_file(size);
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+impl Caches {
+    /// Spawns a background task onto `runtime` which periodically checks the free space on the
+    /// cache volume, and reacts to it falling below the configured watermarks.
+    ///
+    /// Below the low watermark, large downloaded files are no longer cached. Below the critical
+    /// watermark, the least recently used downloaded objects are evicted until the free space
+    /// is back above the low watermark.
+    pub fn spawn_disk_space_watchdog(
+        &self,
+        config: DiskSpaceConfig,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        let Some(interval) = config.check_interval else {
+            return;
+        };
+        let Some(cache_dir) = self.objects.cache_dir.clone() else {
+            return;
+        };
+        let objects = self.objects.clone();
+
+        runtime.spawn(async move {
+            loop {
+                let objects = objects.clone();
+                let cache_dir = cache_dir.clone();
+                let check = tokio::task::spawn_blocking(move || {
+                    check_disk_space(&config, &cache_dir, &objects)
+                });
+                match check.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => {
+                        let stderr: &dyn std::error::Error = &err;
+                        tracing::error!(stderr, "Failed to check free disk space");
+                        if err.kind() == io::ErrorKind::Unsupported {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let stderr: &dyn std::error::Error = &err;
+                        tracing::error!(stderr, "Failed to check free disk space");
+                    }
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+fn check_disk_space(config: &DiskSpaceConfig, cache_dir: &Path, objects: &Cache) -> io::Result<()> {
+    let mut available = available_space(cache_dir)?;
+    metric!(gauge("caches.disk.available_bytes") = available);
+
+    if available < config.critical_watermark {
+        tracing::warn!(
+            available,
+            "Cache volume is running out of space, evicting objects"
+        );
+        let stats = objects.evict_oldest(config.low_watermark.saturating_sub(available))?;
+        metric!(counter("caches.disk.evicted_files") += stats.files as i64);
+        metric!(counter("caches.disk.evicted_bytes") += stats.bytes as i64);
+        available = available_space(cache_dir)?;
+    }
+
+    let is_low = available < config.low_watermark;
+    if is_low != objects.disk_pressure.is_low() {
+        tracing::info!(
+            available,
+            is_low,
+            "Cache volume free space crossed the low watermark"
+        );
+    }
+    objects.disk_pressure.set_low(is_low);
+    metric!(gauge("caches.disk.low_space") = is_low as u64);
+
+    Ok(())
+}
@@ -4,14 +4,15 @@ use std::sync::atomic::AtomicIsize;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 
-use filetime::FileTime;
 use symbolic::common::ByteView;
 use tempfile::NamedTempFile;
+use tokio::sync::Semaphore;
 
 use crate::config::{CacheConfig, Config};
 
 use super::cache_error::cache_entry_from_bytes;
-use super::{CacheEntry, CacheError, CacheName};
+use super::disk_space::DiskPressure;
+use super::{CacheEntry, CacheError, CacheName, CacheStorage, FileSystemStorage};
 
 /// The interval in which positive caches should be touched.
 ///
@@ -35,6 +36,11 @@ pub struct Cache {
     /// Leaving this as None will disable this cache.
     pub(super) cache_dir: Option<PathBuf>,
 
+    /// The storage holding the cache items, which is the `cache_dir` by default.
+    ///
+    /// This is `None` if the cache is disabled.
+    pub(super) storage: Option<Arc<dyn CacheStorage>>,
+
     /// Directory to use for temporary files.
     ///
     /// When writing a new file into the cache it is best to write it to a temporary file in
@@ -53,8 +59,19 @@ pub struct Cache {
     /// The maximum number of lazy refreshes of this cache.
     max_lazy_refreshes: Arc<AtomicIsize>,
 
+    /// Permits for running conversions into this cache, shared by all derived caches.
+    conversion_permits: Option<Arc<Semaphore>>,
+
+    /// Whether the cache volume is running low on free space.
+    pub(super) disk_pressure: DiskPressure,
+
     /// The capacity (in bytes) of the in-memory cache.
     pub(super) in_memory_capacity: u64,
+
+    /// The maximum weight of an item kept in the in-memory cache.
+    ///
+    /// Larger items are loaded from the file system on every use.
+    pub(super) in_memory_max_item_size: Option<u64>,
 }
 
 impl Cache {
@@ -68,21 +85,60 @@ impl Cache {
         let tmp_dir = config.cache_dir("tmp");
         let cache_dir = config.cache_dir(name.as_ref());
 
-        if let Some(ref dir) = cache_dir {
-            std::fs::create_dir_all(dir)?;
-        }
+        let overrides = config.caches.overrides.get(&name);
+        let cache_config = match overrides {
+            Some(overrides) => cache_config.with_overrides(overrides),
+            None => cache_config,
+        };
+        let in_memory_capacity = overrides
+            .and_then(|overrides| overrides.in_memory_capacity)
+            .unwrap_or(in_memory_capacity);
+        let in_memory_max_item_size =
+            overrides.and_then(|overrides| overrides.in_memory_max_item_size);
+
+        let storage = match cache_dir {
+            Some(ref dir) => {
+                let storage = FileSystemStorage::new(dir.clone())?;
+                Some(Arc::new(storage) as Arc<dyn CacheStorage>)
+            }
+            None => None,
+        };
 
         Ok(Cache {
             name,
             cache_dir,
+            storage,
             tmp_dir,
             start_time: SystemTime::now(),
             cache_config,
             max_lazy_refreshes,
+            conversion_permits: None,
             in_memory_capacity,
+            in_memory_max_item_size,
+            disk_pressure: Default::default(),
         })
     }
 
+    /// Stops caching large files while the given [`DiskPressure`] reports low free space.
+    pub fn with_disk_pressure(mut self, disk_pressure: DiskPressure) -> Self {
+        self.disk_pressure = disk_pressure;
+        self
+    }
+
+    /// Bounds the number of conversions into this cache running at once by the given permits.
+    pub fn with_conversion_permits(mut self, permits: Arc<Semaphore>) -> Self {
+        self.conversion_permits = Some(permits);
+        self
+    }
+
+    /// Stores the items of this cache in the given [`CacheStorage`] instead of the `cache_dir`.
+    ///
+    /// This also enables a cache which has no `cache_dir` configured.
+    pub fn with_storage(mut self, storage: Arc<dyn CacheStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
     pub fn name(&self) -> CacheName {
         self.name
     }
@@ -95,14 +151,25 @@ impl Cache {
         self.max_lazy_refreshes.clone()
     }
 
-    /// Validate cache expiration of path.
+    /// The wall-clock budget for converting a single item of this cache.
+    pub fn max_conversion_time(&self) -> Option<Duration> {
+        self.cache_config.max_conversion_time()
+    }
+
+    /// The permits bounding the number of conversions into this cache running at once.
+    pub fn conversion_permits(&self) -> Option<Arc<Semaphore>> {
+        self.conversion_permits.clone()
+    }
+
+    /// Validate cache expiration of the item stored at `key`.
     ///
     /// If cache should not be used, `Err(io::ErrorKind::NotFound)` is returned.
     /// If cache is usable, `Ok(x)` is returned with the opened [`ByteView`], and
-    /// an [`ExpirationTime`] that indicates whether the file should be touched before using.
+    /// an [`ExpirationTime`] that indicates whether the file should be touched before using,
+    /// or whether it is [`Stale`](ExpirationTime::Stale) and should be refreshed.
     pub(super) fn check_expiry(
         &self,
-        path: &Path,
+        key: &str,
     ) -> io::Result<(CacheEntry<ByteView<'static>>, ExpirationTime)> {
         // We use `mtime` to keep track of both "cache last used" and "cache created" depending on
         // whether the file is a negative cache item or not, because literally every other
@@ -118,11 +185,10 @@ impl Cache {
         //   conversion. mtime indicates when we attempted to convert.
         // * ok (don't really have a name): File has any other content, mtime is used to keep track
         //   of last use.
-        let metadata = path.metadata()?;
-        tracing::trace!("File length: {}", metadata.len());
+        let storage = self.storage.as_ref().ok_or(io::ErrorKind::NotFound)?;
+        let (bv, mtime) = storage.get(key)?.ok_or(io::ErrorKind::NotFound)?;
+        tracing::trace!("File length: {}", bv.len());
 
-        let bv = ByteView::open(path)?;
-        let mtime = metadata.modified()?;
         let mtime_elapsed = mtime.elapsed().unwrap_or_default();
 
         let cache_entry = cache_entry_from_bytes(bv);
@@ -131,7 +197,15 @@ impl Cache {
                 let max_unused_for = self.cache_config.max_unused_for().unwrap_or(Duration::MAX);
 
                 if mtime_elapsed > max_unused_for {
-                    return Err(io::ErrorKind::NotFound.into());
+                    // Within the grace period, the stale item is still served while it is being
+                    // refreshed in the background.
+                    let stale_for = mtime_elapsed - max_unused_for;
+                    return match self.cache_config.stale_while_revalidate() {
+                        Some(grace) if stale_for <= grace => {
+                            Ok((cache_entry, ExpirationTime::Stale))
+                        }
+                        _ => Err(io::ErrorKind::NotFound.into()),
+                    };
                 }
 
                 // we want to touch good caches once every `TOUCH_EVERY`
@@ -174,7 +248,7 @@ impl Cache {
         Ok((cache_entry, expiration_time))
     }
 
-    /// Validates `cachefile` against expiration config and open a [`ByteView`] on it.
+    /// Validates the item stored at `key` against expiration config and open a [`ByteView`] on it.
     ///
     /// Takes care of bumping `mtime`.
     ///
@@ -182,17 +256,19 @@ impl Cache {
     /// bumped.
     pub fn open_cachefile(
         &self,
-        path: &Path,
+        key: &str,
     ) -> io::Result<Option<(CacheEntry<ByteView<'static>>, ExpirationTime)>> {
         // `io::ErrorKind::NotFound` can be returned from multiple locations in this function. All
         // of those can indicate a cache miss as cache cleanup can run inbetween. Only when we have
         // an open ByteView we can be sure to have a cache hit.
         catch_not_found(|| {
-            let (cache_entry, mut expiration) = self.check_expiry(path)?;
+            let (cache_entry, mut expiration) = self.check_expiry(key)?;
 
             let should_touch = matches!(expiration, ExpirationTime::TouchIn(Duration::ZERO));
             if should_touch {
-                filetime::set_file_mtime(path, FileTime::now())?;
+                if let Some(storage) = &self.storage {
+                    storage.touch(key)?;
+                }
                 // well, we just touched the file ;-)
                 expiration = ExpirationTime::TouchIn(TOUCH_EVERY);
             }
@@ -201,6 +277,20 @@ impl Cache {
         })
     }
 
+    /// Stores a copy of the file at `path` under `key` in the storage of this cache.
+    ///
+    /// Returns `false` without copying anything if this cache is disabled.
+    pub fn store_copy(&self, key: &str, path: &Path) -> io::Result<bool> {
+        let Some(storage) = &self.storage else {
+            return Ok(false);
+        };
+
+        let mut file = self.tempfile()?;
+        io::copy(&mut std::fs::File::open(path)?, &mut file)?;
+        storage.put(key, file)?;
+        Ok(true)
+    }
+
     /// Create a new temporary file to use in the cache.
     pub fn tempfile(&self) -> io::Result<NamedTempFile> {
         match self.tmp_dir {
@@ -267,12 +357,23 @@ pub enum ExpirationTime {
     /// The [`Duration`] after which a positive cache entry needs to be touched to keep it
     /// alive for a longer time.
     TouchIn(Duration),
+
+    /// A positive cache entry which was unused for longer than `max_unused_for`, but is still
+    /// within its `stale_while_revalidate` grace period.
+    ///
+    /// It can be used right away, but should be refreshed in the background.
+    Stale,
 }
 
 impl ExpirationTime {
     /// Gives the [`ExpirationTime`] for a freshly created cache with the given [`CacheEntry`].
     pub fn for_fresh_status<T>(cache: &Cache, entry: &CacheEntry<T>) -> Self {
         let config = &cache.cache_config;
+        // Rate limited items are retried as soon as the source allows it.
+        if let Err(CacheError::RateLimited(retry_after)) = entry {
+            return Self::RefreshIn(*retry_after);
+        }
+
         let strategy = expiration_strategy(entry);
         match strategy {
             ExpirationStrategy::None => {
@@ -297,13 +398,22 @@ impl ExpirationTime {
         matches!(self, ExpirationTime::TouchIn(TOUCH_EVERY))
     }
 
+    /// Says whether the cache is stale and should be refreshed.
+    pub fn is_stale(&self) -> bool {
+        matches!(self, ExpirationTime::Stale)
+    }
+
     /// Gives the [`Instant`] at which the item expires.
+    ///
+    /// [`Stale`](ExpirationTime::Stale) items expire right away, so they are looked up again
+    /// until their refresh replaces them.
     pub fn as_instant(&self) -> Instant {
         let duration = match self {
-            ExpirationTime::RefreshIn(d) => d,
-            ExpirationTime::TouchIn(d) => d,
+            ExpirationTime::RefreshIn(d) => *d,
+            ExpirationTime::TouchIn(d) => *d,
+            ExpirationTime::Stale => Duration::ZERO,
         };
-        Instant::now() + *duration
+        Instant::now() + duration
     }
 }
 
@@ -0,0 +1,122 @@
+use std::io;
+use std::sync::Arc;
+
+use symbolicator_sources::ObjectId;
+
+use super::cache_error::cache_entry_from_bytes;
+use super::{Cache, CacheStorage, StoredFile};
+
+/// Matches cache entries which refer to any of the identifiers of an object file.
+///
+/// Sources lay out files in different ways, so identifiers show up in cache keys in various
+/// formats: with or without dashes, in upper or lower case, or split across path segments. To
+/// find them regardless, both the identifiers and the metadata of a cache key are reduced to
+/// their lowercase hex digits before they are compared.
+///
+/// Since this is a substring match, short identifiers would match unrelated entries. Nil debug
+/// identifiers and code identifiers with fewer than [`MIN_ID_DIGITS`](Self::MIN_ID_DIGITS) hex
+/// digits are thus ignored.
+#[derive(Debug, Clone)]
+pub struct ObjectIdFilter {
+    ids: Arc<[String]>,
+}
+
+impl ObjectIdFilter {
+    /// The minimum number of hex digits of a code identifier to be matched.
+    ///
+    /// The shortest code identifiers in practice are those of PE files, which start with an
+    /// 8-digit timestamp.
+    pub const MIN_ID_DIGITS: usize = 8;
+
+    /// Creates a filter for the debug and code identifiers of the given object.
+    pub fn new(object_id: &ObjectId) -> Self {
+        let debug_id = object_id
+            .debug_id
+            .filter(|id| !id.is_nil())
+            .map(|id| id.uuid().simple().to_string());
+        let code_id = object_id
+            .code_id
+            .as_ref()
+            .map(|id| hex_digits(id.as_str()))
+            .filter(|id| id.len() >= Self::MIN_ID_DIGITS);
+        let ids = debug_id.into_iter().chain(code_id).collect();
+
+        Self { ids }
+    }
+
+    /// Returns `true` if the filter has no identifiers, and thus does not match anything.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Returns `true` if the given cache key metadata contains any of the identifiers.
+    pub fn matches(&self, metadata: &str) -> bool {
+        let digits = hex_digits(metadata);
+        self.ids.iter().any(|id| digits.contains(id.as_str()))
+    }
+}
+
+fn hex_digits(s: &str) -> String {
+    s.chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+impl Cache {
+    /// Removes the negative and error entries whose key matches the `filter` from the storage.
+    ///
+    /// Entries are matched by the metadata file written next to them. Positive entries are never
+    /// removed. Returns the number of removed entries.
+    ///
+    /// This walks the whole cache storage, and should thus be run on a blocking thread.
+    pub fn invalidate_negative(&self, filter: &ObjectIdFilter) -> io::Result<usize> {
+        let Some(ref storage) = self.storage else {
+            return Ok(0);
+        };
+        if filter.is_empty() {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        storage.iterate("", &mut |file: StoredFile| {
+            if invalidate_file(storage.as_ref(), &file.key, filter)? {
+                removed += 1;
+            }
+            Ok(())
+        })?;
+        tracing::info!("Invalidated {} negative entries in {}", removed, self.name);
+        Ok(removed)
+    }
+}
+
+/// Removes the entry described by the metadata file at `key` if it matches the `filter`, and is
+/// not a positive entry.
+fn invalidate_file(
+    storage: &dyn CacheStorage,
+    key: &str,
+    filter: &ObjectIdFilter,
+) -> io::Result<bool> {
+    let Some(item_key) = key.strip_suffix(".txt") else {
+        return Ok(false);
+    };
+
+    let Some((metadata, _)) = storage.get(key)? else {
+        return Ok(false);
+    };
+    if !filter.matches(&String::from_utf8_lossy(&metadata)) {
+        return Ok(false);
+    }
+
+    let Some((bv, _)) = storage.get(item_key)? else {
+        return Ok(false);
+    };
+    if cache_entry_from_bytes(bv).is_ok() {
+        return Ok(false);
+    }
+
+    tracing::debug!("Invalidating {}", item_key);
+    storage.delete(item_key)?;
+    storage.delete(key)?;
+    Ok(true)
+}
@@ -1,5 +1,5 @@
 use std::collections::HashSet;
-use std::path::Path;
+use std::io::Write;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -13,7 +13,7 @@ use tempfile::NamedTempFile;
 use super::shared_cache::{CacheStoreReason, SharedCacheRef};
 use crate::utils::futures::CallOnDrop;
 
-use super::{Cache, CacheEntry, CacheError, CacheKey, ExpirationTime};
+use super::{Cache, CacheEntry, CacheError, CacheKey, ExpirationTime, ObjectIdFilter};
 
 type InMemoryItem<T> = (Instant, CacheEntry<T>);
 type InMemoryCache<T> = moka::future::Cache<CacheKey, InMemoryItem<T>>;
@@ -35,6 +35,9 @@ pub struct Cacher<T: CacheItemRequest> {
 
     /// A service used to communicate with the shared cache.
     shared_cache: SharedCacheRef,
+
+    /// Whether computed items are discarded instead of being cached, see [`Cacher::read_only`].
+    read_only: bool,
 }
 
 impl<T: CacheItemRequest> std::fmt::Debug for Cacher<T> {
@@ -49,6 +52,7 @@ impl<T: CacheItemRequest> std::fmt::Debug for Cacher<T> {
             .field("in-memory items", &self.cache.entry_count())
             .field("running refreshes", &refreshes)
             .field("shared_cache", &self.shared_cache)
+            .field("read_only", &self.read_only)
             .finish()
     }
 }
@@ -63,6 +67,7 @@ impl<T: CacheItemRequest> Clone for Cacher<T> {
             cache: self.cache.clone(),
             refreshes: Arc::clone(&self.refreshes),
             shared_cache: Arc::clone(&self.shared_cache),
+            read_only: self.read_only,
         }
     }
 }
@@ -74,6 +79,8 @@ impl<T: CacheItemRequest> Cacher<T> {
             .name(config.name().as_ref())
             // NOTE: even though we have a per-item TTL, we still want to have a hard limit here
             .time_to_live(Duration::from_secs(60 * 60))
+            // negative entries can be invalidated once the files they are missing show up
+            .support_invalidation_closures()
             // NOTE: we count all the bookkeeping structures to the weight as well
             .weigher(|_k, v| {
                 let value_size =
@@ -89,12 +96,52 @@ impl<T: CacheItemRequest> Cacher<T> {
             cache,
             refreshes: Default::default(),
             shared_cache,
+            read_only: false,
+        }
+    }
+
+    /// Returns a copy of this cacher which uses existing items, but never caches any.
+    ///
+    /// Computed items are neither written to the file system nor the shared cache, nor are they
+    /// kept in memory, so they are never served to other users of this cacher. Stale and outdated
+    /// items are not refreshed either.
+    pub fn read_only(&self) -> Self {
+        Self {
+            read_only: true,
+            ..self.clone()
         }
     }
 
     pub fn tempfile(&self) -> std::io::Result<NamedTempFile> {
         self.config.tempfile()
     }
+
+    /// Invalidates the negative and error entries whose key matches the `filter`.
+    ///
+    /// Matching entries are dropped from the in-memory cache right away, and removed from the
+    /// file system as described in [`Cache::invalidate_negative`]. Returns the number of removed
+    /// files.
+    pub fn invalidate_negative(&self, filter: &ObjectIdFilter) -> std::io::Result<usize> {
+        let in_memory = filter.clone();
+        let predicate = move |key: &CacheKey, item: &InMemoryItem<T::Item>| {
+            item.1.is_err() && in_memory.matches(key.metadata())
+        };
+        if let Err(err) = self.cache.invalidate_entries_if(predicate) {
+            let error: &dyn std::error::Error = &err;
+            tracing::error!(error, "Failed to invalidate in-memory cache entries");
+        }
+
+        self.config.invalidate_negative(filter)
+    }
+}
+
+/// Why an item that is already cached is computed anew in the background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Refresh {
+    /// The item was found in a fallback version, and is computed for the current version.
+    Fallback,
+    /// The item is stale, and is served while it is refreshed.
+    Stale,
 }
 
 /// Cache Version Configuration used during cache lookup and generation.
@@ -127,6 +174,13 @@ pub trait CacheItemRequest: 'static + Send + Sync + Clone {
     /// Loads an existing element from the cache.
     fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item>;
 
+    /// Checks whether a positive element loaded from the cache is still up to date.
+    ///
+    /// Elements which are not are computed anew. By default, cached elements never go stale.
+    fn revalidate(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async { true })
+    }
+
     /// The "cost" of keeping this item in the in-memory cache.
     fn weight(item: &Self::Item) -> u32 {
         std::mem::size_of_val(item) as u32
@@ -141,34 +195,38 @@ impl<T: CacheItemRequest> Cacher<T> {
     fn lookup_local_cache(
         &self,
         request: &T,
-        cache_dir: &Path,
         key: &CacheKey,
         version: u32,
     ) -> CacheEntry<(Instant, CacheEntry<T::Item>)> {
         let name = self.config.name();
         let cache_key = key.cache_path(version);
 
-        let item_path = cache_dir.join(&cache_key);
-        tracing::trace!("Trying {} cache at path {}", name, item_path.display());
+        tracing::trace!("Trying {} cache at path {}", name, cache_key);
         let _scope = Hub::current().push_scope();
         sentry::configure_scope(|scope| {
             scope.set_extra(
                 &format!("cache.{name}.cache_path"),
-                item_path.to_string_lossy().into(),
+                cache_key.clone().into(),
             );
         });
         let (entry, expiration) = self
             .config
-            .open_cachefile(&item_path)?
+            .open_cachefile(&cache_key)?
             .ok_or(CacheError::NotFound)?;
 
+        // A stale item is used right away, and replaced by a fresh one in the background.
+        if expiration.is_stale() && !self.read_only {
+            metric!(counter("caches.file.stale_served") += 1, "cache" => name.as_ref());
+            self.spawn_refresh(key.clone(), request.clone(), Refresh::Stale);
+        }
+
         // store things into the shared cache when:
         // - we have a positive cache
         // - that has the latest version (we don’t want to upload old versions)
         // - we refreshed the local cache time, so we also refresh the shared cache time.
         let needs_reupload = expiration.was_touched();
         // FIXME: let-chains would be nice here :-)
-        if version == T::VERSIONS.current && needs_reupload {
+        if version == T::VERSIONS.current && needs_reupload && !self.read_only {
             if let Ok(byteview) = &entry {
                 if let Some(shared_cache) = self.shared_cache.get() {
                     shared_cache.store(
@@ -184,6 +242,7 @@ impl<T: CacheItemRequest> Cacher<T> {
         // This is also reported for "negative cache hits": When we cached
         // the 404 response from a server as empty file.
         metric!(counter("caches.file.hit") += 1, "cache" => name.as_ref());
+        metric!(counter("caches.tier.hit") += 1, "cache" => name.as_ref(), "tier" => "local");
         if let Ok(byteview) = &entry {
             metric!(
                 time_raw("caches.file.size") = byteview.len() as u64,
@@ -192,7 +251,7 @@ impl<T: CacheItemRequest> Cacher<T> {
             );
         }
 
-        tracing::trace!("Loading {} at path {}", name, item_path.display());
+        tracing::trace!("Loading {} at path {}", name, cache_key);
 
         let entry = entry.and_then(|byteview| request.load(byteview));
         Ok((expiration.as_instant(), entry))
@@ -201,12 +260,20 @@ impl<T: CacheItemRequest> Cacher<T> {
     /// Compute an item.
     ///
     /// The item is computed using [`T::compute`](CacheItemRequest::compute), and saved in the cache
-    /// if one is configured. The `is_refresh` flag is used only to tag computation metrics.
+    /// if one is configured. A `refresh` of a stale item only saves it if it can be loaded, so
+    /// that the stale item keeps being served if computing it fails. Otherwise, `refresh` is only
+    /// used to tag computation metrics.
     ///
     /// This method does not take care of ensuring the computation only happens once even
     /// for concurrent requests, see the public [`Cacher::compute_memoized`] for this.
-    async fn compute(&self, request: T, key: &CacheKey, is_refresh: bool) -> CacheEntry<T::Item> {
+    async fn compute(
+        &self,
+        request: T,
+        key: &CacheKey,
+        refresh: Option<Refresh>,
+    ) -> CacheEntry<T::Item> {
         let name = self.config.name();
+        let is_refresh = refresh.is_some();
         let cache_path = key.cache_path(T::VERSIONS.current);
         let mut temp_file = self.tempfile()?;
 
@@ -224,6 +291,14 @@ impl<T: CacheItemRequest> Cacher<T> {
             Err(CacheError::NotFound)
         };
 
+        if !is_refresh {
+            metric!(
+                counter("caches.tier.hit") += 1,
+                "cache" => name.as_ref(),
+                "tier" => if shared_cache_hit { "shared" } else { "computed" },
+            );
+        }
+
         if entry.is_err() {
             metric!(counter("caches.computation") += 1, "cache" => name.as_ref());
             match request.compute(&mut temp_file).await {
@@ -242,14 +317,39 @@ impl<T: CacheItemRequest> Cacher<T> {
             }
         }
 
-        if let Some(cache_dir) = self.config.cache_dir() {
-            // Cache is enabled, write it!
-            let mut cache_path = cache_dir.join(&cache_path);
+        // Large files are not written to the cache while the cache volume is low on space.
+        let skip_write = match &entry {
+            Ok(byte_view) => !self
+                .config
+                .disk_pressure
+                .allows_storing(byte_view.len() as u64),
+            Err(_) => false,
+        };
+        if skip_write && self.config.storage.is_some() {
+            metric!(counter("caches.file.skipped_low_space") += 1, "cache" => name.as_ref());
+        }
+        // Rate limits are lifted long before a negative cache entry would expire, so they are
+        // only kept in memory until the source may be tried again.
+        let skip_write =
+            skip_write || self.read_only || matches!(entry, Err(CacheError::RateLimited(_)));
+
+        let item = match &entry {
+            Ok(byteview) => request.load(byteview.clone()),
+            Err(err) => Err(err.clone()),
+        };
+
+        // A failed refresh must not replace the stale item, which is still valid.
+        if refresh == Some(Refresh::Stale) && item.is_err() {
+            metric!(counter("caches.file.stale_refresh_failed") += 1, "cache" => name.as_ref());
+            return item;
+        }
 
+        if let Some(storage) = self.config.storage.as_ref().filter(|_| !skip_write) {
+            // Cache is enabled, write it!
             sentry::configure_scope(|scope| {
                 scope.set_extra(
                     &format!("cache.{name}.cache_path"),
-                    cache_path.to_string_lossy().into(),
+                    cache_path.clone().into(),
                 );
             });
             metric!(
@@ -273,20 +373,23 @@ impl<T: CacheItemRequest> Cacher<T> {
                 );
             }
 
-            tracing::trace!("Creating {name} at path {:?}", cache_path.display());
+            tracing::trace!("Creating {name} at path {:?}", cache_path);
 
-            persist_tempfile(temp_file, &cache_path)?;
+            storage.put(&cache_path, temp_file)?;
 
             // NOTE: we only create the metadata file once, but do not regularly touch it for now
-            cache_path.set_extension("txt");
-            if let Err(err) = std::fs::write(cache_path, key.metadata()) {
+            let metadata = self.tempfile().and_then(|mut metadata_file| {
+                metadata_file.write_all(key.metadata().as_bytes())?;
+                storage.put(&format!("{cache_path}.txt"), metadata_file)
+            });
+            if let Err(err) = metadata {
                 tracing::error!(error = &err as &dyn std::error::Error);
             }
         };
 
         // TODO: Not handling negative caches probably has a huge perf impact.  Need to
         // figure out negative caches.  Maybe put them in redis with a TTL?
-        if !shared_cache_hit {
+        if !shared_cache_hit && !self.read_only {
             if let Ok(byteview) = &entry {
                 if let Some(shared_cache) = self.shared_cache.get() {
                     shared_cache.store(name, &cache_path, byteview.clone(), CacheStoreReason::New);
@@ -294,7 +397,7 @@ impl<T: CacheItemRequest> Cacher<T> {
             }
         }
 
-        entry.and_then(|byteview| request.load(byteview))
+        item
     }
 
     /// Computes an item by loading from or populating the cache.
@@ -314,16 +417,14 @@ impl<T: CacheItemRequest> Cacher<T> {
         metric!(counter("caches.access") += 1, "cache" => name.as_ref());
 
         let init = Box::pin(async {
-            // cache_path is None when caching is disabled.
-            if let Some(cache_dir) = self.config.cache_dir() {
+            // storage is None when caching is disabled.
+            if self.config.storage.is_some() {
                 let versions = std::iter::once(T::VERSIONS.current)
                     .chain(T::VERSIONS.fallbacks.iter().copied());
 
                 for version in versions {
                     // try the new cache key first, then fall back to the old cache key
-                    let item = match self
-                        .lookup_local_cache(&request, cache_dir, &cache_key, version)
-                    {
+                    let item = match self.lookup_local_cache(&request, &cache_key, version) {
                         Err(CacheError::NotFound) => continue,
                         Err(err) => {
                             let item = Err(err);
@@ -333,7 +434,12 @@ impl<T: CacheItemRequest> Cacher<T> {
                         Ok(item) => item,
                     };
 
-                    if version != T::VERSIONS.current {
+                    if item.1.is_ok() && !request.revalidate().await {
+                        metric!(counter("caches.file.stale") += 1, "cache" => name.as_ref());
+                        break;
+                    }
+
+                    if version != T::VERSIONS.current && !self.read_only {
                         // we have found an outdated cache that we will use right away,
                         // and we will kick off a recomputation for the `current` cache version
                         // in a deduplicated background task, which we will not await
@@ -342,7 +448,7 @@ impl<T: CacheItemRequest> Cacher<T> {
                             "version" => &version.to_string(),
                             "cache" => name.as_ref(),
                         );
-                        self.spawn_refresh(cache_key.clone(), request);
+                        self.spawn_refresh(cache_key.clone(), request, Refresh::Fallback);
                     }
 
                     return item;
@@ -354,7 +460,7 @@ impl<T: CacheItemRequest> Cacher<T> {
             metric!(counter("caches.file.miss") += 1, "cache" => name.as_ref());
 
             let item = self
-                .compute(request, &cache_key, false)
+                .compute(request, &cache_key, None)
                 // NOTE: We have seen this deadlock with an SDK that was deadlocking on
                 // out-of-order Scope pops.
                 // To guarantee that this does not happen is really the responsibility of
@@ -369,6 +475,13 @@ impl<T: CacheItemRequest> Cacher<T> {
         });
         let replace_if = |v: &InMemoryItem<T::Item>| Instant::now() >= v.0;
 
+        if self.read_only {
+            return match self.cache.get(&cache_key) {
+                Some(item) if !replace_if(&item) => item.1,
+                _ => init.await.1,
+            };
+        }
+
         let entry = self
             .cache
             .entry_by_ref(&cache_key)
@@ -377,11 +490,28 @@ impl<T: CacheItemRequest> Cacher<T> {
 
         if !entry.is_fresh() {
             metric!(counter("caches.memory.hit") += 1, "cache" => name.as_ref());
+            metric!(counter("caches.tier.hit") += 1, "cache" => name.as_ref(), "tier" => "memory");
+        } else if !self.fits_in_memory(&entry.value().1) {
+            // Concurrent requests were still coalesced, but further ones load the item from the
+            // file system cache again.
+            self.cache.invalidate(&cache_key).await;
+            metric!(counter("caches.memory.skipped_large") += 1, "cache" => name.as_ref());
         }
         entry.into_value().1
     }
 
-    fn spawn_refresh(&self, cache_key: CacheKey, request: T) {
+    /// Returns `true` if the item is small enough to be kept in the in-memory cache.
+    ///
+    /// Errors are always kept, while items are compared to the configured
+    /// `in_memory_max_item_size` by their [`weight`](CacheItemRequest::weight).
+    fn fits_in_memory(&self, item: &CacheEntry<T::Item>) -> bool {
+        match (self.config.in_memory_max_item_size, item) {
+            (Some(max_size), Ok(item)) => u64::from(T::weight(item)) <= max_size,
+            _ => true,
+        }
+    }
+
+    fn spawn_refresh(&self, cache_key: CacheKey, request: T, refresh: Refresh) {
         let name = self.config.name();
 
         let mut refreshes = self.refreshes.lock();
@@ -429,65 +559,27 @@ impl<T: CacheItemRequest> Cacher<T> {
             let transaction = sentry::start_transaction(ctx);
             sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
 
-            let item = this.compute(request, &cache_key, true).await;
+            let item = this.compute(request, &cache_key, Some(refresh)).await;
+
+            // the stale item was kept, and is served from the file system cache until it expires
+            if refresh == Refresh::Stale && item.is_err() {
+                transaction.finish();
+                return;
+            }
 
             // we just created a fresh cache, so use the initial expiration times
             let expiration = ExpirationTime::for_fresh_status(&this.config, &item);
             let value = (expiration.as_instant(), item);
 
             // refresh the memory cache with the newly refreshed result
-            this.cache.insert(cache_key, value).await;
+            if this.fits_in_memory(&value.1) {
+                this.cache.insert(cache_key, value).await;
+            } else {
+                this.cache.invalidate(&cache_key).await;
+            }
 
             transaction.finish();
         };
         tokio::spawn(task.bind_hub(Hub::new_from_top(Hub::current())));
     }
 }
-
-fn persist_tempfile(
-    mut temp_file: NamedTempFile,
-    cache_path: &Path,
-) -> std::io::Result<std::fs::File> {
-    let parent = cache_path.parent().ok_or_else(|| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "no parent directory to persist item",
-        )
-    })?;
-
-    // The `cleanup` process could potentially remove the parent directories we are
-    // operating in, so be defensive here and retry the fs operations.
-    const MAX_RETRIES: usize = 2;
-    let mut retries = 0;
-    let file = loop {
-        retries += 1;
-
-        if let Err(e) = std::fs::create_dir_all(parent) {
-            sentry::with_scope(
-                |scope| scope.set_extra("path", parent.display().to_string().into()),
-                || tracing::error!("Failed to create cache directory: {:?}", e),
-            );
-            if retries > MAX_RETRIES {
-                return Err(e);
-            }
-            continue;
-        }
-
-        match temp_file.persist(cache_path) {
-            Ok(file) => break file,
-            Err(e) => {
-                temp_file = e.file;
-                let err = e.error;
-                sentry::with_scope(
-                    |scope| scope.set_extra("path", cache_path.display().to_string().into()),
-                    || tracing::error!("Failed to create cache file: {:?}", err),
-                );
-                if retries > MAX_RETRIES {
-                    return Err(err);
-                }
-                continue;
-            }
-        }
-    };
-    Ok(file)
-}
@@ -12,7 +12,9 @@
 //! - An in-memory caching layer which is currently used for request coalescing
 //!   (deduplicating concurrent accesses).
 //! - A file-system layer that persists the results of downloads and computations to the file system,
-//!   and also persists errors happening during those.
+//!   and also persists errors happening during those. Files are read and written through a
+//!   [`CacheStorage`], which is a [`FileSystemStorage`] in the cache directory unless another
+//!   storage is plugged in with [`Cache::with_storage`].
 //! - A shared cache layer which is backed by a shared GCS bucket, to more evenly distribute
 //!   the load to multiple Symbolicator instances, and to help the fresh startup path without any
 //!   file-system caches available. The shared-cache layer does not persist errors, but only
@@ -27,6 +29,10 @@
 //! - The freshly computed item will be stored on the file-system and uploaded to the shared cache
 //!   in case both caches are enabled.
 //!
+//! Uploads to the shared cache happen asynchronously in the background, so they never block the
+//! request that computed the item. Multiple uploads of the same item are deduplicated while one of
+//! them is still queued or in flight.
+//!
 //! ### Metrics
 //!
 //! We collect a couple of metrics, each of those is tagged with a `cache` field that corresponds to
@@ -37,14 +43,23 @@
 //! - `caches.file.hit`: Accesses served by the file-system layer.
 //! - `services.shared_cache.fetch(hit:true)`: Accesses served by the shared-cache layer.
 //! - `caches.computation`: Actual computations being run, and not served by any of the caching layers.
+//! - `caches.tier.hit`: Accesses tagged with the `tier` that served them, one of `memory`, `local`,
+//!   `shared` or `computed`. This makes it easy to derive hit ratios for each layer.
 //!
 //! NOTE: The sum of shared-cache hits and computations can exceed the number of cache misses of
-//! previous layers in case of lazy cache recomputation.
+//! previous layers in case of lazy cache recomputation. `caches.tier.hit` does not count those
+//! background recomputations.
 //!
 //! Various other metrics are being collected as well, including:
 //! - `caches.file.size`: A histogram for the size (in bytes) of the successfully loaded / written cache files.
 //! - `caches.file.write`: The number of caches being written to disk.
 //!   This should match `caches.computation` if the file-system layer is enabled.
+//! - `caches.file.stale_served`: Stale entries served during their `stale_while_revalidate` grace
+//!   period, each of which schedules a background refresh.
+//! - `caches.size.files`, `caches.size.bytes`, `caches.size.oldest_entry_age`: Gauges for the
+//!   number of files, their total size, and the age (in seconds) of the oldest file per cache.
+//!   These are reported periodically if `caches.size_metrics_interval` is configured.
+//!   The same scan also categorizes entries, which is published as a [`CacheReport`] per cache.
 //! - TODO: list all the other metrics that are missing here :-)
 //!
 //! ### Configuration
@@ -74,6 +89,11 @@
 //! A "successful" entry is considered immutable and it will be reused indefinitely as long as it
 //! is being actively used.
 //!
+//! Once a "successful" entry was unused for longer than `max_unused_for`, it is treated as a miss.
+//! The `stale_while_revalidate` option adds a grace period to this, during which the stale entry
+//! is still served, while a fresh one is computed in the background like for outdated
+//! [`CacheVersions`]. The lazy refresh limits apply to these computations as well.
+//!
 //! The [`SharedCacheConfig`] is optional, and no shared cache will be used when it is absent. The
 //! configuration is done by providing a GCS bucket and `service_account_path`. A file-system based
 //! shared cache implementation exists for testing purposes.
@@ -154,25 +174,39 @@ use std::io;
 use std::sync::atomic::AtomicIsize;
 use std::sync::Arc;
 
+use tokio::sync::Semaphore;
+
 use crate::config::Config;
 
 mod cache_error;
 mod cache_key;
 mod cleanup;
 mod config;
+mod disk_space;
 mod fs;
+mod invalidate;
 mod memory;
+mod scanner;
 mod shared_cache;
+mod stats;
+mod storage;
 #[cfg(test)]
 mod tests;
 
 pub use cache_error::{CacheEntry, CacheError};
 pub use cache_key::{CacheKey, CacheKeyBuilder};
-pub use cleanup::cleanup;
+pub use cleanup::{cleanup, CleanupStats, FileStats};
 pub use config::CacheName;
+pub use disk_space::DiskPressure;
 pub use fs::{Cache, ExpirationStrategy, ExpirationTime};
+pub use invalidate::ObjectIdFilter;
 pub use memory::{CacheItemRequest, CacheVersions, Cacher};
-pub use shared_cache::{CacheStoreReason, SharedCacheConfig, SharedCacheRef, SharedCacheService};
+pub use shared_cache::{
+    CacheStoreReason, ResultStorageConfig, ResultStore, ResultStoreRef, SharedCacheConfig,
+    SharedCacheRef, SharedCacheService,
+};
+pub use stats::{AgeBucket, CacheReport, CacheStats, CacheStatsRegistry, AGE_BUCKETS};
+pub use storage::{CacheStorage, FileSystemStorage, StoredFile};
 
 pub struct Caches {
     /// Caches for object files, used by [`crate::services::objects::ObjectsActor`].
@@ -183,6 +217,8 @@ pub struct Caches {
     pub auxdifs: Cache,
     /// Caches for il2cpp line mapping files, used by [`crate::services::il2cpp::Il2cppService`].
     pub il2cpp: Cache,
+    /// Caches for ProGuard mapping files, used by [`crate::services::proguard::ProguardService`].
+    pub proguard: Cache,
     /// Caches for [`symbolic::symcache::SymCache`], used by
     /// [`crate::services::symcaches::SymCacheActor`].
     pub symcaches: Cache,
@@ -210,6 +246,10 @@ impl Caches {
         let max_lazy_recomputations = Arc::new(AtomicIsize::new(
             config.caches.derived.max_lazy_recomputations.max(1),
         ));
+        // Shared by all derived caches converting on blocking threads.
+        let conversion_permits = Arc::new(Semaphore::new(
+            config.caches.derived.max_concurrent_conversions.max(1),
+        ));
 
         // NOTE: We default all the caches to ~200 KiB.
         // A cache item with all its structures is at least ~100 bytes, so this gives us an
@@ -218,6 +258,9 @@ impl Caches {
         let default_cap = 200 * 1024;
         let in_memory = &config.caches.in_memory;
 
+        // Shared by all caches of downloaded files, and updated by the disk space watchdog.
+        let disk_pressure = DiskPressure::new(config.caches.disk_space.large_file_size);
+
         Ok(Self {
             objects: Cache::from_config(
                 CacheName::Objects,
@@ -225,7 +268,8 @@ impl Caches {
                 config.caches.downloaded.into(),
                 max_lazy_redownloads.clone(),
                 default_cap,
-            )?,
+            )?
+            .with_disk_pressure(disk_pressure.clone()),
             object_meta: Cache::from_config(
                 CacheName::ObjectMeta,
                 config,
@@ -239,28 +283,40 @@ impl Caches {
                 config.caches.downloaded.into(),
                 max_lazy_redownloads.clone(),
                 default_cap,
-            )?,
+            )?
+            .with_disk_pressure(disk_pressure.clone()),
             il2cpp: Cache::from_config(
                 CacheName::Il2cpp,
                 config,
                 config.caches.downloaded.into(),
                 max_lazy_redownloads.clone(),
                 default_cap,
-            )?,
+            )?
+            .with_disk_pressure(disk_pressure.clone()),
+            proguard: Cache::from_config(
+                CacheName::Proguard,
+                config,
+                config.caches.downloaded.into(),
+                max_lazy_redownloads.clone(),
+                default_cap,
+            )?
+            .with_disk_pressure(disk_pressure.clone()),
             symcaches: Cache::from_config(
                 CacheName::Symcaches,
                 config,
                 config.caches.derived.into(),
                 max_lazy_recomputations.clone(),
                 default_cap,
-            )?,
+            )?
+            .with_conversion_permits(conversion_permits.clone()),
             cficaches: Cache::from_config(
                 CacheName::Cficaches,
                 config,
                 config.caches.derived.into(),
                 max_lazy_recomputations.clone(),
                 in_memory.cficaches_capacity,
-            )?,
+            )?
+            .with_conversion_permits(conversion_permits),
             ppdb_caches: Cache::from_config(
                 CacheName::PpdbCaches,
                 config,
@@ -281,7 +337,8 @@ impl Caches {
                 config.caches.downloaded.into(),
                 max_lazy_redownloads,
                 default_cap,
-            )?,
+            )?
+            .with_disk_pressure(disk_pressure),
             diagnostics: Cache::from_config(
                 CacheName::Diagnostics,
                 config,
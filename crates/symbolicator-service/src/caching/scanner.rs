@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use super::{Cache, CacheName, CacheStats, CacheStatsRegistry, CacheStorage, Caches, StoredFile};
+
+/// The number of shards walked by a single step of the [`IncrementalScan`].
+const SHARDS_PER_STEP: usize = 16;
+
+impl Cache {
+    /// Walks the whole cache storage and gathers [`CacheStats`] about it.
+    ///
+    /// Returns `None` if this cache is disabled.
+    pub fn stats(&self) -> io::Result<Option<CacheStats>> {
+        let Some(storage) = self.storage.as_deref() else {
+            return Ok(None);
+        };
+
+        let mut stats = CacheStats::default();
+        scan_prefix(storage, "", SystemTime::now(), &mut stats)?;
+        Ok(Some(stats))
+    }
+}
+
+fn scan_prefix(
+    storage: &dyn CacheStorage,
+    prefix: &str,
+    now: SystemTime,
+    stats: &mut CacheStats,
+) -> io::Result<()> {
+    storage.iterate(prefix, &mut |file: StoredFile| {
+        stats.add_file(storage, &file, now)
+    })
+}
+
+/// Lists the shards of a cache storage, which are the keys two levels below its root.
+///
+/// For regular caches, these are the hash prefix directories within each cache version.
+fn list_shards(storage: &dyn CacheStorage) -> io::Result<Vec<String>> {
+    let mut shards = vec![];
+    for key in storage.list("")? {
+        let children = storage.list(&key)?;
+        if children.is_empty() {
+            shards.push(key);
+        } else {
+            shards.extend(children);
+        }
+    }
+
+    Ok(shards)
+}
+
+/// Walks a cache in small steps, one batch of shards at a time.
+///
+/// The stats of each shard are kept until it is walked again in the next round, so that the
+/// stats of the whole cache can be published after every step.
+struct IncrementalScan {
+    cache: Cache,
+    shards: BTreeMap<String, CacheStats>,
+    pending: Vec<String>,
+    complete: bool,
+}
+
+impl IncrementalScan {
+    fn new(cache: Cache) -> Self {
+        Self {
+            cache,
+            shards: BTreeMap::new(),
+            pending: vec![],
+            complete: false,
+        }
+    }
+
+    /// Walks the next batch of shards, returning `true` if the current round is finished.
+    fn step(&mut self) -> io::Result<bool> {
+        let Some(storage) = self.cache.storage.clone() else {
+            return Ok(true);
+        };
+        if self.pending.is_empty() {
+            let shards = list_shards(storage.as_ref())?;
+            // Forget about the shards that were removed since the last round.
+            self.shards.retain(|shard, _| shards.contains(shard));
+            self.pending = shards;
+        }
+
+        let now = SystemTime::now();
+        for _ in 0..SHARDS_PER_STEP {
+            let Some(shard) = self.pending.pop() else {
+                break;
+            };
+            let mut stats = CacheStats::default();
+            scan_prefix(storage.as_ref(), &shard, now, &mut stats)?;
+            self.shards.insert(shard, stats);
+        }
+
+        let finished = self.pending.is_empty();
+        self.complete |= finished;
+        Ok(finished)
+    }
+
+    /// Sums up the stats of all the shards walked so far.
+    fn stats(&self) -> CacheStats {
+        let mut total = CacheStats::default();
+        for stats in self.shards.values() {
+            total += *stats;
+        }
+        total
+    }
+
+    /// Walks the cache step by step on blocking threads, publishing its stats after every step.
+    ///
+    /// Returns the stats of the cache once the round is finished.
+    async fn run_round(
+        scan: &Arc<Mutex<Self>>,
+        registry: &CacheStatsRegistry,
+    ) -> io::Result<CacheStats> {
+        loop {
+            let scan = scan.clone();
+            let registry = registry.clone();
+            let step = tokio::task::spawn_blocking(move || -> io::Result<_> {
+                let mut scan = scan.lock().unwrap();
+                let finished = scan.step()?;
+                let stats = scan.stats();
+                registry.publish(scan.cache.name, stats, scan.complete);
+                Ok(finished.then_some(stats))
+            });
+
+            if let Some(stats) = step.await?? {
+                return Ok(stats);
+            }
+        }
+    }
+}
+
+/// Emits the [`CacheStats`] of the given cache as gauges.
+fn record_size_metrics(name: CacheName, stats: &CacheStats) {
+    let name = name.as_ref();
+    metric!(gauge("caches.size.files") = stats.files, "cache" => name);
+    metric!(gauge("caches.size.bytes") = stats.bytes, "cache" => name);
+    if let Some(age) = stats.oldest_entry_age() {
+        metric!(gauge("caches.size.oldest_entry_age") = age.as_secs(), "cache" => name);
+    }
+}
+
+impl Caches {
+    /// Spawns a background task onto `runtime` which continuously walks all the file-system
+    /// caches, and publishes their [`CacheStats`] to `registry`.
+    ///
+    /// Caches are walked incrementally, a few shards at a time, and their stats are published
+    /// after every step. Once all caches have been walked, their size is reported as gauges, and
+    /// the next round starts after `interval`.
+    pub fn spawn_size_scanner(
+        &self,
+        interval: Duration,
+        registry: CacheStatsRegistry,
+        runtime: &tokio::runtime::Handle,
+    ) {
+        // Destructure so we do not accidentally forget to scan one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            proguard,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = &self;
+
+        let scans: Vec<_> = [
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            proguard,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        ]
+        .into_iter()
+        .filter(|cache| cache.storage.is_some())
+        .map(|cache| {
+            let scan = IncrementalScan::new(cache.clone());
+            (cache.name, Arc::new(Mutex::new(scan)))
+        })
+        .collect();
+
+        runtime.spawn(async move {
+            loop {
+                for (name, scan) in &scans {
+                    match IncrementalScan::run_round(scan, &registry).await {
+                        Ok(stats) => record_size_metrics(*name, &stats),
+                        Err(err) => {
+                            let stderr: &dyn std::error::Error = &err;
+                            tracing::error!(stderr, cache = %name, "Failed to scan cache directory");
+                        }
+                    }
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
@@ -1,6 +1,8 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use symbolicator_sources::S3SourceKey;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilesystemSharedCacheConfig {
@@ -22,11 +24,25 @@ pub struct GcsSharedCacheConfig {
     pub service_account_path: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3SharedCacheConfig {
+    /// Name of the S3 bucket.
+    pub bucket: String,
+
+    /// The region and credentials to access the bucket with.
+    ///
+    /// These are given like for S3 sources, with `region`, `aws_credentials_provider`,
+    /// `access_key` and `secret_key`.
+    #[serde(flatten)]
+    pub source_key: S3SourceKey,
+}
+
 /// The backend to use for the shared cache.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SharedCacheBackendConfig {
     Gcs(GcsSharedCacheConfig),
+    S3(S3SharedCacheConfig),
     Filesystem(FilesystemSharedCacheConfig),
 }
 
@@ -66,3 +82,22 @@ fn default_max_upload_queue_size() -> usize {
 fn default_max_concurrent_uploads() -> usize {
     20
 }
+
+/// Durable storage for the responses of finished symbolication requests.
+///
+/// Responses are kept in memory only for a short while after their request finished. With this
+/// storage, they can still be polled after that, or after the instance was restarted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultStorageConfig {
+    /// For how long stored responses can be polled after their request finished.
+    #[serde(default = "default_result_storage_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+
+    /// The backend to store the responses in.
+    #[serde(flatten)]
+    pub backend: SharedCacheBackendConfig,
+}
+
+fn default_result_storage_ttl() -> Duration {
+    Duration::from_secs(7 * 24 * 3600)
+}
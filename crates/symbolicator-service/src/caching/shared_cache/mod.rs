@@ -5,15 +5,18 @@
 //! lives closer to Symbolicator. Expensive computations related to the computation of derived
 //! caches may also be saved via this shared cache.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryInto;
 use std::fmt;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context as _, Error, Result};
+use aws_sdk_s3::types::ByteStream;
+use aws_smithy_http::body::SdkBody;
 use futures::{Future, TryStreamExt};
 use gcp_auth::{AuthenticationManager, CustomServiceAccount, Token};
+use parking_lot::Mutex;
 use reqwest::{Body, Client, StatusCode};
 use sentry::protocol::Context;
 use sentry::{Hub, SentryFutureExt};
@@ -25,6 +28,7 @@ use tokio::sync::{mpsc, oneshot, OnceCell};
 use tokio_util::io::{ReaderStream, StreamReader};
 use url::Url;
 
+use crate::services::download::s3::{self, S3Error};
 use crate::services::download::MeasureSourceDownloadGuard;
 use crate::utils::futures::CancelOnDrop;
 use crate::utils::gcs::{self, GcsError};
@@ -32,9 +36,14 @@ use crate::utils::gcs::{self, GcsError};
 use super::CacheName;
 
 pub mod config;
+mod result_store;
 
-pub use config::SharedCacheConfig;
-use config::{FilesystemSharedCacheConfig, GcsSharedCacheConfig, SharedCacheBackendConfig};
+use config::{
+    FilesystemSharedCacheConfig, GcsSharedCacheConfig, S3SharedCacheConfig,
+    SharedCacheBackendConfig,
+};
+pub use config::{ResultStorageConfig, SharedCacheConfig};
+pub use result_store::{cleanup_results, ResultStore, ResultStoreRef};
 
 // TODO: get timeouts from global config?
 const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
@@ -286,6 +295,15 @@ impl GcsState {
             }
         }
 
+        self.upload(key, content).await
+    }
+
+    /// Uploads a file to GCS, unless an object with the same `key` already exists.
+    async fn upload(
+        &self,
+        key: &str,
+        content: ByteView<'static>,
+    ) -> Result<SharedCacheStoreResult, CacheError> {
         let total_bytes = content.len() as u64;
         let token = self.get_token().await?;
         let mut url =
@@ -340,6 +358,178 @@ impl GcsState {
     }
 }
 
+struct S3State {
+    config: S3SharedCacheConfig,
+    client: aws_sdk_s3::Client,
+}
+
+impl fmt::Debug for S3State {
+    // The config contains the secret key, so only the bucket is printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3State")
+            .field("bucket", &self.config.bucket)
+            .field("region", &self.config.source_key.region)
+            .finish()
+    }
+}
+
+impl S3State {
+    async fn new(config: S3SharedCacheConfig) -> Self {
+        let client = s3::create_client(&config.source_key).await;
+        Self { config, client }
+    }
+
+    fn configure_scope(&self, key: &str) {
+        sentry::configure_scope(|scope| {
+            let mut map = BTreeMap::new();
+            map.insert("bucket".to_string(), self.config.bucket.clone().into());
+            map.insert("key".to_string(), key.into());
+            scope.set_context("S3 Shared Cache", Context::Other(map));
+        });
+    }
+
+    /// Fetches item from shared cache if available and copies them to the writer.
+    ///
+    /// # Returns
+    ///
+    /// If successful the number of bytes written to the writer are returned.
+    async fn fetch<W>(&self, key: &str, writer: &mut W) -> Result<Option<u64>, CacheError>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        self.configure_scope(key);
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.config.bucket)
+            .key(key)
+            .send();
+        let request = tokio::time::timeout(CONNECT_TIMEOUT, request);
+        let request = measure_download_time("services.shared_cache.fetch.connect", "s3", request);
+
+        match request.await {
+            Ok(Ok(response)) => {
+                tracing::trace!("Success hitting shared_cache S3 {}", key);
+                let stream = response
+                    .body
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+                let mut stream = StreamReader::new(stream);
+                let res = io::copy(&mut stream, writer)
+                    .await
+                    .context("IO Error streaming S3 bytes to writer")
+                    .map_err(CacheError::Other);
+                Some(res).transpose()
+            }
+            Ok(Err(err)) => match S3Error::from(err) {
+                S3Error::NoSuchKey(_) | S3Error::NotFound(_) => Ok(None),
+                err => {
+                    tracing::trace!("Error in shared_cache S3 response for {}", key);
+                    Err(err).context("Bad S3 response for shared_cache")?
+                }
+            },
+            Err(_) => Err(CacheError::ConnectTimeout),
+        }
+    }
+
+    async fn exists(&self, cache: CacheName, key: &str) -> Result<bool, CacheError> {
+        let request = self
+            .client
+            .head_object()
+            .bucket(&self.config.bucket)
+            .key(key)
+            .send();
+        let request = tokio::time::timeout(CONNECT_TIMEOUT, request);
+
+        let ret = match request.await {
+            Ok(Ok(_)) => Ok(true),
+            Ok(Err(err)) => match S3Error::from(err) {
+                S3Error::NoSuchKey(_) | S3Error::NotFound(_) => Ok(false),
+                err => Err(err).context("Error connecting to S3")?,
+            },
+            Err(_) => Err(CacheError::ConnectTimeout),
+        };
+        let status = match ret {
+            Ok(_) => "ok",
+            Err(CacheError::ConnectTimeout) => "connect-timeout",
+            Err(_) => "error",
+        };
+        metric!(
+            counter("services.shared_cache.exists") += 1,
+            "cache" => cache.as_ref(),
+            "status" => status
+        );
+        ret
+    }
+
+    /// Stores a file on S3.
+    ///
+    /// S3 has no conditional uploads, so like on GCS, a refreshed file is only uploaded if a
+    /// prior existence check does not find it.
+    async fn store(
+        &self,
+        cache: CacheName,
+        key: &str,
+        content: ByteView<'static>,
+        reason: CacheStoreReason,
+    ) -> Result<SharedCacheStoreResult, CacheError> {
+        self.configure_scope(key);
+        if reason == CacheStoreReason::Refresh {
+            match self.exists(cache, key).await {
+                Ok(true) => return Ok(SharedCacheStoreResult::Skipped),
+                Ok(false) | Err(CacheError::ConnectTimeout) => (),
+                Err(CacheError::Other(err)) => {
+                    sentry::capture_error(&*err);
+                }
+            }
+        }
+
+        self.upload(key, content).await
+    }
+
+    /// Uploads a file to S3, replacing an object with the same `key`.
+    async fn upload(
+        &self,
+        key: &str,
+        content: ByteView<'static>,
+    ) -> Result<SharedCacheStoreResult, CacheError> {
+        let total_bytes = content.len() as u64;
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.config.bucket)
+            .key(key)
+            // The body is streamed, so S3 needs to be told its length upfront.
+            .content_length(total_bytes as i64)
+            .body(s3_upload_body(content))
+            .send();
+        let request = tokio::time::timeout(STORE_TIMEOUT, request);
+        let request = measure_download_time("services.shared_cache.store.upload", "s3", request);
+
+        match request.await {
+            Ok(Ok(_)) => {
+                tracing::trace!("Success hitting shared_cache S3 {}", key);
+                Ok(SharedCacheStoreResult::Written(total_bytes))
+            }
+            Ok(Err(err)) => {
+                tracing::trace!("Error in shared_cache S3 response for {}", key);
+                Err(S3Error::from(err)).context("Bad S3 response for shared_cache")?
+            }
+            Err(_) => Err(CacheError::ConnectTimeout),
+        }
+    }
+}
+
+/// Creates the body of an S3 upload, which streams the `content` instead of copying it.
+///
+/// The body is recreated from the `content` if the SDK retries the upload.
+fn s3_upload_body(content: ByteView<'static>) -> ByteStream {
+    let body = SdkBody::retryable(move || {
+        let stream = ReaderStream::new(std::io::Cursor::new(content.clone()));
+        SdkBody::from(hyper::Body::wrap_stream(stream))
+    });
+    ByteStream::new(body)
+}
+
 impl FilesystemSharedCacheConfig {
     /// Fetches item from shared cache if available and copies them to the writer.
     ///
@@ -432,6 +622,7 @@ impl fmt::Display for SharedCacheStoreResult {
 #[allow(clippy::large_enum_variant)]
 enum SharedCacheBackend {
     Gcs(Arc<GcsState>),
+    S3(Arc<S3State>),
     Fs(FilesystemSharedCacheConfig),
 }
 
@@ -453,6 +644,9 @@ impl SharedCacheBackend {
                     }
                 }
             }
+            SharedCacheBackendConfig::S3(cfg) => {
+                Some(SharedCacheBackend::S3(Arc::new(S3State::new(cfg).await)))
+            }
             // TODO: We could check if we can write in the configured directory here, but
             // this is only test backend so not very important.
             SharedCacheBackendConfig::Filesystem(cfg) => Some(SharedCacheBackend::Fs(cfg)),
@@ -462,6 +656,7 @@ impl SharedCacheBackend {
     fn name(&self) -> &'static str {
         match self {
             Self::Gcs(_) => "GCS",
+            Self::S3(_) => "S3",
             Self::Fs(_) => "filesystem",
         }
     }
@@ -504,13 +699,21 @@ impl AsRef<str> for CacheStoreReason {
 
 pub type SharedCacheRef = Arc<OnceCell<SharedCacheService>>;
 
+/// The set of keys which are currently queued for or in the process of being uploaded.
+type PendingUploads = Arc<Mutex<HashSet<String>>>;
+
 /// A shared cache service.
 ///
+/// This is the remote tier of a two-tier cache topology: Local cache misses read through to the
+/// shared cache via [`fetch`](Self::fetch), and items computed locally are written back
+/// asynchronously via [`store`](Self::store).
+///
 /// Initialising is asynchronous since it may take some time.
 #[derive(Debug, Clone)]
 pub struct SharedCacheService {
     backend: Arc<SharedCacheBackend>,
     upload_queue_tx: mpsc::Sender<UploadMessage>,
+    pending_uploads: PendingUploads,
     runtime: tokio::runtime::Handle,
 }
 
@@ -534,13 +737,20 @@ impl SharedCacheService {
         let (tx, rx) = mpsc::channel(config.max_upload_queue_size);
         if let Some(backend) = SharedCacheBackend::maybe_new(config.backend).await {
             let backend = Arc::new(backend);
+            let pending_uploads = PendingUploads::default();
             tokio::spawn(
-                Self::upload_worker(rx, backend.clone(), config.max_concurrent_uploads)
-                    .bind_hub(Hub::new_from_top(Hub::current())),
+                Self::upload_worker(
+                    rx,
+                    backend.clone(),
+                    pending_uploads.clone(),
+                    config.max_concurrent_uploads,
+                )
+                .bind_hub(Hub::new_from_top(Hub::current())),
             );
             let _ = cache.set(SharedCacheService {
                 backend,
                 upload_queue_tx: tx,
+                pending_uploads,
                 runtime,
             });
         }
@@ -550,6 +760,7 @@ impl SharedCacheService {
     async fn upload_worker(
         mut work_rx: mpsc::Receiver<UploadMessage>,
         backend: Arc<SharedCacheBackend>,
+        pending_uploads: PendingUploads,
         max_concurrent_uploads: usize,
     ) {
         let (done_tx, mut done_rx) = mpsc::channel::<()>(max_concurrent_uploads);
@@ -559,8 +770,13 @@ impl SharedCacheService {
                 Some(message) = work_rx.recv(), if uploads_counter > 0 => {
                     uploads_counter -= 1;
                     tokio::spawn(
-                        Self::single_uploader(done_tx.clone(), backend.clone(), message)
-                            .bind_hub(Hub::new_from_top(Hub::current()))
+                        Self::single_uploader(
+                            done_tx.clone(),
+                            backend.clone(),
+                            pending_uploads.clone(),
+                            message,
+                        )
+                        .bind_hub(Hub::new_from_top(Hub::current()))
                     );
                     let uploads_in_flight: u64 = (max_concurrent_uploads - uploads_counter) as u64;
                     metric!(gauge("services.shared_cache.uploads_in_flight") = uploads_in_flight);
@@ -580,6 +796,7 @@ impl SharedCacheService {
     async fn single_uploader(
         done_tx: mpsc::Sender<()>,
         backend: Arc<SharedCacheBackend>,
+        pending_uploads: PendingUploads,
         message: UploadMessage,
     ) {
         let UploadMessage {
@@ -600,8 +817,10 @@ impl SharedCacheService {
 
         let res = match *backend {
             SharedCacheBackend::Gcs(ref state) => state.store(cache, &key, content, reason).await,
-            SharedCacheBackend::Fs(ref cfg) => cfg.store(key, content).await,
+            SharedCacheBackend::S3(ref state) => state.store(cache, &key, content, reason).await,
+            SharedCacheBackend::Fs(ref cfg) => cfg.store(key.clone(), content).await,
         };
+        pending_uploads.lock().remove(&key);
         match res {
             Ok(op) => {
                 metric!(
@@ -689,6 +908,14 @@ impl SharedCacheService {
                     .await
                     .unwrap_or(Err(CacheError::ConnectTimeout))
             }
+            SharedCacheBackend::S3(state) => {
+                let state = Arc::clone(state);
+                let future = async move { state.fetch(&key, &mut file).await };
+
+                CancelOnDrop::new(self.runtime.spawn(future.bind_hub(sentry::Hub::current())))
+                    .await
+                    .unwrap_or(Err(CacheError::ConnectTimeout))
+            }
             SharedCacheBackend::Fs(cfg) => cfg.fetch(&key, &mut file).await,
         };
         match res {
@@ -753,6 +980,9 @@ impl SharedCacheService {
     /// If [`CacheStoreReason::Refresh`] is used the implementation will trade off an extra
     /// request to check if the file already exists before uploading.  This is racy but a
     /// good tradeoff for refreshed stores.
+    ///
+    /// Stores are deduplicated: If an upload for the same `key` is already queued or in
+    /// flight, this is a noop and the returned receiver resolves into an [`Err`].
     pub fn store(
         &self,
         cache: CacheName,
@@ -765,15 +995,25 @@ impl SharedCacheService {
                 self.upload_queue_tx.capacity() as u64
         );
         let (done_tx, done_rx) = oneshot::channel::<()>();
+        let key = format!("{}/{key}", cache.as_ref());
+        if !self.pending_uploads.lock().insert(key.clone()) {
+            metric!(
+                counter("services.shared_cache.store.deduplicated") += 1,
+                "cache" => cache.as_ref(),
+                "reason" => reason.as_ref(),
+            );
+            return done_rx;
+        }
         self.upload_queue_tx
             .try_send(UploadMessage {
                 cache,
-                key: format!("{}/{key}", cache.as_ref()),
+                key: key.clone(),
                 content,
                 done_tx,
                 reason,
             })
             .unwrap_or_else(|_| {
+                self.pending_uploads.lock().remove(&key);
                 metric!(counter("services.shared_cache.store.dropped") += 1);
                 tracing::error!("Shared cache upload queue full");
             });
@@ -799,6 +1039,22 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_s3_upload_body() {
+        let content = ByteView::from_vec(b"hello world\n".repeat(10_000));
+        let body = s3_upload_body(content.clone()).into_inner();
+
+        // The content is streamed, rather than copied into the body.
+        assert!(body.bytes().is_none());
+
+        // The body can be recreated, so that failed uploads can be retried.
+        let retry = body.try_clone().unwrap();
+        for body in [body, retry] {
+            let uploaded = ByteStream::new(body).collect().await.unwrap().into_bytes();
+            assert_eq!(&uploaded[..], content.as_slice());
+        }
+    }
+
     async fn wait_init(service: &SharedCacheRef) -> &SharedCacheService {
         const MAX_DELAY: Duration = Duration::from_secs(3);
         let start = Instant::now();
@@ -907,6 +1163,53 @@ mod tests {
         assert_eq!(data, b"cache data");
     }
 
+    #[tokio::test]
+    async fn test_filesystem_store_deduplicated() {
+        symbolicator_test::setup();
+        let dir = symbolicator_test::tempdir();
+
+        let key = "global/some_item";
+
+        let cfg = SharedCacheConfig {
+            max_concurrent_uploads: 10,
+            max_upload_queue_size: 10,
+            backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
+                path: dir.path().to_path_buf(),
+            }),
+        };
+        let svc = SharedCacheService::new(Some(cfg), tokio::runtime::Handle::current());
+        let svc = wait_init(&svc).await;
+
+        let first = svc.store(
+            CacheName::Objects,
+            key,
+            ByteView::from_slice(b"cache data"),
+            CacheStoreReason::New,
+        );
+        // The first upload is still pending, so this one is skipped.
+        let second = svc.store(
+            CacheName::Objects,
+            key,
+            ByteView::from_slice(b"other data"),
+            CacheStoreReason::New,
+        );
+        assert!(second.await.is_err());
+        first.await.unwrap();
+
+        let cache_path = dir.path().join("objects/global/some_item");
+        let data = fs::read(&cache_path).await.unwrap();
+        assert_eq!(data, b"cache data");
+
+        // Once the upload completed, the same key can be stored again.
+        let third = svc.store(
+            CacheName::Objects,
+            key,
+            ByteView::from_slice(b"cache data"),
+            CacheStoreReason::Refresh,
+        );
+        third.await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_gcs_fetch_not_found() {
         symbolicator_test::setup();
@@ -0,0 +1,225 @@
+//! Durable storage for the responses of finished symbolication requests.
+//!
+//! The storage uses the same backends as the shared cache, but in contrast to cache items,
+//! responses can not be recomputed. They are stored below the `results/` prefix, keyed by their
+//! request ID.
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use symbolic::common::ByteView;
+use tokio::sync::OnceCell;
+
+use super::config::{ResultStorageConfig, SharedCacheBackendConfig};
+use super::{CacheError, SharedCacheBackend};
+use crate::caching::fs::catch_not_found;
+use crate::caching::FileStats;
+
+/// A reference to the [`ResultStore`], which is set once its backend is initialized.
+pub type ResultStoreRef = Arc<OnceCell<ResultStore>>;
+
+/// Stores the responses of finished requests, so they can be polled long after.
+#[derive(Debug)]
+pub struct ResultStore {
+    backend: SharedCacheBackend,
+    ttl: Duration,
+}
+
+impl ResultStore {
+    /// Creates the result store, if it is configured.
+    ///
+    /// Like the shared cache, the backend is initialized in the background, and the returned
+    /// reference stays empty until it is ready, or if it fails to initialize.
+    pub fn new(
+        config: Option<ResultStorageConfig>,
+        runtime: &tokio::runtime::Handle,
+    ) -> ResultStoreRef {
+        let store = ResultStoreRef::default();
+        if let Some(config) = config {
+            let cell = store.clone();
+            runtime.spawn(async move {
+                if let Some(backend) = SharedCacheBackend::maybe_new(config.backend).await {
+                    let ttl = config.ttl;
+                    let _ = cell.set(ResultStore { backend, ttl });
+                }
+            });
+        }
+        store
+    }
+
+    /// For how long stored responses can be polled after their request finished.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Stores the serialized response under the given `key`.
+    ///
+    /// Errors are logged and otherwise ignored, as the response is still available in memory
+    /// for a while.
+    pub async fn store(&self, key: &str, content: Vec<u8>) {
+        let key = format!("results/{key}");
+        let content = ByteView::from_vec(content);
+        let res = match self.backend {
+            SharedCacheBackend::Gcs(ref state) => state.upload(&key, content).await,
+            SharedCacheBackend::S3(ref state) => state.upload(&key, content).await,
+            SharedCacheBackend::Fs(ref cfg) => cfg.store(key, content).await,
+        };
+
+        let status = match res {
+            Ok(_) => "ok",
+            Err(CacheError::ConnectTimeout) => "connect-timeout",
+            Err(CacheError::Other(err)) => {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(stderr, "Error storing response on {}", self.backend.name());
+                "error"
+            }
+        };
+        metric!(counter("services.result_store.store") += 1, "status" => status);
+    }
+
+    /// Fetches the serialized response stored under the given `key`.
+    ///
+    /// Returns `None` if no response was stored, or if it can not be fetched.
+    pub async fn fetch(&self, key: &str) -> Option<Vec<u8>> {
+        let key = format!("results/{key}");
+        let mut content = Vec::new();
+        let res = match self.backend {
+            SharedCacheBackend::Gcs(ref state) => state.fetch(&key, &mut content).await,
+            SharedCacheBackend::S3(ref state) => state.fetch(&key, &mut content).await,
+            SharedCacheBackend::Fs(ref cfg) => cfg.fetch(&key, &mut content).await,
+        };
+
+        let (status, found) = match res {
+            Ok(Some(_)) => ("hit", true),
+            Ok(None) => ("miss", false),
+            Err(CacheError::ConnectTimeout) => ("connect-timeout", false),
+            Err(CacheError::Other(err)) => {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(
+                    stderr,
+                    "Error fetching response from {}",
+                    self.backend.name()
+                );
+                ("error", false)
+            }
+        };
+        metric!(counter("services.result_store.fetch") += 1, "status" => status);
+
+        found.then_some(content)
+    }
+}
+
+/// Removes the expired responses from a filesystem result storage.
+///
+/// Responses expire `ttl` after they were stored, which is the modification time of their file.
+/// GCS and S3 buckets do not need this, as they are expected to expire responses with a
+/// lifecycle rule. If `dry_run` is set, no files are removed.
+///
+/// Returns the number and size of the removed files.
+pub fn cleanup_results(config: &ResultStorageConfig, dry_run: bool) -> io::Result<FileStats> {
+    let mut removed = FileStats::default();
+    let SharedCacheBackendConfig::Filesystem(ref fs_config) = config.backend else {
+        return Ok(removed);
+    };
+
+    let Some(entries) = catch_not_found(|| fs::read_dir(fs_config.path.join("results")))? else {
+        return Ok(removed);
+    };
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+        if age <= config.ttl {
+            continue;
+        }
+
+        tracing::debug!("Removing expired result {}", entry.path().display());
+        if !dry_run {
+            catch_not_found(|| fs::remove_file(entry.path()))?;
+        }
+        removed.add_file(metadata.len());
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::caching::shared_cache::config::{
+        FilesystemSharedCacheConfig, SharedCacheBackendConfig,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_filesystem_store_fetch() {
+        let dir = symbolicator_test::tempdir();
+        let config = ResultStorageConfig {
+            ttl: Duration::from_secs(60),
+            backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
+                path: dir.path().to_owned(),
+            }),
+        };
+
+        let store = ResultStore::new(Some(config), &tokio::runtime::Handle::current());
+        let store = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(store) = store.get() {
+                    break store;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(store.fetch("some-request").await, None);
+
+        store.store("some-request", b"{}".to_vec()).await;
+        assert!(dir.path().join("results/some-request").exists());
+        assert_eq!(store.fetch("some-request").await, Some(b"{}".to_vec()));
+    }
+
+    #[test]
+    fn test_cleanup_results() {
+        let dir = symbolicator_test::tempdir();
+        let config = ResultStorageConfig {
+            ttl: Duration::from_secs(60),
+            backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
+                path: dir.path().to_owned(),
+            }),
+        };
+
+        // Without any stored results, there is nothing to clean up.
+        assert_eq!(
+            cleanup_results(&config, false).unwrap(),
+            FileStats::default()
+        );
+
+        let results = dir.path().join("results");
+        fs::create_dir_all(&results).unwrap();
+        fs::write(results.join("fresh"), b"{}").unwrap();
+        fs::write(results.join("expired"), b"{}").unwrap();
+        let stored_at = SystemTime::now() - Duration::from_secs(120);
+        filetime::set_file_mtime(
+            results.join("expired"),
+            filetime::FileTime::from_system_time(stored_at),
+        )
+        .unwrap();
+
+        let expected = FileStats { files: 1, bytes: 2 };
+        assert_eq!(cleanup_results(&config, true).unwrap(), expected);
+        assert!(results.join("expired").exists());
+
+        assert_eq!(cleanup_results(&config, false).unwrap(), expected);
+        assert!(!results.join("expired").exists());
+        assert!(results.join("fresh").exists());
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::AddAssign;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::cache_error::cache_entry_from_bytes;
+use super::fs::{expiration_strategy, ExpirationStrategy};
+use super::{CacheName, CacheStorage, StoredFile};
+
+/// Upper bounds of the buckets of [`CacheStats::age_histogram`].
+///
+/// Entries older than the last bound are counted in one more, unbounded bucket.
+pub const AGE_BUCKETS: [Duration; 4] = [
+    Duration::from_secs(3600),
+    Duration::from_secs(24 * 3600),
+    Duration::from_secs(7 * 24 * 3600),
+    Duration::from_secs(30 * 24 * 3600),
+];
+
+/// Statistics of a single file-system [`Cache`](super::Cache).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of files in the cache, including the metadata files next to the entries.
+    pub files: u64,
+    /// The total size of all files in the cache in bytes.
+    pub bytes: u64,
+    /// The `mtime` of the oldest file in the cache.
+    pub oldest_mtime: Option<SystemTime>,
+    /// The number of entries holding a successfully computed item.
+    pub ok: u64,
+    /// The number of entries recording that an item was missing, or could not be fetched.
+    pub negative: u64,
+    /// The number of entries recording that an item was malformed.
+    pub malformed: u64,
+    /// The number of entries in each of the [`AGE_BUCKETS`], by the age of their `mtime` at
+    /// the time they were scanned.
+    pub age_histogram: [u64; AGE_BUCKETS.len() + 1],
+}
+
+impl CacheStats {
+    /// Returns the age of the oldest entry, as measured by its `mtime`.
+    pub fn oldest_entry_age(&self) -> Option<Duration> {
+        self.oldest_mtime
+            .map(|mtime| mtime.elapsed().unwrap_or_default())
+    }
+
+    /// Returns the number of entries, which excludes their metadata files.
+    pub fn entries(&self) -> u64 {
+        self.ok + self.negative + self.malformed
+    }
+
+    /// Adds the given `file` of the `storage` to the stats, categorizing it by its contents.
+    ///
+    /// The age of the file is measured relative to `now`.
+    pub(super) fn add_file(
+        &mut self,
+        storage: &dyn CacheStorage,
+        file: &StoredFile,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        let StoredFile { key, size, mtime } = file;
+        let strategy = if key.ends_with(".txt") {
+            None
+        } else {
+            // Files can be removed by `cleanup` at any time, skip those entirely.
+            let Some((bv, _)) = storage.get(key)? else {
+                return Ok(());
+            };
+            Some(expiration_strategy(&cache_entry_from_bytes(bv)))
+        };
+        let mtime = *mtime;
+
+        self.files += 1;
+        self.bytes += size;
+        self.oldest_mtime = Some(match self.oldest_mtime {
+            Some(oldest) => oldest.min(mtime),
+            None => mtime,
+        });
+
+        let Some(strategy) = strategy else {
+            return Ok(());
+        };
+        match strategy {
+            ExpirationStrategy::None => self.ok += 1,
+            ExpirationStrategy::Negative => self.negative += 1,
+            ExpirationStrategy::Malformed => self.malformed += 1,
+        }
+        let age = now.duration_since(mtime).unwrap_or_default();
+        let bucket = AGE_BUCKETS
+            .iter()
+            .position(|max_age| age < *max_age)
+            .unwrap_or(AGE_BUCKETS.len());
+        self.age_histogram[bucket] += 1;
+
+        Ok(())
+    }
+}
+
+impl AddAssign for CacheStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.bytes += rhs.bytes;
+        self.oldest_mtime = match (self.oldest_mtime, rhs.oldest_mtime) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.ok += rhs.ok;
+        self.negative += rhs.negative;
+        self.malformed += rhs.malformed;
+        for (bucket, count) in self.age_histogram.iter_mut().zip(rhs.age_histogram) {
+            *bucket += count;
+        }
+    }
+}
+
+/// The [`CacheStats`] of a cache, as last published by the background scanner.
+#[derive(Debug, Clone, Copy)]
+struct PublishedStats {
+    stats: CacheStats,
+    updated_at: DateTime<Utc>,
+    complete: bool,
+}
+
+/// Holds the most recent [`CacheStats`] of each cache.
+///
+/// These are published by the background scanner, see
+/// [`Caches::spawn_size_scanner`](super::Caches::spawn_size_scanner), so that reading them
+/// never has to walk the cache directories.
+#[derive(Debug, Clone, Default)]
+pub struct CacheStatsRegistry {
+    caches: Arc<RwLock<BTreeMap<CacheName, PublishedStats>>>,
+}
+
+impl CacheStatsRegistry {
+    /// Publishes the current `stats` of the cache with the given `name`.
+    ///
+    /// `complete` signals that the scanner has walked the whole cache at least once.
+    pub(super) fn publish(&self, name: CacheName, stats: CacheStats, complete: bool) {
+        let published = PublishedStats {
+            stats,
+            updated_at: Utc::now(),
+            complete,
+        };
+        self.caches.write().unwrap().insert(name, published);
+    }
+
+    /// Returns a [`CacheReport`] for each of the caches the scanner has visited so far.
+    pub fn report(&self) -> Vec<CacheReport> {
+        let caches = self.caches.read().unwrap();
+        caches
+            .iter()
+            .map(|(name, published)| CacheReport::new(*name, published))
+            .collect()
+    }
+}
+
+/// Statistics of a file-system cache, as gathered by the background scanner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheReport {
+    /// The name of the cache.
+    pub cache: CacheName,
+    /// The number of files, including the metadata files next to the entries.
+    pub files: u64,
+    /// The total size of all files in bytes.
+    pub bytes: u64,
+    /// The number of entries.
+    pub entries: u64,
+    /// The number of entries holding a successfully computed item.
+    pub ok: u64,
+    /// The number of entries recording that an item was missing, or could not be fetched.
+    pub negative: u64,
+    /// The number of entries recording that an item was malformed.
+    pub malformed: u64,
+    /// The fraction of `ok` entries, from `0` to `1`.
+    pub ok_ratio: f64,
+    /// The fraction of `negative` entries, from `0` to `1`.
+    pub negative_ratio: f64,
+    /// The fraction of `malformed` entries, from `0` to `1`.
+    pub malformed_ratio: f64,
+    /// The age of the oldest file in seconds.
+    pub oldest_entry_age_secs: Option<u64>,
+    /// The number of entries by their age.
+    pub age_histogram: Vec<AgeBucket>,
+    /// The time the stats were last updated.
+    pub updated_at: DateTime<Utc>,
+    /// Whether the whole cache has been scanned at least once.
+    ///
+    /// Until then, the stats only cover a part of the cache.
+    pub complete: bool,
+}
+
+impl CacheReport {
+    fn new(cache: CacheName, published: &PublishedStats) -> Self {
+        let stats = &published.stats;
+        let entries = stats.entries();
+        let ratio = |count: u64| {
+            if entries == 0 {
+                0.0
+            } else {
+                count as f64 / entries as f64
+            }
+        };
+
+        let max_ages = AGE_BUCKETS.iter().map(|max_age| Some(max_age.as_secs()));
+        let age_histogram = max_ages
+            .chain([None])
+            .zip(stats.age_histogram)
+            .map(|(max_age_secs, entries)| AgeBucket {
+                max_age_secs,
+                entries,
+            })
+            .collect();
+
+        Self {
+            cache,
+            files: stats.files,
+            bytes: stats.bytes,
+            entries,
+            ok: stats.ok,
+            negative: stats.negative,
+            malformed: stats.malformed,
+            ok_ratio: ratio(stats.ok),
+            negative_ratio: ratio(stats.negative),
+            malformed_ratio: ratio(stats.malformed),
+            oldest_entry_age_secs: stats.oldest_entry_age().map(|age| age.as_secs()),
+            age_histogram,
+            updated_at: published.updated_at,
+            complete: published.complete,
+        }
+    }
+}
+
+/// A bucket of the age histogram of a [`CacheReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgeBucket {
+    /// The exclusive upper bound of the age of the entries in seconds.
+    ///
+    /// This is `None` for the last bucket, which holds all the older entries.
+    pub max_age_secs: Option<u64>,
+    /// The number of entries in this bucket.
+    pub entries: u64,
+}
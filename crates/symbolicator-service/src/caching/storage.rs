@@ -0,0 +1,267 @@
+use std::fmt;
+use std::fs::{read_dir, remove_dir, remove_file};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use filetime::FileTime;
+use symbolic::common::ByteView;
+use tempfile::NamedTempFile;
+
+use super::fs::catch_not_found;
+
+/// A file kept in a [`CacheStorage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    /// The key of the file.
+    pub key: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The time the file was written or last touched.
+    pub mtime: SystemTime,
+}
+
+/// The storage backing a [`Cache`](super::Cache).
+///
+/// Files are addressed by keys, which are `/`-separated relative paths like the ones created by
+/// [`CacheKey::cache_path`](super::CacheKey::cache_path). The `mtime` of the files drives cache
+/// expiration, so storages have to bump it whenever a file is written or touched.
+///
+/// By default, caches are stored in their directory on the file system, see
+/// [`FileSystemStorage`]. Other storages can be plugged in via
+/// [`Cache::with_storage`](super::Cache::with_storage).
+///
+/// All methods are blocking, and may be called from multiple threads at once.
+pub trait CacheStorage: fmt::Debug + Send + Sync {
+    /// Opens the file at `key` along with its `mtime`, or returns `None` if it does not exist.
+    fn get(&self, key: &str) -> io::Result<Option<(ByteView<'static>, SystemTime)>>;
+
+    /// Stores the fully written `file` at `key`, replacing the file that was there before.
+    fn put(&self, key: &str, file: NamedTempFile) -> io::Result<()>;
+
+    /// Bumps the `mtime` of the file at `key` to the current time.
+    fn touch(&self, key: &str) -> io::Result<()>;
+
+    /// Deletes the file at `key`, returning `false` if it did not exist.
+    fn delete(&self, key: &str) -> io::Result<bool>;
+
+    /// Lists the keys one level below `prefix`, the empty prefix being the root.
+    ///
+    /// This is empty if `prefix` is a file, or does not exist. It is used to split up walks of
+    /// the whole storage into smaller parts.
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+
+    /// Calls `visit` for every file at or below `prefix`, the empty prefix being the root.
+    ///
+    /// Files may be deleted while they are visited, including by `visit` itself.
+    fn iterate(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(StoredFile) -> io::Result<()>,
+    ) -> io::Result<()>;
+
+    /// Removes leftovers which do not hold any files, such as empty directories.
+    ///
+    /// This is called at the end of every cleanup, and does nothing by default.
+    fn sweep(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stores cache files in a directory on the file system.
+///
+/// The keys are used as paths within that directory. Directories are created as needed when
+/// files are stored, and removed once the last file in them is deleted. Directories which were
+/// left empty otherwise are removed by [`sweep`](CacheStorage::sweep) during cleanup.
+#[derive(Debug, Clone)]
+pub struct FileSystemStorage {
+    root: PathBuf,
+}
+
+impl FileSystemStorage {
+    /// Creates a storage in the directory at `root`, which is created if it does not exist.
+    pub fn new(root: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    fn iterate_path(
+        &self,
+        path: &Path,
+        key: &str,
+        visit: &mut dyn FnMut(StoredFile) -> io::Result<()>,
+    ) -> io::Result<()> {
+        // Files and directories can be removed by `cleanup` at any time, so treat those as missing.
+        let Some(metadata) = catch_not_found(|| path.metadata())? else {
+            return Ok(());
+        };
+        if !metadata.is_dir() {
+            return visit(StoredFile {
+                key: key.to_owned(),
+                size: metadata.len(),
+                mtime: metadata.modified()?,
+            });
+        }
+
+        let Some(entries) = catch_not_found(|| read_dir(path))? else {
+            return Ok(());
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(|name| join_key(key, name)) else {
+                continue;
+            };
+            self.iterate_path(&entry.path(), &name, visit)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all the empty directories below `path`, returning `true` if `path` itself is empty.
+    fn sweep_path(&self, path: &Path) -> io::Result<bool> {
+        let Some(entries) = catch_not_found(|| read_dir(path))? else {
+            return Ok(false);
+        };
+
+        let mut is_empty = true;
+        for entry in entries {
+            let entry = entry?;
+            // Removing a directory fails if a file was stored in it concurrently, so keep it then.
+            let removed = entry.file_type()?.is_dir()
+                && self.sweep_path(&entry.path())?
+                && remove_dir(entry.path()).is_ok();
+            is_empty &= removed;
+        }
+
+        Ok(is_empty)
+    }
+}
+
+fn join_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+impl CacheStorage for FileSystemStorage {
+    fn get(&self, key: &str) -> io::Result<Option<(ByteView<'static>, SystemTime)>> {
+        let path = self.path(key);
+        catch_not_found(|| {
+            let mtime = path.metadata()?.modified()?;
+            Ok((ByteView::open(&path)?, mtime))
+        })
+    }
+
+    fn put(&self, key: &str, file: NamedTempFile) -> io::Result<()> {
+        persist_tempfile(file, &self.path(key))?;
+        Ok(())
+    }
+
+    fn touch(&self, key: &str) -> io::Result<()> {
+        filetime::set_file_mtime(self.path(key), FileTime::now())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<bool> {
+        let path = self.path(key);
+        if catch_not_found(|| remove_file(&path))?.is_none() {
+            return Ok(false);
+        }
+
+        // Remove the directories which were left empty. This fails for the first one which still
+        // has files in it. `persist_tempfile` recreates them if a file is stored concurrently.
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|parent| *parent != self.root) {
+            if remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+
+        Ok(true)
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let path = self.path(prefix);
+        if !path.is_dir() {
+            return Ok(vec![]);
+        }
+        let Some(entries) = catch_not_found(|| read_dir(&path))? else {
+            return Ok(vec![]);
+        };
+
+        let mut keys = vec![];
+        for entry in entries {
+            if let Some(name) = entry?.file_name().to_str() {
+                keys.push(join_key(prefix, name));
+            }
+        }
+        Ok(keys)
+    }
+
+    fn iterate(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(StoredFile) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.iterate_path(&self.path(prefix), prefix, visit)
+    }
+
+    fn sweep(&self) -> io::Result<()> {
+        self.sweep_path(&self.root)?;
+        Ok(())
+    }
+}
+
+fn persist_tempfile(
+    mut temp_file: NamedTempFile,
+    cache_path: &Path,
+) -> std::io::Result<std::fs::File> {
+    let parent = cache_path.parent().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no parent directory to persist item",
+        )
+    })?;
+
+    // The `cleanup` process could potentially remove the parent directories we are
+    // operating in, so be defensive here and retry the fs operations.
+    const MAX_RETRIES: usize = 2;
+    let mut retries = 0;
+    let file = loop {
+        retries += 1;
+
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            sentry::with_scope(
+                |scope| scope.set_extra("path", parent.display().to_string().into()),
+                || tracing::error!("Failed to create cache directory: {:?}", e),
+            );
+            if retries > MAX_RETRIES {
+                return Err(e);
+            }
+            continue;
+        }
+
+        match temp_file.persist(cache_path) {
+            Ok(file) => break file,
+            Err(e) => {
+                temp_file = e.file;
+                let err = e.error;
+                sentry::with_scope(
+                    |scope| scope.set_extra("path", cache_path.display().to_string().into()),
+                    || tracing::error!("Failed to create cache file: {:?}", err),
+                );
+                if retries > MAX_RETRIES {
+                    return Err(err);
+                }
+                continue;
+            }
+        }
+    };
+    Ok(file)
+}
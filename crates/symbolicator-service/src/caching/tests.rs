@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::fs::{self, File};
 use std::io::Write;
@@ -9,12 +10,14 @@ use std::time::{Duration, SystemTime};
 use anyhow::Result;
 use filetime::FileTime;
 use futures::future::BoxFuture;
+use parking_lot::Mutex;
 use symbolic::common::ByteView;
+use symbolicator_sources::ObjectId;
 use tempfile::NamedTempFile;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
 use crate::config::{
-    CacheConfig, CacheConfigs, Config, DerivedCacheConfig, DiagnosticsCacheConfig,
+    CacheConfig, CacheConfigs, CacheOverrides, Config, DerivedCacheConfig, DiagnosticsCacheConfig,
     DownloadedCacheConfig,
 };
 use crate::test;
@@ -112,7 +115,7 @@ fn test_max_unused_for() -> Result<()> {
     sleep(Duration::from_millis(100));
 
     File::create(tempdir.path().join("objects/keepthis2"))?.write_all(b"hi")?;
-    cache.cleanup()?;
+    cache.cleanup(false)?;
 
     let mut basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
         .map(|x| x.unwrap().file_name().into_string().unwrap())
@@ -150,7 +153,7 @@ fn test_retry_misses_after() -> Result<()> {
     sleep(Duration::from_millis(100));
 
     File::create(tempdir.path().join("objects/keepthis2"))?.write_all(b"")?;
-    cache.cleanup()?;
+    cache.cleanup(false)?;
 
     let mut basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
         .map(|x| x.unwrap().file_name().into_string().unwrap())
@@ -195,7 +198,7 @@ fn test_cleanup_malformed() -> Result<()> {
         1024,
     )?;
 
-    cache.cleanup()?;
+    cache.cleanup(false)?;
 
     let mut basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
         .map(|x| x.unwrap().file_name().into_string().unwrap())
@@ -208,6 +211,36 @@ fn test_cleanup_malformed() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cleanup_empty_dirs() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects/empty/nested"))?;
+    fs::create_dir_all(tempdir.path().join("objects/full"))?;
+    File::create(tempdir.path().join("objects/full/keepthis"))?.write_all(b"beeep")?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+
+    // Nothing is removed in a dry run
+    cache.cleanup(true)?;
+    assert!(tempdir.path().join("objects/empty/nested").is_dir());
+
+    cache.cleanup(false)?;
+    assert!(!tempdir.path().join("objects/empty").exists());
+    assert!(tempdir.path().join("objects/full/keepthis").is_file());
+
+    Ok(())
+}
+
 #[test]
 fn test_cleanup_cache_download() -> Result<()> {
     let tempdir = tempdir()?;
@@ -239,7 +272,7 @@ fn test_cleanup_cache_download() -> Result<()> {
 
     sleep(Duration::from_millis(30));
 
-    cache.cleanup()?;
+    cache.cleanup(false)?;
 
     let mut basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
         .map(|x| x.unwrap().file_name().into_string().unwrap())
@@ -252,6 +285,373 @@ fn test_cleanup_cache_download() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cleanup_dry_run() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects/nested"))?;
+
+    File::create(tempdir.path().join("objects/killthis"))?.write_all(b"hi")?;
+    File::create(tempdir.path().join("objects/killthis2"))?.write_all(b"")?;
+    File::create(tempdir.path().join("objects/nested/killthis3"))?.write_all(b"malformed")?;
+    sleep(Duration::from_millis(100));
+
+    File::create(tempdir.path().join("objects/keepthis"))?.write_all(b"beeep")?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_unused_for: Some(Duration::from_millis(50)),
+            retry_misses_after: Some(Duration::from_millis(50)),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    let stats = cache.cleanup(true)?;
+
+    assert_eq!(stats.retained, FileStats { files: 1, bytes: 5 });
+    assert_eq!(stats.expired_unused, FileStats { files: 1, bytes: 2 });
+    assert_eq!(stats.expired_negative, FileStats { files: 1, bytes: 0 });
+    assert_eq!(stats.expired_malformed, FileStats { files: 1, bytes: 9 });
+    assert_eq!(
+        stats.expired(),
+        FileStats {
+            files: 3,
+            bytes: 11
+        }
+    );
+
+    // Nothing was actually removed
+    assert!(tempdir.path().join("objects/killthis").is_file());
+    assert!(tempdir.path().join("objects/killthis2").is_file());
+    assert!(tempdir.path().join("objects/nested/killthis3").is_file());
+
+    // A real cleanup removes exactly what the dry-run reported
+    let real_stats = cache.cleanup(false)?;
+    assert_eq!(real_stats, stats);
+
+    let mut basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
+        .map(|x| x.unwrap().file_name().into_string().unwrap())
+        .collect();
+
+    basenames.sort();
+
+    assert_eq!(basenames, vec!["keepthis"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalidate_negative() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let dir = tempdir.path().join("objects/v1/ab/cdef01");
+    fs::create_dir_all(&dir)?;
+
+    let write_entry = |name: &str, contents: &[u8], location: &str| -> Result<()> {
+        File::create(dir.join(name))?.write_all(contents)?;
+        let metadata = format!("scope: global\n\nsource: foo\nlocation: {location}\n");
+        fs::write(dir.join(name).with_extension("txt"), metadata)?;
+        Ok(())
+    };
+    // Identifiers are laid out differently by the various sources.
+    write_entry(
+        "missing",
+        b"",
+        "http://x/Foo.pdb/502FC0A51EC13E479998684FA139DCA71/Foo.pdb",
+    )?;
+    write_entry(
+        "split",
+        b"",
+        "file:///50/2fc0a5-1ec1-3e47-9998-684fa139dca7.debug",
+    )?;
+    write_entry(
+        "found",
+        b"data",
+        "http://x/502fc0a5-1ec1-3e47-9998-684fa139dca7",
+    )?;
+    write_entry(
+        "other",
+        b"",
+        "http://x/0000c0a5-1ec1-3e47-9998-684fa139dca7",
+    )?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+
+    let object_id = ObjectId {
+        debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".parse()?),
+        ..Default::default()
+    };
+    let removed = cache.invalidate_negative(&ObjectIdFilter::new(&object_id))?;
+
+    assert_eq!(removed, 2);
+    assert!(!dir.join("missing").exists());
+    assert!(!dir.join("missing.txt").exists());
+    assert!(!dir.join("split").exists());
+    assert!(dir.join("found").exists());
+    assert!(dir.join("other").exists());
+
+    // Without any identifiers, nothing matches
+    let filter = ObjectIdFilter::new(&ObjectId::default());
+    assert!(filter.is_empty());
+    assert_eq!(cache.invalidate_negative(&filter)?, 0);
+
+    // Nil debug ids and short code ids would match unrelated entries, so they are ignored
+    let object_id = ObjectId {
+        debug_id: Some(Default::default()),
+        code_id: Some("c0a5".parse()?),
+        ..Default::default()
+    };
+    let filter = ObjectIdFilter::new(&object_id);
+    assert!(filter.is_empty());
+    assert_eq!(cache.invalidate_negative(&filter)?, 0);
+    assert!(dir.join("other").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_cleanup_max_size() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects/nested"))?;
+
+    let now = SystemTime::now();
+    let oldest = tempdir.path().join("objects/nested/oldest");
+    File::create(&oldest)?.write_all(b"oldest")?;
+    filetime::set_file_mtime(
+        &oldest,
+        FileTime::from_system_time(now - Duration::from_secs(7200)),
+    )?;
+    let older = tempdir.path().join("objects/older");
+    File::create(&older)?.write_all(b"older")?;
+    filetime::set_file_mtime(
+        &older,
+        FileTime::from_system_time(now - Duration::from_secs(3600)),
+    )?;
+    let newest = tempdir.path().join("objects/newest");
+    File::create(&newest)?.write_all(b"newest")?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(DownloadedCacheConfig {
+            max_size: Some(11),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    let stats = cache.cleanup(true)?;
+    assert_eq!(stats.evicted, FileStats { files: 1, bytes: 6 });
+    assert_eq!(
+        stats.retained,
+        FileStats {
+            files: 2,
+            bytes: 11
+        }
+    );
+    assert_eq!(stats.expired(), FileStats::default());
+    assert!(oldest.exists());
+
+    let real_stats = cache.cleanup(false)?;
+    assert_eq!(real_stats, stats);
+    assert!(!oldest.exists());
+    assert!(older.exists());
+    assert!(newest.exists());
+
+    // The cache fits now, so nothing else is evicted
+    let stats = cache.cleanup(false)?;
+    assert_eq!(stats.evicted, FileStats::default());
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_stats() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects/nested"))?;
+
+    let mtime = SystemTime::now() - Duration::from_secs(3600);
+    let oldest = tempdir.path().join("objects/nested/oldest");
+    File::create(&oldest)?.write_all(b"malformed")?;
+    filetime::set_file_mtime(&oldest, FileTime::from_system_time(mtime))?;
+    File::create(tempdir.path().join("objects/negative"))?.write_all(b"")?;
+    File::create(tempdir.path().join("objects/positive"))?.write_all(b"beeep")?;
+    File::create(tempdir.path().join("objects/positive.txt"))?.write_all(b"meta")?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+
+    let stats = cache.stats()?.unwrap();
+    assert_eq!(stats.files, 4);
+    assert_eq!(stats.bytes, 18);
+    assert_eq!((stats.ok, stats.negative, stats.malformed), (1, 1, 1));
+    assert_eq!(stats.entries(), 3);
+    assert_eq!(stats.age_histogram, [2, 1, 0, 0, 0]);
+    assert!(stats.oldest_entry_age().unwrap() >= Duration::from_secs(3600));
+
+    let config = Config {
+        cache_dir: None,
+        ..Default::default()
+    };
+    let disabled = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+    assert_eq!(disabled.stats()?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_size_scanner_publishes_stats() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    // More shards than are walked in a single step.
+    for shard in 0..40 {
+        let dir = tempdir.path().join(format!("symcaches/v1/{shard:02x}"));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("entry"), if shard % 4 == 0 { "" } else { "ok" })?;
+    }
+
+    let registry = CacheStatsRegistry::default();
+    let caches = Caches::from_config(&config)?;
+    caches.spawn_size_scanner(
+        Duration::from_secs(3600),
+        registry.clone(),
+        &tokio::runtime::Handle::current(),
+    );
+
+    let scanned = async {
+        loop {
+            let report = registry.report();
+            if report.len() == 11 && report.iter().all(|report| report.complete) {
+                break report;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    let report = tokio::time::timeout(Duration::from_secs(10), scanned).await?;
+
+    let symcaches = report
+        .iter()
+        .find(|report| report.cache == CacheName::Symcaches)
+        .unwrap();
+    assert_eq!(symcaches.entries, 40);
+    assert_eq!(symcaches.negative, 10);
+    assert_eq!(symcaches.negative_ratio, 0.25);
+    assert_eq!(symcaches.age_histogram[0].entries, 40);
+    assert_eq!(symcaches.age_histogram[4].max_age_secs, None);
+
+    let objects = report
+        .iter()
+        .find(|report| report.cache == CacheName::Objects)
+        .unwrap();
+    assert_eq!(objects.entries, 0);
+    assert_eq!(objects.ok_ratio, 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_evict_oldest() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects/nested"))?;
+
+    let now = SystemTime::now();
+    let oldest = tempdir.path().join("objects/nested/oldest");
+    File::create(&oldest)?.write_all(b"oldest")?;
+    filetime::set_file_mtime(
+        &oldest,
+        FileTime::from_system_time(now - Duration::from_secs(7200)),
+    )?;
+    let older = tempdir.path().join("objects/older");
+    File::create(&older)?.write_all(b"older")?;
+    filetime::set_file_mtime(
+        &older,
+        FileTime::from_system_time(now - Duration::from_secs(3600)),
+    )?;
+    let newest = tempdir.path().join("objects/newest");
+    File::create(&newest)?.write_all(b"newest")?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+
+    let stats = cache.evict_oldest(8)?;
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.bytes, 11);
+    assert!(!oldest.exists());
+    assert!(!older.exists());
+    assert!(newest.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_disk_pressure() {
+    let pressure = DiskPressure::new(100);
+    assert!(pressure.allows_storing(1000));
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &Config::default(),
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )
+    .unwrap()
+    .with_disk_pressure(pressure.clone());
+    cache.disk_pressure.set_low(true);
+
+    assert!(pressure.is_low());
+    assert!(pressure.allows_storing(100));
+    assert!(!pressure.allows_storing(101));
+}
+
 fn expiration_strategy(path: &Path) -> io::Result<ExpirationStrategy> {
     let bv = ByteView::open(path)?;
     let cache_entry = cache_entry_from_bytes(bv);
@@ -386,7 +786,7 @@ fn test_open_cachefile() -> Result<()> {
     let old_mtime = fs::metadata(&path)?.modified()?;
 
     // Open it with the cache, check contents and new mtime.
-    let (entry, _expiration) = cache.open_cachefile(&path)?.expect("No file found");
+    let (entry, _expiration) = cache.open_cachefile("hello")?.expect("No file found");
     assert_eq!(entry.unwrap().as_slice(), b"world");
 
     let new_mtime = fs::metadata(&path)?.modified()?;
@@ -395,6 +795,82 @@ fn test_open_cachefile() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_store_copy() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let source = tempdir.path().join("source");
+    fs::write(&source, b"minidump")?;
+
+    let cache = Cache::from_config(
+        CacheName::Diagnostics,
+        &config,
+        CacheConfig::from(CacheConfigs::default().diagnostics),
+        Default::default(),
+        1024,
+    )?;
+    assert!(cache.store_copy("crash.dmp", &source)?);
+    assert_eq!(
+        fs::read(tempdir.path().join("diagnostics/crash.dmp"))?,
+        b"minidump"
+    );
+
+    let disabled = Cache::from_config(
+        CacheName::Diagnostics,
+        &Config::default(),
+        CacheConfig::from(CacheConfigs::default().diagnostics),
+        Default::default(),
+        1024,
+    )?;
+    assert!(!disabled.store_copy("crash.dmp", &source)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_file_system_storage() -> Result<()> {
+    let tempdir = tempdir()?;
+    let storage = FileSystemStorage::new(tempdir.path().join("objects"))?;
+
+    let mut file = NamedTempFile::new_in(tempdir.path())?;
+    file.write_all(b"hello")?;
+    storage.put("v1/ab/cdef", file)?;
+    let path = tempdir.path().join("objects/v1/ab/cdef");
+    assert_eq!(fs::read(&path)?, b"hello");
+
+    let old_mtime = SystemTime::now() - Duration::from_secs(3600);
+    filetime::set_file_mtime(&path, FileTime::from_system_time(old_mtime))?;
+    let (bv, mtime) = storage.get("v1/ab/cdef")?.expect("No file found");
+    assert_eq!(bv.as_slice(), b"hello");
+    assert!(mtime < SystemTime::now() - Duration::from_secs(60));
+
+    storage.touch("v1/ab/cdef")?;
+    let (_, mtime) = storage.get("v1/ab/cdef")?.unwrap();
+    assert!(mtime > SystemTime::now() - Duration::from_secs(60));
+    assert!(storage.get("v1/ab/missing")?.is_none());
+
+    assert_eq!(storage.list("")?, vec!["v1"]);
+    assert_eq!(storage.list("v1")?, vec!["v1/ab"]);
+    assert!(storage.list("v1/ab/cdef")?.is_empty());
+    let mut files = vec![];
+    storage.iterate("", &mut |file: StoredFile| {
+        files.push((file.key, file.size));
+        Ok(())
+    })?;
+    assert_eq!(files, vec![("v1/ab/cdef".to_owned(), 5)]);
+
+    // Deleting the last file also removes the directories it leaves empty.
+    assert!(storage.delete("v1/ab/cdef")?);
+    assert!(!storage.delete("v1/ab/cdef")?);
+    assert!(!tempdir.path().join("objects/v1").exists());
+    assert!(tempdir.path().join("objects").is_dir());
+
+    Ok(())
+}
+
 #[test]
 fn test_cleanup() {
     let tempdir = tempdir().unwrap();
@@ -447,7 +923,7 @@ fn test_cleanup() {
     assert!(cficaches_entry.is_file());
     assert!(diagnostics_entry.is_file());
 
-    caches.cleanup().unwrap();
+    caches.cleanup(false).unwrap();
 
     assert!(!object_entry.is_file());
     assert!(!object_meta_entry.is_file());
@@ -645,10 +1121,10 @@ fn test_shared_cache_config_filesystem_common_defaults() {
     assert_eq!(cfg.max_upload_queue_size, 400);
     assert_eq!(cfg.max_concurrent_uploads, 20);
     match cfg.backend {
-        SharedCacheBackendConfig::Gcs(_) => panic!("wrong backend"),
         SharedCacheBackendConfig::Filesystem(cfg) => {
             assert_eq!(cfg.path, Path::new("/path/to/somewhere"))
         }
+        _ => panic!("wrong backend"),
     }
 }
 
@@ -683,7 +1159,28 @@ fn test_shared_cache_config_gcs() {
             assert_eq!(gcs.bucket, "some-bucket");
             assert!(gcs.service_account_path.is_none());
         }
-        SharedCacheBackendConfig::Filesystem(_) => panic!("wrong backend"),
+        _ => panic!("wrong backend"),
+    }
+}
+
+#[test]
+fn test_shared_cache_config_s3() {
+    let yaml = r#"
+            s3:
+              bucket: "some-bucket"
+              region: "us-west-2"
+              access_key: "the-key"
+              secret_key: "the-secret"
+        "#;
+    let cfg: SharedCacheConfig = serde_yaml::from_reader(yaml.as_bytes()).unwrap();
+
+    match cfg.backend {
+        SharedCacheBackendConfig::S3(s3) => {
+            assert_eq!(s3.bucket, "some-bucket");
+            assert_eq!(s3.source_key.region.region.as_ref(), "us-west-2");
+            assert_eq!(s3.source_key.access_key, "the-key");
+        }
+        _ => panic!("wrong backend"),
     }
 }
 
@@ -714,6 +1211,13 @@ fn test_cache_entry() {
         Err(CacheError::Timeout(Duration::from_secs(273)))
     );
 
+    let rate_limited = b"ratelimited1m";
+
+    assert_eq!(
+        read_cache_entry(rate_limited),
+        Err(CacheError::RateLimited(Duration::from_secs(60)))
+    );
+
     let download_error = b"downloaderrorSomeone unplugged the internet";
 
     assert_eq!(
@@ -732,6 +1236,29 @@ fn test_cache_entry() {
         ))
     );
 
+    let id_mismatch = b"idmismatchexpected debug id 1, got 2";
+
+    assert_eq!(
+        read_cache_entry(id_mismatch),
+        Err(CacheError::IdMismatch("expected debug id 1, got 2".into()))
+    );
+
+    let checksum_mismatch = b"checksummismatchexpected SHA256:aa, found SHA256:bb";
+
+    assert_eq!(
+        read_cache_entry(checksum_mismatch),
+        Err(CacheError::ChecksumMismatch(
+            "expected SHA256:aa, found SHA256:bb".into()
+        ))
+    );
+
+    let signature_invalid = b"signatureinvalidmissing signature";
+
+    assert_eq!(
+        read_cache_entry(signature_invalid),
+        Err(CacheError::SignatureInvalid("missing signature".into()))
+    );
+
     let all_good = b"Not any of the error cases";
 
     assert_eq!(
@@ -823,6 +1350,193 @@ async fn test_cache_fallback() {
     assert_eq!(request.computations.load(Ordering::SeqCst), 1);
 }
 
+/// Asserts that stale items are served during the `stale_while_revalidate` grace period while
+/// they are refreshed in the background, and are a miss after it.
+#[tokio::test]
+async fn test_stale_while_revalidate() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let stale_key = CacheKey::for_testing("global/stale");
+    let expired_key = CacheKey::for_testing("global/expired");
+
+    let hour = Duration::from_secs(3600);
+    for (key, unused_for) in [(&stale_key, 2 * hour), (&expired_key, 48 * hour)] {
+        let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+        fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        fs::write(&cache_file, "some old cached contents").unwrap();
+        let mtime = FileTime::from_system_time(SystemTime::now() - unused_for);
+        filetime::set_file_mtime(&cache_file, mtime).unwrap();
+    }
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_unused_for: Some(hour),
+            stale_while_revalidate: Some(24 * hour),
+            ..Default::default()
+        }),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let first_result = cacher
+        .compute_memoized(request.clone(), stale_key.clone())
+        .await;
+    assert_eq!(first_result.unwrap().as_str(), "some old cached contents");
+
+    let second_result = cacher
+        .compute_memoized(request.clone(), stale_key.clone())
+        .await;
+    assert_eq!(second_result.unwrap().as_str(), "some old cached contents");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let third_result = cacher.compute_memoized(request.clone(), stale_key).await;
+    assert_eq!(third_result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    // past the grace period, the item is computed anew right away
+    let expired_result = cacher.compute_memoized(request.clone(), expired_key).await;
+    assert_eq!(expired_result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+}
+
+/// Asserts that a read-only cacher uses existing items, but neither stores nor memoizes the items
+/// it computes, nor refreshes outdated ones.
+#[tokio::test]
+async fn test_read_only() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let cached_key = CacheKey::for_testing("global/cached");
+    let fallback_key = CacheKey::for_testing("global/fallback");
+    let missing_key = CacheKey::for_testing("global/missing");
+
+    let objects_dir = cache_dir.path().join("objects");
+    for (key, version) in [(&cached_key, 1), (&fallback_key, 0)] {
+        let cache_file = objects_dir.join(key.cache_path(version));
+        fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        fs::write(cache_file, "some old cached contents").unwrap();
+    }
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default()).read_only();
+
+    for key in [&cached_key, &fallback_key] {
+        let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+        assert_eq!(result.unwrap().as_str(), "some old cached contents");
+    }
+
+    for _ in 0..2 {
+        let result = cacher
+            .compute_memoized(request.clone(), missing_key.clone())
+            .await;
+        assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The missing item is computed on every use, and the fallback is not refreshed.
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+    assert!(!objects_dir.join(missing_key.cache_path(1)).exists());
+    assert!(!objects_dir.join(fallback_key.cache_path(1)).exists());
+}
+
+/// A cache item whose computation always fails, as if the download failed.
+#[derive(Clone, Default)]
+struct FailingCacheItem {
+    computations: Arc<AtomicUsize>,
+}
+
+impl CacheItemRequest for FailingCacheItem {
+    type Item = String;
+
+    const VERSIONS: CacheVersions = CacheVersions {
+        current: 1,
+        fallbacks: &[],
+    };
+
+    fn compute<'a>(&'a self, _temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
+        self.computations.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async { Err(CacheError::DownloadError("connection reset".into())) })
+    }
+
+    fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
+        Ok(std::str::from_utf8(data.as_slice()).unwrap().to_owned())
+    }
+}
+
+/// Asserts that a failed refresh of a stale item keeps serving the stale item, instead of
+/// replacing it with the error.
+#[tokio::test]
+async fn test_stale_refresh_failed() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = FailingCacheItem::default();
+    let key = CacheKey::for_testing("global/stale");
+
+    let hour = Duration::from_secs(3600);
+    let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+    fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+    fs::write(&cache_file, "some old cached contents").unwrap();
+    let mtime = FileTime::from_system_time(SystemTime::now() - 2 * hour);
+    filetime::set_file_mtime(&cache_file, mtime).unwrap();
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_unused_for: Some(hour),
+            stale_while_revalidate: Some(24 * hour),
+            ..Default::default()
+        }),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let first_result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(first_result.unwrap().as_str(), "some old cached contents");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    // the stale file was neither replaced by the error, nor dropped from memory
+    assert_eq!(
+        fs::read_to_string(&cache_file).unwrap(),
+        "some old cached contents"
+    );
+    let second_result = cacher.compute_memoized(request.clone(), key).await;
+    assert_eq!(second_result.unwrap().as_str(), "some old cached contents");
+}
+
 /// Makes sure that a `NotFound` result does not fall back to older cache versions.
 #[tokio::test]
 async fn test_cache_fallback_notfound() {
@@ -920,3 +1634,156 @@ async fn test_lazy_computation_limit() {
 
     assert_eq!(num_outdated, 2);
 }
+
+/// Items above the `in_memory_max_item_size` are not kept in memory, so without a file system
+/// cache they are computed on every use.
+#[tokio::test]
+async fn test_in_memory_max_item_size() {
+    test::setup();
+
+    let key = CacheKey::for_testing("global/some_cache_key");
+    for (max_item_size, expected_computations) in [(None, 1), (Some(8), 2)] {
+        let mut config = Config {
+            cache_dir: None,
+            ..Default::default()
+        };
+        config.caches.overrides.insert(
+            CacheName::Objects,
+            CacheOverrides {
+                in_memory_max_item_size: max_item_size,
+                ..Default::default()
+            },
+        );
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        let cacher = Cacher::new(cache, Default::default());
+
+        let request = TestCacheItem::new();
+        for _ in 0..2 {
+            let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+            assert_eq!(result.unwrap().as_str(), "some new cached contents");
+        }
+
+        assert_eq!(
+            request.computations.load(Ordering::SeqCst),
+            expected_computations
+        );
+    }
+}
+
+/// A [`CacheStorage`] which keeps all files in memory.
+#[derive(Debug, Default)]
+struct MemoryStorage {
+    files: Mutex<BTreeMap<String, (Vec<u8>, SystemTime)>>,
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> io::Result<Option<(ByteView<'static>, SystemTime)>> {
+        let files = self.files.lock();
+        Ok(files
+            .get(key)
+            .map(|(data, mtime)| (ByteView::from_vec(data.clone()), *mtime)))
+    }
+
+    fn put(&self, key: &str, file: NamedTempFile) -> io::Result<()> {
+        let data = fs::read(file.path())?;
+        let mut files = self.files.lock();
+        files.insert(key.to_owned(), (data, SystemTime::now()));
+        Ok(())
+    }
+
+    fn touch(&self, key: &str) -> io::Result<()> {
+        let mut files = self.files.lock();
+        let (_, mtime) = files.get_mut(key).ok_or(io::ErrorKind::NotFound)?;
+        *mtime = SystemTime::now();
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<bool> {
+        Ok(self.files.lock().remove(key).is_some())
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let files = self.files.lock();
+        let mut keys: Vec<_> = files
+            .keys()
+            .filter_map(|key| {
+                let rest = match prefix {
+                    "" => key.as_str(),
+                    _ => key.strip_prefix(prefix)?.strip_prefix('/')?,
+                };
+                let child = rest.split('/').next()?;
+                Some(format!("{}{child}", &key[..key.len() - rest.len()]))
+            })
+            .collect();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    fn iterate(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(StoredFile) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let files: Vec<_> = self
+            .files
+            .lock()
+            .iter()
+            .filter(|(key, _)| prefix.is_empty() || key.starts_with(prefix))
+            .map(|(key, (data, mtime))| StoredFile {
+                key: key.clone(),
+                size: data.len() as u64,
+                mtime: *mtime,
+            })
+            .collect();
+        files.into_iter().try_for_each(visit)
+    }
+}
+
+/// Caches can be backed by a custom [`CacheStorage`] instead of the file system.
+#[tokio::test]
+async fn test_custom_storage() {
+    test::setup();
+
+    let storage = Arc::new(MemoryStorage::default());
+    let make_cache = || {
+        Cache::from_config(
+            CacheName::Objects,
+            &Config::default(),
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap()
+        .with_storage(storage.clone())
+    };
+
+    let key = CacheKey::for_testing("global/some_cache_key");
+    let request = TestCacheItem::new();
+    let cacher = Cacher::new(make_cache(), Default::default());
+    let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+
+    let cache_path = key.cache_path(TestCacheItem::VERSIONS.current);
+    let (data, _) = storage.get(&cache_path).unwrap().unwrap();
+    assert_eq!(data.as_slice(), b"some new cached contents");
+    let (metadata, _) = storage.get(&format!("{cache_path}.txt")).unwrap().unwrap();
+    assert_eq!(metadata.as_slice(), key.metadata().as_bytes());
+    assert_eq!(storage.list("").unwrap(), vec!["v1"]);
+
+    // A fresh cacher without anything in memory loads the item from the storage.
+    let cacher = Cacher::new(make_cache(), Default::default());
+    let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    let stats = make_cache().stats().unwrap().unwrap();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.ok, 1);
+}
@@ -7,13 +7,18 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use data_encoding::BASE64;
+use regex::Regex;
 use sentry::types::Dsn;
 use serde::{de, Deserialize, Deserializer};
 use tracing::level_filters::LevelFilter;
+use url::Url;
 
 use symbolicator_sources::SourceConfig;
 
-use crate::caching::SharedCacheConfig;
+use crate::caching::{CacheName, ResultStorageConfig, SharedCacheConfig};
+use crate::types::{InAppRule, PackageRule};
+use crate::utils::encryption::MasterKey;
 
 /// Controls the log format
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
@@ -67,6 +72,10 @@ pub struct Logging {
     pub format: LogFormat,
     /// When set to true, backtraces are forced on.
     pub enable_backtraces: bool,
+    /// When set to true, tokio-console can be attached to inspect the tasks of all runtimes.
+    ///
+    /// This requires symbolicator to be built with the `tokio-console` feature.
+    pub tokio_console: bool,
 }
 
 impl Default for Logging {
@@ -75,6 +84,7 @@ impl Default for Logging {
             level: LevelFilter::INFO,
             format: LogFormat::Auto,
             enable_backtraces: true,
+            tokio_console: false,
         }
     }
 }
@@ -132,6 +142,26 @@ pub struct DownloadedCacheConfig {
 
     /// Maximum number of lazy re-downloads
     pub max_lazy_redownloads: isize,
+
+    /// Grace period after `max_unused_for` during which an expired positive cache item is still
+    /// served, while a fresh one is computed in the background.
+    ///
+    /// Defaults to `None`, which treats expired items as cache misses right away.
+    #[serde(with = "humantime_serde")]
+    pub stale_while_revalidate: Option<Duration>,
+
+    /// Maximum duration since an object file was downloaded or last revalidated before its source
+    /// is asked whether it changed.
+    ///
+    /// Revalidation uses the `ETag` and `Last-Modified` headers of the original download, and only
+    /// downloads the file again if it changed. This applies to HTTP sources only.
+    #[serde(with = "humantime_serde")]
+    pub revalidate_after: Option<Duration>,
+
+    /// Maximum size in bytes of each cache of this kind.
+    ///
+    /// During cleanup, the least recently used files are removed until the cache fits.
+    pub max_size: Option<u64>,
 }
 
 impl Default for DownloadedCacheConfig {
@@ -141,6 +171,9 @@ impl Default for DownloadedCacheConfig {
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
             max_lazy_redownloads: 50,
+            stale_while_revalidate: None,
+            revalidate_after: None,
+            max_size: None,
         }
     }
 }
@@ -165,6 +198,29 @@ pub struct DerivedCacheConfig {
 
     /// Maximum number of lazy re-computations
     pub max_lazy_recomputations: isize,
+
+    /// Grace period after `max_unused_for` during which an expired positive cache item is still
+    /// served, while a fresh one is computed in the background.
+    ///
+    /// Defaults to `None`, which treats expired items as cache misses right away.
+    #[serde(with = "humantime_serde")]
+    pub stale_while_revalidate: Option<Duration>,
+
+    /// Maximum wall-clock time a single conversion into a derived cache may take.
+    ///
+    /// Conversions exceeding this are abandoned and cached as malformed.
+    #[serde(with = "humantime_serde")]
+    pub max_conversion_time: Option<Duration>,
+
+    /// Maximum number of conversions into derived caches running at once.
+    ///
+    /// Abandoned conversions count against this until they have actually stopped.
+    pub max_concurrent_conversions: usize,
+
+    /// Maximum size in bytes of each cache of this kind.
+    ///
+    /// During cleanup, the least recently used files are removed until the cache fits.
+    pub max_size: Option<u64>,
 }
 
 impl Default for DerivedCacheConfig {
@@ -174,6 +230,10 @@ impl Default for DerivedCacheConfig {
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
             max_lazy_recomputations: 20,
+            stale_while_revalidate: None,
+            max_conversion_time: Some(Duration::from_secs(600)),
+            max_concurrent_conversions: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            max_size: None,
         }
     }
 }
@@ -227,6 +287,149 @@ impl CacheConfig {
             Self::Diagnostics(_cfg) => None,
         }
     }
+
+    pub fn stale_while_revalidate(&self) -> Option<Duration> {
+        match self {
+            Self::Downloaded(cfg) => cfg.stale_while_revalidate,
+            Self::Derived(cfg) => cfg.stale_while_revalidate,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+
+    pub fn max_conversion_time(&self) -> Option<Duration> {
+        match self {
+            Self::Derived(cfg) => cfg.max_conversion_time,
+            _ => None,
+        }
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        match self {
+            Self::Downloaded(cfg) => cfg.max_size,
+            Self::Derived(cfg) => cfg.max_size,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Applies the given [`CacheOverrides`] on top of this config.
+    ///
+    /// For diagnostics caches, `max_unused_for` overrides the `retention`, and the other
+    /// overrides, including `max_size`, are ignored.
+    pub fn with_overrides(mut self, overrides: &CacheOverrides) -> Self {
+        match &mut self {
+            Self::Downloaded(cfg) => {
+                overrides.apply(
+                    &mut cfg.max_unused_for,
+                    &mut cfg.retry_misses_after,
+                    &mut cfg.retry_malformed_after,
+                    &mut cfg.stale_while_revalidate,
+                    &mut cfg.max_size,
+                );
+            }
+            Self::Derived(cfg) => {
+                overrides.apply(
+                    &mut cfg.max_unused_for,
+                    &mut cfg.retry_misses_after,
+                    &mut cfg.retry_malformed_after,
+                    &mut cfg.stale_while_revalidate,
+                    &mut cfg.max_size,
+                );
+            }
+            Self::Diagnostics(cfg) => {
+                if let Some(max_unused_for) = overrides.max_unused_for {
+                    cfg.retention = max_unused_for;
+                }
+            }
+        }
+        self
+    }
+}
+
+/// Overrides of the cache expiry settings for one specific cache.
+///
+/// Each field that is not specified falls back to the setting of the category the cache
+/// belongs to ("downloaded", "derived" or "diagnostics"). Explicitly setting a field to `null`
+/// disables that kind of expiration for the cache.
+///
+/// This also configures the in-memory tier in front of the file system cache.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct CacheOverrides {
+    /// Maximum duration since last use of cache item (item last used).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub max_unused_for: Option<Option<Duration>>,
+
+    /// Maximum duration since creation of negative cache item (item age).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub retry_misses_after: Option<Option<Duration>>,
+
+    /// Maximum duration since creation of malformed cache item (item age).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub retry_malformed_after: Option<Option<Duration>>,
+
+    /// Grace period after `max_unused_for` during which expired positive cache items are served
+    /// while they are refreshed in the background.
+    #[serde(deserialize_with = "deserialize_override")]
+    pub stale_while_revalidate: Option<Option<Duration>>,
+
+    /// Maximum size of the cache in bytes.
+    #[serde(deserialize_with = "deserialize_size_override")]
+    pub max_size: Option<Option<u64>>,
+
+    /// Capacity (in bytes) of the in-memory cache, replacing the built-in default of the cache.
+    pub in_memory_capacity: Option<u64>,
+
+    /// Maximum estimated size in bytes of an item kept in the in-memory cache.
+    ///
+    /// Larger items are loaded from the file system cache on every use, which leaves the
+    /// in-memory capacity to the small and frequently used ones.
+    pub in_memory_max_item_size: Option<u64>,
+}
+
+impl CacheOverrides {
+    fn apply(
+        &self,
+        max_unused_for: &mut Option<Duration>,
+        retry_misses_after: &mut Option<Duration>,
+        retry_malformed_after: &mut Option<Duration>,
+        stale_while_revalidate: &mut Option<Duration>,
+        max_size: &mut Option<u64>,
+    ) {
+        if let Some(value) = self.max_unused_for {
+            *max_unused_for = value;
+        }
+        if let Some(value) = self.retry_misses_after {
+            *retry_misses_after = value;
+        }
+        if let Some(value) = self.retry_malformed_after {
+            *retry_malformed_after = value;
+        }
+        if let Some(value) = self.stale_while_revalidate {
+            *stale_while_revalidate = value;
+        }
+        if let Some(value) = self.max_size {
+            *max_size = value;
+        }
+    }
+}
+
+/// Deserializes a present (possibly `null`) duration as `Some`, so it can be told apart from
+/// an absent field, which is `None` via `#[serde(default)]`.
+fn deserialize_override<'de, D>(deserializer: D) -> Result<Option<Option<Duration>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    humantime_serde::deserialize(deserializer).map(Some)
+}
+
+/// Like [`deserialize_override`], but for a size in bytes.
+fn deserialize_size_override<'de, D>(deserializer: D) -> Result<Option<Option<u64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
 }
 
 impl From<DownloadedCacheConfig> for CacheConfig {
@@ -307,6 +510,21 @@ pub struct InMemoryCacheConfig {
     ///
     /// Defaults to `600 MiB (= 629_145_600)`.
     pub cficaches_capacity: u64,
+
+    /// Capacity (in number of responses) for the in-memory cache of complete symbolication
+    /// responses.
+    ///
+    /// Defaults to `1_000`.
+    pub responses_capacity: u64,
+
+    /// The TTL for cached symbolication responses.
+    ///
+    /// Identical symbolication requests received within this time are answered from the cache
+    /// without running the symbolication pipeline again. A TTL of `0` disables the cache.
+    ///
+    /// Defaults to `0s`.
+    #[serde(with = "humantime_serde")]
+    pub responses_ttl: Duration,
 }
 
 impl Default for InMemoryCacheConfig {
@@ -319,6 +537,8 @@ impl Default for InMemoryCacheConfig {
             s3_client_capacity: 100,
             object_meta_capacity: 100 * meg,
             cficaches_capacity: 400 * meg,
+            responses_capacity: 1_000,
+            responses_ttl: Duration::ZERO,
         }
     }
 }
@@ -337,6 +557,530 @@ pub struct CacheConfigs {
 
     /// Configuration of various in-memory caches.
     pub in_memory: InMemoryCacheConfig,
+
+    /// Per-cache overrides of the expiry and in-memory settings.
+    ///
+    /// This allows for example to keep `cficaches` around for much longer than the
+    /// downloaded `objects` they were derived from.
+    pub overrides: BTreeMap<CacheName, CacheOverrides>,
+
+    /// The interval in which the size of all file-system caches is reported as metrics.
+    ///
+    /// This walks all the cache directories in a background task, which also gathers the
+    /// [`CacheStats`](crate::caching::CacheStats) of each cache. Defaults to `None`, which
+    /// disables these metrics.
+    #[serde(with = "humantime_serde")]
+    pub size_metrics_interval: Option<Duration>,
+
+    /// Watermarks of free space on the cache volume, and how to react when falling below them.
+    pub disk_space: DiskSpaceConfig,
+}
+
+/// Configuration of the watchdog monitoring the free space on the cache volume.
+///
+/// Instead of failing requests once the volume runs full, the watchdog stops caching large
+/// downloaded files below the `low_watermark`, and evicts the least recently used downloaded
+/// files below the `critical_watermark`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct DiskSpaceConfig {
+    /// The interval in which the free space on the cache volume is checked.
+    ///
+    /// Defaults to `None`, which disables the watchdog.
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Option<Duration>,
+
+    /// The free space in bytes below which large downloaded files are no longer cached.
+    pub low_watermark: u64,
+
+    /// The free space in bytes below which downloaded files are evicted from the cache, until
+    /// the free space is back above the `low_watermark`.
+    pub critical_watermark: u64,
+
+    /// The size in bytes above which downloaded files are considered large.
+    pub large_file_size: u64,
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: None,
+            low_watermark: 10 * 1024 * 1024 * 1024,
+            critical_watermark: 2 * 1024 * 1024 * 1024,
+            large_file_size: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// Configuration of minidump processing.
+///
+/// Minidumps exceeding the caps on their contents are truncated before stackwalking them, and
+/// the response reports what was dropped. All caps on the contents default to `None`, which
+/// processes all contents.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct MinidumpConfig {
+    /// The maximum number of threads which are stackwalked.
+    ///
+    /// The crashing thread is always among the processed threads.
+    pub max_threads: Option<usize>,
+
+    /// The maximum number of modules which are processed.
+    pub max_modules: Option<usize>,
+
+    /// The maximum number of memory regions which are processed.
+    pub max_memory_regions: Option<usize>,
+
+    /// The maximum size in bytes of an uploaded minidump.
+    ///
+    /// Uploads are rejected as soon as they exceed it, before the rest of the minidump is received.
+    /// This applies to uploads over HTTP and gRPC alike, and defaults to the 100 MiB limit on the
+    /// body of HTTP requests.
+    pub max_size: Option<u64>,
+
+    /// Limits on the contents of a minidump, above which it is rejected instead of truncated.
+    pub limits: MinidumpLimits,
+}
+
+impl Default for MinidumpConfig {
+    fn default() -> Self {
+        Self {
+            max_threads: None,
+            max_modules: None,
+            max_memory_regions: None,
+            max_size: Some(100 * 1024 * 1024),
+            limits: MinidumpLimits::default(),
+        }
+    }
+}
+
+/// Limits on the contents of a minidump, which are checked before stackwalking it.
+///
+/// The memory used for stackwalking grows with the number of threads and memory regions, and
+/// not only with the size of the minidump. Malformed or fuzzed minidumps can declare huge
+/// numbers of them, so they are rejected upfront instead of exhausting the available memory.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct MinidumpLimits {
+    /// The maximum number of streams in the stream directory.
+    pub max_streams: usize,
+    /// The maximum number of threads in the thread list.
+    pub max_threads: usize,
+    /// The maximum number of regions in the memory list.
+    pub max_memory_regions: usize,
+    /// The maximum length in bytes of the file names of modules.
+    pub max_string_length: usize,
+}
+
+impl Default for MinidumpLimits {
+    /// Limits which are well above the contents of any legitimate minidump.
+    fn default() -> Self {
+        Self {
+            max_streams: 1024,
+            max_threads: 32_768,
+            max_memory_regions: 131_072,
+            max_string_length: 4096,
+        }
+    }
+}
+
+/// Configuration of the recorder, which archives symbolication requests for offline replay.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RecordingConfig {
+    /// The directory the recorded requests are written to.
+    pub dir: PathBuf,
+
+    /// The fraction of requests which are recorded, from `0` to `1`.
+    #[serde(default = "default_recording_sample_rate")]
+    pub sample_rate: f64,
+}
+
+fn default_recording_sample_rate() -> f64 {
+    1.0
+}
+
+/// Which sources may connect to reserved IP addresses, such as `10.0.0.0/8` or `127.0.0.1`.
+///
+/// This is configured either as a boolean applying to all sources, or as a list of source IDs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReservedIpsPolicy {
+    /// No source may connect to reserved IP addresses.
+    #[default]
+    Deny,
+    /// All sources, including the ones sent with requests, may connect to reserved IP addresses.
+    Allow,
+    /// Only the HTTP sources with these IDs may connect to reserved IP addresses.
+    ///
+    /// As clients choose the IDs of the sources they send with requests, this only applies to
+    /// sources defined in the `sources` of the config, and to sources with the same ID and URL.
+    Sources(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for ReservedIpsPolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bool(bool),
+            Sources(Vec<String>),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Bool(false) => Self::Deny,
+            Repr::Bool(true) => Self::Allow,
+            Repr::Sources(sources) => Self::Sources(sources),
+        })
+    }
+}
+
+/// Tuning of the connection pool of the HTTP client used to download from sources.
+///
+/// Settings which are not given keep the defaults of the HTTP client.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConnectionPoolConfig {
+    /// The maximum number of idle connections kept open per host.
+    ///
+    /// Unlimited by default.
+    pub max_idle_per_host: Option<usize>,
+
+    /// How long an idle connection is kept open before it is closed.
+    ///
+    /// Defaults to `90s`.
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Option<Duration>,
+
+    /// The interval of pings keeping HTTP/2 connections alive.
+    ///
+    /// Keep-alive pings are not sent by default.
+    #[serde(with = "humantime_serde")]
+    pub http2_keep_alive_interval: Option<Duration>,
+
+    /// How long to wait for a keep-alive ping to be acknowledged before closing the connection.
+    ///
+    /// Defaults to `20s`.
+    #[serde(with = "humantime_serde")]
+    pub http2_keep_alive_timeout: Option<Duration>,
+}
+
+impl ConnectionPoolConfig {
+    /// Returns these settings, replacing the ones which are given in `overrides`.
+    pub fn with_overrides(&self, overrides: &Self) -> Self {
+        Self {
+            max_idle_per_host: overrides.max_idle_per_host.or(self.max_idle_per_host),
+            idle_timeout: overrides.idle_timeout.or(self.idle_timeout),
+            http2_keep_alive_interval: overrides
+                .http2_keep_alive_interval
+                .or(self.http2_keep_alive_interval),
+            http2_keep_alive_timeout: overrides
+                .http2_keep_alive_timeout
+                .or(self.http2_keep_alive_timeout),
+        }
+    }
+}
+
+/// Connection pool settings for all sources, and overrides for individual sources.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConnectionPoolConfigs {
+    /// The settings of the connection pool shared by all sources without overrides.
+    #[serde(flatten)]
+    pub default: ConnectionPoolConfig,
+
+    /// Sources which get a connection pool of their own, keyed by source ID.
+    ///
+    /// Settings which are not given for a source are taken from the shared pool.
+    pub sources: BTreeMap<String, ConnectionPoolConfig>,
+}
+
+/// Configuration of the backends which resolve secret references in source credentials.
+///
+/// Each backend is disabled unless configured, and restricts which secrets can be referenced,
+/// as references are resolved for the sources of requests as well.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    /// How long resolved secrets are cached before being resolved again.
+    ///
+    /// This bounds the time it takes for rotated secrets to be picked up.
+    ///
+    /// Defaults to `5m`.
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+
+    /// The prefix of environment variables which can be referenced as `${env:NAME}`.
+    pub env_prefix: Option<String>,
+
+    /// The directory of files which can be referenced as `${file:/path}`.
+    pub files_dir: Option<PathBuf>,
+
+    /// The Vault server whose secrets can be referenced as `${vault:path#key}`.
+    pub vault: Option<VaultConfig>,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(5 * 60),
+            env_prefix: None,
+            files_dir: None,
+            vault: None,
+        }
+    }
+}
+
+/// Configuration of a Vault server, whose KV version 2 secrets engine stores source credentials.
+#[derive(Clone, Debug, Deserialize)]
+pub struct VaultConfig {
+    /// The address of the Vault server.
+    pub url: Url,
+
+    /// A file containing the Vault token, which is read again whenever a secret is resolved.
+    pub token_file: PathBuf,
+
+    /// The mount path of the secrets engine.
+    #[serde(default = "default_vault_mount")]
+    pub mount: String,
+
+    /// The prefix of the secret paths which can be referenced.
+    #[serde(default = "default_vault_path_prefix")]
+    pub path_prefix: String,
+}
+
+/// Configuration of the master key, which encrypts sensitive values of persisted configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EncryptionConfig {
+    /// A file containing the base64 encoded 256-bit master key.
+    ///
+    /// A key managed by a KMS can be provided by having the KMS write it to this file, for
+    /// example by mounting it as a secret volume.
+    pub key_file: PathBuf,
+}
+
+/// Requires the objects downloaded from a set of sources to carry a valid detached signature.
+///
+/// The signature of an object is expected next to it, at its location with a `.sig` suffix. It
+/// is an Ed25519 signature of the (decompressed) object file, either as raw 64 bytes or base64
+/// encoded.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SignatureConfig {
+    /// The IDs of the sources whose objects need to be signed.
+    pub sources: Vec<String>,
+
+    /// The base64 encoded Ed25519 public keys which are trusted to sign objects.
+    ///
+    /// A signature by any of these keys is accepted.
+    #[serde(deserialize_with = "deserialize_public_keys")]
+    pub public_keys: Vec<Vec<u8>>,
+}
+
+/// Configuration of the malware scanner, which checks newly downloaded object files.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MalwareScanConfig {
+    /// The scanner the object files are passed to.
+    #[serde(flatten)]
+    pub scanner: MalwareScannerConfig,
+
+    /// The maximum time a single scan may take.
+    ///
+    /// Scans exceeding this fail, and the object file is not used. Defaults to `30s`.
+    #[serde(default = "default_malware_scan_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+/// The kind of malware scanner.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MalwareScannerConfig {
+    /// An external command, which receives the object file on stdin.
+    ///
+    /// The command exits with `0` if the file is clean, and with `1` if malware was found, in
+    /// which case its output describes the finding. Any other exit code is a failed scan.
+    Command {
+        /// The program to run, followed by its arguments.
+        command: Vec<String>,
+    },
+    /// An ICAP service, which receives the object file in a `RESPMOD` request.
+    Icap {
+        /// The URL of the service, such as `icap://127.0.0.1:1344/avscan`.
+        url: Url,
+    },
+}
+
+fn default_malware_scan_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_vault_mount() -> String {
+    "secret".to_owned()
+}
+
+fn default_vault_path_prefix() -> String {
+    "symbolicator/".to_owned()
+}
+
+/// Heuristics used to fill in missing identifiers of ELF modules when looking up debug files.
+///
+/// All heuristics are disabled by default. When enabled, they can still be disabled for individual
+/// sources with the `strict_ids` source filter.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct ElfIdHeuristicsConfig {
+    /// Derive a missing debug id from the GNU build id (code id) of the module.
+    ///
+    /// This uses the same derivation as the one used when processing ELF files, assuming a
+    /// little-endian target.
+    pub derive_debug_id: bool,
+
+    /// Use the code file name as the debug file name if the latter is missing.
+    pub debug_file_from_code_file: bool,
+}
+
+/// The file paths of a module which a [`PathRewriteRule`] applies to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathRewriteTarget {
+    /// Both the code and the debug file.
+    #[default]
+    All,
+    /// Only the code file.
+    CodeFile,
+    /// Only the debug file.
+    DebugFile,
+}
+
+/// The case a path is converted to after rewriting it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathCase {
+    /// The case is preserved.
+    #[default]
+    Preserve,
+    /// The path is converted to lowercase.
+    Lower,
+    /// The path is converted to uppercase.
+    Upper,
+}
+
+/// Rewrites the file paths of modules before looking up their debug files.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PathRewriteRule {
+    /// The file paths this rule applies to.
+    #[serde(default)]
+    pub target: PathRewriteTarget,
+
+    /// The regular expression matched against the path.
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+
+    /// The replacement for all matches of the pattern, which can refer to capture groups as in
+    /// `$1` or `${name}`.
+    #[serde(default)]
+    pub replacement: String,
+
+    /// The case the path is converted to if the pattern matched.
+    #[serde(default)]
+    pub case: PathCase,
+}
+
+/// Default demangling behavior for symbols of one language.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct DemangleLanguageConfig {
+    /// Whether demangled names include the return type of the function.
+    pub return_type: bool,
+
+    /// Whether demangled names include the argument list of the function.
+    ///
+    /// Requests can override this with the `demangle_parameters` option.
+    pub parameters: bool,
+}
+
+impl Default for DemangleLanguageConfig {
+    fn default() -> Self {
+        Self {
+            return_type: false,
+            parameters: true,
+        }
+    }
+}
+
+/// Default demangling behavior, which is applied when rendering symbolicated frames.
+///
+/// Symbols of languages without a dedicated config use the defaults of
+/// [`DemangleLanguageConfig`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct DemanglingConfig {
+    /// Options for C++ symbols, including Objective-C++.
+    pub cpp: DemangleLanguageConfig,
+
+    /// Options for Swift symbols.
+    pub swift: DemangleLanguageConfig,
+}
+
+/// Maps source files to URLs in a source code repository.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct SourceLinkConfig {
+    /// The scopes of requests this mapping applies to.
+    ///
+    /// The mapping applies to requests of all scopes if this is empty.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    /// The prefix of the absolute source paths recorded in debug files.
+    pub prefix: String,
+
+    /// The template for the URL of a source file.
+    ///
+    /// The placeholders `{path}`, `{line}` and `{commit}` are replaced with the path of the
+    /// source file relative to the [`prefix`](Self::prefix), the line number and the commit
+    /// given in the request, respectively.
+    pub url: String,
+}
+
+/// Substitutes the prefix of source paths in symbolicated frames.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct PathMappingConfig {
+    /// The scopes of requests this mapping applies to.
+    ///
+    /// The mapping applies to requests of all scopes if this is empty.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    /// The prefix of the source paths recorded in debug files, such as the build directory.
+    pub from: String,
+
+    /// The prefix substituted for [`from`](Self::from).
+    ///
+    /// If this is empty, the mapped paths are relative.
+    #[serde(default)]
+    pub to: String,
+}
+
+/// The key deciding whether a [`FeatureFlagConfig`] is enabled for a request.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RolloutKey {
+    /// All requests of a scope get the same decision.
+    #[default]
+    Scope,
+    /// Each request is decided on its own, based on a hash of its contents.
+    Request,
+}
+
+/// A feature flag, which is gradually rolled out to a percentage of requests.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeatureFlagConfig {
+    /// The percentage of requests the flag is enabled for, from `0` to `100`.
+    pub rollout: f64,
+
+    /// Whether the rollout is decided per scope or per request.
+    pub key: RolloutKey,
+
+    /// Scopes for which the flag is always enabled, regardless of the rollout.
+    pub scopes: Vec<String>,
 }
 
 /// See docs/index.md for more information on config values.
@@ -353,6 +1097,16 @@ pub struct Config {
     #[cfg(feature = "https")]
     pub bind_https: Option<String>,
 
+    /// Host and port to bind the gRPC server to.
+    #[cfg(feature = "grpc")]
+    pub bind_grpc: Option<String>,
+
+    /// Host and port the downloader process started with `symbolicator downloader` listens on.
+    ///
+    /// This is a loopback address by default, as the downloader is meant to be reached by
+    /// instances running on the same host.
+    pub bind_downloader: String,
+
     /// Configuration for internal logging.
     pub logging: Logging,
 
@@ -374,8 +1128,45 @@ pub struct Config {
     /// Default list of sources and the sources used for proxy mode.
     pub sources: Arc<[SourceConfig]>,
 
+    /// Backends resolving secret references in the credentials of sources.
+    pub secrets: SecretsConfig,
+
+    /// Sources whose objects are rejected unless they carry a valid detached signature.
+    pub signatures: Vec<SignatureConfig>,
+
+    /// Scans newly downloaded object files for malware before they are cached.
+    ///
+    /// Defaults to `None`, which disables scanning.
+    pub malware_scan: Option<MalwareScanConfig>,
+
+    /// The master key decrypting encrypted values in this configuration, and encrypting the
+    /// credentials of stored sources.
+    ///
+    /// Defaults to `None`, in which case values cannot be encrypted.
+    pub encryption: Option<EncryptionConfig>,
+
     /// Allow reserved IP addresses for requests to sources.
-    pub connect_to_reserved_ips: bool,
+    ///
+    /// This is either `true` or `false` for all sources, or a list of the IDs of sources which
+    /// are allowed to connect to reserved IP addresses.
+    pub connect_to_reserved_ips: ReservedIpsPolicy,
+
+    /// Tuning of the connection pools used to download from HTTP sources.
+    pub connection_pool: ConnectionPoolConfigs,
+
+    /// The URL of a separate downloader process, started with `symbolicator downloader`.
+    ///
+    /// If set, all files are downloaded by that process instead of fetching them from the
+    /// sources directly. Listing files on sources and reading files from filesystem sources is
+    /// still done locally. Requests to the downloader carry the `downloader_token`, which is
+    /// required if this is set.
+    pub downloader_url: Option<Url>,
+
+    /// The token authenticating requests to the downloader process.
+    ///
+    /// The downloader process requires it from clients, and instances configured with a
+    /// `downloader_url` send it along with their requests.
+    pub downloader_token: Option<String>,
 
     /// The maximum timeout for downloads.
     ///
@@ -385,6 +1176,12 @@ pub struct Config {
     #[serde(with = "humantime_serde")]
     pub max_download_timeout: Duration,
 
+    /// The maximum size in bytes of a downloaded file once it is decompressed.
+    ///
+    /// This also applies to files extracted from downloaded archives, such as zipped dSYMs.
+    /// Downloads exceeding it are treated as malformed. The default is 4 GiB.
+    pub max_download_size: u64,
+
     /// The timeout for the initial HEAD request in a download.
     ///
     /// This timeout applies to each individual attempt to establish a
@@ -426,6 +1223,99 @@ pub struct Config {
     /// A value of `None` indicates no limit.
     pub max_concurrent_requests: Option<usize>,
 
+    /// The maximum number of requests that are symbolicated at the same time.
+    ///
+    /// Further requests are accepted, but wait in a queue until a running request finishes.
+    /// Waiting requests are started in order of their priority, and in the order they arrived
+    /// within the same priority. A value of `None` starts all requests right away.
+    pub max_running_requests: Option<usize>,
+
+    /// The maximum time for which the result of a finished request can be polled.
+    ///
+    /// Clients can request a different time to keep their results around using the
+    /// `result_ttl` request option, which is capped to this value.
+    #[serde(with = "humantime_serde")]
+    pub max_result_ttl: Duration,
+
+    /// The maximum time a poll for the result of a request waits for the request to finish.
+    ///
+    /// This caps the `timeout` requested by clients, and also applies to polls without a
+    /// `timeout`, which would otherwise wait until the request finished.
+    ///
+    /// A value of `None` indicates no limit.
+    #[serde(with = "humantime_serde")]
+    pub max_poll_timeout: Option<Duration>,
+
+    /// Whether polls for unknown requests are rejected with `421 Misdirected Request`.
+    ///
+    /// This only applies to polls carrying the `affinity` token of the request, and tells the
+    /// client to retry the poll, so that it can be routed to the instance processing the request.
+    /// Otherwise, polls for unknown requests fail with `404 Not Found`.
+    pub reject_foreign_polls: bool,
+
+    /// The token authenticating requests to the administrative endpoints which modify caches.
+    ///
+    /// Clients pass it as a bearer token in the `Authorization` header. These endpoints are
+    /// disabled if no token is configured.
+    pub admin_token: Option<String>,
+
+    /// Heuristics to fill in missing identifiers of ELF modules.
+    pub elf_id_heuristics: ElfIdHeuristicsConfig,
+
+    /// Rules rewriting the code and debug file paths of modules before looking up debug files.
+    ///
+    /// All matching rules are applied, in order.
+    pub path_rewrites: Vec<PathRewriteRule>,
+
+    /// Default behavior of the demangler.
+    pub demangling: DemanglingConfig,
+
+    /// Mappings of source files to URLs, the first matching mapping is used.
+    pub source_links: Vec<SourceLinkConfig>,
+
+    /// Mappings of source path prefixes in symbolicated frames, the first matching mapping is
+    /// used.
+    pub path_mappings: Vec<PathMappingConfig>,
+
+    /// Rules to classify frames as in-app, the first matching rule is used.
+    ///
+    /// Requests can provide their own rules, which take precedence.
+    pub in_app_rules: Vec<InAppRule>,
+
+    /// Rules to classify the kind of package of frames by their module, the first matching rule
+    /// is used.
+    ///
+    /// Requests can provide their own rules, which take precedence.
+    pub package_rules: Vec<PackageRule>,
+
+    /// Feature flags consulted in the symbolication pipeline, keyed by their name.
+    pub feature_flags: BTreeMap<String, FeatureFlagConfig>,
+
+    /// Caps on the number of threads, modules and memory regions processed per minidump.
+    pub minidump: MinidumpConfig,
+
+    /// Archives a sample of the symbolication requests and their responses to disk.
+    ///
+    /// Defaults to `None`, which disables recording.
+    pub recording: Option<RecordingConfig>,
+
+    /// Path of the file persisting the source configurations stored through the admin API.
+    ///
+    /// Defaults to `None`, in which case stored source configurations are kept in memory only.
+    pub stored_sources: Option<PathBuf>,
+
+    /// Base URLs of other Symbolicators which requests may compare their responses with.
+    ///
+    /// Requests naming any other URL in their `compare_with` option are rejected. Defaults to no
+    /// URLs, which disables comparisons.
+    pub comparison_targets: Vec<Url>,
+
+    /// Directory of the journal of accepted requests.
+    ///
+    /// If configured, minidump and stacktrace requests are journaled when they are accepted,
+    /// and requests which did not complete because the process died are restarted on startup.
+    pub journal: Option<PathBuf>,
+
     /// An optional shared cache between multiple symbolicators.
     ///
     /// If configured this cache location is queried whenever a cache item is not found in
@@ -436,6 +1326,13 @@ pub struct Config {
     /// caches from already running symbolicators.
     pub shared_cache: Option<SharedCacheConfig>,
 
+    /// Optional durable storage for the responses of finished requests.
+    ///
+    /// If configured, every response is also written to this storage, and polls for requests
+    /// which are no longer known to this instance are answered from it. This allows polling
+    /// responses after a restart, or long after their `result_ttl` expired.
+    pub result_storage: Option<ResultStorageConfig>,
+
     /// Internal. Enables crash handling and sets the absolute path to where minidumps should be
     /// cached on disk. The path is created if it doesn't exist. Path must be UTF-8.
     #[serde(default)]
@@ -480,6 +1377,11 @@ fn default_bind() -> String {
     }
 }
 
+/// Default value for the "bind_downloader" configuration.
+fn default_bind_downloader() -> String {
+    "127.0.0.1:3022".to_owned()
+}
+
 /// Default value for the "cache_dir" configuration.
 fn default_cache_dir() -> Option<PathBuf> {
     if is_docker() {
@@ -497,6 +1399,9 @@ impl Default for Config {
             bind: default_bind(),
             #[cfg(feature = "https")]
             bind_https: None,
+            #[cfg(feature = "grpc")]
+            bind_grpc: None,
+            bind_downloader: default_bind_downloader(),
             logging: Logging::default(),
             #[cfg(feature = "https")]
             server_config: ServerConfig::default(),
@@ -504,10 +1409,18 @@ impl Default for Config {
             sentry_dsn: None,
             caches: CacheConfigs::default(),
             symstore_proxy: true,
+            downloader_url: None,
+            downloader_token: None,
             sources: Arc::from(vec![]),
-            connect_to_reserved_ips: false,
+            secrets: SecretsConfig::default(),
+            signatures: vec![],
+            malware_scan: None,
+            encryption: None,
+            connect_to_reserved_ips: ReservedIpsPolicy::Deny,
+            connection_pool: ConnectionPoolConfigs::default(),
             // Allow a 4MB/s connection to download 2GB without timing out
             max_download_timeout: Duration::from_secs(315),
+            max_download_size: 4 * 1024 * 1024 * 1024,
             connect_timeout: Duration::from_secs(15),
             // Allow a 4MB/s connection to download 1GB without timing out
             streaming_timeout: Duration::from_secs(250),
@@ -516,7 +1429,26 @@ impl Default for Config {
             deny_list_threshold: 20,
             deny_list_block_time: Duration::from_secs(24 * 60 * 60),
             max_concurrent_requests: Some(120),
+            max_running_requests: None,
+            max_result_ttl: Duration::from_secs(60 * 60),
+            max_poll_timeout: None,
+            reject_foreign_polls: false,
+            admin_token: None,
+            elf_id_heuristics: ElfIdHeuristicsConfig::default(),
+            path_rewrites: vec![],
+            demangling: DemanglingConfig::default(),
+            source_links: vec![],
+            path_mappings: vec![],
+            in_app_rules: vec![],
+            package_rules: vec![],
+            feature_flags: BTreeMap::new(),
+            minidump: MinidumpConfig::default(),
+            recording: None,
+            stored_sources: None,
+            comparison_targets: vec![],
+            journal: None,
             shared_cache: None,
+            result_storage: None,
             _crash_db: None,
         }
     }
@@ -541,7 +1473,30 @@ impl Config {
             anyhow::bail!("config file empty");
         }
         // check for empty files explicitly
-        serde_yaml::from_str(&config).context("failed to parse config YAML")
+        let parsed: Self = serde_yaml::from_str(&config).context("failed to parse config YAML")?;
+
+        // Encrypted values are decrypted in memory, and the configuration is parsed again.
+        let parsed = match parsed.encryption {
+            Some(ref encryption) => {
+                let key = MasterKey::load(encryption)?;
+                let mut value: serde_yaml::Value = serde_yaml::from_str(&config)?;
+                key.decrypt_yaml(&mut value)
+                    .context("failed to decrypt config values")?;
+                serde_yaml::from_value(value).context("failed to parse decrypted config YAML")?
+            }
+            None => parsed,
+        };
+
+        parsed.validate()?;
+        Ok(parsed)
+    }
+
+    /// Checks constraints between options which cannot be expressed by their types.
+    fn validate(&self) -> Result<()> {
+        if self.downloader_url.is_some() && self.downloader_token.is_none() {
+            anyhow::bail!("downloader_url requires a downloader_token");
+        }
+        Ok(())
     }
 }
 
@@ -583,6 +1538,31 @@ fn deserialize_level_filter<'de, D: Deserializer<'de>>(
     deserializer.deserialize_str(LevelFilterVisitor)
 }
 
+fn deserialize_public_keys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<u8>>, D::Error> {
+    let encoded = Vec::<String>::deserialize(deserializer)?;
+    encoded
+        .iter()
+        .map(|key| {
+            let key = BASE64
+                .decode(key.trim().as_bytes())
+                .map_err(de::Error::custom)?;
+            if key.len() != 32 {
+                return Err(de::Error::custom(
+                    "Ed25519 public keys must be 32 bytes long",
+                ));
+            }
+            Ok(key)
+        })
+        .collect()
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(de::Error::custom)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -654,6 +1634,66 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_cache_overrides() {
+        let yaml = r#"
+            caches:
+              derived:
+                max_unused_for: 1d
+              overrides:
+                cficaches:
+                  max_unused_for: 30d
+                  stale_while_revalidate: 1d
+                symcaches:
+                  retry_misses_after: null
+                objects:
+                  max_size: 1073741824
+                diagnostics:
+                  max_unused_for: 1h
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let derived: CacheConfig = cfg.caches.derived.into();
+        let diagnostics: CacheConfig = cfg.caches.diagnostics.into();
+
+        let cficaches = derived.with_overrides(&cfg.caches.overrides[&CacheName::Cficaches]);
+        assert_eq!(
+            cficaches.max_unused_for(),
+            Some(Duration::from_secs(3600 * 24 * 30))
+        );
+        assert_eq!(cficaches.retry_misses_after(), derived.retry_misses_after());
+        assert_eq!(
+            cficaches.stale_while_revalidate(),
+            Some(Duration::from_secs(3600 * 24))
+        );
+        assert_eq!(derived.stale_while_revalidate(), None);
+
+        let symcaches = derived.with_overrides(&cfg.caches.overrides[&CacheName::Symcaches]);
+        assert_eq!(
+            symcaches.max_unused_for(),
+            Some(Duration::from_secs(3600 * 24))
+        );
+        assert_eq!(symcaches.retry_misses_after(), None);
+        assert_eq!(
+            symcaches.retry_malformed_after(),
+            derived.retry_malformed_after()
+        );
+
+        let diagnostics =
+            diagnostics.with_overrides(&cfg.caches.overrides[&CacheName::Diagnostics]);
+        assert_eq!(
+            diagnostics.max_unused_for(),
+            Some(Duration::from_secs(3600))
+        );
+
+        let downloaded: CacheConfig = cfg.caches.downloaded.into();
+        assert_eq!(downloaded.max_size(), None);
+        let objects = downloaded.with_overrides(&cfg.caches.overrides[&CacheName::Objects]);
+        assert_eq!(objects.max_size(), Some(1024 * 1024 * 1024));
+        assert_eq!(objects.max_unused_for(), downloaded.max_unused_for());
+
+        assert!(!cfg.caches.overrides.contains_key(&CacheName::Il2cpp));
+    }
+
     #[test]
     fn test_unspecified_dl_timeouts() {
         let yaml = r#"
@@ -680,6 +1720,44 @@ mod tests {
         assert_eq!(cfg.streaming_timeout, Duration::from_secs(0));
     }
 
+    #[test]
+    fn test_minidump_limits() {
+        let yaml = r#"
+            minidump:
+              limits:
+                max_threads: 16
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(
+            cfg.minidump.limits,
+            MinidumpLimits {
+                max_threads: 16,
+                ..MinidumpLimits::default()
+            }
+        );
+        assert_eq!(cfg.minidump.max_size, MinidumpConfig::default().max_size);
+    }
+
+    #[test]
+    fn test_downloader_token() {
+        let yaml = r#"
+            downloader_url: http://127.0.0.1:3022/
+        "#;
+        let err = Config::from_reader(yaml.as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "downloader_url requires a downloader_token"
+        );
+
+        let yaml = r#"
+            downloader_url: http://127.0.0.1:3022/
+            downloader_token: secret
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(cfg.downloader_token.as_deref(), Some("secret"));
+        assert_eq!(cfg.admin_token, None);
+    }
+
     #[test]
     fn test_unknown_fields() {
         // Unknown fields should not cause failure
@@ -692,6 +1770,104 @@ mod tests {
         assert!(cfg.is_ok());
     }
 
+    #[test]
+    fn test_encrypted_values() {
+        let dir = crate::test::tempdir();
+        let key_file = dir.path().join("master.key");
+        std::fs::write(&key_file, "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=\n").unwrap();
+
+        let key = MasterKey::load(&EncryptionConfig {
+            key_file: key_file.clone(),
+        })
+        .unwrap();
+        let yaml = format!(
+            r#"
+            encryption:
+              key_file: {}
+            sources:
+              - id: sentry
+                type: sentry
+                url: https://sentry.example.com/
+                token: {}
+            "#,
+            key_file.display(),
+            key.encrypt("hunter2").unwrap()
+        );
+
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        match &cfg.sources[0] {
+            SourceConfig::Sentry(source) => assert_eq!(source.token, "hunter2"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_signature_keys() {
+        let yaml = r#"
+            signatures:
+              - sources: [builds]
+                public_keys: ["MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY="]
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(
+            cfg.signatures[0].public_keys[0],
+            b"0123456789abcdef0123456789abcdef"
+        );
+
+        // Keys of the wrong length are rejected when loading the config.
+        let yaml = r#"
+            signatures:
+              - sources: [builds]
+                public_keys: ["MDEyMzQ1Njc4OWFiY2RlZg=="]
+        "#;
+        assert!(Config::from_reader(yaml.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_reserved_ips_policy() {
+        let cfg = Config::get(None).unwrap();
+        assert_eq!(cfg.connect_to_reserved_ips, ReservedIpsPolicy::Deny);
+
+        let yaml = "connect_to_reserved_ips: true";
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(cfg.connect_to_reserved_ips, ReservedIpsPolicy::Allow);
+
+        let yaml = "connect_to_reserved_ips: [internal]";
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(
+            cfg.connect_to_reserved_ips,
+            ReservedIpsPolicy::Sources(vec!["internal".into()])
+        );
+    }
+
+    #[test]
+    fn test_connection_pool() {
+        let yaml = r#"
+            connection_pool:
+              max_idle_per_host: 16
+              idle_timeout: 30s
+              sources:
+                microsoft:
+                  max_idle_per_host: 64
+                  http2_keep_alive_interval: 10s
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let pools = &cfg.connection_pool;
+        assert_eq!(pools.default.max_idle_per_host, Some(16));
+        assert_eq!(pools.default.http2_keep_alive_interval, None);
+
+        let microsoft = pools.default.with_overrides(&pools.sources["microsoft"]);
+        assert_eq!(
+            microsoft,
+            ConnectionPoolConfig {
+                max_idle_per_host: Some(64),
+                idle_timeout: Some(Duration::from_secs(30)),
+                http2_keep_alive_interval: Some(Duration::from_secs(10)),
+                http2_keep_alive_timeout: None,
+            }
+        );
+    }
+
     #[test]
     fn test_empty_file() {
         // Empty files aren't supported
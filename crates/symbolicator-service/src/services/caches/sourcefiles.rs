@@ -95,6 +95,7 @@ impl SourceFilesCache {
             id: SourceId::new("web-scraping"),
             url,
             headers: Default::default(),
+            auth: None,
             files: Default::default(),
         });
         let location = SourceLocation::new("");
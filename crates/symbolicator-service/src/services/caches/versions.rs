@@ -14,7 +14,7 @@
 //! Some of the versioned caches are also tied to format versions defined in [`symbolic`].
 //! For those cases, there are static assertions that are a reminder to also bump the cache version.
 
-use crate::caching::CacheVersions;
+use crate::caching::{CacheName, CacheVersions};
 
 /// CFI cache, with the following versions:
 ///
@@ -65,22 +65,26 @@ static_assert!(symbolic::symcache::SYMCACHE_VERSION == 8);
 
 /// Data / Objects cache, with the following versions:
 ///
+/// - `2`: Validate that downloaded objects match the requested debug / code id.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const OBJECTS_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
-    fallbacks: &[],
+    current: 2,
+    fallbacks: &[1],
 };
 
 /// Objects Meta cache, with the following versions:
 ///
+/// - `2`: Validate that downloaded objects match the requested debug / code id.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const META_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
-    fallbacks: &[],
+    current: 2,
+    fallbacks: &[1],
 };
 
 /// Portable PDB cache, with the following versions:
@@ -111,6 +115,14 @@ pub const IL2CPP_CACHE_VERSIONS: CacheVersions = CacheVersions {
     fallbacks: &[],
 };
 
+/// ProGuard mapping cache, with the following versions:
+///
+/// - `1`: Initial version.
+pub const PROGUARD_CACHE_VERSIONS: CacheVersions = CacheVersions {
+    current: 1,
+    fallbacks: &[],
+};
+
 /// Bitcode / Auxdif (plist / bcsymbolmap) cache, with the following versions:
 ///
 /// - `1`: Recomputation to use new `CacheKey` format.
@@ -128,3 +140,17 @@ pub const SOURCEFILES_CACHE_VERSIONS: CacheVersions = CacheVersions {
     current: 1,
     fallbacks: &[],
 };
+
+/// The versions of all versioned caches, keyed by the name of the cache.
+pub const ALL_CACHE_VERSIONS: &[(CacheName, CacheVersions)] = &[
+    (CacheName::Objects, OBJECTS_CACHE_VERSIONS),
+    (CacheName::ObjectMeta, META_CACHE_VERSIONS),
+    (CacheName::Auxdifs, BITCODE_CACHE_VERSIONS),
+    (CacheName::Il2cpp, IL2CPP_CACHE_VERSIONS),
+    (CacheName::Proguard, PROGUARD_CACHE_VERSIONS),
+    (CacheName::Symcaches, SYMCACHE_VERSIONS),
+    (CacheName::Cficaches, CFICACHE_VERSIONS),
+    (CacheName::PpdbCaches, PPDB_CACHE_VERSIONS),
+    (CacheName::SourceMapCaches, SOURCEMAP_CACHE_VERSIONS),
+    (CacheName::SourceFiles, SOURCEFILES_CACHE_VERSIONS),
+];
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::io::{self, BufWriter};
 use std::sync::Arc;
 use std::time::Duration;
@@ -12,7 +11,8 @@ use symbolic::common::ByteView;
 use symbolicator_sources::{FileType, ObjectId, ObjectType, SourceConfig};
 
 use crate::caching::{
-    Cache, CacheEntry, CacheError, CacheItemRequest, CacheVersions, Cacher, SharedCacheRef,
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheName, CacheVersions, Cacher,
+    ObjectIdFilter, SharedCacheRef,
 };
 use crate::services::objects::{
     FindObject, ObjectHandle, ObjectMetaHandle, ObjectPurpose, ObjectsActor,
@@ -22,7 +22,9 @@ use crate::utils::futures::{m, measure};
 use crate::utils::sentry::ConfigureScope;
 
 use super::caches::versions::CFICACHE_VERSIONS;
-use super::derived::{derive_from_object_handle, DerivedCache};
+use super::derived::{
+    convert_with_budget, derive_from_object_handle, ConversionFile, ConversionLimits, DerivedCache,
+};
 
 type CfiItem = (u32, Option<Arc<SymbolFile>>);
 
@@ -48,21 +50,41 @@ fn parse_cfi_cache(bytes: ByteView<'static>) -> CacheEntry<CfiItem> {
 pub struct CfiCacheActor {
     cficaches: Arc<Cacher<FetchCfiCacheInternal>>,
     objects: ObjectsActor,
+    conversion_limits: ConversionLimits,
 }
 
 impl CfiCacheActor {
     pub fn new(cache: Cache, shared_cache: SharedCacheRef, objects: ObjectsActor) -> Self {
         CfiCacheActor {
+            conversion_limits: ConversionLimits::from_cache(&cache),
             cficaches: Arc::new(Cacher::new(cache, shared_cache)),
             objects,
         }
     }
+
+    /// Returns a copy of this actor which does not cache the CFI caches it computes.
+    ///
+    /// See [`Cacher::read_only`].
+    pub fn read_only(&self) -> Self {
+        Self {
+            cficaches: Arc::new(self.cficaches.read_only()),
+            ..self.clone()
+        }
+    }
+
+    /// Invalidates the negative CFI cache entries of the object matched by `filter`.
+    ///
+    /// Returns the number of entries removed from the file system.
+    pub fn invalidate_negative(&self, filter: &ObjectIdFilter) -> std::io::Result<usize> {
+        self.cficaches.invalidate_negative(filter)
+    }
 }
 
 #[derive(Clone, Debug)]
 struct FetchCfiCacheInternal {
     objects_actor: ObjectsActor,
     meta_handle: Arc<ObjectMetaHandle>,
+    conversion_limits: ConversionLimits,
 }
 
 /// Extracts the Call Frame Information (CFI) from an object file.
@@ -73,11 +95,18 @@ struct FetchCfiCacheInternal {
 async fn compute_cficache(
     objects_actor: &ObjectsActor,
     meta_handle: Arc<ObjectMetaHandle>,
+    conversion_limits: &ConversionLimits,
     temp_file: &mut NamedTempFile,
 ) -> CacheEntry {
     let object = objects_actor.fetch(meta_handle).await?;
 
-    write_cficache(temp_file.as_file_mut(), &object)
+    convert_with_budget(
+        CacheName::Cficaches,
+        conversion_limits,
+        temp_file,
+        move |file| write_cficache(file, &object),
+    )
+    .await
 }
 
 impl CacheItemRequest for FetchCfiCacheInternal {
@@ -86,7 +115,12 @@ impl CacheItemRequest for FetchCfiCacheInternal {
     const VERSIONS: CacheVersions = CFICACHE_VERSIONS;
 
     fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
-        let future = compute_cficache(&self.objects_actor, self.meta_handle.clone(), temp_file);
+        let future = compute_cficache(
+            &self.objects_actor,
+            self.meta_handle.clone(),
+            &self.conversion_limits,
+            temp_file,
+        );
 
         let timeout = Duration::from_secs(1200);
         let future = tokio::time::timeout(timeout, future);
@@ -138,6 +172,7 @@ impl CfiCacheActor {
             let request = FetchCfiCacheInternal {
                 objects_actor: self.objects.clone(),
                 meta_handle,
+                conversion_limits: self.conversion_limits.clone(),
             };
             async {
                 let entry = self.cficaches.compute_memoized(request, cache_key).await;
@@ -154,7 +189,7 @@ impl CfiCacheActor {
 /// The source file is probably an executable or so, the resulting file is in the format of
 /// [`CfiCache`].
 #[tracing::instrument(skip_all)]
-fn write_cficache(file: &mut File, object_handle: &ObjectHandle) -> CacheEntry {
+fn write_cficache(file: &mut ConversionFile, object_handle: &ObjectHandle) -> CacheEntry {
     object_handle.configure_scope();
 
     tracing::debug!("Converting cficache for {}", object_handle.cache_key);
@@ -165,12 +200,11 @@ fn write_cficache(file: &mut File, object_handle: &ObjectHandle) -> CacheEntry {
 
         CacheError::Malformed(e.to_string())
     })?;
+    file.ensure_active()?;
 
     let mut writer = BufWriter::new(file);
     cficache.write_to(&mut writer)?;
-
-    let file = writer.into_inner().map_err(io::Error::from)?;
-    file.sync_all()?;
+    writer.into_inner().map_err(io::Error::from)?;
 
     Ok(())
 }
@@ -1,6 +1,14 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::caching::{CacheEntry, CacheError};
+use parking_lot::Mutex;
+use tempfile::NamedTempFile;
+use tokio::sync::Semaphore;
+
+use crate::caching::{Cache, CacheEntry, CacheError, CacheName};
 use crate::services::objects::{FindResult, ObjectMetaHandle};
 use crate::types::{AllObjectCandidates, CandidateStatus, ObjectFeatures, ObjectUseInfo};
 
@@ -64,7 +72,11 @@ where
                 CacheError::NotFound
                 | CacheError::PermissionDenied(_)
                 | CacheError::Timeout(_)
-                | CacheError::DownloadError(_) => {
+                | CacheError::RateLimited(_)
+                | CacheError::DownloadError(_)
+                | CacheError::IdMismatch(_)
+                | CacheError::ChecksumMismatch(_)
+                | CacheError::SignatureInvalid(_) => {
                     // NOTE: all download related errors are already exposed as the candidates
                     // `ObjectDownloadInfo`. It is not necessary to duplicate that into the
                     // `ObjectUseInfo`.
@@ -87,3 +99,238 @@ where
         features,
     }
 }
+
+/// Conversions running on blocking threads, including abandoned ones.
+static RUNNING_CONVERSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Abandoned conversions which are still running on blocking threads.
+static ABANDONED_CONVERSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Limits on the conversions into a derived cache.
+#[derive(Clone, Debug)]
+pub struct ConversionLimits {
+    /// The wall-clock budget of a single conversion.
+    pub budget: Option<Duration>,
+    /// Permits for running conversions, shared by all derived caches.
+    ///
+    /// A permit is held until the conversion has actually stopped, even if it was abandoned.
+    pub permits: Option<Arc<Semaphore>>,
+}
+
+impl ConversionLimits {
+    /// Returns the limits configured for the given cache.
+    pub fn from_cache(cache: &Cache) -> Self {
+        Self {
+            budget: cache.max_conversion_time(),
+            permits: cache.conversion_permits(),
+        }
+    }
+}
+
+/// The state of a conversion, shared between its blocking thread and the awaiting task.
+#[derive(Debug, Default)]
+struct ConversionState {
+    /// The conversion exceeded its budget, and its output is discarded.
+    abandoned: bool,
+    /// The conversion function has returned.
+    finished: bool,
+}
+
+/// The output file of a conversion running on a blocking thread.
+///
+/// Once the conversion has been abandoned, the file is truncated and all further writes fail.
+#[derive(Debug)]
+pub struct ConversionFile {
+    file: File,
+    state: Arc<Mutex<ConversionState>>,
+}
+
+impl ConversionFile {
+    /// Fails if the conversion has been abandoned.
+    ///
+    /// Conversions call this between their steps, so that an abandoned conversion stops as soon
+    /// as the step it is currently running returns.
+    pub fn ensure_active(&self) -> io::Result<()> {
+        if self.state.lock().abandoned {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "conversion has been abandoned",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Write for ConversionFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let state = self.state.lock();
+        if state.abandoned {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "conversion has been abandoned",
+            ));
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn report_conversions() {
+    metric!(gauge("caches.conversion.running") = RUNNING_CONVERSIONS.load(Ordering::Relaxed));
+    metric!(
+        gauge("caches.conversion.abandoned_running") =
+            ABANDONED_CONVERSIONS.load(Ordering::Relaxed)
+    );
+}
+
+/// Runs the blocking `convert` function writing a derived cache item within the given `limits`.
+///
+/// The conversion runs on a blocking thread, so it does not occupy a worker of the runtime. The
+/// number of conversions running at once is bounded by the permits of the `limits`.
+///
+/// Once a conversion exceeds its budget it is abandoned: its output is discarded, and the item is
+/// cached as malformed so the same pathological file is not converted over and over again. An
+/// abandoned conversion stops at its next write or [`ConversionFile::ensure_active`] check, and
+/// keeps its permit until then.
+pub async fn convert_with_budget<F>(
+    cache: CacheName,
+    limits: &ConversionLimits,
+    temp_file: &mut NamedTempFile,
+    convert: F,
+) -> CacheEntry
+where
+    F: FnOnce(&mut ConversionFile) -> CacheEntry + Send + 'static,
+{
+    let permit = match &limits.permits {
+        Some(permits) => Some(
+            permits
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|_| CacheError::InternalError)?,
+        ),
+        None => None,
+    };
+
+    let state = Arc::new(Mutex::new(ConversionState::default()));
+    let mut file = ConversionFile {
+        file: temp_file.as_file().try_clone()?,
+        state: state.clone(),
+    };
+
+    RUNNING_CONVERSIONS.fetch_add(1, Ordering::Relaxed);
+    report_conversions();
+
+    let hub = sentry::Hub::current();
+    let span = tracing::Span::current();
+    let conversion = tokio::task::spawn_blocking(move || {
+        let result = sentry::Hub::run(hub, || {
+            span.in_scope(|| {
+                convert(&mut file)?;
+                file.file.sync_all()?;
+                Ok(())
+            })
+        });
+
+        {
+            let mut state = file.state.lock();
+            state.finished = true;
+            if state.abandoned {
+                ABANDONED_CONVERSIONS.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        RUNNING_CONVERSIONS.fetch_sub(1, Ordering::Relaxed);
+        report_conversions();
+        drop(permit);
+
+        result
+    });
+
+    let Some(budget) = limits.budget else {
+        return conversion.await.map_err(|_| CacheError::InternalError)?;
+    };
+
+    match tokio::time::timeout(budget, conversion).await {
+        Ok(result) => result.map_err(|_| CacheError::InternalError)?,
+        Err(_) => {
+            {
+                let mut state = state.lock();
+                state.abandoned = true;
+                if !state.finished {
+                    ABANDONED_CONVERSIONS.fetch_add(1, Ordering::Relaxed);
+                }
+                temp_file.as_file().set_len(0)?;
+            }
+            report_conversions();
+
+            tracing::error!(
+                cache = %cache,
+                budget = ?budget,
+                "Conversion exceeded its time budget and was abandoned"
+            );
+            metric!(counter("caches.conversion.abandoned") += 1, "cache" => cache.as_ref());
+
+            Err(CacheError::Malformed(format!(
+                "conversion exceeded the time budget of {}",
+                humantime::format_duration(budget)
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn read_to_string(temp_file: &NamedTempFile) -> String {
+        let mut content = String::new();
+        temp_file
+            .reopen()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[tokio::test]
+    async fn test_convert_with_budget() {
+        let permits = Arc::new(Semaphore::new(1));
+        let limits = ConversionLimits {
+            budget: Some(Duration::from_millis(100)),
+            permits: Some(permits.clone()),
+        };
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let result = convert_with_budget(CacheName::Symcaches, &limits, &mut temp_file, |file| {
+            file.write_all(b"converted")?;
+            Ok(())
+        })
+        .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(read_to_string(&temp_file), "converted");
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let result = convert_with_budget(CacheName::Symcaches, &limits, &mut temp_file, |file| {
+            file.write_all(b"partial")?;
+            std::thread::sleep(Duration::from_millis(300));
+            file.ensure_active()?;
+            file.write_all(b"converted")?;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(result, Err(CacheError::Malformed(_))));
+
+        // The abandoned conversion still holds its permit until it actually stops.
+        assert_eq!(permits.available_permits(), 0);
+
+        // The abandoned conversion does not write anything anymore.
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(read_to_string(&temp_file), "");
+        assert_eq!(permits.available_permits(), 1);
+    }
+}
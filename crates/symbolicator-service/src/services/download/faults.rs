@@ -0,0 +1,203 @@
+//! Support to download from fault injection sources.
+//!
+//! These serve files from a local directory like filesystem sources, but make downloads slow,
+//! throttled, truncated, or corrupt according to the [`FaultSchedule`] of the source. This
+//! exercises the retries, the host deny list, the cooldowns of rate limited sources, and the
+//! handling of malformed objects without depending on a misbehaving server.
+//!
+//! Faults are drawn from a hash of the seed, the location of the file, and the number of previous
+//! attempts to download it, so a retry of a failed download may succeed, and a test run with
+//! the same requests always sees the same faults.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tokio::fs;
+
+use symbolicator_sources::{FaultInjectionRemoteFile, FaultSchedule};
+
+use crate::caching::{CacheEntry, CacheError};
+
+/// The number of bytes at the start of the file which are overwritten in corrupt downloads.
+///
+/// This covers the headers of all object file formats.
+const CORRUPT_BYTES: usize = 64;
+
+/// A fault injected into a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Throttle,
+    Truncate,
+    Corrupt,
+}
+
+impl Fault {
+    fn as_str(self) -> &'static str {
+        match self {
+            Fault::Throttle => "throttle",
+            Fault::Truncate => "truncate",
+            Fault::Corrupt => "corrupt",
+        }
+    }
+}
+
+/// Draws a number in `[0, 1)` for the given attempt to download `path`.
+///
+/// Independent decisions about the same attempt use a different `salt`.
+fn draw(schedule: &FaultSchedule, path: &Path, attempt: u64, salt: u64) -> f64 {
+    // FNV-1a, which unlike the std hasher is stable across releases.
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in path.to_string_lossy().as_bytes() {
+        hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+
+    // Finalize with the mixing function of splitmix64.
+    let mut x = hash ^ schedule.seed ^ attempt.rotate_left(32) ^ salt.rotate_left(48);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Returns the fault injected into the given attempt to download `path`, if any.
+fn pick_fault(schedule: &FaultSchedule, path: &Path, attempt: u64) -> Option<Fault> {
+    let mut roll = draw(schedule, path, attempt, 0);
+    for (fault, rate) in [
+        (Fault::Throttle, schedule.throttle_rate),
+        (Fault::Truncate, schedule.truncate_rate),
+        (Fault::Corrupt, schedule.corrupt_rate),
+    ] {
+        if roll < rate {
+            return Some(fault);
+        }
+        roll -= rate;
+    }
+    None
+}
+
+/// Downloader implementation that supports the fault injection source.
+#[derive(Debug, Default)]
+pub struct FaultInjectionDownloader {
+    /// The number of download attempts so far, by the path of the file.
+    ///
+    /// This is never cleared, which is fine for the limited sets of files used in tests.
+    attempts: Mutex<HashMap<PathBuf, u64>>,
+}
+
+impl FaultInjectionDownloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Download from a fault injection source.
+    pub async fn download_source(
+        &self,
+        file_source: FaultInjectionRemoteFile,
+        dest: &Path,
+    ) -> CacheEntry {
+        let schedule = &file_source.source.faults;
+        let abspath = file_source.path();
+        // Faults are drawn by location, so that they do not depend on where the directory is.
+        let location = file_source.location().path();
+
+        let attempt = {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(abspath.clone()).or_default();
+            *attempt += 1;
+            *attempt
+        };
+
+        if draw(schedule, location, attempt, 1) < schedule.latency_rate {
+            metric!(counter("source.fault_injection.fault") += 1, "fault" => "latency");
+            tokio::time::sleep(schedule.latency).await;
+        }
+
+        let fault = pick_fault(schedule, location, attempt);
+        if let Some(fault) = fault {
+            tracing::debug!("Injecting {} fault into {:?}", fault.as_str(), abspath);
+            metric!(counter("source.fault_injection.fault") += 1, "fault" => fault.as_str());
+        }
+        if fault == Some(Fault::Throttle) {
+            return Err(CacheError::RateLimited(schedule.retry_after));
+        }
+
+        fs::copy(&abspath, dest).await.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CacheError::NotFound,
+            _ => e.into(),
+        })?;
+
+        match fault {
+            Some(Fault::Truncate) => {
+                let file = fs::OpenOptions::new().write(true).open(dest).await?;
+                let len = file.metadata().await?.len();
+                file.set_len(len / 2).await?;
+            }
+            Some(Fault::Corrupt) => {
+                let mut data = fs::read(dest).await?;
+                for byte in data.iter_mut().take(CORRUPT_BYTES) {
+                    *byte = !*byte;
+                }
+                fs::write(dest, data).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_fault() {
+        let schedule = FaultSchedule {
+            seed: 42,
+            throttle_rate: 0.2,
+            truncate_rate: 0.2,
+            corrupt_rate: 0.2,
+            ..FaultSchedule::default()
+        };
+        let path = Path::new("symbols/crash.pdb");
+
+        let faults: Vec<_> = (1..=1000)
+            .map(|attempt| pick_fault(&schedule, path, attempt))
+            .collect();
+        // The same schedule always injects the same faults.
+        let again: Vec<_> = (1..=1000)
+            .map(|attempt| pick_fault(&schedule, path, attempt))
+            .collect();
+        assert_eq!(faults, again);
+
+        let count = |fault| faults.iter().filter(|f| **f == fault).count();
+        for fault in [
+            Some(Fault::Throttle),
+            Some(Fault::Truncate),
+            Some(Fault::Corrupt),
+        ] {
+            assert!((150..250).contains(&count(fault)), "{fault:?}");
+        }
+        assert!((350..450).contains(&count(None)));
+
+        // Another seed injects faults into other attempts.
+        let other = FaultSchedule {
+            seed: 43,
+            ..schedule
+        };
+        let other: Vec<_> = (1..=1000)
+            .map(|attempt| pick_fault(&other, path, attempt))
+            .collect();
+        assert_ne!(faults, other);
+    }
+
+    #[test]
+    fn test_no_faults() {
+        let schedule = FaultSchedule::default();
+        let path = Path::new("symbols/crash.pdb");
+        assert!((1..=100).all(|attempt| pick_fault(&schedule, path, attempt).is_none()));
+    }
+}
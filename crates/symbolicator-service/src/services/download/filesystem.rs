@@ -1,23 +1,85 @@
 //! Support to download from the local filesystem.
 //!
 //! It allows sources to be present on the local filesystem, usually only used for testing.
+//!
+//! Sources with an `index_ttl` keep an in-memory index of the files in their directory, so that
+//! candidate paths can be checked without touching the disk, which is slow on network
+//! filesystems.
 
+use std::collections::HashSet;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
 use tokio::fs;
 
-use symbolicator_sources::FilesystemRemoteFile;
+use symbolicator_sources::{FilesystemRemoteFile, FilesystemSourceConfig};
 
 use crate::caching::{CacheEntry, CacheError};
 
+/// The relative paths of all files in the directory of a filesystem source.
+type FileIndex = Arc<HashSet<PathBuf>>;
+
+/// The indexes of filesystem sources keyed by their directory, along with their expiration.
+type FileIndexCache = moka::future::Cache<PathBuf, (Instant, CacheEntry<FileIndex>)>;
+
+/// The maximum number of filesystem source directories that are indexed at the same time.
+const INDEX_CAPACITY: u64 = 100;
+
 /// Downloader implementation that supports the filesystem source.
 #[derive(Debug)]
-pub struct FilesystemDownloader {}
+pub struct FilesystemDownloader {
+    index_cache: FileIndexCache,
+}
 
 impl FilesystemDownloader {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            index_cache: FileIndexCache::new(INDEX_CAPACITY),
+        }
+    }
+
+    /// Returns the index of the files of `source`, or `None` if the source is not indexed.
+    ///
+    /// The directory is scanned again once the index is older than the `index_ttl` of the
+    /// source. Concurrent lookups share a single scan.
+    pub async fn index(&self, source: &FilesystemSourceConfig) -> Option<FileIndex> {
+        let ttl = source.index_ttl?;
+        let root = source.path.clone();
+
+        let init = Box::pin(async {
+            metric!(counter("source.filesystem.index.scan") += 1);
+            let scan_root = root.clone();
+            let index = tokio::task::spawn_blocking(move || scan_directory(&scan_root))
+                .await
+                .map_err(|_| CacheError::InternalError)
+                .and_then(|result| result.map_err(CacheError::from))
+                .map(|files| {
+                    metric!(gauge("source.filesystem.index.files") = files.len() as u64);
+                    Arc::new(files)
+                });
+            (Instant::now() + ttl, index)
+        });
+        let replace_if = |(expires, index): &(Instant, CacheEntry<FileIndex>)| {
+            index.is_err() || *expires <= Instant::now()
+        };
+
+        let (_, index) = self
+            .index_cache
+            .entry_by_ref(&root)
+            .or_insert_with_if(init, replace_if)
+            .await
+            .into_value();
+
+        match index {
+            Ok(index) => Some(index),
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Failed to index {}", root.display());
+                None
+            }
+        }
     }
 
     /// Download from a filesystem source.
@@ -39,3 +101,44 @@ impl FilesystemDownloader {
             })
     }
 }
+
+/// Lists the paths of all files below `root`, relative to `root`.
+fn scan_directory(root: &Path) -> io::Result<HashSet<PathBuf>> {
+    let mut files = HashSet::new();
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            // Symlinks to directories are not followed, to rule out cycles.
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.insert(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test;
+
+    #[test]
+    fn test_scan_directory() {
+        let tempdir = test::tempdir();
+        std::fs::create_dir_all(tempdir.path().join("ab/cdef")).unwrap();
+        std::fs::write(tempdir.path().join("ab/cdef/debuginfo"), b"").unwrap();
+        std::fs::write(tempdir.path().join("index.txt"), b"").unwrap();
+
+        let files = scan_directory(tempdir.path()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.contains(Path::new("ab/cdef/debuginfo")));
+        assert!(files.contains(Path::new("index.txt")));
+    }
+}
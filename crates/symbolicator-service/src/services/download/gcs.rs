@@ -77,11 +77,15 @@ impl GcsDownloader {
 
         let url = gcs::download_url(&bucket, &key)?;
 
-        let source = RemoteFile::from(file_source);
-        let request = self
+        let mut request = self
             .client
             .get(url.clone())
             .header("authorization", token.bearer_token());
+        if let Some(ref user_project) = file_source.source.requester_pays {
+            request = request.header("x-goog-user-project", user_project);
+        }
+
+        let source = RemoteFile::from(file_source);
 
         super::download_reqwest(
             &source,
@@ -113,6 +117,7 @@ mod tests {
             id: SourceId::new("gcs-test"),
             bucket: "sentryio-system-symbols-0".to_owned(),
             prefix: "/ios".to_owned(),
+            requester_pays: None,
             source_key: Arc::new(source_key),
             files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
         })
@@ -212,6 +217,7 @@ mod tests {
             id: SourceId::new("gcs-id"),
             bucket: String::from("bucket"),
             prefix: String::from("prefix"),
+            requester_pays: None,
             source_key,
             files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
         });
@@ -0,0 +1,289 @@
+//! Rolling health statistics of the configured sources.
+//!
+//! Every download from a configured source is recorded with its outcome and latency. The most
+//! recent downloads of each source make up its [`SourceHealth`], which is reported by the admin
+//! API and in metrics.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use symbolicator_sources::{RemoteFile, SourceConfig, SourceId};
+
+use crate::caching::{CacheEntry, CacheError};
+
+/// The number of recent downloads from which the health of a source is computed.
+const HEALTH_WINDOW: usize = 100;
+
+/// The outcome of a single download.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    failed: bool,
+    latency: Duration,
+}
+
+/// The recent downloads of a source.
+#[derive(Debug, Default)]
+struct SourceStats {
+    samples: VecDeque<Sample>,
+    consecutive_failures: u64,
+}
+
+impl SourceStats {
+    fn health(&self, source: &SourceId) -> SourceHealth {
+        let downloads = self.samples.len();
+        let failures = self.samples.iter().filter(|sample| sample.failed).count();
+        let error_rate = if downloads > 0 {
+            failures as f64 / downloads as f64
+        } else {
+            0.0
+        };
+
+        let mut latencies: Vec<_> = self.samples.iter().map(|sample| sample.latency).collect();
+        latencies.sort_unstable();
+        let median_latency_ms = latencies
+            .get(downloads / 2)
+            .map(|latency| latency.as_millis() as u64);
+
+        SourceHealth {
+            source: source.clone(),
+            downloads,
+            error_rate,
+            median_latency_ms,
+            consecutive_failures: self.consecutive_failures,
+        }
+    }
+}
+
+/// The health of a source, computed from its most recent downloads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceHealth {
+    /// The ID of the source.
+    pub source: SourceId,
+    /// The number of recent downloads the health is computed from.
+    pub downloads: usize,
+    /// The fraction of recent downloads which failed, from `0` to `1`.
+    ///
+    /// Files which do not exist on the source do not count as failures, but errors, timeouts and
+    /// rate limits do.
+    pub error_rate: f64,
+    /// The median time recent downloads took, including failed ones.
+    pub median_latency_ms: Option<u64>,
+    /// The number of downloads which failed in a row since the last successful one.
+    pub consecutive_failures: u64,
+}
+
+/// Whether the result of a download points to a problem with its source.
+fn is_failure(result: &CacheEntry) -> bool {
+    matches!(
+        result,
+        Err(CacheError::DownloadError(_) | CacheError::Timeout(_) | CacheError::RateLimited(_))
+    )
+}
+
+/// A configured source along with its recent downloads.
+#[derive(Debug)]
+struct TrackedSource {
+    config: SourceConfig,
+    stats: Mutex<SourceStats>,
+}
+
+/// Tracks the health of the configured sources.
+///
+/// Sources sent with requests are not tracked, as there is no bound on their number. This includes
+/// sources which reuse the ID of a configured source.
+#[derive(Debug)]
+pub struct SourceHealthTracker {
+    sources: BTreeMap<SourceId, TrackedSource>,
+}
+
+impl SourceHealthTracker {
+    /// Creates a tracker for the given configured sources.
+    pub fn new(sources: impl IntoIterator<Item = SourceConfig>) -> Self {
+        let sources = sources
+            .into_iter()
+            .map(|config| {
+                let source = TrackedSource {
+                    config,
+                    stats: Mutex::default(),
+                };
+                (source.config.id().clone(), source)
+            })
+            .collect();
+        Self { sources }
+    }
+
+    /// Records the `result` of a download of `file` which took `latency`.
+    pub fn record(&self, file: &RemoteFile, result: &CacheEntry, latency: Duration) {
+        let source = file.source_id();
+        let Some(tracked) = self.sources.get(source) else {
+            return;
+        };
+        if !file.is_on(&tracked.config) {
+            return;
+        }
+        let stats = &tracked.stats;
+
+        let failed = is_failure(result);
+        let health = {
+            let mut stats = stats.lock().unwrap();
+            if stats.samples.len() == HEALTH_WINDOW {
+                stats.samples.pop_front();
+            }
+            stats.samples.push_back(Sample { failed, latency });
+            stats.consecutive_failures = if failed {
+                stats.consecutive_failures + 1
+            } else {
+                0
+            };
+            stats.health(source)
+        };
+
+        let source = source.as_str();
+        let error_percent = (health.error_rate * 100.0).round() as u64;
+        metric!(gauge("source.health.error_rate") = error_percent, "source" => source);
+        metric!(
+            gauge("source.health.consecutive_failures") = health.consecutive_failures,
+            "source" => source
+        );
+        if let Some(latency) = health.median_latency_ms {
+            metric!(gauge("source.health.median_latency") = latency, "source" => source);
+        }
+    }
+
+    /// Returns the health of all tracked sources.
+    pub fn health(&self) -> Vec<SourceHealth> {
+        self.sources
+            .iter()
+            .map(|(id, tracked)| tracked.stats.lock().unwrap().health(id))
+            .collect()
+    }
+
+    /// Returns the health of a single source, or `None` if it is not tracked.
+    pub fn source_health(&self, source: &SourceId) -> Option<SourceHealth> {
+        let tracked = self.sources.get(source)?;
+        Some(tracked.stats.lock().unwrap().health(source))
+    }
+
+    /// Returns how long downloads are currently expected to take.
+    ///
+    /// This is the highest median latency of all sources, as a request waits for the slowest
+    /// of its downloads. It is `None` as long as nothing has been downloaded.
+    pub fn expected_latency(&self) -> Option<Duration> {
+        self.health()
+            .iter()
+            .filter_map(|health| health.median_latency_ms)
+            .max()
+            .map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use symbolicator_sources::{FilesystemRemoteFile, FilesystemSourceConfig, SourceLocation};
+
+    use super::*;
+
+    fn source(id: &str) -> Arc<FilesystemSourceConfig> {
+        Arc::new(FilesystemSourceConfig {
+            id: SourceId::new(id),
+            path: PathBuf::from("/symbols"),
+            index_ttl: None,
+            files: Default::default(),
+        })
+    }
+
+    fn file(source: &Arc<FilesystemSourceConfig>) -> RemoteFile {
+        FilesystemRemoteFile::new(source.clone(), SourceLocation::new("crash.pdb")).into()
+    }
+
+    #[test]
+    fn test_source_health() {
+        let healthy = source("healthy");
+        let flaky = source("flaky");
+        let tracker = SourceHealthTracker::new([
+            SourceConfig::Filesystem(healthy.clone()),
+            SourceConfig::Filesystem(flaky.clone()),
+        ]);
+
+        for latency in [10, 20, 30] {
+            let latency = Duration::from_millis(latency);
+            tracker.record(&file(&healthy), &Ok(()), latency);
+            tracker.record(&file(&healthy), &Err(CacheError::NotFound), latency);
+        }
+        tracker.record(&file(&flaky), &Ok(()), Duration::from_millis(10));
+        let timeout = Duration::from_secs(5);
+        tracker.record(&file(&flaky), &Err(CacheError::Timeout(timeout)), timeout);
+        tracker.record(&file(&flaky), &Err(CacheError::Timeout(timeout)), timeout);
+
+        // Sources sent with requests are not tracked.
+        let unknown = source("unknown");
+        tracker.record(&file(&unknown), &Ok(()), Duration::from_millis(10));
+        assert_eq!(tracker.source_health(&unknown.id), None);
+
+        let health = tracker.health();
+        assert_eq!(
+            health,
+            [
+                SourceHealth {
+                    source: flaky.id.clone(),
+                    downloads: 3,
+                    error_rate: 2.0 / 3.0,
+                    median_latency_ms: Some(5000),
+                    consecutive_failures: 2,
+                },
+                SourceHealth {
+                    source: healthy.id.clone(),
+                    downloads: 6,
+                    error_rate: 0.0,
+                    median_latency_ms: Some(20),
+                    consecutive_failures: 0,
+                },
+            ]
+        );
+
+        assert_eq!(tracker.expected_latency(), Some(timeout));
+    }
+
+    #[test]
+    fn test_source_health_reused_id() {
+        let configured = source("configured");
+        let tracker = SourceHealthTracker::new([SourceConfig::Filesystem(configured.clone())]);
+
+        // A source sent with a request which reuses the ID of a configured one.
+        let impostor = source("configured");
+        tracker.record(
+            &file(&impostor),
+            &Err(CacheError::DownloadError("500".into())),
+            Duration::from_secs(5),
+        );
+
+        let health = tracker.source_health(&configured.id).unwrap();
+        assert_eq!(health.downloads, 0);
+        assert_eq!(health.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_health_window() {
+        let source = source("source");
+        let tracker = SourceHealthTracker::new([SourceConfig::Filesystem(source.clone())]);
+
+        tracker.record(
+            &file(&source),
+            &Err(CacheError::DownloadError("500".into())),
+            Duration::ZERO,
+        );
+        for _ in 0..HEALTH_WINDOW {
+            tracker.record(&file(&source), &Ok(()), Duration::ZERO);
+        }
+
+        let health = tracker.source_health(&source.id).unwrap();
+        assert_eq!(health.downloads, HEALTH_WINDOW);
+        assert_eq!(health.error_rate, 0.0);
+    }
+}
@@ -1,43 +1,229 @@
 //! Support to download from HTTP sources.
 
+use std::fmt;
 use std::path::Path;
 use std::time::Duration;
 
-use reqwest::{header, Client};
+use data_encoding::BASE64;
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
+use url::Url;
 
-use symbolicator_sources::{HttpRemoteFile, RemoteFile};
+use symbolicator_sources::{HttpAuth, HttpRemoteFile, NtlmCredentials, RemoteFile};
 
 use crate::caching::{CacheEntry, CacheError};
 
+use super::ntlm;
+use super::revalidation::Validators;
 use super::USER_AGENT;
 
+/// The content encodings of downloads from HTTP sources which symbolicator can decode.
+///
+/// `gzip`, `deflate` and `br` are decoded by the HTTP client, and `zstd` after the download.
+const ACCEPT_ENCODING: &str = "gzip, deflate, br, zstd";
+
+/// Creates HTTP clients with connection pools of their own.
+pub struct ClientFactory(Box<dyn Fn() -> Client + Send + Sync>);
+
+impl ClientFactory {
+    pub fn new(create: impl Fn() -> Client + Send + Sync + 'static) -> Self {
+        Self(Box::new(create))
+    }
+
+    fn create(&self) -> Client {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for ClientFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ClientFactory").finish()
+    }
+}
+
 /// Downloader implementation that supports the HTTP source.
 #[derive(Debug)]
 pub struct HttpDownloader {
     client: Client,
+    /// Creates the clients of NTLM authenticated downloads.
+    ///
+    /// NTLM authenticates a connection rather than single requests, so every authenticated
+    /// download needs a client of its own which keeps the handshake on one connection.
+    auth_clients: ClientFactory,
     connect_timeout: Duration,
     streaming_timeout: Duration,
 }
 
 impl HttpDownloader {
-    pub fn new(client: Client, connect_timeout: Duration, streaming_timeout: Duration) -> Self {
+    pub fn new(
+        client: Client,
+        auth_clients: ClientFactory,
+        connect_timeout: Duration,
+        streaming_timeout: Duration,
+    ) -> Self {
         Self {
             client,
+            auth_clients,
             connect_timeout,
             streaming_timeout,
         }
     }
 
     /// Downloads a source hosted on an HTTP server.
+    ///
+    /// Returns the [`Validators`] of the downloaded file, if the server sent any.
     pub async fn download_source(
         &self,
         file_source: HttpRemoteFile,
         destination: &Path,
+    ) -> CacheEntry<Option<Validators>> {
+        let source = RemoteFile::from(file_source.clone());
+        let response = super::measure_download_time(
+            source.source_metric_key(),
+            self.send(&file_source, |request| request),
+        )
+        .await?;
+
+        let headers =
+            super::download_response(&source, response, self.streaming_timeout, destination)
+                .await?;
+
+        Ok(Validators::from_headers(&headers))
+    }
+
+    /// Downloads the file at `url` on behalf of `source`.
+    ///
+    /// Unlike [`download_source`](Self::download_source), this does not send the headers
+    /// configured for the source, as `url` may point to any server.
+    pub async fn download_url(
+        &self,
+        source: &RemoteFile,
+        url: Url,
+        destination: &Path,
     ) -> CacheEntry {
+        tracing::debug!("Fetching debug file from {}", url);
+        let request = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::ACCEPT_ENCODING, ACCEPT_ENCODING);
+
+        super::download_reqwest(
+            source,
+            request,
+            self.connect_timeout,
+            self.streaming_timeout,
+            destination,
+        )
+        .await
+    }
+
+    /// Asks the HTTP server whether the file with the given `validators` is still current.
+    ///
+    /// Returns `true` if the file is unchanged. A changed file is not downloaded.
+    pub async fn revalidate_source(
+        &self,
+        file_source: HttpRemoteFile,
+        validators: &Validators,
+    ) -> CacheEntry<bool> {
+        let response = self
+            .send(&file_source, |mut request| {
+                if let Some(ref etag) = validators.etag {
+                    request = request.header(header::IF_NONE_MATCH, etag.as_str());
+                }
+                if let Some(ref last_modified) = validators.last_modified {
+                    request = request.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+                }
+                request
+            })
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            Ok(true)
+        } else if status.is_success() {
+            // The body is discarded along with the response, the changed file is downloaded
+            // again by the caller.
+            Ok(false)
+        } else if status == StatusCode::TOO_MANY_REQUESTS {
+            Err(super::rate_limited(response.headers()))
+        } else if status.is_client_error() {
+            Err(CacheError::NotFound)
+        } else {
+            Err(CacheError::DownloadError(status.to_string()))
+        }
+    }
+
+    /// Sends a GET request for the file, authenticated as configured for its source.
+    ///
+    /// `customize` is applied to every request sent for the file.
+    async fn send(
+        &self,
+        file_source: &HttpRemoteFile,
+        customize: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> CacheEntry<Response> {
+        match file_source.source.auth {
+            Some(HttpAuth::Ntlm(ref credentials)) => {
+                self.send_ntlm(file_source, credentials, customize).await
+            }
+            None => {
+                let request = customize(self.request(&self.client, file_source)?);
+                self.send_request(request).await
+            }
+        }
+    }
+
+    /// Sends the request for the file as part of an NTLM handshake.
+    ///
+    /// The negotiate message is sent along with the first request, so the response of servers
+    /// which do not ask for authentication is returned right away. Otherwise, the authenticated
+    /// request is sent on the connection of the handshake.
+    async fn send_ntlm(
+        &self,
+        file_source: &HttpRemoteFile,
+        credentials: &NtlmCredentials,
+        customize: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> CacheEntry<Response> {
+        let client = self.auth_clients.create();
+
+        let negotiate = BASE64.encode(&ntlm::negotiate_message());
+        let request = customize(self.request(&client, file_source)?)
+            .header(header::AUTHORIZATION, format!("NTLM {negotiate}"));
+        let response = self.send_request(request).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            tracing::debug!("Server did not ask for NTLM authentication");
+            return Ok(response);
+        }
+
+        let Some(challenge) = ntlm::find_challenge(response.headers()) else {
+            let details = if ntlm::offers_negotiate(response.headers()) {
+                "server requires Negotiate authentication, which is not supported"
+            } else {
+                "server did not send an NTLM challenge"
+            };
+            return Err(CacheError::PermissionDenied(details.into()));
+        };
+        // The body needs to be read for the connection to be reused.
+        let _ = response.bytes().await;
+
+        let authenticate = BASE64.encode(&ntlm::authenticate_message(credentials, &challenge));
+        let request = customize(self.request(&client, file_source)?)
+            .header(header::AUTHORIZATION, format!("NTLM {authenticate}"));
+        self.send_request(request).await
+    }
+
+    async fn send_request(&self, request: RequestBuilder) -> CacheEntry<Response> {
+        let response = tokio::time::timeout(self.connect_timeout, request.send())
+            .await
+            .map_err(|_| CacheError::Timeout(self.connect_timeout))??;
+        Ok(response)
+    }
+
+    /// Creates a GET request for the file, including the headers configured for its source.
+    fn request(&self, client: &Client, file_source: &HttpRemoteFile) -> CacheEntry<RequestBuilder> {
         let download_url = file_source.url().map_err(|_| CacheError::NotFound)?;
 
         tracing::debug!("Fetching debug file from {}", download_url);
-        let mut builder = self.client.get(download_url.clone());
+        let mut builder = client.get(download_url);
 
         let headers = file_source
             .source
@@ -50,17 +236,9 @@ impl HttpDownloader {
             }
         }
 
-        let request = builder.header(header::USER_AGENT, USER_AGENT);
-
-        let source = RemoteFile::from(file_source);
-        super::download_reqwest(
-            &source,
-            request,
-            self.connect_timeout,
-            self.streaming_timeout,
-            destination,
-        )
-        .await
+        Ok(builder
+            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::ACCEPT_ENCODING, ACCEPT_ENCODING))
     }
 }
 
@@ -68,9 +246,132 @@ impl HttpDownloader {
 mod tests {
     use super::*;
 
-    use symbolicator_sources::{SourceConfig, SourceLocation};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::ConnectInfo;
+    use axum::http::HeaderMap;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+    use symbolicator_sources::{HttpSourceConfig, SourceConfig, SourceId, SourceLocation};
 
+    use crate::config::Config;
     use crate::test;
+    use crate::utils::http::create_client;
+
+    /// An NTLM challenge message without target information.
+    const NTLM_CHALLENGE: &str = "TlRMTVNTUAACAAAAAAAAAAAAAAAFggigASNFZ4mrze8AAAAAAAAAAAAAAAAwAAAA";
+
+    /// The peer address and NTLM message type of a request received by [`ntlm_server`].
+    type NtlmRequests = Arc<Mutex<Vec<(SocketAddr, Option<u8>)>>>;
+
+    /// Spawns a server which answers NTLM negotiate messages with a challenge if `require_auth`.
+    fn ntlm_server(require_auth: bool) -> (Url, NtlmRequests) {
+        let requests = NtlmRequests::default();
+
+        let received = requests.clone();
+        let handler = move |ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap| {
+            let message_type = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok()?.strip_prefix("NTLM "))
+                .and_then(|token| BASE64.decode(token.as_bytes()).ok())
+                .and_then(|message| message.get(8).copied());
+            received.lock().unwrap().push((addr, message_type));
+
+            async move {
+                if require_auth && message_type == Some(1) {
+                    let challenge = format!("NTLM {NTLM_CHALLENGE}");
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        [(header::WWW_AUTHENTICATE, challenge)],
+                    )
+                        .into_response()
+                } else if require_auth && message_type != Some(3) {
+                    StatusCode::UNAUTHORIZED.into_response()
+                } else {
+                    "hello world\n".into_response()
+                }
+            }
+        };
+
+        let router = Router::new().route("/hello.txt", get(handler));
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = axum::Server::bind(&addr)
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>());
+        let url = format!("http://localhost:{}/", server.local_addr().port());
+        tokio::spawn(server);
+
+        (url.parse().unwrap(), requests)
+    }
+
+    fn ntlm_file(url: Url) -> HttpRemoteFile {
+        let source = HttpSourceConfig {
+            id: SourceId::new("ntlm"),
+            url,
+            headers: Default::default(),
+            auth: Some(HttpAuth::Ntlm(NtlmCredentials {
+                username: "DOMAIN\\user".into(),
+                password: "password".into(),
+                domain: String::new(),
+            })),
+            files: Default::default(),
+        };
+        HttpRemoteFile::new(Arc::new(source), SourceLocation::new("hello.txt"))
+    }
+
+    fn downloader() -> HttpDownloader {
+        HttpDownloader::new(
+            Client::new(),
+            ClientFactory::new(|| Client::builder().http1_only().build().unwrap()),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_ntlm_handshake() {
+        test::setup();
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let (url, requests) = ntlm_server(true);
+
+        let download_status = downloader()
+            .download_source(ntlm_file(url), tmpfile.path())
+            .await;
+        assert!(download_status.is_ok());
+        assert_eq!(
+            std::fs::read_to_string(tmpfile.path()).unwrap(),
+            "hello world\n"
+        );
+
+        // The authenticate message is sent on the connection of the negotiate message.
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].1, Some(1));
+        assert_eq!(requests[1].1, Some(3));
+        assert_eq!(requests[0].0, requests[1].0);
+    }
+
+    #[tokio::test]
+    async fn test_ntlm_not_required() {
+        test::setup();
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let (url, requests) = ntlm_server(false);
+
+        let download_status = downloader()
+            .download_source(ntlm_file(url), tmpfile.path())
+            .await;
+        assert!(download_status.is_ok());
+        assert_eq!(
+            std::fs::read_to_string(tmpfile.path()).unwrap(),
+            "hello world\n"
+        );
+
+        // The response to the negotiate message is used, without sending the request again.
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
 
     #[tokio::test]
     async fn test_download_source() {
@@ -89,6 +390,7 @@ mod tests {
 
         let downloader = HttpDownloader::new(
             Client::new(),
+            ClientFactory::new(Client::new),
             Duration::from_secs(30),
             Duration::from_secs(30),
         );
@@ -117,6 +419,7 @@ mod tests {
 
         let downloader = HttpDownloader::new(
             Client::new(),
+            ClientFactory::new(Client::new),
             Duration::from_secs(30),
             Duration::from_secs(30),
         );
@@ -124,4 +427,53 @@ mod tests {
 
         assert_eq!(download_status, Err(CacheError::NotFound));
     }
+
+    #[tokio::test]
+    async fn test_download_source_brotli() {
+        test::setup();
+
+        // "hello world\n" in a single uncompressed brotli meta-block, followed by an empty last one.
+        const BROTLI: &[u8] = b"\xb0\x00\x10hello world\n\x03";
+
+        let accept_encoding = Arc::new(Mutex::new(None));
+        let received = accept_encoding.clone();
+        let handler = move |headers: HeaderMap| {
+            *received.lock().unwrap() = headers.get(header::ACCEPT_ENCODING).cloned();
+            async move { ([(header::CONTENT_ENCODING, "br")], BROTLI) }
+        };
+
+        let router = Router::new().route("/hello.txt", get(handler));
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = axum::Server::bind(&addr).serve(router.into_make_service());
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+
+        let source = HttpSourceConfig {
+            id: SourceId::new("brotli"),
+            url: url.parse().unwrap(),
+            headers: Default::default(),
+            auth: None,
+            files: Default::default(),
+        };
+        let file_source = HttpRemoteFile::new(Arc::new(source), SourceLocation::new("hello.txt"));
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let downloader = HttpDownloader::new(
+            create_client(&Config::default(), true),
+            ClientFactory::new(Client::new),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+        );
+        let download_status = downloader
+            .download_source(file_source, tmpfile.path())
+            .await;
+        assert!(download_status.is_ok());
+
+        let content = std::fs::read_to_string(tmpfile.path()).unwrap();
+        assert_eq!(content, "hello world\n");
+        assert_eq!(
+            accept_encoding.lock().unwrap().as_ref().unwrap(),
+            ACCEPT_ENCODING
+        );
+    }
 }
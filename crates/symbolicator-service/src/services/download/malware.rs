@@ -0,0 +1,375 @@
+//! Scanning of newly downloaded object files for malware.
+//!
+//! If configured, every object file is passed to an external scanner after it has been
+//! downloaded, and before it is parsed and cached. Files in which the scanner finds malware are
+//! rejected, and copied into the `quarantine` directory of the diagnostics cache together with a
+//! JSON report of the finding, so that they can be inspected later.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use symbolicator_sources::{RemoteFile, RemoteFileUri, SourceId};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::caching::{CacheEntry, CacheError};
+use crate::config::{MalwareScanConfig, MalwareScannerConfig};
+
+/// The directory within the diagnostics cache which holds quarantined object files.
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// The port of ICAP services which do not specify one in their URL.
+const ICAP_DEFAULT_PORT: u16 = 1344;
+
+/// An error running the malware scanner.
+#[derive(Debug, Error)]
+enum ScanError {
+    #[error("the scanner command is empty")]
+    EmptyCommand,
+    #[error("the scanner exited with {0}: {1}")]
+    Command(ExitStatus, String),
+    #[error("the ICAP url has no host")]
+    IcapUrl,
+    #[error("unexpected ICAP response `{0}`")]
+    IcapResponse(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The outcome of a successful scan.
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Clean,
+    /// Malware was found, as described by the attached details.
+    Infected(String),
+}
+
+/// An object file which has been quarantined, as listed in the quarantine report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedObject {
+    /// The name of the quarantined copy within the quarantine directory.
+    pub file: String,
+    /// The source the object file was downloaded from.
+    pub source: SourceId,
+    /// The location of the object file on its source.
+    pub uri: RemoteFileUri,
+    /// The finding reported by the scanner.
+    pub details: String,
+    /// The time the object file was quarantined.
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// Scans downloaded object files with the configured scanner.
+#[derive(Debug)]
+pub struct MalwareScanner {
+    config: MalwareScanConfig,
+    /// The directory infected files are copied into, if the diagnostics cache is enabled.
+    quarantine_dir: Option<PathBuf>,
+}
+
+impl MalwareScanner {
+    pub fn new(config: MalwareScanConfig, quarantine_dir: Option<PathBuf>) -> Self {
+        Self {
+            config,
+            quarantine_dir,
+        }
+    }
+
+    /// Scans the object file at `path`, which has been downloaded for `file`.
+    ///
+    /// Infected files are quarantined and rejected as [`CacheError::Malformed`]. Files which
+    /// could not be scanned are rejected as well, so that they are downloaded and scanned again
+    /// once the negative cache entry expires.
+    pub async fn scan(&self, file: &RemoteFile, path: &Path) -> CacheEntry {
+        let scan = async {
+            match self.config.scanner {
+                MalwareScannerConfig::Command { ref command } => scan_command(command, path).await,
+                MalwareScannerConfig::Icap { ref url } => scan_icap(url, path).await,
+            }
+        };
+
+        let verdict = match tokio::time::timeout(self.config.timeout, scan).await {
+            Ok(Ok(verdict)) => verdict,
+            Ok(Err(err)) => {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Failed to scan `{}` for malware", file);
+                return Err(CacheError::DownloadError(format!(
+                    "malware scan failed: {err}"
+                )));
+            }
+            Err(_) => return Err(CacheError::Timeout(self.config.timeout)),
+        };
+
+        let Verdict::Infected(details) = verdict else {
+            return Ok(());
+        };
+
+        metric!(
+            counter("objects.malware_found") += 1,
+            "source" => file.source_metric_key(),
+        );
+        tracing::warn!("Found malware in `{}`: {}", file, details);
+
+        if let Err(err) = self.quarantine(file, path, &details).await {
+            let stderr: &dyn std::error::Error = &err;
+            tracing::error!(stderr, "Failed to quarantine `{}`", file);
+        }
+
+        Err(CacheError::Malformed(format!("malware found: {details}")))
+    }
+
+    /// Copies the infected file at `path` into the quarantine directory, next to its report.
+    async fn quarantine(&self, file: &RemoteFile, path: &Path, details: &str) -> io::Result<()> {
+        let Some(ref dir) = self.quarantine_dir else {
+            tracing::debug!("No diagnostics cache configured, not quarantining object file");
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(dir).await?;
+
+        let name = uuid::Uuid::new_v4().simple().to_string();
+        tokio::fs::copy(path, dir.join(&name)).await?;
+
+        let report = QuarantinedObject {
+            file: name.clone(),
+            source: file.source_id().clone(),
+            uri: file.uri(),
+            details: details.to_owned(),
+            quarantined_at: Utc::now(),
+        };
+        let report = serde_json::to_vec(&report)?;
+        tokio::fs::write(dir.join(format!("{name}.json")), report).await
+    }
+}
+
+/// Lists the object files quarantined in `dir`, most recently quarantined first.
+pub fn read_quarantine(dir: &Path) -> io::Result<Vec<QuarantinedObject>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let mut quarantined = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension() != Some("json".as_ref()) {
+            continue;
+        }
+
+        // Reports can be removed by cache cleanup at any time.
+        let report = match std::fs::read(&path) {
+            Ok(report) => report,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        match serde_json::from_slice(&report) {
+            Ok(report) => quarantined.push(report),
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Invalid quarantine report {}", path.display());
+            }
+        }
+    }
+
+    quarantined.sort_by(|a: &QuarantinedObject, b| b.quarantined_at.cmp(&a.quarantined_at));
+    Ok(quarantined)
+}
+
+/// Scans the file at `path` by passing it to `command` on stdin.
+async fn scan_command(command: &[String], path: &Path) -> Result<Verdict, ScanError> {
+    let (program, args) = command.split_first().ok_or(ScanError::EmptyCommand)?;
+
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::fs::File::open(path)?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    match output.status.code() {
+        Some(0) => Ok(Verdict::Clean),
+        Some(1) => {
+            let details = String::from_utf8_lossy(&output.stdout);
+            Ok(Verdict::Infected(details.trim().to_owned()))
+        }
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(ScanError::Command(output.status, stderr.trim().to_owned()))
+        }
+    }
+}
+
+/// Scans the file at `path` by sending it to the ICAP service at `url` in a `RESPMOD` request.
+///
+/// The service responds with `204 No Content` if the file is clean. Any other successful
+/// response means that the service modified the content, which it does to block malware.
+async fn scan_icap(url: &Url, path: &Path) -> Result<Verdict, ScanError> {
+    let host = url.host_str().ok_or(ScanError::IcapUrl)?;
+    let port = url.port().unwrap_or(ICAP_DEFAULT_PORT);
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+
+    // The file is encapsulated as the body of an HTTP response.
+    let http_head = "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\r\n";
+    let icap_head = format!(
+        "RESPMOD {url} ICAP/1.0\r\n\
+         Host: {host}\r\n\
+         Allow: 204\r\n\
+         Connection: close\r\n\
+         Encapsulated: res-hdr=0, res-body={}\r\n\r\n",
+        http_head.len()
+    );
+
+    let mut stream = TcpStream::connect((host, port)).await?;
+    stream.write_all(icap_head.as_bytes()).await?;
+    stream.write_all(http_head.as_bytes()).await?;
+    // The body is sent as a single chunk of the chunked transfer encoding.
+    if len > 0 {
+        stream.write_all(format!("{len:x}\r\n").as_bytes()).await?;
+        tokio::io::copy(&mut file, &mut stream).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.flush().await?;
+
+    let mut response = BufReader::new(stream);
+    let mut status_line = String::new();
+    response.read_line(&mut status_line).await?;
+
+    let mut details = None;
+    loop {
+        let mut line = String::new();
+        if response.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("X-Infection-Found")
+                || name.eq_ignore_ascii_case("X-Virus-ID")
+            {
+                details = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    match status_line.split_whitespace().nth(1) {
+        Some("204") => Ok(Verdict::Clean),
+        Some("200") => Ok(Verdict::Infected(
+            details.unwrap_or_else(|| "blocked by the ICAP service".into()),
+        )),
+        _ => Err(ScanError::IcapResponse(status_line.trim().to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use symbolicator_sources::{FilesystemRemoteFile, FilesystemSourceConfig, SourceLocation};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::test;
+
+    fn remote_file() -> RemoteFile {
+        let source = FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: PathBuf::from("/symbols"),
+            index_ttl: None,
+            files: Default::default(),
+        };
+        FilesystemRemoteFile::new(Arc::new(source), SourceLocation::new("foo.pdb")).into()
+    }
+
+    fn scanner(scanner: MalwareScannerConfig, quarantine_dir: Option<PathBuf>) -> MalwareScanner {
+        let config = MalwareScanConfig {
+            scanner,
+            timeout: Duration::from_secs(10),
+        };
+        MalwareScanner::new(config, quarantine_dir)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_scan_command() {
+        let dir = test::tempdir();
+        let quarantine_dir = dir.path().join(QUARANTINE_DIR);
+        let object = dir.path().join("object");
+        std::fs::write(&object, "X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR").unwrap();
+
+        let command = |script: &str| MalwareScannerConfig::Command {
+            command: vec!["sh".into(), "-c".into(), script.into()],
+        };
+
+        let failing = scanner(command("exit 2"), Some(quarantine_dir.clone()));
+        let result = failing.scan(&remote_file(), &object).await;
+        assert!(matches!(result, Err(CacheError::DownloadError(_))));
+
+        let clean = scanner(command("cat > /dev/null"), Some(quarantine_dir.clone()));
+        assert_eq!(clean.scan(&remote_file(), &object).await, Ok(()));
+        assert!(read_quarantine(&quarantine_dir).unwrap().is_empty());
+
+        // The scanner receives the file on stdin.
+        let infected = scanner(
+            command("grep -q EICAR && echo Eicar-Signature && exit 1"),
+            Some(quarantine_dir.clone()),
+        );
+        let result = infected.scan(&remote_file(), &object).await;
+        assert!(matches!(result, Err(CacheError::Malformed(_))));
+
+        let quarantined = read_quarantine(&quarantine_dir).unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].source.as_str(), "local");
+        assert_eq!(quarantined[0].details, "Eicar-Signature");
+        let copy = std::fs::read(quarantine_dir.join(&quarantined[0].file)).unwrap();
+        assert_eq!(copy, std::fs::read(&object).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_scan_icap() {
+        let dir = test::tempdir();
+        let object = dir.path().join("object");
+        std::fs::write(&object, "object contents").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("icap://{}/avscan", listener.local_addr().unwrap())).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            while !request.ends_with(b"0\r\n\r\n") {
+                let mut buf = [0; 1024];
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"ICAP/1.0 200 OK\r\nX-Infection-Found: Type=0; Threat=Eicar;\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let infected = scanner(MalwareScannerConfig::Icap { url }, None);
+        let result = infected.scan(&remote_file(), &object).await;
+        assert_eq!(
+            result,
+            Err(CacheError::Malformed(
+                "malware found: Type=0; Threat=Eicar;".into()
+            ))
+        );
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("RESPMOD icap://"));
+        assert!(request.ends_with("f\r\nobject contents\r\n0\r\n\r\n"));
+    }
+}
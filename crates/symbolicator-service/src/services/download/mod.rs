@@ -3,7 +3,7 @@
 //! The sources are described on
 //! <https://getsentry.github.io/symbolicator/advanced/symbol-server-compatibility/>
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::convert::TryInto;
 use std::error::Error;
 use std::path::{Path, PathBuf};
@@ -13,30 +13,51 @@ use std::time::{Duration, Instant, SystemTime};
 use ::sentry::SentryFutureExt;
 use futures::prelude::*;
 use reqwest::StatusCode;
+use symbolic::common::{DebugId, Uuid};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
+pub use health::SourceHealth;
+pub use malware::{read_quarantine, QuarantinedObject, QUARANTINE_DIR};
+pub use revalidation::VALIDATORS_DIR;
+pub use secrets::contains_secret_references;
 pub use symbolicator_sources::{
     DirectoryLayout, FileType, ObjectId, ObjectType, RemoteFile, RemoteFileUri, SourceConfig,
     SourceFilters, SourceLocation,
 };
 use symbolicator_sources::{
-    FilesystemRemoteFile, GcsRemoteFile, HttpRemoteFile, S3RemoteFile, SentrySourceConfig,
-    SourceLocationIter,
+    FaultInjectionRemoteFile, FilesystemRemoteFile, GcsRemoteFile, HttpRemoteFile,
+    HttpSourceConfig, S3RemoteFile, SentrySourceConfig, SourceId, SourceLocationIter,
 };
 
-use crate::caching::{CacheEntry, CacheError};
-use crate::config::{CacheConfigs, Config, InMemoryCacheConfig};
+use crate::caching::{CacheEntry, CacheError, ObjectIdFilter};
+use crate::config::{
+    CacheConfigs, Config, ConnectionPoolConfig, ElfIdHeuristicsConfig, InMemoryCacheConfig,
+    PathCase, PathRewriteRule, PathRewriteTarget, ReservedIpsPolicy,
+};
 use crate::services::download::sentry::SearchArtifactResult;
+use crate::utils::compression::zstd_decode_in_place;
 use crate::utils::futures::{m, measure, CancelOnDrop};
 use crate::utils::gcs::GcsError;
+use crate::utils::http::{create_connection_client, create_pooled_client};
 use crate::utils::sentry::ConfigureScope;
 
+mod faults;
 mod filesystem;
 mod gcs;
+mod health;
 mod http;
-mod s3;
+mod malware;
+mod ntlm;
+mod pointers;
+mod revalidation;
+pub(crate) mod s3;
+mod secrets;
 pub mod sentry;
+mod sidecar;
+mod signatures;
+
+use pointers::PointerTarget;
 
 impl ConfigureScope for RemoteFile {
     fn to_scope(&self, scope: &mut ::sentry::Scope) {
@@ -208,6 +229,116 @@ impl HostDenyList {
     }
 }
 
+/// The cooldown of a source which is rate limiting without a usable `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The longest cooldown of a rate limiting source, regardless of what it asks for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps track of sources which are rate limiting downloads.
+///
+/// When a source responds with `429 Too Many Requests`, all requests skip it until the end of
+/// the cooldown it asked for, instead of each of them hitting the source again on its own.
+/// Sources are identified by their ID along with their host, since sources sent with requests
+/// may reuse an ID for different servers.
+#[derive(Debug, Default)]
+struct SourceCooldowns {
+    cooling_down: Mutex<HashMap<(SourceId, String), Instant>>,
+}
+
+impl SourceCooldowns {
+    /// Returns the remaining cooldown of the source, if it is cooling down.
+    fn remaining(&self, source_id: &SourceId, host: &str) -> Option<Duration> {
+        let key = (source_id.clone(), host.to_owned());
+        let until = *self.cooling_down.lock().unwrap().get(&key)?;
+        let remaining = until.saturating_duration_since(Instant::now());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    /// Skips the source for the given `retry_after` period.
+    ///
+    /// A cooldown which is already in effect is only ever extended.
+    fn register(&self, source_id: SourceId, host: String, retry_after: Duration) {
+        if retry_after.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let until = now + retry_after.min(MAX_RETRY_AFTER);
+
+        let mut cooling_down = self.cooling_down.lock().unwrap();
+        cooling_down.retain(|_, until| *until > now);
+
+        tracing::info!(
+            source = %source_id,
+            %host,
+            retry_after = %humantime::format_duration(retry_after),
+            "Skipping rate limited source"
+        );
+        let entry = cooling_down.entry((source_id, host)).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+}
+
+/// Returns the configured HTTP sources which are allowed to connect to reserved IP addresses.
+fn reserved_ips_sources(config: &Config) -> Vec<Arc<HttpSourceConfig>> {
+    let ReservedIpsPolicy::Sources(ref ids) = config.connect_to_reserved_ips else {
+        return vec![];
+    };
+
+    let sources: Vec<_> = config
+        .sources
+        .iter()
+        .filter_map(|source| match source {
+            SourceConfig::Http(http) if ids.iter().any(|id| id == http.id.as_str()) => {
+                Some(http.clone())
+            }
+            _ => None,
+        })
+        .collect();
+
+    for id in ids {
+        if !sources.iter().any(|source| source.id.as_str() == id) {
+            tracing::warn!(
+                "Source `{}` may connect to reserved IP addresses, but it is not a configured HTTP source",
+                id
+            );
+        }
+    }
+
+    sources
+}
+
+/// The HTTP downloaders sharing the settings of a connection pool.
+#[derive(Debug)]
+struct HttpDownloaders {
+    /// Downloads from sources which may not connect to reserved IP addresses.
+    restricted: http::HttpDownloader,
+    /// Downloads from the HTTP sources which may connect to reserved IP addresses.
+    reserved_ips: http::HttpDownloader,
+}
+
+impl HttpDownloaders {
+    fn new(config: &Config, pool: &ConnectionPoolConfig) -> Self {
+        let shared_config = Arc::new(config.clone());
+        let downloader = |trusted| {
+            let auth_config = shared_config.clone();
+            let auth_clients =
+                http::ClientFactory::new(move || create_connection_client(&auth_config, trusted));
+            http::HttpDownloader::new(
+                create_pooled_client(config, trusted, pool),
+                auth_clients,
+                config.connect_timeout,
+                config.streaming_timeout,
+            )
+        };
+
+        Self {
+            restricted: downloader(false),
+            reserved_ips: downloader(true),
+        }
+    }
+}
+
 /// A service which can download files from a [`SourceConfig`].
 ///
 /// The service is rather simple on the outside but will one day control
@@ -216,12 +347,27 @@ impl HostDenyList {
 pub struct DownloadService {
     runtime: tokio::runtime::Handle,
     max_download_timeout: Duration,
+    max_download_size: u64,
     sentry: sentry::SentryDownloader,
-    http: http::HttpDownloader,
+    http: HttpDownloaders,
+    /// Downloaders with connection pools of their own, keyed by the ID of the source using them.
+    source_http: BTreeMap<String, HttpDownloaders>,
+    /// The configured HTTP sources which may connect to reserved IP addresses.
+    reserved_ips_sources: Vec<Arc<HttpSourceConfig>>,
     s3: s3::S3Downloader,
     gcs: gcs::GcsDownloader,
     fs: filesystem::FilesystemDownloader,
+    faults: faults::FaultInjectionDownloader,
+    sidecar: Option<sidecar::SidecarDownloader>,
     host_deny_list: HostDenyList,
+    cooldowns: SourceCooldowns,
+    health: health::SourceHealthTracker,
+    elf_id_heuristics: ElfIdHeuristicsConfig,
+    path_rewrites: Arc<[PathRewriteRule]>,
+    secrets: secrets::SecretResolver,
+    signatures: signatures::SignatureKeys,
+    malware_scanner: Option<malware::MalwareScanner>,
+    validators: revalidation::ValidatorStore,
 }
 
 impl DownloadService {
@@ -238,6 +384,8 @@ impl DownloadService {
             deny_list_bucket_size,
             deny_list_threshold,
             deny_list_block_time,
+            elf_id_heuristics,
+            ref downloader_url,
             ..
         } = *config;
 
@@ -250,12 +398,19 @@ impl DownloadService {
         Arc::new(Self {
             runtime: runtime.clone(),
             max_download_timeout: config.max_download_timeout,
-            sentry: sentry::SentryDownloader::new(trusted_client, runtime, config),
-            http: http::HttpDownloader::new(
-                restricted_client.clone(),
-                connect_timeout,
-                streaming_timeout,
-            ),
+            max_download_size: config.max_download_size,
+            sentry: sentry::SentryDownloader::new(trusted_client.clone(), runtime, config),
+            http: HttpDownloaders::new(config, &config.connection_pool.default),
+            source_http: config
+                .connection_pool
+                .sources
+                .iter()
+                .map(|(id, overrides)| {
+                    let pool = config.connection_pool.default.with_overrides(overrides);
+                    (id.clone(), HttpDownloaders::new(config, &pool))
+                })
+                .collect(),
+            reserved_ips_sources: reserved_ips_sources(config),
             s3: s3::S3Downloader::new(connect_timeout, streaming_timeout, *s3_client_capacity),
             gcs: gcs::GcsDownloader::new(
                 restricted_client,
@@ -264,17 +419,53 @@ impl DownloadService {
                 *gcs_token_capacity,
             ),
             fs: filesystem::FilesystemDownloader::new(),
+            faults: faults::FaultInjectionDownloader::new(),
+            sidecar: downloader_url.clone().map(|url| {
+                sidecar::SidecarDownloader::new(
+                    trusted_client.clone(),
+                    url,
+                    config.downloader_token.clone(),
+                    config.max_download_timeout,
+                    streaming_timeout,
+                )
+            }),
             host_deny_list: HostDenyList::new(
                 deny_list_time_window,
                 deny_list_bucket_size,
                 deny_list_threshold,
                 deny_list_block_time,
             ),
+            cooldowns: SourceCooldowns::default(),
+            health: health::SourceHealthTracker::new(config.sources.iter().cloned()),
+            elf_id_heuristics,
+            path_rewrites: config.path_rewrites.clone().into(),
+            secrets: secrets::SecretResolver::new(config.secrets.clone(), trusted_client),
+            signatures: signatures::SignatureKeys::new(config.signatures.clone()),
+            malware_scanner: config.malware_scan.clone().map(|scan| {
+                let quarantine_dir = config
+                    .cache_dir("diagnostics")
+                    .map(|d| d.join(QUARANTINE_DIR));
+                malware::MalwareScanner::new(scan, quarantine_dir)
+            }),
+            validators: revalidation::ValidatorStore::new(
+                config.cache_dir("objects").map(|d| d.join(VALIDATORS_DIR)),
+                config.caches.downloaded.revalidate_after,
+            ),
         })
     }
 
     /// Dispatches downloading of the given file to the appropriate source.
     async fn dispatch_download(&self, source: &RemoteFile, destination: &Path) -> CacheEntry {
+        let source = &self.secrets.resolve_file(source).await?;
+
+        // The downloader process already retries downloads on its own. Files on filesystem
+        // sources are local to this instance, and are always read directly.
+        if let Some(ref sidecar) = self.sidecar {
+            if !matches!(source, RemoteFile::Filesystem(_)) {
+                return sidecar.download_source(source, destination).await;
+            }
+        }
+
         let result = retry(|| async {
             match source {
                 RemoteFile::Sentry(inner) => {
@@ -283,7 +474,12 @@ impl DownloadService {
                         .await
                 }
                 RemoteFile::Http(inner) => {
-                    self.http.download_source(inner.clone(), destination).await
+                    let http = self.http_downloader(&inner.source);
+                    let validators = http.download_source(inner.clone(), destination).await?;
+                    if let Some(validators) = validators {
+                        self.validators.store(source, validators);
+                    }
+                    Ok(())
                 }
                 RemoteFile::S3(inner) => self.s3.download_source(inner.clone(), destination).await,
                 RemoteFile::Gcs(inner) => {
@@ -292,10 +488,18 @@ impl DownloadService {
                 RemoteFile::Filesystem(inner) => {
                     self.fs.download_source(inner.clone(), destination).await
                 }
+                RemoteFile::FaultInjection(inner) => {
+                    self.faults
+                        .download_source(inner.clone(), destination)
+                        .await
+                }
             }
         });
 
-        let result = result.await;
+        let result = match result.await {
+            Ok(()) if source.is_pointer() => self.follow_pointer(source, destination).await,
+            result => result,
+        };
 
         if let Err(err) = &result {
             tracing::debug!("File `{}` fetching failed: {}", source, err);
@@ -306,6 +510,69 @@ impl DownloadService {
         result
     }
 
+    /// Downloads the file referenced by the pointer file `source`, which was downloaded to
+    /// `destination`, replacing the pointer.
+    ///
+    /// URLs are downloaded without the credentials of the source, and may only connect to
+    /// reserved IP addresses if the HTTP source of the pointer may. Paths are only followed
+    /// within the directory of filesystem sources.
+    async fn follow_pointer(&self, source: &RemoteFile, destination: &Path) -> CacheEntry {
+        let contents = tokio::fs::read(destination).await?;
+        let target = pointers::parse_pointer(&contents)?;
+        tracing::debug!("Following pointer `{}` to {:?}", source, target);
+
+        match target {
+            PointerTarget::Url(url) => {
+                metric!(counter("downloads.pointer") += 1, "target" => "url");
+                let host = url.host_str().unwrap_or_default();
+                if self.host_deny_list.is_blocked(host) {
+                    return Err(CacheError::DownloadError(
+                        "Server is temporarily blocked".to_string(),
+                    ));
+                }
+                let downloader = match source {
+                    RemoteFile::Http(inner) => self.http_downloader(&inner.source),
+                    _ => &self.http.restricted,
+                };
+                downloader.download_url(source, url, destination).await
+            }
+            PointerTarget::Path(path) => {
+                metric!(counter("downloads.pointer") += 1, "target" => "path");
+                let RemoteFile::Filesystem(ref inner) = source else {
+                    return Err(CacheError::PermissionDenied(
+                        "paths in pointers are only followed in filesystem sources".into(),
+                    ));
+                };
+                let path = pointers::check_path_target(&inner.source.path, &path)?;
+                tokio::fs::copy(path, destination).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the downloader for the HTTP `source`, using the connection pool configured for it.
+    fn http_downloader(&self, source: &HttpSourceConfig) -> &http::HttpDownloader {
+        let downloaders = self
+            .source_http
+            .get(source.id.as_str())
+            .unwrap_or(&self.http);
+        if self.may_connect_to_reserved_ips(source) {
+            &downloaders.reserved_ips
+        } else {
+            &downloaders.restricted
+        }
+    }
+
+    /// Returns whether the HTTP `source` may connect to reserved IP addresses.
+    ///
+    /// Sources sent with requests are matched against the configured sources by ID and URL, so
+    /// that clients cannot reach arbitrary internal hosts by reusing the ID of a trusted source.
+    fn may_connect_to_reserved_ips(&self, source: &HttpSourceConfig) -> bool {
+        self.reserved_ips_sources
+            .iter()
+            .any(|trusted| trusted.id == source.id && trusted.url == source.url)
+    }
+
     /// Download a file from a source and store it on the local filesystem.
     ///
     /// This does not do any deduplication of requests, every requested file is freshly downloaded.
@@ -328,8 +595,16 @@ impl DownloadService {
         // this should be `"sentry:project"` instead, as defined here:
         // <https://github.com/getsentry/sentry/blob/b27ef04df6ecbaa0a34a472f787a163ca8400cc0/src/sentry/lang/native/sources.py#L17>
         let source_is_external = !source_metric_key.starts_with("sentry:");
+        // The downloader process maintains its own deny list if downloads are delegated to it.
+        let use_deny_list = source_is_external && self.sidecar.is_none();
 
-        if source_is_external && self.host_deny_list.is_blocked(&host) {
+        let source_id = source.source_id().clone();
+        if let Some(remaining) = self.cooldowns.remaining(&source_id, &host) {
+            metric!(counter("service.download.rate_limited") += 1, "source" => &source_metric_key);
+            return Err(CacheError::RateLimited(remaining));
+        }
+
+        if use_deny_list && self.host_deny_list.is_blocked(&host) {
             metric!(counter("service.download.blocked") += 1, "source" => &source_metric_key);
             return Err(CacheError::DownloadError(
                 "Server is temporarily blocked".to_string(),
@@ -337,12 +612,14 @@ impl DownloadService {
         }
 
         let slf = self.clone();
+        let file = source.clone();
 
-        let job = async move { slf.dispatch_download(&source, &destination).await };
+        let job = async move { slf.dispatch_download(&file, &destination).await };
         let job = CancelOnDrop::new(self.runtime.spawn(job.bind_hub(::sentry::Hub::current())));
         let job = tokio::time::timeout(self.max_download_timeout, job);
         let job = measure("service.download", m::timed_result, job);
 
+        let start = Instant::now();
         let result = match job.await {
             // Timeout
             Err(_) => Err(CacheError::Timeout(self.max_download_timeout)),
@@ -350,8 +627,14 @@ impl DownloadService {
             Ok(Err(_)) => Err(CacheError::InternalError),
             Ok(Ok(res)) => res,
         };
+        self.health.record(&source, &result, start.elapsed());
 
-        if source_is_external
+        if let Err(CacheError::RateLimited(retry_after)) = result {
+            self.cooldowns
+                .register(source_id, host.clone(), retry_after);
+        }
+
+        if use_deny_list
             && matches!(
                 result,
                 Err(CacheError::DownloadError(_) | CacheError::Timeout(_))
@@ -364,6 +647,120 @@ impl DownloadService {
         result
     }
 
+    /// Returns the health of the configured sources.
+    pub fn source_health(&self) -> Vec<SourceHealth> {
+        self.health.health()
+    }
+
+    /// Drops the cached file listings of Sentry sources for the object matched by `filter`.
+    pub fn invalidate_indexes(&self, filter: &ObjectIdFilter) {
+        self.sentry.invalidate_index(filter);
+    }
+
+    /// Returns how long downloads from the configured sources currently take.
+    ///
+    /// This is `None` until something has been downloaded from them.
+    pub fn expected_latency(&self) -> Option<Duration> {
+        self.health.expected_latency()
+    }
+
+    /// Returns the maximum size in bytes of a downloaded file once it is decompressed.
+    pub fn max_download_size(&self) -> u64 {
+        self.max_download_size
+    }
+
+    /// Returns whether the objects downloaded for `file` need to carry a valid signature.
+    pub fn requires_signature(&self, file: &RemoteFile) -> bool {
+        self.signatures.is_required(file.source_id())
+    }
+
+    /// Verifies the detached signature of the downloaded `data` of `file`.
+    ///
+    /// The signature is downloaded from next to the file into `signature_path`. A missing or
+    /// invalid signature results in [`CacheError::SignatureInvalid`], while a failure to download
+    /// the signature is returned as is.
+    pub async fn verify_signature(
+        self: &Arc<Self>,
+        file: &RemoteFile,
+        data: &[u8],
+        signature_path: PathBuf,
+    ) -> CacheEntry {
+        let Some(signature_file) = file.with_suffix(signatures::SIGNATURE_SUFFIX) else {
+            return Err(CacheError::SignatureInvalid(
+                "source does not support detached signatures".into(),
+            ));
+        };
+
+        match self.download(signature_file, signature_path.clone()).await {
+            Ok(()) => {}
+            Err(CacheError::NotFound) => {
+                return Err(CacheError::SignatureInvalid("missing signature".into()))
+            }
+            Err(err) => return Err(err),
+        }
+
+        let signature = tokio::fs::read(&signature_path).await?;
+        self.signatures.verify(file.source_id(), data, &signature)
+    }
+
+    /// Scans the newly downloaded object file at `path` for malware, if scanning is configured.
+    ///
+    /// Files in which malware is found are quarantined in the diagnostics cache.
+    pub async fn scan_object(&self, file: &RemoteFile, path: &Path) -> CacheEntry {
+        match self.malware_scanner {
+            Some(ref scanner) => scanner.scan(file, path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether the previously downloaded `file` is still current at its source.
+    ///
+    /// Files from HTTP sources are revalidated with a conditional request once their `ETag` or
+    /// `Last-Modified` validators are older than `revalidate_after`. This returns `false` if the
+    /// file changed or disappeared, and it needs to be downloaded again. If the source cannot be
+    /// reached, the previously downloaded file is considered current.
+    pub async fn revalidate(&self, file: &RemoteFile) -> bool {
+        // The downloader process records no validators for this service.
+        if self.sidecar.is_some() || !matches!(file, RemoteFile::Http(_)) {
+            return true;
+        }
+        let Some(validators) = self.validators.stale(file) else {
+            return true;
+        };
+
+        let result = match self.secrets.resolve_file(file).await {
+            Ok(RemoteFile::Http(inner)) => {
+                let http = self.http_downloader(&inner.source);
+                let job = http.revalidate_source(inner, &validators);
+                tokio::time::timeout(self.max_download_timeout, job)
+                    .await
+                    .unwrap_or(Err(CacheError::Timeout(self.max_download_timeout)))
+            }
+            Ok(_) => unreachable!("resolving secrets keeps the type of the source"),
+            Err(err) => Err(err),
+        };
+
+        let (current, status) = match result {
+            Ok(true) => {
+                self.validators.store(file, validators);
+                (true, "not_modified")
+            }
+            Ok(false) => (false, "modified"),
+            Err(CacheError::NotFound) => (false, "not_found"),
+            Err(err) => {
+                tracing::debug!("Failed to revalidate `{}`: {}", file, err);
+                (true, "error")
+            }
+        };
+        metric!(
+            counter("downloads.revalidation") += 1,
+            "source" => file.source_metric_key(),
+            "status" => status,
+        );
+
+        current
+    }
+
     /// Returns all objects matching the [`ObjectId`] at the source.
     ///
     /// Some sources, namely all the symbol servers, simply return the locations at which a
@@ -375,6 +772,10 @@ impl DownloadService {
     /// Note that the `filetypes` argument is not more then a hint, not all source types
     /// will respect this and they may return all DIFs matching the `object_id`.  After
     /// downloading you may still need to filter the files.
+    ///
+    /// The file paths of the `object_id` are first rewritten using the configured
+    /// [`PathRewriteRule`]s. Missing identifiers are then filled in using the configured
+    /// [`ElfIdHeuristicsConfig`], unless a source opts out of this via `strict_ids`.
     pub async fn list_files(
         &self,
         sources: &[SourceConfig],
@@ -383,8 +784,18 @@ impl DownloadService {
     ) -> Vec<RemoteFile> {
         let mut remote_files = vec![];
 
+        let rewritten_id = rewrite_paths(&self.path_rewrites, object_id);
+        let object_id = rewritten_id.as_ref().unwrap_or(object_id);
+
+        let guessed_id = guess_elf_ids(&self.elf_id_heuristics, object_id);
+        let object_id_for = |filters: &SourceFilters| match &guessed_id {
+            Some(guessed_id) if !filters.strict_ids => guessed_id,
+            _ => object_id,
+        };
+
         macro_rules! check_source {
             ($source:ident => $file_ty:ty) => {{
+                let object_id = object_id_for(&$source.files.filters);
                 let mut iter =
                     SourceLocationIter::new(&$source.files, filetypes, object_id).peekable();
                 if iter.peek().is_none() {
@@ -399,7 +810,12 @@ impl DownloadService {
         for source in sources {
             match source {
                 SourceConfig::Sentry(cfg) => {
-                    let job = self.sentry.list_files(cfg.clone(), object_id, filetypes);
+                    let cfg = match self.secrets.resolve_sentry(cfg).await {
+                        Ok(cfg) => cfg,
+                        // The error has already been logged while resolving.
+                        Err(_) => continue,
+                    };
+                    let job = self.sentry.list_files(cfg, object_id, filetypes);
                     let timeout = Duration::from_secs(30);
                     let job = tokio::time::timeout(timeout, job);
                     let job = measure("service.download.list_files", m::timed_result, job);
@@ -415,6 +831,7 @@ impl DownloadService {
                     }
                 }
                 SourceConfig::Http(cfg) => {
+                    let object_id = object_id_for(&cfg.files.filters);
                     let mut iter =
                         SourceLocationIter::new(&cfg.files, filetypes, object_id).peekable();
                     if iter.peek().is_none() {
@@ -436,7 +853,20 @@ impl DownloadService {
                 }
                 SourceConfig::S3(cfg) => check_source!(cfg => S3RemoteFile),
                 SourceConfig::Gcs(cfg) => check_source!(cfg => GcsRemoteFile),
-                SourceConfig::Filesystem(cfg) => check_source!(cfg => FilesystemRemoteFile),
+                SourceConfig::Filesystem(cfg) => match self.fs.index(cfg).await {
+                    Some(index) => {
+                        let object_id = object_id_for(&cfg.files.filters);
+                        let iter = SourceLocationIter::new(&cfg.files, filetypes, object_id);
+                        remote_files.extend(
+                            iter.filter(|loc| index.contains(loc.path()))
+                                .map(|loc| FilesystemRemoteFile::new(cfg.clone(), loc).into()),
+                        );
+                    }
+                    None => check_source!(cfg => FilesystemRemoteFile),
+                },
+                SourceConfig::FaultInjection(cfg) => {
+                    check_source!(cfg => FaultInjectionRemoteFile)
+                }
             }
         }
         remote_files
@@ -447,6 +877,10 @@ impl DownloadService {
         source: Arc<SentrySourceConfig>,
     ) -> Vec<SearchArtifactResult> {
         let mut remote_artifacts = vec![];
+        let Ok(source) = self.secrets.resolve_sentry(&source).await else {
+            // The error has already been logged while resolving.
+            return remote_artifacts;
+        };
         let job = self.sentry.list_artifacts(source.clone());
         let timeout = Duration::from_secs(30);
         let job = tokio::time::timeout(timeout, job);
@@ -477,7 +911,10 @@ where
         // its highly unlikely we get a different result when retrying these
         let should_not_retry = matches!(
             result,
-            Ok(_) | Err(CacheError::NotFound | CacheError::PermissionDenied(_))
+            Ok(_)
+                | Err(CacheError::NotFound
+                    | CacheError::PermissionDenied(_)
+                    | CacheError::RateLimited(_))
         );
 
         if should_not_retry || tries >= 3 {
@@ -545,6 +982,20 @@ async fn download_reqwest(
     let timeout_err = CacheError::Timeout(connect_timeout);
     let response = request.await.map_err(|_| timeout_err)??;
 
+    download_response(source, response, streaming_timeout, destination)
+        .await
+        .map(|_headers| ())
+}
+
+/// Downloads the body of a `response` to `destination`, and returns its headers.
+///
+/// Unsuccessful responses are mapped to the matching [`CacheError`].
+async fn download_response(
+    source: &RemoteFile,
+    response: reqwest::Response,
+    streaming_timeout: Duration,
+    destination: &Path,
+) -> CacheEntry<reqwest::header::HeaderMap> {
     let status = response.status();
     if status.is_success() {
         tracing::trace!("Success hitting `{}`", source);
@@ -556,9 +1007,12 @@ async fn download_reqwest(
             .and_then(|s| s.parse::<i64>().ok());
 
         let timeout = content_length.map(|cl| content_length_timeout(cl, streaming_timeout));
+        let headers = response.headers().clone();
         let stream = response.bytes_stream().map_err(CacheError::from);
 
-        download_stream(source, stream, destination, timeout).await
+        download_stream(source, stream, destination, timeout).await?;
+        decode_content(&headers, destination).await?;
+        Ok(headers)
     } else if matches!(status, StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED) {
         tracing::debug!(
             "Insufficient permissions to download `{}`: {}",
@@ -571,6 +1025,10 @@ async fn download_reqwest(
         let details = status.to_string();
 
         Err(CacheError::PermissionDenied(details))
+    } else if status == StatusCode::TOO_MANY_REQUESTS {
+        tracing::debug!("Rate limited by `{}`", source);
+
+        Err(rate_limited(response.headers()))
         // If it's a client error, chances are it's a 404.
     } else if status.is_client_error() {
         tracing::debug!(
@@ -588,6 +1046,48 @@ async fn download_reqwest(
     }
 }
 
+/// Creates the error for a `429 Too Many Requests` response with the given `headers`.
+///
+/// Only a `Retry-After` header in seconds is understood, an HTTP date is treated like a missing
+/// header.
+fn rate_limited(headers: &reqwest::header::HeaderMap) -> CacheError {
+    let retry_after = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+
+    CacheError::RateLimited(retry_after.min(MAX_RETRY_AFTER))
+}
+
+/// Removes the `Content-Encoding` of a response body downloaded to `destination`.
+///
+/// The HTTP client transparently decodes `gzip`, `deflate` and `br` bodies, which leaves
+/// `zstd` to be decoded here. Bodies in any other encoding are rejected.
+async fn decode_content(headers: &reqwest::header::HeaderMap, destination: &Path) -> CacheEntry {
+    let encoding = headers
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim);
+
+    match encoding {
+        None | Some("identity") => Ok(()),
+        Some("zstd") => {
+            metric!(counter("downloads.content_encoding") += 1, "encoding" => "zstd");
+            let destination = destination.to_owned();
+            tokio::task::spawn_blocking(move || zstd_decode_in_place(&destination))
+                .await
+                .map_err(|_| CacheError::InternalError)?
+                .map_err(|err| {
+                    CacheError::DownloadError(format!("failed to decode zstd content: {err}"))
+                })
+        }
+        Some(encoding) => Err(CacheError::DownloadError(format!(
+            "unsupported content encoding `{encoding}`"
+        ))),
+    }
+}
+
 /// State of the [`MeasureSourceDownloadGuard`].
 #[derive(Clone, Copy, Debug)]
 enum MeasureState {
@@ -696,6 +1196,89 @@ where
     }
 }
 
+/// Fills in missing identifiers of ELF modules according to the configured heuristics.
+///
+/// Returns `None` if the heuristics did not change anything.
+fn guess_elf_ids(config: &ElfIdHeuristicsConfig, object_id: &ObjectId) -> Option<ObjectId> {
+    if object_id.object_type != ObjectType::Elf {
+        return None;
+    }
+
+    let mut guessed_id = object_id.clone();
+    let mut changed = false;
+    if config.derive_debug_id && object_id.debug_id.is_none() {
+        if let Some(code_id) = &object_id.code_id {
+            guessed_id.debug_id = debug_id_from_build_id(code_id.as_str());
+            changed |= guessed_id.debug_id.is_some();
+        }
+    }
+    if config.debug_file_from_code_file && object_id.debug_file.is_none() {
+        guessed_id.debug_file = object_id.code_file.clone();
+        changed |= guessed_id.debug_file.is_some();
+    }
+
+    changed.then_some(guessed_id)
+}
+
+/// Rewrites the code and debug file paths according to the configured rules.
+///
+/// Returns `None` if no rule matched.
+fn rewrite_paths(rules: &[PathRewriteRule], object_id: &ObjectId) -> Option<ObjectId> {
+    let mut rewritten_id = object_id.clone();
+    let mut changed = false;
+
+    for rule in rules {
+        let paths = match rule.target {
+            PathRewriteTarget::All => [
+                Some(&mut rewritten_id.code_file),
+                Some(&mut rewritten_id.debug_file),
+            ],
+            PathRewriteTarget::CodeFile => [Some(&mut rewritten_id.code_file), None],
+            PathRewriteTarget::DebugFile => [None, Some(&mut rewritten_id.debug_file)],
+        };
+
+        for path in paths.into_iter().flatten() {
+            let Some(original) = path.as_deref() else {
+                continue;
+            };
+            if !rule.pattern.is_match(original) {
+                continue;
+            }
+
+            let rewritten = {
+                let replaced = rule
+                    .pattern
+                    .replace_all(original, rule.replacement.as_str());
+                match rule.case {
+                    PathCase::Preserve => replaced.into_owned(),
+                    PathCase::Lower => replaced.to_lowercase(),
+                    PathCase::Upper => replaced.to_uppercase(),
+                }
+            };
+            *path = Some(rewritten);
+            changed = true;
+        }
+    }
+
+    changed.then_some(rewritten_id)
+}
+
+/// Derives a debug id from a hex-encoded GNU build id.
+///
+/// This mirrors how symbolic computes the debug id of an ELF file: The first 16 bytes of the build
+/// id are used as UUID, with the first three fields converted from little-endian.
+fn debug_id_from_build_id(build_id: &str) -> Option<DebugId> {
+    let mut data = [0; 16];
+    for (byte, hex) in data.iter_mut().zip(build_id.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+    }
+    data[0..4].reverse();
+    data[4..6].reverse();
+    data[6..8].reverse();
+
+    Some(DebugId::from_uuid(Uuid::from_bytes(data)))
+}
+
 /// Computes a download timeout based on a content length in bytes and a per-gigabyte timeout.
 ///
 /// Returns `content_length / 2^30 * timeout_per_gb`, with a minimum value of 10s.
@@ -709,7 +1292,13 @@ mod tests {
     // Actual implementation is tested in the sub-modules, this only needs to
     // ensure the service interface works correctly.
 
-    use symbolicator_sources::{HttpRemoteFile, ObjectType, SourceConfig};
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use axum::Router;
+    use symbolicator_sources::{
+        DirectoryLayoutType, FaultInjectionSourceConfig, FaultSchedule, FilesystemSourceConfig,
+        HttpRemoteFile, ObjectType, SourceConfig, SourceId,
+    };
 
     use super::*;
 
@@ -728,7 +1317,7 @@ mod tests {
         };
 
         let config = Config {
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
             ..Config::default()
         };
 
@@ -745,6 +1334,180 @@ mod tests {
         assert_eq!(content, "hello world\n")
     }
 
+    #[tokio::test]
+    async fn test_follow_pointer_reserved_ips() {
+        test::setup();
+
+        // The pointer refers to a file on the same server on localhost.
+        let router = Router::new()
+            .route(
+                "/hello.txt/1/file.ptr",
+                get(|headers: HeaderMap| async move {
+                    let host = headers["host"].to_str().unwrap().to_owned();
+                    format!("PATH:http://{host}/hello.txt/1/hello.txt")
+                }),
+            )
+            .route("/hello.txt/1/hello.txt", get(|| async { "hello world\n" }));
+        let server = test::Server::with_router(router);
+        let source = server.source("local", "/");
+        let SourceConfig::Http(ref http_source) = source else {
+            unreachable!();
+        };
+        let file_source = RemoteFile::from(HttpRemoteFile::new(
+            http_source.clone(),
+            SourceLocation::new("hello.txt/1/file.ptr"),
+        ));
+
+        let download = |policy| {
+            let config = Config {
+                sources: Arc::from(vec![source.clone()]),
+                connect_to_reserved_ips: policy,
+                ..Config::default()
+            };
+            let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+            let file_source = file_source.clone();
+            async move {
+                let temp_file = tempfile::NamedTempFile::new().unwrap();
+                service
+                    .download(file_source, temp_file.path().to_owned())
+                    .await
+                    .map(|_| std::fs::read_to_string(temp_file.path()).unwrap())
+            }
+        };
+
+        assert!(download(ReservedIpsPolicy::Deny).await.is_err());
+        let content = download(ReservedIpsPolicy::Sources(vec!["local".into()]))
+            .await
+            .unwrap();
+        assert_eq!(content, "hello world\n");
+    }
+
+    #[tokio::test]
+    async fn test_download_fault_injection() {
+        test::setup();
+
+        let source = |faults| {
+            let source = Arc::new(FaultInjectionSourceConfig {
+                id: SourceId::new("faulty"),
+                path: test::fixture("symbols"),
+                faults,
+                files: Default::default(),
+            });
+            let location =
+                SourceLocation::new("crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
+            RemoteFile::from(FaultInjectionRemoteFile::new(source, location))
+        };
+
+        let config = Config::default();
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let original = std::fs::read(test::fixture(
+            "symbols/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb",
+        ))
+        .unwrap();
+
+        // Throttled downloads are not retried.
+        let throttled = source(FaultSchedule {
+            throttle_rate: 1.0,
+            ..FaultSchedule::default()
+        });
+        let result = service
+            .download(throttled, temp_file.path().to_owned())
+            .await;
+        assert_eq!(result, Err(CacheError::RateLimited(Duration::ZERO)));
+
+        let truncated = source(FaultSchedule {
+            truncate_rate: 1.0,
+            ..FaultSchedule::default()
+        });
+        service
+            .download(truncated, temp_file.path().to_owned())
+            .await
+            .unwrap();
+        let content = std::fs::read(temp_file.path()).unwrap();
+        assert_eq!(content, original[..original.len() / 2]);
+    }
+
+    #[tokio::test]
+    async fn test_download_rate_limited() {
+        test::setup();
+
+        let source = |id, faults| {
+            let source = Arc::new(FaultInjectionSourceConfig {
+                id: SourceId::new(id),
+                path: test::fixture("symbols"),
+                faults,
+                files: Default::default(),
+            });
+            let location =
+                SourceLocation::new("crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
+            RemoteFile::from(FaultInjectionRemoteFile::new(source, location))
+        };
+
+        let config = Config::default();
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+
+        let throttled = source(
+            "faulty",
+            FaultSchedule {
+                throttle_rate: 1.0,
+                retry_after: Duration::from_secs(60),
+                ..FaultSchedule::default()
+            },
+        );
+        let result = service
+            .download(throttled, temp_file.path().to_owned())
+            .await;
+        assert_eq!(
+            result,
+            Err(CacheError::RateLimited(Duration::from_secs(60)))
+        );
+
+        // The source is skipped for the remaining cooldown, even for files it would serve.
+        let skipped = source("faulty", FaultSchedule::default());
+        let result = service.download(skipped, temp_file.path().to_owned()).await;
+        let Err(CacheError::RateLimited(remaining)) = result else {
+            panic!("expected the source to be skipped, got {result:?}");
+        };
+        assert!(remaining <= Duration::from_secs(60));
+
+        // Other sources are not affected.
+        let other = source("reliable", FaultSchedule::default());
+        service
+            .download(other, temp_file.path().to_owned())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_rate_limited_retry_after() {
+        use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+        let retry_after = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            rate_limited(&headers)
+        };
+
+        assert_eq!(
+            retry_after("120"),
+            CacheError::RateLimited(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after("86400"),
+            CacheError::RateLimited(MAX_RETRY_AFTER)
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            CacheError::RateLimited(DEFAULT_RETRY_AFTER)
+        );
+        assert_eq!(
+            rate_limited(&HeaderMap::new()),
+            CacheError::RateLimited(DEFAULT_RETRY_AFTER)
+        );
+    }
+
     #[tokio::test]
     async fn test_list_files() {
         test::setup();
@@ -770,6 +1533,119 @@ mod tests {
         assert_eq!(item.source_id(), source.id());
     }
 
+    #[test]
+    fn test_guess_elf_ids() {
+        let objid = ObjectId {
+            code_id: Some("dfb85de42daffd09640c8fe377d572de3e168920".parse().unwrap()),
+            code_file: Some("/lib/x86_64-linux-gnu/libm-2.23.so".into()),
+            object_type: ObjectType::Elf,
+            ..Default::default()
+        };
+
+        // The heuristics are opt-in.
+        assert!(guess_elf_ids(&ElfIdHeuristicsConfig::default(), &objid).is_none());
+
+        let config = ElfIdHeuristicsConfig {
+            derive_debug_id: true,
+            debug_file_from_code_file: true,
+        };
+        let guessed = guess_elf_ids(&config, &objid).unwrap();
+        assert_eq!(
+            guessed.debug_id,
+            Some("e45db8df-af2d-09fd-640c-8fe377d572de".parse().unwrap())
+        );
+        assert_eq!(guessed.debug_file, objid.code_file);
+
+        let objid = ObjectId {
+            object_type: ObjectType::Pe,
+            ..objid
+        };
+        assert!(guess_elf_ids(&config, &objid).is_none());
+    }
+
+    #[test]
+    fn test_rewrite_paths() {
+        let rule = |target, pattern: &str, replacement: &str, case| PathRewriteRule {
+            target,
+            pattern: pattern.parse().unwrap(),
+            replacement: replacement.into(),
+            case,
+        };
+        let objid = ObjectId {
+            code_file: Some("C:\\build\\agent-7\\Release\\Crash.exe".into()),
+            debug_file: Some("C:\\build\\agent-7\\Release\\Crash.pdb".into()),
+            object_type: ObjectType::Pe,
+            ..Default::default()
+        };
+
+        assert!(rewrite_paths(&[], &objid).is_none());
+
+        let rules = [
+            rule(
+                PathRewriteTarget::All,
+                r"^C:\\build\\[^\\]+\\",
+                r"D:\",
+                PathCase::Preserve,
+            ),
+            rule(PathRewriteTarget::DebugFile, ".*", "$0", PathCase::Lower),
+        ];
+        let rewritten = rewrite_paths(&rules, &objid).unwrap();
+        assert_eq!(
+            rewritten.code_file.as_deref(),
+            Some("D:\\Release\\Crash.exe")
+        );
+        assert_eq!(
+            rewritten.debug_file.as_deref(),
+            Some("d:\\release\\crash.pdb")
+        );
+
+        let rules = [rule(
+            PathRewriteTarget::CodeFile,
+            "^/usr/",
+            "/",
+            PathCase::Upper,
+        )];
+        assert!(rewrite_paths(&rules, &objid).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_files_strict_ids() {
+        test::setup();
+
+        let objid = ObjectId {
+            code_id: Some("dfb85de42daffd09640c8fe377d572de3e168920".parse().unwrap()),
+            code_file: Some("/lib/x86_64-linux-gnu/libm-2.23.so".into()),
+            object_type: ObjectType::Elf,
+            ..Default::default()
+        };
+
+        let source = |strict_ids| {
+            let mut files =
+                test::source_config(DirectoryLayoutType::Native, vec![FileType::Breakpad]);
+            files.filters.strict_ids = strict_ids;
+            SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+                id: SourceId::new("local"),
+                path: test::fixture("symbols"),
+                index_ttl: None,
+                files,
+            }))
+        };
+
+        let config = Config::default();
+        let svc = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        // The breakpad path can only be constructed using the derived debug id.
+        let file_list = svc
+            .list_files(&[source(false)], FileType::all(), &objid)
+            .await;
+        assert_eq!(file_list.len(), 1);
+
+        let file_list = svc
+            .list_files(&[source(true)], FileType::all(), &objid)
+            .await;
+        assert!(file_list.is_empty());
+    }
+
     #[test]
     fn test_content_length_timeout() {
         let timeout_per_gb = Duration::from_secs(30);
@@ -0,0 +1,291 @@
+//! The messages of the NTLMv2 authentication handshake.
+//!
+//! NTLM authenticates a connection in three steps: the client sends a negotiate message, the
+//! server answers with a challenge in a `401` response, and the client proves its credentials in
+//! an authenticate message, which is sent along with the actual request on the same connection.
+//!
+//! See [MS-NLMP](https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-nlmp) for the
+//! message formats. Only NTLMv2 responses are computed, without message signing or sealing.
+//!
+//! Only the `NTLM` scheme is supported. The `Negotiate` scheme, which wraps either Kerberos or
+//! NTLM in SPNEGO, is not.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use symbolicator_sources::NtlmCredentials;
+
+/// The signature at the start of every NTLM message.
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+/// The negotiated flags: Unicode strings, NTLM with extended session security, and a request
+/// for the target name.
+const NEGOTIATE_FLAGS: u32 = 0xa008_8205;
+
+/// The size of the fixed part of the authenticate message, which precedes its fields.
+const HEADER_LEN: usize = 64;
+
+/// The seconds between the Windows epoch (1601-01-01) and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// The challenge sent by the server in response to a negotiate message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Challenge {
+    /// The nonce which the client response is computed over.
+    server_challenge: [u8; 8],
+    /// Information about the server, which is included in the client response.
+    target_info: Vec<u8>,
+}
+
+/// Returns whether a `401` response offers the unsupported `Negotiate` scheme.
+pub fn offers_negotiate(headers: &HeaderMap) -> bool {
+    authenticate_params(headers, "Negotiate").is_some()
+}
+
+/// Returns the NTLM challenge sent by the server.
+pub fn find_challenge(headers: &HeaderMap) -> Option<Challenge> {
+    let token = authenticate_params(headers, "NTLM")?;
+    parse_challenge(&BASE64.decode(token.as_bytes()).ok()?)
+}
+
+/// Returns the parameters of the `WWW-Authenticate` header for `scheme`.
+fn authenticate_params<'a>(headers: &'a HeaderMap, scheme: &str) -> Option<&'a str> {
+    headers
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| {
+            let (name, params) = value.split_once(' ').unwrap_or((value, ""));
+            name.eq_ignore_ascii_case(scheme).then(|| params.trim())
+        })
+}
+
+/// Creates the negotiate message, which starts the handshake.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut message = Vec::with_capacity(32);
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&1u32.to_le_bytes());
+    message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+    // Neither the domain nor the workstation are sent.
+    message.extend_from_slice(&[0; 16]);
+    message
+}
+
+/// Parses the challenge message of the server.
+fn parse_challenge(message: &[u8]) -> Option<Challenge> {
+    if message.len() < 48 || !message.starts_with(SIGNATURE) || read_u32(message, 8)? != 2 {
+        return None;
+    }
+
+    let server_challenge = message.get(24..32)?.try_into().ok()?;
+    let len = read_u16(message, 40)? as usize;
+    let offset = read_u32(message, 44)? as usize;
+    let target_info = message.get(offset..offset.checked_add(len)?)?.to_vec();
+
+    Some(Challenge {
+        server_challenge,
+        target_info,
+    })
+}
+
+/// Creates the authenticate message, which answers the `challenge` of the server.
+pub fn authenticate_message(credentials: &NtlmCredentials, challenge: &Challenge) -> Vec<u8> {
+    let mut client_challenge = [0; 8];
+    // The system RNG only fails on platforms which symbolicator does not run on.
+    SystemRandom::new().fill(&mut client_challenge).unwrap();
+
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let timestamp = (since_epoch.as_secs() + WINDOWS_EPOCH_OFFSET) * 10_000_000
+        + u64::from(since_epoch.subsec_nanos() / 100);
+
+    build_authenticate_message(credentials, challenge, client_challenge, timestamp)
+}
+
+fn build_authenticate_message(
+    credentials: &NtlmCredentials,
+    challenge: &Challenge,
+    client_challenge: [u8; 8],
+    timestamp: u64,
+) -> Vec<u8> {
+    let (user, domain) = credentials.user_and_domain();
+    let key = ntowf_v2(user, domain, &credentials.password);
+
+    let mut blob = Vec::with_capacity(32 + challenge.target_info.len());
+    blob.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+    blob.extend_from_slice(&timestamp.to_le_bytes());
+    blob.extend_from_slice(&client_challenge);
+    blob.extend_from_slice(&[0; 4]);
+    blob.extend_from_slice(&challenge.target_info);
+    blob.extend_from_slice(&[0; 4]);
+
+    let mut nt_response = hmac_md5(&key, &[&challenge.server_challenge, &blob]).to_vec();
+    nt_response.extend_from_slice(&blob);
+
+    let mut lm_response =
+        hmac_md5(&key, &[&challenge.server_challenge, &client_challenge]).to_vec();
+    lm_response.extend_from_slice(&client_challenge);
+
+    let fields = [
+        lm_response,
+        nt_response,
+        utf16le(domain),
+        utf16le(user),
+        // The workstation and the session key are left empty.
+        Vec::new(),
+        Vec::new(),
+    ];
+
+    let mut message = Vec::with_capacity(HEADER_LEN + fields.iter().map(Vec::len).sum::<usize>());
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&3u32.to_le_bytes());
+
+    let mut offset = HEADER_LEN;
+    for field in &fields {
+        let len = field.len() as u16;
+        message.extend_from_slice(&len.to_le_bytes());
+        message.extend_from_slice(&len.to_le_bytes());
+        message.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+
+    for field in &fields {
+        message.extend_from_slice(field);
+    }
+    message
+}
+
+/// Computes the NTLMv2 key of a user from their password.
+fn ntowf_v2(user: &str, domain: &str, password: &str) -> [u8; 16] {
+    let nt_hash = Md4::digest(utf16le(password));
+    let identity = utf16le(&(user.to_uppercase() + domain));
+    hmac_md5(&nt_hash, &[&identity])
+}
+
+fn hmac_md5(key: &[u8], data: &[&[u8]]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    let mut digest = [0; 16];
+    digest.copy_from_slice(&mac.finalize().into_bytes());
+    digest
+}
+
+fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use data_encoding::HEXLOWER;
+    use reqwest::header::HeaderValue;
+
+    fn credentials() -> NtlmCredentials {
+        NtlmCredentials {
+            username: "User".into(),
+            password: "Password".into(),
+            domain: "Domain".into(),
+        }
+    }
+
+    fn target_info() -> Vec<u8> {
+        let mut info = Vec::new();
+        for (id, value) in [(2u16, "Domain"), (1, "Server")] {
+            let value = utf16le(value);
+            info.extend_from_slice(&id.to_le_bytes());
+            info.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            info.extend_from_slice(&value);
+        }
+        info.extend_from_slice(&[0; 4]);
+        info
+    }
+
+    fn challenge_message() -> Vec<u8> {
+        let target_info = target_info();
+        let mut message = SIGNATURE.to_vec();
+        message.extend_from_slice(&2u32.to_le_bytes());
+        message.extend_from_slice(&[0; 8]); // target name
+        message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+        message.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]);
+        message.extend_from_slice(&[0; 8]); // reserved
+        message.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        message.extend_from_slice(&48u32.to_le_bytes());
+        message.extend_from_slice(&target_info);
+        message
+    }
+
+    #[test]
+    fn test_ntowf_v2() {
+        assert_eq!(
+            HEXLOWER.encode(&ntowf_v2("User", "Domain", "Password")),
+            "0c868a403bfd7a93a3001ef22ef02e3f"
+        );
+    }
+
+    #[test]
+    fn test_find_challenge() {
+        let mut headers = HeaderMap::new();
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static("Negotiate"));
+        assert!(offers_negotiate(&headers));
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static("NTLM"));
+        assert_eq!(find_challenge(&headers), None);
+
+        let token = format!("NTLM {}", BASE64.encode(&challenge_message()));
+        headers.insert(WWW_AUTHENTICATE, token.parse().unwrap());
+        assert!(!offers_negotiate(&headers));
+        assert_eq!(
+            find_challenge(&headers),
+            Some(Challenge {
+                server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+                target_info: target_info(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_authenticate_message() {
+        let challenge = parse_challenge(&challenge_message()).unwrap();
+        let message = build_authenticate_message(&credentials(), &challenge, [0xaa; 8], 0);
+
+        let field = |index: usize| {
+            let len = read_u16(&message, 12 + index * 8).unwrap() as usize;
+            let offset = read_u32(&message, 16 + index * 8).unwrap() as usize;
+            &message[offset..offset + len]
+        };
+
+        assert_eq!(
+            HEXLOWER.encode(field(0)),
+            "86c35097ac9cec102554764a57cccc19aaaaaaaaaaaaaaaa"
+        );
+        assert_eq!(
+            HEXLOWER.encode(&field(1)[..16]),
+            "68cd0ab851e51c96aabc927bebef6a1c"
+        );
+        assert_eq!(field(2), utf16le("Domain"));
+        assert_eq!(field(3), utf16le("User"));
+    }
+}
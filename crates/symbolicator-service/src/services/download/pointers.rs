@@ -0,0 +1,119 @@
+//! Parsing of the `file.ptr` pointer files of symbol stores.
+//!
+//! `symstore.exe /p` publishes a `file.ptr` instead of a copy of a file, which contains either
+//! `PATH:<location>` with the location of the actual file, or `MSG:<message>` if the file is not
+//! available.
+//!
+//! Locations on Windows file shares, given as UNC paths such as `\\server\share\file.pdb`, are not
+//! supported, as symbolicator cannot access them.
+
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use crate::caching::{CacheEntry, CacheError};
+
+/// The location of a file referenced by a pointer file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PointerTarget {
+    /// An `http` or `https` URL.
+    Url(Url),
+    /// A path on the filesystem, possibly a UNC path.
+    Path(PathBuf),
+}
+
+/// Parses the contents of a pointer file.
+///
+/// Pointers with a message instead of a location are treated as missing files.
+pub fn parse_pointer(contents: &[u8]) -> CacheEntry<PointerTarget> {
+    let contents = String::from_utf8_lossy(contents);
+    let contents = contents.trim();
+
+    if let Some(message) = contents.strip_prefix("MSG:") {
+        tracing::debug!("Pointer to unavailable file: {}", message.trim());
+        return Err(CacheError::NotFound);
+    }
+
+    let location = contents
+        .strip_prefix("PATH:")
+        .map(str::trim)
+        .ok_or_else(|| CacheError::Malformed("invalid pointer file".into()))?;
+
+    if location.starts_with("http://") || location.starts_with("https://") {
+        let url = location
+            .parse()
+            .map_err(|_| CacheError::Malformed(format!("invalid pointer URL `{location}`")))?;
+        Ok(PointerTarget::Url(url))
+    } else {
+        Ok(PointerTarget::Path(PathBuf::from(location)))
+    }
+}
+
+/// Checks that `target` is a file within the directory `root` of a filesystem source.
+///
+/// Returns the canonical path of the target.
+pub fn check_path_target(root: &Path, target: &Path) -> CacheEntry<PathBuf> {
+    if target.to_string_lossy().starts_with(r"\\") {
+        return Err(CacheError::PermissionDenied(
+            "pointers to UNC paths are not supported".into(),
+        ));
+    }
+
+    let not_found = |_| CacheError::NotFound;
+    let root = root.canonicalize().map_err(not_found)?;
+    let target = root.join(target).canonicalize().map_err(not_found)?;
+    if !target.starts_with(&root) {
+        return Err(CacheError::PermissionDenied(
+            "pointer target is outside of the source".into(),
+        ));
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test;
+
+    #[test]
+    fn test_parse_pointer() {
+        assert_eq!(
+            parse_pointer(b"PATH:https://example.com/crash.pdb\r\n").unwrap(),
+            PointerTarget::Url("https://example.com/crash.pdb".parse().unwrap())
+        );
+        assert_eq!(
+            parse_pointer(br"PATH:\\fileserver\symbols\crash.pdb").unwrap(),
+            PointerTarget::Path(PathBuf::from(r"\\fileserver\symbols\crash.pdb"))
+        );
+        assert_eq!(
+            parse_pointer(b"MSG:file was deleted"),
+            Err(CacheError::NotFound)
+        );
+        assert!(matches!(
+            parse_pointer(b"<html></html>"),
+            Err(CacheError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_path_target() {
+        let tempdir = test::tempdir();
+        let root = tempdir.path().join("symbols");
+        std::fs::create_dir_all(root.join("crash.pdb")).unwrap();
+        std::fs::write(root.join("crash.pdb/crash.pdb"), b"").unwrap();
+        std::fs::write(tempdir.path().join("secret"), b"").unwrap();
+
+        assert!(check_path_target(&root, Path::new("crash.pdb/crash.pdb")).is_ok());
+        assert!(check_path_target(&root, &root.join("crash.pdb/crash.pdb")).is_ok());
+        assert!(matches!(
+            check_path_target(&root, Path::new("../secret")),
+            Err(CacheError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            check_path_target(&root, Path::new(r"\\fileserver\symbols\crash.pdb")),
+            Err(CacheError::PermissionDenied(_))
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! Conditional revalidation of downloaded files.
+//!
+//! The `ETag` and `Last-Modified` headers of files downloaded from HTTP sources are recorded in
+//! the `validators` directory of the objects cache, keyed by the hash of the file's URI. Once a
+//! record is older than `revalidate_after`, the source is asked with a conditional request whether
+//! the file changed, instead of downloading it again unconditionally.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use filetime::FileTime;
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use symbolicator_sources::RemoteFile;
+
+/// The directory within the objects cache holding the validators of downloaded files.
+pub const VALIDATORS_DIR: &str = "validators";
+
+/// The headers of a download which identify the version of the downloaded file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    /// The value of the `ETag` header, sent as `If-None-Match`.
+    pub etag: Option<String>,
+    /// The value of the `Last-Modified` header, sent as `If-Modified-Since`.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Extracts the validators from the headers of a response.
+    ///
+    /// Returns `None` if the response carries neither an `ETag` nor a `Last-Modified` header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| {
+            let value = headers.get(name)?.to_str().ok()?;
+            Some(value.to_owned())
+        };
+
+        let validators = Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        (validators != Self::default()).then_some(validators)
+    }
+}
+
+/// The validators of a downloaded file, along with the time they were last confirmed.
+#[derive(Debug, Serialize, Deserialize)]
+struct ValidatorsRecord {
+    #[serde(flatten)]
+    validators: Validators,
+    validated_at: DateTime<Utc>,
+}
+
+/// Stores the [`Validators`] of downloaded files and tells which of them need revalidation.
+///
+/// Records are only kept if both a cache directory and `revalidate_after` are configured.
+#[derive(Debug)]
+pub struct ValidatorStore {
+    dir: Option<PathBuf>,
+    revalidate_after: Option<Duration>,
+}
+
+impl ValidatorStore {
+    pub fn new(dir: Option<PathBuf>, revalidate_after: Option<Duration>) -> Self {
+        let dir = dir.filter(|_| revalidate_after.is_some());
+        Self {
+            dir,
+            revalidate_after,
+        }
+    }
+
+    /// Records that `file` currently has the given `validators`.
+    pub fn store(&self, file: &RemoteFile, validators: Validators) {
+        let Some(path) = self.path(file) else {
+            return;
+        };
+
+        let record = ValidatorsRecord {
+            validators,
+            validated_at: Utc::now(),
+        };
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, serde_json::to_vec(&record)?));
+        if let Err(err) = result {
+            let stderr: &dyn std::error::Error = &err;
+            tracing::error!(stderr, "Failed to store validators of `{}`", file);
+        }
+    }
+
+    /// Returns the validators of `file` if they are due for revalidation.
+    ///
+    /// Records which are still fresh are touched, so that cache cleanup keeps them as long as
+    /// the file is in use.
+    pub fn stale(&self, file: &RemoteFile) -> Option<Validators> {
+        let path = self.path(file)?;
+        let revalidate_after = self.revalidate_after?;
+
+        let record: ValidatorsRecord = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).ok()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Failed to read validators of `{}`", file);
+                return None;
+            }
+        };
+
+        let age = (Utc::now() - record.validated_at)
+            .to_std()
+            .unwrap_or_default();
+        if age >= revalidate_after {
+            return Some(record.validators);
+        }
+
+        filetime::set_file_mtime(&path, FileTime::now()).ok();
+        None
+    }
+
+    fn path(&self, file: &RemoteFile) -> Option<PathBuf> {
+        let hash = Sha256::digest(file.uri().to_string().as_bytes());
+        let name = format!("{hash:x}");
+        Some(self.dir.as_ref()?.join(&name[..2]).join(&name[2..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::header::HeaderValue;
+    use symbolicator_sources::{
+        FilesystemRemoteFile, FilesystemSourceConfig, SourceId, SourceLocation,
+    };
+
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn test_validators_from_headers() {
+        assert_eq!(Validators::from_headers(&HeaderMap::new()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+        assert_eq!(
+            Validators::from_headers(&headers),
+            Some(Validators {
+                etag: Some("\"abc\"".into()),
+                last_modified: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_stale_validators() {
+        let tempdir = test::tempdir();
+        let source = FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: PathBuf::from("/symbols"),
+            index_ttl: None,
+            files: Default::default(),
+        };
+        let file: RemoteFile =
+            FilesystemRemoteFile::new(Arc::new(source), SourceLocation::new("foo.pdb")).into();
+        let validators = Validators {
+            etag: Some("\"abc\"".into()),
+            last_modified: None,
+        };
+
+        // Without `revalidate_after`, nothing is recorded.
+        let store = ValidatorStore::new(Some(tempdir.path().into()), None);
+        store.store(&file, validators.clone());
+        assert_eq!(store.stale(&file), None);
+
+        let store = ValidatorStore::new(Some(tempdir.path().into()), Some(Duration::from_secs(60)));
+        store.store(&file, validators.clone());
+        assert_eq!(store.stale(&file), None);
+
+        let store = ValidatorStore::new(Some(tempdir.path().into()), Some(Duration::ZERO));
+        assert_eq!(store.stale(&file), Some(validators));
+    }
+}
@@ -7,11 +7,14 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use aws_config::meta::credentials::lazy_caching::LazyCachingCredentialsProvider;
+use aws_sdk_s3::model::RequestPayer;
 use aws_sdk_s3::types::SdkError;
 pub use aws_sdk_s3::Error as S3Error;
 use aws_sdk_s3::{Client, Endpoint};
 use aws_types::credentials::{Credentials, ProvideCredentials};
+use data_encoding::BASE64;
 use futures::TryStreamExt;
+use md5::{Digest, Md5};
 use reqwest::StatusCode;
 
 use symbolicator_sources::{
@@ -24,6 +27,9 @@ use super::content_length_timeout;
 
 type ClientCache = moka::future::Cache<Arc<S3SourceKey>, Arc<Client>>;
 
+/// The encryption algorithm of SSE-C encrypted objects, which is the only one S3 supports.
+const SSE_CUSTOMER_ALGORITHM: &str = "AES256";
+
 /// Downloader implementation that supports the S3 source.
 pub struct S3Downloader {
     client_cache: ClientCache,
@@ -56,27 +62,7 @@ impl S3Downloader {
     async fn get_s3_client(&self, key: &Arc<S3SourceKey>) -> Arc<Client> {
         let init = Box::pin(async {
             metric!(counter("source.s3.client.create") += 1);
-
-            tracing::debug!(
-                "Using AWS credentials provider: {:?}",
-                key.aws_credentials_provider
-            );
-            Arc::new(match key.aws_credentials_provider {
-                AwsCredentialsProvider::Container => {
-                    let provider = LazyCachingCredentialsProvider::builder()
-                        .load(aws_config::ecs::EcsCredentialsProvider::builder().build())
-                        .build();
-                    self.create_s3_client(provider, &key.region).await
-                }
-                AwsCredentialsProvider::Static => {
-                    let provider = Credentials::from_keys(
-                        key.access_key.clone(),
-                        key.secret_key.clone(),
-                        None,
-                    );
-                    self.create_s3_client(provider, &key.region).await
-                }
-            })
+            Arc::new(create_client(key).await)
         });
 
         let entry = self
@@ -91,29 +77,6 @@ impl S3Downloader {
         entry.into_value()
     }
 
-    async fn create_s3_client(
-        &self,
-        provider: impl ProvideCredentials + Send + Sync + 'static,
-        region: &S3Region,
-    ) -> Client {
-        let mut config_loader = aws_config::from_env()
-            .credentials_provider(provider)
-            .region(region.region.clone());
-
-        if let Some(endpoint) = region.endpoint.as_ref() {
-            match Endpoint::immutable(endpoint) {
-                Ok(endpoint) => config_loader = config_loader.endpoint_resolver(endpoint),
-                Err(err) => {
-                    let error: &dyn std::error::Error = &err;
-                    tracing::error!(error, "Failed creating custom `Endpoint`",);
-                }
-            };
-        }
-
-        let config = config_loader.load().await;
-        Client::new(&config)
-    }
-
     /// Downloads a source hosted on an S3 bucket.
     pub async fn download_source(
         &self,
@@ -126,7 +89,18 @@ impl S3Downloader {
 
         let source_key = file_source.source.source_key.clone();
         let client = self.get_s3_client(&source_key).await;
-        let request = client.get_object().bucket(&bucket).key(&key).send();
+        let mut request = client.get_object().bucket(&bucket).key(&key);
+        if file_source.source.requester_pays {
+            request = request.request_payer(RequestPayer::Requester);
+        }
+        if let Some(ref customer_key) = file_source.source.sse_customer_key {
+            request = request
+                .sse_customer_algorithm(SSE_CUSTOMER_ALGORITHM)
+                .sse_customer_key(customer_key)
+                .sse_customer_key_md5(customer_key_md5(customer_key)?);
+        }
+        let request = request.send();
+        let sse_kms_key_id = file_source.source.sse_kms_key_id.clone();
 
         let source = RemoteFile::from(file_source);
         let request = tokio::time::timeout(self.connect_timeout, request);
@@ -192,6 +166,19 @@ impl S3Downloader {
             }
         };
 
+        if let Some(expected) = sse_kms_key_id {
+            if response.ssekms_key_id() != Some(expected.as_str()) {
+                tracing::debug!(
+                    "Rejecting s3://{}/{} which is not encrypted with KMS key {}",
+                    &bucket,
+                    &key,
+                    expected
+                );
+                let details = format!("object is not encrypted with KMS key `{expected}`");
+                return Err(CacheError::PermissionDenied(details));
+            }
+        }
+
         let timeout = Some(content_length_timeout(
             response.content_length(),
             self.streaming_timeout,
@@ -210,6 +197,65 @@ impl S3Downloader {
     }
 }
 
+/// Creates an S3 client for the region and credentials of the given key.
+///
+/// This is also used by the S3 backend of the shared cache.
+pub async fn create_client(key: &S3SourceKey) -> Client {
+    tracing::debug!(
+        "Using AWS credentials provider: {:?}",
+        key.aws_credentials_provider
+    );
+    match key.aws_credentials_provider {
+        AwsCredentialsProvider::Container => {
+            let provider = LazyCachingCredentialsProvider::builder()
+                .load(aws_config::ecs::EcsCredentialsProvider::builder().build())
+                .build();
+            create_client_with_provider(provider, &key.region).await
+        }
+        AwsCredentialsProvider::Static => {
+            let provider =
+                Credentials::from_keys(key.access_key.clone(), key.secret_key.clone(), None);
+            create_client_with_provider(provider, &key.region).await
+        }
+    }
+}
+
+async fn create_client_with_provider(
+    provider: impl ProvideCredentials + Send + Sync + 'static,
+    region: &S3Region,
+) -> Client {
+    let mut config_loader = aws_config::from_env()
+        .credentials_provider(provider)
+        .region(region.region.clone());
+
+    if let Some(endpoint) = region.endpoint.as_ref() {
+        match Endpoint::immutable(endpoint) {
+            Ok(endpoint) => config_loader = config_loader.endpoint_resolver(endpoint),
+            Err(err) => {
+                let error: &dyn std::error::Error = &err;
+                tracing::error!(error, "Failed creating custom `Endpoint`",);
+            }
+        };
+    }
+
+    let config = config_loader.load().await;
+    Client::new(&config)
+}
+
+/// Computes the base64-encoded MD5 digest of a base64-encoded SSE-C key.
+///
+/// S3 uses this digest to verify that the key was transmitted without errors.
+fn customer_key_md5(customer_key: &str) -> CacheEntry<String> {
+    let invalid = || CacheError::DownloadError("invalid SSE-C customer key".into());
+    let key = BASE64
+        .decode(customer_key.as_bytes())
+        .map_err(|_| invalid())?;
+    if key.len() != 32 {
+        return Err(invalid());
+    }
+    Ok(BASE64.encode(&Md5::digest(key)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,6 +309,9 @@ mod tests {
             id: SourceId::new("s3-test"),
             bucket: S3_BUCKET.to_owned(),
             prefix: String::new(),
+            requester_pays: false,
+            sse_kms_key_id: None,
+            sse_customer_key: None,
             source_key: Arc::new(source_key),
             files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
         })
@@ -436,6 +485,19 @@ mod tests {
         assert!(!target_path.exists());
     }
 
+    #[test]
+    fn test_customer_key_md5() {
+        let customer_key = BASE64.encode(&[0; 32]);
+        assert_eq!(
+            customer_key_md5(&customer_key).unwrap(),
+            "cLyPS3KoaSFGi/joRB3OUQ=="
+        );
+
+        let short_key = BASE64.encode(&[0; 16]);
+        assert!(customer_key_md5(&short_key).is_err());
+        assert!(customer_key_md5("not base64").is_err());
+    }
+
     #[test]
     fn test_s3_remote_dif_uri() {
         let source_key = Arc::new(S3SourceKey {
@@ -448,6 +510,9 @@ mod tests {
             id: SourceId::new("s3-id"),
             bucket: String::from("bucket"),
             prefix: String::from("prefix"),
+            requester_pays: false,
+            sse_kms_key_id: None,
+            sse_customer_key: None,
             source_key,
             files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
         });
@@ -0,0 +1,361 @@
+//! Resolution of secret references in the credentials of sources.
+//!
+//! Instead of plaintext credentials, sources can reference secrets which are resolved right
+//! before they are used:
+//!
+//! - `${env:NAME}` reads the environment variable `NAME`.
+//! - `${file:/path}` reads the file at `/path`.
+//! - `${vault:path#key}` reads `key` of the secret at `path` from Vault.
+//!
+//! Resolved secrets are cached for the configured TTL, after which they are resolved again to
+//! pick up rotated secrets.
+//!
+//! Only the sources from the configuration file and the stored sources may reference secrets.
+//! Requests which bring their own sources are rejected if those contain references, see
+//! [`contains_secret_references`], as they could otherwise read arbitrary secrets of the server.
+//! Secrets are resolved before downloads are delegated to a downloader process, which rejects
+//! references as well.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use symbolicator_sources::{
+    GcsSourceConfig, HttpAuth, HttpSourceConfig, RemoteFile, S3SourceConfig, SentrySourceConfig,
+    SourceConfig,
+};
+use thiserror::Error;
+
+use crate::caching::{CacheEntry, CacheError};
+use crate::config::SecretsConfig;
+
+/// An error resolving a secret reference.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("invalid secret reference `{0}`")]
+    Invalid(String),
+    #[error("the `{0}` secret backend is not configured")]
+    NotConfigured(&'static str),
+    #[error("secret `{0}` is not allowed by the configuration")]
+    NotAllowed(String),
+    #[error("secret `{0}` was not found")]
+    NotFound(String),
+    #[error("failed to read secret: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to fetch secret from Vault: {0}")]
+    Vault(#[from] reqwest::Error),
+}
+
+/// Returns whether `value` is a secret reference rather than a plaintext secret.
+fn is_reference(value: &str) -> bool {
+    value.starts_with("${") && value.ends_with('}')
+}
+
+/// Returns whether any value in `sources`, or any other serializable value, looks like a secret
+/// reference.
+///
+/// This is deliberately stricter than the resolver and matches `${` anywhere, so that no value
+/// of sources sent with a request can be resolved.
+pub fn contains_secret_references<T: Serialize + ?Sized>(sources: &T) -> bool {
+    serde_json::to_string(sources).map_or(true, |json| json.contains("${"))
+}
+
+/// The response of the Vault KV version 2 secrets engine.
+#[derive(Debug, Deserialize)]
+struct VaultResponse {
+    data: VaultSecret,
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultSecret {
+    data: BTreeMap<String, String>,
+}
+
+/// Resolves the secret references in the credentials of sources.
+#[derive(Debug)]
+pub struct SecretResolver {
+    config: SecretsConfig,
+    client: reqwest::Client,
+    cache: moka::future::Cache<String, Arc<str>>,
+}
+
+impl SecretResolver {
+    /// Creates a resolver, which uses `client` to talk to Vault.
+    pub fn new(config: SecretsConfig, client: reqwest::Client) -> Self {
+        let cache = moka::future::Cache::builder()
+            .time_to_live(config.ttl)
+            .build();
+        Self {
+            config,
+            client,
+            cache,
+        }
+    }
+
+    /// Returns the `file` with all secret references in the credentials of its source resolved.
+    pub async fn resolve_file(&self, file: &RemoteFile) -> CacheEntry<RemoteFile> {
+        Ok(match file {
+            RemoteFile::Sentry(inner) => {
+                let mut inner = inner.clone();
+                inner.source = self.resolve_sentry(&inner.source).await?;
+                inner.into()
+            }
+            RemoteFile::Http(inner) => {
+                let mut inner = inner.clone();
+                inner.source = self.resolve_http(&inner.source).await?;
+                inner.into()
+            }
+            RemoteFile::S3(inner) => {
+                let mut inner = inner.clone();
+                inner.source = self.resolve_s3(&inner.source).await?;
+                inner.into()
+            }
+            RemoteFile::Gcs(inner) => {
+                let mut inner = inner.clone();
+                inner.source = self.resolve_gcs(&inner.source).await?;
+                inner.into()
+            }
+            RemoteFile::Filesystem(_) | RemoteFile::FaultInjection(_) => file.clone(),
+        })
+    }
+
+    /// Resolves the token of a Sentry source.
+    pub async fn resolve_sentry(
+        &self,
+        source: &Arc<SentrySourceConfig>,
+    ) -> CacheEntry<Arc<SentrySourceConfig>> {
+        if !is_reference(&source.token) {
+            return Ok(source.clone());
+        }
+
+        let mut resolved = SentrySourceConfig::clone(source);
+        resolved.token = self.resolve(&source.token).await?;
+        Ok(Arc::new(resolved))
+    }
+
+    /// Resolves the header values and the authentication credentials of an HTTP source.
+    async fn resolve_http(
+        &self,
+        source: &Arc<HttpSourceConfig>,
+    ) -> CacheEntry<Arc<HttpSourceConfig>> {
+        let auth_has_reference = match source.auth {
+            Some(HttpAuth::Ntlm(ref credentials)) => {
+                is_reference(&credentials.username) || is_reference(&credentials.password)
+            }
+            None => false,
+        };
+        if !auth_has_reference && !source.headers.values().any(|value| is_reference(value)) {
+            return Ok(source.clone());
+        }
+
+        let mut resolved = HttpSourceConfig::clone(source);
+        for value in resolved.headers.values_mut() {
+            *value = self.resolve(value).await?;
+        }
+        if let Some(HttpAuth::Ntlm(ref mut credentials)) = resolved.auth {
+            credentials.username = self.resolve(&credentials.username).await?;
+            credentials.password = self.resolve(&credentials.password).await?;
+        }
+        Ok(Arc::new(resolved))
+    }
+
+    /// Resolves the access, secret and SSE-C keys of an S3 source.
+    async fn resolve_s3(&self, source: &Arc<S3SourceConfig>) -> CacheEntry<Arc<S3SourceConfig>> {
+        let key = &source.source_key;
+        let customer_key = source.sse_customer_key.as_deref();
+        if !is_reference(&key.access_key)
+            && !is_reference(&key.secret_key)
+            && !customer_key.map_or(false, is_reference)
+        {
+            return Ok(source.clone());
+        }
+
+        let mut resolved_key = key.as_ref().clone();
+        resolved_key.access_key = self.resolve(&key.access_key).await?;
+        resolved_key.secret_key = self.resolve(&key.secret_key).await?;
+
+        let mut resolved = S3SourceConfig::clone(source);
+        resolved.source_key = Arc::new(resolved_key);
+        if let Some(customer_key) = customer_key {
+            resolved.sse_customer_key = Some(self.resolve(customer_key).await?);
+        }
+        Ok(Arc::new(resolved))
+    }
+
+    /// Resolves the private key and client email of a GCS source.
+    async fn resolve_gcs(&self, source: &Arc<GcsSourceConfig>) -> CacheEntry<Arc<GcsSourceConfig>> {
+        let key = &source.source_key;
+        if !is_reference(&key.private_key) && !is_reference(&key.client_email) {
+            return Ok(source.clone());
+        }
+
+        let mut resolved_key = key.as_ref().clone();
+        resolved_key.private_key = self.resolve(&key.private_key).await?;
+        resolved_key.client_email = self.resolve(&key.client_email).await?;
+
+        let mut resolved = GcsSourceConfig::clone(source);
+        resolved.source_key = Arc::new(resolved_key);
+        Ok(Arc::new(resolved))
+    }
+
+    /// Resolves a single `value`, which is returned as is if it is not a secret reference.
+    async fn resolve(&self, value: &str) -> CacheEntry<String> {
+        if !is_reference(value) {
+            return Ok(value.to_owned());
+        }
+
+        let secret = self
+            .cache
+            .try_get_with(value.to_owned(), self.fetch(value))
+            .await
+            .map_err(|err| {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(stderr, "Failed to resolve secret `{}`", value);
+                CacheError::DownloadError(format!("failed to resolve secret: {err}"))
+            })?;
+        Ok(secret.to_string())
+    }
+
+    /// Fetches the secret referenced by `reference` from its backend.
+    async fn fetch(&self, reference: &str) -> Result<Arc<str>, SecretError> {
+        let invalid = || SecretError::Invalid(reference.to_owned());
+        let inner = &reference[2..reference.len() - 1];
+        let (backend, path) = inner.split_once(':').ok_or_else(invalid)?;
+
+        let secret = match backend {
+            "env" => self.fetch_env(path)?,
+            "file" => self.fetch_file(path).await?,
+            "vault" => {
+                let (path, key) = path.split_once('#').ok_or_else(invalid)?;
+                self.fetch_vault(path, key).await?
+            }
+            _ => return Err(invalid()),
+        };
+
+        metric!(counter("secrets.resolved") += 1, "backend" => backend);
+        Ok(secret.into())
+    }
+
+    fn fetch_env(&self, name: &str) -> Result<String, SecretError> {
+        let prefix = self
+            .config
+            .env_prefix
+            .as_deref()
+            .ok_or(SecretError::NotConfigured("env"))?;
+        if !name.starts_with(prefix) {
+            return Err(SecretError::NotAllowed(name.to_owned()));
+        }
+
+        std::env::var(name).map_err(|_| SecretError::NotFound(name.to_owned()))
+    }
+
+    async fn fetch_file(&self, path: &str) -> Result<String, SecretError> {
+        let dir = self
+            .config
+            .files_dir
+            .as_deref()
+            .ok_or(SecretError::NotConfigured("file"))?;
+
+        // Resolve symlinks and `..` components, so that references cannot escape the directory.
+        let dir = tokio::fs::canonicalize(dir).await?;
+        let canonical_path = tokio::fs::canonicalize(path).await?;
+        if !canonical_path.starts_with(dir) {
+            return Err(SecretError::NotAllowed(path.to_owned()));
+        }
+
+        let secret = tokio::fs::read_to_string(canonical_path).await?;
+        // Files usually end with a newline, which is not part of the secret.
+        Ok(secret.trim_end_matches(['\r', '\n']).to_owned())
+    }
+
+    async fn fetch_vault(&self, path: &str, key: &str) -> Result<String, SecretError> {
+        let vault = self
+            .config
+            .vault
+            .as_ref()
+            .ok_or(SecretError::NotConfigured("vault"))?;
+        if !path.starts_with(&vault.path_prefix) || path.split('/').any(|c| c == "..") {
+            return Err(SecretError::NotAllowed(path.to_owned()));
+        }
+
+        let url = vault
+            .url
+            .join(&format!("v1/{}/data/{path}", vault.mount))
+            .map_err(|_| SecretError::Invalid(path.to_owned()))?;
+        // The token is read every time, so that rotating it does not require a restart.
+        let token = tokio::fs::read_to_string(&vault.token_file).await?;
+
+        let response: VaultResponse = self
+            .client
+            .get(url)
+            .header("X-Vault-Token", token.trim())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut secret = response.data.data;
+        secret
+            .remove(key)
+            .ok_or_else(|| SecretError::NotFound(format!("{path}#{key}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+
+    fn resolver(config: SecretsConfig) -> SecretResolver {
+        SecretResolver::new(config, reqwest::Client::new())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_env() {
+        std::env::set_var("SYMBOLICATOR_TEST_SECRET", "hunter2");
+        std::env::set_var("OTHER_TEST_SECRET", "hunter3");
+
+        let resolver = resolver(SecretsConfig {
+            env_prefix: Some("SYMBOLICATOR_".into()),
+            ..SecretsConfig::default()
+        });
+
+        let secret = resolver.resolve("${env:SYMBOLICATOR_TEST_SECRET}").await;
+        assert_eq!(secret.unwrap(), "hunter2");
+        assert_eq!(resolver.resolve("plaintext").await.unwrap(), "plaintext");
+
+        assert!(resolver.resolve("${env:OTHER_TEST_SECRET}").await.is_err());
+        assert!(resolver
+            .resolve("${env:SYMBOLICATOR_MISSING}")
+            .await
+            .is_err());
+        assert!(resolver.resolve("${unknown:SECRET}").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_file() {
+        let dir = test::tempdir();
+        let secrets_dir = dir.path().join("secrets");
+        std::fs::create_dir(&secrets_dir).unwrap();
+        std::fs::write(secrets_dir.join("token"), "hunter2\n").unwrap();
+        std::fs::write(dir.path().join("other"), "hunter3").unwrap();
+
+        let resolver = resolver(SecretsConfig {
+            files_dir: Some(secrets_dir.clone()),
+            ..SecretsConfig::default()
+        });
+
+        let reference = format!("${{file:{}}}", secrets_dir.join("token").display());
+        assert_eq!(resolver.resolve(&reference).await.unwrap(), "hunter2");
+
+        // Rotated secrets are picked up once the cached secret expires.
+        std::fs::write(secrets_dir.join("token"), "hunter4").unwrap();
+        assert_eq!(resolver.resolve(&reference).await.unwrap(), "hunter2");
+        resolver.cache.invalidate_all();
+        assert_eq!(resolver.resolve(&reference).await.unwrap(), "hunter4");
+
+        let escaping = format!("${{file:{}/../other}}", secrets_dir.display());
+        assert!(resolver.resolve(&escaping).await.is_err());
+    }
+}
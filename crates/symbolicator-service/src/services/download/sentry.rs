@@ -6,8 +6,9 @@ use std::collections::BTreeMap;
 use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use reqwest::header::{HeaderMap, LINK};
 use sentry::SentryFutureExt;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
@@ -18,13 +19,16 @@ use symbolicator_sources::{
 };
 
 use super::{FileType, USER_AGENT};
-use crate::caching::{CacheEntry, CacheError};
+use crate::caching::{CacheEntry, CacheError, ObjectIdFilter};
 use crate::config::Config;
 use crate::utils::futures::CancelOnDrop;
 
 #[derive(Clone, Debug, Deserialize)]
 struct SearchResult {
     pub id: SentryFileId,
+    /// The format of the file, as in the `file_formats` query.
+    #[serde(rename = "symbolType")]
+    pub symbol_type: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -43,13 +47,21 @@ struct SearchQuery {
     token: String,
 }
 
-/// An LRU cache sentry DIF index responses.
-type SentryIndexCache = moka::future::Cache<SearchQuery, CacheEntry<Vec<SearchResult>>>;
+/// The maximum number of pages that are fetched from a Sentry index.
+const MAX_INDEX_PAGES: usize = 50;
+
+/// An LRU cache sentry DIF index responses, along with the time at which they expire.
+///
+/// As sources can configure their own `index_ttl`, expiration is tracked per entry instead of
+/// using the TTL of the cache.
+type SentryIndexCache = moka::future::Cache<SearchQuery, (Instant, CacheEntry<Vec<SearchResult>>)>;
 
 pub struct SentryDownloader {
     client: reqwest::Client,
     runtime: tokio::runtime::Handle,
     index_cache: SentryIndexCache,
+    /// The time index responses are cached for sources without an `index_ttl`.
+    index_ttl: Duration,
     connect_timeout: Duration,
     streaming_timeout: Duration,
 }
@@ -70,14 +82,18 @@ impl SentryDownloader {
             runtime,
             index_cache: SentryIndexCache::builder()
                 .max_capacity(config.caches.in_memory.sentry_index_capacity)
-                .time_to_live(config.caches.in_memory.sentry_index_ttl)
+                .support_invalidation_closures()
                 .build(),
+            index_ttl: config.caches.in_memory.sentry_index_ttl,
             connect_timeout: config.connect_timeout,
             streaming_timeout: config.streaming_timeout,
         }
     }
 
     /// Make a request to sentry, parse the result as a JSON SearchResult list.
+    ///
+    /// Paginated responses are followed up to [`MAX_INDEX_PAGES`] pages, and the results of
+    /// all pages are concatenated.
     async fn fetch_sentry_json<T>(
         client: &reqwest::Client,
         query: &SearchQuery,
@@ -85,33 +101,58 @@ impl SentryDownloader {
     where
         T: DeserializeOwned,
     {
-        let mut request = client
-            .get(query.index_url.clone())
-            .bearer_auth(&query.token)
-            .header("Accept-Encoding", "identity")
-            .header("User-Agent", USER_AGENT);
-        if let Some(span) = sentry::configure_scope(|scope| scope.get_span()) {
-            for (k, v) in span.iter_headers() {
-                request = request.header(k, v);
+        let mut results = Vec::new();
+        let mut next_url = Some(query.index_url.clone());
+
+        for _ in 0..MAX_INDEX_PAGES {
+            let Some(url) = next_url.take() else {
+                return Ok(results);
+            };
+
+            let mut request = client
+                .get(url)
+                .bearer_auth(&query.token)
+                .header("Accept-Encoding", "identity")
+                .header("User-Agent", USER_AGENT);
+            if let Some(span) = sentry::configure_scope(|scope| scope.get_span()) {
+                for (k, v) in span.iter_headers() {
+                    request = request.header(k, v);
+                }
             }
-        }
 
-        let response = request.send().await?;
+            let response = request.send().await?;
+
+            if !response.status().is_success() {
+                tracing::warn!("Sentry returned status code {}", response.status());
+                let details = response.status().to_string();
+                return Err(CacheError::DownloadError(details));
+            }
 
-        if response.status().is_success() {
             tracing::trace!("Success fetching index from Sentry");
-            Ok(response.json().await?)
-        } else {
-            tracing::warn!("Sentry returned status code {}", response.status());
-            let details = response.status().to_string();
-            Err(CacheError::DownloadError(details))
+            next_url = next_page(response.headers());
+            results.extend(response.json::<Vec<T>>().await?);
+        }
+
+        if next_url.is_some() {
+            tracing::warn!(
+                "Sentry index at {} has more than {} pages, ignoring the remaining ones",
+                query.index_url,
+                MAX_INDEX_PAGES
+            );
         }
+
+        Ok(results)
     }
 
     /// Return the search results.
     ///
-    /// If there are cached search results this skips the actual search.
-    async fn cached_sentry_search(&self, query: SearchQuery) -> CacheEntry<Vec<SearchResult>> {
+    /// If there are cached search results which are younger than `ttl`, this skips the actual
+    /// search. Concurrent searches for the same query are coalesced into a single request.
+    async fn cached_sentry_search(
+        &self,
+        query: SearchQuery,
+        ttl: Duration,
+    ) -> CacheEntry<Vec<SearchResult>> {
         let query_ = query.clone();
         let init = Box::pin(async {
             tracing::debug!(
@@ -126,14 +167,36 @@ impl SentryDownloader {
             let future =
                 CancelOnDrop::new(self.runtime.spawn(future.bind_hub(sentry::Hub::current())));
 
-            future.await.map_err(|_| CacheError::InternalError)?
+            let entry = future
+                .await
+                .map_err(|_| CacheError::InternalError)
+                .and_then(|entry| entry);
+            (Instant::now() + ttl, entry)
         });
 
+        let replace_if = |(expires, entry): &(Instant, CacheEntry<_>)| {
+            entry.is_err() || Instant::now() >= *expires
+        };
+
         self.index_cache
             .entry(query)
-            .or_insert_with_if(init, |entry| entry.is_err())
+            .or_insert_with_if(init, replace_if)
             .await
             .into_value()
+            .1
+    }
+
+    /// Drops the cached index responses of queries for the object matched by `filter`.
+    ///
+    /// Lookups of an object which was missing from the index query it again afterwards, instead
+    /// of waiting for the cached response to expire.
+    pub fn invalidate_index(&self, filter: &ObjectIdFilter) {
+        let filter = filter.clone();
+        let predicate = move |query: &SearchQuery, _: &_| filter.matches(query.index_url.as_str());
+        if let Err(err) = self.index_cache.invalidate_entries_if(predicate) {
+            let error: &dyn std::error::Error = &err;
+            tracing::error!(error, "Failed to invalidate Sentry index responses");
+        }
     }
 
     pub async fn list_files(
@@ -142,15 +205,14 @@ impl SentryDownloader {
         object_id: &ObjectId,
         file_types: &[FileType],
     ) -> CacheEntry<Vec<RemoteFile>> {
-        // TODO(flub): These queries do not handle pagination.  But sentry only starts to
-        // paginate at 20 results so we get away with this for now.
-
         // There needs to be either a debug_id or a code_id filter in the query. Otherwise, this would
         // return a list of all debug files in the project.
         if object_id.debug_id.is_none() && object_id.code_id.is_none() {
             return Ok(Vec::new());
         }
 
+        // The index is queried for files of all formats, and filtered afterwards. This way, the
+        // lookups of different file types for the same object share a single cached query.
         let mut index_url = source.url.clone();
         if let Some(ref debug_id) = object_id.debug_id {
             index_url
@@ -158,26 +220,6 @@ impl SentryDownloader {
                 .append_pair("debug_id", &debug_id.to_string());
         }
 
-        // See <sentry-repo>/src/sentry/constants.py KNOWN_DIF_FORMATS for these query strings.
-        index_url.query_pairs_mut().extend_pairs(
-            file_types
-                .iter()
-                .map(|file_type| match file_type {
-                    FileType::UuidMap => "uuidmap",
-                    FileType::BcSymbolMap => "bcsymbolmap",
-                    FileType::Pe => "pe",
-                    FileType::Pdb => "pdb",
-                    FileType::MachDebug | FileType::MachCode => "macho",
-                    FileType::ElfDebug | FileType::ElfCode => "elf",
-                    FileType::WasmDebug | FileType::WasmCode => "wasm",
-                    FileType::Breakpad => "breakpad",
-                    FileType::SourceBundle => "sourcebundle",
-                    FileType::Il2cpp => "il2cpp",
-                    FileType::PortablePdb => "portablepdb",
-                })
-                .map(|val| ("file_formats", val)),
-        );
-
         if let Some(ref code_id) = object_id.code_id {
             index_url
                 .query_pairs_mut()
@@ -189,9 +231,16 @@ impl SentryDownloader {
             token: source.token.clone(),
         };
 
-        let search = self.cached_sentry_search(query).await?;
+        let ttl = source.index_ttl.unwrap_or(self.index_ttl);
+        let search = self.cached_sentry_search(query, ttl).await?;
+        let file_formats: Vec<_> = file_types.iter().copied().map(file_format).collect();
         let file_ids = search
             .into_iter()
+            .filter(|search_result| match search_result.symbol_type {
+                Some(ref symbol_type) => file_formats.contains(&symbol_type.as_str()),
+                // Keep files of unknown formats, they are filtered when they are downloaded.
+                None => true,
+            })
             .map(|search_result| {
                 SentryRemoteFile::new(source.clone(), search_result.id, SentryFileType::DebugFile)
                     .into()
@@ -256,6 +305,42 @@ impl SentryDownloader {
     }
 }
 
+/// Returns the format of a file type in the Sentry index.
+///
+/// See <sentry-repo>/src/sentry/constants.py KNOWN_DIF_FORMATS for these values.
+fn file_format(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::UuidMap => "uuidmap",
+        FileType::BcSymbolMap => "bcsymbolmap",
+        FileType::Pe => "pe",
+        FileType::Pdb => "pdb",
+        FileType::MachDebug | FileType::MachCode => "macho",
+        FileType::ElfDebug | FileType::ElfCode => "elf",
+        FileType::WasmDebug | FileType::WasmCode => "wasm",
+        FileType::Breakpad => "breakpad",
+        FileType::SourceBundle => "sourcebundle",
+        FileType::Il2cpp => "il2cpp",
+        FileType::Proguard => "proguard",
+        FileType::PortablePdb => "portablepdb",
+    }
+}
+
+/// Returns the URL of the next page of a paginated Sentry response.
+///
+/// Sentry announces pages in a `Link` header, in which the `next` link carries `results="true"`
+/// if there are more results.
+fn next_page(headers: &HeaderMap) -> Option<Url> {
+    let links = headers.get(LINK)?.to_str().ok()?;
+
+    links.split(',').find_map(|link| {
+        let (url, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+        let mut params = params.split(';').map(str::trim);
+        let is_next = params.clone().any(|param| param == r#"rel="next""#);
+        let has_results = params.any(|param| param == r#"results="true""#);
+        (is_next && has_results).then(|| url.parse().ok())?
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,6 +353,7 @@ mod tests {
             id: SourceId::new("test"),
             url: Url::parse("https://example.net/endpoint/").unwrap(),
             token: "token".into(),
+            index_ttl: None,
         };
         let file_source = SentryRemoteFile::new(
             Arc::new(source),
@@ -278,12 +364,35 @@ mod tests {
         assert_eq!(url.as_str(), "https://example.net/endpoint/?id=abc123");
     }
 
+    #[test]
+    fn test_next_page() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_page(&headers), None);
+
+        let links = concat!(
+            r#"<https://sentry.io/api/0/projects/a/b/files/dsyms/?cursor=100:-1:1>; "#,
+            r#"rel="previous"; results="false"; cursor="100:-1:1", "#,
+            r#"<https://sentry.io/api/0/projects/a/b/files/dsyms/?cursor=100:1:0>; "#,
+            r#"rel="next"; results="true"; cursor="100:1:0""#,
+        );
+        headers.insert(LINK, links.parse().unwrap());
+        assert_eq!(
+            next_page(&headers).unwrap().as_str(),
+            "https://sentry.io/api/0/projects/a/b/files/dsyms/?cursor=100:1:0"
+        );
+
+        let last_page = links.replace(r#"results="true""#, r#"results="false""#);
+        headers.insert(LINK, last_page.parse().unwrap());
+        assert_eq!(next_page(&headers), None);
+    }
+
     #[test]
     fn test_uri() {
         let source = SentrySourceConfig {
             id: SourceId::new("test"),
             url: Url::parse("https://example.net/endpoint/").unwrap(),
             token: "token".into(),
+            index_ttl: None,
         };
         let file_source = SentryRemoteFile::new(
             Arc::new(source),
@@ -0,0 +1,79 @@
+//! Support to delegate downloads to a separate downloader process.
+//!
+//! The downloader process is started with `symbolicator downloader` and exposes a `POST /download`
+//! endpoint, which receives the serialized [`RemoteFile`] and responds with its contents. Requests
+//! are authenticated with the `downloader_token`, which both processes need to share.
+
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::{header, Client};
+use url::Url;
+
+use symbolicator_sources::RemoteFile;
+
+use crate::caching::{CacheEntry, CacheError};
+
+use super::USER_AGENT;
+
+/// Downloader implementation that forwards all downloads to a downloader process.
+#[derive(Debug)]
+pub struct SidecarDownloader {
+    client: Client,
+    url: Url,
+    token: Option<String>,
+    download_timeout: Duration,
+    streaming_timeout: Duration,
+}
+
+impl SidecarDownloader {
+    pub fn new(
+        client: Client,
+        url: Url,
+        token: Option<String>,
+        download_timeout: Duration,
+        streaming_timeout: Duration,
+    ) -> Self {
+        Self {
+            client,
+            url,
+            token,
+            download_timeout,
+            streaming_timeout,
+        }
+    }
+
+    /// Downloads a file from any source through the downloader process.
+    ///
+    /// The downloader process only responds once it has fetched the complete file, which is why
+    /// the entire download timeout applies to receiving the response.
+    pub async fn download_source(
+        &self,
+        file_source: &RemoteFile,
+        destination: &Path,
+    ) -> CacheEntry {
+        let download_url = self
+            .url
+            .join("download")
+            .map_err(|_| CacheError::InternalError)?;
+
+        tracing::debug!("Fetching {} through {}", file_source, download_url);
+        let mut request = self
+            .client
+            .post(download_url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .json(file_source);
+        if let Some(ref token) = self.token {
+            request = request.bearer_auth(token);
+        }
+
+        super::download_reqwest(
+            file_source,
+            request,
+            self.download_timeout,
+            self.streaming_timeout,
+            destination,
+        )
+        .await
+    }
+}
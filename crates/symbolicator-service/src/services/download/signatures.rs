@@ -0,0 +1,124 @@
+//! Verification of detached signatures of downloaded objects.
+//!
+//! Sources can be configured to require signatures, in which case every object file downloaded
+//! from them must be accompanied by an Ed25519 signature at its location with a `.sig` suffix.
+//! Signatures are made over the decompressed object file, and can be stored either as the raw
+//! 64 bytes or base64 encoded.
+
+use data_encoding::BASE64;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use symbolicator_sources::SourceId;
+
+use crate::caching::{CacheEntry, CacheError};
+use crate::config::SignatureConfig;
+
+/// The suffix appended to the location of an object to find its signature.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// The length of an Ed25519 signature.
+const SIGNATURE_LEN: usize = 64;
+
+/// The public keys trusted to sign the objects of the sources which require signatures.
+#[derive(Debug)]
+pub struct SignatureKeys {
+    configs: Vec<SignatureConfig>,
+}
+
+impl SignatureKeys {
+    pub fn new(configs: Vec<SignatureConfig>) -> Self {
+        Self { configs }
+    }
+
+    /// Returns whether the objects of `source` need to be signed.
+    pub fn is_required(&self, source: &SourceId) -> bool {
+        self.configs_for(source).next().is_some()
+    }
+
+    /// Verifies that `signature` is a signature of `data` by one of the keys trusted for `source`.
+    pub fn verify(&self, source: &SourceId, data: &[u8], signature: &[u8]) -> CacheEntry {
+        let Some(signature) = parse_signature(signature) else {
+            return Err(CacheError::SignatureInvalid("malformed signature".into()));
+        };
+
+        let valid = self
+            .configs_for(source)
+            .flat_map(|config| &config.public_keys)
+            .any(|key| {
+                UnparsedPublicKey::new(&ED25519, key)
+                    .verify(data, &signature)
+                    .is_ok()
+            });
+
+        if valid {
+            Ok(())
+        } else {
+            Err(CacheError::SignatureInvalid(
+                "not signed by a trusted key".into(),
+            ))
+        }
+    }
+
+    fn configs_for<'a>(
+        &'a self,
+        source: &'a SourceId,
+    ) -> impl Iterator<Item = &'a SignatureConfig> {
+        self.configs
+            .iter()
+            .filter(move |config| config.sources.iter().any(|id| id == source.as_str()))
+    }
+}
+
+/// Parses a signature which is either stored as raw bytes or base64 encoded.
+fn parse_signature(raw: &[u8]) -> Option<Vec<u8>> {
+    if raw.len() == SIGNATURE_LEN {
+        return Some(raw.to_vec());
+    }
+
+    let encoded = std::str::from_utf8(raw).ok()?.trim();
+    let decoded = BASE64.decode(encoded.as_bytes()).ok()?;
+    (decoded.len() == SIGNATURE_LEN).then_some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let trusted = key_pair();
+        let untrusted = key_pair();
+        let keys = SignatureKeys::new(vec![SignatureConfig {
+            sources: vec!["signed".into()],
+            public_keys: vec![trusted.public_key().as_ref().to_vec()],
+        }]);
+        let signed = SourceId::new("signed");
+
+        assert!(keys.is_required(&signed));
+        assert!(!keys.is_required(&SourceId::new("unsigned")));
+
+        let data = b"object file contents";
+        let signature = trusted.sign(data);
+        assert_eq!(keys.verify(&signed, data, signature.as_ref()), Ok(()));
+
+        let encoded = format!("{}\n", BASE64.encode(signature.as_ref()));
+        assert_eq!(keys.verify(&signed, data, encoded.as_bytes()), Ok(()));
+
+        let tampered = keys.verify(&signed, b"tampered contents", signature.as_ref());
+        assert!(matches!(tampered, Err(CacheError::SignatureInvalid(_))));
+
+        let other_signature = untrusted.sign(data);
+        let untrusted = keys.verify(&signed, data, other_signature.as_ref());
+        assert!(matches!(untrusted, Err(CacheError::SignatureInvalid(_))));
+
+        let malformed = keys.verify(&signed, data, b"not a signature");
+        assert!(matches!(malformed, Err(CacheError::SignatureInvalid(_))));
+    }
+}
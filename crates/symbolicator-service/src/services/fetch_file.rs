@@ -6,28 +6,35 @@ use tempfile::NamedTempFile;
 
 use crate::caching::{CacheEntry, CacheError};
 use crate::services::download::DownloadService;
-use crate::utils::compression::maybe_decompress_file;
+use crate::utils::compression::maybe_decompress_file_limited;
 
 /// Downloads the gives [`RemoteFile`] and decompresses it.
 ///
+/// Files which decompress to more than the configured `max_download_size` are malformed.
+///
 /// This takes a [`NamedTempFile`] to store the resulting file into, and will return a
 /// [`NamedTempFile`] back to the caller. This is either the original in case no decompression
 /// needs to happen, or a new one in case the downloaded file needs to be decompressed. In that case,
 /// a new [`NamedTempFile`] in the same directory will be created and returned.
+///
+/// On success, returns the number of bytes that were downloaded, before decompression.
 #[tracing::instrument(skip(downloader, temp_file), fields(%file_id))]
 pub async fn fetch_file(
     downloader: Arc<DownloadService>,
     file_id: RemoteFile,
     temp_file: &mut NamedTempFile,
-) -> CacheEntry {
+) -> CacheEntry<u64> {
     downloader
         .download(file_id, temp_file.path().to_owned())
         .await?;
     tracing::trace!("Finished download");
+    let downloaded_bytes = temp_file.as_file().metadata()?.len();
 
     // Treat decompression errors as malformed files. It is more likely that
     // the error comes from a corrupt file than a local file system error.
-    maybe_decompress_file(temp_file).map_err(|e| CacheError::Malformed(e.to_string()))?;
+    maybe_decompress_file_limited(temp_file, downloader.max_download_size())
+        .map_err(|e| CacheError::Malformed(e.to_string()))?;
 
-    Ok(temp_file.as_file().rewind()?)
+    temp_file.as_file().rewind()?;
+    Ok(downloaded_bytes)
 }
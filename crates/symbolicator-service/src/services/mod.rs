@@ -13,7 +13,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 
-use crate::caching::{Caches, SharedCacheService};
+use crate::caching::{CacheStatsRegistry, Caches, SharedCacheService};
 use crate::config::Config;
 
 pub mod bitcode;
@@ -27,6 +27,7 @@ mod minidump;
 mod module_lookup;
 pub mod objects;
 pub mod ppdb_caches;
+pub mod proguard;
 pub mod sourcemap;
 mod sourcemap_lookup;
 pub mod symbolication;
@@ -39,10 +40,12 @@ use self::download::DownloadService;
 use self::il2cpp::Il2cppService;
 use self::objects::ObjectsActor;
 use self::ppdb_caches::PortablePdbCacheActor;
+use self::proguard::ProguardService;
 use self::sourcemap::SourceMapService;
 use self::symbolication::SymbolicationActor;
 use self::symcaches::SymCacheActor;
 pub use fetch_file::fetch_file;
+pub use module_lookup::object_id_from_object_info;
 
 pub fn create_service(
     config: &Config,
@@ -53,6 +56,12 @@ pub fn create_service(
         .clear_tmp(config)
         .context("failed to clear tmp caches")?;
 
+    let cache_stats = CacheStatsRegistry::default();
+    if let Some(interval) = config.caches.size_metrics_interval {
+        caches.spawn_size_scanner(interval, cache_stats.clone(), &io_pool);
+    }
+    caches.spawn_disk_space_watchdog(config.caches.disk_space, &io_pool);
+
     let downloader = DownloadService::new(config, io_pool.clone());
 
     let shared_cache = SharedCacheService::new(config.shared_cache.clone(), io_pool);
@@ -87,6 +96,8 @@ pub fn create_service(
     let ppdb_caches =
         PortablePdbCacheActor::new(caches.ppdb_caches, shared_cache.clone(), objects.clone());
 
+    let proguard = ProguardService::new(caches.proguard, shared_cache.clone(), downloader.clone());
+
     let sourcemaps = SourceMapService::new(
         sourcefiles_cache,
         caches.sourcemap_caches,
@@ -101,6 +112,9 @@ pub fn create_service(
         ppdb_caches,
         caches.diagnostics,
         sourcemaps,
+        proguard,
+        cache_stats,
+        config,
     );
 
     Ok((symbolication, objects))
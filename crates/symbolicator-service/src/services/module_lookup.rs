@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Instant;
 
-use futures::future;
 use sentry::{Hub, SentryFutureExt};
 
 use symbolic::debuginfo::ObjectDebugSession;
@@ -13,14 +13,16 @@ use crate::services::objects::{FindObject, FindResult, ObjectHandle, ObjectPurpo
 use crate::services::ppdb_caches::{
     FetchPortablePdbCache, OwnedPortablePdbCache, PortablePdbCacheActor,
 };
+use crate::services::symbolication::{ProgressTracker, TraceEventKind, TraceRecorder};
 use crate::services::symcaches::{FetchSymCache, OwnedSymCache, SymCacheActor};
 use crate::types::{
     AllObjectCandidates, CompleteObjectInfo, CompleteStacktrace, ObjectFeatures, ObjectFileStatus,
     RawFrame, RawObjectInfo, RawStacktrace, Scope,
 };
-use crate::utils::addr::AddrMode;
+use crate::utils::addr::{AddrMode, KernelSpace};
+use crate::utils::futures::join_until;
 
-fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
+pub fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
     ObjectId {
         debug_id: match object_info.debug_id.as_deref() {
             None | Some("") => None,
@@ -40,10 +42,15 @@ fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
 pub fn object_file_status_from_cache_entry<T>(cache_entry: &CacheEntry<T>) -> ObjectFileStatus {
     match cache_entry {
         Ok(_) => ObjectFileStatus::Found,
-        Err(CacheError::NotFound) => ObjectFileStatus::Missing,
-        Err(CacheError::PermissionDenied(_) | CacheError::DownloadError(_)) => {
-            ObjectFileStatus::FetchingFailed
-        }
+        // A file of the wrong build is as good as no file at all.
+        Err(CacheError::NotFound | CacheError::IdMismatch(_)) => ObjectFileStatus::Missing,
+        Err(
+            CacheError::PermissionDenied(_)
+            | CacheError::RateLimited(_)
+            | CacheError::DownloadError(_)
+            | CacheError::ChecksumMismatch(_)
+            | CacheError::SignatureInvalid(_),
+        ) => ObjectFileStatus::FetchingFailed,
         Err(CacheError::Timeout(_)) => ObjectFileStatus::Timeout,
         Err(CacheError::Malformed(_)) => ObjectFileStatus::Malformed,
         Err(CacheError::InternalError) => ObjectFileStatus::Other,
@@ -106,11 +113,14 @@ pub struct ModuleLookup {
     modules: Vec<ModuleEntry>,
     scope: Scope,
     sources: Arc<[SourceConfig]>,
+    kernel: KernelSpace,
 }
 
 impl ModuleLookup {
     /// Creates a new [`ModuleLookup`] out of the given module iterator.
-    pub fn new<I>(scope: Scope, sources: Arc<[SourceConfig]>, iter: I) -> Self
+    ///
+    /// Frames in the `kernel` space are only attributed to kernel modules and vice versa.
+    pub fn new<I>(scope: Scope, sources: Arc<[SourceConfig]>, kernel: KernelSpace, iter: I) -> Self
     where
         I: IntoIterator<Item = CompleteObjectInfo>,
     {
@@ -125,7 +135,7 @@ impl ModuleLookup {
             })
             .collect();
 
-        modules.sort_by_key(|entry| entry.object_info.raw.image_addr.0);
+        modules.sort_by_key(|entry| entry.object_info.base_addr());
 
         // back-fill the `image_size` in case it is missing (or 0), so that it spans up to the
         // next image.
@@ -139,10 +149,10 @@ impl ModuleLookup {
             for i in 0..modules.len() - 1 {
                 let next_addr = modules
                     .get(i + 1)
-                    .map(|entry| entry.object_info.raw.image_addr.0);
+                    .map(|entry| entry.object_info.base_addr());
                 if let Some(entry) = modules.get_mut(i) {
                     if entry.object_info.raw.image_size.unwrap_or(0) == 0 {
-                        let entry_addr = entry.object_info.raw.image_addr.0;
+                        let entry_addr = entry.object_info.base_addr();
                         let size = next_addr.unwrap_or(entry_addr) - entry_addr;
                         entry.object_info.raw.image_size = Some(size);
                     }
@@ -154,6 +164,7 @@ impl ModuleLookup {
             modules,
             scope,
             sources,
+            kernel,
         }
     }
 
@@ -167,13 +178,22 @@ impl ModuleLookup {
     }
 
     /// Fetches all the SymCaches for the modules referenced by the `stacktraces`.
+    ///
+    /// Stops waiting for outstanding fetches once the `deadline` is hit, in which case the
+    /// affected modules are marked as [`ObjectFileStatus::Timeout`] and `true` is returned.
+    ///
+    /// The number of fetched modules is reported to `progress` as fetches complete, and the
+    /// outcome of each fetch is recorded in the `trace`.
     #[tracing::instrument(skip_all)]
     pub async fn fetch_caches(
         &mut self,
         symcache_actor: SymCacheActor,
         ppdb_cache_actor: PortablePdbCacheActor,
         stacktraces: &[RawStacktrace],
-    ) {
+        deadline: Option<Instant>,
+        progress: &ProgressTracker,
+        trace: &TraceRecorder,
+    ) -> bool {
         let mut referenced_objects = HashSet::new();
         for stacktrace in stacktraces {
             for frame in &stacktrace.frames {
@@ -185,7 +205,7 @@ impl ModuleLookup {
             }
         }
 
-        let futures = self
+        let futures: Vec<_> = self
             .modules
             .iter_mut()
             .enumerate()
@@ -195,15 +215,23 @@ impl ModuleLookup {
                     entry.object_info.debug_status = ObjectFileStatus::Unused;
                     return None;
                 }
+                // This is overwritten once the fetch completes, so it only sticks for modules
+                // that are still being fetched when the deadline is hit.
+                entry.object_info.debug_status = ObjectFileStatus::Timeout;
+
                 let symcache_actor = symcache_actor.clone();
                 let ppdb_cache_actor = ppdb_cache_actor.clone();
                 let identifier = object_id_from_object_info(&entry.object_info.raw);
                 let sources = self.sources.clone();
                 let scope = self.scope.clone();
                 let object_type = entry.object_info.raw.ty;
+                let progress = progress.clone();
+                let trace = trace.clone();
+                let module_index = entry.module_index;
 
                 let fut = async move {
-                    match object_type {
+                    let start = Instant::now();
+                    let result = match object_type {
                         ObjectType::PeDotnet => {
                             let request = FetchPortablePdbCache {
                                 identifier,
@@ -247,13 +275,25 @@ impl ModuleLookup {
 
                             (idx, cache_file)
                         }
-                    }
+                    };
+                    progress.update(|progress| progress.modules_fetched += 1);
+                    trace.record(|| TraceEventKind::DebugInfo {
+                        module: module_index,
+                        status: object_file_status_from_cache_entry(&result.1.file),
+                        duration_ms: start.elapsed().as_millis() as u64,
+                        candidates: result.1.candidates.0.clone(),
+                    });
+                    result
                 };
 
                 Some(fut.bind_hub(Hub::new_from_top(Hub::current())))
-            });
+            })
+            .collect();
 
-        for (idx, cache_file) in future::join_all(futures).await {
+        progress.update(|progress| progress.modules_total = futures.len());
+
+        let (results, deadline_hit) = join_until(futures, deadline).await;
+        for (idx, cache_file) in results {
             let CacheFile {
                 file,
                 candidates,
@@ -273,15 +313,21 @@ impl ModuleLookup {
                 entry.cache = file;
             }
         }
+
+        deadline_hit
     }
 
     /// Fetches all the sources for the modules referenced by the `stacktraces`.
+    ///
+    /// Stops waiting for outstanding fetches once the `deadline` is hit, in which case `true`
+    /// is returned and the affected modules will not have any source context.
     #[tracing::instrument(skip_all)]
     pub async fn fetch_sources(
         &mut self,
         objects: ObjectsActor,
         stacktraces: &[CompleteStacktrace],
-    ) {
+        deadline: Option<Instant>,
+    ) -> bool {
         let mut referenced_objects = HashSet::new();
         for stacktrace in stacktraces {
             for frame in &stacktrace.frames {
@@ -331,7 +377,8 @@ impl ModuleLookup {
                 Some(fut.bind_hub(Hub::new_from_top(Hub::current())))
             });
 
-        for (idx, source_object, candidates) in future::join_all(futures).await {
+        let (results, deadline_hit) = join_until(futures, deadline).await;
+        for (idx, source_object, candidates) in results {
             if let Some(entry) = self.modules.get_mut(idx) {
                 entry.source_object = source_object;
                 entry.object_info.candidates.merge(&candidates);
@@ -341,6 +388,8 @@ impl ModuleLookup {
                 }
             }
         }
+
+        deadline_hit
     }
 
     /// Look up the corresponding SymCache based on the instruction `addr`.
@@ -417,7 +466,7 @@ impl ModuleLookup {
             AddrMode::Abs => {
                 let idx = match self
                     .modules
-                    .binary_search_by_key(&addr, |entry| entry.object_info.raw.image_addr.0)
+                    .binary_search_by_key(&addr, |entry| entry.object_info.base_addr())
                 {
                     Ok(idx) => idx,
                     Err(idx) if idx == 0 => {
@@ -427,7 +476,13 @@ impl ModuleLookup {
                 };
                 let entry = self.modules.get(idx)?;
 
-                let start_addr = entry.object_info.raw.image_addr.0;
+                // Images with unknown sizes would otherwise extend across the boundary between
+                // user and kernel space, and claim kernel frames for which no module was sent.
+                if self.kernel.contains(addr) != entry.object_info.is_kernel_module(self.kernel) {
+                    return None;
+                }
+
+                let start_addr = entry.object_info.base_addr();
                 let size = entry.object_info.raw.image_size.unwrap_or(0);
                 let end_addr = start_addr.checked_add(size)?;
 
@@ -449,6 +504,8 @@ impl ModuleLookup {
 
 #[cfg(test)]
 mod tests {
+    use symbolic::common::Arch;
+
     use crate::types::RawObjectInfo;
     use crate::utils::hex::HexValue;
 
@@ -474,6 +531,7 @@ mod tests {
         let modules = ModuleLookup::new(
             Scope::Global,
             Arc::new([]),
+            KernelSpace::default(),
             raw_modules.into_iter().map(From::from),
         );
 
@@ -510,6 +568,7 @@ mod tests {
         let modules = ModuleLookup::new(
             Scope::Global,
             Arc::new([]),
+            KernelSpace::default(),
             raw_modules.into_iter().map(From::from),
         );
 
@@ -526,6 +585,92 @@ mod tests {
         assert_eq!(entry.unwrap().object_info.raw.code_id.as_deref(), Some("c"));
     }
 
+    #[test]
+    fn module_lookup_kernel() {
+        let raw_modules: Vec<RawObjectInfo> = serde_json::from_str(
+            r#"[{
+                "code_id": "app",
+                "type":"elf",
+                "image_addr": "0x555555554000"
+            },{
+                "code_id": "vmlinux",
+                "type":"elf",
+                "image_addr": "0xffffffff9a000000",
+                "image_size": 33554432
+            },{
+                "code_id": "module",
+                "type":"elf",
+                "image_addr": "0xffffffffc0a00000"
+            }]"#,
+        )
+        .unwrap();
+
+        let modules = ModuleLookup::new(
+            Scope::Global,
+            Arc::new([]),
+            KernelSpace::new("Linux", Arch::Amd64),
+            raw_modules.into_iter().map(From::from),
+        );
+
+        let entry = modules.lookup_cache(0x555555556000, AddrMode::Abs);
+        assert_eq!(
+            entry.unwrap().object_info.raw.code_id.as_deref(),
+            Some("app")
+        );
+
+        let entry = modules.lookup_cache(0xffffffff9a123456, AddrMode::Abs);
+        assert_eq!(entry.unwrap().relative_addr, Some(0x123456));
+
+        let entry = modules.lookup_cache(0xffffffffc0a01234, AddrMode::Abs);
+        assert_eq!(
+            entry.unwrap().object_info.raw.code_id.as_deref(),
+            Some("module")
+        );
+
+        // Kernel addresses below the first kernel module are not attributed to user space.
+        let entry = modules.lookup_cache(0xffffffff81000000, AddrMode::Abs);
+        assert!(entry.is_none());
+    }
+
+    #[test]
+    fn module_lookup_slide() {
+        let raw_modules: Vec<RawObjectInfo> = serde_json::from_str(
+            r#"[{
+                "code_id": "a",
+                "type":"macho",
+                "image_addr": "0x104a3c000",
+                "image_size": 16384,
+                "slide": "0x4a3c000"
+            },{
+                "code_id": "b",
+                "type":"macho",
+                "image_addr": "0x1a2b3c000",
+                "image_size": 16384
+            }]"#,
+        )
+        .unwrap();
+
+        let modules = ModuleLookup::new(
+            Scope::Global,
+            Arc::new([]),
+            KernelSpace::default(),
+            raw_modules.into_iter().map(From::from),
+        );
+
+        // Frames of the slid image are reported with unslid addresses.
+        let entry = modules.lookup_cache(0x100001234, AddrMode::Abs).unwrap();
+        assert_eq!(entry.object_info.raw.code_id.as_deref(), Some("a"));
+        assert_eq!(entry.relative_addr, Some(0x1234));
+        assert_eq!(entry.expose_preferred_addr(0x1234), 0x100001234);
+
+        let entry = modules.lookup_cache(0x104a3d234, AddrMode::Abs);
+        assert!(entry.is_none());
+
+        let entry = modules.lookup_cache(0x1a2b3d234, AddrMode::Abs).unwrap();
+        assert_eq!(entry.object_info.raw.code_id.as_deref(), Some("b"));
+        assert_eq!(entry.relative_addr, Some(0x1234));
+    }
+
     #[test]
     fn test_symcache_lookup_open_end_addr() {
         // The Rust SDK and some other clients sometimes send zero-sized images when no end addr
@@ -538,10 +683,16 @@ mod tests {
             debug_file: None,
             debug_checksum: None,
             image_addr: HexValue(42),
+            slide: None,
             image_size: Some(0),
         });
 
-        let lookup = ModuleLookup::new(Scope::Global, Arc::new([]), std::iter::once(info.clone()));
+        let lookup = ModuleLookup::new(
+            Scope::Global,
+            Arc::new([]),
+            KernelSpace::default(),
+            std::iter::once(info.clone()),
+        );
 
         let lookup_result = lookup.lookup_cache(43, AddrMode::Abs).unwrap();
         assert_eq!(lookup_result.module_index, 0);
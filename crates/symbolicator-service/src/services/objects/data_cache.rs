@@ -9,17 +9,20 @@
 use std::cmp;
 use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures::future::BoxFuture;
 use sentry::{Hub, SentryFutureExt};
+use sha2::{Digest, Sha256};
 use symbolic::common::SelfCell;
 use tempfile::NamedTempFile;
 
 use symbolic::common::ByteView;
 use symbolic::debuginfo::{Archive, Object};
-use symbolicator_sources::{ObjectId, RemoteFile};
+use symbolicator_sources::{ObjectId, RemoteFile, SourceId};
 
 use crate::caching::CacheVersions;
 use crate::caching::{CacheEntry, CacheError, CacheItemRequest, CacheKey};
@@ -27,6 +30,7 @@ use crate::services::caches::versions::OBJECTS_CACHE_VERSIONS;
 use crate::services::download::DownloadService;
 use crate::services::fetch_file;
 use crate::types::Scope;
+use crate::utils::archives::{self, ArchiveFormat};
 use crate::utils::compression::tempfile_in_parent;
 use crate::utils::futures::{m, measure};
 use crate::utils::sentry::ConfigureScope;
@@ -34,9 +38,22 @@ use crate::utils::sentry::ConfigureScope;
 use super::meta_cache::FetchFileMetaRequest;
 
 /// This requests the file content of a single file at a specific path/url.
-/// The attributes for this are the same as for `FetchFileMetaRequest`, hence the newtype
+/// The attributes for this are the same as for `FetchFileMetaRequest`, which it wraps.
 #[derive(Clone, Debug)]
-pub(super) struct FetchFileDataRequest(pub(super) FetchFileMetaRequest);
+pub(super) struct FetchFileDataRequest {
+    pub(super) meta: FetchFileMetaRequest,
+    /// Set when the file is downloaded for this request instead of being loaded from the cache.
+    pub(super) downloaded: Arc<AtomicBool>,
+}
+
+impl FetchFileDataRequest {
+    pub(super) fn new(meta: FetchFileMetaRequest) -> Self {
+        Self {
+            meta,
+            downloaded: Default::default(),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct OwnedObject(SelfCell<ByteView<'static>, Object<'static>>);
@@ -112,10 +129,13 @@ impl fmt::Display for ObjectHandle {
 
 /// Downloads the object file, processes it and returns whether the file is in the cache.
 ///
-/// If the object file was successfully downloaded it is first decompressed.  If it is
-/// an archive containing multiple objects, then next the object matching the code or
-/// debug ID of our request is extracted first.  Finally the object is parsed with
-/// symbolic to ensure it is not malformed.
+/// If the object file was successfully downloaded it is first decompressed.  If it is a zip
+/// or tar archive, or an archive containing multiple objects, then next the object matching
+/// the code or debug ID of our request is extracted first.  Finally the object is parsed with
+/// symbolic to ensure it is not malformed, and that it actually matches the requested
+/// code or debug ID, as well as the requested checksum if one was provided.  Sources can
+/// additionally require the downloaded file to carry a valid detached signature, and the file
+/// is scanned for malware if a scanner is configured.
 ///
 /// This is the actual implementation of [`CacheItemRequest::compute`] for
 /// [`FetchFileDataRequest`] but outside of the trait so it can be written as async/await
@@ -132,12 +152,76 @@ async fn fetch_object_file(
         object_id.to_scope(scope);
     });
 
-    fetch_file(downloader, file_id, temp_file).await?;
+    let source = file_id.source_id().clone();
+    let downloaded_bytes = fetch_file(downloader.clone(), file_id.clone(), temp_file).await?;
+    let downloaded_bytes: i64 = downloaded_bytes.try_into().unwrap_or(i64::MAX);
+    metric!(
+        counter("objects.download.bytes") += downloaded_bytes,
+        "source" => source.as_str(),
+        "object_type" => &object_id.object_type.to_string(),
+    );
+
+    let view = ByteView::map_file_ref(temp_file.as_file())?;
+
+    // Signatures are made over the whole decompressed file, before any objects are extracted
+    // from archives.
+    if downloader.requires_signature(&file_id) {
+        let signature_file = tempfile_in_parent(temp_file)?;
+        let signature_path = signature_file.path().to_owned();
+        if let Err(err) = downloader
+            .verify_signature(&file_id, &view, signature_path)
+            .await
+        {
+            if matches!(err, CacheError::SignatureInvalid(_)) {
+                metric!(
+                    counter("objects.signature_invalid") += 1,
+                    "source" => source.as_str(),
+                    "object_type" => &object_id.object_type.to_string(),
+                );
+            }
+            return Err(err);
+        }
+    }
+
+    downloader.scan_object(&file_id, temp_file.path()).await?;
+
+    // Sources may serve objects packaged in zip or tar archives, such as zipped dSYMs. Only the
+    // member matching the requested object is kept, and cached in place of the archive.
+    let view = match ArchiveFormat::detect(&view) {
+        Some(format) => {
+            metric!(
+                counter("objects.extracted") += 1,
+                "source" => source.as_str(),
+                "format" => format.name(),
+            );
+            let is_wanted = |data: &[u8]| match Archive::parse(data) {
+                Ok(archive) => archive
+                    .objects()
+                    .filter_map(Result::ok)
+                    .any(|object| object_matches_id(&object, object_id)),
+                Err(_) => false,
+            };
+            let limit = downloader.max_download_size();
+            let extracted = archives::extract_file(format, &view, temp_file, limit, is_wanted)
+                .map_err(|err| {
+                    CacheError::Malformed(format!("invalid {} archive: {err}", format.name()))
+                })?;
+            if !extracted {
+                let details = format!(
+                    "no file in {} archive matches {}",
+                    format.name(),
+                    describe_id(object_id)
+                );
+                return Err(id_mismatch(&source, object_id, details));
+            }
+            ByteView::map_file_ref(temp_file.as_file())?
+        }
+        None => view,
+    };
 
     // Since objects in Sentry (and potentially also other sources) might be
     // multi-arch files (e.g. FatMach), we parse as Archive and try to
     // extract the wanted file.
-    let view = ByteView::map_file_ref(temp_file.as_file())?;
     let archive = match Archive::parse(&view) {
         Ok(archive) => archive,
         Err(e) => return Err(CacheError::Malformed(e.to_string())),
@@ -155,7 +239,9 @@ async fn fetch_object_file(
                 if let Some(Err(err)) = archive.objects().find(|r| r.is_err()) {
                     return Err(CacheError::Malformed(err.to_string()));
                 } else {
-                    return Err(CacheError::NotFound);
+                    let details =
+                        format!("no object in archive matches {}", describe_id(object_id));
+                    return Err(id_mismatch(&source, object_id, details));
                 }
             }
         };
@@ -166,16 +252,126 @@ async fn fetch_object_file(
 
         std::mem::swap(temp_file, &mut dst);
     } else {
-        // Attempt to parse the object to capture errors. The result can be
-        // discarded as the object's data is the entire ByteView.
-        if let Err(err) = archive.object_by_index(0) {
-            return Err(CacheError::Malformed(err.to_string()));
+        // Attempt to parse the object to capture errors. The object's data is the entire
+        // ByteView, so we only need it to verify that we got the file we asked for.
+        let object = match archive.object_by_index(0) {
+            Ok(Some(object)) => object,
+            Ok(None) => return Err(CacheError::Malformed("empty archive".into())),
+            Err(err) => return Err(CacheError::Malformed(err.to_string())),
+        };
+
+        if !object_matches_id(&object, object_id) {
+            let details = format!(
+                "expected {}, found debug id {}",
+                describe_id(object_id),
+                object.debug_id()
+            );
+            return Err(id_mismatch(&source, object_id, details));
+        }
+
+        if let Some(checksum) = &object_id.debug_checksum {
+            if let Err(err) = verify_checksum(&object, &view, checksum) {
+                metric!(
+                    counter("objects.checksum_mismatch") += 1,
+                    "source" => source.as_str(),
+                    "object_type" => &object_id.object_type.to_string(),
+                );
+                return Err(err);
+            }
         }
     };
 
     Ok(())
 }
 
+/// Creates a [`CacheError::IdMismatch`] and counts the mismatch per source.
+fn id_mismatch(source: &SourceId, object_id: &ObjectId, details: String) -> CacheError {
+    metric!(
+        counter("objects.id_mismatch") += 1,
+        "source" => source.as_str(),
+        "object_type" => &object_id.object_type.to_string(),
+    );
+    CacheError::IdMismatch(details)
+}
+
+/// Describes the identifiers of `id` which are used by [`object_matches_id`].
+fn describe_id(id: &ObjectId) -> String {
+    match (&id.debug_id, &id.code_id) {
+        (Some(debug_id), _) => format!("debug id {debug_id}"),
+        (None, Some(code_id)) => format!("code id {code_id}"),
+        (None, None) => "unknown id".into(),
+    }
+}
+
+/// Verifies the contents of the object against the `expected` checksum.
+///
+/// The checksum is given as `<algorithm>:<hex digest>`, and is currently only provided for
+/// Portable PDB files. As defined by the Portable PDB format, it is computed over the whole file
+/// with its PDB ID zeroed out. Other object types and hash algorithms are not verified.
+fn verify_checksum(object: &Object<'_>, data: &[u8], expected: &str) -> CacheEntry {
+    if !matches!(object, Object::PortablePdb(_)) {
+        return Ok(());
+    }
+    let Some(("SHA256" | "sha256", expected)) = expected.split_once(':') else {
+        return Ok(());
+    };
+    let Some(pdb_id) = portable_pdb_id_range(data) else {
+        return Err(CacheError::Malformed("missing Portable PDB ID".into()));
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(&data[..pdb_id.start]);
+    hasher.update([0; 20]);
+    hasher.update(&data[pdb_id.end..]);
+    let actual = format!("{:x}", hasher.finalize());
+
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(CacheError::ChecksumMismatch(format!(
+            "expected SHA256:{expected}, found SHA256:{actual}"
+        )))
+    }
+}
+
+/// Finds the location of the 20-byte PDB ID within a Portable PDB file.
+///
+/// The PDB ID is at the very start of the `#Pdb` metadata stream, which is located by walking the
+/// stream headers that follow the metadata root.
+fn portable_pdb_id_range(data: &[u8]) -> Option<Range<usize>> {
+    let read_u16 = |offset: usize| {
+        Some(u16::from_le_bytes(
+            data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    let read_u32 = |offset: usize| {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    // The metadata root starts with a signature, versions and a reserved field, followed by the
+    // length of the version string, the version string itself, flags, and the number of streams.
+    let version_len = read_u32(12)? as usize;
+    let mut offset = 16 + version_len;
+    let streams = read_u16(offset + 2)?;
+    offset += 4;
+
+    for _ in 0..streams {
+        let stream_offset = read_u32(offset)? as usize;
+        let name = data.get(offset + 8..)?;
+        let name_len = name.iter().position(|b| *b == 0)?;
+        if &name[..name_len] == b"#Pdb" {
+            let range = stream_offset..stream_offset + 20;
+            return data.get(range.clone()).map(|_| range);
+        }
+        // The name is null-terminated and padded to a multiple of 4 bytes.
+        offset += 8 + (name_len + 4) / 4 * 4;
+    }
+
+    None
+}
+
 /// Validates that the object matches expected identifiers.
 fn object_matches_id(object: &Object<'_>, id: &ObjectId) -> bool {
     if let Some(ref debug_id) = id.debug_id {
@@ -208,12 +404,13 @@ impl CacheItemRequest for FetchFileDataRequest {
     const VERSIONS: CacheVersions = OBJECTS_CACHE_VERSIONS;
 
     fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
-        let cache_key = CacheKey::from_scoped_file(&self.0.scope, &self.0.file_source);
+        let cache_key = CacheKey::from_scoped_file(&self.meta.scope, &self.meta.file_source);
         tracing::trace!("Fetching file data for {}", cache_key);
+        self.downloaded.store(true, Ordering::Relaxed);
         let future = fetch_object_file(
-            &self.0.object_id,
-            self.0.file_source.clone(),
-            self.0.download_svc.clone(),
+            &self.meta.object_id,
+            self.meta.file_source.clone(),
+            self.meta.download_svc.clone(),
             temp_file,
         )
         .bind_hub(Hub::current());
@@ -227,11 +424,11 @@ impl CacheItemRequest for FetchFileDataRequest {
     fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
         let object = OwnedObject::parse(data)?;
         let object_handle = ObjectHandle {
-            object_id: self.0.object_id.clone(),
+            object_id: self.meta.object_id.clone(),
             object,
 
-            scope: self.0.scope.clone(),
-            cache_key: CacheKey::from_scoped_file(&self.0.scope, &self.0.file_source),
+            scope: self.meta.scope.clone(),
+            cache_key: CacheKey::from_scoped_file(&self.meta.scope, &self.meta.file_source),
         };
 
         // FIXME(swatinem): This `configure_scope` call happens in a spawned/deduplicated
@@ -241,29 +438,39 @@ impl CacheItemRequest for FetchFileDataRequest {
 
         Ok(Arc::new(object_handle))
     }
+
+    /// Asks the source whether the object file changed since it was downloaded.
+    fn revalidate(&self) -> BoxFuture<'_, bool> {
+        Box::pin(self.meta.download_svc.revalidate(&self.meta.file_source))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
     use std::sync::Arc;
     use std::time::Duration;
 
-    use symbolicator_sources::FileType;
+    use symbolicator_sources::{
+        CommonSourceConfig, DirectoryLayoutType, FileType, FilesystemSourceConfig, SourceConfig,
+    };
 
     use super::*;
     use crate::caching::{Cache, CacheName};
-    use crate::config::{CacheConfig, CacheConfigs, Config};
+    use crate::config::{CacheConfig, CacheConfigs, Config, ReservedIpsPolicy};
     use crate::services::download::DownloadService;
     use crate::services::objects::data_cache::Scope;
     use crate::services::objects::{FindObject, ObjectPurpose, ObjectsActor};
     use crate::test::{self, tempdir};
+    use crate::types::ObjectDownloadInfo;
 
     use symbolic::common::DebugId;
+    use symbolicator_test::read_fixture;
     use tempfile::TempDir;
 
     async fn make_objects_actor(tempdir: &TempDir) -> ObjectsActor {
         let config = Config {
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
             max_download_timeout: Duration::from_millis(100),
             cache_dir: Some(tempdir.path().to_path_buf()),
             ..Default::default()
@@ -495,4 +702,74 @@ mod tests {
         assert_eq!(result, err);
         assert_eq!(hitcounter.accesses(), 0);
     }
+
+    #[tokio::test]
+    async fn test_id_mismatch() {
+        test::setup();
+
+        let symbol_dir = tempdir();
+        let cachedir = tempdir();
+        let objects_actor = make_objects_actor(&cachedir).await;
+
+        // The source serves an object with debug id `2d10c42f-591d-3265-b147-78ba0868073f` at
+        // the path of a different debug id.
+        let requested = "11111111-2222-3333-4444-555555555555";
+        let object_dir = symbol_dir.path().join("11/111111222233334444555555555555");
+        std::fs::create_dir_all(&object_dir).unwrap();
+        std::fs::copy(
+            test::fixture("symbols/2d10c42f-591d-3265-b147-78ba0868073f.dwarf-hidden"),
+            object_dir.join("debuginfo"),
+        )
+        .unwrap();
+
+        let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: symbol_dir.path().to_owned(),
+            index_ttl: None,
+            files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
+        }));
+
+        let find_object = FindObject {
+            filetypes: &[FileType::MachDebug],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: DebugId::from_str(requested).unwrap().into(),
+            sources: Arc::new([source]),
+        };
+        let result = objects_actor.find(find_object).await;
+
+        let err = result.meta.unwrap().handle.unwrap_err();
+        assert!(matches!(err, CacheError::IdMismatch(_)), "{err:?}");
+
+        let [candidate] = result.candidates.0.as_slice() else {
+            panic!("Expected a single candidate: {:?}", result.candidates);
+        };
+        assert!(
+            matches!(candidate.download, ObjectDownloadInfo::Mismatch { .. }),
+            "{:?}",
+            candidate.download
+        );
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let data = read_fixture(
+            "symbols/integration.pdb/0C1033F78632492E91C6C314B72E1920ffffffff/integration.pdb",
+        );
+        let object = Object::parse(&data).unwrap();
+
+        assert_eq!(portable_pdb_id_range(&data), Some(124..144));
+
+        let checksum = "SHA256:f733100c32862eb911c6c314b72e19209d810b666ea92c703106800697230a62";
+        assert_eq!(verify_checksum(&object, &data, checksum), Ok(()));
+
+        let checksum = "SHA256:0000000000000000000000000000000000000000000000000000000000000000";
+        assert!(matches!(
+            verify_checksum(&object, &data, checksum),
+            Err(CacheError::ChecksumMismatch(_))
+        ));
+
+        // Unsupported algorithms are not verified.
+        assert_eq!(verify_checksum(&object, &data, "MD5:00"), Ok(()));
+    }
 }
@@ -94,7 +94,7 @@ impl FetchFileMetaRequest {
 
         let object_handle = self
             .data_cache
-            .compute_memoized(FetchFileDataRequest(self.clone()), cache_key.clone())
+            .compute_memoized(FetchFileDataRequest::new(self.clone()), cache_key.clone())
             .await?;
 
         let object = object_handle.object();
@@ -1,13 +1,18 @@
 use std::collections::BTreeSet;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::future;
 use sentry::{Hub, SentryFutureExt};
+use serde::Deserialize;
 
 use symbolicator_sources::{FileType, ObjectId, RemoteFile, RemoteFileUri, SourceConfig, SourceId};
 
-use crate::caching::{Cache, CacheEntry, CacheError, CacheKey, Cacher, SharedCacheRef};
-use crate::services::download::DownloadService;
+use crate::caching::{
+    Cache, CacheEntry, CacheError, CacheKey, CacheName, Cacher, ObjectIdFilter, SharedCacheRef,
+};
+use crate::services::download::{DownloadService, SourceHealth};
 use crate::types::{AllObjectCandidates, ObjectCandidate, ObjectDownloadInfo, Scope};
 
 use data_cache::FetchFileDataRequest;
@@ -45,7 +50,8 @@ pub struct FindObject {
     pub sources: Arc<[SourceConfig]>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ObjectPurpose {
     Unwind,
     Debug,
@@ -97,17 +103,68 @@ impl ObjectsActor {
     ///
     /// This fetches the requested object, re-downloading it from the source if it is no
     /// longer in the cache.
+    ///
+    /// The size of every object served this way is counted per source and object type, to
+    /// be compared with the bytes actually downloaded from that source. Objects which had to be
+    /// downloaded for this call are tagged as cache misses.
     pub async fn fetch(&self, file_handle: Arc<ObjectMetaHandle>) -> CacheEntry<Arc<ObjectHandle>> {
         let cache_key = CacheKey::from_scoped_file(&file_handle.scope, &file_handle.file_source);
-        let request = FetchFileDataRequest(FetchFileMetaRequest {
+        let request = FetchFileDataRequest::new(FetchFileMetaRequest {
             scope: file_handle.scope.clone(),
             file_source: file_handle.file_source.clone(),
             object_id: file_handle.object_id.clone(),
             data_cache: self.data_cache.clone(),
             download_svc: self.download_svc.clone(),
         });
+        let downloaded = request.downloaded.clone();
+
+        let handle = self.data_cache.compute_memoized(request, cache_key).await?;
+
+        let served_bytes: i64 = handle.data().len().try_into().unwrap_or(i64::MAX);
+        let cache = if downloaded.load(Ordering::Relaxed) {
+            "miss"
+        } else {
+            "hit"
+        };
+        metric!(
+            counter("objects.served.bytes") += served_bytes,
+            "source" => file_handle.file_source.source_id().as_str(),
+            "object_type" => &file_handle.object_id.object_type.to_string(),
+            "cache" => cache,
+        );
+
+        Ok(handle)
+    }
+
+    /// Returns the health of the configured sources.
+    pub fn source_health(&self) -> Vec<SourceHealth> {
+        self.download_svc.source_health()
+    }
+
+    /// Invalidates the negative cache entries of the object matched by `filter`, including the
+    /// cached listings of Sentry sources.
+    ///
+    /// Returns the number of entries removed from each file system cache.
+    pub fn invalidate_negative(
+        &self,
+        filter: &ObjectIdFilter,
+    ) -> std::io::Result<Vec<(CacheName, usize)>> {
+        self.download_svc.invalidate_indexes(filter);
+        Ok(vec![
+            (
+                CacheName::Objects,
+                self.data_cache.invalidate_negative(filter)?,
+            ),
+            (
+                CacheName::ObjectMeta,
+                self.meta_cache.invalidate_negative(filter)?,
+            ),
+        ])
+    }
 
-        self.data_cache.compute_memoized(request, cache_key).await
+    /// Returns how long downloads from the configured sources currently take, if known.
+    pub fn expected_download_latency(&self) -> Option<Duration> {
+        self.download_svc.expected_latency()
     }
 
     /// Fetches matching objects and returns the metadata of the most suitable object.
@@ -285,7 +342,18 @@ fn create_candidate_info(meta_lookup: &FoundMeta) -> ObjectCandidate {
             CacheError::PermissionDenied(msg) => ObjectDownloadInfo::NoPerm {
                 details: msg.clone(),
             },
+            CacheError::RateLimited(_) => ObjectDownloadInfo::RateLimited {
+                details: error.to_string(),
+            },
             CacheError::Malformed(_) => ObjectDownloadInfo::Malformed,
+            CacheError::IdMismatch(_) | CacheError::ChecksumMismatch(_) => {
+                ObjectDownloadInfo::Mismatch {
+                    details: error.to_string(),
+                }
+            }
+            CacheError::SignatureInvalid(msg) => ObjectDownloadInfo::Unverified {
+                details: msg.clone(),
+            },
             err => ObjectDownloadInfo::Error {
                 details: err.to_string(),
             },
@@ -52,6 +52,16 @@ impl PortablePdbCacheActor {
         }
     }
 
+    /// Returns a copy of this actor which does not cache the Portable PDB caches it computes.
+    ///
+    /// See [`Cacher::read_only`].
+    pub fn read_only(&self) -> Self {
+        Self {
+            ppdb_caches: Arc::new(self.ppdb_caches.read_only()),
+            ..self.clone()
+        }
+    }
+
     pub async fn fetch(
         &self,
         request: FetchPortablePdbCache,
@@ -0,0 +1,191 @@
+//! Service for retrieving ProGuard mapping files.
+//!
+//! This service downloads and caches the mapping files which ProGuard and R8 write when
+//! obfuscating JVM code, and parses them into a [`ProguardMapper`] that translates obfuscated
+//! class and method names back to the original ones.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use proguard::ProguardMapping;
+use sentry::{Hub, SentryFutureExt};
+use tempfile::NamedTempFile;
+
+use symbolic::common::{AsSelf, ByteView, DebugId, SelfCell};
+use symbolicator_sources::{FileType, ObjectId, ObjectType, RemoteFile, SourceConfig};
+
+use crate::caching::{
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheKey, CacheVersions, Cacher,
+    SharedCacheRef,
+};
+use crate::services::download::DownloadService;
+use crate::types::Scope;
+use crate::utils::futures::{m, measure};
+
+use super::caches::versions::PROGUARD_CACHE_VERSIONS;
+use super::fetch_file;
+
+/// A parsed ProGuard mapping, along with the mapper built from it.
+struct ProguardInner<'a> {
+    mapper: proguard::ProguardMapper<'a>,
+}
+
+impl<'slf, 'a: 'slf> AsSelf<'slf> for ProguardInner<'a> {
+    type Ref = ProguardInner<'slf>;
+
+    fn as_self(&'slf self) -> &Self::Ref {
+        self
+    }
+}
+
+/// A [`proguard::ProguardMapper`] which owns the mapping file it was built from.
+///
+/// Building the mapper indexes all classes and members of the mapping, so it is built once when
+/// loading the file and shared between requests.
+#[derive(Clone)]
+pub struct ProguardMapper {
+    inner: Arc<SelfCell<ByteView<'static>, ProguardInner<'static>>>,
+}
+
+impl ProguardMapper {
+    /// Builds the mapper for the mapping file in `data`.
+    pub fn new(data: ByteView<'static>) -> Self {
+        let inner = SelfCell::new(data, |data| {
+            let mapping = ProguardMapping::new(unsafe { &*data });
+            ProguardInner {
+                mapper: proguard::ProguardMapper::new(mapping),
+            }
+        });
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Returns the mapper for deobfuscating class names and frames.
+    pub fn get(&self) -> &proguard::ProguardMapper<'_> {
+        &self.inner.get().mapper
+    }
+}
+
+impl std::fmt::Debug for ProguardMapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProguardMapper").finish_non_exhaustive()
+    }
+}
+
+/// The interface to the [`Cacher`] service.
+///
+/// The main work is done by the [`CacheItemRequest`] impl.
+#[derive(Debug, Clone)]
+struct FetchProguardRequest {
+    file_source: RemoteFile,
+    download_svc: Arc<DownloadService>,
+}
+
+impl FetchProguardRequest {
+    /// Downloads the mapping file and saves it to `temp_file`.
+    ///
+    /// Actual implementation of [`FetchProguardRequest::compute`].
+    async fn fetch_mapping(self, temp_file: &mut NamedTempFile) -> CacheEntry {
+        fetch_file(self.download_svc, self.file_source, temp_file).await?;
+
+        let view = ByteView::map_file_ref(temp_file.as_file())?;
+
+        if !ProguardMapping::new(&view).is_valid() {
+            metric!(counter("services.proguard.loaderror") += 1);
+            tracing::debug!("Failed to parse proguard mapping");
+            return Err(CacheError::Malformed(
+                "Failed to parse proguard mapping".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheItemRequest for FetchProguardRequest {
+    type Item = ProguardMapper;
+
+    const VERSIONS: CacheVersions = PROGUARD_CACHE_VERSIONS;
+
+    fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
+        let fut = self
+            .clone()
+            .fetch_mapping(temp_file)
+            .bind_hub(Hub::current());
+
+        let timeout = Duration::from_secs(1200);
+        let future = tokio::time::timeout(timeout, fut);
+        let future = measure("proguard", m::timed_result, future);
+        Box::pin(async move { future.await.map_err(|_| CacheError::Timeout(timeout))? })
+    }
+
+    fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
+        Ok(ProguardMapper::new(data))
+    }
+
+    fn weight(item: &Self::Item) -> u32 {
+        item.inner.owner().len().try_into().unwrap_or(u32::MAX)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProguardService {
+    cache: Arc<Cacher<FetchProguardRequest>>,
+    download_svc: Arc<DownloadService>,
+}
+
+impl ProguardService {
+    pub fn new(
+        proguard_cache: Cache,
+        shared_cache: SharedCacheRef,
+        download_svc: Arc<DownloadService>,
+    ) -> Self {
+        Self {
+            cache: Arc::new(Cacher::new(proguard_cache, shared_cache)),
+            download_svc,
+        }
+    }
+
+    /// Returns the [`ProguardMapper`] for the mapping file with the given `uuid`.
+    ///
+    /// The first of the `sources` which has a valid mapping file wins. If none of them has one,
+    /// the error of the last attempt is returned.
+    pub async fn fetch_mapper(
+        &self,
+        uuid: DebugId,
+        scope: &Scope,
+        sources: &[SourceConfig],
+    ) -> CacheEntry<ProguardMapper> {
+        let identifier = ObjectId {
+            debug_id: Some(uuid),
+            object_type: ObjectType::Unknown,
+            ..Default::default()
+        };
+        let files = self
+            .download_svc
+            .list_files(sources, &[FileType::Proguard], &identifier)
+            .await;
+
+        let mut result = Err(CacheError::NotFound);
+        for file_source in files {
+            let scope = if file_source.is_public() {
+                Scope::Global
+            } else {
+                scope.clone()
+            };
+            let cache_key = CacheKey::from_scoped_file(&scope, &file_source);
+            let request = FetchProguardRequest {
+                file_source,
+                download_svc: self.download_svc.clone(),
+            };
+            match self.cache.compute_memoized(request, cache_key).await {
+                Ok(mapper) => return Ok(mapper),
+                Err(CacheError::NotFound) => (),
+                Err(error) => result = Err(error),
+            }
+        }
+        result
+    }
+}
@@ -13,6 +13,7 @@ use crate::types::{
     CompleteObjectInfo, CompletedSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace,
     Scope, SystemInfo,
 };
+use crate::utils::addr::KernelSpace;
 use crate::utils::hex::HexValue;
 
 use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
@@ -71,15 +72,6 @@ impl SymbolicationActor {
             });
         }
 
-        let request = SymbolicateStacktraces {
-            modules,
-            scope,
-            sources,
-            origin: StacktraceOrigin::AppleCrashReport,
-            signal: None,
-            stacktraces,
-        };
-
         let mut system_info = SystemInfo {
             os_name: metadata.remove("OS Version").unwrap_or_default(),
             device_model: metadata.remove("Hardware Model").unwrap_or_default(),
@@ -99,6 +91,16 @@ impl SymbolicationActor {
             system_info.os_name = "macOS".to_string();
         }
 
+        let request = SymbolicateStacktraces {
+            modules,
+            scope,
+            sources,
+            origin: StacktraceOrigin::AppleCrashReport,
+            signal: None,
+            stacktraces,
+            kernel_space: KernelSpace::new(&system_info.os_name, system_info.cpu_arch),
+        };
+
         // https://developer.apple.com/library/archive/technotes/tn2151/_index.html
         let crash_reason = metadata.remove("Exception Type");
         let crash_details = report
@@ -175,6 +177,7 @@ fn map_apple_binary_image(image: apple_crash_report_parser::BinaryImage) -> Comp
         debug_file: Some(image.path),
         debug_checksum: None,
         image_addr: HexValue(image.addr.0),
+        slide: None,
         image_size: match image.size {
             0 => None,
             size => Some(size),
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::config::{FeatureFlagConfig, RolloutKey};
+use crate::types::Scope;
+
+use super::SymbolicateStacktraces;
+
+/// Feature flags which gradually roll out changes to the symbolication pipeline.
+///
+/// Flags are decided by hashing the flag name together with the scope or the request, so the
+/// decision is stable across instances and restarts, and independent between flags.
+#[derive(Clone, Debug, Default)]
+pub struct FeatureFlags {
+    flags: Arc<BTreeMap<String, FeatureFlagConfig>>,
+}
+
+impl FeatureFlags {
+    /// Creates the feature flags from their configuration.
+    pub fn new(flags: BTreeMap<String, FeatureFlagConfig>) -> Self {
+        Self {
+            flags: Arc::new(flags),
+        }
+    }
+
+    /// Returns whether `flag` is configured at all.
+    pub fn contains(&self, flag: &str) -> bool {
+        self.flags.contains_key(flag)
+    }
+
+    /// Returns whether `flag` is enabled for a request of `scope`.
+    ///
+    /// The `request_key` is only computed for flags which are rolled out per request. Unknown
+    /// flags are disabled.
+    pub fn is_enabled<K>(&self, flag: &str, scope: &Scope, request_key: K) -> bool
+    where
+        K: FnOnce() -> Vec<u8>,
+    {
+        let Some(config) = self.flags.get(flag) else {
+            return false;
+        };
+        if config.scopes.iter().any(|s| s.as_str() == scope.as_ref()) {
+            return true;
+        }
+
+        let percentage = match config.key {
+            RolloutKey::Scope => rollout_percentage(flag, scope.as_ref().as_bytes()),
+            RolloutKey::Request => rollout_percentage(flag, &request_key()),
+        };
+        percentage < config.rollout
+    }
+}
+
+/// Maps the `key` to a stable percentage in `[0, 100)` for the given `flag`.
+fn rollout_percentage(flag: &str, key: &[u8]) -> f64 {
+    let mut hasher = Sha256::new();
+    hasher.update(flag.as_bytes());
+    hasher.update([0]);
+    hasher.update(key);
+    let digest = hasher.finalize();
+
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    // Only use 53 bits, which can be represented exactly as `f64`.
+    let value = u64::from_be_bytes(bytes) >> 11;
+    value as f64 / (1u64 << 53) as f64 * 100.0
+}
+
+/// Hashes the stacktraces and modules of a request, to decide flags rolled out per request.
+pub(super) fn request_key(request: &SymbolicateStacktraces) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, &(&request.stacktraces, &request.modules)).ok();
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(rollout: f64, key: RolloutKey, scopes: &[&str]) -> FeatureFlags {
+        let config = FeatureFlagConfig {
+            rollout,
+            key,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+        FeatureFlags::new(BTreeMap::from([("new-walker".to_owned(), config)]))
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        let scope = Scope::Scoped("1".into());
+        let no_key = || -> Vec<u8> { unreachable!("the request key is not needed") };
+
+        assert!(!FeatureFlags::default().is_enabled("new-walker", &scope, no_key));
+        assert!(!flags(0.0, RolloutKey::Scope, &[]).is_enabled("new-walker", &scope, no_key));
+        assert!(flags(100.0, RolloutKey::Scope, &[]).is_enabled("new-walker", &scope, no_key));
+        assert!(flags(0.0, RolloutKey::Scope, &["1"]).is_enabled("new-walker", &scope, no_key));
+        assert!(!flags(100.0, RolloutKey::Scope, &[]).is_enabled("other", &scope, no_key));
+    }
+
+    #[test]
+    fn test_feature_flags_rollout() {
+        let flags = flags(10.0, RolloutKey::Scope, &[]);
+        let enabled = (0..10_000)
+            .filter(|i| {
+                let scope = Scope::Scoped(i.to_string().into());
+                flags.is_enabled("new-walker", &scope, Vec::new)
+            })
+            .count();
+        assert!((800..1200).contains(&enabled), "{enabled}");
+    }
+
+    #[test]
+    fn test_feature_flags_per_request() {
+        let flags = flags(50.0, RolloutKey::Request, &[]);
+        let decisions: Vec<_> = (0u32..100)
+            .map(|i| flags.is_enabled("new-walker", &Scope::Global, || i.to_be_bytes().to_vec()))
+            .collect();
+
+        // Decisions are stable for the same request, but differ between requests.
+        let first = flags.is_enabled("new-walker", &Scope::Global, || 0u32.to_be_bytes().to_vec());
+        assert_eq!(first, decisions[0]);
+        assert!(decisions.contains(&true));
+        assert!(decisions.contains(&false));
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use symbolicator_sources::{FileType, SourceConfig};
+
+use crate::services::module_lookup::object_id_from_object_info;
+use crate::services::objects::{FindObject, ObjectPurpose};
+use crate::types::{CompletedSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace, Scope};
+use crate::utils::addr::{AddrMode, KernelSpace};
+use crate::utils::hex::HexValue;
+
+use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+
+impl SymbolicationActor {
+    /// Symbolicates raw instruction addresses of a bare-metal firmware image.
+    ///
+    /// Firmware is not dynamically loaded and runs at the addresses it was linked at, so there is
+    /// no module list, and the `image_addr` of the `firmware` is ignored. Instead, the image is
+    /// placed at the load address of its object file, moved by `load_offset` if it was relocated,
+    /// for instance by a bootloader. Each list of `addresses` becomes a stack trace, starting with
+    /// the innermost frame.
+    pub async fn symbolicate_firmware(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        mut firmware: RawObjectInfo,
+        load_offset: u64,
+        addresses: Vec<Vec<HexValue>>,
+    ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        let link_addr = self
+            .firmware_link_address(&scope, &sources, &firmware)
+            .await
+            .unwrap_or_default();
+        firmware.image_addr = HexValue(link_addr.wrapping_add(load_offset));
+
+        // Images at `0` do not support absolute addresses, but this is where many
+        // microcontrollers map their flash. Relative addresses are the same in that case.
+        let addr_mode = match firmware.image_addr.0 {
+            0 => AddrMode::Rel(0),
+            _ => AddrMode::Abs,
+        };
+
+        let stacktraces = addresses
+            .into_iter()
+            .map(|addresses| RawStacktrace {
+                frames: addresses
+                    .into_iter()
+                    .map(|instruction_addr| RawFrame {
+                        addr_mode,
+                        instruction_addr,
+                        ..RawFrame::default()
+                    })
+                    .collect(),
+                ..RawStacktrace::default()
+            })
+            .collect();
+
+        let request = SymbolicateStacktraces {
+            scope,
+            signal: None,
+            sources,
+            origin: StacktraceOrigin::Firmware,
+            stacktraces,
+            modules: vec![firmware.into()],
+            kernel_space: KernelSpace::Unknown,
+        };
+        self.symbolicate(request).await
+    }
+
+    /// Returns the address the `firmware` was linked at, if its object file can be found.
+    async fn firmware_link_address(
+        &self,
+        scope: &Scope,
+        sources: &Arc<[SourceConfig]>,
+        firmware: &RawObjectInfo,
+    ) -> Option<u64> {
+        let found = self
+            .objects
+            .find(FindObject {
+                filetypes: FileType::from_object_type(firmware.ty),
+                purpose: ObjectPurpose::Debug,
+                scope: scope.clone(),
+                identifier: object_id_from_object_info(firmware),
+                sources: sources.clone(),
+            })
+            .await;
+
+        let meta = found.meta?.handle.ok()?;
+        let object = self.objects.fetch(meta).await.ok()?;
+        Some(object.object().load_address())
+    }
+}
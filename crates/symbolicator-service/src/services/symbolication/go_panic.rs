@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+
+use anyhow::Context;
+use regex::Regex;
+
+use symbolic::common::Language;
+use symbolicator_sources::SourceConfig;
+
+use crate::types::{
+    CompletedSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace, Scope, Signal,
+};
+use crate::utils::addr::KernelSpace;
+use crate::utils::hex::HexValue;
+
+use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+
+impl SymbolicationActor {
+    /// Symbolicates the goroutine stack traces of a Go panic or `fatal error` dump.
+    ///
+    /// Go does not print any information about the loaded binaries, so these have to be provided
+    /// as `modules`. Instruction addresses are only part of the dump if it was produced with
+    /// `GOTRACEBACK=system` or higher. Frames without one keep the function, file and line
+    /// printed by the Go runtime.
+    pub async fn process_go_panic(
+        &self,
+        scope: Scope,
+        mut report: File,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+    ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        let mut text = String::new();
+        report
+            .read_to_string(&mut text)
+            .context("failed to read go panic")?;
+        let report = parse_go_panic(&text);
+
+        let request = SymbolicateStacktraces {
+            scope,
+            signal: report.signal,
+            sources,
+            origin: StacktraceOrigin::GoPanic,
+            stacktraces: report.stacktraces,
+            modules: modules.into_iter().map(From::from).collect(),
+            kernel_space: KernelSpace::Unknown,
+        };
+        let mut response = self.symbolicate(request).await?;
+
+        response.crashed = Some(report.crash_reason.is_some());
+        response.crash_reason = report.crash_reason;
+        response.crash_details = report.crash_details;
+        Ok(response)
+    }
+}
+
+lazy_static::lazy_static! {
+    /// The header of a goroutine, like `goroutine 1 [running]:`.
+    ///
+    /// Newer Go versions print additional information before the state with `GOTRACEBACK=system`.
+    static ref GOROUTINE_REGEX: Regex = Regex::new(r"^goroutine (?P<id>\d+)(?: [^\[]*)? ?\[(?P<state>[^\]]*)\]:$").unwrap();
+    /// The location of a frame, like `\t/src/main.go:12 +0x1d fp=0xc00 sp=0xc00 pc=0x48f2a6`.
+    static ref LOCATION_REGEX: Regex = Regex::new(r"^\t(?P<path>.+):(?P<line>\d+)(?: \+0x[0-9a-f]+)?(?:.* pc=0x(?P<pc>[0-9a-f]+))?").unwrap();
+    /// The signal line, like `[signal SIGSEGV: segmentation violation code=0x1 addr=0x0 pc=0x48f2a6]`.
+    static ref SIGNAL_REGEX: Regex = Regex::new(r"^\[signal (?P<name>SIG[A-Z]+)").unwrap();
+}
+
+/// The parts of a Go panic relevant for symbolication.
+#[derive(Debug, Default)]
+struct GoPanic {
+    signal: Option<Signal>,
+    crash_reason: Option<String>,
+    crash_details: Option<String>,
+    stacktraces: Vec<RawStacktrace>,
+}
+
+fn parse_go_panic(text: &str) -> GoPanic {
+    let mut report = GoPanic::default();
+
+    for line in text.lines() {
+        let line = line.trim_end();
+
+        if let Some(captures) = GOROUTINE_REGEX.captures(line) {
+            // The goroutine which panicked is always printed first.
+            let is_requesting = report.stacktraces.is_empty() && report.crash_reason.is_some();
+            report.stacktraces.push(RawStacktrace {
+                thread_id: captures["id"].parse().ok(),
+                thread_name: Some(captures["state"].to_owned()),
+                is_requesting: Some(is_requesting),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(stacktrace) = report.stacktraces.last_mut() else {
+            if report.crash_reason.is_none()
+                && (line.starts_with("panic: ") || line.starts_with("fatal error: "))
+            {
+                report.crash_reason = Some(line.to_owned());
+            } else if let Some(captures) = SIGNAL_REGEX.captures(line) {
+                report.signal = signal_from_name(&captures["name"]);
+                report.crash_details = Some(line.trim_matches(['[', ']']).to_owned());
+            }
+            continue;
+        };
+
+        if let Some(captures) = LOCATION_REGEX.captures(line) {
+            // The location belongs to the function printed on the previous line.
+            if let Some(frame) = stacktrace.frames.last_mut() {
+                if frame.abs_path.is_none() {
+                    frame.abs_path = Some(captures["path"].to_owned());
+                    frame.lineno = captures["line"].parse().ok();
+                    if let Some(pc) = captures.name("pc") {
+                        frame.instruction_addr =
+                            HexValue(u64::from_str_radix(pc.as_str(), 16).unwrap_or_default());
+                    }
+                }
+            }
+        } else if !line.is_empty() && !line.starts_with(['\t', ' ', '.']) {
+            stacktrace.frames.push(RawFrame {
+                function: Some(parse_function_name(line).to_owned()),
+                lang: Some(Language::Go),
+                ..Default::default()
+            });
+        }
+    }
+
+    // Every frame is followed by its location, so anything else is trailing output like
+    // `exit status 2`, or a placeholder for an unavailable stack.
+    for stacktrace in &mut report.stacktraces {
+        stacktrace.frames.retain(|frame| frame.abs_path.is_some());
+    }
+
+    report
+}
+
+/// Extracts the function name from a function line, stripping the arguments as well as the
+/// `created by` prefix.
+fn parse_function_name(line: &str) -> &str {
+    if let Some(creator) = line.strip_prefix("created by ") {
+        return creator
+            .split_once(" in goroutine ")
+            .map_or(creator, |(function, _)| function);
+    }
+
+    // Method receivers also contain parentheses, like `main.(*T).foo(...)`, so only strip the
+    // last pair.
+    match line.rfind('(') {
+        Some(index) if line.ends_with(')') && index > 0 => &line[..index],
+        _ => line,
+    }
+}
+
+/// Maps the name of a signal to its number, for the signals that are the same on all platforms.
+fn signal_from_name(name: &str) -> Option<Signal> {
+    let number = match name {
+        "SIGILL" => 4,
+        "SIGTRAP" => 5,
+        "SIGABRT" => 6,
+        "SIGFPE" => 8,
+        "SIGSEGV" => 11,
+        _ => return None,
+    };
+    Some(Signal(number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANIC: &str = "\
+panic: runtime error: invalid memory address or nil pointer dereference
+[signal SIGSEGV: segmentation violation code=0x1 addr=0x0 pc=0x48f2a6]
+
+goroutine 1 gp=0xc000002380 m=0 mp=0x5a1e40 [running]:
+panic({0x4a1b20?, 0x59b6a0?})
+\t/usr/lib/go/src/runtime/panic.go:779 +0x158 fp=0xc000066e80 sp=0xc000066dd0 pc=0x432eb8
+main.(*Server).handle(0x0, {0x4c4b2e, 0x5})
+\t/src/app/main.go:12 +0x26 fp=0xc000066ee8 sp=0xc000066e80 pc=0x48f2a6
+main.main()
+\t/src/app/main.go:20 +0x3a fp=0xc000066f50 sp=0xc000066ee8 pc=0x48f2fa
+
+goroutine 18 gp=0xc000082380 m=nil [chan receive]:
+main.worker(...)
+\t/src/app/worker.go:8
+created by main.main in goroutine 1
+\t/src/app/main.go:18 +0x25 fp=0xc000066fe0 sp=0xc000066fb8 pc=0x48f2d5
+exit status 2
+";
+
+    #[test]
+    fn test_parse_go_panic() {
+        let report = parse_go_panic(PANIC);
+
+        assert_eq!(report.signal, Some(Signal(11)));
+        assert_eq!(
+            report.crash_reason.as_deref(),
+            Some("panic: runtime error: invalid memory address or nil pointer dereference")
+        );
+        assert_eq!(
+            report.crash_details.as_deref(),
+            Some("signal SIGSEGV: segmentation violation code=0x1 addr=0x0 pc=0x48f2a6")
+        );
+        assert_eq!(report.stacktraces.len(), 2);
+
+        let crashed = &report.stacktraces[0];
+        assert_eq!(crashed.thread_id, Some(1));
+        assert_eq!(crashed.thread_name.as_deref(), Some("running"));
+        assert_eq!(crashed.is_requesting, Some(true));
+        let frames: Vec<_> = crashed
+            .frames
+            .iter()
+            .map(|frame| {
+                (
+                    frame.function.as_deref().unwrap(),
+                    frame.abs_path.as_deref().unwrap(),
+                    frame.lineno.unwrap(),
+                    frame.instruction_addr.0,
+                )
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("panic", "/usr/lib/go/src/runtime/panic.go", 779, 0x432eb8),
+                ("main.(*Server).handle", "/src/app/main.go", 12, 0x48f2a6),
+                ("main.main", "/src/app/main.go", 20, 0x48f2fa),
+            ]
+        );
+
+        let worker = &report.stacktraces[1];
+        assert_eq!(worker.thread_id, Some(18));
+        assert_eq!(worker.thread_name.as_deref(), Some("chan receive"));
+        assert_eq!(worker.is_requesting, Some(false));
+        // The trailing `exit status 2` is not a frame.
+        assert_eq!(worker.frames.len(), 2);
+        // Inlined frames are printed without an address.
+        assert_eq!(worker.frames[0].function.as_deref(), Some("main.worker"));
+        assert_eq!(worker.frames[0].lineno, Some(8));
+        assert_eq!(worker.frames[0].instruction_addr.0, 0);
+        assert_eq!(worker.frames[1].function.as_deref(), Some("main.main"));
+        assert_eq!(worker.frames[1].instruction_addr.0, 0x48f2d5);
+    }
+
+    #[test]
+    fn test_parse_function_name() {
+        assert_eq!(parse_function_name("main.main()"), "main.main");
+        assert_eq!(
+            parse_function_name("main.(*T).foo(0x0, {0x4c4b2e, 0x5})"),
+            "main.(*T).foo"
+        );
+        assert_eq!(parse_function_name("main.worker(...)"), "main.worker");
+        assert_eq!(
+            parse_function_name("created by main.main in goroutine 1"),
+            "main.main"
+        );
+        assert_eq!(parse_function_name("created by main.init.0"), "main.init.0");
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use symbolicator_sources::{ObjectType, SentrySourceConfig, SourceConfig};
+
+use crate::types::{
+    CompleteObjectInfo, CompleteStacktrace, CompletedHybridSymbolicationResponse, HybridFrame,
+    HybridStacktrace, JsFrameStatus, JsStacktrace, RawObjectInfo, RawStacktrace, Scope, Signal,
+    SymbolicatedHybridFrame, SymbolicatedHybridStacktrace, SymbolicatedJsFrame,
+    SymbolicatedJsStacktrace,
+};
+use crate::utils::addr::{AddrMode, KernelSpace};
+
+use super::{
+    StacktraceOrigin, SymbolicateJsStacktraces, SymbolicateStacktraces, SymbolicationActor,
+};
+
+/// A request for symbolication of stack traces mixing native and JavaScript frames.
+#[derive(Debug, Clone)]
+pub struct SymbolicateHybridStacktraces {
+    /// The scope of this request which determines access to cached files.
+    pub scope: Scope,
+
+    /// The signal thrown on certain operating systems.
+    pub signal: Option<Signal>,
+
+    /// A list of external sources to load native debug files.
+    pub sources: Arc<[SourceConfig]>,
+
+    /// The Sentry source to load source maps and minified files from.
+    ///
+    /// If this is `None`, JavaScript frames are not symbolicated.
+    pub source: Option<Arc<SentrySourceConfig>>,
+
+    /// The distribution of the release the source maps belong to.
+    pub dist: Option<String>,
+
+    /// Whether files missing from the `source` may be scraped from the web.
+    pub allow_scraping: bool,
+
+    /// A list of threads containing stack traces.
+    pub stacktraces: Vec<HybridStacktrace>,
+
+    /// A list of native images and source map modules that were loaded into the process.
+    ///
+    /// Relative addresses of native frames refer to indices into this list.
+    pub modules: Vec<RawObjectInfo>,
+}
+
+impl SymbolicationActor {
+    /// Symbolicates stack traces which mix native and JavaScript frames.
+    ///
+    /// Native frames are symbolicated just like in [`symbolicate`](Self::symbolicate), and
+    /// JavaScript frames like in [`symbolicate_js`](Self::symbolicate_js). The results are
+    /// merged back into the original frame order.
+    #[tracing::instrument(skip_all)]
+    pub async fn symbolicate_hybrid(
+        &self,
+        request: SymbolicateHybridStacktraces,
+    ) -> Result<CompletedHybridSymbolicationResponse, anyhow::Error> {
+        let SymbolicateHybridStacktraces {
+            scope,
+            signal,
+            sources,
+            source,
+            dist,
+            allow_scraping,
+            stacktraces,
+            modules,
+        } = request;
+
+        let split = SplitModules::new(&modules);
+        let (native_stacktraces, js_stacktraces) = split_stacktraces(&stacktraces, &split);
+
+        let native_request = SymbolicateStacktraces {
+            scope: scope.clone(),
+            signal,
+            sources,
+            origin: StacktraceOrigin::Hybrid,
+            stacktraces: native_stacktraces,
+            modules: split.native_modules(&modules),
+            kernel_space: KernelSpace::Unknown,
+        };
+        let native_response = self.symbolicate(native_request).await?;
+
+        let has_js_frames = js_stacktraces.iter().any(|st| !st.frames.is_empty());
+        let js_stacktraces = match source {
+            Some(source) if has_js_frames => {
+                let js_request = SymbolicateJsStacktraces {
+                    scope,
+                    source,
+                    dist,
+                    stacktraces: js_stacktraces,
+                    modules: modules.clone(),
+                    allow_scraping,
+                };
+                self.symbolicate_js(js_request).await?.stacktraces
+            }
+            _ => js_stacktraces
+                .into_iter()
+                .map(|st| SymbolicatedJsStacktrace {
+                    frames: st
+                        .frames
+                        .into_iter()
+                        .map(|raw| SymbolicatedJsFrame {
+                            status: JsFrameStatus::MissingSourcemap,
+                            raw,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+
+        let stacktraces = stacktraces
+            .into_iter()
+            .zip(native_response.stacktraces)
+            .zip(js_stacktraces)
+            .map(|((hybrid, native), js)| merge_stacktrace(hybrid, native, js))
+            .collect();
+
+        Ok(CompletedHybridSymbolicationResponse {
+            signal,
+            partial: native_response.partial,
+            stacktraces,
+            modules: split.merge_modules(modules, native_response.modules),
+        })
+    }
+}
+
+/// The partition of the requested modules into native and source map modules.
+struct SplitModules {
+    /// For each requested module, its index in the native module list, or `None` for source maps.
+    native_indices: Vec<Option<usize>>,
+}
+
+impl SplitModules {
+    fn new(modules: &[RawObjectInfo]) -> Self {
+        let mut next_index = 0;
+        let native_indices = modules
+            .iter()
+            .map(|module| {
+                (module.ty != ObjectType::SourceMap).then(|| {
+                    next_index += 1;
+                    next_index - 1
+                })
+            })
+            .collect();
+        Self { native_indices }
+    }
+
+    /// Returns the native modules, which are the ones passed to native symbolication.
+    fn native_modules(&self, modules: &[RawObjectInfo]) -> Vec<CompleteObjectInfo> {
+        modules
+            .iter()
+            .zip(&self.native_indices)
+            .filter(|(_, index)| index.is_some())
+            .map(|(module, _)| module.clone().into())
+            .collect()
+    }
+
+    /// Translates the addressing mode of a native frame to refer to the native module list.
+    fn native_addr_mode(&self, addr_mode: AddrMode) -> AddrMode {
+        match addr_mode {
+            AddrMode::Abs => AddrMode::Abs,
+            // Relative addresses pointing to a source map or out of bounds stay out of bounds.
+            AddrMode::Rel(index) => AddrMode::Rel(
+                self.native_indices
+                    .get(index)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(usize::MAX),
+            ),
+        }
+    }
+
+    /// Puts the symbolicated native modules back into the order of the request.
+    fn merge_modules(
+        &self,
+        modules: Vec<RawObjectInfo>,
+        native_modules: Vec<CompleteObjectInfo>,
+    ) -> Vec<CompleteObjectInfo> {
+        let mut native_modules = native_modules.into_iter();
+        modules
+            .into_iter()
+            .zip(&self.native_indices)
+            .map(|(module, index)| match index {
+                Some(_) => native_modules.next().unwrap_or_else(|| module.into()),
+                None => module.into(),
+            })
+            .collect()
+    }
+}
+
+/// Splits each hybrid stack trace into a native and a JavaScript stack trace.
+fn split_stacktraces(
+    stacktraces: &[HybridStacktrace],
+    modules: &SplitModules,
+) -> (Vec<RawStacktrace>, Vec<JsStacktrace>) {
+    stacktraces
+        .iter()
+        .map(|stacktrace| {
+            let mut native = RawStacktrace {
+                thread_id: stacktrace.thread_id,
+                thread_name: stacktrace.thread_name.clone(),
+                is_requesting: stacktrace.is_requesting,
+                registers: stacktrace.registers.clone(),
+                frames: vec![],
+            };
+            let mut js = JsStacktrace { frames: vec![] };
+
+            for frame in &stacktrace.frames {
+                match frame {
+                    HybridFrame::Native(frame) => {
+                        let mut frame = frame.clone();
+                        frame.addr_mode = modules.native_addr_mode(frame.addr_mode);
+                        native.frames.push(frame);
+                    }
+                    HybridFrame::Javascript(frame) => js.frames.push(frame.clone()),
+                }
+            }
+
+            (native, js)
+        })
+        .unzip()
+}
+
+/// Merges the symbolicated native and JavaScript frames back into the order of the `hybrid`
+/// stack trace they were split from.
+fn merge_stacktrace(
+    hybrid: HybridStacktrace,
+    native: CompleteStacktrace,
+    js: SymbolicatedJsStacktrace,
+) -> SymbolicatedHybridStacktrace {
+    let mut native_frames = native.frames.into_iter().peekable();
+    let mut js_frames = js.frames.into_iter();
+    let mut native_index = 0;
+
+    let mut frames = Vec::with_capacity(hybrid.frames.len());
+    for (index, frame) in hybrid.frames.into_iter().enumerate() {
+        match frame {
+            HybridFrame::Native(raw) => {
+                // A single native frame can expand to multiple inline frames, all of which share
+                // the index of the raw frame they originate from.
+                while let Some(mut symbolicated) = native_frames
+                    .next_if(|symbolicated| symbolicated.original_index == Some(native_index))
+                {
+                    symbolicated.original_index = Some(index);
+                    // Restore the addressing mode of the request.
+                    symbolicated.raw.addr_mode = raw.addr_mode;
+                    frames.push(SymbolicatedHybridFrame::Native(symbolicated));
+                }
+                native_index += 1;
+            }
+            HybridFrame::Javascript(_) => {
+                if let Some(symbolicated) = js_frames.next() {
+                    frames.push(SymbolicatedHybridFrame::Javascript(symbolicated));
+                }
+            }
+        }
+    }
+
+    SymbolicatedHybridStacktrace {
+        thread_id: hybrid.thread_id,
+        thread_name: hybrid.thread_name,
+        is_requesting: hybrid.is_requesting,
+        frames,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{FrameStatus, JsFrame, RawFrame, SymbolicatedFrame};
+    use crate::utils::hex::HexValue;
+
+    use super::*;
+
+    fn module(ty: ObjectType) -> RawObjectInfo {
+        RawObjectInfo {
+            ty,
+            code_id: None,
+            code_file: None,
+            debug_id: None,
+            debug_file: None,
+            debug_checksum: None,
+            image_addr: HexValue(0),
+            slide: None,
+            image_size: None,
+        }
+    }
+
+    fn native_frame(addr: u64, addr_mode: AddrMode) -> HybridFrame {
+        HybridFrame::Native(RawFrame {
+            instruction_addr: HexValue(addr),
+            addr_mode,
+            ..Default::default()
+        })
+    }
+
+    fn js_frame(abs_path: &str) -> HybridFrame {
+        HybridFrame::Javascript(JsFrame {
+            abs_path: abs_path.into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let modules = SplitModules::new(&[
+            module(ObjectType::SourceMap),
+            module(ObjectType::Elf),
+            module(ObjectType::SourceMap),
+            module(ObjectType::Elf),
+        ]);
+
+        let hybrid = HybridStacktrace {
+            frames: vec![
+                js_frame("app:///a.js"),
+                native_frame(0x10, AddrMode::Rel(3)),
+                js_frame("app:///b.js"),
+                native_frame(0x20, AddrMode::Rel(0)),
+                native_frame(0x30, AddrMode::Abs),
+            ],
+            ..Default::default()
+        };
+
+        let (native, js) = split_stacktraces(std::slice::from_ref(&hybrid), &modules);
+        let addr_modes: Vec<_> = native[0].frames.iter().map(|f| f.addr_mode).collect();
+        assert_eq!(
+            addr_modes,
+            [AddrMode::Rel(1), AddrMode::Rel(usize::MAX), AddrMode::Abs]
+        );
+        assert_eq!(js[0].frames.len(), 2);
+
+        // The first native frame expands to two inline frames.
+        let native_frames = [0, 0, 1, 2]
+            .into_iter()
+            .map(|original_index| SymbolicatedFrame {
+                status: FrameStatus::Symbolicated,
+                original_index: Some(original_index),
+                raw: native[0].frames[original_index].clone(),
+            });
+        let native = CompleteStacktrace {
+            frames: native_frames.collect(),
+            ..Default::default()
+        };
+        let js = SymbolicatedJsStacktrace {
+            frames: js[0]
+                .frames
+                .iter()
+                .map(|raw| SymbolicatedJsFrame {
+                    status: JsFrameStatus::Symbolicated,
+                    raw: raw.clone(),
+                })
+                .collect(),
+        };
+
+        let merged = merge_stacktrace(hybrid, native, js);
+        let frames: Vec<_> = merged
+            .frames
+            .iter()
+            .map(|frame| match frame {
+                SymbolicatedHybridFrame::Native(frame) => {
+                    format!("{} {}", frame.original_index.unwrap(), frame.raw.addr_mode)
+                }
+                SymbolicatedHybridFrame::Javascript(frame) => frame.raw.abs_path.clone(),
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                "app:///a.js",
+                "1 rel:3",
+                "1 rel:3",
+                "app:///b.js",
+                "3 rel:0",
+                "4 abs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hybrid_frame_platform() {
+        let json = r#"[
+            {"platform": "native", "instruction_addr": "0x1000"},
+            {"platform": "javascript", "abs_path": "app:///main.js", "lineno": 1, "colno": 2}
+        ]"#;
+        let frames: Vec<HybridFrame> = serde_json::from_str(json).unwrap();
+        assert!(
+            matches!(frames[0], HybridFrame::Native(ref frame) if frame.instruction_addr.0 == 0x1000)
+        );
+        assert!(matches!(frames[1], HybridFrame::Javascript(ref frame) if frame.colno == Some(2)));
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Context;
+use symbolicator_sources::ObjectId;
+
+use crate::caching::{CacheName, ObjectIdFilter};
+
+use super::SymbolicationActor;
+
+impl SymbolicationActor {
+    /// Invalidates the negative cache entries of the given object, so that the next lookup
+    /// fetches it from the sources again.
+    ///
+    /// This covers downloaded objects and their metadata, SymCaches, CFI caches, the cached file
+    /// listings of Sentry sources, and cached responses which list the object as a module.
+    /// Positive entries are left untouched.
+    ///
+    /// Returns the number of entries removed from each file system cache.
+    pub async fn invalidate_negative_caches(
+        &self,
+        object_id: &ObjectId,
+    ) -> Result<Vec<(CacheName, usize)>, anyhow::Error> {
+        let filter = ObjectIdFilter::new(object_id);
+        anyhow::ensure!(!filter.is_empty(), "a debug id or code id is required");
+
+        self.responses.invalidate_object(&filter);
+
+        let objects = self.objects.clone();
+        let symcaches = self.symcaches.clone();
+        let cficaches = self.cficaches.clone();
+        let removed = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+            let mut removed = objects.invalidate_negative(&filter)?;
+            removed.push((
+                CacheName::Symcaches,
+                symcaches.invalidate_negative(&filter)?,
+            ));
+            removed.push((
+                CacheName::Cficaches,
+                cficaches.invalidate_negative(&filter)?,
+            ));
+            Ok(removed)
+        })
+        .await?
+        .context("failed to invalidate cache files")?;
+
+        for (name, count) in &removed {
+            metric!(
+                counter("caches.invalidated") += *count as i64,
+                "cache" => name.as_ref(),
+            );
+        }
+
+        Ok(removed)
+    }
+}
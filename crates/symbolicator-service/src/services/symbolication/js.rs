@@ -17,9 +17,11 @@ use super::SymbolicationActor;
 pub struct SymbolicateJsStacktraces {
     pub scope: Scope,
     pub source: Arc<SentrySourceConfig>,
+    /// The distribution of the release the source maps belong to.
     pub dist: Option<String>,
     pub stacktraces: Vec<JsStacktrace>,
     pub modules: Vec<RawObjectInfo>,
+    /// Whether files missing from the `source` may be scraped from the web.
     pub allow_scraping: bool,
 }
 
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use futures::future;
+use proguard::StackFrame;
+use symbolicator_sources::SourceConfig;
+
+use crate::caching::CacheError;
+use crate::services::proguard::ProguardMapper;
+use crate::types::{
+    CompletedJvmSymbolicationResponse, JvmException, JvmFrame, JvmModule, JvmStacktrace,
+    ProguardError, ProguardErrorKind, Scope,
+};
+
+use super::SymbolicationActor;
+
+#[derive(Debug, Clone)]
+pub struct SymbolicateJvmStacktraces {
+    pub scope: Scope,
+    pub sources: Arc<[SourceConfig]>,
+    pub exceptions: Vec<JvmException>,
+    pub stacktraces: Vec<JvmStacktrace>,
+    pub modules: Vec<JvmModule>,
+}
+
+impl SymbolicationActor {
+    /// Deobfuscates the class and method names of JVM exceptions and stack traces, using the
+    /// ProGuard mapping files listed in the `modules` of the request.
+    #[tracing::instrument(skip_all)]
+    pub async fn symbolicate_jvm(
+        &self,
+        request: SymbolicateJvmStacktraces,
+    ) -> Result<CompletedJvmSymbolicationResponse, anyhow::Error> {
+        let SymbolicateJvmStacktraces {
+            scope,
+            sources,
+            exceptions,
+            stacktraces,
+            modules,
+        } = request;
+
+        let fetches = modules
+            .iter()
+            .map(|module| self.proguard.fetch_mapper(module.uuid, &scope, &sources));
+        let results = future::join_all(fetches).await;
+
+        let mut mappers = Vec::with_capacity(modules.len());
+        let mut errors = Vec::new();
+        for (module, result) in modules.iter().zip(results) {
+            let kind = match result {
+                Ok(mapper) => {
+                    mappers.push(mapper);
+                    continue;
+                }
+                Err(CacheError::NotFound) => ProguardErrorKind::Missing,
+                Err(error) => {
+                    let error: &dyn std::error::Error = &error;
+                    tracing::debug!(error, uuid = %module.uuid, "Failed to fetch proguard mapping");
+                    ProguardErrorKind::Invalid
+                }
+            };
+            errors.push(ProguardError {
+                uuid: module.uuid,
+                kind,
+            });
+        }
+
+        let exceptions = exceptions
+            .into_iter()
+            .map(|exception| remap_exception(&mappers, exception))
+            .collect();
+
+        let stacktraces = stacktraces
+            .into_iter()
+            .map(|stacktrace| JvmStacktrace {
+                frames: stacktrace
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, frame)| remap_frame(&mappers, frame, index))
+                    .collect(),
+            })
+            .collect();
+
+        metric!(counter("symbolication.jvm.mapping_errors") += errors.len() as i64);
+
+        Ok(CompletedJvmSymbolicationResponse {
+            exceptions,
+            stacktraces,
+            errors,
+        })
+    }
+}
+
+/// Replaces the class of an exception with its original name, if any mapper knows it.
+fn remap_exception(mappers: &[ProguardMapper], exception: JvmException) -> JvmException {
+    let class = format!("{}.{}", exception.module, exception.ty);
+    let Some(original) = mappers
+        .iter()
+        .find_map(|mapper| mapper.get().remap_class(&class))
+    else {
+        return exception;
+    };
+
+    match original.rsplit_once('.') {
+        Some((module, ty)) => JvmException {
+            ty: ty.to_owned(),
+            module: module.to_owned(),
+        },
+        None => JvmException {
+            ty: original.to_owned(),
+            module: String::new(),
+        },
+    }
+}
+
+/// Remaps a frame with the first mapper that knows its method.
+///
+/// Methods which were inlined into the frame are expanded into frames of their own, innermost
+/// first. If no mapper knows the method, only its class is remapped, and failing that the frame
+/// is returned unchanged.
+fn remap_frame(mappers: &[ProguardMapper], frame: &JvmFrame, index: usize) -> Vec<JvmFrame> {
+    let line = frame.lineno.unwrap_or(0) as usize;
+    let stack_frame = match frame.filename {
+        Some(ref filename) => StackFrame::with_file(&frame.module, &frame.function, line, filename),
+        None => StackFrame::new(&frame.module, &frame.function, line),
+    };
+
+    for mapper in mappers {
+        let remapped: Vec<_> = mapper
+            .get()
+            .remap_frame(&stack_frame)
+            .map(|remapped| JvmFrame {
+                function: remapped.method().to_owned(),
+                filename: remapped.file().map(ToOwned::to_owned),
+                module: remapped.class().to_owned(),
+                lineno: (remapped.line() > 0).then(|| remapped.line() as u32),
+                index,
+            })
+            .collect();
+        if !remapped.is_empty() {
+            return remapped;
+        }
+    }
+
+    let module = mappers
+        .iter()
+        .find_map(|mapper| mapper.get().remap_class(&frame.module));
+    vec![JvmFrame {
+        module: module.map_or_else(|| frame.module.clone(), ToOwned::to_owned),
+        index,
+        ..frame.clone()
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use symbolic::common::ByteView;
+
+    const MAPPING: &[u8] = br#"
+io.sentry.sample.MainActivity -> a.b:
+    void onClick() -> a
+    4:4:void bar():54:54 -> b
+    4:4:void foo():44 -> b
+    4:4:void onClick():38 -> b
+io.sentry.sample.Crash -> c:
+"#;
+
+    fn mapper() -> ProguardMapper {
+        ProguardMapper::new(ByteView::from_slice(MAPPING))
+    }
+
+    #[test]
+    fn test_remap_inlined_frame() {
+        let frame = JvmFrame {
+            function: "b".into(),
+            module: "a.b".into(),
+            lineno: Some(4),
+            ..Default::default()
+        };
+
+        let frames = remap_frame(&[mapper()], &frame, 3);
+        let functions: Vec<_> = frames.iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(functions, ["bar", "foo", "onClick"]);
+        assert!(frames
+            .iter()
+            .all(|f| f.module == "io.sentry.sample.MainActivity" && f.index == 3));
+        assert_eq!(frames[0].lineno, Some(54));
+    }
+
+    #[test]
+    fn test_remap_unknown_frame() {
+        let frame = JvmFrame {
+            function: "x".into(),
+            module: "c".into(),
+            lineno: Some(1),
+            ..Default::default()
+        };
+
+        let frames = remap_frame(&[mapper()], &frame, 0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].module, "io.sentry.sample.Crash");
+        assert_eq!(frames[0].function, "x");
+    }
+
+    #[test]
+    fn test_remap_exception() {
+        let exception = JvmException {
+            ty: "b".into(),
+            module: "a".into(),
+        };
+
+        let remapped = remap_exception(&[mapper()], exception);
+        assert_eq!(remapped.ty, "MainActivity");
+        assert_eq!(remapped.module, "io.sentry.sample");
+    }
+}
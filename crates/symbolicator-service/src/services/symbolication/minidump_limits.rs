@@ -0,0 +1,304 @@
+use std::ops::Range;
+
+use minidump::{MinidumpMemoryList, MinidumpModuleList, MinidumpThreadList, Module};
+use symbolic::common::ByteView;
+use thiserror::Error;
+
+use crate::config::{MinidumpConfig, MinidumpLimits};
+use crate::types::Truncation;
+
+type Minidump = minidump::Minidump<'static, ByteView<'static>>;
+
+/// A minidump which exceeds one of the [`MinidumpLimits`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MinidumpLimitError {
+    #[error("minidump has {count} streams, exceeding the limit of {limit}")]
+    TooManyStreams { count: usize, limit: usize },
+    #[error("minidump has {count} threads, exceeding the limit of {limit}")]
+    TooManyThreads { count: usize, limit: usize },
+    #[error("minidump has {count} memory regions, exceeding the limit of {limit}")]
+    TooManyMemoryRegions { count: usize, limit: usize },
+    #[error("minidump has a string of {length} bytes, exceeding the limit of {limit}")]
+    StringTooLong { length: usize, limit: usize },
+}
+
+impl MinidumpLimitError {
+    /// Returns the name of the exceeded limit, for use in metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TooManyStreams { .. } => "streams",
+            Self::TooManyThreads { .. } => "threads",
+            Self::TooManyMemoryRegions { .. } => "memory_regions",
+            Self::StringTooLong { .. } => "string_length",
+        }
+    }
+}
+
+impl MinidumpLimits {
+    /// Checks the `minidump` against these limits.
+    ///
+    /// Streams which are missing or cannot be read are skipped here, and handled by the
+    /// stackwalker instead.
+    pub fn check(&self, minidump: &Minidump) -> Result<(), MinidumpLimitError> {
+        let count = minidump.all_streams().count();
+        if count > self.max_streams {
+            return Err(MinidumpLimitError::TooManyStreams {
+                count,
+                limit: self.max_streams,
+            });
+        }
+
+        if let Ok(threads) = minidump.get_stream::<MinidumpThreadList>() {
+            let count = threads.threads.len();
+            if count > self.max_threads {
+                return Err(MinidumpLimitError::TooManyThreads {
+                    count,
+                    limit: self.max_threads,
+                });
+            }
+        }
+
+        if let Ok(memory) = minidump.get_stream::<MinidumpMemoryList>() {
+            let count = memory.iter().count();
+            if count > self.max_memory_regions {
+                return Err(MinidumpLimitError::TooManyMemoryRegions {
+                    count,
+                    limit: self.max_memory_regions,
+                });
+            }
+        }
+
+        if let Ok(modules) = minidump.get_stream::<MinidumpModuleList>() {
+            for module in modules.iter() {
+                let debug_file = module.debug_file().unwrap_or_default();
+                let length = module.code_file().len().max(debug_file.len());
+                if length > self.max_string_length {
+                    return Err(MinidumpLimitError::StringTooLong {
+                        length,
+                        limit: self.max_string_length,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const EXCEPTION_STREAM: u32 = 6;
+
+/// The size of an entry in the stream directory.
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+/// The size of a `MINIDUMP_THREAD`.
+const THREAD_SIZE: usize = 48;
+/// The size of a `MINIDUMP_MODULE`.
+const MODULE_SIZE: usize = 108;
+/// The size of a `MINIDUMP_MEMORY_DESCRIPTOR`.
+const MEMORY_DESCRIPTOR_SIZE: usize = 16;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// A stream consisting of a count followed by a list of fixed-size entries.
+#[derive(Debug, Clone, Copy)]
+struct ListStream {
+    /// The offset of the stream's entry in the stream directory.
+    directory_offset: usize,
+    /// The offset of the stream's data.
+    rva: usize,
+    /// The size of the count, including padding some writers insert before the entries.
+    header_size: usize,
+    /// The size of the entries.
+    entry_size: usize,
+    /// The number of entries.
+    count: usize,
+}
+
+impl ListStream {
+    /// Reads the list stream whose directory entry is at `directory_offset`.
+    fn read(data: &[u8], directory_offset: usize, entry_size: usize) -> Option<Self> {
+        let data_size = read_u32(data, directory_offset + 4)? as usize;
+        let rva = read_u32(data, directory_offset + 8)? as usize;
+        data.get(rva..rva.checked_add(data_size)?)?;
+
+        let count = read_u32(data, rva)? as usize;
+        let entries_size = count.checked_mul(entry_size)?;
+        let header_size = [4, 8]
+            .into_iter()
+            .find(|header_size| header_size + entries_size == data_size)?;
+
+        Some(Self {
+            directory_offset,
+            rva,
+            header_size,
+            entry_size,
+            count,
+        })
+    }
+
+    /// Returns the range of the entry at `index`.
+    fn entry(&self, index: usize) -> Range<usize> {
+        let start = self.rva + self.header_size + index * self.entry_size;
+        start..start + self.entry_size
+    }
+
+    /// Truncates the list to at most `limit` entries, and returns the number of dropped entries.
+    fn truncate(&self, data: &mut [u8], limit: usize) -> usize {
+        if self.count <= limit {
+            return 0;
+        }
+
+        write_u32(data, self.rva, limit as u32);
+        let data_size = self.header_size + limit * self.entry_size;
+        write_u32(data, self.directory_offset + 4, data_size as u32);
+
+        self.count - limit
+    }
+}
+
+/// Truncates the thread, module and memory lists of a minidump to the configured caps.
+///
+/// The lists are truncated in place, by lowering their counts. If a thread is truncated, the
+/// crashing thread is moved into the last thread which is kept.
+///
+/// Returns `None` if the minidump does not exceed any of the caps, or cannot be truncated, for
+/// example because it is malformed or big-endian.
+pub fn truncate_minidump(data: &[u8], config: &MinidumpConfig) -> Option<(Vec<u8>, Truncation)> {
+    if data.get(..4)? != b"MDMP" {
+        return None;
+    }
+
+    let stream_count = read_u32(data, 8)? as usize;
+    let directory_rva = read_u32(data, 12)? as usize;
+
+    let mut threads = None;
+    let mut modules = None;
+    let mut memory_regions = None;
+    let mut crashing_thread = None;
+    for index in 0..stream_count {
+        let offset = directory_rva + index * DIRECTORY_ENTRY_SIZE;
+        match read_u32(data, offset)? {
+            THREAD_LIST_STREAM => threads = ListStream::read(data, offset, THREAD_SIZE),
+            MODULE_LIST_STREAM => modules = ListStream::read(data, offset, MODULE_SIZE),
+            MEMORY_LIST_STREAM => {
+                memory_regions = ListStream::read(data, offset, MEMORY_DESCRIPTOR_SIZE)
+            }
+            EXCEPTION_STREAM => {
+                let rva = read_u32(data, offset + 8)? as usize;
+                crashing_thread = read_u32(data, rva);
+            }
+            _ => {}
+        }
+    }
+
+    let caps = [
+        (threads, config.max_threads),
+        (modules, config.max_modules),
+        (memory_regions, config.max_memory_regions),
+    ];
+    let exceeded = caps.iter().any(|(stream, cap)| match (stream, cap) {
+        (Some(stream), Some(cap)) => stream.count > *cap,
+        _ => false,
+    });
+    if !exceeded {
+        return None;
+    }
+
+    let mut data = data.to_vec();
+    let mut truncation = Truncation::default();
+
+    if let (Some(threads), Some(cap)) = (threads, config.max_threads) {
+        // The thread id is the first field of a thread.
+        let crashing_index = crashing_thread.and_then(|thread_id| {
+            (cap..threads.count)
+                .find(|&index| read_u32(&data, threads.entry(index).start) == Some(thread_id))
+        });
+        if let (Some(index), Some(last)) = (crashing_index, cap.checked_sub(1)) {
+            data.copy_within(threads.entry(index), threads.entry(last).start);
+        }
+        truncation.threads = threads.truncate(&mut data, cap);
+    }
+    if let (Some(modules), Some(cap)) = (modules, config.max_modules) {
+        truncation.modules = modules.truncate(&mut data, cap);
+    }
+    if let (Some(memory_regions), Some(cap)) = (memory_regions, config.max_memory_regions) {
+        truncation.memory_regions = memory_regions.truncate(&mut data, cap);
+    }
+
+    Some((data, truncation))
+}
+
+#[cfg(test)]
+mod tests {
+    use minidump::MinidumpException;
+
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn test_minidump_limits() {
+        let bv = ByteView::open(test::fixture("windows.dmp")).unwrap();
+        let minidump = Minidump::read(bv).unwrap();
+
+        assert_eq!(MinidumpLimits::default().check(&minidump), Ok(()));
+
+        let limits = MinidumpLimits {
+            max_threads: 1,
+            ..MinidumpLimits::default()
+        };
+        let err = limits.check(&minidump).unwrap_err();
+        assert!(matches!(
+            err,
+            MinidumpLimitError::TooManyThreads { limit: 1, .. }
+        ));
+        assert_eq!(err.name(), "threads");
+
+        let limits = MinidumpLimits {
+            max_string_length: 8,
+            ..MinidumpLimits::default()
+        };
+        assert!(matches!(
+            limits.check(&minidump),
+            Err(MinidumpLimitError::StringTooLong { limit: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncate_minidump() {
+        let data = std::fs::read(test::fixture("windows.dmp")).unwrap();
+        let original = Minidump::read(ByteView::from_vec(data.clone())).unwrap();
+        let original_threads = original.get_stream::<MinidumpThreadList>().unwrap();
+        let crashing_thread = original
+            .get_stream::<MinidumpException>()
+            .unwrap()
+            .get_crashing_thread_id();
+
+        assert!(truncate_minidump(&data, &MinidumpConfig::default()).is_none());
+
+        let config = MinidumpConfig {
+            max_threads: Some(1),
+            max_modules: Some(2),
+            ..MinidumpConfig::default()
+        };
+        let (truncated_data, truncation) = truncate_minidump(&data, &config).unwrap();
+        assert_eq!(truncation.threads, original_threads.threads.len() - 1);
+        assert!(truncation.modules > 0);
+        assert_eq!(truncation.memory_regions, 0);
+
+        let truncated = Minidump::read(ByteView::from_vec(truncated_data)).unwrap();
+        let threads = truncated.get_stream::<MinidumpThreadList>().unwrap();
+        assert_eq!(threads.threads.len(), 1);
+        assert_eq!(threads.threads[0].raw.thread_id, crashing_thread);
+        let modules = truncated.get_stream::<MinidumpModuleList>().unwrap();
+        assert_eq!(modules.iter().count(), 2);
+    }
+}
@@ -0,0 +1,405 @@
+//! Spooling of minidumps which are uploaded as a stream of chunks.
+//!
+//! The minidump is written to a file while it arrives, without holding all of it in memory. As
+//! soon as its system info and module list have been received, the unwind information of the
+//! listed modules is fetched in the background. For large minidumps, most of it is then ready
+//! by the time the upload completes and stackwalking starts.
+//!
+//! Fetching ahead is cancelled if the upload fails, or if the request is rejected once the upload
+//! completes, see [`SpooledMinidump`].
+
+use std::io;
+use std::sync::Arc;
+
+use futures::future::{AbortHandle, Abortable};
+use futures::{Stream, StreamExt};
+use minidump::format::{MINIDUMP_SIGNATURE, MINIDUMP_STREAM_TYPE};
+use minidump::{MinidumpModuleList, MinidumpSystemInfo, Module};
+use sentry::{Hub, SentryFutureExt};
+use thiserror::Error;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use symbolicator_sources::{ObjectId, ObjectType, SourceConfig};
+
+use crate::services::cficaches::FetchCfiCache;
+use crate::types::Scope;
+
+use super::process_minidump::{object_id_from_minidump_module, object_type_from_minidump_os};
+use super::SymbolicationActor;
+
+/// The number of bytes at the start of a minidump which are kept in memory to find its modules.
+///
+/// Writers usually put the module list after the thread stacks, but before the bulk of the
+/// memory. Minidumps whose module list lies further back are spooled without fetching ahead.
+/// This is lowered to the `max_size` of minidumps if that is smaller, see [`max_modules_prefix`].
+const MAX_MODULES_PREFIX: usize = 32 * 1024 * 1024;
+
+/// The maximum number of modules of a minidump for which unwind information is fetched ahead.
+///
+/// Further modules are left to stackwalking, which only fetches those that it needs.
+const MAX_PREFETCHED_MODULES: usize = 256;
+
+/// The size of an entry in the stream directory of a minidump.
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+
+/// The size of an entry in the module list of a minidump.
+const MODULE_SIZE: usize = 108;
+
+const SYSTEM_INFO_STREAM: u32 = MINIDUMP_STREAM_TYPE::SystemInfoStream as u32;
+const MODULE_LIST_STREAM: u32 = MINIDUMP_STREAM_TYPE::ModuleListStream as u32;
+
+/// An error while receiving a minidump upload.
+#[derive(Debug, Error)]
+pub enum MinidumpUploadError {
+    #[error("minidump exceeds the limit of {0} bytes")]
+    TooLarge(u64),
+    #[error("failed to receive minidump")]
+    Stream(#[source] anyhow::Error),
+    #[error("failed to write minidump")]
+    Io(#[from] io::Error),
+}
+
+/// A minidump which has been written to a file by [`SymbolicationActor::spool_minidump`].
+///
+/// If unwind information is fetched ahead for its modules, fetching is cancelled when this is
+/// dropped, unless [`keep_prefetching`](Self::keep_prefetching) is called once the request has
+/// been admitted.
+#[derive(Debug, Default)]
+pub struct SpooledMinidump {
+    size: u64,
+    prefetch: Option<AbortHandle>,
+}
+
+impl SpooledMinidump {
+    /// Returns the size of the minidump in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Lets fetching unwind information continue, as the request for the minidump was admitted.
+    pub fn keep_prefetching(mut self) {
+        self.prefetch = None;
+    }
+}
+
+impl Drop for SpooledMinidump {
+    fn drop(&mut self) {
+        if let Some(prefetch) = self.prefetch.take() {
+            metric!(counter("minidump.upload.prefetch_cancelled") += 1);
+            prefetch.abort();
+        }
+    }
+}
+
+/// Why the modules of a minidump cannot be read from a prefix of it.
+enum Unreadable {
+    /// More of the minidump needs to be received first.
+    Incomplete,
+    /// This is not a minidump, or it lacks its system info or module list.
+    Missing,
+}
+
+/// Reads the locations of the system info and module list from a prefix of a minidump.
+struct PrefixReader<'a> {
+    prefix: &'a [u8],
+    /// The length of the prefix needed for everything that has been read so far.
+    required: usize,
+}
+
+impl PrefixReader<'_> {
+    /// Requires the prefix to extend up to `end`.
+    fn require(&mut self, end: usize) -> Result<(), Unreadable> {
+        self.required = self.required.max(end);
+        if end <= self.prefix.len() {
+            Ok(())
+        } else {
+            Err(Unreadable::Incomplete)
+        }
+    }
+
+    /// Reads a little-endian `u32` at `offset`.
+    fn u32(&mut self, offset: usize) -> Result<usize, Unreadable> {
+        self.require(offset + 4)?;
+        let bytes = &self.prefix[offset..offset + 4];
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    /// Requires the record described by the location at `offset`, if there is one.
+    fn record(&mut self, offset: usize) -> Result<(), Unreadable> {
+        let size = self.u32(offset)?;
+        let rva = self.u32(offset + 4)?;
+        if size > 0 {
+            self.require(rva + size)?;
+        }
+        Ok(())
+    }
+
+    /// Requires the length-prefixed string at `rva`, if there is one.
+    fn string(&mut self, rva: usize) -> Result<(), Unreadable> {
+        if rva > 0 {
+            let size = self.u32(rva)?;
+            self.require(rva + 4 + size)?;
+        }
+        Ok(())
+    }
+
+    /// Requires the system info and the module list, including the records they reference.
+    fn modules(&mut self) -> Result<(), Unreadable> {
+        if self.u32(0)? != MINIDUMP_SIGNATURE as usize {
+            return Err(Unreadable::Missing);
+        }
+        let stream_count = self.u32(8)?;
+        let directory = self.u32(12)?;
+        self.require(directory + stream_count * DIRECTORY_ENTRY_SIZE)?;
+
+        let mut system_info = None;
+        let mut module_list = None;
+        for index in 0..stream_count {
+            let entry = directory + index * DIRECTORY_ENTRY_SIZE;
+            let location = (self.u32(entry + 4)?, self.u32(entry + 8)?);
+            match self.u32(entry)? as u32 {
+                SYSTEM_INFO_STREAM => system_info = Some(location),
+                MODULE_LIST_STREAM => module_list = Some(location),
+                _ => {}
+            }
+        }
+        let (Some((info_size, info_rva)), Some((list_size, list_rva))) = (system_info, module_list)
+        else {
+            return Err(Unreadable::Missing);
+        };
+
+        self.require(info_rva + info_size)?;
+        let csd_version = self.u32(info_rva + 24)?;
+        self.string(csd_version)?;
+
+        self.require(list_rva + list_size)?;
+        let module_count = self.u32(list_rva)?;
+        // Some writers pad the number of modules to 8 bytes.
+        let mut module = if list_size == 8 + module_count * MODULE_SIZE {
+            list_rva + 8
+        } else {
+            list_rva + 4
+        };
+        for _ in 0..module_count {
+            let name = self.u32(module + 20)?;
+            self.string(name)?;
+            self.record(module + 76)?;
+            self.record(module + 84)?;
+            module += MODULE_SIZE;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns how many bytes at the start of a minidump are needed to read its modules.
+///
+/// This only looks at the locations of the system info and module list, and of the records they
+/// reference. As long as some of them have not been received, the returned length only covers
+/// what is known so far, and grows as more of the minidump is received. Once the prefix is as long
+/// as the returned length, [`modules_from_prefix`] can read the modules.
+///
+/// Returns `None` if this is not a minidump, or it lacks its system info or module list.
+fn modules_len(prefix: &[u8]) -> Option<usize> {
+    let mut reader = PrefixReader {
+        prefix,
+        required: 0,
+    };
+    match reader.modules() {
+        Ok(()) | Err(Unreadable::Incomplete) => Some(reader.required),
+        Err(Unreadable::Missing) => None,
+    }
+}
+
+/// Returns the number of bytes at the start of a minidump which are kept to find its modules.
+///
+/// Minidumps exceeding `max_size` are rejected, so a prefix longer than that is never needed.
+fn max_modules_prefix(max_size: Option<u64>) -> usize {
+    let max_size = max_size.map_or(usize::MAX, |size| size.try_into().unwrap_or(usize::MAX));
+    MAX_MODULES_PREFIX.min(max_size)
+}
+
+/// Returns the modules listed in the start of a minidump, once both its system info and module
+/// list have been received.
+fn modules_from_prefix(prefix: &[u8]) -> Option<(ObjectType, Vec<ObjectId>)> {
+    let minidump = minidump::Minidump::read(prefix).ok()?;
+    let system_info = minidump.get_stream::<MinidumpSystemInfo>().ok()?;
+    let module_list = minidump.get_stream::<MinidumpModuleList>().ok()?;
+
+    let ty = object_type_from_minidump_os(system_info.os);
+    let modules = module_list
+        .iter()
+        // Modules without a debug ID are mapped files rather than code.
+        .filter(|module| module.debug_identifier().is_some())
+        .map(|module| object_id_from_minidump_module(ty, module))
+        .collect();
+    Some((ty, modules))
+}
+
+impl SymbolicationActor {
+    /// Writes a minidump which arrives as a `stream` of chunks to `file`.
+    ///
+    /// If `sources` are given, unwind information for the modules of the minidump is fetched from
+    /// them as soon as the module list has been received. The minidump is only parsed once, when
+    /// its system info, its module list and the records they reference are complete. Fails once
+    /// the minidump exceeds the configured `max_size`, which cancels fetching ahead. The cursor of
+    /// the `file` is back at its start afterwards.
+    pub async fn spool_minidump<S, B, E>(
+        &self,
+        scope: &Scope,
+        sources: Option<Arc<[SourceConfig]>>,
+        stream: S,
+        file: &mut File,
+    ) -> Result<SpooledMinidump, MinidumpUploadError>
+    where
+        S: Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<anyhow::Error>,
+    {
+        futures::pin_mut!(stream);
+        let mut spooled = SpooledMinidump::default();
+        // The start of the minidump, as long as its modules are still to be fetched.
+        let mut prefix = sources.is_some().then(Vec::new);
+        // The length of the prefix needed to read the modules, as far as it is known.
+        let mut required = 0;
+        let max_prefix = max_modules_prefix(self.minidump.max_size);
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|err| MinidumpUploadError::Stream(err.into()))?;
+            let chunk = chunk.as_ref();
+
+            spooled.size += chunk.len() as u64;
+            if let Some(max_size) = self.minidump.max_size {
+                if spooled.size > max_size {
+                    metric!(counter("minidump.upload.too_large") += 1);
+                    return Err(MinidumpUploadError::TooLarge(max_size));
+                }
+            }
+            file.write_all(chunk).await?;
+
+            let Some(ref mut data) = prefix else {
+                continue;
+            };
+            if data.len() + chunk.len() > max_prefix {
+                prefix = None;
+                continue;
+            }
+            data.extend_from_slice(chunk);
+
+            if data.len() < required {
+                continue;
+            }
+            match modules_len(data) {
+                Some(len) if len <= data.len() => {}
+                // More of the minidump is needed to read its modules.
+                Some(len) if len <= max_prefix => {
+                    required = len;
+                    continue;
+                }
+                _ => {
+                    prefix = None;
+                    continue;
+                }
+            }
+
+            if let (Some((object_type, modules)), Some(ref sources)) =
+                (modules_from_prefix(data), &sources)
+            {
+                spooled.prefetch =
+                    Some(self.prefetch_unwind_info(scope, sources, object_type, modules));
+            }
+            prefix = None;
+        }
+
+        file.flush().await?;
+        file.rewind().await?;
+        metric!(time_raw("minidump.upload.streamed_size") = spooled.size);
+        Ok(spooled)
+    }
+
+    /// Fetches the unwind information of the given `modules` in the background.
+    ///
+    /// The fetched caches are kept in memory, where stackwalking picks them up. Fetching stops
+    /// when the returned handle is aborted.
+    fn prefetch_unwind_info(
+        &self,
+        scope: &Scope,
+        sources: &Arc<[SourceConfig]>,
+        object_type: ObjectType,
+        mut modules: Vec<ObjectId>,
+    ) -> AbortHandle {
+        tracing::debug!("Fetching unwind info for {} modules ahead", modules.len());
+        metric!(time_raw("minidump.upload.prefetched_modules") = modules.len() as u64);
+        if modules.len() > MAX_PREFETCHED_MODULES {
+            metric!(counter("minidump.upload.prefetch_truncated") += 1);
+            modules.truncate(MAX_PREFETCHED_MODULES);
+        }
+
+        let fetches = modules.into_iter().map(|identifier| {
+            let cficaches = self.cficaches.clone();
+            let request = FetchCfiCache {
+                object_type,
+                identifier,
+                sources: sources.clone(),
+                scope: scope.clone(),
+            };
+            async move { cficaches.fetch(request).await }
+        });
+        let (abort, registration) = AbortHandle::new_pair();
+        let prefetch = Abortable::new(futures::future::join_all(fetches), registration);
+        tokio::spawn(prefetch.bind_hub(Hub::new_from_top(Hub::current())));
+        abort
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use symbolicator_test::read_fixture;
+
+    #[test]
+    fn test_modules_from_prefix() {
+        let minidump = read_fixture("windows.dmp");
+
+        let (object_type, modules) = modules_from_prefix(&minidump).unwrap();
+        assert_eq!(object_type, ObjectType::Pe);
+        assert!(!modules.is_empty());
+        assert!(modules.iter().all(|module| module.debug_id.is_some()));
+
+        // The module list is not complete within the first few bytes.
+        assert!(modules_from_prefix(&minidump[..1024]).is_none());
+    }
+
+    #[test]
+    fn test_max_modules_prefix() {
+        assert_eq!(max_modules_prefix(None), MAX_MODULES_PREFIX);
+        assert_eq!(max_modules_prefix(Some(u64::MAX)), MAX_MODULES_PREFIX);
+        assert_eq!(max_modules_prefix(Some(1024)), 1024);
+    }
+
+    #[test]
+    fn test_modules_len() {
+        let minidump = read_fixture("windows.dmp");
+
+        // The required length grows as more of the minidump is received.
+        let mut received = 4;
+        loop {
+            let required = modules_len(&minidump[..received]).unwrap();
+            if required <= received {
+                break;
+            }
+            received = required;
+        }
+        assert_eq!(modules_len(&minidump), Some(received));
+
+        let (_, modules) = modules_from_prefix(&minidump[..received]).unwrap();
+        assert_eq!(
+            modules.len(),
+            modules_from_prefix(&minidump).unwrap().1.len()
+        );
+
+        assert_eq!(modules_len(b"MZ\x90\x00 this is no minidump"), None);
+    }
+}
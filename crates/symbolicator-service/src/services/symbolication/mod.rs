@@ -1,30 +1,70 @@
 use std::sync::Arc;
+use std::time::Instant;
 
-use symbolic::common::{split_path, DebugId, InstructionInfo, Language, Name};
+use symbolic::common::{
+    split_path, CpuFamily, DebugId, InstructionInfo, Language, Name, NameMangling,
+};
 use symbolic::demangle::{Demangle, DemangleOptions};
 use symbolic::ppdb::PortablePdbCache;
 use symbolic::symcache::SymCache;
 use symbolicator_sources::{ObjectType, SourceConfig};
 
-use crate::caching::{Cache, CacheError};
+use crate::caching::{Cache, CacheError, CacheReport, CacheStatsRegistry};
+use crate::config::{
+    Config, DemangleLanguageConfig, DemanglingConfig, MinidumpConfig, PathMappingConfig,
+    SourceLinkConfig,
+};
 use crate::services::cficaches::CfiCacheActor;
 use crate::services::module_lookup::{CacheFileEntry, CacheLookupResult, ModuleLookup};
 use crate::services::objects::ObjectsActor;
 use crate::services::ppdb_caches::PortablePdbCacheActor;
+use crate::services::proguard::ProguardService;
 use crate::services::sourcemap::SourceMapService;
 use crate::services::symcaches::SymCacheActor;
 use crate::types::{
     CompleteObjectInfo, CompleteStacktrace, CompletedSymbolicationResponse, FrameStatus,
-    FrameTrust, ObjectFileStatus, RawFrame, RawStacktrace, Registers, Scope, Signal,
-    SymbolicatedFrame,
+    FrameTrust, InAppRule, ObjectFileStatus, PackageRule, PartialReason, RawFrame, RawStacktrace,
+    Registers, Scope, Signal, SymbolicatedFrame, SymbolicationOptions, ThumbBit,
 };
+use crate::utils::addr::KernelSpace;
 use crate::utils::hex::HexValue;
 
 mod apple;
+mod feature_flags;
+mod firmware;
+mod go_panic;
+mod hybrid;
+mod invalidate;
 mod js;
+mod jvm;
+mod minidump_limits;
+mod minidump_upload;
+mod path_mappings;
+mod prefetch;
 mod process_minidump;
-
+mod profile;
+mod profile_chunk;
+mod progress;
+mod response_cache;
+mod shadow;
+mod source_links;
+mod text;
+mod trace;
+
+pub use feature_flags::FeatureFlags;
+pub use hybrid::SymbolicateHybridStacktraces;
 pub use js::SymbolicateJsStacktraces;
+pub use jvm::SymbolicateJvmStacktraces;
+pub use minidump_upload::{MinidumpUploadError, SpooledMinidump};
+use path_mappings::PathMappings;
+pub use prefetch::PrefetchDifs;
+pub use profile::ProfileFormat;
+pub use profile_chunk::SymbolicateProfileChunk;
+pub use progress::{ProgressTracker, SymbolicationPhase, SymbolicationProgress};
+use response_cache::ResponseCache;
+pub use shadow::{frame_differences, SHADOW_FLAG};
+use source_links::SourceLinks;
+pub use trace::{TraceEvent, TraceEventKind, TraceRecorder};
 
 /// Whether a frame's instruction address needs to be "adjusted" by subtracting a word.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -82,9 +122,25 @@ pub struct SymbolicationActor {
     ppdb_caches: PortablePdbCacheActor,
     diagnostics_cache: Cache,
     sourcemaps: SourceMapService,
+    proguard: ProguardService,
+    cache_stats: CacheStatsRegistry,
+    responses: ResponseCache,
+    deadline: Option<Instant>,
+    progress: ProgressTracker,
+    trace: TraceRecorder,
+    options: SymbolicationOptions,
+    demangling: DemanglingConfig,
+    source_links: Arc<[SourceLinkConfig]>,
+    path_mappings: Arc<[PathMappingConfig]>,
+    in_app_rules: Arc<[InAppRule]>,
+    package_rules: Arc<[PackageRule]>,
+    feature_flags: FeatureFlags,
+    minidump: MinidumpConfig,
+    shadow: bool,
 }
 
 impl SymbolicationActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         objects: ObjectsActor,
         symcaches: SymCacheActor,
@@ -92,6 +148,9 @@ impl SymbolicationActor {
         ppdb_caches: PortablePdbCacheActor,
         diagnostics_cache: Cache,
         sourcemaps: SourceMapService,
+        proguard: ProguardService,
+        cache_stats: CacheStatsRegistry,
+        config: &Config,
     ) -> Self {
         SymbolicationActor {
             objects,
@@ -100,13 +159,187 @@ impl SymbolicationActor {
             ppdb_caches,
             diagnostics_cache,
             sourcemaps,
+            proguard,
+            cache_stats,
+            responses: ResponseCache::new(&config.caches.in_memory),
+            deadline: None,
+            progress: ProgressTracker::default(),
+            trace: TraceRecorder::default(),
+            options: SymbolicationOptions::default(),
+            demangling: config.demangling,
+            source_links: config.source_links.clone().into(),
+            path_mappings: config.path_mappings.clone().into(),
+            in_app_rules: config.in_app_rules.clone().into(),
+            package_rules: config.package_rules.clone().into(),
+            feature_flags: FeatureFlags::new(config.feature_flags.clone()),
+            minidump: config.minidump,
+            shadow: false,
         }
     }
 
-    #[tracing::instrument(skip_all)]
+    /// Returns a copy of this actor which stops waiting for outstanding downloads once the
+    /// `deadline` is hit.
+    ///
+    /// Symbolication then continues with whatever debug files were fetched until then, and the
+    /// response is flagged as [`partial`](CompletedSymbolicationResponse::partial).
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this actor which reports the progress of symbolication to `progress`.
+    pub fn with_progress(&self, progress: ProgressTracker) -> Self {
+        Self {
+            progress,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this actor which records the decisions it makes in `trace`.
+    pub fn with_trace(&self, trace: TraceRecorder) -> Self {
+        Self {
+            trace,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this actor which symbolicates frames according to `options`.
+    pub fn with_options(&self, options: SymbolicationOptions) -> Self {
+        Self {
+            options,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this actor for symbolicating in shadow mode.
+    ///
+    /// Shadow symbolication does not report any progress, nor does it record a trace. The caches it
+    /// computes are discarded, so that an alternate implementation never affects the primary one.
+    fn as_shadow(&self) -> Self {
+        Self {
+            symcaches: self.symcaches.read_only(),
+            cficaches: self.cficaches.read_only(),
+            ppdb_caches: self.ppdb_caches.read_only(),
+            progress: ProgressTracker::default(),
+            trace: TraceRecorder::default(),
+            shadow: true,
+            ..self.clone()
+        }
+    }
+
+    /// Returns whether this actor symbolicates in shadow mode.
+    ///
+    /// Pipeline code can branch on this to run an alternate implementation, which is compared to
+    /// the primary implementation without affecting the returned result. Metrics of the
+    /// symbolicated stacktraces are tagged with it.
+    pub fn is_shadow(&self) -> bool {
+        self.shadow
+    }
+
+    /// Returns the statistics of the file-system caches, as last gathered by the background
+    /// scanner.
+    ///
+    /// This is empty unless `caches.size_metrics_interval` is configured.
+    pub fn cache_stats(&self) -> Vec<CacheReport> {
+        self.cache_stats.report()
+    }
+
+    /// Moves the request into the given `phase`, and records it in the trace.
+    fn enter_phase(&self, phase: SymbolicationPhase) {
+        self.progress.set_phase(phase);
+        self.trace.record(|| TraceEventKind::Phase { phase });
+    }
+
+    /// Records the frames which could not be symbolicated in the trace, along with their module.
+    fn trace_unsymbolicated_frames(
+        &self,
+        stacktraces: &[CompleteStacktrace],
+        module_lookup: &ModuleLookup,
+    ) {
+        for (thread, stacktrace) in stacktraces.iter().enumerate() {
+            for (frame, symbolicated) in stacktrace.frames.iter().enumerate() {
+                if symbolicated.status == FrameStatus::Symbolicated {
+                    continue;
+                }
+                let raw = &symbolicated.raw;
+                self.trace.record(|| TraceEventKind::UnsymbolicatedFrame {
+                    thread,
+                    frame,
+                    instruction_addr: raw.instruction_addr,
+                    status: symbolicated.status,
+                    module: module_lookup
+                        .lookup_cache(raw.instruction_addr.0, raw.addr_mode)
+                        .map(|lookup| lookup.module_index),
+                });
+            }
+        }
+    }
+
+    /// Returns whether the feature `flag` is enabled for the given `request`.
+    ///
+    /// The decision is recorded in metrics and on the Sentry scope, so the effects of a rollout
+    /// can be compared between requests with and without the flag.
+    pub fn feature_enabled(&self, flag: &str, request: &SymbolicateStacktraces) -> bool {
+        if !self.feature_flags.contains(flag) {
+            return false;
+        }
+
+        let enabled = self
+            .feature_flags
+            .is_enabled(flag, &request.scope, || feature_flags::request_key(request));
+
+        metric!(
+            counter("symbolication.feature_flag") += 1,
+            "flag" => flag,
+            "enabled" => if enabled { "true" } else { "false" },
+        );
+        sentry::configure_scope(|scope| {
+            scope.set_tag(&format!("feature.{flag}"), enabled);
+        });
+
+        enabled
+    }
+
     pub async fn symbolicate(
         &self,
         request: SymbolicateStacktraces,
+    ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        let request_hash = self.responses.hash_request(&request, &self.options);
+        if let Some(response) = request_hash.and_then(|hash| self.responses.get(&hash)) {
+            self.trace.record(|| TraceEventKind::ResponseCacheHit);
+            return Ok(response);
+        }
+
+        // Sampled requests are also symbolicated in shadow mode, and compared in the background.
+        let shadow = (!self.shadow && self.feature_enabled(SHADOW_FLAG, &request)).then(|| {
+            let actor = self.as_shadow();
+            let request = request.clone();
+            tokio::spawn(async move { actor.symbolicate_uncached(request).await })
+        });
+
+        let response = self.symbolicate_uncached(request).await;
+
+        if let Some(shadow) = shadow {
+            let primary = response.as_ref().ok().cloned();
+            tokio::spawn(async move {
+                let shadow = shadow.await.ok().and_then(Result::ok);
+                shadow::report(primary.as_ref(), shadow.as_ref());
+            });
+        }
+
+        let response = response?;
+        if let Some(hash) = request_hash {
+            self.responses.insert(hash, &response);
+        }
+        Ok(response)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn symbolicate_uncached(
+        &self,
+        request: SymbolicateStacktraces,
     ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
         let SymbolicateStacktraces {
             stacktraces,
@@ -115,29 +348,69 @@ impl SymbolicationActor {
             signal,
             origin,
             modules,
+            kernel_space,
             ..
         } = request;
 
-        let mut module_lookup = ModuleLookup::new(scope, sources, modules.into_iter());
-        module_lookup
+        self.enter_phase(SymbolicationPhase::FetchingDebugFiles);
+        self.progress
+            .update(|progress| progress.threads_total = stacktraces.len());
+
+        let source_links = SourceLinks::new(
+            &self.source_links,
+            &scope,
+            self.options.source_commit.as_deref(),
+        );
+        let path_mappings = PathMappings::new(&self.path_mappings, &scope);
+        let mut module_lookup =
+            ModuleLookup::new(scope, sources, kernel_space, modules.into_iter());
+        let mut deadline_hit = module_lookup
             .fetch_caches(
                 self.symcaches.clone(),
                 self.ppdb_caches.clone(),
                 &stacktraces,
+                self.deadline,
+                &self.progress,
+                &self.trace,
             )
             .await;
 
+        self.enter_phase(SymbolicationPhase::Symbolicating);
+
         let mut metrics = StacktraceMetrics::default();
+        let ctx = FrameContext {
+            signal,
+            options: &self.options,
+            demangling: &self.demangling,
+        };
         let mut stacktraces: Vec<_> = stacktraces
             .into_iter()
-            .map(|trace| symbolicate_stacktrace(trace, &module_lookup, &mut metrics, signal))
+            .map(|trace| {
+                let trace = symbolicate_stacktrace(trace, &module_lookup, &mut metrics, ctx);
+                self.progress
+                    .update(|progress| progress.threads_processed += 1);
+                trace
+            })
             .collect();
 
-        module_lookup
-            .fetch_sources(self.objects.clone(), &stacktraces)
+        self.trace_unsymbolicated_frames(&stacktraces, &module_lookup);
+        self.enter_phase(SymbolicationPhase::FetchingSources);
+
+        deadline_hit |= module_lookup
+            .fetch_sources(self.objects.clone(), &stacktraces, self.deadline)
             .await;
 
         let debug_sessions = module_lookup.prepare_debug_sessions();
+        let in_app_rules = self
+            .options
+            .in_app_rules
+            .iter()
+            .chain(self.in_app_rules.iter());
+        let package_rules = self
+            .options
+            .package_rules
+            .iter()
+            .chain(self.package_rules.iter());
 
         for trace in &mut stacktraces {
             for frame in &mut trace.frames {
@@ -148,6 +421,22 @@ impl SymbolicationActor {
                     frame.raw.context_line = Some(context_line);
                     frame.raw.post_context = post_context;
                 }
+                if let Some(source_link) = source_links.link(&frame.raw) {
+                    frame.raw.source_link = Some(source_link);
+                }
+                if frame.raw.in_app.is_none() {
+                    frame.raw.in_app = in_app_rules
+                        .clone()
+                        .find(|rule| rule.matches(&frame.raw))
+                        .map(|rule| rule.in_app);
+                }
+                if frame.raw.package_kind.is_none() {
+                    frame.raw.package_kind = package_rules
+                        .clone()
+                        .find(|rule| rule.matches(&frame.raw))
+                        .map(|rule| rule.kind);
+                }
+                path_mappings.apply(&mut frame.raw);
             }
         }
         // explicitly drop this, so it does not borrow `module_lookup` anymore.
@@ -155,10 +444,30 @@ impl SymbolicationActor {
 
         // bring modules back into the original order
         let modules = module_lookup.into_inner();
-        record_symbolication_metrics(origin, metrics, &modules, &stacktraces);
+        let shadow = if self.is_shadow() { "true" } else { "false" };
+        record_symbolication_metrics(
+            origin,
+            shadow,
+            kernel_space,
+            metrics,
+            &modules,
+            &stacktraces,
+        );
+
+        let partial = if deadline_hit {
+            metric!(
+                counter("symbolication.partial") += 1,
+                "reason" => "timeout",
+                "shadow" => shadow,
+            );
+            Some(PartialReason::Timeout)
+        } else {
+            None
+        };
 
         Ok(CompletedSymbolicationResponse {
             signal,
+            partial,
             stacktraces,
             modules,
             ..Default::default()
@@ -193,15 +502,61 @@ pub struct SymbolicateStacktraces {
     /// [`stacktraces`](Self::stacktraces). If a frame is not covered by any image, the frame cannot
     /// be symbolicated as it is not clear which debug file to load.
     pub modules: Vec<CompleteObjectInfo>,
+
+    /// The kernel space of the platform the images were loaded on.
+    ///
+    /// Frames in kernel space are only symbolicated with kernel modules, and frames in user space
+    /// only with user space images.
+    pub kernel_space: KernelSpace,
+}
+
+/// Request-wide settings which influence how individual frames are symbolicated.
+#[derive(Debug, Clone, Copy)]
+struct FrameContext<'a> {
+    /// The signal thrown on certain operating systems.
+    signal: Option<Signal>,
+    /// The options of the request.
+    options: &'a SymbolicationOptions,
+    /// The configured default behavior of the demangler.
+    demangling: &'a DemanglingConfig,
+}
+
+impl FrameContext<'_> {
+    /// Returns the options for demangling symbols of the given `language`.
+    ///
+    /// This uses the configured defaults for the language, unless overridden by the request.
+    fn demangle_options(&self, language: Language) -> DemangleOptions {
+        let config = match language {
+            Language::Cpp | Language::ObjCpp => self.demangling.cpp,
+            Language::Swift => self.demangling.swift,
+            _ => DemangleLanguageConfig::default(),
+        };
+        let parameters = self
+            .options
+            .demangle_parameters
+            .unwrap_or(config.parameters);
+        DemangleOptions::complete()
+            .return_type(config.return_type)
+            .parameters(parameters)
+    }
+
+    /// Demangles the given `name`, detecting its language if it is not known.
+    fn demangle(&self, name: &Name) -> Option<String> {
+        let language = match name.language() {
+            Language::Unknown => name.detect_language(),
+            language => language,
+        };
+        name.demangle(self.demangle_options(language))
+    }
 }
 
 fn symbolicate_frame(
     caches: &ModuleLookup,
     registers: &Registers,
-    signal: Option<Signal>,
     frame: &mut RawFrame,
     index: usize,
     adjustment: AdjustInstructionAddr,
+    ctx: FrameContext,
 ) -> Result<Vec<SymbolicatedFrame>, FrameStatus> {
     let lookup_result = caches
         .lookup_cache(frame.instruction_addr.0, frame.addr_mode)
@@ -214,10 +569,10 @@ fn symbolicate_frame(
             symcache.get(),
             lookup_result,
             registers,
-            signal,
             frame,
             index,
             adjustment,
+            ctx,
         ),
         Ok(CacheFileEntry::PortablePdbCache(ppdb_cache)) => {
             symbolicate_dotnet_frame(ppdb_cache.get(), frame, index)
@@ -261,10 +616,10 @@ fn symbolicate_native_frame(
     symcache: &SymCache,
     lookup_result: CacheLookupResult,
     registers: &Registers,
-    signal: Option<Signal>,
     frame: &RawFrame,
     index: usize,
     adjustment: AdjustInstructionAddr,
+    ctx: FrameContext,
 ) -> Result<Vec<SymbolicatedFrame>, FrameStatus> {
     // get the relative caller address
     let relative_addr = if let Some(addr) = lookup_result.relative_addr {
@@ -287,7 +642,7 @@ fn symbolicate_native_frame(
             let mut instruction_info = InstructionInfo::new(symcache.arch(), absolute_addr);
             let instruction_info = instruction_info
                 .is_crashing_frame(is_crashing_frame)
-                .signal(signal.map(|signal| signal.0))
+                .signal(ctx.signal.map(|signal| signal.0))
                 .ip_register_value(ip_register_value);
 
             let absolute_caller_addr = match adjustment {
@@ -315,6 +670,11 @@ fn symbolicate_native_frame(
         return Err(FrameStatus::MissingSymbol);
     };
 
+    let relative_addr = match symcache.arch().cpu_family() {
+        CpuFamily::Arm32 => apply_thumb_bit(ctx.options.thumb_bit, frame, relative_addr),
+        _ => relative_addr,
+    };
+
     tracing::trace!("Symbolicating {:#x}", relative_addr);
     let mut rv = vec![];
 
@@ -322,9 +682,17 @@ fn symbolicate_native_frame(
     // We keep track of it while iterating and only set it for the last frame,
     // which is the top-level function.
     let mut sym_addr = None;
+    let mut entry_pc = None;
     let instruction_addr = HexValue(lookup_result.expose_preferred_addr(relative_addr));
 
-    for source_location in symcache.lookup(relative_addr) {
+    // Inlinees are yielded first, followed by the function containing the instruction.
+    let source_locations: Vec<_> = symcache.lookup(relative_addr).collect();
+    let skipped = match ctx.options.max_inline_frames {
+        Some(max) => source_locations.len().saturating_sub(max.saturating_add(1)),
+        None => 0,
+    };
+
+    for source_location in source_locations.into_iter().skip(skipped) {
         let abs_path = source_location
             .file()
             .map(|f| f.full_path())
@@ -333,30 +701,40 @@ fn symbolicate_native_frame(
 
         let func = source_location.function();
         let symbol = func.name();
+        // Fall back to the language hint of the request if the language is not known.
+        let language = match func.language() {
+            Language::Unknown => ctx.options.demangle_language.unwrap_or(Language::Unknown),
+            language => language,
+        };
 
         // Detect the language from the bare name, ignoring any pre-set language. There are a few
         // languages that we should always be able to demangle. Only complain about those that we
         // detect explicitly, but silently ignore the rest. For instance, there are C-identifiers
         // reported as C++, which are expected not to demangle.
         let detected_language = Name::from(symbol).detect_language();
-        let should_demangle = match (func.language(), detected_language) {
+        let should_demangle = match (language, detected_language) {
             (_, Language::Unknown) => false, // can't demangle what we cannot detect
             (Language::ObjCpp, Language::Cpp) => true, // C++ demangles even if it was in ObjC++
             (Language::Unknown, _) => true,  // if there was no language, then rely on detection
             (lang, detected) => lang == detected, // avoid false-positive detections
         };
 
-        let demangled_opt = func.name_for_demangling().demangle(DEMANGLE_OPTIONS);
+        let mut name = func.name_for_demangling();
+        if name.language() == Language::Unknown {
+            name = Name::new(symbol, name.mangling(), language);
+        }
+        let demangled_opt = ctx.demangle(&name);
         if should_demangle && demangled_opt.is_none() {
             sentry::with_scope(
                 |scope| scope.set_extra("identifier", symbol.to_string().into()),
                 || {
-                    let message = format!("Failed to demangle {} identifier", func.language());
+                    let message = format!("Failed to demangle {language} identifier");
                     sentry::capture_message(&message, sentry::Level::Error);
                 },
             );
         }
 
+        entry_pc = Some(func.entry_pc() as u64);
         sym_addr = Some(HexValue(
             lookup_result.expose_preferred_addr(func.entry_pc() as u64),
         ));
@@ -374,7 +752,7 @@ fn symbolicate_native_frame(
                 instruction_addr,
                 adjust_instruction_addr: frame.adjust_instruction_addr,
                 function_id: frame.function_id,
-                symbol: Some(symbol.to_string()),
+                symbol: ctx.options.include_symbols.then(|| symbol.to_string()),
                 abs_path: if !abs_path.is_empty() {
                     Some(abs_path)
                 } else {
@@ -389,7 +767,11 @@ fn symbolicate_native_frame(
                 pre_context: vec![],
                 context_line: None,
                 post_context: vec![],
+                source_link: None,
+                in_app: frame.in_app,
+                package_kind: frame.package_kind,
                 sym_addr: None,
+                sym_size: None,
                 lang: match func.language() {
                     Language::Unknown => None,
                     language => Some(language),
@@ -401,6 +783,10 @@ fn symbolicate_native_frame(
 
     if let Some(last_frame) = rv.last_mut() {
         last_frame.raw.sym_addr = sym_addr;
+        if ctx.options.function_sizes {
+            last_frame.raw.sym_size =
+                entry_pc.and_then(|entry_pc| function_size(symcache, relative_addr, entry_pc));
+        }
     }
 
     if rv.is_empty() {
@@ -410,8 +796,53 @@ fn symbolicate_native_frame(
     Ok(rv)
 }
 
-/// Options for demangling all symbols.
-const DEMANGLE_OPTIONS: DemangleOptions = DemangleOptions::complete().return_type(false);
+/// Determines the size of the function at `addr` which starts at `entry_pc`.
+///
+/// SymCaches do not record where functions end, so this probes increasingly distant addresses
+/// until they resolve to a different function, and then narrows down the end using a binary
+/// search. This assumes that functions are contiguous, and returns `None` if the function extends
+/// to the end of the address space, which is the case for the last function in a SymCache.
+/// Applies the requested treatment of the Thumb bit to the `relative_addr` of an ARM32 frame.
+fn apply_thumb_bit(thumb_bit: ThumbBit, frame: &RawFrame, relative_addr: u64) -> u64 {
+    match thumb_bit {
+        ThumbBit::Auto => relative_addr,
+        ThumbBit::Mask => relative_addr & !1,
+        // Images are at least aligned to pages, so this is the bit of the reported address.
+        ThumbBit::Preserve => relative_addr | (frame.instruction_addr.0 & 1),
+    }
+}
+
+fn function_size(symcache: &SymCache, addr: u64, entry_pc: u64) -> Option<u64> {
+    // Inlinees are yielded first, so the last location belongs to the outermost function.
+    let is_inside = |probe: u64| {
+        symcache.lookup(probe).last().map_or(false, |location| {
+            location.function().entry_pc() as u64 == entry_pc
+        })
+    };
+
+    let mut inside = addr;
+    let mut step = 1;
+    let mut outside = loop {
+        // Addresses in SymCaches are 32-bit.
+        let probe = inside.checked_add(step).filter(|&p| p <= u32::MAX as u64)?;
+        if !is_inside(probe) {
+            break probe;
+        }
+        inside = probe;
+        step *= 2;
+    };
+
+    while outside - inside > 1 {
+        let middle = inside + (outside - inside) / 2;
+        if is_inside(middle) {
+            inside = middle;
+        } else {
+            outside = middle;
+        }
+    }
+
+    outside.checked_sub(entry_pc)
+}
 
 /// Stacktrace related Metrics
 ///
@@ -500,8 +931,12 @@ fn is_likely_base_frame(frame: &SymbolicatedFrame) -> bool {
     false
 }
 
+/// Records metrics about the symbolicated stacktraces, tagged with whether they are symbolicated
+/// in `shadow` mode.
 fn record_symbolication_metrics(
     origin: StacktraceOrigin,
+    shadow: &str,
+    kernel_space: KernelSpace,
     metrics: StacktraceMetrics,
     modules: &[CompleteObjectInfo],
     stacktraces: &[CompleteStacktrace],
@@ -524,11 +959,14 @@ fn record_symbolication_metrics(
     let mut unusable_modules = 0;
     // Modules that failed parsing
     let mut unparsable_modules = 0;
+    // Drivers, kernel extensions and the kernel itself
+    let mut kernel_modules = 0;
 
     for m in modules {
         metric!(
             counter("symbolication.debug_status") += 1,
-            "status" => m.debug_status.name()
+            "status" => m.debug_status.name(),
+            "shadow" => shadow,
         );
 
         let usable_code_id = !matches!(m.raw.code_id.as_deref(), None | Some(""));
@@ -547,69 +985,77 @@ fn record_symbolication_metrics(
         if m.debug_status == ObjectFileStatus::Malformed {
             unparsable_modules += 1;
         }
+
+        if m.is_kernel_module(kernel_space) {
+            kernel_modules += 1;
+        }
     }
 
     metric!(
         time_raw("symbolication.num_modules") = modules.len() as u64,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unusable_modules") = unusable_modules,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unparsable_modules") = unparsable_modules,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
+    );
+    metric!(
+        time_raw("symbolication.kernel_modules") = kernel_modules,
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
 
     metric!(
         time_raw("symbolication.num_stacktraces") = stacktraces.len() as u64,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.short_stacktraces") = metrics.short_traces,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.truncated_stacktraces") = metrics.truncated_traces,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.bad_stacktraces") = metrics.bad_traces,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
 
     metric!(
         time_raw("symbolication.num_frames") =
             stacktraces.iter().map(|s| s.frames.len() as u64).sum::<u64>(),
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.scanned_frames") = metrics.scanned_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unsymbolicated_frames") = metrics.unsymbolicated_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unsymbolicated_context_frames") =
             metrics.unsymbolicated_context_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unsymbolicated_cfi_frames") =
             metrics.unsymbolicated_cfi_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unsymbolicated_scanned_frames") =
             metrics.unsymbolicated_scanned_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
     metric!(
         time_raw("symbolication.unmapped_frames") = metrics.unmapped_frames,
-        "platform" => &platform, "origin" => &origin
+        "platform" => &platform, "origin" => &origin, "shadow" => shadow
     );
 }
 
@@ -617,7 +1063,7 @@ fn symbolicate_stacktrace(
     thread: RawStacktrace,
     caches: &ModuleLookup,
     metrics: &mut StacktraceMetrics,
-    signal: Option<Signal>,
+    ctx: FrameContext,
 ) -> CompleteStacktrace {
     let default_adjustment = AdjustInstructionAddr::default_for_thread(&thread);
     let mut symbolicated_frames = vec![];
@@ -628,10 +1074,10 @@ fn symbolicate_stacktrace(
         match symbolicate_frame(
             caches,
             &thread.registers,
-            signal,
             &mut frame,
             index,
             adjustment,
+            ctx,
         ) {
             Ok(frames) => {
                 if matches!(frame.trust, FrameTrust::Scan) {
@@ -644,10 +1090,14 @@ fn symbolicate_stacktrace(
                 // either one of `function` or `symbol`, treat that as mangled name and try to
                 // demangle it. If that succeeds, write the demangled name back.
                 let mangled = frame.function.as_deref().xor(frame.symbol.as_deref());
-                let demangled = mangled.and_then(|m| Name::from(m).demangle(DEMANGLE_OPTIONS));
+                let language = ctx.options.demangle_language.unwrap_or(Language::Unknown);
+                let demangled = mangled
+                    .and_then(|m| ctx.demangle(&Name::new(m, NameMangling::Unknown, language)));
                 if let Some(demangled) = demangled {
                     if let Some(old_mangled) = frame.function.replace(demangled) {
-                        frame.symbol = Some(old_mangled);
+                        if ctx.options.include_symbols {
+                            frame.symbol = Some(old_mangled);
+                        }
                     }
                 }
 
@@ -752,6 +1202,18 @@ pub enum StacktraceOrigin {
     Minidump,
     /// The stack traces came from an Apple Crash Report.
     AppleCrashReport,
+    /// The stack traces are the native part of stack traces mixing native and JavaScript frames.
+    Hybrid,
+    /// The stack traces were parsed from a Go panic.
+    GoPanic,
+    /// The stack traces were parsed from an unstructured, textual backtrace.
+    Text,
+    /// The stack traces are the unique stacks of a textual profile.
+    Profile,
+    /// The stack trace is the frame table of a chunk of profiler samples.
+    ProfileChunk,
+    /// The stack traces are raw address lists of a bare-metal firmware image.
+    Firmware,
 }
 
 impl std::fmt::Display for StacktraceOrigin {
@@ -760,6 +1222,12 @@ impl std::fmt::Display for StacktraceOrigin {
             StacktraceOrigin::Symbolicate => "symbolicate",
             StacktraceOrigin::Minidump => "minidump",
             StacktraceOrigin::AppleCrashReport => "applecrashreport",
+            StacktraceOrigin::Hybrid => "hybrid",
+            StacktraceOrigin::GoPanic => "gopanic",
+            StacktraceOrigin::Text => "text",
+            StacktraceOrigin::Profile => "profile",
+            StacktraceOrigin::ProfileChunk => "profile_chunk",
+            StacktraceOrigin::Firmware => "firmware",
         })
     }
 }
@@ -0,0 +1,108 @@
+use crate::config::PathMappingConfig;
+use crate::types::{RawFrame, Scope};
+
+/// Substitutes the prefixes of source paths, using the mappings which apply to a request.
+#[derive(Debug)]
+pub(super) struct PathMappings<'a> {
+    mappings: Vec<&'a PathMappingConfig>,
+}
+
+impl<'a> PathMappings<'a> {
+    /// Creates the path mappings for a request of the given `scope`.
+    pub fn new(mappings: &'a [PathMappingConfig], scope: &Scope) -> Self {
+        let mappings = mappings
+            .iter()
+            .filter(|mapping| {
+                mapping.scopes.is_empty()
+                    || mapping.scopes.iter().any(|s| s.as_str() == scope.as_ref())
+            })
+            .collect();
+        Self { mappings }
+    }
+
+    /// Maps the `abs_path` and `filename` of the `frame`, using the first matching mapping.
+    pub fn apply(&self, frame: &mut RawFrame) {
+        for path in [&mut frame.abs_path, &mut frame.filename] {
+            if let Some(mapped) = path.as_deref().and_then(|path| self.map(path)) {
+                *path = Some(mapped);
+            }
+        }
+    }
+
+    /// Maps a single `path`, returning `None` if no mapping matches.
+    fn map(&self, path: &str) -> Option<String> {
+        self.mappings.iter().find_map(|mapping| {
+            let from = mapping.from.trim_end_matches(['/', '\\']);
+            let rest = path.strip_prefix(from)?;
+
+            // Like `set substitute-path` in GDB, prefixes only match whole path components.
+            if !rest.is_empty() && !rest.starts_with(['/', '\\']) {
+                return None;
+            }
+
+            Some(if mapping.to.is_empty() {
+                rest.trim_start_matches(['/', '\\']).to_owned()
+            } else {
+                format!("{}{rest}", mapping.to.trim_end_matches(['/', '\\']))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(scopes: &[&str], from: &str, to: &str) -> PathMappingConfig {
+        PathMappingConfig {
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    fn frame(abs_path: &str, filename: &str) -> RawFrame {
+        RawFrame {
+            abs_path: Some(abs_path.into()),
+            filename: Some(filename.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_path_mappings() {
+        let mappings = [
+            mapping(&["1"], "/builds/ci/app/", ""),
+            mapping(&[], "C:\\agent\\work", "D:\\src"),
+        ];
+        let mappings = PathMappings::new(&mappings, &Scope::Scoped("1".into()));
+
+        let mut mapped = frame("/builds/ci/app/src/main.cpp", "main.cpp");
+        mappings.apply(&mut mapped);
+        assert_eq!(mapped.abs_path.as_deref(), Some("src/main.cpp"));
+        assert_eq!(mapped.filename.as_deref(), Some("main.cpp"));
+
+        let mut mapped = frame("C:\\agent\\work\\lib.cpp", "C:\\agent\\work\\lib.cpp");
+        mappings.apply(&mut mapped);
+        assert_eq!(mapped.abs_path.as_deref(), Some("D:\\src\\lib.cpp"));
+        assert_eq!(mapped.filename.as_deref(), Some("D:\\src\\lib.cpp"));
+
+        // Prefixes only match whole path components.
+        let mut unmapped = frame("/builds/ci/application/main.cpp", "main.cpp");
+        mappings.apply(&mut unmapped);
+        assert_eq!(
+            unmapped.abs_path.as_deref(),
+            Some("/builds/ci/application/main.cpp")
+        );
+    }
+
+    #[test]
+    fn test_path_mappings_scope() {
+        let mappings = [mapping(&["1"], "/builds/", "")];
+        let mappings = PathMappings::new(&mappings, &Scope::Global);
+
+        let mut unmapped = frame("/builds/main.cpp", "main.cpp");
+        mappings.apply(&mut unmapped);
+        assert_eq!(unmapped.abs_path.as_deref(), Some("/builds/main.cpp"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use futures::future;
+use sentry::{Hub, SentryFutureExt};
+use symbolicator_sources::{ObjectType, SourceConfig};
+
+use crate::services::cficaches::FetchCfiCache;
+use crate::services::derived::DerivedCache;
+use crate::services::module_lookup::{
+    object_file_status_from_cache_entry, object_id_from_object_info,
+};
+use crate::services::ppdb_caches::FetchPortablePdbCache;
+use crate::services::symcaches::FetchSymCache;
+use crate::types::{CompleteObjectInfo, CompletedSymbolicationResponse, RawObjectInfo, Scope};
+
+use super::SymbolicationActor;
+
+/// A request to fetch and convert the debug information files of some modules ahead of time.
+#[derive(Debug, Clone)]
+pub struct PrefetchDifs {
+    /// The scope of this request which determines access to cached files.
+    pub scope: Scope,
+
+    /// A list of external sources to load debug files.
+    pub sources: Arc<[SourceConfig]>,
+
+    /// The modules whose caches should be created.
+    pub modules: Vec<RawObjectInfo>,
+}
+
+impl SymbolicationActor {
+    /// Creates the SymCaches and CFI caches of all the given modules, without symbolicating any
+    /// frames.
+    ///
+    /// The response has no stack traces. Its modules carry the `debug_status` and
+    /// `unwind_status` of the caches, which tell whether later requests will find them.
+    #[tracing::instrument(skip_all)]
+    pub async fn prefetch_difs(
+        &self,
+        request: PrefetchDifs,
+    ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        let PrefetchDifs {
+            scope,
+            sources,
+            modules,
+        } = request;
+
+        let futures = modules.into_iter().map(|raw| {
+            self.prefetch_module(scope.clone(), sources.clone(), raw.into())
+                .bind_hub(Hub::new_from_top(Hub::current()))
+        });
+        let modules = future::join_all(futures).await;
+
+        metric!(counter("symbolication.prefetch.modules") += modules.len() as i64);
+
+        Ok(CompletedSymbolicationResponse {
+            modules,
+            ..Default::default()
+        })
+    }
+
+    async fn prefetch_module(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        mut object_info: CompleteObjectInfo,
+    ) -> CompleteObjectInfo {
+        let identifier = object_id_from_object_info(&object_info.raw);
+        let object_type = object_info.raw.ty;
+
+        // .NET modules are neither unwound nor symbolicated with SymCaches.
+        if object_type == ObjectType::PeDotnet {
+            let DerivedCache {
+                cache,
+                candidates,
+                features,
+            } = self
+                .ppdb_caches
+                .fetch(FetchPortablePdbCache {
+                    identifier,
+                    sources,
+                    scope,
+                })
+                .await;
+
+            object_info.debug_status = object_file_status_from_cache_entry(&cache);
+            object_info.features.merge(features);
+            object_info.candidates.merge(&candidates);
+            return object_info;
+        }
+
+        let symcache = self.symcaches.fetch(FetchSymCache {
+            object_type,
+            identifier: identifier.clone(),
+            sources: sources.clone(),
+            scope: scope.clone(),
+        });
+        let cficache = self.cficaches.fetch(FetchCfiCache {
+            object_type,
+            identifier,
+            sources,
+            scope,
+        });
+        let (symcache, cficache) = future::join(symcache, cficache).await;
+
+        object_info.debug_status = object_file_status_from_cache_entry(&symcache.cache);
+        if let Ok(ref symcache) = symcache.cache {
+            object_info.arch = symcache.get().arch();
+        }
+        object_info.features.merge(symcache.features);
+        object_info.candidates.merge(&symcache.candidates);
+
+        object_info.unwind_status = Some(object_file_status_from_cache_entry(&cficache.cache));
+        object_info.features.merge(cficache.features);
+        object_info.candidates.merge(&cficache.candidates);
+
+        object_info
+    }
+}
@@ -1,12 +1,13 @@
 use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::{bail, Context};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use minidump::system_info::Os;
+use minidump::system_info::{Cpu, Os};
 use minidump::{MinidumpContext, MinidumpModuleList, MinidumpSystemInfo};
 use minidump::{MinidumpModule, Module};
 use minidump_processor::{
@@ -20,16 +21,22 @@ use tempfile::TempPath;
 use symbolic::common::{Arch, ByteView, CodeId, DebugId};
 use symbolicator_sources::{ObjectId, ObjectType, SourceConfig};
 
+use crate::caching::CacheError;
 use crate::services::cficaches::{CfiCacheActor, FetchCfiCache, FetchedCfiCache};
 use crate::services::minidump::parse_stacktraces_from_minidump;
 use crate::services::module_lookup::object_file_status_from_cache_entry;
 use crate::types::{
-    CompleteObjectInfo, CompletedSymbolicationResponse, ObjectFileStatus, RawFrame, RawObjectInfo,
-    RawStacktrace, Registers, Scope, SystemInfo,
+    CompleteObjectInfo, CompletedSymbolicationResponse, ObjectFileStatus, PartialReason, RawFrame,
+    RawObjectInfo, RawStacktrace, Registers, Scope, SystemInfo, Truncation,
 };
+use crate::utils::addr::KernelSpace;
 use crate::utils::hex::HexValue;
 
-use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+use super::minidump_limits::truncate_minidump;
+use super::{
+    StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor, SymbolicationPhase,
+    TraceEventKind,
+};
 
 type Minidump = minidump::Minidump<'static, ByteView<'static>>;
 
@@ -54,6 +61,11 @@ pub(super) struct MinidumpState {
     crashed: bool,
     crash_reason: String,
     assertion: String,
+    #[serde(default)]
+    truncated: Option<Truncation>,
+    /// Set if stackwalking stopped waiting for CFI because the deadline was hit.
+    #[serde(default)]
+    partial: Option<PartialReason>,
 }
 
 impl MinidumpState {
@@ -61,17 +73,21 @@ impl MinidumpState {
         let info = &process_state.system_info;
 
         let cpu_arch = match info.cpu {
-            minidump::system_info::Cpu::X86 => Arch::X86,
-            minidump::system_info::Cpu::X86_64 => Arch::Amd64,
-            minidump::system_info::Cpu::Ppc => Arch::Ppc,
-            minidump::system_info::Cpu::Ppc64 => Arch::Ppc64,
-            minidump::system_info::Cpu::Arm => Arch::Arm,
-            minidump::system_info::Cpu::Arm64 => Arch::Arm64,
-            minidump::system_info::Cpu::Mips => Arch::Mips,
-            minidump::system_info::Cpu::Mips64 => Arch::Mips64,
+            Cpu::X86 => Arch::X86,
+            Cpu::X86_64 => Arch::Amd64,
+            Cpu::Ppc => Arch::Ppc,
+            Cpu::Ppc64 => Arch::Ppc64,
+            Cpu::Arm => Arch::Arm,
+            Cpu::Arm64 => Arch::Arm64,
+            Cpu::Mips => Arch::Mips,
+            Cpu::Mips64 => Arch::Mips64,
             arch => {
                 let msg = format!("Unknown minidump arch: {arch}");
                 sentry::capture_message(&msg, sentry::Level::Error);
+                metric!(
+                    counter("minidump.unsupported_arch") += 1,
+                    "arch" => unsupported_cpu_name(arch)
+                );
                 Arch::Unknown
             }
         };
@@ -92,6 +108,8 @@ impl MinidumpState {
                 .map(|info| format!("{} / {:#x}", info.reason, info.address))
                 .unwrap_or_default(),
             assertion: process_state.assertion.clone().unwrap_or_default(),
+            truncated: None,
+            partial: None,
         }
     }
 
@@ -113,6 +131,8 @@ impl MinidumpState {
         response.crashed = Some(self.crashed);
         response.crash_reason = Some(self.crash_reason);
         response.assertion = Some(self.assertion);
+        response.truncated = self.truncated;
+        response.partial = response.partial.or(self.partial);
     }
 }
 
@@ -152,6 +172,10 @@ struct SymbolicatorSymbolProvider {
     ///
     /// The key consists of a module's debug identifier and base address.
     cficaches: moka::future::Cache<LookupKey, FetchedCfiCache>,
+    /// The time after which stackwalking stops waiting for CFI.
+    deadline: Option<Instant>,
+    /// Whether CFI fetches were abandoned because the `deadline` was hit.
+    deadline_hit: AtomicBool,
 }
 
 impl SymbolicatorSymbolProvider {
@@ -160,6 +184,7 @@ impl SymbolicatorSymbolProvider {
         sources: Arc<[SourceConfig]>,
         cficache_actor: CfiCacheActor,
         object_type: ObjectType,
+        deadline: Option<Instant>,
     ) -> Self {
         Self {
             scope,
@@ -168,28 +193,24 @@ impl SymbolicatorSymbolProvider {
             object_type,
             // use `CacheBuilder` to create a cache with no max capacity
             cficaches: moka::future::Cache::builder().build(),
+            deadline,
+            deadline_hit: AtomicBool::new(false),
         }
     }
 
     /// Fetches CFI for the given module, parses it into a `SymbolFile`, and stores it internally.
+    ///
+    /// Once the `deadline` is hit, this stops waiting for the CFI and reports a timeout instead,
+    /// so that the module is unwound without CFI.
     async fn load_cfi_module(&self, module: &(dyn Module + Sync)) -> FetchedCfiCache {
         let key = LookupKey::new(module);
         let init = Box::pin(async {
             let sources = self.sources.clone();
             let scope = self.scope.clone();
+            let identifier = object_id_from_minidump_module(self.object_type, module);
 
-            let identifier = ObjectId {
-                code_id: key.code_id.clone(),
-                code_file: Some(module.code_file().into_owned()),
-                debug_id: key.debug_id,
-                debug_file: module
-                    .debug_file()
-                    .map(|debug_file| debug_file.into_owned()),
-                debug_checksum: None,
-                object_type: self.object_type,
-            };
-
-            self.cficache_actor
+            let fetch = self
+                .cficache_actor
                 .fetch(FetchCfiCache {
                     object_type: self.object_type,
                     identifier,
@@ -200,8 +221,24 @@ impl SymbolicatorSymbolProvider {
                 // `load_cfi_module` is being called concurrently from `rust-minidump` via
                 // `join_all`. We do need proper isolation of any async task that might
                 // manipulate any Sentry scope.
-                .bind_hub(Hub::new_from_top(Hub::current()))
-                .await
+                .bind_hub(Hub::new_from_top(Hub::current()));
+
+            let Some(deadline) = self.deadline else {
+                return fetch.await;
+            };
+            let start = Instant::now();
+            let timeout_at = tokio::time::Instant::from_std(deadline);
+            match tokio::time::timeout_at(timeout_at, fetch).await {
+                Ok(cfi_module) => cfi_module,
+                Err(_elapsed) => {
+                    self.deadline_hit.store(true, Ordering::Relaxed);
+                    FetchedCfiCache {
+                        cache: Err(CacheError::Timeout(start.elapsed())),
+                        candidates: Default::default(),
+                        features: Default::default(),
+                    }
+                }
+            }
         });
         self.cficaches.get_with_by_ref(&key, init).await
     }
@@ -241,6 +278,30 @@ impl SymbolProvider for SymbolicatorSymbolProvider {
     }
 }
 
+/// Returns the identifier by which debug files of a minidump module are looked up.
+pub(super) fn object_id_from_minidump_module(ty: ObjectType, module: &(dyn Module)) -> ObjectId {
+    ObjectId {
+        code_id: module.code_identifier(),
+        code_file: Some(module.code_file().into_owned()),
+        debug_id: module.debug_identifier(),
+        debug_file: module
+            .debug_file()
+            .map(|debug_file| debug_file.into_owned()),
+        debug_checksum: None,
+        object_type: ty,
+    }
+}
+
+/// Returns the type of the objects loaded by a minidump written on the given OS.
+pub(super) fn object_type_from_minidump_os(os: Os) -> ObjectType {
+    match os {
+        Os::Windows => ObjectType::Pe,
+        Os::MacOs | Os::Ios => ObjectType::Macho,
+        Os::Linux | Os::Solaris | Os::Android => ObjectType::Elf,
+        _ => ObjectType::Unknown,
+    }
+}
+
 fn object_info_from_minidump_module(ty: ObjectType, module: &MinidumpModule) -> CompleteObjectInfo {
     // Some modules are not objects but rather fonts or JIT areas or other mmapped files
     // which we don't care about.  These may not have complete information so map these to
@@ -263,6 +324,7 @@ fn object_info_from_minidump_module(ty: ObjectType, module: &MinidumpModule) ->
         debug_file: module.debug_file().map(|c| c.into_owned()),
         debug_checksum: None,
         image_addr: HexValue(module.base_address()),
+        slide: None,
         image_size: match module.size() {
             0 => None,
             size => Some(size),
@@ -275,23 +337,23 @@ async fn stackwalk(
     minidump: &Minidump,
     scope: Scope,
     sources: Arc<[SourceConfig]>,
+    deadline: Option<Instant>,
 ) -> anyhow::Result<StackWalkMinidumpResult> {
     // Stackwalk the minidump.
     let duration = Instant::now();
     let system_info = minidump
         .get_stream::<MinidumpSystemInfo>()
         .map_err(|_| minidump_processor::ProcessError::MissingSystemInfo)?;
-    let ty = match system_info.os {
-        Os::Windows => ObjectType::Pe,
-        Os::MacOs | Os::Ios => ObjectType::Macho,
-        Os::Linux | Os::Solaris | Os::Android => ObjectType::Elf,
-        _ => ObjectType::Unknown,
-    };
-    let provider = SymbolicatorSymbolProvider::new(scope, sources, cficaches, ty);
+    let ty = object_type_from_minidump_os(system_info.os);
+    let provider = SymbolicatorSymbolProvider::new(scope, sources, cficaches, ty, deadline);
     let process_state = minidump_processor::process_minidump(minidump, &provider).await?;
     let duration = duration.elapsed();
 
-    let minidump_state = MinidumpState::from_process_state(&process_state);
+    let mut minidump_state = MinidumpState::from_process_state(&process_state);
+    if provider.deadline_hit.load(Ordering::Relaxed) {
+        metric!(counter("symbolication.partial") += 1, "reason" => "stackwalk_timeout");
+        minidump_state.partial = Some(PartialReason::Timeout);
+    }
 
     // Finally iterate through the threads and build the stacktraces to
     // return, marking modules as used when they are referenced by a frame.
@@ -375,23 +437,23 @@ async fn stackwalk(
 impl SymbolicationActor {
     /// Saves the given `minidump_file` in the diagnostics cache if configured to do so.
     fn maybe_persist_minidump(&self, minidump_file: TempPath) {
-        if let Some(dir) = self.diagnostics_cache.cache_dir() {
-            if let Some(file_name) = minidump_file.file_name() {
-                let path = dir.join(file_name);
-                match minidump_file.persist(&path) {
-                    Ok(_) => {
-                        sentry::configure_scope(|scope| {
-                            scope.set_extra(
-                                "crashed_minidump",
-                                sentry::protocol::Value::String(path.to_string_lossy().to_string()),
-                            );
-                        });
-                    }
-                    Err(e) => tracing::error!("Failed to save minidump {:?}", &e),
-                };
+        let Some(key) = minidump_file.file_name().and_then(|name| name.to_str()) else {
+            return;
+        };
+
+        match self.diagnostics_cache.store_copy(key, &minidump_file) {
+            Ok(true) => {
+                sentry::configure_scope(|scope| {
+                    scope.set_extra(
+                        "crashed_minidump",
+                        sentry::protocol::Value::String(key.to_owned()),
+                    );
+                });
+            }
+            Ok(false) => {
+                tracing::debug!("No diagnostics retention configured, not saving minidump")
             }
-        } else {
-            tracing::debug!("No diagnostics retention configured, not saving minidump");
+            Err(e) => tracing::error!("Failed to save minidump {:?}", &e),
         }
     }
 
@@ -401,6 +463,8 @@ impl SymbolicationActor {
         minidump_file: TempPath,
         sources: Arc<[SourceConfig]>,
     ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        self.enter_phase(SymbolicationPhase::Stackwalking);
+
         let (request, state) = self
             .stackwalk_minidump(scope, minidump_file, sources)
             .await?;
@@ -424,7 +488,7 @@ impl SymbolicationActor {
 
         let minidump_path = minidump_file.to_path_buf();
 
-        let minidump = match read_minidump(&minidump_path) {
+        let mut minidump = match read_minidump(&minidump_path) {
             Ok(md) => md,
             Err(err) => {
                 self.maybe_persist_minidump(minidump_file);
@@ -432,6 +496,22 @@ impl SymbolicationActor {
             }
         };
 
+        if let Err(err) = self.minidump.limits.check(&minidump) {
+            tracing::error!(%err, "Minidump exceeds processing limits");
+            metric!(counter("minidump.rejected") += 1, "limit" => err.name());
+            return Err(err.into());
+        }
+
+        let truncated = match truncate_minidump(&ByteView::open(&minidump_path)?, &self.minidump) {
+            Some((data, truncated)) => {
+                tracing::debug!(?truncated, "Truncating minidump to the configured caps");
+                metric!(counter("minidump.truncated") += 1);
+                minidump = Minidump::read(ByteView::from_vec(data))?;
+                Some(truncated)
+            }
+            None => None,
+        };
+
         let modules = minidump
             .get_stream::<MinidumpModuleList>()
             .context("Failed to read minidump module list")?;
@@ -456,6 +536,7 @@ impl SymbolicationActor {
             &minidump,
             scope.clone(),
             sources.clone(),
+            self.deadline,
         );
 
         let result = match stackwalk_future.await {
@@ -469,11 +550,29 @@ impl SymbolicationActor {
         let StackWalkMinidumpResult {
             modules,
             mut stacktraces,
-            minidump_state,
+            mut minidump_state,
             duration,
         } = result;
+        minidump_state.truncated = truncated;
 
         metric!(timer("minidump.stackwalk.duration") = duration);
+        self.trace.record(|| TraceEventKind::Stackwalk {
+            duration_ms: duration.as_millis() as u64,
+            threads: stacktraces.len(),
+            modules: modules.len(),
+        });
+        for (module, info) in modules.iter().enumerate() {
+            let Some(status) = info.unwind_status else {
+                continue;
+            };
+            if status != ObjectFileStatus::Unused {
+                self.trace.record(|| TraceEventKind::UnwindInfo {
+                    module,
+                    status,
+                    candidates: info.candidates.0.clone(),
+                });
+            }
+        }
 
         match parse_stacktraces_from_minidump(&minidump) {
             Ok(Some(client_stacktraces)) => {
@@ -490,6 +589,10 @@ impl SymbolicationActor {
             origin: StacktraceOrigin::Minidump,
             signal: None,
             stacktraces,
+            kernel_space: KernelSpace::new(
+                &minidump_state.system_info.os_name,
+                minidump_state.system_info.cpu_arch,
+            ),
         };
 
         Ok((request, minidump_state))
@@ -533,6 +636,19 @@ fn map_symbolic_registers(context: &MinidumpContext) -> BTreeMap<String, HexValu
         .collect()
 }
 
+/// Returns the name of a CPU architecture that minidumps cannot be stackwalked for.
+///
+/// RISC-V is identified by the values Breakpad and Crashpad write, which are not known to the
+/// minidump parser yet.
+fn unsupported_cpu_name(cpu: Cpu) -> &'static str {
+    match cpu {
+        Cpu::Sparc => "sparc",
+        Cpu::Unknown(0x8005) => "riscv",
+        Cpu::Unknown(0x8006) => "riscv64",
+        _ => "unknown",
+    }
+}
+
 fn normalize_minidump_os_name(os: Os) -> &'static str {
     // Be aware that MinidumpState::object_type matches on names produced here.
     match os {
@@ -611,6 +727,7 @@ mod tests {
             Arc::from_iter([]),
             sym.cficaches.clone(),
             Default::default(),
+            None,
         );
 
         let module = ("foo", DebugId::nil());
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_sources::SourceConfig;
+
+use crate::types::{
+    CompleteStacktrace, CompletedProfileSymbolicationResponse, FrameStatus, RawFrame,
+    RawObjectInfo, RawStacktrace, Scope,
+};
+use crate::utils::addr::{AddrMode, KernelSpace};
+use crate::utils::hex::HexValue;
+
+use super::text::{find_module, parse_hex};
+use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+
+/// The format of a textual profile.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileFormat {
+    /// Folded stacks, with one `root;caller;leaf count` line per stack.
+    #[default]
+    Folded,
+    /// The output of `perf script`.
+    Perf,
+}
+
+impl SymbolicationActor {
+    /// Symbolicates the stacks of a textual profile and returns them as folded stacks.
+    ///
+    /// Identical stacks are only symbolicated once, and their sample counts are summed up.
+    pub async fn symbolicate_profile(
+        &self,
+        scope: Scope,
+        format: ProfileFormat,
+        profile: &str,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+    ) -> Result<CompletedProfileSymbolicationResponse, anyhow::Error> {
+        let stacks = match format {
+            ProfileFormat::Folded => parse_folded(profile, &modules),
+            ProfileFormat::Perf => parse_perf_script(profile, &modules),
+        };
+
+        let stacktraces = stacks
+            .iter()
+            .map(|stack| RawStacktrace {
+                frames: stack
+                    .frames
+                    .iter()
+                    .filter_map(|frame| frame.raw.clone())
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        let request = SymbolicateStacktraces {
+            scope,
+            signal: None,
+            sources,
+            origin: StacktraceOrigin::Profile,
+            stacktraces,
+            modules: modules.into_iter().map(From::from).collect(),
+            kernel_space: KernelSpace::Unknown,
+        };
+        let response = self.symbolicate(request).await?;
+
+        let mut folded = String::new();
+        for (stack, symbolicated) in stacks.into_iter().zip(response.stacktraces) {
+            let names = stack.symbolicated_names(symbolicated);
+            let line = names.into_iter().rev().collect::<Vec<_>>().join(";");
+            writeln!(folded, "{line} {}", stack.count).unwrap();
+        }
+
+        Ok(CompletedProfileSymbolicationResponse {
+            partial: response.partial,
+            folded,
+            modules: response.modules,
+        })
+    }
+}
+
+lazy_static::lazy_static! {
+    /// A folded frame referencing a module, like `libfoo.so+0x1234`.
+    static ref FOLDED_MODULE_REGEX: Regex = Regex::new(r"^(?P<module>.+)\+0x(?P<offset>[0-9a-fA-F]+)$").unwrap();
+    /// A frame of `perf script`, like `55d4c1a2b3c4 main+0x14 (/usr/bin/prog)`.
+    ///
+    /// With `-F +dsoff`, the module also contains the offset, like `(/usr/bin/prog+0x1234)`.
+    static ref PERF_FRAME_REGEX: Regex = Regex::new(r"^\s+(?P<addr>[0-9a-fA-F]+) (?P<symbol>.*?) \((?P<module>.+?)(?:\+0x(?P<offset>[0-9a-fA-F]+))?\)$").unwrap();
+}
+
+/// A frame of a profile stack.
+#[derive(Debug)]
+struct ProfileFrame {
+    /// The frame to symbolicate, if the profile contains an address for it.
+    raw: Option<RawFrame>,
+    /// The name used if the frame cannot be symbolicated.
+    name: String,
+}
+
+impl ProfileFrame {
+    fn text(name: &str) -> Self {
+        Self {
+            raw: None,
+            name: name.to_owned(),
+        }
+    }
+}
+
+/// A unique stack of a profile, along with the number of samples it appears in.
+#[derive(Debug)]
+struct ProfileStack {
+    /// The frames of the stack, with the leaf frame first.
+    frames: Vec<ProfileFrame>,
+    count: u64,
+}
+
+impl ProfileStack {
+    /// Returns the names of all frames, with inline frames expanded, using the symbolicated
+    /// names where available.
+    fn symbolicated_names(self, symbolicated: CompleteStacktrace) -> Vec<String> {
+        let mut symbolicated = symbolicated.frames.into_iter().peekable();
+        let mut raw_index = 0;
+
+        let mut names = Vec::with_capacity(self.frames.len());
+        for frame in self.frames {
+            if frame.raw.is_none() {
+                names.push(frame.name);
+                continue;
+            }
+
+            let len = names.len();
+            while let Some(symbolicated) =
+                symbolicated.next_if(|symbolicated| symbolicated.original_index == Some(raw_index))
+            {
+                match symbolicated.raw.function {
+                    Some(function) if symbolicated.status == FrameStatus::Symbolicated => {
+                        names.push(function)
+                    }
+                    _ => names.push(frame.name.clone()),
+                }
+            }
+            // Symbolication might drop frames it considers bogus, keep them in the profile.
+            if names.len() == len {
+                names.push(frame.name);
+            }
+            raw_index += 1;
+        }
+
+        names
+    }
+}
+
+/// Collects stacks, merging the counts of identical stacks.
+#[derive(Default)]
+struct StackCollector {
+    stacks: Vec<ProfileStack>,
+    indices: HashMap<String, usize>,
+}
+
+impl StackCollector {
+    fn add(&mut self, key: String, frames: Vec<ProfileFrame>, count: u64) {
+        if frames.is_empty() {
+            return;
+        }
+        match self.indices.get(&key) {
+            Some(&index) => self.stacks[index].count += count,
+            None => {
+                self.indices.insert(key, self.stacks.len());
+                self.stacks.push(ProfileStack { frames, count });
+            }
+        }
+    }
+}
+
+/// Parses folded stacks, like `prog;libfoo.so+0x10;0x5555555548e5 42`.
+///
+/// Frames are either `module+0xoffset` references to one of the `modules`, absolute `0xaddress`
+/// addresses, or anything else which is kept as it is.
+fn parse_folded(profile: &str, modules: &[RawObjectInfo]) -> Vec<ProfileStack> {
+    let mut collector = StackCollector::default();
+
+    for line in profile.lines() {
+        let Some((stack, count)) = line.trim().rsplit_once(' ') else {
+            continue;
+        };
+        let Ok(count) = count.parse() else {
+            continue;
+        };
+
+        let frames = stack
+            .split(';')
+            .rev()
+            .map(|name| parse_folded_frame(name, modules))
+            .collect();
+        collector.add(stack.to_owned(), frames, count);
+    }
+
+    collector.stacks
+}
+
+fn parse_folded_frame(name: &str, modules: &[RawObjectInfo]) -> ProfileFrame {
+    let (addr_mode, addr) = if let Some(addr) = name.strip_prefix("0x") {
+        match u64::from_str_radix(addr, 16) {
+            Ok(addr) => (AddrMode::Abs, HexValue(addr)),
+            Err(_) => return ProfileFrame::text(name),
+        }
+    } else if let Some(captures) = FOLDED_MODULE_REGEX.captures(name) {
+        match find_module(modules, &captures["module"]) {
+            Some(index) => (AddrMode::Rel(index), parse_hex(&captures["offset"])),
+            None => return ProfileFrame::text(name),
+        }
+    } else {
+        return ProfileFrame::text(name);
+    };
+
+    ProfileFrame {
+        raw: Some(RawFrame {
+            addr_mode,
+            instruction_addr: addr,
+            ..Default::default()
+        }),
+        name: name.to_owned(),
+    }
+}
+
+/// Parses the output of `perf script`, counting each sample once.
+///
+/// Each sample starts with a header line, the first word of which is the command name, and is
+/// used as the root frame. The header is followed by one line per frame, with the leaf first.
+fn parse_perf_script(profile: &str, modules: &[RawObjectInfo]) -> Vec<ProfileStack> {
+    let mut collector = StackCollector::default();
+    let mut sample = None;
+
+    for line in profile.lines().map(str::trim_end) {
+        if let Some(captures) = PERF_FRAME_REGEX.captures(line) {
+            let Some(sample) = sample.as_mut() else {
+                continue;
+            };
+            let module = &captures["module"];
+            let symbol = captures["symbol"].split('+').next().unwrap_or_default();
+            let index = find_module(modules, module);
+
+            let (addr_mode, addr, name) = match (captures.name("offset"), index) {
+                (Some(offset), Some(index)) => {
+                    let name = format!("{module}+0x{}", offset.as_str());
+                    (AddrMode::Rel(index), parse_hex(offset.as_str()), name)
+                }
+                _ => {
+                    let name = format!("{module}@0x{}", &captures["addr"]);
+                    (AddrMode::Abs, parse_hex(&captures["addr"]), name)
+                }
+            };
+            sample.add_frame(ProfileFrame {
+                raw: Some(RawFrame {
+                    addr_mode,
+                    instruction_addr: addr,
+                    ..Default::default()
+                }),
+                // Prefer the symbol `perf` resolved itself, if any.
+                name: match symbol {
+                    "" | "[unknown]" => name,
+                    symbol => symbol.to_owned(),
+                },
+            });
+        } else if line.is_empty() {
+            if let Some(sample) = sample.take() {
+                sample.finish(&mut collector);
+            }
+        } else if !line.starts_with(char::is_whitespace) {
+            if let Some(sample) = sample.take() {
+                sample.finish(&mut collector);
+            }
+            let command = line.split_whitespace().next().unwrap_or_default();
+            sample = Some(PerfSample::new(command));
+        }
+    }
+
+    if let Some(sample) = sample {
+        sample.finish(&mut collector);
+    }
+
+    collector.stacks
+}
+
+/// A single sample of `perf script` output, which is being parsed.
+struct PerfSample<'a> {
+    command: &'a str,
+    key: String,
+    frames: Vec<ProfileFrame>,
+}
+
+impl<'a> PerfSample<'a> {
+    fn new(command: &'a str) -> Self {
+        Self {
+            command,
+            key: String::new(),
+            frames: vec![],
+        }
+    }
+
+    fn add_frame(&mut self, frame: ProfileFrame) {
+        let raw = frame.raw.as_ref();
+        let addr = raw.map_or(0, |raw| raw.instruction_addr.0);
+        write!(self.key, "{}@{addr:x};", frame.name).unwrap();
+        self.frames.push(frame);
+    }
+
+    /// Adds the command as the root frame, and the sample to the `collector`.
+    fn finish(mut self, collector: &mut StackCollector) {
+        write!(self.key, "{}", self.command).unwrap();
+        self.frames.push(ProfileFrame::text(self.command));
+        collector.add(self.key, self.frames, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symbolicator_sources::ObjectType;
+
+    use crate::types::SymbolicatedFrame;
+
+    use super::*;
+
+    fn module(code_file: &str) -> RawObjectInfo {
+        RawObjectInfo {
+            ty: ObjectType::Elf,
+            code_id: None,
+            code_file: Some(code_file.into()),
+            debug_id: None,
+            debug_file: None,
+            debug_checksum: None,
+            image_addr: HexValue(0x5555_5555_4000),
+            slide: None,
+            image_size: None,
+        }
+    }
+
+    fn raw_frames(stack: &ProfileStack) -> Vec<Option<(AddrMode, u64)>> {
+        stack
+            .frames
+            .iter()
+            .map(|frame| {
+                let raw = frame.raw.as_ref()?;
+                Some((raw.addr_mode, raw.instruction_addr.0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_folded() {
+        let modules = [module("/usr/bin/prog")];
+        let profile = "\
+prog;prog+0x10;0x7f0010 3
+prog;prog+0x10;0x7f0010 2
+prog;unknown.so+0x20 1
+";
+        let stacks = parse_folded(profile, &modules);
+
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks[0].count, 5);
+        assert_eq!(
+            raw_frames(&stacks[0]),
+            [
+                Some((AddrMode::Abs, 0x7f0010)),
+                Some((AddrMode::Rel(0), 0x10)),
+                None
+            ]
+        );
+        assert_eq!(raw_frames(&stacks[1]), [None, None]);
+        assert_eq!(stacks[1].frames[0].name, "unknown.so+0x20");
+    }
+
+    #[test]
+    fn test_parse_perf_script() {
+        let modules = [module("/usr/bin/prog")];
+        let profile = "\
+prog  1234 12345.678:     250000 cpu-clock:
+\t    5555555548e5 [unknown] (/usr/bin/prog+0x8e5)
+\t    7ffff7a2d830 __libc_start_main+0xf0 (/usr/lib/libc.so.6)
+
+prog  1234 12345.679:     250000 cpu-clock:
+\t    5555555548e5 [unknown] (/usr/bin/prog+0x8e5)
+\t    7ffff7a2d830 __libc_start_main+0xf0 (/usr/lib/libc.so.6)
+";
+        let stacks = parse_perf_script(profile, &modules);
+
+        assert_eq!(stacks.len(), 1);
+        assert_eq!(stacks[0].count, 2);
+        assert_eq!(
+            raw_frames(&stacks[0]),
+            [
+                Some((AddrMode::Rel(0), 0x8e5)),
+                Some((AddrMode::Abs, 0x7ffff7a2d830)),
+                None
+            ]
+        );
+        let names: Vec<_> = stacks[0].frames.iter().map(|f| &f.name[..]).collect();
+        assert_eq!(names, ["/usr/bin/prog+0x8e5", "__libc_start_main", "prog"]);
+    }
+
+    #[test]
+    fn test_symbolicated_names() {
+        let stack = parse_folded("prog;prog+0x10;prog+0x20 1", &[module("prog")])
+            .pop()
+            .unwrap();
+
+        let frame = |index, status, function: Option<&str>| SymbolicatedFrame {
+            status,
+            original_index: Some(index),
+            raw: RawFrame {
+                function: function.map(Into::into),
+                ..Default::default()
+            },
+        };
+        // The leaf frame expands to an inline frame, the caller could not be symbolicated.
+        let symbolicated = CompleteStacktrace {
+            frames: vec![
+                frame(0, FrameStatus::Symbolicated, Some("inlined")),
+                frame(0, FrameStatus::Symbolicated, Some("leaf")),
+                frame(1, FrameStatus::Missing, None),
+            ],
+            ..Default::default()
+        };
+
+        let names = stack.symbolicated_names(symbolicated);
+        assert_eq!(names, ["inlined", "leaf", "prog+0x10", "prog"]);
+    }
+}
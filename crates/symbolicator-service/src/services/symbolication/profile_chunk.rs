@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use symbolicator_sources::SourceConfig;
+
+use crate::types::{
+    CompleteObjectInfo, CompletedProfileChunkResponse, FrameStatus, RawFrame, RawStacktrace, Scope,
+    SymbolicatedFrame,
+};
+use crate::utils::addr::KernelSpace;
+
+use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+
+/// A request for symbolication of a chunk of profiler samples.
+///
+/// Instead of repeating frames for every stack, the stacks reference a shared table of frames,
+/// which in turn reference the shared table of modules.
+#[derive(Debug, Clone)]
+pub struct SymbolicateProfileChunk {
+    /// The scope of this request which determines access to cached files.
+    pub scope: Scope,
+
+    /// A list of external sources to load debug files.
+    pub sources: Arc<[SourceConfig]>,
+
+    /// A list of images that were loaded into the process.
+    pub modules: Vec<CompleteObjectInfo>,
+
+    /// The table of unique frames referenced by the [`stacks`](Self::stacks).
+    pub frames: Vec<RawFrame>,
+
+    /// A list of stacks, each being a list of indices into [`frames`](Self::frames), with the
+    /// leaf frame first.
+    pub stacks: Vec<Vec<usize>>,
+}
+
+impl SymbolicationActor {
+    /// Symbolicates a chunk of profiler samples.
+    ///
+    /// Every frame is only symbolicated once, no matter how many stacks it appears in, and the
+    /// response contains a deduplicated table of symbolicated frames.
+    pub async fn symbolicate_profile_chunk(
+        &self,
+        request: SymbolicateProfileChunk,
+    ) -> Result<CompletedProfileChunkResponse, anyhow::Error> {
+        let SymbolicateProfileChunk {
+            scope,
+            sources,
+            modules,
+            frames,
+            stacks,
+        } = request;
+
+        if let Some(index) = stacks
+            .iter()
+            .flatten()
+            .find(|&&index| index >= frames.len())
+        {
+            anyhow::bail!("stack references unknown frame {index}");
+        }
+
+        let (raw_frames, origins, stacks) = dedupe_frames(&frames, stacks);
+
+        let request = SymbolicateStacktraces {
+            scope,
+            signal: None,
+            sources,
+            origin: StacktraceOrigin::ProfileChunk,
+            stacktraces: vec![RawStacktrace {
+                frames: raw_frames.clone(),
+                ..Default::default()
+            }],
+            modules,
+            kernel_space: KernelSpace::Unknown,
+        };
+        let response = self.symbolicate(request).await?;
+
+        let symbolicated = response
+            .stacktraces
+            .into_iter()
+            .next()
+            .map(|stacktrace| stacktrace.frames)
+            .unwrap_or_default();
+        let (mut frames, ranges) = build_frame_table(raw_frames, symbolicated);
+        for frame in &mut frames {
+            frame.original_index = frame.original_index.map(|index| origins[index]);
+        }
+
+        let stacks = stacks
+            .into_iter()
+            .map(|stack| {
+                stack
+                    .into_iter()
+                    .flat_map(|index| ranges[index].clone())
+                    .collect()
+            })
+            .collect();
+
+        Ok(CompletedProfileChunkResponse {
+            partial: response.partial,
+            frames,
+            stacks,
+            modules: response.modules,
+        })
+    }
+}
+
+/// Creates the list of frames to symbolicate, and maps the `stacks` to indices into that list.
+///
+/// The same address needs to be symbolicated differently depending on whether it is the leaf
+/// of a stack, or a return address of one of its callers. Each combination is only included
+/// once. Also returns the index into `frames` for each of the returned frames.
+fn dedupe_frames(
+    frames: &[RawFrame],
+    stacks: Vec<Vec<usize>>,
+) -> (Vec<RawFrame>, Vec<usize>, Vec<Vec<usize>>) {
+    let mut raw_frames = vec![];
+    let mut origins = vec![];
+    let mut indices = HashMap::new();
+
+    let stacks = stacks
+        .into_iter()
+        .map(|stack| {
+            stack
+                .into_iter()
+                .enumerate()
+                .map(|(position, index)| {
+                    let frame = &frames[index];
+                    let adjust = frame.adjust_instruction_addr.unwrap_or(position > 0);
+                    *indices.entry((index, adjust)).or_insert_with(|| {
+                        raw_frames.push(RawFrame {
+                            adjust_instruction_addr: Some(adjust),
+                            ..frame.clone()
+                        });
+                        origins.push(index);
+                        raw_frames.len() - 1
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    (raw_frames, origins, stacks)
+}
+
+/// Groups the `symbolicated` frames by the raw frame they originate from.
+///
+/// Returns the table of symbolicated frames, and the range of frames in that table for every
+/// raw frame.
+fn build_frame_table(
+    raw_frames: Vec<RawFrame>,
+    symbolicated: Vec<SymbolicatedFrame>,
+) -> (Vec<SymbolicatedFrame>, Vec<Range<usize>>) {
+    let mut symbolicated = symbolicated.into_iter().peekable();
+    let mut frames = Vec::with_capacity(raw_frames.len());
+    let mut ranges = Vec::with_capacity(raw_frames.len());
+
+    for (index, raw) in raw_frames.into_iter().enumerate() {
+        let start = frames.len();
+        while let Some(frame) = symbolicated.next_if(|frame| frame.original_index == Some(index)) {
+            frames.push(frame);
+        }
+        // Symbolication might drop frames it considers bogus, keep them in the table.
+        if frames.len() == start {
+            frames.push(SymbolicatedFrame {
+                status: FrameStatus::UnknownImage,
+                original_index: Some(index),
+                raw,
+            });
+        }
+        ranges.push(start..frames.len());
+    }
+
+    (frames, ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::hex::HexValue;
+
+    use super::*;
+
+    fn frame(addr: u64) -> RawFrame {
+        RawFrame {
+            instruction_addr: HexValue(addr),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dedupe_frames() {
+        let frames = [frame(0x10), frame(0x20), frame(0x30)];
+        let stacks = vec![vec![0, 1, 2], vec![1, 2], vec![0, 1, 2]];
+
+        let (raw_frames, origins, stacks) = dedupe_frames(&frames, stacks);
+
+        // Frame 1 is both a leaf and a caller.
+        let raw: Vec<_> = raw_frames
+            .iter()
+            .map(|frame| (frame.instruction_addr.0, frame.adjust_instruction_addr))
+            .collect();
+        assert_eq!(
+            raw,
+            [
+                (0x10, Some(false)),
+                (0x20, Some(true)),
+                (0x30, Some(true)),
+                (0x20, Some(false)),
+            ]
+        );
+        assert_eq!(origins, [0, 1, 2, 1]);
+        assert_eq!(stacks, [vec![0, 1, 2], vec![3, 2], vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_build_frame_table() {
+        let raw_frames = vec![frame(0x10), frame(0x20), frame(0x30)];
+        let symbolicated = |index, function: &str| SymbolicatedFrame {
+            status: FrameStatus::Symbolicated,
+            original_index: Some(index),
+            raw: RawFrame {
+                function: Some(function.into()),
+                ..raw_frames[index].clone()
+            },
+        };
+        // The first frame expands to an inline frame, the last one was dropped.
+        let symbolicated = vec![
+            symbolicated(0, "inlined"),
+            symbolicated(0, "leaf"),
+            symbolicated(1, "caller"),
+        ];
+
+        let (frames, ranges) = build_frame_table(raw_frames, symbolicated);
+
+        assert_eq!(ranges, [0..2, 2..3, 3..4]);
+        assert_eq!(frames[1].raw.function.as_deref(), Some("leaf"));
+        assert_eq!(frames[3].status, FrameStatus::UnknownImage);
+        assert_eq!(frames[3].raw.instruction_addr.0, 0x30);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// The phase a running symbolication request is currently in.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolicationPhase {
+    /// The request has not started processing yet.
+    #[default]
+    Queued,
+    /// The minidump is being stackwalked, which includes fetching CFI for all its modules.
+    Stackwalking,
+    /// The debug files for the modules referenced by the stack traces are being fetched.
+    FetchingDebugFiles,
+    /// The stack traces are being symbolicated.
+    Symbolicating,
+    /// The source files for source context are being fetched.
+    FetchingSources,
+}
+
+/// A snapshot of the progress of a running symbolication request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SymbolicationProgress {
+    /// The phase the request is currently in.
+    pub phase: SymbolicationPhase,
+    /// The number of modules whose debug files have been fetched.
+    pub modules_fetched: usize,
+    /// The total number of modules whose debug files need to be fetched.
+    pub modules_total: usize,
+    /// The number of threads that have been symbolicated.
+    pub threads_processed: usize,
+    /// The total number of threads to symbolicate.
+    pub threads_total: usize,
+}
+
+/// A shared handle to the [`SymbolicationProgress`] of a request.
+///
+/// The running symbolication task updates the progress, while clones of this handle can be used
+/// to take [`snapshot`](Self::snapshot)s of it at any time.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressTracker(Arc<Mutex<SymbolicationProgress>>);
+
+impl ProgressTracker {
+    /// Returns a copy of the current progress.
+    pub fn snapshot(&self) -> SymbolicationProgress {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Moves the request into the given `phase`.
+    pub(crate) fn set_phase(&self, phase: SymbolicationPhase) {
+        self.update(|progress| progress.phase = phase);
+    }
+
+    /// Updates the progress using the given function.
+    pub(crate) fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut SymbolicationProgress),
+    {
+        f(&mut self.0.lock().unwrap())
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::caching::ObjectIdFilter;
+use crate::config::InMemoryCacheConfig;
+use crate::types::{CompletedSymbolicationResponse, SymbolicationOptions};
+
+use super::SymbolicateStacktraces;
+
+/// The sha-256 hash of a normalized [`SymbolicateStacktraces`] request.
+pub(super) type RequestHash = [u8; 32];
+
+/// An in-memory cache of complete symbolication responses.
+///
+/// Crash loops tend to produce lots of identical events in a short time. Keying the final response
+/// on a hash of the request allows us to skip the whole symbolication pipeline for these.
+#[derive(Clone, Debug)]
+pub(super) struct ResponseCache {
+    responses: Option<moka::sync::Cache<RequestHash, Arc<CompletedSymbolicationResponse>>>,
+}
+
+impl ResponseCache {
+    /// Creates a new [`ResponseCache`], which is disabled if the configured TTL is zero.
+    pub fn new(config: &InMemoryCacheConfig) -> Self {
+        let responses = (!config.responses_ttl.is_zero()).then(|| {
+            moka::sync::Cache::builder()
+                .max_capacity(config.responses_capacity)
+                .time_to_live(config.responses_ttl)
+                .support_invalidation_closures()
+                .build()
+        });
+        Self { responses }
+    }
+
+    /// Hashes the parts of the `request` and its `options` which have an influence on the response.
+    ///
+    /// Returns `None` if the cache is disabled.
+    pub fn hash_request(
+        &self,
+        request: &SymbolicateStacktraces,
+        options: &SymbolicationOptions,
+    ) -> Option<RequestHash> {
+        self.responses.as_ref()?;
+
+        let SymbolicateStacktraces {
+            scope,
+            signal,
+            sources,
+            // The origin is only used for metrics.
+            origin: _,
+            stacktraces,
+            modules,
+            kernel_space,
+        } = request;
+
+        let mut hasher = Sha256::new();
+        let normalized = (
+            scope,
+            signal,
+            &sources[..],
+            stacktraces,
+            modules,
+            kernel_space,
+            options,
+        );
+        serde_json::to_writer(&mut hasher, &normalized).ok()?;
+        Some(hasher.finalize().into())
+    }
+
+    /// Looks up the response for the request with the given hash.
+    pub fn get(&self, hash: &RequestHash) -> Option<CompletedSymbolicationResponse> {
+        let Some(response) = self.responses.as_ref()?.get(hash) else {
+            metric!(counter("symbolication.response_cache.miss") += 1);
+            return None;
+        };
+        metric!(counter("symbolication.response_cache.hit") += 1);
+        Some(CompletedSymbolicationResponse::clone(&response))
+    }
+
+    /// Stores the response for the request with the given hash.
+    ///
+    /// Partial responses are not stored, as a later request might be able to do better.
+    pub fn insert(&self, hash: RequestHash, response: &CompletedSymbolicationResponse) {
+        if let Some(responses) = &self.responses {
+            if response.partial.is_none() {
+                responses.insert(hash, Arc::new(response.clone()));
+            }
+        }
+    }
+
+    /// Drops all responses listing a module matched by `filter`.
+    pub fn invalidate_object(&self, filter: &ObjectIdFilter) {
+        let Some(responses) = &self.responses else {
+            return;
+        };
+
+        let filter = filter.clone();
+        let predicate = move |_: &RequestHash, response: &Arc<CompletedSymbolicationResponse>| {
+            response.modules.iter().any(|module| {
+                let raw = &module.raw;
+                [&raw.debug_id, &raw.code_id]
+                    .into_iter()
+                    .flatten()
+                    .any(|id| filter.matches(id))
+            })
+        };
+        if let Err(err) = responses.invalidate_entries_if(predicate) {
+            let error: &dyn std::error::Error = &err;
+            tracing::error!(error, "Failed to invalidate cached responses");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::services::symbolication::StacktraceOrigin;
+    use crate::types::{PartialReason, RawStacktrace, Scope};
+    use crate::utils::addr::KernelSpace;
+
+    use super::*;
+
+    fn request(scope: Scope) -> SymbolicateStacktraces {
+        SymbolicateStacktraces {
+            scope,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![RawStacktrace::default()],
+            modules: vec![],
+            kernel_space: KernelSpace::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_response_cache() {
+        let config = InMemoryCacheConfig {
+            responses_ttl: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let cache = ResponseCache::new(&config);
+
+        let options = SymbolicationOptions::default();
+        let hash = cache
+            .hash_request(&request(Scope::Global), &options)
+            .unwrap();
+        assert_eq!(
+            cache.hash_request(&request(Scope::Global), &options),
+            Some(hash)
+        );
+        let other_hash = cache.hash_request(&request(Scope::Scoped("1".into())), &options);
+        assert_ne!(other_hash, Some(hash));
+        let other_options = SymbolicationOptions {
+            function_sizes: true,
+            ..Default::default()
+        };
+        let other_hash = cache.hash_request(&request(Scope::Global), &other_options);
+        assert_ne!(other_hash, Some(hash));
+
+        assert!(cache.get(&hash).is_none());
+
+        let partial = CompletedSymbolicationResponse {
+            partial: Some(PartialReason::Timeout),
+            ..Default::default()
+        };
+        cache.insert(hash, &partial);
+        assert!(cache.get(&hash).is_none());
+
+        cache.insert(hash, &CompletedSymbolicationResponse::default());
+        assert!(cache.get(&hash).is_some());
+    }
+
+    #[test]
+    fn test_response_cache_disabled() {
+        let cache = ResponseCache::new(&InMemoryCacheConfig::default());
+        let options = SymbolicationOptions::default();
+        assert!(cache
+            .hash_request(&request(Scope::Global), &options)
+            .is_none());
+    }
+}
@@ -0,0 +1,191 @@
+//! Shadow mode, which compares an alternate implementation of the pipeline to the primary one.
+//!
+//! For requests sampled by the [`SHADOW_FLAG`] feature flag, symbolication runs a second time in
+//! parallel, with [`SymbolicationActor::is_shadow`](super::SymbolicationActor::is_shadow) set.
+//! Pipeline code can branch on that to run the alternate implementation. The shadow response is
+//! only compared to the primary one and then discarded.
+//!
+//! The [`frame_differences`] are also used to compare responses with those of another
+//! Symbolicator, for requests that ask for it.
+
+use crate::types::{CompletedSymbolicationResponse, FrameDifference};
+
+/// The feature flag sampling the requests which are also symbolicated in shadow mode.
+pub const SHADOW_FLAG: &str = "shadow";
+
+/// The differences between a primary and a shadow response.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct Divergence {
+    /// The number of frames which differ in their status or symbolicated data.
+    pub frames: usize,
+    /// The number of modules which differ in their debug or unwind status.
+    pub modules: usize,
+}
+
+impl Divergence {
+    /// Compares the `shadow` response to the `primary` one.
+    pub fn between(
+        primary: &CompletedSymbolicationResponse,
+        shadow: &CompletedSymbolicationResponse,
+    ) -> Self {
+        let frames = frame_differences(primary, shadow).len();
+
+        let mut modules = primary.modules.len().abs_diff(shadow.modules.len());
+        modules += primary
+            .modules
+            .iter()
+            .zip(&shadow.modules)
+            .filter(|(a, b)| a.debug_status != b.debug_status || a.unwind_status != b.unwind_status)
+            .count();
+
+        Self { frames, modules }
+    }
+
+    /// Whether the responses differ at all.
+    pub fn is_diverged(&self) -> bool {
+        self.frames > 0 || self.modules > 0
+    }
+}
+
+/// Lists the frames which differ in their status or symbolicated data between two responses.
+///
+/// Frames are compared by their position in the stack traces. Frames which only one of the
+/// responses has are listed as well.
+pub fn frame_differences(
+    ours: &CompletedSymbolicationResponse,
+    theirs: &CompletedSymbolicationResponse,
+) -> Vec<FrameDifference> {
+    let mut differences = vec![];
+
+    let threads = ours.stacktraces.len().max(theirs.stacktraces.len());
+    for thread in 0..threads {
+        let our_frames = ours
+            .stacktraces
+            .get(thread)
+            .map_or(&[][..], |trace| &trace.frames);
+        let their_frames = theirs
+            .stacktraces
+            .get(thread)
+            .map_or(&[][..], |trace| &trace.frames);
+
+        for frame in 0..our_frames.len().max(their_frames.len()) {
+            let our_frame = our_frames.get(frame);
+            let their_frame = their_frames.get(frame);
+            if let (Some(a), Some(b)) = (our_frame, their_frame) {
+                if a.status == b.status && a.raw == b.raw {
+                    continue;
+                }
+            }
+            differences.push(FrameDifference {
+                thread,
+                frame,
+                ours: our_frame.cloned(),
+                theirs: their_frame.cloned(),
+            });
+        }
+    }
+
+    differences
+}
+
+/// Compares the outcomes of the primary and shadow symbolication, and reports metrics about it.
+///
+/// Only the outcome is compared if either symbolication failed.
+pub(super) fn report(
+    primary: Option<&CompletedSymbolicationResponse>,
+    shadow: Option<&CompletedSymbolicationResponse>,
+) {
+    let outcome = match (primary, shadow) {
+        (Some(primary), Some(shadow)) => {
+            let divergence = Divergence::between(primary, shadow);
+            metric!(histogram("symbolication.shadow.diverged_frames") = divergence.frames as u64);
+            metric!(histogram("symbolication.shadow.diverged_modules") = divergence.modules as u64);
+            if divergence.is_diverged() {
+                tracing::debug!(?divergence, "Shadow symbolication diverged");
+                "diverged"
+            } else {
+                "identical"
+            }
+        }
+        (Some(_), None) => "shadow_failed",
+        (None, Some(_)) => "primary_failed",
+        (None, None) => "both_failed",
+    };
+
+    metric!(counter("symbolication.shadow.compared") += 1, "outcome" => outcome);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{CompleteStacktrace, FrameStatus, RawFrame, SymbolicatedFrame};
+
+    use super::*;
+
+    fn response(functions: &[Option<&str>]) -> CompletedSymbolicationResponse {
+        let frames = functions
+            .iter()
+            .map(|function| SymbolicatedFrame {
+                status: match function {
+                    Some(_) => FrameStatus::Symbolicated,
+                    None => FrameStatus::Missing,
+                },
+                raw: RawFrame {
+                    function: function.map(Into::into),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        CompletedSymbolicationResponse {
+            stacktraces: vec![CompleteStacktrace {
+                frames,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_divergence() {
+        let primary = response(&[Some("main"), Some("start"), None]);
+
+        let divergence = Divergence::between(&primary, &primary);
+        assert!(!divergence.is_diverged());
+
+        let shadow = response(&[Some("main"), Some("_start"), Some("libc_start")]);
+        let divergence = Divergence::between(&primary, &shadow);
+        assert_eq!(
+            divergence,
+            Divergence {
+                frames: 2,
+                modules: 0
+            }
+        );
+
+        let shadow = response(&[Some("main")]);
+        assert_eq!(Divergence::between(&primary, &shadow).frames, 2);
+        assert_eq!(Divergence::between(&shadow, &primary).frames, 2);
+    }
+
+    #[test]
+    fn test_frame_differences() {
+        let ours = response(&[Some("main"), Some("start")]);
+        let theirs = response(&[Some("main"), Some("_start"), Some("libc_start")]);
+
+        let differences = frame_differences(&ours, &theirs);
+        let positions: Vec<_> = differences.iter().map(|d| (d.thread, d.frame)).collect();
+        assert_eq!(positions, [(0, 1), (0, 2)]);
+        assert!(differences[1].ours.is_none());
+        assert_eq!(
+            differences[1]
+                .theirs
+                .as_ref()
+                .unwrap()
+                .raw
+                .function
+                .as_deref(),
+            Some("libc_start")
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::config::SourceLinkConfig;
+use crate::types::{RawFrame, Scope};
+
+/// Resolves links to source files in repositories, using the mappings which apply to a request.
+#[derive(Debug)]
+pub(super) struct SourceLinks<'a> {
+    mappings: Vec<&'a SourceLinkConfig>,
+    commit: Option<&'a str>,
+}
+
+impl<'a> SourceLinks<'a> {
+    /// Creates the source links for a request of the given `scope`, linking to `commit`.
+    pub fn new(mappings: &'a [SourceLinkConfig], scope: &Scope, commit: Option<&'a str>) -> Self {
+        let mappings = mappings
+            .iter()
+            .filter(|mapping| {
+                mapping.scopes.is_empty()
+                    || mapping.scopes.iter().any(|s| s.as_str() == scope.as_ref())
+            })
+            .collect();
+        Self { mappings, commit }
+    }
+
+    /// Returns the link to the source line of the `frame`, using the first matching mapping.
+    pub fn link(&self, frame: &RawFrame) -> Option<String> {
+        let abs_path = frame.abs_path.as_deref()?;
+        let line = frame.lineno?;
+
+        self.mappings.iter().find_map(|mapping| {
+            let path = strip_path_prefix(abs_path, &mapping.prefix)?;
+            substitute(&mapping.url, path, self.commit, line)
+        })
+    }
+}
+
+/// Characters which are percent-encoded in the substituted path segments and commit.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Strips `prefix` from `abs_path`, returning the remaining path without leading separators.
+///
+/// The prefix only matches at a path boundary, so `/build/app` does not match `/build/application`.
+fn strip_path_prefix<'p>(abs_path: &'p str, prefix: &str) -> Option<&'p str> {
+    let rest = abs_path.strip_prefix(prefix)?;
+    if !prefix.ends_with(is_separator) && !rest.starts_with(is_separator) {
+        return None;
+    }
+    let rest = rest.trim_start_matches(is_separator);
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// Substitutes the placeholders in `template` in a single pass.
+///
+/// Substituted values are never scanned for placeholders again. Each segment of the path is
+/// percent-encoded separately, and the link requires a `commit` if the template references it.
+fn substitute(template: &str, path: &str, commit: Option<&str>, line: u32) -> Option<String> {
+    let mut url = String::with_capacity(template.len() + path.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("{path}") {
+            for (i, segment) in path.split(is_separator).enumerate() {
+                if i > 0 {
+                    url.push('/');
+                }
+                url.extend(utf8_percent_encode(segment, SEGMENT));
+            }
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{commit}") {
+            url.extend(utf8_percent_encode(commit?, SEGMENT));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{line}") {
+            url.push_str(&line.to_string());
+            rest = after;
+        } else {
+            url.push('{');
+            rest = &rest[1..];
+        }
+    }
+    url.push_str(rest);
+
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(scopes: &[&str], prefix: &str, url: &str) -> SourceLinkConfig {
+        SourceLinkConfig {
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            prefix: prefix.into(),
+            url: url.into(),
+        }
+    }
+
+    fn frame(abs_path: &str, lineno: u32) -> RawFrame {
+        RawFrame {
+            abs_path: Some(abs_path.into()),
+            lineno: Some(lineno),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_source_links() {
+        let mappings = [
+            mapping(
+                &["1"],
+                "/build/app/",
+                "https://git.example.com/app/blob/{commit}/{path}#L{line}",
+            ),
+            mapping(
+                &[],
+                "C:\\build\\lib",
+                "https://example.com/lib/{path}#{line}",
+            ),
+        ];
+        let scope = Scope::Scoped("1".into());
+        let links = SourceLinks::new(&mappings, &scope, Some("abc123"));
+
+        assert_eq!(
+            links.link(&frame("/build/app/src/main.cpp", 12)).as_deref(),
+            Some("https://git.example.com/app/blob/abc123/src/main.cpp#L12")
+        );
+        assert_eq!(
+            links
+                .link(&frame("C:\\build\\lib\\src\\lib.cpp", 3))
+                .as_deref(),
+            Some("https://example.com/lib/src/lib.cpp#3")
+        );
+        assert_eq!(links.link(&frame("/usr/include/stdio.h", 1)), None);
+        assert_eq!(links.link(&RawFrame::default()), None);
+    }
+
+    #[test]
+    fn test_source_links_prefix_boundary() {
+        let mappings = [mapping(&[], "/build/app", "https://example.com/{path}")];
+        let links = SourceLinks::new(&mappings, &Scope::Global, None);
+
+        assert_eq!(
+            links.link(&frame("/build/app/main.cpp", 1)).as_deref(),
+            Some("https://example.com/main.cpp")
+        );
+        assert_eq!(links.link(&frame("/build/application/main.cpp", 1)), None);
+        assert_eq!(links.link(&frame("/build/app", 1)), None);
+    }
+
+    #[test]
+    fn test_source_links_encoding() {
+        let mappings = [mapping(
+            &[],
+            "/build/",
+            "https://example.com/{commit}/{path}#L{line}",
+        )];
+        let links = SourceLinks::new(&mappings, &Scope::Global, Some("{path}"));
+
+        // Substituted values are encoded and not substituted again.
+        assert_eq!(
+            links
+                .link(&frame("/build/src/a b#{line}?.cpp", 7))
+                .as_deref(),
+            Some("https://example.com/%7Bpath%7D/src/a%20b%23%7Bline%7D%3F.cpp#L7")
+        );
+    }
+
+    #[test]
+    fn test_source_links_scope_and_commit() {
+        let mappings = [mapping(
+            &["1"],
+            "/build/",
+            "https://example.com/{commit}/{path}#L{line}",
+        )];
+
+        // The mapping does not apply to other scopes.
+        let links = SourceLinks::new(&mappings, &Scope::Global, Some("abc123"));
+        assert_eq!(links.link(&frame("/build/main.cpp", 1)), None);
+
+        // The mapping requires a commit.
+        let links = SourceLinks::new(&mappings, &Scope::Scoped("1".into()), None);
+        assert_eq!(links.link(&frame("/build/main.cpp", 1)), None);
+    }
+}
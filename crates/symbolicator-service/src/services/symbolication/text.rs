@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use regex::Regex;
+
+use symbolic::common::split_path;
+use symbolicator_sources::SourceConfig;
+
+use crate::types::{
+    CompletedSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace, Scope, Signal,
+};
+use crate::utils::addr::{AddrMode, KernelSpace};
+use crate::utils::hex::HexValue;
+
+use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
+
+impl SymbolicationActor {
+    /// Symbolicates the frames found in an unstructured, textual backtrace.
+    ///
+    /// This supports Rust backtraces, the output of glibc `backtrace_symbols`, as well as frames
+    /// of the form `module+0xoffset`. Modules are referenced by their file name, and have to be
+    /// part of `modules` to be symbolicated.
+    pub async fn symbolicate_text(
+        &self,
+        scope: Scope,
+        signal: Option<Signal>,
+        backtrace: &str,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+    ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
+        let stacktraces = parse_text_backtrace(backtrace, &modules);
+
+        let request = SymbolicateStacktraces {
+            scope,
+            signal,
+            sources,
+            origin: StacktraceOrigin::Text,
+            stacktraces,
+            modules: modules.into_iter().map(From::from).collect(),
+            kernel_space: KernelSpace::Unknown,
+        };
+        self.symbolicate(request).await
+    }
+}
+
+lazy_static::lazy_static! {
+    /// A frame of a Rust backtrace, like `  3:     0x55d4c1a2b3c4 - std::rt::lang_start::h1234`.
+    static ref RUST_FRAME_REGEX: Regex = Regex::new(r"^\s*(?P<index>\d+):\s+0x(?P<addr>[0-9a-fA-F]+) - (?P<symbol>.+)$").unwrap();
+    /// A frame as printed by glibc `backtrace_symbols`, like `./prog(main+0x21) [0x4008e5]`.
+    static ref GLIBC_FRAME_REGEX: Regex = Regex::new(r"^(?P<module>[^\s(]+)\((?P<symbol>[^+)]*)(?:\+0x(?P<offset>[0-9a-fA-F]+))?\) \[0x(?P<addr>[0-9a-fA-F]+)\]$").unwrap();
+    /// A module-relative frame, like `libfoo.so+0x1234` or `libfoo.so + 0x1234`.
+    static ref MODULE_OFFSET_REGEX: Regex = Regex::new(r"(?P<module>[\w.\-/\\]+) ?\+ ?0x(?P<offset>[0-9a-fA-F]+)").unwrap();
+}
+
+/// Extracts the stack traces from an unstructured, textual backtrace.
+///
+/// Each line is matched against the following formats, and lines not matching any of them are
+/// ignored:
+///
+/// - Rust backtraces with addresses, as printed with `RUST_BACKTRACE=full`.
+/// - The output of glibc `backtrace_symbols`, which references modules by their path.
+/// - Frames of the form `module+0xoffset`, where the offset is relative to the start of the module.
+///
+/// Module references are resolved against the `code_file` or `debug_file` of the given `modules`
+/// by file name. Empty lines, as well as a Rust frame with index `0`, start a new stack trace.
+fn parse_text_backtrace(text: &str, modules: &[RawObjectInfo]) -> Vec<RawStacktrace> {
+    let mut stacktraces = vec![];
+    let mut current = RawStacktrace::default();
+
+    for line in text.lines() {
+        let line = line.trim_end();
+
+        let frame = if let Some(captures) = RUST_FRAME_REGEX.captures(line) {
+            if &captures["index"] == "0" && !current.frames.is_empty() {
+                stacktraces.push(std::mem::take(&mut current));
+            }
+            RawFrame {
+                instruction_addr: parse_hex(&captures["addr"]),
+                function: Some(captures["symbol"].to_owned()),
+                ..Default::default()
+            }
+        } else if let Some(captures) = GLIBC_FRAME_REGEX.captures(line) {
+            let symbol = &captures["symbol"];
+            let mut frame = RawFrame {
+                instruction_addr: parse_hex(&captures["addr"]),
+                package: Some(captures["module"].to_owned()),
+                symbol: (!symbol.is_empty()).then(|| symbol.to_owned()),
+                ..Default::default()
+            };
+            // Without a symbol, the offset is relative to the start of the module, which is
+            // more reliable than the absolute address if we know about that module.
+            let offset = captures.name("offset").filter(|_| symbol.is_empty());
+            let index = find_module(modules, &captures["module"]);
+            if let (Some(offset), Some(index)) = (offset, index) {
+                frame.addr_mode = AddrMode::Rel(index);
+                frame.instruction_addr = parse_hex(offset.as_str());
+            }
+            frame
+        } else if let Some(captures) = MODULE_OFFSET_REGEX.captures(line) {
+            let module = &captures["module"];
+            let Some(index) = find_module(modules, module) else {
+                continue;
+            };
+            RawFrame {
+                addr_mode: AddrMode::Rel(index),
+                instruction_addr: parse_hex(&captures["offset"]),
+                package: Some(module.to_owned()),
+                ..Default::default()
+            }
+        } else {
+            if line.is_empty() && !current.frames.is_empty() {
+                stacktraces.push(std::mem::take(&mut current));
+            }
+            continue;
+        };
+
+        current.frames.push(frame);
+    }
+
+    if !current.frames.is_empty() {
+        stacktraces.push(current);
+    }
+
+    stacktraces
+}
+
+pub(super) fn parse_hex(s: &str) -> HexValue {
+    HexValue(u64::from_str_radix(s, 16).unwrap_or_default())
+}
+
+/// Finds the index of the module with the given path, comparing file names only.
+pub(super) fn find_module(modules: &[RawObjectInfo], path: &str) -> Option<usize> {
+    let name = split_path(path).1;
+    modules.iter().position(|module| {
+        [&module.code_file, &module.debug_file]
+            .into_iter()
+            .flatten()
+            .any(|file| split_path(file).1 == name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use symbolicator_sources::ObjectType;
+
+    use super::*;
+
+    fn module(code_file: &str) -> RawObjectInfo {
+        RawObjectInfo {
+            ty: ObjectType::Elf,
+            code_id: None,
+            code_file: Some(code_file.into()),
+            debug_id: None,
+            debug_file: None,
+            debug_checksum: None,
+            image_addr: HexValue(0x5555_5555_4000),
+            slide: None,
+            image_size: None,
+        }
+    }
+
+    fn frames(stacktrace: &RawStacktrace) -> Vec<(AddrMode, u64)> {
+        stacktrace
+            .frames
+            .iter()
+            .map(|frame| (frame.addr_mode, frame.instruction_addr.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_backtrace() {
+        let text = "\
+thread 'main' panicked at src/main.rs:2:5:
+boom
+stack backtrace:
+   0:     0x55d4c1a2b3c4 - std::backtrace_rs::backtrace::libunwind::trace::h5d9d6e2ba1a2ac25
+                               at /rustc/library/std/src/../../backtrace/src/backtrace/libunwind.rs:93:5
+   1:     0x55d4c1a2b4d5 - app::main::h0123456789abcdef
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+        let stacktraces = parse_text_backtrace(text, &[]);
+
+        assert_eq!(stacktraces.len(), 1);
+        assert_eq!(
+            frames(&stacktraces[0]),
+            [
+                (AddrMode::Abs, 0x55d4c1a2b3c4),
+                (AddrMode::Abs, 0x55d4c1a2b4d5)
+            ]
+        );
+        assert_eq!(
+            stacktraces[0].frames[1].function.as_deref(),
+            Some("app::main::h0123456789abcdef")
+        );
+    }
+
+    #[test]
+    fn test_glibc_backtrace() {
+        let modules = [module("/usr/bin/prog"), module("libc.so.6")];
+        let text = "\
+./prog(+0x8e5) [0x5555555548e5]
+./prog(main+0x21) [0x555555554901]
+/lib/x86_64-linux-gnu/libc.so.6(__libc_start_main+0xf0) [0x7ffff7a2d830]
+
+./other(+0x10) [0x400010]
+";
+        let stacktraces = parse_text_backtrace(text, &modules);
+
+        assert_eq!(stacktraces.len(), 2);
+        assert_eq!(
+            frames(&stacktraces[0]),
+            [
+                (AddrMode::Rel(0), 0x8e5),
+                (AddrMode::Abs, 0x555555554901),
+                (AddrMode::Abs, 0x7ffff7a2d830)
+            ]
+        );
+        let frame = &stacktraces[0].frames[2];
+        assert_eq!(frame.symbol.as_deref(), Some("__libc_start_main"));
+        assert_eq!(
+            frame.package.as_deref(),
+            Some("/lib/x86_64-linux-gnu/libc.so.6")
+        );
+
+        // Unknown modules fall back to the absolute address.
+        assert_eq!(frames(&stacktraces[1]), [(AddrMode::Abs, 0x400010)]);
+    }
+
+    #[test]
+    fn test_module_offset_backtrace() {
+        let modules = [module("C:\\app\\app.exe"), module("libfoo.so")];
+        let text = "\
+#0 libfoo.so + 0x1234
+#1 app.exe+0xabc
+#2 unknown.dll+0x10
+";
+        let stacktraces = parse_text_backtrace(text, &modules);
+
+        assert_eq!(stacktraces.len(), 1);
+        assert_eq!(
+            frames(&stacktraces[0]),
+            [(AddrMode::Rel(1), 0x1234), (AddrMode::Rel(0), 0xabc)]
+        );
+    }
+}
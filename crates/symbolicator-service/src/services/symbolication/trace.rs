@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{FrameStatus, ObjectCandidate, ObjectFileStatus};
+use crate::utils::hex::HexValue;
+
+use super::SymbolicationPhase;
+
+/// A decision made while processing a request, as captured in its trace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEvent {
+    /// The number of milliseconds since the request started processing.
+    pub elapsed_ms: u64,
+    /// What happened.
+    #[serde(flatten)]
+    pub kind: TraceEventKind,
+}
+
+/// The kinds of [`TraceEvent`]s.
+///
+/// Modules are referred to by their index in the `modules` list of the response, and frames by
+/// the index of their thread in `stacktraces` and their index within that thread.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEventKind {
+    /// The request moved into a new phase.
+    Phase {
+        /// The phase which was entered.
+        phase: SymbolicationPhase,
+    },
+    /// An identical request was symbolicated recently, and its response was reused.
+    ResponseCacheHit,
+    /// The minidump was stackwalked.
+    Stackwalk {
+        /// How long stackwalking took, including fetching unwind information.
+        duration_ms: u64,
+        /// The number of threads which were walked.
+        threads: usize,
+        /// The number of modules which were listed in the minidump.
+        modules: usize,
+    },
+    /// Unwind information was looked up for a module during stackwalking.
+    UnwindInfo {
+        /// The index of the module.
+        module: usize,
+        /// Whether unwind information was found.
+        status: ObjectFileStatus,
+        /// The files which were probed on the sources, and why they were used or rejected.
+        candidates: Vec<ObjectCandidate>,
+    },
+    /// Debug information was fetched for a module referenced by the stack traces.
+    DebugInfo {
+        /// The index of the module.
+        module: usize,
+        /// Whether debug information was found.
+        status: ObjectFileStatus,
+        /// How long fetching took, including downloading and converting files.
+        duration_ms: u64,
+        /// The files which were probed on the sources, and why they were used or rejected.
+        candidates: Vec<ObjectCandidate>,
+    },
+    /// A frame could not be symbolicated.
+    UnsymbolicatedFrame {
+        /// The index of the thread of the frame.
+        thread: usize,
+        /// The index of the frame within its thread.
+        frame: usize,
+        /// The instruction address of the frame.
+        instruction_addr: HexValue,
+        /// Why the frame was not symbolicated.
+        status: FrameStatus,
+        /// The index of the module containing the instruction address, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        module: Option<usize>,
+    },
+}
+
+#[derive(Debug)]
+struct TraceState {
+    start: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+/// A shared handle to the trace of a request.
+///
+/// Requests without a trace use a disabled recorder, which is the default and discards all events
+/// without constructing them.
+#[derive(Debug, Clone, Default)]
+pub struct TraceRecorder(Option<Arc<TraceState>>);
+
+impl TraceRecorder {
+    /// Creates a recorder which captures events if `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled.then(|| {
+            Arc::new(TraceState {
+                start: Instant::now(),
+                events: Mutex::default(),
+            })
+        }))
+    }
+
+    /// Returns the events recorded so far, or `None` if the recorder is disabled.
+    pub fn events(&self) -> Option<Vec<TraceEvent>> {
+        let state = self.0.as_ref()?;
+        let events = state.events.lock().unwrap().clone();
+        Some(events)
+    }
+
+    /// Records the event created by `f`, if the recorder is enabled.
+    pub(crate) fn record<F>(&self, f: F)
+    where
+        F: FnOnce() -> TraceEventKind,
+    {
+        if let Some(ref state) = self.0 {
+            let event = TraceEvent {
+                elapsed_ms: state.start.elapsed().as_millis() as u64,
+                kind: f(),
+            };
+            state.events.lock().unwrap().push(event);
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use std::fmt::Write;
-use std::fs::File;
 use std::io::{self, BufWriter};
 use std::sync::Arc;
 use std::time::Duration;
@@ -12,7 +11,8 @@ use symbolic::symcache::{SymCache, SymCacheConverter};
 use symbolicator_sources::{FileType, ObjectId, ObjectType, SourceConfig};
 
 use crate::caching::{
-    Cache, CacheEntry, CacheError, CacheItemRequest, CacheVersions, Cacher, SharedCacheRef,
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheName, CacheVersions, Cacher,
+    ObjectIdFilter, SharedCacheRef,
 };
 use crate::services::bitcode::BitcodeService;
 use crate::services::objects::{
@@ -24,7 +24,9 @@ use crate::utils::sentry::ConfigureScope;
 
 use super::bitcode::BcSymbolMapHandle;
 use super::caches::versions::SYMCACHE_VERSIONS;
-use super::derived::{derive_from_object_handle, DerivedCache};
+use super::derived::{
+    convert_with_budget, derive_from_object_handle, ConversionFile, ConversionLimits, DerivedCache,
+};
 use super::il2cpp::{Il2cppHandle, Il2cppService};
 
 pub type OwnedSymCache = SelfCell<ByteView<'static>, SymCache<'static>>;
@@ -44,6 +46,7 @@ pub struct SymCacheActor {
     objects: ObjectsActor,
     bitcode_svc: BitcodeService,
     il2cpp_svc: Il2cppService,
+    conversion_limits: ConversionLimits,
 }
 
 impl SymCacheActor {
@@ -55,12 +58,30 @@ impl SymCacheActor {
         il2cpp_svc: Il2cppService,
     ) -> Self {
         SymCacheActor {
+            conversion_limits: ConversionLimits::from_cache(&cache),
             symcaches: Arc::new(Cacher::new(cache, shared_cache)),
             objects,
             bitcode_svc,
             il2cpp_svc,
         }
     }
+
+    /// Returns a copy of this actor which does not cache the SymCaches it computes.
+    ///
+    /// See [`Cacher::read_only`].
+    pub fn read_only(&self) -> Self {
+        Self {
+            symcaches: Arc::new(self.symcaches.read_only()),
+            ..self.clone()
+        }
+    }
+
+    /// Invalidates the negative SymCache entries of the object matched by `filter`.
+    ///
+    /// Returns the number of entries removed from the file system.
+    pub fn invalidate_negative(&self, filter: &ObjectIdFilter) -> std::io::Result<usize> {
+        self.symcaches.invalidate_negative(filter)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -73,6 +94,9 @@ struct FetchSymCacheInternal {
 
     /// ObjectMeta handle of the original DIF object to fetch.
     object_meta: Arc<ObjectMetaHandle>,
+
+    /// The limits on the conversion into a SymCache.
+    conversion_limits: ConversionLimits,
 }
 
 /// Fetches the needed DIF objects and spawns symcache computation.
@@ -90,10 +114,17 @@ async fn fetch_difs_and_compute_symcache(
     objects_actor: &ObjectsActor,
     object_meta: Arc<ObjectMetaHandle>,
     secondary_sources: SecondarySymCacheSources,
+    conversion_limits: &ConversionLimits,
 ) -> CacheEntry {
     let object_handle = objects_actor.fetch(object_meta.clone()).await?;
 
-    write_symcache(temp_file.as_file_mut(), &object_handle, secondary_sources)
+    convert_with_budget(
+        CacheName::Symcaches,
+        conversion_limits,
+        temp_file,
+        move |file| write_symcache(file, &object_handle, secondary_sources),
+    )
+    .await
 }
 
 impl CacheItemRequest for FetchSymCacheInternal {
@@ -107,6 +138,7 @@ impl CacheItemRequest for FetchSymCacheInternal {
             &self.objects_actor,
             self.object_meta.clone(),
             self.secondary_sources.clone(),
+            &self.conversion_limits,
         );
 
         let timeout = Duration::from_secs(1200);
@@ -201,6 +233,7 @@ impl SymCacheActor {
                 objects_actor: self.objects.clone(),
                 secondary_sources,
                 object_meta: Arc::clone(&handle),
+                conversion_limits: self.conversion_limits.clone(),
             };
             self.symcaches.compute_memoized(request, cache_key).await
         })
@@ -221,7 +254,7 @@ struct SecondarySymCacheSources {
 /// Any secondary source can only exist for a positive cache so does not have this issue.
 #[tracing::instrument(skip_all)]
 fn write_symcache(
-    file: &mut File,
+    file: &mut ConversionFile,
     object_handle: &ObjectHandle,
     secondary_sources: SecondarySymCacheSources,
 ) -> CacheEntry {
@@ -277,11 +310,11 @@ fn write_symcache(
 
         CacheError::Malformed(e.to_string())
     })?;
+    file.ensure_active()?;
 
     let mut writer = BufWriter::new(file);
     converter.serialize(&mut writer)?;
-    let file = writer.into_inner().map_err(io::Error::from)?;
-    file.sync_all()?;
+    writer.into_inner().map_err(io::Error::from)?;
 
     Ok(())
 }
@@ -296,7 +329,7 @@ mod tests {
 
     use super::*;
     use crate::caching::Caches;
-    use crate::config::{CacheConfigs, Config};
+    use crate::config::{CacheConfigs, Config, ReservedIpsPolicy};
     use crate::services::bitcode::BitcodeService;
     use crate::services::DownloadService;
     use crate::test::{self, fixture};
@@ -312,7 +345,7 @@ mod tests {
 
         let config = Config {
             cache_dir: Some(cache_dir),
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
             caches: cache_config,
             ..Default::default()
         };
@@ -374,6 +407,7 @@ mod tests {
         let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
             id: SourceId::new("local"),
             path: symbol_dir.path().to_owned(),
+            index_ttl: None,
             files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
         }));
 
@@ -10,12 +10,14 @@ use std::fmt;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use symbolic::common::{Arch, CodeId, DebugId, Language};
-use symbolicator_sources::ObjectType;
+use symbolicator_sources::{Glob, ObjectType};
 
-use crate::utils::addr::AddrMode;
+use crate::services::symbolication::TraceEvent;
+use crate::utils::addr::{AddrMode, KernelSpace};
 use crate::utils::hex::HexValue;
 
 mod objects;
+mod sentry_event;
 
 pub use objects::{
     AllObjectCandidates, CandidateStatus, ObjectCandidate, ObjectDownloadInfo, ObjectUseInfo,
@@ -59,6 +61,159 @@ impl fmt::Display for Scope {
     }
 }
 
+/// Options which control how frames are symbolicated.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SymbolicationOptions {
+    /// Whether to determine the size of the function native frames are located in.
+    ///
+    /// SymCaches do not record the extent of functions, so this requires a number of additional
+    /// lookups per frame. See [`RawFrame::sym_size`].
+    #[serde(default)]
+    pub function_sizes: bool,
+
+    /// The language to demangle symbols as if the debug file does not specify it.
+    ///
+    /// If not given, the language is detected from the mangled name.
+    #[serde(default)]
+    pub demangle_language: Option<Language>,
+
+    /// Whether demangled function names include their argument lists.
+    ///
+    /// If not given, the configured default for the language of the symbol is used.
+    #[serde(default)]
+    pub demangle_parameters: Option<bool>,
+
+    /// Whether to return the mangled name as [`symbol`](RawFrame::symbol) alongside the demangled
+    /// [`function`](RawFrame::function) name.
+    #[serde(default = "default_include_symbols")]
+    pub include_symbols: bool,
+
+    /// The maximum number of inline frames each native frame is expanded into.
+    ///
+    /// The inline frames closest to the function containing the instruction are kept, so that
+    /// each frame's line refers to the call of the next frame. `0` disables expansion, returning
+    /// only physical frames. If not given, all inline frames are returned.
+    #[serde(default)]
+    pub max_inline_frames: Option<usize>,
+
+    /// The commit of the source code repository to link to.
+    ///
+    /// This is required for configured source links which reference the `{commit}`.
+    #[serde(default)]
+    pub source_commit: Option<String>,
+
+    /// Rules to classify frames as [`in_app`](RawFrame::in_app).
+    ///
+    /// These take precedence over the rules configured in Symbolicator.
+    #[serde(default)]
+    pub in_app_rules: Vec<InAppRule>,
+
+    /// How the Thumb bit of ARM32 instruction addresses is treated when looking them up.
+    #[serde(default)]
+    pub thumb_bit: ThumbBit,
+
+    /// Rules to classify the [`package_kind`](RawFrame::package_kind) of frames.
+    ///
+    /// These take precedence over the rules configured in Symbolicator.
+    #[serde(default)]
+    pub package_rules: Vec<PackageRule>,
+}
+
+fn default_include_symbols() -> bool {
+    true
+}
+
+impl Default for SymbolicationOptions {
+    fn default() -> Self {
+        Self {
+            function_sizes: false,
+            demangle_language: None,
+            demangle_parameters: None,
+            include_symbols: default_include_symbols(),
+            max_inline_frames: None,
+            source_commit: None,
+            in_app_rules: vec![],
+            thumb_bit: ThumbBit::default(),
+            package_rules: vec![],
+        }
+    }
+}
+
+/// The treatment of the Thumb bit, the lowest bit of ARM32 addresses in Thumb code.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbBit {
+    /// The bit is cleared along with the instruction address heuristics, which are only applied
+    /// to images that support absolute addresses.
+    #[default]
+    Auto,
+    /// The bit is always cleared, including in frames of images mounted at `0`.
+    Mask,
+    /// The bit is kept as reported, for debug files whose symbol addresses include it.
+    Preserve,
+}
+
+/// A rule which classifies matching frames as [`in_app`](RawFrame::in_app) or not.
+///
+/// A rule without any patterns matches all frames.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InAppRule {
+    /// A glob matched against the absolute path of the source file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Glob>,
+
+    /// A glob matched against the path of the module the frame is located in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<Glob>,
+
+    /// Whether matching frames are in-app.
+    pub in_app: bool,
+}
+
+impl InAppRule {
+    /// Returns whether all the patterns of this rule match the `frame`.
+    pub fn matches(&self, frame: &RawFrame) -> bool {
+        let matches = |pattern: &Option<Glob>, value: &Option<String>| match pattern {
+            Some(pattern) => value
+                .as_deref()
+                .map_or(false, |value| pattern.matches(value)),
+            None => true,
+        };
+        matches(&self.path, &frame.abs_path) && matches(&self.module, &frame.package)
+    }
+}
+
+/// The kind of package the module of a frame belongs to.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageKind {
+    /// A library or framework of the operating system.
+    System,
+    /// A third-party library shipped with the application.
+    Dependency,
+    /// The code of the application itself.
+    App,
+}
+
+/// A rule which classifies the [`package_kind`](RawFrame::package_kind) of frames by their module.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PackageRule {
+    /// Globs matched against the path of the module the frame is located in.
+    pub modules: Vec<Glob>,
+
+    /// The kind of package of matching modules.
+    pub kind: PackageKind,
+}
+
+impl PackageRule {
+    /// Returns whether any of the module patterns of this rule match the `frame`.
+    pub fn matches(&self, frame: &RawFrame) -> bool {
+        frame.package.as_deref().map_or(false, |package| {
+            self.modules.iter().any(|pattern| pattern.matches(package))
+        })
+    }
+}
+
 /// A map of register values.
 pub type Registers = BTreeMap<String, HexValue>;
 
@@ -124,6 +279,13 @@ pub struct RawFrame {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sym_addr: Option<HexValue>,
 
+    /// Size of the function this frame is located in, in bytes.
+    ///
+    /// This is only set alongside [`sym_addr`](Self::sym_addr), and only if requested via
+    /// [`SymbolicationOptions::function_sizes`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sym_size: Option<u64>,
+
     /// The demangled function name.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub function: Option<String>,
@@ -152,6 +314,22 @@ pub struct RawFrame {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub post_context: Vec<String>,
 
+    /// A link to the line in the source code repository, based on the configured source links.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_link: Option<String>,
+
+    /// Whether the frame is part of the application, as opposed to a system or third-party library.
+    ///
+    /// Frames without a value are classified using the [`InAppRule`]s of the request and config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_app: Option<bool>,
+
+    /// The kind of package the module of the frame belongs to.
+    ///
+    /// Frames without a value are classified using the [`PackageRule`]s of the request and config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_kind: Option<PackageKind>,
+
     /// Information about how the raw frame was created.
     #[serde(default, skip_serializing_if = "is_default_value")]
     pub trust: FrameTrust,
@@ -261,6 +439,15 @@ pub struct RawObjectInfo {
     #[serde(default)]
     pub image_addr: HexValue,
 
+    /// Offset by which the image was moved from its preferred address, if frames of this image
+    /// are reported with unslid addresses.
+    ///
+    /// Some crash reports list the slid `image_addr` of images, but the unslid instruction
+    /// addresses of frames. The slide is taken off the `image_addr` for addresses of such frames
+    /// to be relative to the image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slide: Option<HexValue>,
+
     /// Size of the image in virtual memory.
     ///
     /// The size is infered from the module list if not specified.
@@ -440,7 +627,7 @@ impl CompleteObjectInfo {
     /// If it does not fit into the object `None` is returned.
     pub fn abs_to_rel_addr(&self, addr: u64) -> Option<u64> {
         if self.supports_absolute_addresses() {
-            addr.checked_sub(self.raw.image_addr.0)
+            addr.checked_sub(self.base_addr())
         } else {
             None
         }
@@ -452,18 +639,33 @@ impl CompleteObjectInfo {
     /// case this returns `None`.
     pub fn rel_to_abs_addr(&self, addr: u64) -> Option<u64> {
         if self.supports_absolute_addresses() {
-            self.raw.image_addr.0.checked_add(addr)
+            self.base_addr().checked_add(addr)
         } else {
             None
         }
     }
 
+    /// Returns the absolute address that addresses within this image are relative to.
+    ///
+    /// This is the `image_addr`, unless the image has a `slide` because its frames are reported
+    /// with unslid addresses. In that case, the slide is subtracted.
+    pub fn base_addr(&self) -> u64 {
+        let slide = self.raw.slide.map_or(0, |slide| slide.0);
+        self.raw.image_addr.0.wrapping_sub(slide)
+    }
+
     /// Checks if this image supports absolute addressing.
     ///
     /// Per definition images at 0 do not support absolute addresses.
     pub fn supports_absolute_addresses(&self) -> bool {
         self.raw.image_addr.0 != 0
     }
+
+    /// Checks if this image is loaded into the given `kernel` space, such as a driver or kernel
+    /// extension.
+    pub fn is_kernel_module(&self, kernel: KernelSpace) -> bool {
+        kernel.contains(self.base_addr())
+    }
 }
 
 impl From<RawObjectInfo> for CompleteObjectInfo {
@@ -491,14 +693,47 @@ impl From<RawObjectInfo> for CompleteObjectInfo {
     }
 }
 
+/// The reason why a [`CompletedSymbolicationResponse`] only contains partial results.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialReason {
+    /// The request ran out of time while fetching debug files, so modules which were still
+    /// being fetched are marked as [`ObjectFileStatus::Timeout`] and their frames are left
+    /// unsymbolicated.
+    Timeout,
+}
+
+/// The number of threads, modules and memory regions of a minidump which were not processed,
+/// because they exceeded the configured caps.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Truncation {
+    /// The number of threads which were not stackwalked.
+    pub threads: usize,
+    /// The number of modules which were dropped.
+    pub modules: usize,
+    /// The number of memory regions which were dropped.
+    pub memory_regions: usize,
+}
+
 /// A wrapper around possible completed endpoint responses.
 ///
 /// This allows us to support multiple independent types of symbolication.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum CompletedResponse {
+    // NOTE: This has to come first, as native responses without any frames would otherwise also
+    // match it. Only JVM responses have `exceptions`.
+    JvmSymbolication(CompletedJvmSymbolicationResponse),
     NativeSymbolication(CompletedSymbolicationResponse),
     JsSymbolication(CompletedJsSymbolicationResponse),
+    ProfileSymbolication(CompletedProfileSymbolicationResponse),
+    ProfileChunkSymbolication(CompletedProfileChunkResponse),
+    // NOTE: This has to come after the other symbolication responses, as it would otherwise also
+    // match native responses without any frames.
+    HybridSymbolication(CompletedHybridSymbolicationResponse),
+    // A native response converted into a Sentry event. This matches any JSON object, so it has
+    // to come last.
+    SentryEvent(serde_json::Value),
 }
 
 impl From<CompletedSymbolicationResponse> for CompletedResponse {
@@ -507,12 +742,58 @@ impl From<CompletedSymbolicationResponse> for CompletedResponse {
     }
 }
 
+impl From<CompletedJvmSymbolicationResponse> for CompletedResponse {
+    fn from(response: CompletedJvmSymbolicationResponse) -> Self {
+        Self::JvmSymbolication(response)
+    }
+}
+
 impl From<CompletedJsSymbolicationResponse> for CompletedResponse {
     fn from(response: CompletedJsSymbolicationResponse) -> Self {
         Self::JsSymbolication(response)
     }
 }
 
+impl From<CompletedProfileSymbolicationResponse> for CompletedResponse {
+    fn from(response: CompletedProfileSymbolicationResponse) -> Self {
+        Self::ProfileSymbolication(response)
+    }
+}
+
+impl From<CompletedProfileChunkResponse> for CompletedResponse {
+    fn from(response: CompletedProfileChunkResponse) -> Self {
+        Self::ProfileChunkSymbolication(response)
+    }
+}
+
+impl From<CompletedHybridSymbolicationResponse> for CompletedResponse {
+    fn from(response: CompletedHybridSymbolicationResponse) -> Self {
+        Self::HybridSymbolication(response)
+    }
+}
+
+/// A [`CompletedResponse`] with an explicit `type`, to store responses and load them again.
+///
+/// The variants of the untagged [`CompletedResponse`] cannot always be told apart by their
+/// contents. For instance, a hybrid response without JavaScript frames looks just like a native
+/// response. Use this with `#[serde(with = "TaggedCompletedResponse")]`.
+#[derive(Deserialize, Serialize)]
+#[serde(
+    remote = "CompletedResponse",
+    tag = "type",
+    content = "response",
+    rename_all = "snake_case"
+)]
+pub enum TaggedCompletedResponse {
+    JvmSymbolication(CompletedJvmSymbolicationResponse),
+    NativeSymbolication(CompletedSymbolicationResponse),
+    JsSymbolication(CompletedJsSymbolicationResponse),
+    ProfileSymbolication(CompletedProfileSymbolicationResponse),
+    ProfileChunkSymbolication(CompletedProfileChunkResponse),
+    HybridSymbolication(CompletedHybridSymbolicationResponse),
+    SentryEvent(serde_json::Value),
+}
+
 /// The symbolicated crash data.
 ///
 /// It contains the symbolicated stack frames, module information as well as other
@@ -558,11 +839,85 @@ pub struct CompletedSymbolicationResponse {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub assertion: Option<String>,
 
+    /// Set if symbolication did not run to completion, in which case the stack traces and
+    /// modules are only partially symbolicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialReason>,
+
+    /// Set if parts of a minidump were not processed, because they exceeded the configured caps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncation>,
+
     /// The threads containing symbolicated stack frames.
     pub stacktraces: Vec<CompleteStacktrace>,
 
     /// A list of images, extended with status information.
     pub modules: Vec<CompleteObjectInfo>,
+
+    /// The decisions made while processing the request, if a trace was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceEvent>>,
+}
+
+/// A frame which two responses differ in.
+///
+/// Frames are compared by their position, so a frame which is missing in one of the responses
+/// shifts all frames after it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FrameDifference {
+    /// The index of the stack trace.
+    pub thread: usize,
+
+    /// The index of the frame within the stack trace.
+    pub frame: usize,
+
+    /// The frame of this response, if it has one at this position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ours: Option<SymbolicatedFrame>,
+
+    /// The frame of the other response, if it has one at this position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theirs: Option<SymbolicatedFrame>,
+}
+
+/// The result of symbolicating a textual profile.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CompletedProfileSymbolicationResponse {
+    /// Set if symbolication did not run to completion, in which case the stacks are only
+    /// partially symbolicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialReason>,
+
+    /// The symbolicated stacks in the folded format, with one `root;caller;leaf count` line per
+    /// unique stack.
+    pub folded: String,
+
+    /// A list of images, extended with status information.
+    pub modules: Vec<CompleteObjectInfo>,
+}
+
+/// The result of symbolicating a chunk of profiler samples.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CompletedProfileChunkResponse {
+    /// Set if symbolication did not run to completion, in which case the frames are only
+    /// partially symbolicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialReason>,
+
+    /// The table of symbolicated frames.
+    ///
+    /// The [`original_index`](SymbolicatedFrame::original_index) of each frame refers to the
+    /// frame in the request it originates from.
+    pub frames: Vec<SymbolicatedFrame>,
+
+    /// The symbolicated stacks, each being a list of indices into [`frames`](Self::frames),
+    /// with the leaf frame first.
+    ///
+    /// These are in the same order as in the request, with inline frames expanded.
+    pub stacks: Vec<Vec<usize>>,
+
+    /// A list of images, extended with status information.
+    pub modules: Vec<CompleteObjectInfo>,
 }
 
 /// Information on the symbolication status of this JavaScript frame.
@@ -651,3 +1006,172 @@ pub struct JsStacktrace {
 pub struct SymbolicatedJsStacktrace {
     pub frames: Vec<SymbolicatedJsFrame>,
 }
+
+/// A frame of a JVM stack trace.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct JvmFrame {
+    /// The name of the method, as in `onClick`.
+    pub function: String,
+
+    /// The name of the source file, as in `MainActivity.java`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    /// The fully qualified name of the class, as in `io.sentry.sample.MainActivity`.
+    pub module: String,
+
+    /// The line number in the source file, starting at `1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lineno: Option<u32>,
+
+    /// The index of the frame in the stack trace of the request.
+    ///
+    /// A frame is expanded into several if methods were inlined into it, which all share the
+    /// index of the obfuscated frame.
+    #[serde(default)]
+    pub index: usize,
+}
+
+/// An exception thrown in a JVM program.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct JvmException {
+    /// The simple name of the exception class, as in `IllegalStateException`.
+    #[serde(rename = "type")]
+    pub ty: String,
+
+    /// The package of the exception class, as in `java.lang`.
+    pub module: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct JvmStacktrace {
+    pub frames: Vec<JvmFrame>,
+}
+
+/// A ProGuard mapping file that is used to deobfuscate a JVM event.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct JvmModule {
+    /// The UUID of the mapping file.
+    pub uuid: DebugId,
+}
+
+/// The reason why a ProGuard mapping file could not be used.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProguardErrorKind {
+    /// The mapping file was not found on any of the sources.
+    Missing,
+    /// The mapping file could not be downloaded or is malformed.
+    Invalid,
+}
+
+/// A ProGuard mapping file that could not be used.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProguardError {
+    /// The UUID of the mapping file.
+    pub uuid: DebugId,
+    /// The reason why it could not be used.
+    #[serde(rename = "type")]
+    pub kind: ProguardErrorKind,
+}
+
+/// The deobfuscated exceptions and stack traces of a JVM event.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CompletedJvmSymbolicationResponse {
+    pub exceptions: Vec<JvmException>,
+    pub stacktraces: Vec<JvmStacktrace>,
+    /// The mapping files of the request which could not be used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ProguardError>,
+}
+
+/// A frame of a [`HybridStacktrace`], which is either a native or a JavaScript frame.
+///
+/// The kind of frame is determined by its `platform` field.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "platform", rename_all = "lowercase")]
+pub enum HybridFrame {
+    /// A native frame, symbolicated using the debug files of the native modules.
+    Native(RawFrame),
+    /// A JavaScript frame, symbolicated using source maps.
+    Javascript(JsFrame),
+}
+
+/// A stack trace which mixes native and JavaScript frames, as found in Electron or React Native
+/// applications.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HybridStacktrace {
+    /// The OS-dependent identifier of the thread.
+    #[serde(default)]
+    pub thread_id: Option<u64>,
+
+    /// The name of the thread.
+    #[serde(default)]
+    pub thread_name: Option<String>,
+
+    /// `true` if this thread triggered the report. Usually indicates that this trace crashed.
+    #[serde(default)]
+    pub is_requesting: Option<bool>,
+
+    /// Values of CPU registers in the top native frame in the trace.
+    #[serde(default)]
+    pub registers: Registers,
+
+    /// A list of unsymbolicated stack frames.
+    ///
+    /// The first entry in the list is the active frame, with its callers below.
+    pub frames: Vec<HybridFrame>,
+}
+
+/// A potentially symbolicated frame of a [`SymbolicatedHybridStacktrace`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "platform", rename_all = "lowercase")]
+pub enum SymbolicatedHybridFrame {
+    /// A native frame.
+    ///
+    /// Its [`original_index`](SymbolicatedFrame::original_index) refers to the position of the
+    /// frame in the [`HybridStacktrace`].
+    Native(SymbolicatedFrame),
+    /// A JavaScript frame.
+    Javascript(SymbolicatedJsFrame),
+}
+
+/// A symbolicated [`HybridStacktrace`].
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SymbolicatedHybridStacktrace {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_requesting: Option<bool>,
+
+    /// Frames of this stack trace, in the same order as in the request.
+    ///
+    /// Native frames might expand to multiple inline frames.
+    pub frames: Vec<SymbolicatedHybridFrame>,
+}
+
+/// The result of symbolicating [`HybridStacktrace`]s.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CompletedHybridSymbolicationResponse {
+    /// The signal that caused this crash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<Signal>,
+
+    /// Set if native symbolication did not run to completion, in which case the native frames
+    /// and modules are only partially symbolicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialReason>,
+
+    /// The threads containing symbolicated stack frames.
+    pub stacktraces: Vec<SymbolicatedHybridStacktrace>,
+
+    /// The list of modules from the request, in the same order, extended with status
+    /// information.
+    ///
+    /// Source map modules are only used to resolve JavaScript frames and are returned as-is.
+    pub modules: Vec<CompleteObjectInfo>,
+}
@@ -66,11 +66,31 @@ pub enum ObjectDownloadInfo {
     /// be parsed.  Actually using the object for CFI or symbols might result in more
     /// detailed problems, see [`ObjectUseInfo`] for more on this.
     Malformed,
+    /// The DIF object was downloaded, but it does not match the requested debug or code ID,
+    /// or its contents do not match the requested checksum.
+    ///
+    /// This happens when a source serves a file of a different build than the one requested,
+    /// which usually points to a misconfigured source, or when the download was truncated or
+    /// tampered with.  More details should be available in the `details` field, which is not
+    /// meant to be machine parsable.
+    Mismatch { details: String },
+    /// The DIF object was downloaded, but its source requires signed objects and the object's
+    /// signature is missing or invalid.
+    ///
+    /// The object is not used, as it cannot be trusted.  More details should be available in
+    /// the `details` field, which is not meant to be machine parsable.
+    Unverified { details: String },
     /// Symbolicator had insufficient permissions to download the DIF object.
     ///
     /// More details should be available in the `details` field, which is not meant to be
     /// machine parsable.
     NoPerm { details: String },
+    /// The source of the DIF object is rate limiting requests, so it was not downloaded.
+    ///
+    /// The source is skipped until the end of the period it asked for, after which the next
+    /// attempt to access this DIF object will retry the download.  More details should be
+    /// available in the `details` field, which is not meant to be machine parsable.
+    RateLimited { details: String },
     /// The DIF object was not found.
     ///
     /// This is considered a *regular notfound* where the object was simply not available at
@@ -0,0 +1,197 @@
+//! Conversion of native symbolication responses into Sentry event payloads.
+//!
+//! The payload follows the [event schema] of the Sentry ingestion API, so that it can be sent on
+//! to Sentry without further processing.
+//!
+//! [event schema]: https://develop.sentry.dev/sdk/event-payloads/
+
+use serde_json::{json, Map, Value};
+
+use super::{CompleteStacktrace, CompletedSymbolicationResponse, SystemInfo};
+
+impl CompletedSymbolicationResponse {
+    /// Converts the response into a Sentry event.
+    ///
+    /// The stack trace of the crashing thread becomes the stack trace of the event's exception,
+    /// while all other threads keep their own. Frames are listed outermost first, as Sentry
+    /// expects them, and the status of each frame is kept in its `data`.
+    pub fn into_sentry_event(self) -> Value {
+        let mut event = Map::new();
+        event.insert("platform".into(), "native".into());
+
+        let level = match self.crashed {
+            Some(true) => "fatal",
+            _ => "error",
+        };
+        event.insert("level".into(), level.into());
+
+        if let Some(timestamp) = self.timestamp {
+            event.insert("timestamp".into(), timestamp.timestamp().into());
+        }
+
+        if let Some(system_info) = &self.system_info {
+            event.insert("contexts".into(), contexts(system_info));
+        }
+
+        let mut exception_stacktrace = None;
+        let mut exception_thread_id = None;
+        let mut threads = vec![];
+        for stacktrace in self.stacktraces {
+            let is_requesting = stacktrace.is_requesting.unwrap_or(false);
+            let mut thread = json!({
+                "id": stacktrace.thread_id,
+                "name": stacktrace.thread_name,
+                "crashed": is_requesting && self.crashed.unwrap_or(false),
+                "current": is_requesting,
+            });
+
+            if is_requesting && exception_stacktrace.is_none() {
+                exception_thread_id = stacktrace.thread_id;
+                exception_stacktrace = Some(stacktrace_value(stacktrace));
+            } else {
+                thread["stacktrace"] = stacktrace_value(stacktrace);
+            }
+            threads.push(thread);
+        }
+        event.insert("threads".into(), json!({ "values": threads }));
+
+        if self.crash_reason.is_some() || exception_stacktrace.is_some() {
+            let ty = self.crash_reason.as_deref().unwrap_or("Fatal Error");
+            let value = self.assertion.or(self.crash_details);
+            let exception = json!({
+                "type": ty,
+                "value": value,
+                "thread_id": exception_thread_id,
+                "mechanism": {
+                    "type": "native",
+                    "handled": false,
+                    "synthetic": true,
+                },
+                "stacktrace": exception_stacktrace,
+            });
+            event.insert("exception".into(), json!({ "values": [exception] }));
+        }
+
+        let images = serde_json::to_value(self.modules).unwrap_or_default();
+        event.insert("debug_meta".into(), json!({ "images": images }));
+
+        Value::Object(event)
+    }
+}
+
+/// Creates the `os` and `device` contexts from the system information of a crash.
+fn contexts(system_info: &SystemInfo) -> Value {
+    let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_owned());
+
+    json!({
+        "os": {
+            "type": "os",
+            "name": non_empty(&system_info.os_name),
+            "version": non_empty(&system_info.os_version),
+            "build": non_empty(&system_info.os_build),
+        },
+        "device": {
+            "type": "device",
+            "arch": system_info.cpu_arch.name(),
+            "model": non_empty(&system_info.device_model),
+        },
+    })
+}
+
+fn stacktrace_value(stacktrace: CompleteStacktrace) -> Value {
+    let frames: Vec<_> = stacktrace
+        .frames
+        .into_iter()
+        .rev()
+        .map(|frame| {
+            let mut value = serde_json::to_value(frame.raw).unwrap_or_default();
+            value["data"] = json!({ "symbolicator_status": frame.status });
+            value
+        })
+        .collect();
+
+    let mut value = json!({ "frames": frames });
+    if !stacktrace.registers.is_empty() {
+        value["registers"] = json!(stacktrace.registers);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use symbolic::common::Arch;
+
+    use crate::types::{FrameStatus, RawFrame, SymbolicatedFrame};
+    use crate::utils::hex::HexValue;
+
+    use super::*;
+
+    fn frame(instruction_addr: u64, status: FrameStatus) -> SymbolicatedFrame {
+        SymbolicatedFrame {
+            status,
+            original_index: None,
+            raw: RawFrame {
+                instruction_addr: HexValue(instruction_addr),
+                ..RawFrame::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_sentry_event() {
+        let response = CompletedSymbolicationResponse {
+            system_info: Some(SystemInfo {
+                os_name: "Windows NT".into(),
+                os_version: "10.0.19045".into(),
+                os_build: String::new(),
+                cpu_arch: Arch::Amd64,
+                device_model: String::new(),
+            }),
+            crashed: Some(true),
+            crash_reason: Some("EXCEPTION_ACCESS_VIOLATION_WRITE".into()),
+            stacktraces: vec![
+                CompleteStacktrace {
+                    thread_id: Some(1),
+                    is_requesting: Some(false),
+                    frames: vec![frame(0x1000, FrameStatus::Symbolicated)],
+                    ..CompleteStacktrace::default()
+                },
+                CompleteStacktrace {
+                    thread_id: Some(2),
+                    is_requesting: Some(true),
+                    frames: vec![
+                        frame(0x2000, FrameStatus::Symbolicated),
+                        frame(0x3000, FrameStatus::Missing),
+                    ],
+                    ..CompleteStacktrace::default()
+                },
+            ],
+            ..CompletedSymbolicationResponse::default()
+        };
+
+        let event = response.into_sentry_event();
+        assert_eq!(event["level"], "fatal");
+        assert_eq!(event["contexts"]["os"]["name"], "Windows NT");
+        assert_eq!(event["contexts"]["os"]["build"], Value::Null);
+        assert_eq!(event["contexts"]["device"]["arch"], "x86_64");
+
+        let exception = &event["exception"]["values"][0];
+        assert_eq!(exception["type"], "EXCEPTION_ACCESS_VIOLATION_WRITE");
+        assert_eq!(exception["thread_id"], 2);
+
+        // Frames are reversed, so that the outermost frame comes first.
+        let frames = &exception["stacktrace"]["frames"];
+        assert_eq!(frames[0]["instruction_addr"], "0x3000");
+        assert_eq!(frames[0]["data"]["symbolicator_status"], "missing");
+        assert_eq!(frames[1]["instruction_addr"], "0x2000");
+
+        let threads = &event["threads"]["values"];
+        assert_eq!(threads[0]["crashed"], false);
+        assert_eq!(
+            threads[0]["stacktrace"]["frames"][0]["instruction_addr"],
+            "0x1000"
+        );
+        assert_eq!(threads[1]["crashed"], true);
+        assert_eq!(threads[1]["stacktrace"], Value::Null);
+    }
+}
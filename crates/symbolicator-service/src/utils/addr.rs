@@ -4,8 +4,68 @@ use std::str::FromStr;
 
 use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
+use symbolic::common::{Arch, CpuFamily};
 use thiserror::Error;
 
+/// The part of the address space into which the kernel of a platform maps itself, its drivers and
+/// its kernel extensions.
+///
+/// User space can never reach these addresses, so images and frames in kernel space are kept
+/// apart from those in user space.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize)]
+pub enum KernelSpace {
+    /// Kernel addresses are not recognized on this platform.
+    #[default]
+    Unknown,
+    /// The kernel occupies all addresses starting at the given one.
+    Above(u64),
+    /// The kernel occupies the addresses with bit 55 set, which selects the kernel's translation
+    /// table on arm64. The bits above may hold a pointer authentication code in both halves.
+    Arm64,
+}
+
+impl KernelSpace {
+    /// Determines the kernel space of the platform with the given OS name and architecture.
+    ///
+    /// 64-bit kernels map themselves into the upper half of the address space. 32-bit Windows and
+    /// Linux kernels do the same with their default split at 2 GiB and 3 GiB respectively, while
+    /// 32-bit Apple kernels are not recognized.
+    pub fn new(os_name: &str, arch: Arch) -> Self {
+        let os_name = os_name.to_ascii_lowercase();
+        let known = [
+            "windows", "linux", "android", "mac", "ios", "iphone", "ipad", "tvos",
+        ]
+        .iter()
+        .any(|prefix| os_name.starts_with(prefix));
+        if !known {
+            return Self::Unknown;
+        }
+
+        match arch.cpu_family() {
+            CpuFamily::Amd64 => Self::Above(0xffff_8000_0000_0000),
+            CpuFamily::Arm64 => Self::Arm64,
+            CpuFamily::Intel32 | CpuFamily::Arm32 if os_name.starts_with("windows") => {
+                Self::Above(0x8000_0000)
+            }
+            CpuFamily::Intel32 | CpuFamily::Arm32
+                if os_name.starts_with("linux") || os_name.starts_with("android") =>
+            {
+                Self::Above(0xc000_0000)
+            }
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Checks whether `addr` lies in kernel space.
+    pub fn contains(self, addr: u64) -> bool {
+        match self {
+            Self::Unknown => false,
+            Self::Above(start) => addr >= start,
+            Self::Arm64 => addr & (1 << 55) != 0,
+        }
+    }
+}
+
 /// Defines the addressing mode.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub enum AddrMode {
@@ -67,3 +127,52 @@ impl<'de> Deserialize<'de> for AddrMode {
         AddrMode::from_str(&s).map_err(de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_space_64bit() {
+        for os_name in ["Windows", "macOS", "Linux"] {
+            let kernel = KernelSpace::new(os_name, Arch::Amd64);
+            assert!(!kernel.contains(0x7ff6_1234_0000));
+            assert!(kernel.contains(0xffff_f803_1000_0000));
+            assert!(kernel.contains(0xffff_ffff_c0a0_0000));
+        }
+
+        let kernel = KernelSpace::new("iOS", Arch::Arm64e);
+        assert!(!kernel.contains(0x1_0234_5678));
+        // User space return address signed with a pointer authentication code
+        assert!(!kernel.contains(0xa47c_0001_0234_5678));
+        assert!(kernel.contains(0xffff_fe00_0720_4000));
+
+        let kernel = KernelSpace::new("Android", Arch::Arm64);
+        assert!(!kernel.contains(0x7b_1234_5678));
+        assert!(kernel.contains(0xffff_ffc0_0800_0000));
+    }
+
+    #[test]
+    fn test_kernel_space_32bit() {
+        let kernel = KernelSpace::new("Windows", Arch::X86);
+        assert!(!kernel.contains(0x7700_0000));
+        assert!(kernel.contains(0x8040_0000));
+
+        let kernel = KernelSpace::new("Linux", Arch::Arm);
+        assert!(!kernel.contains(0xb6f0_0000));
+        assert!(kernel.contains(0xc000_8000));
+
+        let kernel = KernelSpace::new("iOS", Arch::ArmV7);
+        assert!(!kernel.contains(0x8000_1000));
+    }
+
+    #[test]
+    fn test_kernel_space_unknown() {
+        assert_eq!(KernelSpace::new("", Arch::Amd64), KernelSpace::Unknown);
+        assert_eq!(
+            KernelSpace::new("Linux", Arch::Unknown),
+            KernelSpace::Unknown
+        );
+        assert!(!KernelSpace::default().contains(u64::MAX));
+    }
+}
@@ -0,0 +1,359 @@
+//! Extraction of files from the zip and tar archives served by some sources.
+//!
+//! Sources may package debug files in archives, for instance Xcode exports dSYMs as
+//! `<name>.dSYM.zip`. Compressed tar archives are decompressed like any other download first,
+//! which leaves plain tar archives to be handled here.
+
+use std::io::{self, Cursor, Read, Write};
+
+use symbolic::common::ByteView;
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+use super::compression::{size_limit_exceeded, tempfile_in_parent, LimitedWriter};
+
+/// The size of the header and the alignment of the members in a tar archive.
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// An archive format that files can be extracted from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    /// Detects the archive format of `data` from its magic bytes.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"PK\x03\x04") {
+            Some(Self::Zip)
+        } else if data.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the format, as used in metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+        }
+    }
+}
+
+/// Extracts the first file in the archive `data` for which `is_wanted` returns `true`.
+///
+/// The extracted file replaces `file`, which is expected to hold the archive. Returns whether a
+/// file was extracted. Directories and, in tar archives, links and other special members are
+/// skipped. Fails if a zip member decompresses to more than `limit` bytes.
+pub fn extract_file(
+    format: ArchiveFormat,
+    data: &[u8],
+    file: &mut NamedTempFile,
+    limit: u64,
+    is_wanted: impl FnMut(&[u8]) -> bool,
+) -> io::Result<bool> {
+    match format {
+        ArchiveFormat::Zip => extract_zip_file(data, file, limit, is_wanted),
+        ArchiveFormat::Tar => extract_tar_file(data, file, is_wanted),
+    }
+}
+
+fn extract_zip_file(
+    data: &[u8],
+    file: &mut NamedTempFile,
+    limit: u64,
+    mut is_wanted: impl FnMut(&[u8]) -> bool,
+) -> io::Result<bool> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index)?;
+        if member.is_dir() {
+            continue;
+        }
+
+        // The size in the header may be forged, so decompression is limited as well.
+        if member.size() > limit {
+            return Err(size_limit_exceeded());
+        }
+
+        // Members need to be decompressed to be inspected, so they are written to a file right
+        // away to not hold large objects in memory.
+        let mut dst = tempfile_in_parent(file)?;
+        io::copy(
+            &mut member,
+            &mut LimitedWriter::new(dst.as_file_mut(), limit),
+        )?;
+        let contents = ByteView::map_file_ref(dst.as_file())?;
+
+        if is_wanted(&contents) {
+            std::mem::swap(file, &mut dst);
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn extract_tar_file(
+    data: &[u8],
+    file: &mut NamedTempFile,
+    is_wanted: impl FnMut(&[u8]) -> bool,
+) -> io::Result<bool> {
+    let Some(contents) = find_tar_file(data, is_wanted)? else {
+        return Ok(false);
+    };
+
+    let mut dst = tempfile_in_parent(file)?;
+    dst.write_all(contents)?;
+    std::mem::swap(file, &mut dst);
+    Ok(true)
+}
+
+/// Returns the contents of the first regular file in a tar archive for which `is_wanted`
+/// returns `true`.
+fn find_tar_file(
+    data: &[u8],
+    mut is_wanted: impl FnMut(&[u8]) -> bool,
+) -> io::Result<Option<&[u8]>> {
+    for file in TarFiles::new(data) {
+        let file = file?;
+        if is_wanted(file.contents) {
+            return Ok(Some(file.contents));
+        }
+    }
+    Ok(None)
+}
+
+/// Calls `visit` with the name and contents of every file in the archive `data`.
+///
+/// Directories and, in tar archives, links and other special members are skipped.
+pub fn visit_files(
+    format: ArchiveFormat,
+    data: &[u8],
+    mut visit: impl FnMut(&str, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = ZipArchive::new(Cursor::new(data))?;
+            for index in 0..archive.len() {
+                let mut member = archive.by_index(index)?;
+                if !member.is_dir() {
+                    let name = member.name().to_owned();
+                    visit(&name, &mut member)?;
+                }
+            }
+        }
+        ArchiveFormat::Tar => {
+            for file in TarFiles::new(data) {
+                let mut file = file?;
+                visit(&file.name(), &mut file.contents)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A regular file in a tar archive.
+struct TarFile<'a> {
+    header: &'a [u8],
+    contents: &'a [u8],
+}
+
+impl TarFile<'_> {
+    /// Returns the path of the file, joined from the prefix and name fields of the header.
+    fn name(&self) -> String {
+        let field = |range: std::ops::Range<usize>| {
+            let field = &self.header[range];
+            let len = field
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..len])
+        };
+
+        let name = field(0..100);
+        match &self.header[257..262] {
+            b"ustar" if self.header[345] != 0 => format!("{}/{name}", field(345..500)),
+            _ => name.into_owned(),
+        }
+    }
+}
+
+/// An iterator over the regular files in a tar archive.
+///
+/// Only the fields of the original tar format and the name prefix of the ustar format are read,
+/// which is sufficient to find the contents of all members. Extended pax and GNU headers precede
+/// the member they describe, and are skipped like any other special member.
+struct TarFiles<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TarFiles<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn next_member(&mut self) -> io::Result<Option<TarFile<'a>>> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let data = self.data;
+
+        while let Some(header) = data.get(self.offset..self.offset + TAR_BLOCK_SIZE) {
+            // The archive ends with (at least) two blocks of zeros.
+            if header.iter().all(|&byte| byte == 0) {
+                break;
+            }
+
+            let size =
+                parse_octal(&header[124..136]).ok_or_else(|| invalid("invalid tar header"))?;
+            let start = self.offset + TAR_BLOCK_SIZE;
+            let contents = start
+                .checked_add(size)
+                .and_then(|end| data.get(start..end))
+                .ok_or_else(|| invalid("truncated tar archive"))?;
+
+            self.offset = start + (size + TAR_BLOCK_SIZE - 1) / TAR_BLOCK_SIZE * TAR_BLOCK_SIZE;
+
+            // Regular files are marked with `0`, or a NUL byte in archives of old tar versions.
+            if matches!(header[156], b'0' | 0) {
+                return Ok(Some(TarFile { header, contents }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for TarFiles<'a> {
+    type Item = io::Result<TarFile<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_member() {
+            Ok(file) => file.map(Ok),
+            Err(err) => {
+                // Stop after the first error, as the following members cannot be located.
+                self.offset = self.data.len();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Parses a numeric field of a tar header, which is an octal number padded with spaces or NULs.
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let digits = std::str::from_utf8(field).ok()?;
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(digits, 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_member(name: &str, typeflag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{:011o}\0", contents.len()).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+
+        let padded_len = (contents.len() + TAR_BLOCK_SIZE - 1) / TAR_BLOCK_SIZE * TAR_BLOCK_SIZE;
+        let mut member = header;
+        member.extend_from_slice(contents);
+        member.resize(TAR_BLOCK_SIZE + padded_len, 0);
+        member
+    }
+
+    #[test]
+    fn test_find_tar_file() {
+        let mut archive = tar_member("Foo.dSYM/", b'5', b"");
+        archive.extend(tar_member("Foo.dSYM/Info.plist", b'0', b"<plist/>"));
+        archive.extend(tar_member("Foo.dSYM/Foo", b'0', &[0xcf; 600]));
+        archive.extend([0; 2 * TAR_BLOCK_SIZE]);
+        assert_eq!(ArchiveFormat::detect(&archive), Some(ArchiveFormat::Tar));
+
+        let found = find_tar_file(&archive, |contents| contents.starts_with(&[0xcf])).unwrap();
+        assert_eq!(found, Some(&[0xcf; 600][..]));
+
+        let found = find_tar_file(&archive, |contents| contents.is_empty()).unwrap();
+        assert_eq!(found, None);
+
+        // The contents of the last member are cut off.
+        archive.truncate(4 * TAR_BLOCK_SIZE + 100);
+        assert!(find_tar_file(&archive, |_| false).is_err());
+    }
+
+    #[test]
+    fn test_visit_tar_files() {
+        let mut archive = tar_member("dumps/", b'5', b"");
+        archive.extend(tar_member("dumps/a.dmp", b'0', b"MDMPa"));
+        archive.extend(tar_member("dumps/b.dmp", b'0', b"MDMPb"));
+        archive.extend([0; 2 * TAR_BLOCK_SIZE]);
+
+        let mut files = vec![];
+        visit_files(ArchiveFormat::Tar, &archive, |name, contents| {
+            let mut buf = vec![];
+            contents.read_to_end(&mut buf)?;
+            files.push((name.to_owned(), buf));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            files,
+            [
+                ("dumps/a.dmp".to_owned(), b"MDMPa".to_vec()),
+                ("dumps/b.dmp".to_owned(), b"MDMPb".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_zip_file() {
+        let mut data = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut data);
+        let options = zip::write::FileOptions::default();
+        writer.add_directory("Foo.dSYM/", options).unwrap();
+        writer.start_file("Foo.dSYM/Info.plist", options).unwrap();
+        writer.write_all(b"<plist/>").unwrap();
+        writer.start_file("Foo.dSYM/Foo", options).unwrap();
+        writer.write_all(&[0xcf; 600]).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let data = data.into_inner();
+        assert_eq!(ArchiveFormat::detect(&data), Some(ArchiveFormat::Zip));
+
+        let tempdir = crate::test::tempdir();
+        let mut file = NamedTempFile::new_in(tempdir.path()).unwrap();
+        let extracted = extract_zip_file(&data, &mut file, 1024, |contents| {
+            contents.starts_with(&[0xcf])
+        });
+        assert!(extracted.unwrap());
+        assert_eq!(std::fs::read(file.path()).unwrap(), [0xcf; 600]);
+    }
+
+    #[test]
+    fn test_extract_zip_file_too_large() {
+        let mut data = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut data);
+        let options = zip::write::FileOptions::default();
+        writer.start_file("Foo.dSYM/Foo", options).unwrap();
+        writer.write_all(&[0xcf; 4096]).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let data = data.into_inner();
+
+        let tempdir = crate::test::tempdir();
+        let mut file = NamedTempFile::new_in(tempdir.path()).unwrap();
+        let err = extract_zip_file(&data, &mut file, 1024, |_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
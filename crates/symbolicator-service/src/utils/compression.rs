@@ -1,4 +1,6 @@
-use std::io::{self, Read, Seek};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 use flate2::read::{MultiGzDecoder, ZlibDecoder};
@@ -12,6 +14,15 @@ use tempfile::NamedTempFile;
 /// The passed [`NamedTempFile`] might be swapped with a fresh one in case decompression happens.
 /// That new temp file will be created in the same directory as the original one.
 pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
+    maybe_decompress_file_limited(src, u64::MAX)
+}
+
+/// Decompresses a file like [`maybe_decompress_file`], failing if it decompresses to more than
+/// `limit` bytes.
+///
+/// This is used for files uploaded by clients, which could otherwise fill the disk with a small
+/// file that decompresses to a huge one.
+pub fn maybe_decompress_file_limited(src: &mut NamedTempFile, limit: u64) -> io::Result<()> {
     // Ensure that both meta data and file contents are available to the
     // subsequent reads of the file metadata and reads from other threads.
     let mut file = src.as_file();
@@ -41,7 +52,7 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
             metric!(counter("compression") += 1, "type" => "zstd");
 
             let mut dst = tempfile_in_parent(src)?;
-            zstd::stream::copy_decode(file, &mut dst)?;
+            zstd::stream::copy_decode(file, LimitedWriter::new(&mut dst, limit))?;
 
             std::mem::swap(src, &mut dst);
         }
@@ -54,7 +65,7 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
             // values compared to GzDecoder.
             let mut dst = tempfile_in_parent(src)?;
             let mut reader = MultiGzDecoder::new(file);
-            io::copy(&mut reader, &mut dst)?;
+            io::copy(&mut reader, &mut LimitedWriter::new(&mut dst, limit))?;
 
             std::mem::swap(src, &mut dst);
         }
@@ -64,7 +75,7 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
 
             let mut dst = tempfile_in_parent(src)?;
             let mut reader = ZlibDecoder::new(file);
-            io::copy(&mut reader, &mut dst)?;
+            io::copy(&mut reader, &mut LimitedWriter::new(&mut dst, limit))?;
 
             std::mem::swap(src, &mut dst);
         }
@@ -78,7 +89,14 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
                 .arg(src.path())
                 .stdout(Stdio::from(dst.reopen()?))
                 .stderr(Stdio::null())
-                .status()?;
+                .status()
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::NotFound => io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "cabextract is required to decompress cab files",
+                    ),
+                    _ => err,
+                })?;
 
             if !status.success() {
                 return Err(io::Error::new(
@@ -86,6 +104,19 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
                     "failed to decompress cab file",
                 ));
             }
+            if dst.as_file().metadata()?.len() > limit {
+                return Err(size_limit_exceeded());
+            }
+
+            std::mem::swap(src, &mut dst);
+        }
+        // Magic bytes for SZDD, written by `compress.exe` and read by `expand.exe`
+        [0x53, 0x5a, 0x44, 0x44] => {
+            metric!(counter("compression") += 1, "type" => "szdd");
+
+            let mut dst = tempfile_in_parent(src)?;
+            let writer = LimitedWriter::new(BufWriter::new(dst.as_file_mut()), limit);
+            szdd_decode(BufReader::new(file), writer)?;
 
             std::mem::swap(src, &mut dst);
         }
@@ -98,6 +129,113 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
     Ok(())
 }
 
+/// A writer which fails once more than a limited number of bytes are written to it.
+pub(crate) struct LimitedWriter<W> {
+    inner: W,
+    remaining: u64,
+}
+
+impl<W> LimitedWriter<W> {
+    pub(crate) fn new(inner: W, limit: u64) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            return Err(size_limit_exceeded());
+        }
+        let written = self.inner.write(buf)?;
+        self.remaining -= written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(crate) fn size_limit_exceeded() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "decompressed file exceeds the size limit",
+    )
+}
+
+/// Decodes the zstd compressed file at `path` in place.
+///
+/// The decoded contents are written back to the same file rather than replacing it, so that open
+/// handles to the file observe them.
+pub fn zstd_decode_in_place(path: &Path) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let mut decoded = NamedTempFile::new_in(dir)?;
+    zstd::stream::copy_decode(File::open(path)?, &mut decoded)?;
+    fs::copy(decoded.path(), path)?;
+    Ok(())
+}
+
+/// Decodes a file in the SZDD format of `compress.exe`.
+///
+/// SZDD is a plain LZSS compression with a 4 KiB window, which some symbol servers still use for
+/// the `file.pd_` convention instead of cab archives. See
+/// <https://www.cabextract.org.uk/libmspack/doc/szdd_kwaj_format.html> for the format.
+fn szdd_decode(mut reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+    const WINDOW_SIZE: usize = 4096;
+
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    // The header consists of the 8 byte signature, the compression mode, the replaced last
+    // character of the file name, and the decompressed size.
+    let mut header = [0; 14];
+    reader.read_exact(&mut header)?;
+    if header[..8] != [0x53, 0x5a, 0x44, 0x44, 0x88, 0xf0, 0x27, 0x33] || header[8] != b'A' {
+        return Err(invalid("invalid SZDD header"));
+    }
+
+    let mut window = [b' '; WINDOW_SIZE];
+    let mut pos = WINDOW_SIZE - 16;
+    let mut bytes = reader.bytes();
+
+    while let Some(control) = bytes.next() {
+        let control = control?;
+        for bit in 0..8 {
+            let Some(first) = bytes.next() else {
+                break;
+            };
+            let first = first?;
+
+            if control & (1 << bit) != 0 {
+                // A literal byte.
+                writer.write_all(&[first])?;
+                window[pos] = first;
+                pos = (pos + 1) % WINDOW_SIZE;
+            } else {
+                // A match of 3 to 18 bytes at an absolute position of the window.
+                let second = bytes
+                    .next()
+                    .ok_or_else(|| invalid("truncated SZDD match"))??;
+                let mut offset = first as usize | ((second as usize & 0xf0) << 4);
+                let length = (second as usize & 0x0f) + 3;
+                for _ in 0..length {
+                    let byte = window[offset];
+                    writer.write_all(&[byte])?;
+                    window[pos] = byte;
+                    pos = (pos + 1) % WINDOW_SIZE;
+                    offset = (offset + 1) % WINDOW_SIZE;
+                }
+            }
+        }
+    }
+
+    writer.flush()
+}
+
 // FIXME(swatinem): this fn needs a better place
 pub fn tempfile_in_parent(file: &NamedTempFile) -> io::Result<NamedTempFile> {
     let dir = file
@@ -106,3 +244,52 @@ pub fn tempfile_in_parent(file: &NamedTempFile) -> io::Result<NamedTempFile> {
         .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
     NamedTempFile::new_in(dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_szdd_decode() {
+        let mut encoded = vec![0x53, 0x5a, 0x44, 0x44, 0x88, 0xf0, 0x27, 0x33, b'A', b'b'];
+        encoded.extend(9u32.to_le_bytes());
+        // Three literals, followed by a match of six bytes starting at the first literal.
+        encoded.extend([0b0111, b'a', b'b', b'c', 0xf0, 0xf3]);
+
+        let mut decoded = Vec::new();
+        szdd_decode(&encoded[..], &mut decoded).unwrap();
+        assert_eq!(decoded, b"abcabcabc");
+
+        encoded[8] = b'B';
+        assert!(szdd_decode(&encoded[..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_decompress_limited() {
+        let tempdir = crate::test::tempdir();
+        let mut file = NamedTempFile::new_in(tempdir.path()).unwrap();
+        let encoded = zstd::encode_all(&[0; 4096][..], 0).unwrap();
+        fs::write(file.path(), encoded).unwrap();
+
+        let err = maybe_decompress_file_limited(&mut file, 1024).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        maybe_decompress_file_limited(&mut file, 4096).unwrap();
+        assert_eq!(file.as_file().metadata().unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn test_zstd_decode_in_place() {
+        let tempdir = crate::test::tempdir();
+        let mut file = NamedTempFile::new_in(tempdir.path()).unwrap();
+        let encoded = zstd::encode_all(&b"hello world"[..], 0).unwrap();
+        fs::write(file.path(), encoded).unwrap();
+
+        zstd_decode_in_place(file.path()).unwrap();
+
+        // The decoded contents are visible through the handle that was opened before.
+        let mut decoded = String::new();
+        file.as_file_mut().read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "hello world");
+    }
+}
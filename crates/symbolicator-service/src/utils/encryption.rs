@@ -0,0 +1,222 @@
+//! Encryption of sensitive values in persisted configuration.
+//!
+//! Values are encrypted with AES-256-GCM using a master key, and stored as
+//! `enc:v1:<base64 of nonce, ciphertext and tag>`. They are decrypted in memory when the
+//! configuration is loaded.
+
+use anyhow::{anyhow, bail, Context, Result};
+use data_encoding::BASE64;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::config::EncryptionConfig;
+
+/// The prefix of encrypted values.
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Returns whether `value` is an encrypted value.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// The master key which encrypts sensitive values.
+#[derive(Debug)]
+pub struct MasterKey {
+    key: LessSafeKey,
+}
+
+impl MasterKey {
+    /// Loads the master key from the configured key file.
+    pub fn load(config: &EncryptionConfig) -> Result<Self> {
+        let encoded = std::fs::read_to_string(&config.key_file)
+            .with_context(|| format!("failed to read master key {}", config.key_file.display()))?;
+        Self::from_base64(&encoded)
+    }
+
+    /// Creates the master key from its base64 encoding.
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim().as_bytes())
+            .context("master key is not valid base64")?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| anyhow!("master key must be 32 bytes long"))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// Encrypts `plaintext`, returning the encrypted value including its prefix.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("failed to generate nonce"))?;
+
+        let mut data = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| anyhow!("failed to encrypt value"))?;
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(data);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", BASE64.encode(&encrypted)))
+    }
+
+    /// Decrypts an encrypted `value`.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            bail!("value is not encrypted");
+        };
+        let data = BASE64
+            .decode(encoded.as_bytes())
+            .context("encrypted value is not valid base64")?;
+        if data.len() < NONCE_LEN {
+            bail!("encrypted value is too short");
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("invalid nonce in encrypted value"))?;
+        let mut ciphertext = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+            .map_err(|_| {
+                anyhow!("failed to decrypt value, it may be encrypted with another key")
+            })?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    /// Decrypts all encrypted strings contained in a YAML `value`.
+    pub fn decrypt_yaml(&self, value: &mut serde_yaml::Value) -> Result<()> {
+        match value {
+            serde_yaml::Value::String(s) if is_encrypted(s) => *s = self.decrypt(s)?,
+            serde_yaml::Value::Sequence(values) => {
+                for value in values {
+                    self.decrypt_yaml(value)?;
+                }
+            }
+            serde_yaml::Value::Mapping(mapping) => {
+                for (_, value) in mapping.iter_mut() {
+                    self.decrypt_yaml(value)?;
+                }
+            }
+            serde_yaml::Value::Tagged(tagged) => self.decrypt_yaml(&mut tagged.value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decrypts all encrypted strings contained in a JSON `value`.
+    pub fn decrypt_json(&self, value: &mut serde_json::Value) -> Result<()> {
+        match value {
+            serde_json::Value::String(s) if is_encrypted(s) => *s = self.decrypt(s)?,
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.decrypt_json(value)?;
+                }
+            }
+            serde_json::Value::Object(object) => {
+                for value in object.values_mut() {
+                    self.decrypt_json(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Encrypts the strings of all object entries named one of `fields` in a JSON `value`.
+    ///
+    /// If such an entry is an object itself, all of its strings are encrypted.
+    pub fn encrypt_json_fields(
+        &self,
+        value: &mut serde_json::Value,
+        fields: &[&str],
+    ) -> Result<()> {
+        match value {
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.encrypt_json_fields(value, fields)?;
+                }
+            }
+            serde_json::Value::Object(object) => {
+                for (name, value) in object.iter_mut() {
+                    if fields.contains(&name.as_str()) {
+                        self.encrypt_json(value)?;
+                    } else {
+                        self.encrypt_json_fields(value, fields)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Encrypts all strings contained in a JSON `value`.
+    fn encrypt_json(&self, value: &mut serde_json::Value) -> Result<()> {
+        match value {
+            serde_json::Value::String(s) if !is_encrypted(s) => *s = self.encrypt(s)?,
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.encrypt_json(value)?;
+                }
+            }
+            serde_json::Value::Object(object) => {
+                for value in object.values_mut() {
+                    self.encrypt_json(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = MasterKey::from_base64(KEY).unwrap();
+
+        let encrypted = key.encrypt("hunter2").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_ne!(encrypted, key.encrypt("hunter2").unwrap());
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+
+        let other_key = MasterKey::from_base64(&BASE64.encode(&[1; 32])).unwrap();
+        assert!(other_key.decrypt(&encrypted).is_err());
+
+        assert!(MasterKey::from_base64(&BASE64.encode(&[1; 16])).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_json_fields() {
+        let key = MasterKey::from_base64(KEY).unwrap();
+
+        let original = json!([{
+            "id": "http",
+            "headers": { "Authorization": "Bearer hunter2" },
+            "token": "hunter3",
+        }]);
+        let mut value = original.clone();
+        key.encrypt_json_fields(&mut value, &["headers", "token"])
+            .unwrap();
+        assert_eq!(value[0]["id"], "http");
+        assert!(is_encrypted(value[0]["token"].as_str().unwrap()));
+        assert!(is_encrypted(
+            value[0]["headers"]["Authorization"].as_str().unwrap()
+        ));
+
+        key.decrypt_json(&mut value).unwrap();
+        assert_eq!(value, original);
+    }
+}
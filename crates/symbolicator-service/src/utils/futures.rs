@@ -1,6 +1,7 @@
 use std::future::Future;
 use std::time::Instant;
 
+use futures::stream::{FuturesUnordered, StreamExt};
 use tokio::task::JoinHandle;
 
 use crate::metrics::{self, prelude::*};
@@ -160,6 +161,40 @@ where
     }
 }
 
+/// Polls all the `futures` concurrently until they either complete or the `deadline` is hit.
+///
+/// Returns the outputs of all the futures that completed in time, in completion order, and
+/// whether the `deadline` was hit before all of them completed. Without a `deadline`, this
+/// behaves like [`join_all`](futures::future::join_all) apart from the order of outputs.
+pub async fn join_until<I>(
+    futures: I,
+    deadline: Option<Instant>,
+) -> (Vec<<I::Item as Future>::Output>, bool)
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let mut futures: FuturesUnordered<_> = futures.into_iter().collect();
+    let mut outputs = Vec::with_capacity(futures.len());
+
+    loop {
+        let next = match deadline {
+            Some(deadline) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                match tokio::time::timeout_at(deadline, futures.next()).await {
+                    Ok(next) => next,
+                    Err(_elapsed) => return (outputs, true),
+                }
+            }
+            None => futures.next().await,
+        };
+        match next {
+            Some(output) => outputs.push(output),
+            None => return (outputs, false),
+        }
+    }
+}
+
 /// Status helpers for [`measure`].
 #[allow(dead_code)]
 pub mod m {
@@ -205,3 +240,40 @@ pub mod m {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join_until() {
+        let completed_late = Arc::new(AtomicBool::new(false));
+        let sleep = |secs: u64| {
+            let completed_late = completed_late.clone();
+            async move {
+                tokio::time::sleep(Duration::from_secs(secs)).await;
+                completed_late.store(secs > 10, Ordering::Relaxed);
+                secs
+            }
+        };
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        let futures = [sleep(20), sleep(5), sleep(1)];
+        let (outputs, deadline_hit) = join_until(futures, Some(deadline.into_std())).await;
+        assert_eq!(outputs, [1, 5]);
+        assert!(deadline_hit);
+
+        // The futures still running at the deadline are dropped and never complete.
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(!completed_late.load(Ordering::Relaxed));
+
+        let futures = [sleep(20), sleep(5), sleep(1)];
+        let (outputs, deadline_hit) = join_until(futures, None).await;
+        assert_eq!(outputs, [1, 5, 20]);
+        assert!(!deadline_hit);
+    }
+}
@@ -2,7 +2,7 @@ use std::net::IpAddr;
 
 use ipnetwork::Ipv4Network;
 
-use crate::config::Config;
+use crate::config::{Config, ConnectionPoolConfig, ReservedIpsPolicy};
 
 lazy_static::lazy_static! {
     static ref RESERVED_IP_BLOCKS: Vec<Ipv4Network> = vec![
@@ -37,16 +37,67 @@ fn is_external_ip(ip: std::net::IpAddr) -> bool {
     true
 }
 
+/// Creates an HTTP client.
+///
+/// Unless the client is `trusted` or all sources are allowed to connect to reserved IP addresses,
+/// the client refuses to connect to them.
 pub fn create_client(config: &Config, trusted: bool) -> reqwest::Client {
-    let mut builder = reqwest::ClientBuilder::new().gzip(true).trust_dns(true);
+    create_pooled_client(config, trusted, &config.connection_pool.default)
+}
 
-    if !(trusted || config.connect_to_reserved_ips) {
-        builder = builder.ip_filter(is_external_ip);
+/// Creates an HTTP client whose connection pool is tuned by `pool`.
+///
+/// See [`create_client`] for the meaning of `trusted`.
+pub fn create_pooled_client(
+    config: &Config,
+    trusted: bool,
+    pool: &ConnectionPoolConfig,
+) -> reqwest::Client {
+    let mut builder = client_builder(config, trusted);
+
+    if let Some(max_idle) = pool.max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = pool.idle_timeout {
+        builder = builder.pool_idle_timeout(idle_timeout);
+    }
+    if let Some(interval) = pool.http2_keep_alive_interval {
+        builder = builder.http2_keep_alive_interval(interval);
+    }
+    if let Some(timeout) = pool.http2_keep_alive_timeout {
+        builder = builder.http2_keep_alive_timeout(timeout);
     }
 
     builder.build().unwrap()
 }
 
+/// Creates an HTTP client for authentication schemes which authenticate a connection, like NTLM.
+///
+/// The client only speaks HTTP/1.1 and keeps its connection alive between the requests of a
+/// handshake, regardless of the configured connection pools. See [`create_client`] for the
+/// meaning of `trusted`.
+pub fn create_connection_client(config: &Config, trusted: bool) -> reqwest::Client {
+    client_builder(config, trusted)
+        .http1_only()
+        .pool_max_idle_per_host(1)
+        .build()
+        .unwrap()
+}
+
+fn client_builder(config: &Config, trusted: bool) -> reqwest::ClientBuilder {
+    let builder = reqwest::ClientBuilder::new()
+        .gzip(true)
+        .deflate(true)
+        .brotli(true)
+        .trust_dns(true);
+
+    if trusted || config.connect_to_reserved_ips == ReservedIpsPolicy::Allow {
+        builder
+    } else {
+        builder.ip_filter(is_external_ip)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,7 +109,7 @@ mod tests {
         let server = symbolicator_test::Server::new();
 
         let config = Config {
-            connect_to_reserved_ips: false,
+            connect_to_reserved_ips: ReservedIpsPolicy::Deny,
             ..Config::default()
         };
 
@@ -76,7 +127,7 @@ mod tests {
 
         let server = symbolicator_test::Server::new();
         let config = Config {
-            connect_to_reserved_ips: false,
+            connect_to_reserved_ips: ReservedIpsPolicy::Deny,
             ..Config::default()
         };
 
@@ -97,7 +148,7 @@ mod tests {
         let server = symbolicator_test::Server::new();
 
         let config = Config {
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
             ..Config::default()
         };
 
@@ -118,7 +169,7 @@ mod tests {
         let server = symbolicator_test::Server::new();
 
         let config = Config {
-            connect_to_reserved_ips: false,
+            connect_to_reserved_ips: ReservedIpsPolicy::Deny,
             ..Config::default()
         };
 
@@ -1,5 +1,7 @@
 pub mod addr;
+pub mod archives;
 pub mod compression;
+pub mod encryption;
 pub mod futures;
 pub mod gcs;
 pub mod hex;
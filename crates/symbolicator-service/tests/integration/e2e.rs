@@ -1,6 +1,7 @@
 use std::path::Path;
 use std::sync::Arc;
 
+use symbolicator_service::config::ReservedIpsPolicy;
 use symbolicator_service::services::symbolication::SymbolicateStacktraces;
 use symbolicator_service::types::{FrameStatus, ObjectDownloadInfo, ObjectFileStatus, Scope};
 use symbolicator_sources::{
@@ -90,6 +91,7 @@ async fn test_path_patterns() {
         let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
             id: SourceId::new("local"),
             path: fixture("symbols"),
+            index_ttl: None,
             files,
         }));
 
@@ -203,6 +205,7 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("getsentry"),
         url: url.clone(),
         headers: Default::default(),
+        auth: None,
         files: files.clone(),
     })));
 
@@ -211,6 +214,7 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("ip"),
         url: url.clone(),
         headers: Default::default(),
+        auth: None,
         files: files.clone(),
     })));
 
@@ -219,6 +223,7 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("localhost"),
         url,
         headers: Default::default(),
+        auth: None,
         files,
     })));
 
@@ -255,7 +260,7 @@ async fn test_reserved_ip_addresses() {
     // ---
 
     let (symbolication, _cache_dir) = setup_service(|cfg| {
-        cfg.connect_to_reserved_ips = false;
+        cfg.connect_to_reserved_ips = ReservedIpsPolicy::Deny;
     });
     let mut response = symbolication.symbolicate(request.clone()).await.unwrap();
     let candidates = response.modules.pop().unwrap().candidates.0;
@@ -288,6 +293,46 @@ async fn test_reserved_ip_addresses() {
         .to_string()
         .starts_with("http://localhost"));
     assert_eq!(candidates[5].download, error);
+
+    // ---
+
+    // Only the configured `ip` source may connect to reserved IP addresses. Another source
+    // reusing its ID, but pointing to a different URL, is still restricted.
+    let trusted = request.sources[1].clone();
+    let SourceConfig::Http(ref localhost) = request.sources[2] else {
+        unreachable!();
+    };
+    let impostor = SourceConfig::Http(Arc::new(HttpSourceConfig {
+        id: SourceId::new("ip"),
+        ..HttpSourceConfig::clone(localhost)
+    }));
+    let request = example_request(vec![request.sources[0].clone(), trusted.clone(), impostor]);
+
+    let (symbolication, _cache_dir) = setup_service(|cfg| {
+        cfg.sources = Arc::from(vec![trusted]);
+        cfg.connect_to_reserved_ips = ReservedIpsPolicy::Sources(vec!["ip".into()]);
+    });
+    let mut response = symbolication.symbolicate(request).await.unwrap();
+    let candidates = response.modules.pop().unwrap().candidates.0;
+
+    assert_eq!(hitcounter.accesses(), 1);
+
+    assert_eq!(candidates[1].source, SourceId::new("getsentry"));
+    assert_eq!(candidates[1].download, error);
+
+    assert_eq!(candidates[3].source, SourceId::new("ip"));
+    assert!(candidates[3]
+        .location
+        .to_string()
+        .starts_with("http://127.0.0.1"));
+    assert_eq!(candidates[3].download, ObjectDownloadInfo::NotFound);
+
+    assert_eq!(candidates[5].source, SourceId::new("ip"));
+    assert!(candidates[5]
+        .location
+        .to_string()
+        .starts_with("http://localhost"));
+    assert_eq!(candidates[5].download, error);
 }
 
 /// Tests that symbolicator correctly follows redirects
@@ -333,6 +378,7 @@ async fn test_unreachable_bucket() {
                     id: SourceId::new(format!("broken-{ty}-{code}")),
                     url: hitcounter.url(&format!("respond_statuscode/{code}")),
                     token: "123abc".into(),
+                    index_ttl: None,
                 }))
             };
 
@@ -4,6 +4,8 @@ use std::sync::Arc;
 use tempfile::NamedTempFile;
 
 use symbolicator_service::types::Scope;
+use symbolicator_test as test;
+use symbolicator_test::synth::{self, Arch, Minidump, Module, Os};
 
 use crate::{assert_snapshot, read_fixture, setup_service, symbol_server};
 
@@ -43,3 +45,87 @@ async fn test_minidump_macos() {
 async fn test_minidump_linux() {
     stackwalk_minidump!("linux.dmp").await
 }
+
+#[tokio::test]
+async fn test_minidump_synthetic() {
+    let platforms = [
+        (Os::Windows, Arch::X86, "crash.exe"),
+        (Os::Linux, Arch::Amd64, "libcrash.so"),
+        (Os::MacOs, Arch::Arm64, "Crash"),
+    ];
+
+    for (os, arch, name) in platforms {
+        let (symbolication, _cache_dir) = setup_service(|_| ());
+
+        let module = Module::new(name, 0x10000)
+            .function("crash")
+            .function("run")
+            .function("main");
+        let minidump = Minidump::new(os, arch)
+            .module(module)
+            .thread(1, &[(name, "crash"), (name, "run"), (name, "main")])
+            .crashed(1);
+
+        let symbols_dir = test::tempdir();
+        minidump.write_symbols(symbols_dir.path()).unwrap();
+        let mut minidump_file = NamedTempFile::new().unwrap();
+        minidump_file.write_all(&minidump.to_bytes()).unwrap();
+
+        let response = symbolication
+            .process_minidump(
+                Scope::Global,
+                minidump_file.into_temp_path(),
+                Arc::new([synth::symbols_source(symbols_dir.path())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.crashed, Some(true), "{os:?} {arch:?}");
+        let functions: Vec<_> = response.stacktraces[0]
+            .frames
+            .iter()
+            .map(|frame| frame.raw.function.as_deref())
+            .collect();
+        assert_eq!(
+            functions,
+            [Some("crash"), Some("run"), Some("main")],
+            "{os:?} {arch:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_minidump_synthetic_elf() {
+    for arch in [Arch::X86, Arch::Amd64, Arch::Arm64] {
+        let (symbolication, _cache_dir) = setup_service(|_| ());
+
+        let module = Module::new("libcrash.so", 0x10000)
+            .function("crash")
+            .function("main");
+        let minidump = Minidump::new(Os::Linux, arch)
+            .module(module)
+            .thread(1, &[("libcrash.so", "crash"), ("libcrash.so", "main")])
+            .crashed(1);
+
+        let symbols_dir = test::tempdir();
+        minidump.write_debug_files(symbols_dir.path()).unwrap();
+        let mut minidump_file = NamedTempFile::new().unwrap();
+        minidump_file.write_all(&minidump.to_bytes()).unwrap();
+
+        let response = symbolication
+            .process_minidump(
+                Scope::Global,
+                minidump_file.into_temp_path(),
+                Arc::new([synth::symbols_source(symbols_dir.path())]),
+            )
+            .await
+            .unwrap();
+
+        let functions: Vec<_> = response.stacktraces[0]
+            .frames
+            .iter()
+            .map(|frame| frame.raw.function.as_deref())
+            .collect();
+        assert_eq!(functions, [Some("crash"), Some("main")], "{arch:?}");
+    }
+}
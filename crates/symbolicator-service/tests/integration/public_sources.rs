@@ -22,6 +22,7 @@ async fn test_nuget_source() {
             .parse()
             .unwrap(),
         headers: Default::default(),
+        auth: None,
         files: source_config(DirectoryLayoutType::Symstore, vec![FileType::PortablePdb]),
     }));
 
@@ -55,6 +56,7 @@ async fn test_ubuntu_source() {
         id: SourceId::new("ubuntu"),
         url: "https://debuginfod.ubuntu.com/buildid/".parse().unwrap(),
         headers: Default::default(),
+        auth: None,
         files: source_config(
             DirectoryLayoutType::Debuginfod,
             vec![FileType::ElfCode, FileType::ElfDebug],
@@ -1,6 +1,9 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use symbolicator_service::types::Scope;
+use symbolicator_service::types::{
+    CompletedSymbolicationResponse, FrameStatus, PackageKind, Scope, SymbolicationOptions,
+};
 
 use crate::{
     assert_snapshot, example_request, fixture, make_symbolication_request, setup_service,
@@ -45,6 +48,101 @@ async fn test_add_bucket() {
     assert_snapshot!(response.unwrap());
 }
 
+#[tokio::test]
+async fn test_function_sizes() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let (_symsrv, source) = symbol_server();
+
+    let symbolication = symbolication.with_options(SymbolicationOptions {
+        function_sizes: true,
+        ..Default::default()
+    });
+    let request = example_request(vec![source]);
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let frames: Vec<_> = response
+        .stacktraces
+        .iter()
+        .flat_map(|stacktrace| &stacktrace.frames)
+        .filter(|frame| frame.status == FrameStatus::Symbolicated)
+        .collect();
+    assert!(!frames.is_empty());
+    for frame in frames {
+        // The size is only determined for the outermost function, along with its address.
+        if let Some(sym_size) = frame.raw.sym_size {
+            let sym_addr = frame.raw.sym_addr.unwrap().0;
+            assert!(sym_addr <= frame.raw.instruction_addr.0);
+            assert!(frame.raw.instruction_addr.0 < sym_addr + sym_size);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_demangling_options() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    let request = || {
+        make_symbolication_request(
+            vec![],
+            "[]",
+            r#"[{
+              "frames":[{
+                "instruction_addr":"0x1000",
+                "function":"_ZN3foo3barEi"
+              }]
+            }]"#,
+        )
+    };
+
+    let response = symbolication.symbolicate(request()).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(frame.function.as_deref(), Some("foo::bar(int)"));
+    assert_eq!(frame.symbol.as_deref(), Some("_ZN3foo3barEi"));
+
+    let symbolication = symbolication.with_options(SymbolicationOptions {
+        demangle_parameters: Some(false),
+        include_symbols: false,
+        ..Default::default()
+    });
+    let response = symbolication.symbolicate(request()).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(frame.function.as_deref(), Some("foo::bar"));
+    assert_eq!(frame.symbol, None);
+}
+
+#[tokio::test]
+async fn test_demangling_config() {
+    let (symbolication, _cache_dir) = setup_service(|config| {
+        config.demangling.cpp.parameters = false;
+    });
+
+    let request = || {
+        make_symbolication_request(
+            vec![],
+            "[]",
+            r#"[{
+              "frames":[{
+                "instruction_addr":"0x1000",
+                "function":"_ZN3foo3barEi"
+              }]
+            }]"#,
+        )
+    };
+
+    let response = symbolication.symbolicate(request()).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(frame.function.as_deref(), Some("foo::bar"));
+
+    // Requests can override the configured default.
+    let symbolication = symbolication.with_options(SymbolicationOptions {
+        demangle_parameters: Some(true),
+        ..Default::default()
+    });
+    let response = symbolication.symbolicate(request()).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(frame.function.as_deref(), Some("foo::bar(int)"));
+}
+
 #[tokio::test]
 async fn test_apple_crash_report() {
     let (symbolication, _cache_dir) = setup_service(|_| ());
@@ -59,6 +157,145 @@ async fn test_apple_crash_report() {
     assert_snapshot!(response.unwrap());
 }
 
+#[tokio::test]
+async fn test_in_app_rules() {
+    let (symbolication, _cache_dir) = setup_service(|config| {
+        config.in_app_rules = serde_json::from_str(
+            r#"[
+              {"module": "/app/*", "in_app": true},
+              {"in_app": false}
+            ]"#,
+        )
+        .unwrap();
+    });
+
+    let symbolication = symbolication.with_options(SymbolicationOptions {
+        in_app_rules: serde_json::from_str(r#"[{"path": "*/vendor/*", "in_app": false}]"#).unwrap(),
+        ..Default::default()
+    });
+    let request = make_symbolication_request(
+        vec![],
+        "[]",
+        r#"[{
+          "frames":[
+            {"instruction_addr":"0x1000", "package":"/app/bin/app"},
+            {"instruction_addr":"0x2000", "package":"/app/bin/app", "abs_path":"/src/vendor/lib.c"},
+            {"instruction_addr":"0x3000", "package":"/usr/lib/libc.so"},
+            {"instruction_addr":"0x4000", "package":"/usr/lib/libc.so", "in_app":true}
+          ]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let in_app: Vec<_> = response.stacktraces[0]
+        .frames
+        .iter()
+        .map(|frame| frame.raw.in_app)
+        .collect();
+    assert_eq!(in_app, [Some(true), Some(false), Some(false), Some(true)]);
+}
+
+#[tokio::test]
+async fn test_package_rules() {
+    let (symbolication, _cache_dir) = setup_service(|config| {
+        config.package_rules = serde_json::from_str(
+            r#"[
+              {"modules": ["/usr/lib/*", "/lib/*"], "kind": "system"},
+              {"modules": ["/app/*"], "kind": "app"}
+            ]"#,
+        )
+        .unwrap();
+    });
+
+    let symbolication = symbolication.with_options(SymbolicationOptions {
+        package_rules: serde_json::from_str(
+            r#"[{"modules": ["/app/lib/*"], "kind": "dependency"}]"#,
+        )
+        .unwrap(),
+        ..Default::default()
+    });
+    let request = make_symbolication_request(
+        vec![],
+        "[]",
+        r#"[{
+          "frames":[
+            {"instruction_addr":"0x1000", "package":"/app/bin/app"},
+            {"instruction_addr":"0x2000", "package":"/app/lib/libcurl.so"},
+            {"instruction_addr":"0x3000", "package":"/usr/lib/libc.so"},
+            {"instruction_addr":"0x4000", "package":"/opt/other/libfoo.so"}
+          ]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let kinds: Vec<_> = response.stacktraces[0]
+        .frames
+        .iter()
+        .map(|frame| frame.raw.package_kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            Some(PackageKind::App),
+            Some(PackageKind::Dependency),
+            Some(PackageKind::System),
+            None
+        ]
+    );
+}
+
+/// Counts the frames each requested frame was expanded into, keyed by thread and frame index.
+fn count_expanded_frames(
+    response: &CompletedSymbolicationResponse,
+) -> BTreeMap<(usize, usize), usize> {
+    let mut counts = BTreeMap::new();
+    for (thread, stacktrace) in response.stacktraces.iter().enumerate() {
+        for frame in &stacktrace.frames {
+            *counts
+                .entry((thread, frame.original_index.unwrap()))
+                .or_default() += 1;
+        }
+    }
+    counts
+}
+
+#[tokio::test]
+async fn test_max_inline_frames() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let (_symsrv, source) = symbol_server();
+    let sources: Arc<[_]> = Arc::new([source]);
+
+    let symbolicate = |max_inline_frames| {
+        let symbolication = symbolication.with_options(SymbolicationOptions {
+            max_inline_frames,
+            ..Default::default()
+        });
+        let sources = sources.clone();
+        async move {
+            let report_file = std::fs::File::open(fixture("apple_crash_report.txt")).unwrap();
+            let response = symbolication
+                .process_apple_crash_report(Scope::Global, report_file, sources)
+                .await
+                .unwrap();
+            count_expanded_frames(&response)
+        }
+    };
+
+    let all = symbolicate(None).await;
+
+    let physical = symbolicate(Some(0)).await;
+    assert_eq!(
+        physical.keys().collect::<Vec<_>>(),
+        all.keys().collect::<Vec<_>>()
+    );
+    assert!(physical.values().all(|&count| count == 1));
+
+    let limited = symbolicate(Some(1)).await;
+    for (key, count) in limited {
+        assert_eq!(count, all[&key].min(2));
+    }
+}
+
 #[tokio::test]
 async fn test_wasm_payload() {
     let (symbolication, _cache_dir) = setup_service(|_| ());
@@ -1,11 +1,12 @@
 use std::sync::Arc;
 
-use symbolicator_service::config::Config;
+use symbolicator_service::config::{Config, ReservedIpsPolicy};
 use symbolicator_service::services::create_service;
 use symbolicator_service::services::symbolication::{
     StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor,
 };
 use symbolicator_service::types::RawObjectInfo;
+use symbolicator_service::utils::addr::KernelSpace;
 use symbolicator_sources::SourceConfig;
 use symbolicator_test as test;
 
@@ -30,7 +31,7 @@ pub fn setup_service(
 
     let mut config = Config {
         cache_dir: Some(cache_dir.path().to_owned()),
-        connect_to_reserved_ips: true,
+        connect_to_reserved_ips: ReservedIpsPolicy::Allow,
         ..Default::default()
     };
     update_config(&mut config);
@@ -58,6 +59,7 @@ pub fn make_symbolication_request(
         origin: StacktraceOrigin::Symbolicate,
         sources: Arc::from(sources),
         scope: Default::default(),
+        kernel_space: KernelSpace::Unknown,
     }
 }
 
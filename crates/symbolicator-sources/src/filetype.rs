@@ -55,6 +55,10 @@ pub enum FileType {
     /// This file maps from C++ source locations to the original C# source location it was transpiled from.
     #[serde(rename = "il2cpp")]
     Il2cpp,
+    /// A ProGuard mapping file, de-obfuscates class and method names of JVM stack traces.
+    ///
+    /// These are identified by the UUID of the mapping, which is used as the debug ID.
+    Proguard,
 }
 
 impl FileType {
@@ -137,6 +141,7 @@ impl AsRef<str> for FileType {
             FileType::UuidMap => "uuidmap",
             FileType::BcSymbolMap => "bcsymbolmap",
             FileType::Il2cpp => "il2cpp",
+            FileType::Proguard => "proguard",
             FileType::PortablePdb => "portablepdb",
         }
     }
@@ -1,12 +1,17 @@
 use std::borrow::Cow;
-use std::fmt::Write;
+use std::fmt::{self, Write};
+use std::str::FromStr;
 
+use serde::{de, Deserialize, Deserializer, Serialize};
 use symbolic::common::{CodeId, DebugId, Uuid};
 
 use crate::filetype::FileType;
 use crate::sources::{DirectoryLayout, DirectoryLayoutType, FilenameCasing};
 use crate::types::{Glob, ObjectId, ObjectType};
 
+/// The name of the pointer files of symbol stores, see [`DirectoryLayout::pointer_files`].
+pub const POINTER_FILE: &str = "file.ptr";
+
 const GLOB_OPTIONS: glob::MatchOptions = glob::MatchOptions {
     case_sensitive: false,
     require_literal_separator: false,
@@ -206,6 +211,7 @@ fn get_native_paths(filetype: FileType, identifier: &ObjectId) -> Vec<String> {
         FileType::UuidMap => Vec::new(),
         FileType::BcSymbolMap => Vec::new(),
         FileType::Il2cpp => Vec::new(),
+        FileType::Proguard => Vec::new(),
     }
 }
 
@@ -283,6 +289,7 @@ fn get_symstore_path(
         FileType::UuidMap => None,
         FileType::BcSymbolMap => None,
         FileType::Il2cpp => None,
+        FileType::Proguard => None,
     }
 }
 
@@ -329,6 +336,7 @@ fn get_debuginfod_path(filetype: FileType, identifier: &ObjectId) -> Option<Stri
         FileType::UuidMap => None,
         FileType::BcSymbolMap => None,
         FileType::Il2cpp => None,
+        FileType::Proguard => None,
     }
 }
 
@@ -369,7 +377,8 @@ fn get_search_target_id(filetype: FileType, identifier: &ObjectId) -> Option<Cow
         | FileType::WasmDebug
         | FileType::WasmCode
         | FileType::UuidMap
-        | FileType::BcSymbolMap => {
+        | FileType::BcSymbolMap
+        | FileType::Proguard => {
             if identifier.code_id.is_none() {
                 Some(Cow::Owned(
                     identifier.debug_id?.uuid().as_simple().to_string(),
@@ -401,6 +410,7 @@ fn get_unified_path(filetype: FileType, identifier: &ObjectId) -> Option<String>
         FileType::UuidMap => "uuidmap",
         FileType::BcSymbolMap => "bcsymbolmap",
         FileType::Il2cpp => "il2cpp",
+        FileType::Proguard => "proguard",
     };
 
     // determine the ID we use for the path
@@ -409,11 +419,181 @@ fn get_unified_path(filetype: FileType, identifier: &ObjectId) -> Option<String>
     Some(format!("{}/{}/{}", id.get(..2)?, id.get(2..)?, suffix))
 }
 
+/// A value that can be inserted into a [`PathTemplate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Placeholder {
+    /// The debug id, such as `3249d99d-0c40-4931-8610-f4e4fb0b6936-1`.
+    DebugId,
+    /// The debug id in breakpad format, such as `3249D99D0C4049318610F4E4FB0B69361`.
+    BreakpadId,
+    /// The code id.
+    CodeId,
+    /// The basename of the debug file.
+    DebugFile,
+    /// The basename of the code file.
+    CodeFile,
+    /// The requested [`FileType`], such as `pdb` or `elf_debug`.
+    FileType,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "debug_id" => Self::DebugId,
+            "breakpad_id" => Self::BreakpadId,
+            "code_id" => Self::CodeId,
+            "debug_file" => Self::DebugFile,
+            "code_file" => Self::CodeFile,
+            "file_type" => Self::FileType,
+            _ => return None,
+        })
+    }
+
+    fn value(self, filetype: FileType, identifier: &ObjectId) -> Option<String> {
+        Some(match self {
+            Self::DebugId => identifier.debug_id?.to_string(),
+            Self::BreakpadId => identifier.debug_id?.breakpad().to_string(),
+            Self::CodeId => identifier.code_id.as_ref()?.as_str().to_owned(),
+            Self::DebugFile => identifier.debug_file_basename()?.to_owned(),
+            Self::CodeFile => identifier.code_file_basename()?.to_owned(),
+            Self::FileType => filetype.as_ref().to_owned(),
+        })
+    }
+}
+
+/// A literal or a placeholder of a [`PathTemplate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Placeholder(Placeholder, FilenameCasing),
+}
+
+/// An error parsing a [`PathTemplate`].
+#[derive(Debug)]
+pub struct PathTemplateError(String);
+
+impl fmt::Display for PathTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid path template: {}", self.0)
+    }
+}
+
+impl std::error::Error for PathTemplateError {}
+
+/// A template for the paths of files in a source with a custom layout.
+///
+/// Placeholders in braces are replaced with the identifiers of the requested file, for instance
+/// `{debug_file}/{breakpad_id}/{file_type}`. The supported placeholders are `debug_id`,
+/// `breakpad_id`, `code_id`, `debug_file`, `code_file` and `file_type`. Each can be suffixed with
+/// `:upper` or `:lower` to change its casing, as in `{code_id:upper}`.
+///
+/// If any of the placeholders is not available for a file, the template yields no path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    source: String,
+    parts: Vec<TemplatePart>,
+}
+
+impl PathTemplate {
+    /// Renders the path of the given file.
+    pub fn render(&self, filetype: FileType, identifier: &ObjectId) -> Option<String> {
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => path.push_str(literal),
+                TemplatePart::Placeholder(placeholder, casing) => {
+                    let mut value = placeholder.value(filetype, identifier)?;
+                    match casing {
+                        FilenameCasing::Lowercase => value.make_ascii_lowercase(),
+                        FilenameCasing::Uppercase => value.make_ascii_uppercase(),
+                        FilenameCasing::Default => (),
+                    }
+                    path.push_str(&value);
+                }
+            }
+        }
+        Some(path)
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = PathTemplateError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_owned()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| PathTemplateError("unclosed placeholder".into()))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, casing) = match placeholder.split_once(':') {
+                None => (placeholder, FilenameCasing::Default),
+                Some((name, "upper")) => (name, FilenameCasing::Uppercase),
+                Some((name, "lower")) => (name, FilenameCasing::Lowercase),
+                Some((_, modifier)) => {
+                    return Err(PathTemplateError(format!("unknown modifier `{modifier}`")))
+                }
+            };
+            let placeholder = Placeholder::parse(name)
+                .ok_or_else(|| PathTemplateError(format!("unknown placeholder `{name}`")))?;
+
+            parts.push(TemplatePart::Placeholder(placeholder, casing));
+            rest = &rest[start + end + 1..];
+        }
+
+        if rest.contains('}') {
+            return Err(PathTemplateError("unopened placeholder".into()));
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_owned()));
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for PathTemplate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for PathTemplate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+/// Changes the casing of a whole `path` according to the `casing` of a layout.
+fn apply_casing(path: &mut String, casing: FilenameCasing) {
+    match casing {
+        FilenameCasing::Lowercase => path.make_ascii_lowercase(),
+        FilenameCasing::Uppercase => path.make_ascii_uppercase(),
+        FilenameCasing::Default => (),
+    }
+}
+
 /// Determines the paths for an object file in the given layout.
 ///
 /// The vector is ordered from lower priority to highest priority.
 pub fn get_directory_paths(
-    directory_layout: DirectoryLayout,
+    directory_layout: &DirectoryLayout,
     filetype: FileType,
     identifier: &ObjectId,
 ) -> Vec<String> {
@@ -434,14 +614,16 @@ pub fn get_directory_paths(
         DirectoryLayoutType::Unified => {
             get_unified_path(filetype, identifier).into_iter().collect()
         }
+        DirectoryLayoutType::Custom => directory_layout
+            .template
+            .as_ref()
+            .and_then(|template| template.render(filetype, identifier))
+            .into_iter()
+            .collect(),
     };
 
     for path in paths.iter_mut() {
-        match directory_layout.casing {
-            FilenameCasing::Lowercase => path.make_ascii_lowercase(),
-            FilenameCasing::Uppercase => path.make_ascii_uppercase(),
-            FilenameCasing::Default => (),
-        }
+        apply_casing(path, directory_layout.casing);
     }
 
     // when fetching PE and PDB files we generally allow the also the
@@ -458,6 +640,30 @@ pub fn get_directory_paths(
             .collect();
     };
 
+    // Pointers are looked up last, as symbol stores publish them only if the file is missing.
+    let is_symstore = matches!(
+        directory_layout.ty,
+        DirectoryLayoutType::Symstore
+            | DirectoryLayoutType::SymstoreIndex2
+            | DirectoryLayoutType::Ssqp
+    );
+    let has_pointers = matches!(
+        filetype,
+        FileType::Pdb | FileType::Pe | FileType::PortablePdb
+    );
+    if directory_layout.pointer_files && is_symstore && has_pointers {
+        let mut pointer_paths: Vec<_> = paths
+            .iter()
+            .filter_map(|path| {
+                let mut pointer_path = format!("{}/{POINTER_FILE}", path.rsplit_once('/')?.0);
+                apply_casing(&mut pointer_path, directory_layout.casing);
+                Some(pointer_path)
+            })
+            .collect();
+        pointer_paths.dedup();
+        paths.append(&mut pointer_paths);
+    }
+
     paths
 }
 
@@ -592,6 +798,16 @@ mod tests {
                 debug_checksum: None,
             object_type: ObjectType::Pe,
         };
+        /// A stripped third-party DLL without a PDB reference, which only has its code file,
+        /// timestamp, and size available.
+        static ref STRIPPED_PE_OBJECT_ID: ObjectId = ObjectId {
+            code_id: Some("5ab380779000".parse().unwrap()),
+            code_file: Some("C:\\Program Files\\Vendor\\vendor.dll".into()),
+            debug_id: None,
+            debug_file: None,
+            debug_checksum: None,
+            object_type: ObjectType::Pe,
+        };
         static ref MACHO_OBJECT_ID: ObjectId = ObjectId {
             code_id: None,
             code_file: Some("/Users/travis/build/getsentry/breakpad-tools/macos/build/./crash".into()),
@@ -634,6 +850,9 @@ mod tests {
         path_test!(FileType::Pe, PE_OBJECT_ID, @"crash.exe/5AB380779000/crash.exe");
         path_test!(FileType::Breakpad, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.sym");
         path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.src.zip");
+        path_test!(FileType::Pe, STRIPPED_PE_OBJECT_ID, @"vendor.dll/5AB380779000/vendor.dll");
+        path_test!(FileType::Pdb, STRIPPED_PE_OBJECT_ID, @"");
+        path_test!(FileType::Breakpad, STRIPPED_PE_OBJECT_ID, @"");
         path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"67E9/247C/814E/392B/A027/DBDE6748FCBF.app");
         path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"67E9/247C/814E/392B/A027/DBDE6748FCBF");
         path_test!(FileType::Breakpad, MACHO_OBJECT_ID, @"crash/67E9247C814E392BA027DBDE6748FCBF0/crash.sym");
@@ -701,6 +920,7 @@ mod tests {
         path_test!(FileType::Pdb, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
         path_test!(FileType::Pe, PE_OBJECT_ID, @"crash.exe/5AB380779000/crash.exe");
         path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.src.zip");
+        path_test!(FileType::Pe, STRIPPED_PE_OBJECT_ID, @"vendor.dll/5AB380779000/vendor.dll");
         path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"crash/mach-uuid-67e9247c814e392ba027dbde6748fcbf/crash");
         path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"_.dwarf/mach-uuid-sym-67e9247c814e392ba027dbde6748fcbf/_.dwarf");
         path_test!(FileType::ElfCode, ELF_OBJECT_ID, @"libm-2.23.so/elf-buildid-dfb85de42daffd09640c8fe377d572de3e168920/libm-2.23.so");
@@ -718,12 +938,88 @@ mod tests {
         path_test!(FileType::Pdb, PE_OBJECT_ID, @"cr/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
         path_test!(FileType::Pe, PE_OBJECT_ID, @"cr/crash.exe/5AB380779000/crash.exe");
         path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"cr/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.src.zip");
+        path_test!(FileType::Pe, STRIPPED_PE_OBJECT_ID, @"ve/vendor.dll/5AB380779000/vendor.dll");
         path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"cr/crash/mach-uuid-67e9247c814e392ba027dbde6748fcbf/crash");
         path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"_/_.dwarf/mach-uuid-sym-67e9247c814e392ba027dbde6748fcbf/_.dwarf");
         path_test!(FileType::ElfCode, ELF_OBJECT_ID, @"li/libm-2.23.so/elf-buildid-dfb85de42daffd09640c8fe377d572de3e168920/libm-2.23.so");
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"_/_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug");
     }
 
+    #[test]
+    fn test_get_directory_paths_pointers() {
+        let layout = DirectoryLayout {
+            ty: DirectoryLayoutType::Symstore,
+            pointer_files: true,
+            ..Default::default()
+        };
+
+        insta::assert_snapshot!(
+            get_directory_paths(&layout, FileType::Pdb, &PE_OBJECT_ID).join("\n"),
+            @r###"
+        crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pd_
+        crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb
+        crash.pdb/3249D99D0C4049318610F4E4FB0B69361/file.ptr
+        "###
+        );
+        insta::assert_snapshot!(
+            get_directory_paths(&layout, FileType::Breakpad, &PE_OBJECT_ID).join("\n"),
+            @""
+        );
+    }
+
+    #[test]
+    fn test_get_directory_paths_pointers_uppercase() {
+        let layout = DirectoryLayout {
+            ty: DirectoryLayoutType::Symstore,
+            casing: FilenameCasing::Uppercase,
+            pointer_files: true,
+            ..Default::default()
+        };
+
+        insta::assert_snapshot!(
+            get_directory_paths(&layout, FileType::Pdb, &PE_OBJECT_ID).join("\n"),
+            @r###"
+        CRASH.PDB/3249D99D0C4049318610F4E4FB0B69361/CRASH.PD_
+        CRASH.PDB/3249D99D0C4049318610F4E4FB0B69361/CRASH.PDB
+        CRASH.PDB/3249D99D0C4049318610F4E4FB0B69361/FILE.PTR
+        "###
+        );
+    }
+
+    #[test]
+    fn test_path_template() {
+        let template: PathTemplate = "{debug_file}/{breakpad_id:lower}/{file_type}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            template.render(FileType::Pdb, &PE_OBJECT_ID).unwrap(),
+            "crash.pdb/3249d99d0c4049318610f4e4fb0b69361/pdb"
+        );
+        assert_eq!(template.render(FileType::Pdb, &STRIPPED_PE_OBJECT_ID), None);
+
+        let template: PathTemplate = "builds/{code_id:upper}/{code_file}".parse().unwrap();
+        assert_eq!(
+            template.render(FileType::ElfCode, &ELF_OBJECT_ID).unwrap(),
+            "builds/DFB85DE42DAFFD09640C8FE377D572DE3E168920/libm-2.23.so"
+        );
+
+        assert!("{debug_id".parse::<PathTemplate>().is_err());
+        assert!("debug_id}".parse::<PathTemplate>().is_err());
+        assert!("{arch}".parse::<PathTemplate>().is_err());
+        assert!("{debug_id:title}".parse::<PathTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_custom_layout_requires_template() {
+        let layout: DirectoryLayout =
+            serde_yaml::from_str("{type: custom, template: \"{debug_file}/{debug_id}\"}").unwrap();
+        assert_eq!(layout.ty, DirectoryLayoutType::Custom);
+        assert!(layout.template.is_some());
+
+        let error = serde_yaml::from_str::<DirectoryLayout>("type: custom").unwrap_err();
+        assert!(error.to_string().contains("requires a template"));
+    }
+
     #[test]
     fn test_matches_path_patterns_empty() {
         assert!(matches_path_patterns(
@@ -5,14 +5,15 @@
 
 use std::fmt;
 use std::path::Path;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::{
-    get_directory_paths, CommonSourceConfig, DirectoryLayout, FileType, FilesystemRemoteFile,
-    GcsRemoteFile, HttpRemoteFile, ObjectId, S3RemoteFile, SentryRemoteFile, SourceFilters,
-    SourceId,
+    get_directory_paths, CommonSourceConfig, DirectoryLayout, FaultInjectionRemoteFile, FileType,
+    FilesystemRemoteFile, GcsRemoteFile, HttpRemoteFile, ObjectId, S3RemoteFile, SentryRemoteFile,
+    SourceConfig, SourceFilters, SourceId, POINTER_FILE,
 };
 
 /// A location for a file retrievable from many source configs.
@@ -91,7 +92,7 @@ pub struct SourceLocationIter<'a> {
     object_id: &'a ObjectId,
 
     /// Directory from `SourceConfig` to define what kind of paths we generate.
-    layout: DirectoryLayout,
+    layout: &'a DirectoryLayout,
 
     /// Remaining locations to iterate.
     next: Vec<String>,
@@ -108,7 +109,7 @@ impl<'a> SourceLocationIter<'a> {
             filetypes: filetypes.iter(),
             filters: &config.filters,
             object_id,
-            layout: config.layout,
+            layout: &config.layout,
             next: vec![],
         }
     }
@@ -138,8 +139,13 @@ impl Iterator for SourceLocationIter<'_> {
 /// This joins the file location together with a [`SourceConfig`](crate::SourceConfig) and thus
 /// provides all information to retrieve the DIF from its source.  The file could be any DIF type:
 /// an auxiliary DIF or an object file.
-#[derive(Debug, Clone)]
+///
+/// This can be serialized to pass it on to another process which downloads the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum RemoteFile {
+    /// A file on a fault injection source.
+    FaultInjection(FaultInjectionRemoteFile),
     /// A file on a filesystem source.
     Filesystem(FilesystemRemoteFile),
     /// A file on a gcs source.
@@ -167,6 +173,13 @@ impl fmt::Display for RemoteFile {
             Self::Gcs(ref s) => {
                 write!(f, "GCS source '{}' location '{}'", s.source.id, s.location)
             }
+            Self::FaultInjection(ref s) => {
+                write!(
+                    f,
+                    "Fault injection source '{}' location '{}'",
+                    s.source.id, s.location
+                )
+            }
             Self::Filesystem(ref s) => {
                 write!(
                     f,
@@ -186,6 +199,7 @@ impl RemoteFile {
             Self::Http(ref x) => x.source.files.is_public,
             Self::S3(ref x) => x.source.files.is_public,
             Self::Gcs(ref x) => x.source.files.is_public,
+            Self::FaultInjection(ref x) => x.source.files.is_public,
             Self::Filesystem(ref x) => x.source.files.is_public,
         }
     }
@@ -205,6 +219,9 @@ impl RemoteFile {
             Self::Gcs(ref x) => {
                 format!("{}.{}", x.source.id, x.location)
             }
+            Self::FaultInjection(ref x) => {
+                format!("{}.{}", x.source.id, x.location)
+            }
             Self::Filesystem(ref x) => {
                 format!("{}.{}", x.source.id, x.location)
             }
@@ -221,10 +238,31 @@ impl RemoteFile {
             Self::Http(ref x) => &x.source.id,
             Self::S3(ref x) => &x.source.id,
             Self::Gcs(ref x) => &x.source.id,
+            Self::FaultInjection(ref x) => &x.source.id,
             Self::Filesystem(ref x) => &x.source.id,
         }
     }
 
+    /// Returns whether this file is on the given `source`.
+    ///
+    /// This compares the identity of the source config rather than its ID, which sources sent with
+    /// requests may reuse.
+    pub fn is_on(&self, source: &SourceConfig) -> bool {
+        match (self, source) {
+            (Self::Sentry(x), SourceConfig::Sentry(source)) => Arc::ptr_eq(&x.source, source),
+            (Self::Http(x), SourceConfig::Http(source)) => Arc::ptr_eq(&x.source, source),
+            (Self::S3(x), SourceConfig::S3(source)) => Arc::ptr_eq(&x.source, source),
+            (Self::Gcs(x), SourceConfig::Gcs(source)) => Arc::ptr_eq(&x.source, source),
+            (Self::FaultInjection(x), SourceConfig::FaultInjection(source)) => {
+                Arc::ptr_eq(&x.source, source)
+            }
+            (Self::Filesystem(x), SourceConfig::Filesystem(source)) => {
+                Arc::ptr_eq(&x.source, source)
+            }
+            _ => false,
+        }
+    }
+
     /// Returns a key that uniquely identifies the source for metrics.
     ///
     /// If this is a built-in source the source_id is returned, otherwise this falls
@@ -243,6 +281,7 @@ impl RemoteFile {
             Self::S3(..) => "s3",
             Self::Gcs(..) => "gcs",
             Self::Http(..) => "http",
+            Self::FaultInjection(..) => "fault_injection",
             Self::Filesystem(..) => "filesystem",
         }
     }
@@ -259,19 +298,77 @@ impl RemoteFile {
             Self::Http(file_source) => file_source.uri(),
             Self::S3(file_source) => file_source.uri(),
             Self::Gcs(file_source) => file_source.uri(),
+            Self::FaultInjection(file_source) => file_source.uri(),
             Self::Filesystem(file_source) => file_source.uri(),
         }
     }
 
+    /// Returns the file next to this one, with `suffix` appended to its location.
+    ///
+    /// This is used for files accompanying an object file, like detached signatures. Returns
+    /// `None` for Sentry, whose files are not addressed by location.
+    pub fn with_suffix(&self, suffix: &str) -> Option<Self> {
+        let suffixed =
+            |location: &SourceLocation| SourceLocation(format!("{}{suffix}", location.0));
+        Some(match self {
+            Self::Sentry(_) => return None,
+            Self::Http(x) => {
+                let mut x = x.clone();
+                x.location = suffixed(&x.location);
+                x.into()
+            }
+            Self::S3(x) => {
+                let mut x = x.clone();
+                x.location = suffixed(&x.location);
+                x.into()
+            }
+            Self::Gcs(x) => {
+                let mut x = x.clone();
+                x.location = suffixed(&x.location);
+                x.into()
+            }
+            Self::FaultInjection(x) => {
+                let mut x = x.clone();
+                x.location = suffixed(&x.location);
+                x.into()
+            }
+            Self::Filesystem(x) => {
+                let mut x = x.clone();
+                x.location = suffixed(&x.location);
+                x.into()
+            }
+        })
+    }
+
+    /// Returns whether this is a symbol store pointer file, which contains the location of the
+    /// actual file.
+    pub fn is_pointer(&self) -> bool {
+        let location = match self {
+            Self::Sentry(_) => return false,
+            Self::Http(x) => &x.location,
+            Self::S3(x) => &x.location,
+            Self::Gcs(x) => &x.location,
+            Self::FaultInjection(x) => &x.location,
+            Self::Filesystem(x) => &x.location,
+        };
+        // Layouts may change the casing of the whole path, including the name of the pointer.
+        location
+            .segments()
+            .last()
+            .map_or(false, |name| name.eq_ignore_ascii_case(POINTER_FILE))
+    }
+
     /// Returns a string representation of the host this file resides on.
     ///
     /// This is:
     /// * The host name for http;
     /// * The bucket name for GCS and S3;
     /// * The URL for Sentry;
-    /// * A placeholder string for the filesystem.
+    /// * A placeholder string for the filesystem;
+    /// * The source ID for fault injection sources.
     pub fn host(&self) -> String {
         match self {
+            RemoteFile::FaultInjection(source) => source.host(),
             RemoteFile::Filesystem(source) => source.host(),
             RemoteFile::Gcs(source) => source.host(),
             RemoteFile::Http(source) => source.host(),
@@ -440,4 +537,33 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_remote_file_roundtrip() {
+        let source: crate::SourceConfig = serde_yaml::from_str(
+            r#"
+            id: http-source
+            type: http
+            url: https://example.org/symbols/
+            headers:
+              Authorization: secret
+            "#,
+        )
+        .unwrap();
+        let crate::SourceConfig::Http(source) = source else {
+            panic!("expected an http source");
+        };
+        let mut file = HttpRemoteFile::new(source, SourceLocation::new("foo/bar.pdb"));
+        file.headers.insert("X-Extra".into(), "1".into());
+        let file = RemoteFile::from(file);
+
+        let serialized = serde_yaml::to_string(&file).unwrap();
+        let RemoteFile::Http(roundtrip) = serde_yaml::from_str(&serialized).unwrap() else {
+            panic!("expected an http file");
+        };
+
+        assert_eq!(roundtrip.uri(), file.uri());
+        assert_eq!(roundtrip.source.headers["Authorization"], "secret");
+        assert_eq!(roundtrip.headers["X-Extra"], "1");
+    }
 }
@@ -3,17 +3,19 @@
 use std::fmt;
 use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::filetype::FileType;
-use crate::paths;
+use crate::paths::{self, PathTemplate};
 use crate::types::{Glob, ObjectId};
 
+mod faults;
 mod filesystem;
 mod gcs;
 mod http;
 mod s3;
 mod sentry;
+pub use faults::*;
 pub use filesystem::*;
 pub use gcs::*;
 pub use http::*;
@@ -55,6 +57,8 @@ impl fmt::Display for SourceId {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SourceConfig {
+    /// Local file system, with faults injected into downloads for testing.
+    FaultInjection(Arc<FaultInjectionSourceConfig>),
     /// Local file system.
     Filesystem(Arc<FilesystemSourceConfig>),
     /// A google cloud storage bucket.
@@ -71,6 +75,7 @@ impl SourceConfig {
     /// The unique identifier of this source.
     pub fn id(&self) -> &SourceId {
         match self {
+            Self::FaultInjection(x) => &x.id,
             Self::Filesystem(x) => &x.id,
             Self::Gcs(x) => &x.id,
             Self::Http(x) => &x.id,
@@ -82,6 +87,7 @@ impl SourceConfig {
     /// Name of this source.
     pub fn type_name(&self) -> &'static str {
         match self {
+            Self::FaultInjection(..) => "fault_injection",
             Self::Filesystem(..) => "filesystem",
             Self::Gcs(..) => "gcs",
             Self::Http(..) => "http",
@@ -134,6 +140,12 @@ pub struct SourceFilters {
     /// If a debug image does not contain any path information it will be treated like an image
     /// whose path doesn't match any pattern.
     pub path_patterns: Vec<Glob>,
+
+    /// Only use the identifiers provided with a request to look up files on this source.
+    ///
+    /// This disables heuristics which fill in missing identifiers, such as deriving the debug id
+    /// of an ELF module from its build id. Use this for sources where false positives are costly.
+    pub strict_ids: bool,
 }
 
 impl SourceFilters {
@@ -145,8 +157,11 @@ impl SourceFilters {
 }
 
 /// Determines how files are named in an external source.
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
-#[serde(default)]
+///
+/// A [`DirectoryLayoutType::Custom`] layout requires a [`template`](Self::template), which is
+/// checked when the layout is deserialized.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(remote = "Self", default)]
 pub struct DirectoryLayout {
     /// Directory layout of this symbol server.
     #[serde(rename = "type")]
@@ -159,6 +174,16 @@ pub struct DirectoryLayout {
     /// well-specified. For instance, in S3 buckets it is not possible to perform case-insensitive
     /// queries.
     pub casing: FilenameCasing,
+
+    /// The template of the paths of files, used by [`DirectoryLayoutType::Custom`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<PathTemplate>,
+
+    /// Whether to look up `file.ptr` pointer files next to PE and PDB files.
+    ///
+    /// This applies to the symstore layouts, in which `symstore.exe /p` publishes pointers to the
+    /// actual files instead of copies of them.
+    pub pointer_files: bool,
 }
 
 impl Default for DirectoryLayout {
@@ -166,10 +191,34 @@ impl Default for DirectoryLayout {
         DirectoryLayout {
             ty: DirectoryLayoutType::Native,
             casing: Default::default(),
+            template: None,
+            pointer_files: false,
         }
     }
 }
 
+impl<'de> Deserialize<'de> for DirectoryLayout {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let layout = Self::deserialize(deserializer)?;
+        if layout.ty == DirectoryLayoutType::Custom && layout.template.is_none() {
+            return Err(de::Error::custom("the custom layout requires a template"));
+        }
+        Ok(layout)
+    }
+}
+
+impl Serialize for DirectoryLayout {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Self::serialize(self, serializer)
+    }
+}
+
 /// Known conventions for [`DirectoryLayout`].
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum DirectoryLayoutType {
@@ -191,10 +240,13 @@ pub enum DirectoryLayoutType {
     /// Unified sentry proprietary bucket format.
     #[serde(rename = "unified")]
     Unified,
+    /// Paths are built from the [`template`](DirectoryLayout::template) of the layout.
+    #[serde(rename = "custom")]
+    Custom,
 }
 
 /// Casing of filenames on the symbol server
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[derive(Default)]
 pub enum FilenameCasing {
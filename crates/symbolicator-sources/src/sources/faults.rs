@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CommonSourceConfig, RemoteFile, RemoteFileUri, SourceId, SourceLocation};
+
+/// Configuration for a local directory which misbehaves on purpose.
+///
+/// This serves files like a [`FilesystemSourceConfig`](crate::FilesystemSourceConfig), but
+/// injects faults into downloads according to a seeded schedule. It is meant for testing how
+/// Symbolicator copes with unreliable sources, and should not be used in production.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FaultInjectionSourceConfig {
+    /// Unique source identifier.
+    pub id: SourceId,
+
+    /// Path to symbol directory.
+    pub path: PathBuf,
+
+    /// The faults which are injected into downloads.
+    #[serde(flatten)]
+    pub faults: FaultSchedule,
+
+    /// Configuration common to all sources.
+    #[serde(flatten)]
+    pub files: CommonSourceConfig,
+}
+
+/// How often the faults of a [`FaultInjectionSourceConfig`] are injected.
+///
+/// Rates are fractions of download attempts from `0` to `1`. Whether a fault is injected into an
+/// attempt only depends on the `seed`, the location of the file, and how often it has been
+/// attempted before, so the same schedule produces the same faults in every run.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FaultSchedule {
+    /// The seed of the schedule.
+    pub seed: u64,
+
+    /// The delay added to slow downloads.
+    #[serde(with = "humantime_serde")]
+    pub latency: Duration,
+
+    /// The rate of downloads which are delayed by `latency`.
+    ///
+    /// This is independent of the other faults, which may be injected into delayed downloads.
+    pub latency_rate: f64,
+
+    /// The rate of downloads which are rejected with `429 Too Many Requests`.
+    pub throttle_rate: f64,
+
+    /// The `Retry-After` period of throttled downloads, during which the source is skipped.
+    #[serde(with = "humantime_serde")]
+    pub retry_after: Duration,
+
+    /// The rate of downloads which only return the first half of the file.
+    pub truncate_rate: f64,
+
+    /// The rate of downloads which return the file with its header overwritten.
+    pub corrupt_rate: f64,
+}
+
+/// Fault-injection-specific [`RemoteFile`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultInjectionRemoteFile {
+    pub source: Arc<FaultInjectionSourceConfig>,
+    pub(crate) location: SourceLocation,
+}
+
+impl From<FaultInjectionRemoteFile> for RemoteFile {
+    fn from(source: FaultInjectionRemoteFile) -> Self {
+        Self::FaultInjection(source)
+    }
+}
+
+impl FaultInjectionRemoteFile {
+    /// Creates a new [`FaultInjectionRemoteFile`].
+    pub fn new(source: Arc<FaultInjectionSourceConfig>, location: SourceLocation) -> Self {
+        Self { source, location }
+    }
+
+    /// Returns the location of this file within the source.
+    pub fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+
+    /// Returns the path from which to fetch this object file.
+    pub fn path(&self) -> PathBuf {
+        self.source.path.join(self.location.path())
+    }
+
+    pub(crate) fn uri(&self) -> RemoteFileUri {
+        format!("file:///{}", self.path().display()).into()
+    }
+
+    /// Every source is its own host, so that the faults of one source only block that source.
+    pub(crate) fn host(&self) -> String {
+        format!("fault injection {}", self.source.id)
+    }
+}
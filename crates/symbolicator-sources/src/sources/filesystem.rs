@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -14,15 +15,26 @@ pub struct FilesystemSourceConfig {
     /// Path to symbol directory.
     pub path: PathBuf,
 
+    /// How long an in-memory index of the files in the symbol directory is used.
+    ///
+    /// If set, the directory is scanned once per interval, and only candidate paths which are
+    /// in the index are looked up. Otherwise, every candidate path is probed on disk.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub index_ttl: Option<Duration>,
+
     /// Configuration common to all sources.
     #[serde(flatten)]
     pub files: CommonSourceConfig,
 }
 
 /// Filesystem-specific [`RemoteFile`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilesystemRemoteFile {
-    pub(crate) source: Arc<FilesystemSourceConfig>,
+    pub source: Arc<FilesystemSourceConfig>,
     pub(crate) location: SourceLocation,
 }
 
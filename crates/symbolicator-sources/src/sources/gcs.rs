@@ -17,6 +17,12 @@ pub struct GcsSourceConfig {
     #[serde(default)]
     pub prefix: String,
 
+    /// The project that is billed for downloads, if this is a requester-pays bucket.
+    ///
+    /// This is sent as the `x-goog-user-project` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requester_pays: Option<String>,
+
     /// Authorization information for this bucket. Needs read access.
     #[serde(flatten)]
     pub source_key: Arc<GcsSourceKey>,
@@ -27,7 +33,7 @@ pub struct GcsSourceConfig {
 }
 
 /// The GCS-specific [`RemoteFile`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GcsRemoteFile {
     /// The underlying [`GcsSourceConfig`].
     pub source: Arc<GcsSourceConfig>,
@@ -19,13 +19,52 @@ pub struct HttpSourceConfig {
     #[serde(default)]
     pub headers: BTreeMap<String, String>,
 
+    /// Authentication with the symbol server, in addition to the `headers`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<HttpAuth>,
+
     /// Configuration common to all sources.
     #[serde(flatten)]
     pub files: CommonSourceConfig,
 }
 
+/// An authentication scheme for HTTP sources which cannot be expressed as static headers.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HttpAuth {
+    /// NTLMv2 authentication, as used by IIS servers with Windows authentication.
+    ///
+    /// Only the `NTLM` scheme is supported, not `Negotiate`.
+    Ntlm(NtlmCredentials),
+}
+
+/// The Windows account used for NTLM authentication.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NtlmCredentials {
+    /// The name of the user, optionally in the `DOMAIN\user` form.
+    pub username: String,
+    /// The password of the user.
+    pub password: String,
+    /// The domain of the user.
+    ///
+    /// Takes precedence over a domain in the `username`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub domain: String,
+}
+
+impl NtlmCredentials {
+    /// Returns the name and the domain of the user.
+    pub fn user_and_domain(&self) -> (&str, &str) {
+        match self.username.split_once('\\') {
+            Some((domain, user)) if self.domain.is_empty() => (user, domain),
+            Some((_, user)) => (user, &self.domain),
+            None => (&self.username, &self.domain),
+        }
+    }
+}
+
 /// The HTTP-specific [`RemoteFile`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpRemoteFile {
     /// The underlying [`HttpSourceConfig`].
     pub source: Arc<HttpSourceConfig>,
@@ -20,6 +20,21 @@ pub struct S3SourceConfig {
     #[serde(default)]
     pub prefix: String,
 
+    /// Whether this is a requester-pays bucket, whose download costs are charged to the
+    /// account of the credentials.
+    #[serde(default)]
+    pub requester_pays: bool,
+
+    /// The ARN of the KMS key that objects in this bucket must be encrypted with.
+    ///
+    /// Downloads of objects which are not encrypted with this key using SSE-KMS are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sse_kms_key_id: Option<String>,
+
+    /// The base64-encoded 256-bit key that objects in this bucket are encrypted with using SSE-C.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sse_customer_key: Option<String>,
+
     /// Authorization information for this bucket. Needs read access.
     #[serde(flatten)]
     pub source_key: Arc<S3SourceKey>,
@@ -30,7 +45,7 @@ pub struct S3SourceConfig {
 }
 
 /// The S3-specific [`RemoteFile`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct S3RemoteFile {
     /// The underlying [`S3SourceConfig`].
     pub source: Arc<S3SourceConfig>,
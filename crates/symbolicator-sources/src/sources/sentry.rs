@@ -1,5 +1,6 @@
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -17,10 +18,20 @@ pub struct SentrySourceConfig {
 
     /// Bearer authorization token.
     pub token: String,
+
+    /// How long the responses of the debug file index of this source are cached.
+    ///
+    /// Defaults to the `sentry_index_ttl` of the in-memory caches.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub index_ttl: Option<Duration>,
 }
 
 /// The Sentry-specific [`RemoteFile`].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SentryRemoteFile {
     /// The underlying [`SentrySourceConfig`].
     pub source: Arc<SentrySourceConfig>,
@@ -85,7 +96,7 @@ impl SentryRemoteFile {
 }
 
 /// An identifier for a file retrievable from a [`SentrySourceConfig`].
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SentryFileId(pub String);
 
 impl fmt::Display for SentryFileId {
@@ -95,7 +106,8 @@ impl fmt::Display for SentryFileId {
 }
 
 /// Available file types stored on Sentry servers.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SentryFileType {
     /// Native Debug Information File
     DebugFile,
@@ -8,7 +8,7 @@ use serde::{de, Deserialize, Deserializer, Serialize};
 use symbolic::common::{split_path, CodeId, DebugId};
 
 /// A Wrapper around [`glob::Pattern`] that allows de/serialization.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Glob(pub glob::Pattern);
 
 impl<'de> Deserialize<'de> for Glob {
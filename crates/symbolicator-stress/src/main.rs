@@ -17,6 +17,7 @@ use symbolicator_service::services::symbolication::{
 use symbolicator_service::types::{
     CompletedSymbolicationResponse, RawObjectInfo, RawStacktrace, Scope,
 };
+use symbolicator_service::utils::addr::KernelSpace;
 use tokio::sync::Semaphore;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -127,6 +128,7 @@ async fn main() -> Result<()> {
 
                         stacktraces,
                         modules,
+                        kernel_space: KernelSpace::Unknown,
                     })
                 }
             };
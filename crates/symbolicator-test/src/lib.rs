@@ -37,6 +37,8 @@ use symbolicator_sources::{
 
 pub use tempfile::TempDir;
 
+pub mod synth;
+
 /// Setup the test environment.
 ///
 ///  - Initializes logs: The logger only captures logs from the `symbolicator` crate and mutes all
@@ -102,6 +104,7 @@ pub fn local_source() -> SourceConfig {
     SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
         id: SourceId::new("local"),
         path: fixture("symbols"),
+        index_ttl: None,
         files: Default::default(),
     }))
 }
@@ -116,6 +119,7 @@ pub fn microsoft_symsrv() -> SourceConfig {
             .parse()
             .unwrap(),
         headers: Default::default(),
+        auth: None,
         files: CommonSourceConfig {
             filters: SourceFilters {
                 filetypes: vec![FileType::Pe, FileType::Pdb],
@@ -311,6 +315,7 @@ impl Server {
             filters: SourceFilters {
                 filetypes: vec![FileType::MachCode],
                 path_patterns: vec![],
+                strict_ids: false,
             },
             layout: Default::default(),
             is_public: false,
@@ -330,6 +335,7 @@ impl Server {
             id: SourceId::new(id),
             url: self.url(path),
             headers: Default::default(),
+            auth: None,
             files,
         }))
     }
@@ -365,6 +371,7 @@ pub fn symbol_server() -> (Server, SourceConfig) {
         id: SourceId::new("local"),
         url: server.url("symbols/"),
         headers: Default::default(),
+        auth: None,
         files: Default::default(),
     }));
 
@@ -378,6 +385,7 @@ pub fn sourcemap_server(fixtures_dir: &str) -> (Server, SentrySourceConfig) {
         id: SourceId::new("sentry:project"),
         url: server.url("/files/"),
         token: String::new(),
+        index_ttl: None,
     };
 
     (server, source)
@@ -0,0 +1,871 @@
+//! Generation of minidumps along with matching debug files.
+//!
+//! Tests for new platforms and formats can describe the modules and threads of a crashed process,
+//! instead of checking in large binary fixtures. [`Minidump::to_bytes`] writes a minidump of the
+//! crash, and [`Minidump::write_symbols`] writes Breakpad symbol files for all of its modules,
+//! which can be served with [`symbols_source`]. For Linux, [`Minidump::write_debug_files`] writes
+//! ELF debug files with a symbol table instead.
+//!
+//! Stacks are laid out as a chain of frame pointers, so that they can be unwound without CFI:
+//!
+//! ```
+//! use symbolicator_test::synth::{Arch, Minidump, Module, Os};
+//!
+//! let minidump = Minidump::new(Os::Linux, Arch::Amd64)
+//!     .module(Module::new("libfoo.so", 0x10000).function("foo").function("bar"))
+//!     .thread(1, &[("libfoo.so", "foo"), ("libfoo.so", "bar")])
+//!     .crashed(1);
+//!
+//! let dir = symbolicator_test::tempdir();
+//! minidump.write_symbols(dir.path()).unwrap();
+//! assert!(minidump.to_bytes().starts_with(b"MDMP"));
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use symbolicator_sources::{
+    DirectoryLayoutType, FileType, FilesystemSourceConfig, SourceConfig, SourceId,
+};
+
+/// The offset of the first function in a module.
+const FUNCTIONS_OFFSET: u64 = 0x1000;
+/// The size of every function in a module.
+const FUNCTION_SIZE: u64 = 0x100;
+/// The offset of frame addresses into their function.
+const FRAME_OFFSET: u64 = 0x10;
+/// The address of the stack of the first thread.
+const STACK_BASE: u64 = 0x7ff0_0000;
+/// The timestamp of PE modules, which is part of their code ID.
+const PE_TIMESTAMP: u32 = 0x5ab3_8077;
+
+/// The operating system of the crashed process, which determines the format of its modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    /// Windows, with PE modules and PDB debug files.
+    Windows,
+    /// macOS, with Mach-O modules.
+    MacOs,
+    /// Linux, with ELF modules.
+    Linux,
+}
+
+impl Os {
+    fn platform_id(self) -> u32 {
+        match self {
+            Os::Windows => 2,
+            Os::MacOs => 0x8101,
+            Os::Linux => 0x8201,
+        }
+    }
+
+    fn breakpad_name(self) -> &'static str {
+        match self {
+            Os::Windows => "windows",
+            Os::MacOs => "mac",
+            Os::Linux => "Linux",
+        }
+    }
+
+    /// The exception code of an invalid memory access.
+    fn crash_code(self) -> u32 {
+        match self {
+            Os::Windows => 0xc000_0005, // EXCEPTION_ACCESS_VIOLATION
+            Os::MacOs => 1,             // EXC_BAD_ACCESS
+            Os::Linux => 11,            // SIGSEGV
+        }
+    }
+}
+
+/// The CPU architecture of the crashed process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// 32-bit x86.
+    X86,
+    /// 64-bit x86.
+    Amd64,
+    /// 64-bit ARM.
+    Arm64,
+}
+
+impl Arch {
+    fn processor_architecture(self) -> u16 {
+        match self {
+            Arch::X86 => 0,
+            Arch::Amd64 => 9,
+            Arch::Arm64 => 12,
+        }
+    }
+
+    fn breakpad_name(self) -> &'static str {
+        match self {
+            Arch::X86 => "x86",
+            Arch::Amd64 => "x86_64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+
+    fn pointer_size(self) -> u64 {
+        match self {
+            Arch::X86 => 4,
+            Arch::Amd64 | Arch::Arm64 => 8,
+        }
+    }
+
+    fn elf_machine(self) -> u16 {
+        match self {
+            Arch::X86 => 3,     // EM_386
+            Arch::Amd64 => 62,  // EM_X86_64
+            Arch::Arm64 => 183, // EM_AARCH64
+        }
+    }
+}
+
+/// A module loaded into the crashed process.
+#[derive(Debug, Clone)]
+pub struct Module {
+    name: String,
+    base: u64,
+    functions: Vec<String>,
+}
+
+impl Module {
+    /// Creates a module with the given file name, loaded at `base`.
+    ///
+    /// On Windows, the name is that of the PE file, and its PDB is named after it.
+    pub fn new(name: impl Into<String>, base: u64) -> Self {
+        Self {
+            name: name.into(),
+            base,
+            functions: vec![],
+        }
+    }
+
+    /// Adds a function to the module, right after the previously added one.
+    pub fn function(mut self, name: impl Into<String>) -> Self {
+        self.functions.push(name.into());
+        self
+    }
+
+    fn size(&self) -> u64 {
+        FUNCTIONS_OFFSET + self.functions.len() as u64 * FUNCTION_SIZE
+    }
+
+    /// The address of a frame in `function`, or `None` if the module does not contain it.
+    fn address(&self, function: &str) -> Option<u64> {
+        let index = self.functions.iter().position(|f| f == function)? as u64;
+        Some(self.base + FUNCTIONS_OFFSET + index * FUNCTION_SIZE + FRAME_OFFSET)
+    }
+
+    /// The UUID of the module, derived from its name, in the byte order it is displayed in.
+    fn uuid(&self) -> [u8; 16] {
+        // FNV-1a, with two different offsets to fill all 16 bytes.
+        let hash = |offset: u64| {
+            self.name.bytes().fold(offset, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3)
+            })
+        };
+        let mut uuid = [0; 16];
+        uuid[..8].copy_from_slice(&hash(0xcbf2_9ce4_8422_2325).to_be_bytes());
+        uuid[8..].copy_from_slice(&hash(0x6c62_272e_07bb_0142).to_be_bytes());
+        uuid
+    }
+
+    /// The age of the debug file, which is only used by PDBs.
+    fn age(&self, os: Os) -> u32 {
+        match os {
+            Os::Windows => 1,
+            Os::MacOs | Os::Linux => 0,
+        }
+    }
+
+    fn debug_file(&self, os: Os) -> String {
+        match os {
+            Os::Windows => {
+                let stem = self
+                    .name
+                    .rsplit_once('.')
+                    .map_or(&*self.name, |(stem, _)| stem);
+                format!("{stem}.pdb")
+            }
+            Os::MacOs | Os::Linux => self.name.clone(),
+        }
+    }
+
+    /// The debug ID, in the format used by Breakpad.
+    fn breakpad_id(&self, os: Os) -> String {
+        format!("{}{:X}", hex(&self.uuid()).to_uppercase(), self.age(os))
+    }
+
+    fn code_id(&self, os: Os) -> String {
+        match os {
+            Os::Windows => format!("{PE_TIMESTAMP:08X}{:x}", self.size()),
+            // The build ID is the raw GUID that the debug ID is read from.
+            Os::Linux => hex(&guid_bytes(self.uuid())),
+            Os::MacOs => hex(&self.uuid()),
+        }
+    }
+
+    /// The CodeView record of the module in the minidump.
+    fn codeview_record(&self, os: Os) -> Vec<u8> {
+        let mut record = Vec::new();
+        match os {
+            Os::Linux => {
+                record.extend_from_slice(&0x4270_454cu32.to_le_bytes()); // BpEL
+                record.extend_from_slice(&guid_bytes(self.uuid()));
+            }
+            Os::Windows | Os::MacOs => {
+                record.extend_from_slice(&0x5344_5352u32.to_le_bytes()); // RSDS
+                record.extend_from_slice(&guid_bytes(self.uuid()));
+                record.extend_from_slice(&self.age(os).to_le_bytes());
+                record.extend_from_slice(self.debug_file(os).as_bytes());
+                record.push(0);
+            }
+        }
+        record
+    }
+
+    /// Renders the Breakpad symbol file of the module.
+    fn breakpad_symbols(&self, os: Os, arch: Arch) -> String {
+        let debug_file = self.debug_file(os);
+        let mut symbols = format!(
+            "MODULE {} {} {} {debug_file}\nINFO CODE_ID {} {}\n",
+            os.breakpad_name(),
+            arch.breakpad_name(),
+            self.breakpad_id(os),
+            self.code_id(os).to_uppercase(),
+            self.name,
+        );
+
+        let stem = debug_file.split('.').next().unwrap_or(&debug_file);
+        symbols.push_str(&format!("FILE 0 {stem}.c\n"));
+        for (index, function) in self.functions.iter().enumerate() {
+            let address = FUNCTIONS_OFFSET + index as u64 * FUNCTION_SIZE;
+            let line = (index + 1) * 10;
+            symbols.push_str(&format!(
+                "FUNC {address:x} {FUNCTION_SIZE:x} 0 {function}\n"
+            ));
+            symbols.push_str(&format!("{address:x} {FUNCTION_SIZE:x} {line} 0\n"));
+        }
+        symbols
+    }
+
+    /// Renders an ELF debug file of the module, with a symbol table and a build ID note.
+    ///
+    /// The file has no program headers and the `.text` section holds no data, like the debug files
+    /// created by `objcopy --only-keep-debug`.
+    fn elf_debug_file(&self, arch: Arch) -> Vec<u8> {
+        let word = arch.pointer_size();
+        let (header_size, section_header_size, symbol_size) = match word {
+            4 => (52, 40, 16),
+            _ => (64, 64, 24),
+        };
+
+        // The sections follow the file header, which is written last.
+        let mut file = Writer::default();
+        file.zeros(header_size);
+
+        let mut note = Writer::default();
+        note.u32(4); // name size
+        note.u32(16); // descriptor size
+        note.u32(3); // NT_GNU_BUILD_ID
+        note.buf.extend_from_slice(b"GNU\0");
+        note.buf.extend_from_slice(&guid_bytes(self.uuid()));
+        let note = file.data(&note.buf);
+
+        let mut strtab = vec![0];
+        let mut symbols = Writer::default();
+        symbols.zeros(symbol_size);
+        for (index, function) in self.functions.iter().enumerate() {
+            let name = strtab.len() as u32;
+            strtab.extend_from_slice(function.as_bytes());
+            strtab.push(0);
+
+            let address = FUNCTIONS_OFFSET + index as u64 * FUNCTION_SIZE;
+            symbols.u32(name);
+            if word == 4 {
+                symbols.u32(address as u32);
+                symbols.u32(FUNCTION_SIZE as u32);
+            }
+            symbols.u8(0x12); // STB_GLOBAL, STT_FUNC
+            symbols.u8(0); // visibility
+            symbols.u16(1); // .text
+            if word != 4 {
+                symbols.u64(address);
+                symbols.u64(FUNCTION_SIZE);
+            }
+        }
+        let symtab = file.data(&symbols.buf);
+        let strtab = file.data(&strtab);
+
+        let names = [
+            "",
+            ".text",
+            ".note.gnu.build-id",
+            ".symtab",
+            ".strtab",
+            ".shstrtab",
+        ];
+        let mut shstrtab = vec![];
+        let mut name_offsets = vec![];
+        for name in names {
+            name_offsets.push(shstrtab.len() as u32);
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+        }
+        let shstrtab = file.data(&shstrtab);
+
+        let sections = [
+            ElfSection::default(),
+            ElfSection {
+                ty: 8,    // SHT_NOBITS
+                flags: 6, // SHF_ALLOC | SHF_EXECINSTR
+                addr: FUNCTIONS_OFFSET,
+                offset: u64::from(note.1),
+                size: self.functions.len() as u64 * FUNCTION_SIZE,
+                align: 16,
+                ..Default::default()
+            },
+            ElfSection {
+                ty: 7,    // SHT_NOTE
+                flags: 2, // SHF_ALLOC
+                offset: u64::from(note.1),
+                size: u64::from(note.0),
+                align: 4,
+                ..Default::default()
+            },
+            ElfSection {
+                ty: 2, // SHT_SYMTAB
+                offset: u64::from(symtab.1),
+                size: u64::from(symtab.0),
+                link: 4,
+                info: 1, // the index of the first global symbol
+                align: word,
+                entsize: symbol_size as u64,
+                ..Default::default()
+            },
+            ElfSection {
+                ty: 3, // SHT_STRTAB
+                offset: u64::from(strtab.1),
+                size: u64::from(strtab.0),
+                align: 1,
+                ..Default::default()
+            },
+            ElfSection {
+                ty: 3, // SHT_STRTAB
+                offset: u64::from(shstrtab.1),
+                size: u64::from(shstrtab.0),
+                align: 1,
+                ..Default::default()
+            },
+        ];
+
+        file.align();
+        let section_headers = file.position() as u64;
+        for (section, name) in sections.iter().zip(name_offsets) {
+            section.write(&mut file, word, name);
+        }
+
+        let mut header = Writer::default();
+        header.buf.extend_from_slice(b"\x7fELF");
+        header.u8(if word == 4 { 1 } else { 2 }); // ELFCLASS32 or ELFCLASS64
+        header.u8(1); // ELFDATA2LSB
+        header.u8(1); // EV_CURRENT
+        header.zeros(9);
+        header.u16(3); // ET_DYN
+        header.u16(arch.elf_machine());
+        header.u32(1); // EV_CURRENT
+        header.word(word, 0); // entry point
+        header.word(word, 0); // program headers
+        header.word(word, section_headers);
+        header.u32(0); // flags
+        header.u16(header_size as u16);
+        header.u16(if word == 4 { 32 } else { 56 }); // program header size
+        header.u16(0); // number of program headers
+        header.u16(section_header_size);
+        header.u16(sections.len() as u16);
+        header.u16(5); // .shstrtab
+        file.buf[..header_size].copy_from_slice(&header.buf);
+
+        file.buf
+    }
+}
+
+/// A section header of an ELF file.
+#[derive(Debug, Default)]
+struct ElfSection {
+    ty: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl ElfSection {
+    /// Writes the section header, with the offset of its `name` in the section name table.
+    fn write(&self, writer: &mut Writer, word: u64, name: u32) {
+        writer.u32(name);
+        writer.u32(self.ty);
+        writer.word(word, self.flags);
+        writer.word(word, self.addr);
+        writer.word(word, self.offset);
+        writer.word(word, self.size);
+        writer.u32(self.link);
+        writer.u32(self.info);
+        writer.word(word, self.align);
+        writer.word(word, self.entsize);
+    }
+}
+
+/// A thread of the crashed process, with the addresses of its frames, innermost first.
+#[derive(Debug, Clone)]
+struct Thread {
+    id: u32,
+    frames: Vec<u64>,
+}
+
+/// A minidump of a crashed process.
+#[derive(Debug, Clone)]
+pub struct Minidump {
+    os: Os,
+    arch: Arch,
+    modules: Vec<Module>,
+    threads: Vec<Thread>,
+    crashed_thread: Option<u32>,
+}
+
+impl Minidump {
+    /// Creates a minidump without any modules or threads.
+    pub fn new(os: Os, arch: Arch) -> Self {
+        Self {
+            os,
+            arch,
+            modules: vec![],
+            threads: vec![],
+            crashed_thread: None,
+        }
+    }
+
+    /// Adds a loaded module.
+    pub fn module(mut self, module: Module) -> Self {
+        self.modules.push(module);
+        self
+    }
+
+    /// Adds a thread, whose stack consists of the given `(module, function)` frames.
+    ///
+    /// The frames are listed innermost first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a frame refers to a function that is not in one of the added modules.
+    pub fn thread(mut self, id: u32, frames: &[(&str, &str)]) -> Self {
+        let frames = frames
+            .iter()
+            .map(|&(module, function)| {
+                self.modules
+                    .iter()
+                    .filter(|m| m.name == module)
+                    .find_map(|m| m.address(function))
+                    .unwrap_or_else(|| panic!("unknown frame {module}!{function}"))
+            })
+            .collect();
+        self.threads.push(Thread { id, frames });
+        self
+    }
+
+    /// Marks the thread with the given ID as the one which crashed.
+    pub fn crashed(mut self, thread_id: u32) -> Self {
+        self.crashed_thread = Some(thread_id);
+        self
+    }
+
+    /// Returns the debug ID of the named module, as reported by Symbolicator.
+    pub fn debug_id(&self, module: &str) -> Option<String> {
+        let module = self.modules.iter().find(|m| m.name == module)?;
+        let uuid = hex(&module.uuid());
+        let mut debug_id = format!(
+            "{}-{}-{}-{}-{}",
+            &uuid[..8],
+            &uuid[8..12],
+            &uuid[12..16],
+            &uuid[16..20],
+            &uuid[20..]
+        );
+        match module.age(self.os) {
+            0 => {}
+            age => debug_id.push_str(&format!("-{age:x}")),
+        }
+        Some(debug_id)
+    }
+
+    /// Writes the Breakpad symbol files of all modules to `dir`, in the native layout.
+    pub fn write_symbols(&self, dir: &Path) -> io::Result<()> {
+        for module in &self.modules {
+            let debug_file = module.debug_file(self.os);
+            let stem = ["exe", "dll", "pdb"]
+                .iter()
+                .find_map(|ext| debug_file.strip_suffix(&format!(".{ext}")))
+                .unwrap_or(&debug_file);
+
+            let path = dir
+                .join(&debug_file)
+                .join(module.breakpad_id(self.os))
+                .join(format!("{stem}.sym"));
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, module.breakpad_symbols(self.os, self.arch))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the ELF debug files of all modules to `dir`, in the native layout.
+    ///
+    /// Only Linux modules have ELF debug files, so this fails with
+    /// [`Unsupported`](io::ErrorKind::Unsupported) for other operating systems.
+    pub fn write_debug_files(&self, dir: &Path) -> io::Result<()> {
+        if self.os != Os::Linux {
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+
+        for module in &self.modules {
+            let code_id = module.code_id(self.os);
+            let path = dir
+                .join(&code_id[..2])
+                .join(format!("{}.debug", &code_id[2..]));
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, module.elf_debug_file(self.arch))?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the minidump.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        let num_streams = if self.crashed_thread.is_some() { 4 } else { 3 };
+
+        // MINIDUMP_HEADER
+        writer.u32(0x504d_444d); // MDMP
+        writer.u32(0xa793);
+        writer.u32(num_streams);
+        writer.u32(32); // the stream directory follows the header
+        writer.u32(0); // checksum
+        writer.u32(PE_TIMESTAMP);
+        writer.u64(0); // flags
+        let directory = writer.position();
+        writer.zeros(num_streams as usize * 12);
+
+        let mut streams = vec![
+            (7, self.write_system_info(&mut writer)),
+            (4, self.write_module_list(&mut writer)),
+        ];
+        let (thread_list, contexts) = self.write_thread_list(&mut writer);
+        streams.push((3, thread_list));
+        if let Some(thread_id) = self.crashed_thread {
+            let thread = self.threads.iter().position(|t| t.id == thread_id);
+            let context = thread.map_or((0, 0), |index| contexts[index]);
+            streams.push((6, self.write_exception(&mut writer, thread_id, context)));
+        }
+
+        for (index, (stream_type, (size, rva))) in streams.into_iter().enumerate() {
+            let entry = directory + index * 12;
+            writer.patch_u32(entry, stream_type);
+            writer.patch_u32(entry + 4, size);
+            writer.patch_u32(entry + 8, rva);
+        }
+
+        writer.buf
+    }
+
+    /// Writes the MINIDUMP_SYSTEM_INFO stream.
+    fn write_system_info(&self, writer: &mut Writer) -> (u32, u32) {
+        let csd_version = writer.string("");
+
+        let (major, minor, build) = match self.os {
+            Os::Windows => (10, 0, 19045),
+            Os::MacOs => (13, 0, 22_000),
+            Os::Linux => (5, 15, 0),
+        };
+
+        writer.stream(|writer| {
+            writer.u16(self.arch.processor_architecture());
+            writer.u16(0); // processor level
+            writer.u16(0); // processor revision
+            writer.u8(1); // number of processors
+            writer.u8(1); // product type
+            writer.u32(major);
+            writer.u32(minor);
+            writer.u32(build);
+            writer.u32(self.os.platform_id());
+            writer.u32(csd_version);
+            writer.u16(0); // suite mask
+            writer.u16(0);
+            writer.zeros(24); // CPU information
+        })
+    }
+
+    /// Writes the MINIDUMP_MODULE_LIST stream.
+    fn write_module_list(&self, writer: &mut Writer) -> (u32, u32) {
+        let references: Vec<_> = self
+            .modules
+            .iter()
+            .map(|module| {
+                let name = writer.string(&module.name);
+                let record = writer.data(&module.codeview_record(self.os));
+                (name, record)
+            })
+            .collect();
+
+        writer.stream(|writer| {
+            writer.u32(self.modules.len() as u32);
+            for (module, (name, (record_size, record_rva))) in self.modules.iter().zip(references) {
+                writer.u64(module.base);
+                writer.u32(module.size() as u32);
+                writer.u32(0); // checksum
+                writer.u32(PE_TIMESTAMP);
+                writer.u32(name);
+                writer.zeros(52); // VS_FIXEDFILEINFO
+                writer.u32(record_size);
+                writer.u32(record_rva);
+                writer.zeros(8); // misc record
+                writer.zeros(16); // reserved
+            }
+        })
+    }
+
+    /// Writes the MINIDUMP_THREAD_LIST stream, returning it along with the thread contexts.
+    fn write_thread_list(&self, writer: &mut Writer) -> ((u32, u32), Vec<(u32, u32)>) {
+        let mut stacks = vec![];
+        let mut contexts = vec![];
+        for (index, thread) in self.threads.iter().enumerate() {
+            let stack_base = STACK_BASE + index as u64 * 0x10000;
+            let (stack, context) = self.thread_memory(thread, stack_base);
+            stacks.push((stack_base, writer.data(&stack)));
+            contexts.push(writer.data(&context));
+        }
+
+        let list = writer.stream(|writer| {
+            writer.u32(self.threads.len() as u32);
+            for ((thread, stack), context) in self.threads.iter().zip(&stacks).zip(&contexts) {
+                let &(stack_base, (stack_size, stack_rva)) = stack;
+                writer.u32(thread.id);
+                writer.u32(0); // suspend count
+                writer.u32(0); // priority class
+                writer.u32(0); // priority
+                writer.u64(0); // TEB
+                writer.u64(stack_base);
+                writer.u32(stack_size);
+                writer.u32(stack_rva);
+                writer.u32(context.0);
+                writer.u32(context.1);
+            }
+        });
+
+        (list, contexts)
+    }
+
+    /// Writes the MINIDUMP_EXCEPTION_STREAM of the crashed thread.
+    fn write_exception(
+        &self,
+        writer: &mut Writer,
+        thread_id: u32,
+        context: (u32, u32),
+    ) -> (u32, u32) {
+        let address = self
+            .threads
+            .iter()
+            .find(|t| t.id == thread_id)
+            .and_then(|t| t.frames.first().copied())
+            .unwrap_or_default();
+
+        writer.stream(|writer| {
+            writer.u32(thread_id);
+            writer.u32(0);
+            writer.u32(self.os.crash_code());
+            writer.u32(0); // flags
+            writer.u64(0); // nested exception record
+            writer.u64(address);
+            writer.u32(0); // number of parameters
+            writer.u32(0);
+            writer.zeros(15 * 8);
+            writer.u32(context.0);
+            writer.u32(context.1);
+        })
+    }
+
+    /// Lays out the stack memory and CPU context of a thread.
+    ///
+    /// The stack is a chain of frame records, each holding the frame pointer and return address
+    /// of the caller. On ARM64, the return address of the innermost frame is in the link register
+    /// instead.
+    fn thread_memory(&self, thread: &Thread, stack_base: u64) -> (Vec<u8>, Vec<u8>) {
+        let word = self.arch.pointer_size();
+        let frame = |index: usize| thread.frames.get(index).copied().unwrap_or_default();
+        let (link_register, first_return) = match self.arch {
+            Arch::Arm64 => (frame(1), 2),
+            Arch::X86 | Arch::Amd64 => (0, 1),
+        };
+
+        let mut stack = Writer::default();
+        for index in 0..thread.frames.len() {
+            let next_record = stack_base + (index as u64 + 1) * 2 * word;
+            let return_address = frame(index + first_return);
+            let caller_fp = if return_address != 0 { next_record } else { 0 };
+            stack.word(word, caller_fp);
+            stack.word(word, return_address);
+        }
+        stack.zeros(2 * word as usize);
+
+        let ip = frame(0);
+        let context = match self.arch {
+            Arch::X86 => x86_context(ip, stack_base),
+            Arch::Amd64 => amd64_context(ip, stack_base),
+            Arch::Arm64 => arm64_context(ip, stack_base, link_register),
+        };
+        (stack.buf, context)
+    }
+}
+
+/// Returns a [`SourceConfig`] serving the files written by [`Minidump::write_symbols`] and
+/// [`Minidump::write_debug_files`].
+pub fn symbols_source(dir: &Path) -> SourceConfig {
+    SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+        id: SourceId::new("synthetic"),
+        path: dir.to_owned(),
+        index_ttl: None,
+        files: crate::source_config(
+            DirectoryLayoutType::Native,
+            vec![FileType::Breakpad, FileType::ElfDebug],
+        ),
+    }))
+}
+
+/// Converts a UUID into the byte order of a GUID in memory, or the other way around.
+fn guid_bytes(mut uuid: [u8; 16]) -> [u8; 16] {
+    uuid[..4].reverse();
+    uuid[4..6].reverse();
+    uuid[6..8].reverse();
+    uuid
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A CONTEXT_X86 with the control and integer registers.
+fn x86_context(ip: u64, stack: u64) -> Vec<u8> {
+    let mut context = vec![0; 716];
+    put(&mut context, 0, 0x1_0003u32.to_le_bytes());
+    put(&mut context, 180, (stack as u32).to_le_bytes()); // ebp
+    put(&mut context, 184, (ip as u32).to_le_bytes()); // eip
+    put(&mut context, 196, (stack as u32).to_le_bytes()); // esp
+    context
+}
+
+/// A CONTEXT_AMD64 with the control and integer registers.
+fn amd64_context(ip: u64, stack: u64) -> Vec<u8> {
+    let mut context = vec![0; 1232];
+    put(&mut context, 48, 0x10_0003u32.to_le_bytes());
+    put(&mut context, 152, stack.to_le_bytes()); // rsp
+    put(&mut context, 160, stack.to_le_bytes()); // rbp
+    put(&mut context, 248, ip.to_le_bytes()); // rip
+    context
+}
+
+/// A CONTEXT_ARM64 with the control and integer registers.
+fn arm64_context(ip: u64, stack: u64, link_register: u64) -> Vec<u8> {
+    let mut context = vec![0; 912];
+    put(&mut context, 0, 0x40_0003u32.to_le_bytes());
+    put(&mut context, 240, stack.to_le_bytes()); // fp
+    put(&mut context, 248, link_register.to_le_bytes()); // lr
+    put(&mut context, 256, stack.to_le_bytes()); // sp
+    put(&mut context, 264, ip.to_le_bytes()); // pc
+    context
+}
+
+fn put<const N: usize>(buf: &mut [u8], offset: usize, bytes: [u8; N]) {
+    buf[offset..offset + N].copy_from_slice(&bytes);
+}
+
+/// Appends little-endian data to a minidump.
+#[derive(Debug, Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn position(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a pointer-sized value.
+    fn word(&mut self, size: u64, value: u64) {
+        match size {
+            4 => self.u32(value as u32),
+            _ => self.u64(value),
+        }
+    }
+
+    fn zeros(&mut self, len: usize) {
+        self.buf.resize(self.buf.len() + len, 0);
+    }
+
+    fn align(&mut self) {
+        let padding = (4 - self.buf.len() % 4) % 4;
+        self.zeros(padding);
+    }
+
+    fn patch_u32(&mut self, offset: usize, value: u32) {
+        put(&mut self.buf, offset, value.to_le_bytes());
+    }
+
+    /// Writes a blob, returning its location descriptor as `(size, rva)`.
+    fn data(&mut self, data: &[u8]) -> (u32, u32) {
+        self.align();
+        let rva = self.position() as u32;
+        self.buf.extend_from_slice(data);
+        (data.len() as u32, rva)
+    }
+
+    /// Writes a MINIDUMP_STRING, returning its RVA.
+    fn string(&mut self, value: &str) -> u32 {
+        self.align();
+        let rva = self.position() as u32;
+        let chars: Vec<u16> = value.encode_utf16().collect();
+        self.u32(chars.len() as u32 * 2);
+        for c in chars {
+            self.u16(c);
+        }
+        self.u16(0);
+        rva
+    }
+
+    /// Writes a stream with `write`, returning its location descriptor as `(size, rva)`.
+    fn stream(&mut self, write: impl FnOnce(&mut Self)) -> (u32, u32) {
+        self.align();
+        let rva = self.position();
+        write(self);
+        ((self.position() - rva) as u32, rva as u32)
+    }
+}
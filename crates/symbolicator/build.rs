@@ -43,7 +43,16 @@ fn emit_release_var() -> Result<(), io::Error> {
     Ok(())
 }
 
+#[cfg(feature = "grpc")]
+fn compile_protos() -> Result<(), io::Error> {
+    println!("cargo:rerun-if-changed=proto/symbolicator.proto");
+    tonic_build::compile_protos("proto/symbolicator.proto")
+}
+
 fn main() {
     emit_version_var().ok();
     emit_release_var().ok();
+
+    #[cfg(feature = "grpc")]
+    compile_protos().expect("failed to compile protobuf definitions");
 }
@@ -0,0 +1,172 @@
+//! Delivery of completed responses to the `callback_url` of their request.
+//!
+//! Clients which pass a `callback_url` do not need to poll for the response of their request.
+//! Once the request finishes, its response is `POST`ed to that URL as JSON, with the ID of the
+//! request in the `X-Symbolicator-Request-Id` header. Deliveries which fail are retried with an
+//! exponential backoff. The response can still be polled as usual in the meantime.
+
+use std::time::Duration;
+
+use url::Url;
+
+use symbolicator_service::config::Config;
+use symbolicator_service::metric;
+
+use crate::service::RequestId;
+
+/// The header carrying the ID of the request whose response is delivered.
+pub const REQUEST_ID_HEADER: &str = "x-symbolicator-request-id";
+
+/// The number of times a response is sent before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The delay before the first retry, which doubles for every following one.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The time a single delivery may take, including sending the response.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delivers responses to the callback URLs of their requests.
+#[derive(Debug, Clone)]
+pub struct CallbackSender {
+    client: reqwest::Client,
+    max_attempts: u32,
+    initial_backoff: Duration,
+}
+
+impl CallbackSender {
+    /// Creates a sender with the HTTP settings of the given config.
+    ///
+    /// Callback URLs are given by clients, so they may only point to reserved IP addresses if
+    /// sources may do so as well.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: symbolicator_service::utils::http::create_client(config, false),
+            max_attempts: MAX_ATTEMPTS,
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Sends the serialized `response` of a request to its `url`, retrying on failure.
+    ///
+    /// Returns whether the response was delivered.
+    pub async fn deliver(&self, url: &Url, request_id: RequestId, response: Vec<u8>) -> bool {
+        let mut backoff = self.initial_backoff;
+        for attempt in 1..=self.max_attempts {
+            let result = self
+                .client
+                .post(url.clone())
+                .header("content-type", "application/json")
+                .header(REQUEST_ID_HEADER, request_id.to_string())
+                .timeout(DELIVERY_TIMEOUT)
+                .body(response.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => {
+                    metric!(counter("requests.callback.delivered") += 1);
+                    return true;
+                }
+                Err(err) => {
+                    let stderr: &dyn std::error::Error = &err;
+                    tracing::warn!(stderr, %request_id, attempt, "Failed to deliver callback");
+                }
+            }
+
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        tracing::error!(%request_id, "Giving up on delivering callback");
+        metric!(counter("requests.callback.failed") += 1);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::test;
+
+    /// Starts a server which fails the first `failures` deliveries, and records the others.
+    fn callback_server(failures: usize) -> (test::Server, Arc<Mutex<Vec<(String, String)>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let attempts = Arc::new(Mutex::new(0));
+
+        let recorded = received.clone();
+        let handler = move |headers: HeaderMap, body: String| {
+            let recorded = recorded.clone();
+            let attempts = attempts.clone();
+            async move {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts <= failures {
+                    return StatusCode::SERVICE_UNAVAILABLE;
+                }
+
+                let request_id = headers[REQUEST_ID_HEADER].to_str().unwrap().to_owned();
+                recorded.lock().unwrap().push((request_id, body));
+                StatusCode::OK
+            }
+        };
+
+        let router = Router::new().route("/callback", post(handler));
+        (test::Server::with_router(router), received)
+    }
+
+    fn sender(max_attempts: u32) -> CallbackSender {
+        let config = Config {
+            connect_to_reserved_ips: crate::config::ReservedIpsPolicy::Allow,
+            ..Config::default()
+        };
+        CallbackSender {
+            max_attempts,
+            initial_backoff: Duration::from_millis(10),
+            ..CallbackSender::new(&config)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_with_retries() {
+        test::setup();
+
+        let (server, received) = callback_server(2);
+        let url = server.url("/callback");
+        let request_id = RequestId::new(Uuid::new_v4());
+
+        let delivered = sender(3)
+            .deliver(&url, request_id, br#"{"status":"timeout"}"#.to_vec())
+            .await;
+
+        assert!(delivered);
+        assert_eq!(
+            *received.lock().unwrap(),
+            [(request_id.to_string(), r#"{"status":"timeout"}"#.to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deliver_gives_up() {
+        test::setup();
+
+        let (server, received) = callback_server(usize::MAX);
+        let url = server.url("/callback");
+        let request_id = RequestId::new(Uuid::new_v4());
+
+        let delivered = sender(2).deliver(&url, request_id, b"{}".to_vec()).await;
+
+        assert!(!delivered);
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
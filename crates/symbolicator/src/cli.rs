@@ -1,4 +1,5 @@
 //! Exposes the command line application.
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
@@ -6,8 +7,10 @@ use structopt::StructOpt;
 
 use symbolicator_service::caching;
 use symbolicator_service::metrics;
+use symbolicator_service::utils::encryption::MasterKey;
 
 use crate::config::Config;
+use crate::load_test::LoadTestOptions;
 use crate::logging;
 use crate::server;
 
@@ -32,9 +35,33 @@ enum Command {
     #[structopt(name = "run")]
     Run,
 
+    /// Run the downloader process, which downloads files for other instances.
+    #[structopt(name = "downloader")]
+    Downloader,
+
     /// Clean local caches.
     #[structopt(name = "cleanup")]
-    Cleanup,
+    Cleanup {
+        /// Only report which files would be removed, without actually removing them.
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Replay recorded requests and compare their responses to the recorded ones.
+    #[structopt(name = "replay")]
+    Replay {
+        /// The directory containing the recorded requests.
+        #[structopt(value_name = "DIR")]
+        dir: PathBuf,
+    },
+
+    /// Send requests to a running Symbolicator at a fixed rate, and report their latencies.
+    #[structopt(name = "load-test")]
+    LoadTest(LoadTestOptions),
+
+    /// Encrypt a value read from stdin with the configured master key.
+    #[structopt(name = "encrypt")]
+    Encrypt,
 }
 
 /// Command line interface parser.
@@ -59,6 +86,21 @@ impl Cli {
     }
 }
 
+/// Encrypts a value read from stdin with the configured master key, and prints it.
+fn encrypt_value(config: &Config) -> Result<()> {
+    let encryption = config
+        .encryption
+        .as_ref()
+        .context("no master key configured in `encryption`")?;
+    let key = MasterKey::load(encryption)?;
+
+    let mut value = String::new();
+    std::io::stdin().read_to_string(&mut value)?;
+    println!("{}", key.encrypt(value.trim_end_matches(['\r', '\n']))?);
+
+    Ok(())
+}
+
 /// Runs the main application.
 pub fn execute() -> Result<()> {
     let cli = Cli::from_args();
@@ -124,7 +166,19 @@ pub fn execute() -> Result<()> {
 
     match cli.command {
         Command::Run => server::run(config).context("failed to start the server")?,
-        Command::Cleanup => caching::cleanup(config).context("failed to clean up caches")?,
+        Command::Downloader => {
+            server::run_downloader(config).context("failed to start the downloader")?
+        }
+        Command::Cleanup { dry_run } => {
+            caching::cleanup(config, dry_run).context("failed to clean up caches")?
+        }
+        Command::Replay { dir } => {
+            server::run_replay(config, &dir).context("failed to replay requests")?
+        }
+        Command::LoadTest(options) => {
+            server::run_load_test(&options).context("failed to run load test")?
+        }
+        Command::Encrypt => encrypt_value(&config).context("failed to encrypt value")?,
     }
 
     Ok(())
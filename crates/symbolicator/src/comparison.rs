@@ -0,0 +1,267 @@
+//! Comparison of native symbolication responses with those of another Symbolicator.
+//!
+//! Requests to `/symbolicate` which set the `compare_with` option are also sent to the
+//! Symbolicator at that URL, which must be one of the configured `comparison_targets`. This
+//! allows operators to canary a new release on real traffic before rolling it out.
+//!
+//! The comparison runs detached from the request, which responds as soon as its own result is
+//! ready. Once both results are in, the frames that the two instances symbolicated differently
+//! are reported in metrics and logs.
+//!
+//! Forwarded requests carry the same sources as the original request, so that both instances
+//! symbolicate the same payload. Callback URLs are dropped, so that clients do not receive two
+//! responses.
+
+use std::time::Duration;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use url::Url;
+
+use symbolicator_service::config::Config;
+use symbolicator_service::metric;
+use symbolicator_service::services::symbolication::frame_differences;
+use symbolicator_service::types::{
+    CompletedResponse, CompletedSymbolicationResponse, FrameDifference,
+};
+use symbolicator_sources::SourceConfig;
+
+use crate::service::{
+    OutputFormat, RawObjectInfo, RawStacktrace, RequestOptions, Scope, Signal,
+    SymbolicateStacktraces, SymbolicationResponse,
+};
+
+/// The time the other Symbolicator has to respond, including polls.
+const COMPARISON_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The timeout of a single poll for the response of the other Symbolicator.
+const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The body of a request forwarded to the other Symbolicator.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparedRequest {
+    signal: Option<Signal>,
+    sources: Arc<[SourceConfig]>,
+    stacktraces: Vec<RawStacktrace>,
+    modules: Vec<RawObjectInfo>,
+    options: RequestOptions,
+}
+
+impl ComparedRequest {
+    /// Creates the body forwarded to the other Symbolicator, without the callback.
+    pub fn new(request: &SymbolicateStacktraces, options: &RequestOptions) -> Self {
+        Self {
+            signal: request.signal,
+            sources: request.sources.clone(),
+            stacktraces: request.stacktraces.clone(),
+            modules: request.modules.iter().map(|m| m.raw.clone()).collect(),
+            options: RequestOptions {
+                callback_url: None,
+                compare_with: None,
+                output: OutputFormat::Symbolication,
+                ..options.clone()
+            },
+        }
+    }
+}
+
+/// Sends requests to other Symbolicators and compares their responses.
+#[derive(Debug, Clone)]
+pub struct Comparator {
+    client: reqwest::Client,
+    targets: Arc<[Url]>,
+}
+
+impl Comparator {
+    /// Creates a comparator for the `comparison_targets` of the given config.
+    ///
+    /// The targets are configured by the operator, so they may point to reserved IP addresses.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: symbolicator_service::utils::http::create_client(config, true),
+            targets: config.comparison_targets.clone().into(),
+        }
+    }
+
+    /// Returns whether requests may compare their responses with the Symbolicator at `url`.
+    pub fn allows(&self, url: &Url) -> bool {
+        self.targets.contains(url)
+    }
+
+    /// Sends the `request` to the Symbolicator at `url`, and returns its response.
+    pub async fn fetch(
+        &self,
+        url: &Url,
+        scope: &Scope,
+        request: &ComparedRequest,
+    ) -> Result<CompletedSymbolicationResponse> {
+        let fetch = async {
+            let mut response: SymbolicationResponse = self
+                .client
+                .post(endpoint(url, &["symbolicate"])?)
+                .query(&[("scope", scope.as_ref())])
+                .json(request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            loop {
+                let request_id = match response {
+                    SymbolicationResponse::Pending { request_id, .. } => request_id,
+                    SymbolicationResponse::Completed(completed) => match *completed {
+                        CompletedResponse::NativeSymbolication(completed) => return Ok(completed),
+                        _ => bail!("unexpected response type"),
+                    },
+                    SymbolicationResponse::Failed { message } => bail!(message),
+                    other => bail!("request did not complete: {other:?}"),
+                };
+
+                response = self
+                    .client
+                    .get(endpoint(url, &["requests", &request_id.to_string()])?)
+                    .query(&[("timeout", POLL_TIMEOUT.as_secs())])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+            }
+        };
+
+        match tokio::time::timeout(COMPARISON_TIMEOUT, fetch).await {
+            Ok(result) => result,
+            Err(_) => bail!("timed out after {}s", COMPARISON_TIMEOUT.as_secs()),
+        }
+    }
+}
+
+/// Appends the path `segments` of an endpoint to the base `url` of a Symbolicator.
+fn endpoint(url: &Url, segments: &[&str]) -> Result<Url> {
+    let mut url = url.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("invalid URL to compare with"))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// Compares our response to the one of the other Symbolicator, and reports the differences.
+///
+/// Returns the frames the two responses differ in, or the error of the other Symbolicator.
+pub fn compare(
+    ours: &CompletedSymbolicationResponse,
+    theirs: Result<CompletedSymbolicationResponse>,
+) -> Result<Vec<FrameDifference>> {
+    match theirs {
+        Ok(theirs) => {
+            let frames = frame_differences(ours, &theirs);
+            let outcome = if frames.is_empty() {
+                "identical"
+            } else {
+                tracing::info!(
+                    frames = %serde_json::to_string(&frames).unwrap_or_default(),
+                    "Compared response diverged in {} frames",
+                    frames.len(),
+                );
+                "diverged"
+            };
+            metric!(counter("requests.compared") += 1, "outcome" => outcome);
+            Ok(frames)
+        }
+        Err(err) => {
+            let stderr: &dyn std::error::Error = &*err;
+            tracing::warn!(stderr, "Failed to compare response");
+            metric!(counter("requests.compared") += 1, "outcome" => "failed");
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test;
+
+    fn comparator() -> Comparator {
+        let config = Config {
+            connect_to_reserved_ips: crate::config::ReservedIpsPolicy::Allow,
+            ..Config::default()
+        };
+        Comparator::new(&config)
+    }
+
+    fn request() -> ComparedRequest {
+        ComparedRequest {
+            signal: None,
+            sources: Arc::new([]),
+            stacktraces: vec![],
+            modules: vec![],
+            options: RequestOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let url = Url::parse("https://canary.example.com/symbolicator").unwrap();
+        assert_eq!(
+            endpoint(&url, &["symbolicate"]).unwrap().as_str(),
+            "https://canary.example.com/symbolicator/symbolicate"
+        );
+
+        let url = Url::parse("https://canary.example.com/").unwrap();
+        assert_eq!(
+            endpoint(&url, &["requests", "abc"]).unwrap().as_str(),
+            "https://canary.example.com/requests/abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        test::setup();
+
+        let handler = || async {
+            Json(json!({
+                "status": "completed",
+                "stacktraces": [{"frames": [{
+                    "status": "missing",
+                    "original_index": 0,
+                    "instruction_addr": "0x1000",
+                }]}],
+                "modules": [],
+            }))
+        };
+        let server = test::Server::with_router(Router::new().route("/symbolicate", post(handler)));
+
+        let theirs = comparator()
+            .fetch(&server.url("/"), &Scope::Global, &request())
+            .await;
+
+        let frames = compare(&CompletedSymbolicationResponse::default(), theirs).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].ours.is_none());
+        assert!(frames[0].theirs.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_failed() {
+        test::setup();
+
+        let handler = || async { Json(json!({"status": "failed", "message": "boom"})) };
+        let server = test::Server::with_router(Router::new().route("/symbolicate", post(handler)));
+
+        let theirs = comparator()
+            .fetch(&server.url("/"), &Scope::Global, &request())
+            .await;
+
+        let err = compare(&CompletedSymbolicationResponse::default(), theirs).unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+    }
+}
@@ -3,7 +3,7 @@ use axum::http::StatusCode;
 use axum::response::Json;
 use tokio::fs::File;
 
-use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
 use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
@@ -20,7 +20,7 @@ pub async fn handle_apple_crash_report_request(
     params.configure_scope();
 
     let mut report = None;
-    let mut sources = service.config().default_sources();
+    let mut sources = params.default_sources(&service)?;
     let mut options = RequestOptions::default();
 
     while let Some(field) = multipart.next_field().await? {
@@ -32,7 +32,7 @@ pub async fn handle_apple_crash_report_request(
             }
             Some("sources") => {
                 let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
-                sources = serde_json::from_slice(&data)?;
+                sources = request_sources(serde_json::from_slice(&data)?)?;
             }
             Some("options") => {
                 let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
@@ -0,0 +1,84 @@
+use axum::extract;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+
+use crate::endpoints::error::ApiErrorResponse;
+use crate::endpoints::DownloaderState;
+use crate::service::RequestService;
+
+/// Rejects requests which do not carry the configured `admin_token` as a bearer token.
+///
+/// If no `admin_token` is configured, all requests are rejected with `403 Forbidden`.
+pub async fn require_admin_token<B>(
+    extract::State(service): extract::State<RequestService>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Err(response) = authorize(
+        "admin_token",
+        service.config().admin_token.as_deref(),
+        &request,
+    ) {
+        return response;
+    }
+    next.run(request).await
+}
+
+/// Rejects requests to the downloader process which do not carry its `downloader_token`.
+///
+/// Like for the administrative endpoints, all requests are rejected if no token is configured.
+pub async fn require_downloader_token<B>(
+    extract::State(state): extract::State<DownloaderState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Err(response) = authorize("downloader_token", state.token.as_deref(), &request) {
+        return response;
+    }
+    next.run(request).await
+}
+
+/// Checks that the `request` carries the `expected` token, or returns the response rejecting it.
+///
+/// `option` is the name of the configuration option holding the token.
+fn authorize<B>(
+    option: &str,
+    expected: Option<&str>,
+    request: &Request<B>,
+) -> Result<(), Response> {
+    let Some(expected) = expected else {
+        return Err(reject(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("no {option} is configured"),
+        ));
+    };
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => {
+            crate::metric!(counter("requests.rejected") += 1, "reason" => "unauthorized");
+            Err(reject(
+                StatusCode::UNAUTHORIZED,
+                anyhow::anyhow!("invalid or missing {option}"),
+            ))
+        }
+    }
+}
+
+fn reject(status: StatusCode, error: anyhow::Error) -> Response {
+    let error = ApiErrorResponse::from(error);
+    (status, Json(error)).into_response()
+}
+
+/// Compares the two byte strings in a time that does not depend on where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
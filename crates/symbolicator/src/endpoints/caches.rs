@@ -0,0 +1,77 @@
+use axum::extract;
+use axum::response::Json;
+use symbolicator_service::caching::CacheReport;
+
+use crate::service::RequestService;
+
+/// Reports statistics of the file-system caches.
+///
+/// These are gathered by a background scanner, so this only reports the caches it has visited.
+pub async fn cache_stats(
+    extract::State(service): extract::State<RequestService>,
+) -> Json<Vec<CacheReport>> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/caches"));
+    });
+
+    Json(service.cache_stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{Client, StatusCode};
+    use symbolicator_service::caching::{CacheName, CacheReport};
+
+    use crate::config::Config;
+    use crate::endpoints;
+    use crate::service::RequestService;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let handle = tokio::runtime::Handle::current();
+        let mut config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            admin_token: Some(test::ADMIN_TOKEN.into()),
+            ..Config::default()
+        };
+        config.caches.size_metrics_interval = Some(Duration::from_secs(3600));
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service));
+
+        let response = Client::new()
+            .get(server.url("/admin/caches"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let report = loop {
+            let response = Client::new()
+                .get(server.url("/admin/caches"))
+                .bearer_auth(test::ADMIN_TOKEN)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let report: Vec<CacheReport> = response.json().await.unwrap();
+            if report.iter().any(|cache| cache.cache == CacheName::Objects) {
+                break report;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        let objects = report
+            .iter()
+            .find(|cache| cache.cache == CacheName::Objects)
+            .unwrap();
+        assert_eq!(objects.entries, 0);
+        assert_eq!(objects.age_histogram.len(), 5);
+    }
+}
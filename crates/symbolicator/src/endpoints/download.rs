@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::body::Body;
+use axum::extract;
+use axum::http::{Response, StatusCode};
+use axum::response::Json;
+use tempfile::NamedTempFile;
+
+use symbolicator_service::caching::CacheError;
+use symbolicator_service::services::download::{
+    contains_secret_references, DownloadService, RemoteFile,
+};
+
+use super::ResponseError;
+
+/// The state of the downloader process.
+#[derive(Clone, Debug)]
+pub struct DownloaderState {
+    /// The service downloading from sources.
+    pub downloader: Arc<DownloadService>,
+    /// The directory for files which are downloaded, before they are sent to the client.
+    pub tmp_dir: Option<PathBuf>,
+    /// The token clients have to send, see [`require_downloader_token`](super::auth::require_downloader_token).
+    pub token: Option<String>,
+}
+
+impl DownloaderState {
+    fn tempfile(&self) -> std::io::Result<NamedTempFile> {
+        match self.tmp_dir {
+            Some(ref path) => {
+                std::fs::create_dir_all(path)?;
+                tempfile::Builder::new().prefix("tmp").tempfile_in(path)
+            }
+            None => NamedTempFile::new(),
+        }
+    }
+}
+
+/// Downloads a file on behalf of another symbolicator instance and responds with its contents.
+///
+/// The status codes of the response follow the outcome of the download, so that the client can
+/// treat this like any other HTTP source.
+///
+/// Files on filesystem sources are rejected, as they are local to the downloader. So are files
+/// whose source references secrets, as clients resolve those before delegating a download.
+pub async fn download_file(
+    extract::State(state): extract::State<DownloaderState>,
+    Json(file): Json<RemoteFile>,
+) -> Result<Response<Body>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("POST /download"));
+    });
+
+    if let RemoteFile::Filesystem(_) = file {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "files on filesystem sources cannot be downloaded",
+        )
+            .into());
+    }
+    if contains_secret_references(&file) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "sources of downloads must not reference secrets",
+        )
+            .into());
+    }
+
+    let temp_file = state.tempfile()?;
+    let result = state
+        .downloader
+        .download(file, temp_file.path().to_owned())
+        .await;
+
+    let status = match result {
+        Ok(()) => StatusCode::OK,
+        Err(CacheError::NotFound) => StatusCode::NOT_FOUND,
+        Err(CacheError::PermissionDenied(_)) => StatusCode::FORBIDDEN,
+        Err(CacheError::Timeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+        Err(CacheError::RateLimited(retry_after)) => {
+            // Round up, so that the client does not come back before the source cooled down.
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("retry-after", seconds)
+                .body(Body::empty())?);
+        }
+        Err(_) => StatusCode::BAD_GATEWAY,
+    };
+    if status != StatusCode::OK {
+        return Ok(Response::builder().status(status).body(Body::empty())?);
+    }
+
+    // The file is deleted right away, but remains readable until it has been sent.
+    let file = temp_file.into_file();
+    let len = file
+        .metadata()
+        .context("failed to read downloaded file")?
+        .len();
+    let file = tokio::fs::File::from_std(file);
+
+    Ok(Response::builder()
+        .header("content-length", len)
+        .header("content-type", "application/octet-stream")
+        .body(Body::wrap_stream(tokio_util::io::ReaderStream::new(file)))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use symbolicator_sources::{
+        FilesystemRemoteFile, HttpRemoteFile, SourceConfig, SourceLocation,
+    };
+
+    use super::*;
+    use crate::config::{Config, ReservedIpsPolicy};
+    use crate::test;
+
+    const DOWNLOADER_TOKEN: &str = "downloader-secret";
+
+    fn downloader_server() -> test::Server {
+        let config = Config {
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            ..Config::default()
+        };
+        let downloader = DownloadService::new(&config, tokio::runtime::Handle::current());
+        let state = DownloaderState {
+            downloader,
+            tmp_dir: None,
+            token: Some(DOWNLOADER_TOKEN.into()),
+        };
+        test::Server::with_router(crate::endpoints::create_downloader_app(state))
+    }
+
+    #[tokio::test]
+    async fn test_download_file() {
+        test::setup();
+
+        let server = downloader_server();
+        let (_srv, source) = test::symbol_server();
+        let SourceConfig::Http(source) = source else {
+            panic!("expected an http source");
+        };
+        let client = Client::new();
+
+        let file = RemoteFile::from(HttpRemoteFile::new(
+            source.clone(),
+            SourceLocation::new("hello.txt"),
+        ));
+        let response = client
+            .post(server.url("/download"))
+            .bearer_auth(DOWNLOADER_TOKEN)
+            .json(&file)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "hello world\n");
+
+        let response = client
+            .post(server.url("/download"))
+            .json(&file)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let file = RemoteFile::from(HttpRemoteFile::new(
+            source,
+            SourceLocation::new("i-do-not-exist"),
+        ));
+        let response = client
+            .post(server.url("/download"))
+            .bearer_auth(DOWNLOADER_TOKEN)
+            .json(&file)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_download_rejected_sources() {
+        test::setup();
+
+        let server = downloader_server();
+        let (_srv, source) = test::symbol_server();
+        let SourceConfig::Http(source) = source else {
+            panic!("expected an http source");
+        };
+        let client = Client::new();
+
+        let mut source = (*source).clone();
+        source
+            .headers
+            .insert("Authorization".into(), "${env:SECRET}".into());
+        let file = RemoteFile::from(HttpRemoteFile::new(
+            source.into(),
+            SourceLocation::new("hello.txt"),
+        ));
+        let response = client
+            .post(server.url("/download"))
+            .bearer_auth(DOWNLOADER_TOKEN)
+            .json(&file)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let (_dir, source) = test::local_source();
+        let SourceConfig::Filesystem(source) = source else {
+            panic!("expected a filesystem source");
+        };
+        let file = RemoteFile::from(FilesystemRemoteFile::new(
+            source,
+            SourceLocation::new("hello.txt"),
+        ));
+        let response = client
+            .post(server.url("/download"))
+            .bearer_auth(DOWNLOADER_TOKEN)
+            .json(&file)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_delegated_download() {
+        test::setup();
+
+        let server = downloader_server();
+        let (_srv, source) = test::symbol_server();
+        let SourceConfig::Http(source) = source else {
+            panic!("expected an http source");
+        };
+
+        // The frontend itself is not allowed to connect to the local symbol server.
+        let config = Config {
+            connect_to_reserved_ips: ReservedIpsPolicy::Deny,
+            downloader_url: Some(server.url("/")),
+            downloader_token: Some(DOWNLOADER_TOKEN.into()),
+            ..Config::default()
+        };
+        let downloader = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let file = RemoteFile::from(HttpRemoteFile::new(
+            source,
+            SourceLocation::new("hello.txt"),
+        ));
+        let result = downloader.download(file, temp_file.path().to_owned()).await;
+
+        assert!(result.is_ok(), "{result:?}");
+        let content = std::fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(content, "hello world\n");
+        assert_eq!(server.accesses(), 1);
+    }
+}
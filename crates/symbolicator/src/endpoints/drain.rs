@@ -0,0 +1,123 @@
+use axum::extract;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::endpoints::error::ApiErrorResponse;
+use crate::service::RequestService;
+
+/// The number of seconds after which clients should retry a request rejected while draining.
+///
+/// Retries are expected to be routed to another instance, so this can be short.
+const RETRY_AFTER_SECS: &str = "5";
+
+/// The drain status of the service.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DrainResponse {
+    /// Whether the service is draining.
+    pub draining: bool,
+    /// The number of symbolication requests which are still being processed.
+    pub in_flight: usize,
+}
+
+/// Switches the service into drain mode, and reports the remaining in-flight requests.
+///
+/// This is meant to be called from a `preStop` hook. Draining cannot be undone, so this only
+/// responds to authenticated `POST` requests. Calling it repeatedly is fine and can be used to
+/// wait for in-flight requests to finish.
+pub async fn drain(extract::State(service): extract::State<RequestService>) -> Json<DrainResponse> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("POST /drain"));
+    });
+
+    service.start_draining();
+
+    Json(DrainResponse {
+        draining: true,
+        in_flight: service.in_flight_requests(),
+    })
+}
+
+/// Rejects new submissions with a retryable `503 Service Unavailable` while draining.
+pub async fn reject_when_draining<B>(
+    extract::State(service): extract::State<RequestService>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !service.is_draining() {
+        return next.run(request).await;
+    }
+
+    crate::metric!(counter("requests.rejected") += 1, "reason" => "draining");
+    let error = ApiErrorResponse::from(anyhow::anyhow!("symbolicator is draining"));
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, RETRY_AFTER_SECS)],
+        Json(error),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_drain() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        let response = client.get(server.url("/healthcheck")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client.post(server.url("/drain")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = client
+            .get(server.url("/drain"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = client.get(server.url("/healthcheck")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .post(server.url("/drain"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: DrainResponse = response.json().await.unwrap();
+        assert!(status.draining);
+        assert_eq!(status.in_flight, 0);
+
+        let response = client.get(server.url("/healthcheck")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = client
+            .post(server.url("/symbolicate"))
+            .json(&serde_json::json!({"stacktraces": [], "modules": []}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER.as_str()], RETRY_AFTER_SECS);
+
+        // Polling existing requests keeps working.
+        let response = client
+            .get(server.url("/requests/00000000-0000-0000-0000-000000000000"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -5,6 +5,8 @@ use axum::Json;
 use sentry::integrations::anyhow::capture_anyhow;
 use serde::{Deserialize, Serialize};
 
+use symbolicator_service::services::symbolication::MinidumpUploadError;
+
 use crate::service::MaxRequestsError;
 
 #[derive(Debug)]
@@ -40,6 +42,20 @@ impl From<MaxRequestsError> for ResponseError {
     }
 }
 
+impl From<MinidumpUploadError> for ResponseError {
+    fn from(err: MinidumpUploadError) -> Self {
+        let status = match err {
+            MinidumpUploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            MinidumpUploadError::Stream(_) => StatusCode::BAD_REQUEST,
+            MinidumpUploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self {
+            status,
+            err: err.into(),
+        }
+    }
+}
+
 impl From<&'static str> for ResponseError {
     fn from(msg: &'static str) -> Self {
         Self {
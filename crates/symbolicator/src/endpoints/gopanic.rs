@@ -0,0 +1,132 @@
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use tokio::fs::File;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
+use crate::utils::sentry::ConfigureScope;
+
+use super::multipart::{read_multipart_data, stream_multipart_file};
+use super::ResponseError;
+
+pub async fn handle_go_panic_request(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    mut multipart: extract::Multipart,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let mut go_panic = None;
+    let mut sources = params.default_sources(&service)?;
+    let mut modules = Vec::new();
+    let mut options = RequestOptions::default();
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("go_panic") => {
+                let mut panic_file = File::from_std(tempfile::tempfile()?);
+                stream_multipart_file(field, &mut panic_file).await?;
+                go_panic = Some(panic_file.into_std().await)
+            }
+            Some("sources") => {
+                let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
+                sources = request_sources(serde_json::from_slice(&data)?)?;
+            }
+            Some("modules") => {
+                let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
+                modules = serde_json::from_slice(&data)?;
+            }
+            Some("options") => {
+                let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
+                options = serde_json::from_slice(&data)?
+            }
+            _ => (), // Always ignore unknown fields.
+        }
+    }
+
+    let go_panic = go_panic.ok_or((StatusCode::BAD_REQUEST, "missing go panic"))?;
+
+    let request_id = service.process_go_panic(params.scope, go_panic, sources, modules, options)?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{multipart, Client, StatusCode};
+    use symbolicator_service::types::CompletedResponse;
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    const GO_PANIC: &str = "\
+panic: boom
+
+goroutine 1 [running]:
+main.main()
+\t/src/app/main.go:5 +0x27 fp=0xc000066f50 sp=0xc000066f38 pc=0x45d1a7
+exit status 2
+";
+
+    #[tokio::test]
+    async fn test_basic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let file_part = multipart::Part::bytes(GO_PANIC.as_bytes()).file_name("panic.txt");
+        let form = multipart::Form::new()
+            .part("go_panic", file_part)
+            .text("sources", "[]")
+            .text("modules", "[]");
+
+        let response = Client::new()
+            .post(server.url("/gopanic"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.text().await.unwrap();
+        let response = serde_json::from_str::<SymbolicationResponse>(&body).unwrap();
+        let SymbolicationResponse::Completed(response) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        let CompletedResponse::NativeSymbolication(response) = *response else {
+            panic!("unexpected response: {response:?}");
+        };
+
+        assert_eq!(response.crash_reason.as_deref(), Some("panic: boom"));
+        assert_eq!(response.stacktraces.len(), 1);
+        let frame = &response.stacktraces[0].frames[0];
+        assert_eq!(frame.raw.instruction_addr.0, 0x45d1a7);
+        assert_eq!(frame.raw.function.as_deref(), Some("main.main"));
+        assert_eq!(frame.raw.lineno, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_missing_panic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let form = multipart::Form::new().text("sources", "[]");
+
+        let response = Client::new()
+            .post(server.url("/gopanic"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
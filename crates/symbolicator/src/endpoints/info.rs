@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_service::services::caches::versions::ALL_CACHE_VERSIONS;
+
+use crate::service::RequestService;
+
+/// The cargo features symbolicator was built with.
+const FEATURES: &[(&str, bool)] = &[
+    ("https", cfg!(feature = "https")),
+    ("symbolicator-crash", cfg!(feature = "symbolicator-crash")),
+    ("tokio-console", cfg!(feature = "tokio-console")),
+    ("heap-profiling", cfg!(feature = "heap-profiling")),
+    ("grpc", cfg!(feature = "grpc")),
+];
+
+/// A configured source, without any of its settings or credentials.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceInfo {
+    /// The id of the source.
+    pub id: String,
+    /// The type of the source, such as `http` or `s3`.
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// The number of worker threads of the runtimes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    /// The runtime serving HTTP requests.
+    pub web_pool: usize,
+    /// The runtime processing symbolication requests.
+    pub cpu_pool: usize,
+    /// The runtime downloading files.
+    pub io_pool: usize,
+}
+
+/// Information about the build and runtime of this instance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InfoResponse {
+    /// The version of symbolicator.
+    pub version: String,
+    /// The output of `git describe` for the commit symbolicator was built from.
+    pub git_version: String,
+    /// The full SHA of the commit symbolicator was built from.
+    pub git_sha: String,
+    /// The enabled cargo features.
+    pub features: Vec<String>,
+    /// The current format versions of all caches, keyed by the name of the cache.
+    pub cache_versions: BTreeMap<String, u32>,
+    /// The sources configured in the config file.
+    pub sources: Vec<SourceInfo>,
+    /// The sizes of the runtimes.
+    pub runtime: RuntimeInfo,
+}
+
+/// Reports the build and runtime information of this instance.
+///
+/// This allows tooling to verify which version and configuration is actually deployed. The
+/// response does not include any settings of the sources, which may contain credentials.
+pub async fn info(extract::State(service): extract::State<RequestService>) -> Json<InfoResponse> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /info"));
+    });
+
+    let features = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    let cache_versions = ALL_CACHE_VERSIONS
+        .iter()
+        .map(|(name, versions)| (name.to_string(), versions.current))
+        .collect();
+
+    let sources = service
+        .config()
+        .sources
+        .iter()
+        .map(|source| SourceInfo {
+            id: source.id().to_string(),
+            ty: source.type_name().to_owned(),
+        })
+        .collect();
+
+    let workers = crate::server::worker_threads();
+
+    Json(InfoResponse {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        git_version: env!("SYMBOLICATOR_GIT_VERSION").trim().to_owned(),
+        git_sha: env!("SYMBOLICATOR_RELEASE").trim().to_owned(),
+        features,
+        cache_versions,
+        sources,
+        runtime: RuntimeInfo {
+            web_pool: workers,
+            cpu_pool: workers,
+            io_pool: workers,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_info() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        let response = client.get(server.url("/info")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let info: InfoResponse = response.json().await.unwrap();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.cache_versions.len(), ALL_CACHE_VERSIONS.len());
+        assert!(info.sources.is_empty());
+        assert!(info.runtime.cpu_pool > 0);
+    }
+}
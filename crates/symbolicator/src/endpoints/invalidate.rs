@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use symbolic::common::{CodeId, DebugId};
+use symbolicator_service::caching::ObjectIdFilter;
+use symbolicator_sources::ObjectId;
+
+use crate::service::RequestService;
+
+use super::ResponseError;
+
+/// JSON body of the invalidation request.
+#[derive(Debug, Deserialize)]
+pub struct InvalidateRequestBody {
+    #[serde(default)]
+    pub debug_id: Option<String>,
+    #[serde(default)]
+    pub code_id: Option<String>,
+}
+
+/// The number of entries removed from each cache.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvalidateResponse {
+    pub removed: BTreeMap<String, usize>,
+}
+
+/// Invalidates the negative cache entries of an object, so that it is fetched again right away.
+pub async fn invalidate_negative_caches(
+    extract::State(service): extract::State<RequestService>,
+    extract::Json(body): extract::Json<InvalidateRequestBody>,
+) -> Result<Json<InvalidateResponse>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("POST /admin/invalidate"));
+    });
+
+    let bad_request = |err: anyhow::Error| ResponseError::from((StatusCode::BAD_REQUEST, err));
+    let debug_id = body
+        .debug_id
+        .map(|id| id.parse::<DebugId>())
+        .transpose()
+        .map_err(|_| bad_request(anyhow::anyhow!("invalid debug id")))?;
+    let code_id = body
+        .code_id
+        .map(|id| id.parse::<CodeId>())
+        .transpose()
+        .map_err(|_| bad_request(anyhow::anyhow!("invalid code id")))?;
+
+    // Identifiers are matched as substrings of cache keys, so short ones would invalidate
+    // unrelated entries.
+    if debug_id.map_or(false, |id| id.is_nil()) {
+        return Err(bad_request(anyhow::anyhow!("nil debug id")));
+    }
+    let code_id_digits = code_id.as_ref().map(|id| {
+        let digits = id.as_str().chars().filter(char::is_ascii_hexdigit);
+        digits.count()
+    });
+    if code_id_digits.map_or(false, |count| count < ObjectIdFilter::MIN_ID_DIGITS) {
+        return Err(bad_request(anyhow::anyhow!(
+            "code id needs at least {} hex digits",
+            ObjectIdFilter::MIN_ID_DIGITS
+        )));
+    }
+    if debug_id.is_none() && code_id.is_none() {
+        return Err(bad_request(anyhow::anyhow!(
+            "a debug_id or code_id is required"
+        )));
+    }
+
+    let object_id = ObjectId {
+        debug_id,
+        code_id,
+        ..Default::default()
+    };
+    let removed = service.invalidate_negative_caches(&object_id).await?;
+
+    Ok(Json(InvalidateResponse {
+        removed: removed
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use super::InvalidateResponse;
+    use crate::config::Config;
+    use crate::endpoints;
+    use crate::service::RequestService;
+    use crate::test;
+
+    fn server(admin_token: Option<&str>) -> test::Server {
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            admin_token: admin_token.map(Into::into),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        test::Server::with_router(endpoints::create_app(service))
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_requires_token() {
+        test::setup();
+
+        let body = json!({"debug_id": "502fc0a5-1ec1-3e47-9998-684fa139dca7"});
+
+        let server_without_token = server(None);
+        let response = Client::new()
+            .post(server_without_token.url("/admin/invalidate"))
+            .bearer_auth("secret")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let server = server(Some("secret"));
+        let response = Client::new()
+            .post(server.url("/admin/invalidate"))
+            .bearer_auth("wrong")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = Client::new()
+            .post(server.url("/admin/invalidate"))
+            .bearer_auth("secret")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response: InvalidateResponse = response.json().await.unwrap();
+        assert_eq!(response.removed["object_meta"], 0);
+        assert_eq!(response.removed["symcaches"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_requires_id() {
+        test::setup();
+
+        let server = server(Some("secret"));
+        let response = Client::new()
+            .post(server.url("/admin/invalidate"))
+            .bearer_auth("secret")
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        for body in [
+            json!({"debug_id": "00000000-0000-0000-0000-000000000000"}),
+            json!({"code_id": "c0a5"}),
+        ] {
+            let response = Client::new()
+                .post(server.url("/admin/invalidate"))
+                .bearer_auth("secret")
+                .json(&body)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use axum::body::Body;
+use axum::http::{Response, StatusCode};
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use super::ResponseError;
+
+/// Statistics of the global allocator, all in bytes.
+///
+/// See the [jemalloc documentation](https://jemalloc.net/jemalloc.3.html) for the exact
+/// meaning of the individual values.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryStats {
+    /// The name of the allocator.
+    pub allocator: String,
+    /// The total number of bytes allocated by the application.
+    pub allocated: u64,
+    /// The total number of bytes in active pages allocated by the application.
+    pub active: u64,
+    /// The total number of bytes dedicated to allocator metadata.
+    pub metadata: u64,
+    /// The number of bytes in physically resident pages mapped by the allocator.
+    pub resident: u64,
+    /// The total number of bytes in active extents mapped by the allocator.
+    pub mapped: u64,
+    /// The number of bytes in virtual memory mappings that were retained rather than being
+    /// returned to the operating system.
+    pub retained: u64,
+}
+
+#[cfg(not(target_env = "msvc"))]
+impl MemoryStats {
+    fn read() -> anyhow::Result<Self> {
+        use jemalloc_ctl::{epoch, stats};
+
+        fn read_stat(stat: jemalloc_ctl::Result<usize>) -> anyhow::Result<u64> {
+            stat.map(|value| value as u64)
+                .map_err(|err| anyhow::anyhow!("failed to read allocator stats: {err}"))
+        }
+
+        // Statistics are cached, and only refreshed when advancing the epoch.
+        epoch::advance().map_err(|err| anyhow::anyhow!("failed to refresh stats: {err}"))?;
+
+        Ok(Self {
+            allocator: "jemalloc".into(),
+            allocated: read_stat(stats::allocated::read())?,
+            active: read_stat(stats::active::read())?,
+            metadata: read_stat(stats::metadata::read())?,
+            resident: read_stat(stats::resident::read())?,
+            mapped: read_stat(stats::mapped::read())?,
+            retained: read_stat(stats::retained::read())?,
+        })
+    }
+}
+
+#[cfg(target_env = "msvc")]
+impl MemoryStats {
+    fn read() -> anyhow::Result<Self> {
+        anyhow::bail!("allocator statistics are not available on this platform")
+    }
+}
+
+/// Dumps a heap profile to `path`, returning `false` if heap profiling is not active.
+#[cfg(not(target_env = "msvc"))]
+fn dump_heap_profile(path: &std::path::Path) -> anyhow::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // This option only exists if jemalloc was built with profiling support.
+    // SAFETY: `opt.prof` is a boolean option.
+    let active = unsafe { jemalloc_ctl::raw::read::<bool>(b"opt.prof\0") }.unwrap_or(false);
+    if !active {
+        return Ok(false);
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `prof.dump` expects a nul-terminated file name, which outlives the call.
+    unsafe { jemalloc_ctl::raw::write(b"prof.dump\0", path.as_ptr()) }
+        .map_err(|err| anyhow::anyhow!("failed to dump heap profile: {err}"))?;
+
+    Ok(true)
+}
+
+#[cfg(target_env = "msvc")]
+fn dump_heap_profile(_path: &std::path::Path) -> anyhow::Result<bool> {
+    Ok(false)
+}
+
+/// Reports statistics of the global allocator.
+pub async fn memory_stats() -> Result<Json<MemoryStats>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/memory"));
+    });
+
+    Ok(Json(MemoryStats::read()?))
+}
+
+/// Dumps a heap profile and responds with it.
+///
+/// This requires symbolicator to be built with the `heap-profiling` feature, and to be started
+/// with profiling enabled, for example with `MALLOC_CONF=prof:true`. The profile can be
+/// analyzed with `jeprof`.
+///
+/// Like all admin endpoints, this requires the `admin_token`, as the profile is written to disk
+/// and reveals the contents of memory.
+pub async fn heap_profile() -> Result<Response<Body>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("POST /admin/heap-profile"));
+    });
+
+    let temp_file = tempfile::NamedTempFile::new()?;
+    if !dump_heap_profile(temp_file.path())? {
+        return Err((StatusCode::NOT_IMPLEMENTED, "heap profiling is not enabled").into());
+    }
+    let profile = tokio::fs::read(temp_file.path()).await?;
+
+    Ok(Response::builder()
+        .header("content-type", "application/octet-stream")
+        .body(Body::from(profile))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    #[cfg(not(target_env = "msvc"))]
+    async fn test_memory_stats() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        let response = client
+            .get(server.url("/admin/memory"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let stats: MemoryStats = response.json().await.unwrap();
+        assert_eq!(stats.allocator, "jemalloc");
+        assert!(stats.allocated > 0);
+        assert!(stats.resident >= stats.active);
+    }
+
+    #[tokio::test]
+    async fn test_heap_profile_disabled() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        // Without the token, no profile is dumped.
+        let response = client
+            .post(server.url("/admin/heap-profile"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(server.url("/admin/heap-profile"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+}
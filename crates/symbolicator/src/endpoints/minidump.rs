@@ -4,12 +4,12 @@ use axum::response::Json;
 use symbolic::common::ByteView;
 use tokio::fs::File;
 
-use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
 use crate::metric;
 use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
-use super::multipart::{read_multipart_data, stream_multipart_file};
+use super::multipart::read_multipart_data;
 use super::ResponseError;
 
 pub async fn handle_minidump_request(
@@ -22,7 +22,9 @@ pub async fn handle_minidump_request(
     params.configure_scope();
 
     let mut minidump = None;
-    let mut sources = service.config().default_sources();
+    let mut spooled = None;
+    let mut sources = params.default_sources(&service)?;
+    let mut sources_received = false;
     let mut options = RequestOptions::default();
 
     while let Some(field) = multipart.next_field().await? {
@@ -37,12 +39,19 @@ pub async fn handle_minidump_request(
                 }?;
                 let (file, temp_path) = minidump_file.into_parts();
                 let mut file = File::from_std(file);
-                stream_multipart_file(field, &mut file).await?;
-                minidump = Some(temp_path)
+                // Unwind information can only be fetched ahead if the sources came first. The
+                // default sources are not used for this, as they may still be replaced.
+                let known_sources = sources_received.then(|| sources.clone());
+                let spooled_minidump = service
+                    .spool_minidump(&params.scope, known_sources, field, &mut file)
+                    .await?;
+                minidump = Some(temp_path);
+                spooled = Some(spooled_minidump);
             }
             Some("sources") => {
                 let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
-                sources = serde_json::from_slice(&data)?;
+                sources = request_sources(serde_json::from_slice(&data)?)?;
+                sources_received = true;
             }
             Some("options") => {
                 let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
@@ -65,7 +74,11 @@ pub async fn handle_minidump_request(
         )
             .into());
     }
+    // Fetching ahead is cancelled if the request is rejected.
     let request_id = service.process_minidump(params.scope, minidump_file, sources, options)?;
+    if let Some(spooled) = spooled {
+        spooled.keep_prefetching();
+    }
 
     match service.get_response(request_id, params.timeout).await {
         Some(response) => Ok(Json(response)),
@@ -77,7 +90,8 @@ pub async fn handle_minidump_request(
 mod tests {
     use reqwest::{multipart, Client, StatusCode};
 
-    use crate::service::SymbolicationResponse;
+    use crate::config::Config;
+    use crate::service::{RequestService, SymbolicationResponse};
     use crate::test;
 
     #[tokio::test]
@@ -158,6 +172,33 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_max_size() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let mut config = Config::default();
+        config.minidump.max_size = Some(1024);
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(crate::endpoints::create_app(service));
+
+        let file_contents = test::read_fixture("windows.dmp");
+        let file_part = multipart::Part::bytes(file_contents).file_name("windows.dmp");
+
+        let form = multipart::Form::new()
+            .text("sources", "[]")
+            .part("upload_file_minidump", file_part);
+
+        let response = Client::new()
+            .post(server.url("/minidump"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
     #[tokio::test]
     async fn test_body_limit() {
         test::setup();
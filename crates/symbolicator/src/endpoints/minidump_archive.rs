@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::Path;
+
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use symbolic::common::ByteView;
+use tempfile::{NamedTempFile, TempPath};
+use tokio::fs::File;
+
+use symbolicator_service::utils::archives::{self, ArchiveFormat};
+use symbolicator_service::utils::compression::maybe_decompress_file_limited;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::metric;
+use crate::service::{MaxRequestsError, RequestId, RequestOptions, RequestService};
+use crate::utils::sentry::ConfigureScope;
+
+use super::multipart::{read_multipart_data, stream_multipart_file};
+use super::ResponseError;
+
+/// The requests created for the minidumps in an archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MinidumpArchiveResponse {
+    /// The ID of the request processing each minidump, keyed by its path in the archive.
+    pub requests: BTreeMap<String, RequestId>,
+}
+
+/// The maximum number of members of an archive which are inspected.
+const MAX_ARCHIVE_MEMBERS: usize = 10_000;
+
+/// The maximum size in bytes of a decompressed archive, and of all minidumps extracted from it.
+const MAX_EXTRACTED_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Creates a temporary file in the configured directory for temporary files.
+fn create_tempfile(
+    tmp_dir: Option<&Path>,
+    prefix: &str,
+    suffix: &str,
+) -> io::Result<NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(prefix).suffix(suffix);
+    match tmp_dir {
+        Some(tmp_dir) => builder.tempfile_in(tmp_dir),
+        None => builder.tempfile(),
+    }
+}
+
+/// Extracts the minidumps in the `archive`, keyed by their path in the archive.
+///
+/// This blocks on file I/O and decompression. Archives with more than [`MAX_ARCHIVE_MEMBERS`]
+/// members, or which extract to more than [`MAX_EXTRACTED_SIZE`] bytes, are rejected.
+fn extract_minidumps(
+    tmp_dir: Option<&Path>,
+    mut archive: NamedTempFile,
+) -> Result<(ArchiveFormat, Vec<(String, TempPath)>), ResponseError> {
+    let invalid = |err: io::Error| (StatusCode::BAD_REQUEST, anyhow::Error::from(err));
+
+    // Archives of minidumps are commonly compressed tarballs.
+    maybe_decompress_file_limited(&mut archive, MAX_EXTRACTED_SIZE).map_err(invalid)?;
+    let data = ByteView::map_file_ref(archive.as_file())?;
+    let format = ArchiveFormat::detect(&data)
+        .ok_or((StatusCode::BAD_REQUEST, "unsupported archive format"))?;
+
+    // Members which are not minidumps, such as metadata files, are skipped.
+    let mut minidumps = vec![];
+    let mut members = 0;
+    let mut remaining = MAX_EXTRACTED_SIZE;
+    archives::visit_files(format, &data, |name, contents| {
+        members += 1;
+        if members > MAX_ARCHIVE_MEMBERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "archive has too many members",
+            ));
+        }
+
+        let mut minidump = create_tempfile(tmp_dir, "minidump", ".dmp")?;
+        let copied = io::copy(&mut contents.take(remaining + 1), &mut minidump)?;
+        if copied > remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "archive exceeds the size limit",
+            ));
+        }
+
+        if ByteView::map_file_ref(minidump.as_file())?.starts_with(b"MDMP") {
+            remaining -= copied;
+            minidumps.push((name.to_owned(), minidump.into_temp_path()));
+        }
+        Ok(())
+    })
+    .map_err(invalid)?;
+
+    Ok((format, minidumps))
+}
+
+pub async fn handle_minidump_archive_request(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    mut multipart: extract::Multipart,
+) -> Result<Json<MinidumpArchiveResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let tmp_dir = service.config().cache_dir("tmp");
+    let mut archive = None;
+    let mut sources = params.default_sources(&service)?;
+    let mut options = RequestOptions::default();
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("upload_file_archive") => {
+                let (file, temp_path) =
+                    create_tempfile(tmp_dir.as_deref(), "archive", "")?.into_parts();
+                let mut file = File::from_std(file);
+                stream_multipart_file(field, &mut file).await?;
+                archive = Some(NamedTempFile::from_parts(file.into_std().await, temp_path));
+            }
+            Some("sources") => {
+                let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
+                sources = request_sources(serde_json::from_slice(&data)?)?;
+            }
+            Some("options") => {
+                let data = read_multipart_data(field, 1024 * 1024).await?; // 1Mb
+                options = serde_json::from_slice(&data)?;
+            }
+            _ => (), // Always ignore unknown fields.
+        }
+    }
+
+    let archive = archive.ok_or((StatusCode::BAD_REQUEST, "missing archive"))?;
+
+    let (format, minidumps) =
+        tokio::task::spawn_blocking(move || extract_minidumps(tmp_dir.as_deref(), archive))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+    if minidumps.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "archive contains no minidumps").into());
+    }
+
+    metric!(
+        time_raw("symbolication.minidump_archive.minidumps") = minidumps.len() as u64,
+        "format" => format.name()
+    );
+
+    // Either all minidumps are processed, or none of them.
+    if !service.has_capacity(minidumps.len()) {
+        return Err(MaxRequestsError.into());
+    }
+
+    let mut requests = BTreeMap::new();
+    for (name, minidump) in minidumps {
+        let result = service.process_minidump(
+            params.scope.clone(),
+            minidump,
+            sources.clone(),
+            options.clone(),
+        );
+        match result {
+            Ok(request_id) => {
+                requests.insert(name, request_id);
+            }
+            Err(err) => {
+                // Other requests took the remaining capacity in the meantime.
+                for request_id in requests.into_values() {
+                    service.cancel(request_id);
+                }
+                return Err(err.into());
+            }
+        }
+    }
+
+    Ok(Json(MinidumpArchiveResponse { requests }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use reqwest::{multipart, Client, StatusCode};
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_basic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let mut archive = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut archive);
+        let options = zip::write::FileOptions::default();
+        writer.start_file("crashes/windows.dmp", options).unwrap();
+        writer
+            .write_all(&test::read_fixture("windows.dmp"))
+            .unwrap();
+        writer.start_file("crashes/metadata.json", options).unwrap();
+        writer.write_all(b"{}").unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let file_part = multipart::Part::bytes(archive.into_inner()).file_name("crashes.zip");
+        let form = multipart::Form::new()
+            .part("upload_file_archive", file_part)
+            .text("sources", "[]");
+
+        let response = Client::new()
+            .post(server.url("/minidump-archive"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response: MinidumpArchiveResponse = response.json().await.unwrap();
+        let names: Vec<_> = response.requests.keys().collect();
+        assert_eq!(names, ["crashes/windows.dmp"]);
+
+        let request_id = response.requests["crashes/windows.dmp"];
+        let response = Client::new()
+            .get(server.url(&format!("/requests/{request_id}?timeout=30")))
+            .send()
+            .await
+            .unwrap();
+        let response: SymbolicationResponse = response.json().await.unwrap();
+        assert!(matches!(response, SymbolicationResponse::Completed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_too_many_members() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let mut archive = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut archive);
+        let options = zip::write::FileOptions::default();
+        for index in 0..=MAX_ARCHIVE_MEMBERS {
+            writer.start_file(format!("{index}.json"), options).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+
+        let file_part = multipart::Part::bytes(archive.into_inner()).file_name("crashes.zip");
+        let form = multipart::Form::new().part("upload_file_archive", file_part);
+
+        let response = Client::new()
+            .post(server.url("/minidump-archive"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_for_all_minidumps() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let config = crate::config::Config {
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(crate::endpoints::create_app(service.clone()));
+
+        let mut archive = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut archive);
+        let options = zip::write::FileOptions::default();
+        for name in ["1.dmp", "2.dmp"] {
+            writer.start_file(name, options).unwrap();
+            writer
+                .write_all(&test::read_fixture("windows.dmp"))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+
+        let file_part = multipart::Part::bytes(archive.into_inner()).file_name("crashes.zip");
+        let form = multipart::Form::new()
+            .part("upload_file_archive", file_part)
+            .text("sources", "[]");
+
+        let response = Client::new()
+            .post(server.url("/minidump-archive"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        // None of the minidumps is processed, rather than only the first one.
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(service.in_flight_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_minidumps() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let file_part = multipart::Part::bytes(b"not an archive".to_vec()).file_name("crashes");
+        let form = multipart::Form::new().part("upload_file_archive", file_part);
+
+        let response = Client::new()
+            .post(server.url("/minidump-archive"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
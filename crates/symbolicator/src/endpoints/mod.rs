@@ -1,4 +1,6 @@
 use axum::extract::DefaultBodyLimit;
+use axum::http::StatusCode;
+use axum::middleware;
 use axum::routing::{get, post};
 use axum::Router;
 use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
@@ -7,28 +9,76 @@ use tower::ServiceBuilder;
 use crate::service::RequestService;
 
 mod applecrashreport;
+mod auth;
+mod caches;
+mod download;
+mod drain;
 mod error;
+mod gopanic;
+mod info;
+mod invalidate;
+mod memory;
 mod metrics;
 mod minidump;
+mod minidump_archive;
 mod multipart;
+mod objects;
+mod prefetch;
 mod proxy;
+mod quarantine;
 mod requests;
+mod source_health;
+mod stored_sources;
 mod symbolicate;
+mod symbolicate_firmware;
+mod symbolicate_hybrid;
 mod symbolicate_js;
+mod symbolicate_jvm;
+mod symbolicate_profile;
+mod symbolicate_profile_chunk;
+mod symbolicate_text;
 
+pub use download::DownloaderState;
 pub use error::ResponseError;
 use metrics::MetricsLayer;
 
 use self::minidump::handle_minidump_request as minidump;
+use self::minidump_archive::handle_minidump_archive_request as minidump_archive;
 use applecrashreport::handle_apple_crash_report_request as applecrashreport;
+use auth::{require_admin_token, require_downloader_token};
+use caches::cache_stats;
+use download::download_file;
+use drain::{drain, reject_when_draining};
+use gopanic::handle_go_panic_request as gopanic;
+use info::info;
+use invalidate::invalidate_negative_caches;
+use memory::{heap_profile, memory_stats};
+use objects::fetch_object;
+use prefetch::prefetch_difs;
 use proxy::proxy_symstore_request as proxy;
-use requests::poll_request as requests;
+use quarantine::quarantine_report;
+use requests::{cancel_request, poll_request as requests};
+use source_health::source_health;
+use stored_sources::{
+    delete_stored_sources, get_stored_sources, list_stored_sources, put_stored_sources,
+};
 use symbolicate::symbolicate_frames as symbolicate;
+use symbolicate_firmware::symbolicate_firmware;
+use symbolicate_hybrid::handle_symbolication_request as symbolicate_hybrid;
 use symbolicate_js::handle_symbolication_request as symbolicate_js;
+use symbolicate_jvm::handle_symbolication_request as symbolicate_jvm;
+use symbolicate_profile::symbolicate_profile;
+use symbolicate_profile_chunk::symbolicate_profile_chunk;
+use symbolicate_text::symbolicate_text;
 
-pub async fn healthcheck() -> &'static str {
+pub async fn healthcheck(
+    service: RequestService,
+) -> Result<&'static str, (StatusCode, &'static str)> {
     crate::metric!(counter("healthcheck") += 1);
-    "ok"
+    if service.is_draining() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "draining"));
+    }
+    Ok("ok")
 }
 
 pub fn create_app(service: RequestService) -> Router {
@@ -40,16 +90,76 @@ pub fn create_app(service: RequestService) -> Router {
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024));
     // We have a global 100M body limit, but a 5M symbolicate body limit
     let symbolicate_route = post(symbolicate).layer(DefaultBodyLimit::max(5 * 1024 * 1024));
+    // Administrative endpoints all require the `admin_token`.
+    let admin_routes = Router::new()
+        .route("/drain", post(drain))
+        .route("/admin/memory", get(memory_stats))
+        .route("/admin/heap-profile", post(heap_profile))
+        .route("/admin/quarantine", get(quarantine_report))
+        .route("/admin/source-health", get(source_health))
+        .route("/admin/caches", get(cache_stats))
+        .route("/admin/invalidate", post(invalidate_negative_caches))
+        .route("/admin/objects", post(fetch_object))
+        .route("/admin/sources/:scope", get(list_stored_sources))
+        .route(
+            "/admin/sources/:scope/:name",
+            get(get_stored_sources)
+                .put(put_stored_sources)
+                .delete(delete_stored_sources),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            service.clone(),
+            require_admin_token,
+        ));
     Router::new()
-        .route("/proxy/*path", get(proxy).head(proxy))
-        .route("/requests/:request_id", get(requests))
         .route("/applecrashreport", post(applecrashreport))
         .route("/minidump", post(minidump))
+        .route("/minidump-archive", post(minidump_archive))
+        .route("/gopanic", post(gopanic))
         // TODO(sourcemap): Verify whether this is the endpoint name we actually want to use.
         .route("/symbolicate-js", post(symbolicate_js))
+        .route("/symbolicate-jvm", post(symbolicate_jvm))
+        .route("/symbolicate-hybrid", post(symbolicate_hybrid))
+        .route("/symbolicate-text", post(symbolicate_text))
+        .route("/symbolicate-firmware", post(symbolicate_firmware))
+        .route("/symbolicate-profile", post(symbolicate_profile))
+        .route(
+            "/symbolicate-profile-chunk",
+            post(symbolicate_profile_chunk),
+        )
         .route("/symbolicate", symbolicate_route)
-        .with_state(service)
+        .route("/prefetch", post(prefetch_difs))
+        // new submissions are rejected while draining, the routes below keep working
+        .route_layer(middleware::from_fn_with_state(
+            service.clone(),
+            reject_when_draining,
+        ))
+        .route("/proxy/*path", get(proxy).head(proxy))
+        .route(
+            "/requests/:request_id",
+            get(requests).delete(cancel_request),
+        )
+        .route("/info", get(info))
+        .merge(admin_routes)
+        .with_state(service.clone())
         .layer(layer)
         // the healthcheck is last, as it will bypass all the middlewares
-        .route("/healthcheck", get(healthcheck))
+        .route("/healthcheck", get(move || healthcheck(service)))
+}
+
+/// Creates the app of the downloader process, which downloads files for other instances.
+pub fn create_downloader_app(state: DownloaderState) -> Router {
+    let layer = ServiceBuilder::new()
+        .layer(NewSentryLayer::new_from_top())
+        .layer(SentryHttpLayer::with_transaction())
+        .layer(MetricsLayer);
+    Router::new()
+        .route("/download", post(download_file))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_downloader_token,
+        ))
+        .with_state(state)
+        .layer(layer)
+        .route("/healthcheck", get(|| async { "ok" }))
 }
@@ -0,0 +1,173 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::body::Body;
+use axum::extract;
+use axum::http::{Response, StatusCode};
+use serde::Deserialize;
+
+use symbolicator_service::caching::{CacheEntry, CacheError};
+use symbolicator_service::services::object_id_from_object_info;
+use symbolicator_sources::{FileType, SourceConfig};
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    FindObject, ObjectHandle, ObjectPurpose, RawObjectInfo, RequestService, Scope,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the object fetch request.
+#[derive(Deserialize)]
+pub struct FetchObjectRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The identifiers of the object, as they appear in a module list.
+    pub object: RawObjectInfo,
+    /// What the object is needed for, which determines the file types that are searched.
+    pub purpose: ObjectPurpose,
+}
+
+async fn load_object(
+    service: &RequestService,
+    scope: Scope,
+    sources: Arc<[SourceConfig]>,
+    object: &RawObjectInfo,
+    purpose: ObjectPurpose,
+) -> CacheEntry<Arc<ObjectHandle>> {
+    let found_object = service
+        .find_object(FindObject {
+            filetypes: FileType::from_object_type(object.ty),
+            identifier: object_id_from_object_info(object),
+            sources,
+            scope,
+            purpose,
+        })
+        .await;
+
+    let Some(meta) = found_object.meta else {
+        return Err(CacheError::NotFound);
+    };
+
+    service.fetch_object(meta.handle?).await
+}
+
+/// Returns the raw contents of an object file, as found on the sources of the request.
+///
+/// The object is looked up and cached just like the objects needed for symbolication, so that
+/// tools working with object files can share the sources and caches of Symbolicator. As this
+/// gives access to the objects of all configured and stored sources, it is an administrative
+/// endpoint.
+pub async fn fetch_object(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<FetchObjectRequestBody>,
+) -> Result<Response<Body>, ResponseError> {
+    params.configure_scope();
+
+    // Downloads count against the limit of concurrent requests until the response is created.
+    let _admission = service.admit_request()?;
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let loaded = load_object(&service, params.scope, sources, &body.object, body.purpose).await;
+    let object_handle = match loaded {
+        Ok(handle) => handle,
+        Err(CacheError::NotFound) => {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())?)
+        }
+        Err(e) => {
+            return Err(e)
+                .context("failed to download object")
+                .map_err(|e| e.into())
+        }
+    };
+
+    let data = object_handle.data().clone();
+    let response = Response::builder()
+        .header("content-length", data.len())
+        .header("content-type", "application/octet-stream");
+
+    let bytes = Cursor::new(data);
+    Ok(response.body(Body::wrap_stream(tokio_util::io::ReaderStream::new(bytes)))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_fetch_object() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let client = Client::new();
+
+        let body = serde_json::json!({
+            "sources": [test::local_source()],
+            "object": {
+                "type": "pe",
+                "debug_id": "3249d99d-0c40-4931-8610-f4e4fb0b6936-1",
+                "debug_file": "crash.pdb",
+            },
+            "purpose": "debug",
+        });
+        let response = client
+            .post(server.url("/admin/objects"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let expected =
+            test::read_fixture("symbols/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
+        assert_eq!(response.bytes().await.unwrap(), expected);
+
+        let body = serde_json::json!({
+            "sources": [],
+            "object": {"type": "elf", "code_id": "7f883fcdc55336d0a809b0150f09500b"},
+            "purpose": "unwind",
+        });
+        let response = client
+            .post(server.url("/admin/objects"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_object_requires_token() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [test::local_source()],
+            "object": {"type": "elf", "code_id": "7f883fcdc55336d0a809b0150f09500b"},
+            "purpose": "debug",
+        });
+        let response = Client::new()
+            .post(server.url("/admin/objects"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,92 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    PrefetchDifs, RawObjectInfo, RequestOptions, RequestService, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the prefetch request.
+#[derive(Serialize, Deserialize)]
+pub struct PrefetchRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The modules whose debug information files should be fetched and converted.
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn prefetch_difs(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<PrefetchRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.prefetch_difs(
+        PrefetchDifs {
+            scope: params.scope,
+            sources,
+            modules: body.modules,
+        },
+        body.options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("prefetch request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_prefetch_missing() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = json!({
+            "sources": [],
+            "modules": [{
+                "type": "macho",
+                "debug_id": "502fc0a5-1ec1-3e47-9998-684fa139dca7",
+                "image_addr": "0x100000000",
+            }],
+        });
+
+        let response = Client::new()
+            .post(server.url("/prefetch"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(response["status"], "completed");
+        assert_eq!(response["stacktraces"], json!([]));
+        assert_eq!(response["modules"][0]["debug_status"], "missing");
+        assert_eq!(response["modules"][0]["unwind_status"], "missing");
+    }
+}
@@ -0,0 +1,80 @@
+use axum::extract;
+use axum::response::Json;
+use symbolicator_service::services::download::{
+    read_quarantine, QuarantinedObject, QUARANTINE_DIR,
+};
+
+use crate::service::RequestService;
+
+use super::ResponseError;
+
+/// Lists the object files which have been quarantined by the malware scanner.
+///
+/// The quarantined copies are kept in the diagnostics cache, and are removed along with their
+/// reports once the diagnostics retention expires.
+pub async fn quarantine_report(
+    extract::State(service): extract::State<RequestService>,
+) -> Result<Json<Vec<QuarantinedObject>>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/quarantine"));
+    });
+
+    let Some(diagnostics_dir) = service.config().cache_dir("diagnostics") else {
+        return Ok(Json(vec![]));
+    };
+    let quarantine_dir = diagnostics_dir.join(QUARANTINE_DIR);
+    let quarantined = tokio::task::spawn_blocking(move || read_quarantine(&quarantine_dir))
+        .await
+        .map_err(anyhow::Error::from)??;
+
+    Ok(Json(quarantined))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::config::Config;
+    use crate::endpoints;
+    use crate::service::RequestService;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_quarantine_report() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let quarantine_dir = cache_dir.path().join("diagnostics").join("quarantine");
+        std::fs::create_dir_all(&quarantine_dir).unwrap();
+        std::fs::write(quarantine_dir.join("0123"), "infected").unwrap();
+        let report = json!({
+            "file": "0123",
+            "source": "local",
+            "uri": "file:///symbols/foo.pdb",
+            "details": "Eicar-Signature",
+            "quarantined_at": "2023-03-01T12:00:00Z",
+        });
+        std::fs::write(quarantine_dir.join("0123.json"), report.to_string()).unwrap();
+
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            admin_token: Some(test::ADMIN_TOKEN.into()),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service));
+
+        let response = Client::new()
+            .get(server.url("/admin/quarantine"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let quarantined: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(quarantined, json!([report]));
+    }
+}
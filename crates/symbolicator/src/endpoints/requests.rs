@@ -1,6 +1,7 @@
 use axum::extract;
+use axum::http::header::RETRY_AFTER;
 use axum::http::StatusCode;
-use axum::response::Json;
+use axum::response::{IntoResponse, Json, Response};
 use serde::Deserialize;
 
 use crate::service::{RequestId, RequestService, SymbolicationResponse};
@@ -10,13 +11,19 @@ use crate::service::{RequestId, RequestService, SymbolicationResponse};
 pub struct PollSymbolicationRequestQueryParams {
     #[serde(default)]
     pub timeout: Option<u64>,
+    /// The affinity token returned with the pending response of the request.
+    #[serde(default)]
+    pub affinity: Option<String>,
 }
 
+/// The header carrying the affinity token of a poll that was sent to the wrong instance.
+const AFFINITY_HEADER: &str = "x-symbolicator-affinity";
+
 pub async fn poll_request(
     extract::State(service): extract::State<RequestService>,
     extract::Path(request_id): extract::Path<RequestId>,
     extract::Query(query): extract::Query<PollSymbolicationRequestQueryParams>,
-) -> Result<Json<SymbolicationResponse>, StatusCode> {
+) -> Result<Json<SymbolicationResponse>, Response> {
     sentry::configure_scope(|scope| {
         scope.set_transaction(Some("GET /requests"));
     });
@@ -25,7 +32,35 @@ pub async fn poll_request(
 
     match response_opt {
         Some(response) => Ok(Json(response)),
-        None => Err(StatusCode::NOT_FOUND),
+        // The request is likely processed by another instance, ask the client to try again so
+        // that the load balancer can route the poll according to its affinity token.
+        None => match query.affinity {
+            Some(affinity) if service.config().reject_foreign_polls => Err((
+                StatusCode::MISDIRECTED_REQUEST,
+                [
+                    (RETRY_AFTER.as_str(), "1"),
+                    (AFFINITY_HEADER, affinity.as_str()),
+                ],
+            )
+                .into_response()),
+            _ => Err(StatusCode::NOT_FOUND.into_response()),
+        },
+    }
+}
+
+/// Cancels a request which has not finished yet.
+pub async fn cancel_request(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path(request_id): extract::Path<RequestId>,
+) -> StatusCode {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("DELETE /requests"));
+    });
+
+    if service.cancel(request_id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
     }
 }
 
@@ -33,10 +68,12 @@ pub async fn poll_request(
 mod tests {
     use super::*;
 
+    use crate::config::Config;
     use crate::endpoints::symbolicate::SymbolicationRequestBody;
     use crate::test;
 
     use reqwest::Client;
+    use symbolicator_service::services::symbolication::SymbolicationPhase;
     use symbolicator_sources::{DirectoryLayoutType, FileType};
 
     #[track_caller]
@@ -94,7 +131,19 @@ mod tests {
             .await
             .unwrap();
 
-        let request_id = ensure_request_id(response.json().await.unwrap());
+        // The request is still waiting for the delayed download of its only debug file.
+        let (request_id, progress) = match response.json().await.unwrap() {
+            SymbolicationResponse::Pending {
+                request_id,
+                progress: Some(progress),
+                ..
+            } => (request_id, progress),
+            res => panic!("expected a pending response with progress, got: {res:#?}"),
+        };
+        assert_eq!(progress.phase, SymbolicationPhase::FetchingDebugFiles);
+        assert_eq!(progress.modules_fetched, 0);
+        assert_eq!(progress.modules_total, 1);
+        assert_eq!(progress.threads_total, 1);
 
         let response = client
             .get(server.url(&format!("/requests/{request_id}")))
@@ -105,4 +154,32 @@ mod tests {
         let response: SymbolicationResponse = response.json().await.unwrap();
         test::assert_snapshot!(response);
     }
+
+    #[tokio::test]
+    async fn test_reject_foreign_polls() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            reject_foreign_polls: true,
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(crate::endpoints::create_app(service));
+        let client = Client::new();
+
+        let url = "/requests/00000000-0000-0000-0000-000000000000";
+
+        // Without an affinity token, there is no hint to route the poll elsewhere.
+        let response = client.get(server.url(url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client
+            .get(server.url(&format!("{url}?affinity=abcd")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::MISDIRECTED_REQUEST);
+        assert_eq!(response.headers()[AFFINITY_HEADER], "abcd");
+    }
 }
@@ -0,0 +1,61 @@
+use axum::extract;
+use axum::response::Json;
+use symbolicator_service::services::download::SourceHealth;
+
+use crate::service::RequestService;
+
+/// Reports the health of the configured sources, based on their most recent downloads.
+pub async fn source_health(
+    extract::State(service): extract::State<RequestService>,
+) -> Json<Vec<SourceHealth>> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/source-health"));
+    });
+
+    Json(service.source_health())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::config::Config;
+    use crate::endpoints;
+    use crate::service::RequestService;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_source_health() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            sources: vec![test::local_source()].into(),
+            admin_token: Some(test::ADMIN_TOKEN.into()),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service));
+
+        let response = Client::new()
+            .get(server.url("/admin/source-health"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let health: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            health,
+            json!([{
+                "source": "local",
+                "downloads": 0,
+                "error_rate": 0.0,
+                "median_latency_ms": null,
+                "consecutive_failures": 0,
+            }])
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use symbolicator_sources::SourceConfig;
+
+use crate::service::{RequestService, Scope};
+use crate::stored_sources::redact_credentials;
+
+use super::ResponseError;
+
+/// Lists the names of the source configurations stored for a scope.
+///
+/// Only the names are listed, so this never exposes credentials.
+pub async fn list_stored_sources(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path(scope): extract::Path<String>,
+) -> Json<Vec<String>> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/sources/:scope"));
+    });
+
+    Json(service.stored_sources().names(&Scope::Scoped(scope)))
+}
+
+/// Responds with a stored source configuration.
+///
+/// Credentials are replaced with a placeholder, so they cannot be read back once stored.
+pub async fn get_stored_sources(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path((scope, name)): extract::Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("GET /admin/sources/:scope/:name"));
+    });
+
+    let sources = service
+        .stored_sources()
+        .get(&Scope::Scoped(scope), &name)
+        .ok_or((StatusCode::NOT_FOUND, "stored sources not found"))?;
+    Ok(Json(redact_credentials(&sources)?))
+}
+
+/// Stores a source configuration, replacing any configuration of the same name.
+pub async fn put_stored_sources(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path((scope, name)): extract::Path<(String, String)>,
+    extract::Json(sources): extract::Json<Vec<SourceConfig>>,
+) -> Result<StatusCode, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("PUT /admin/sources/:scope/:name"));
+    });
+
+    service
+        .stored_sources()
+        .put(&Scope::Scoped(scope), &name, sources.into())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes a stored source configuration.
+pub async fn delete_stored_sources(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path((scope, name)): extract::Path<(String, String)>,
+) -> Result<StatusCode, ResponseError> {
+    sentry::configure_scope(|scope| {
+        scope.set_transaction(Some("DELETE /admin/sources/:scope/:name"));
+    });
+
+    if !service
+        .stored_sources()
+        .delete(&Scope::Scoped(scope), &name)?
+    {
+        return Err((StatusCode::NOT_FOUND, "stored sources not found").into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use serde_json::json;
+
+    use super::*;
+    use crate::stored_sources::REDACTED;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_stored_sources() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        let sources = json!([{
+            "type": "filesystem",
+            "id": "builds",
+            "path": "/symbols",
+        }, {
+            "type": "http",
+            "id": "private",
+            "url": "https://symbols.example.com/",
+            "headers": {"Authorization": "Bearer hunter2"},
+        }]);
+        let response = client
+            .put(server.url("/admin/sources/1/builds"))
+            .json(&sources)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .put(server.url("/admin/sources/1/builds"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .json(&sources)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = client
+            .get(server.url("/admin/sources/1"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        let names: Vec<String> = response.json().await.unwrap();
+        assert_eq!(names, ["builds"]);
+
+        let response = client
+            .get(server.url("/admin/sources/1/builds"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        let stored: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(stored[0]["id"], "builds");
+        // Credentials are never returned.
+        assert_eq!(stored[1]["url"], "https://symbols.example.com/");
+        assert_eq!(stored[1]["headers"]["Authorization"], REDACTED);
+
+        // Requests of the scope can reference the stored sources.
+        let response = client
+            .post(server.url("/symbolicate?scope=1&stored_sources=builds"))
+            .json(&json!({ "stacktraces": [], "modules": [] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Stored sources are not visible to other scopes.
+        let response = client
+            .post(server.url("/symbolicate?scope=2&stored_sources=builds"))
+            .json(&json!({ "stacktraces": [], "modules": [] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .delete(server.url("/admin/sources/1/builds"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = client
+            .get(server.url("/admin/sources/1/builds"))
+            .bearer_auth(test::ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
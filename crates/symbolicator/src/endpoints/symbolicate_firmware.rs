@@ -0,0 +1,107 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_service::utils::hex::HexValue;
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{RawObjectInfo, RequestOptions, RequestService, SymbolicationResponse};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the bare-metal firmware symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct FirmwareSymbolicationRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The firmware image, which is placed at the address it was linked at.
+    pub firmware: RawObjectInfo,
+    /// The offset the firmware was relocated by when loading it.
+    #[serde(default)]
+    pub load_offset: HexValue,
+    /// Lists of instruction addresses, each of which is symbolicated as a stack trace.
+    pub addresses: Vec<Vec<HexValue>>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn symbolicate_firmware(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<FirmwareSymbolicationRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.symbolicate_firmware(
+        params.scope,
+        sources,
+        body.firmware,
+        body.load_offset.0,
+        body.addresses,
+        body.options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use symbolicator_service::types::{CompletedResponse, FrameStatus};
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_basic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [],
+            "firmware": {"type": "elf", "code_file": "firmware.elf"},
+            "load_offset": "0x8000000",
+            "addresses": [["0x8001234", "0x8000abc"], ["0x80004d0"]],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-firmware"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = response.json::<SymbolicationResponse>().await.unwrap();
+        let SymbolicationResponse::Completed(response) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        let CompletedResponse::NativeSymbolication(response) = *response else {
+            panic!("unexpected response: {response:?}");
+        };
+
+        // Without sources, the firmware is placed at the load offset.
+        assert_eq!(response.modules.len(), 1);
+        assert_eq!(response.modules[0].raw.image_addr.0, 0x800_0000);
+
+        assert_eq!(response.stacktraces.len(), 2);
+        let frames = &response.stacktraces[0].frames;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].raw.instruction_addr.0, 0x800_1234);
+        assert_eq!(frames[0].status, FrameStatus::Missing);
+    }
+}
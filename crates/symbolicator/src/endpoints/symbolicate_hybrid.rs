@@ -0,0 +1,88 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use symbolicator_sources::{SentrySourceConfig, SourceConfig};
+
+use crate::endpoints::symbolicate::{
+    request_sentry_source, request_sources, SymbolicationRequestQueryParams,
+};
+use crate::service::{
+    HybridStacktrace, RawObjectInfo, RequestOptions, RequestService, Signal,
+    SymbolicateHybridStacktraces, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+fn default_allow_scraping() -> bool {
+    true
+}
+
+/// JSON body of the hybrid symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct HybridSymbolicationRequestBody {
+    #[serde(default)]
+    pub signal: Option<Signal>,
+    /// The sources to fetch native debug files from.
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The Sentry source to fetch source maps and minified files from.
+    #[serde(default)]
+    pub source: Option<SentrySourceConfig>,
+    #[serde(default)]
+    pub stacktraces: Vec<HybridStacktrace>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub dist: Option<String>,
+    #[serde(default = "default_allow_scraping")]
+    pub allow_scraping: bool,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn handle_symbolication_request(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<HybridSymbolicationRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let HybridSymbolicationRequestBody {
+        signal,
+        sources,
+        source,
+        stacktraces,
+        modules,
+        dist,
+        allow_scraping,
+        options,
+    } = body;
+
+    let sources = match sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+    let source = source.map(request_sentry_source).transpose()?;
+
+    let request_id = service.symbolicate_hybrid_stacktraces(
+        SymbolicateHybridStacktraces {
+            scope: params.scope,
+            signal,
+            sources,
+            source,
+            dist,
+            allow_scraping,
+            stacktraces,
+            modules,
+        },
+        options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
@@ -1,13 +1,12 @@
-use std::sync::Arc;
-
 use axum::extract;
+use axum::http::StatusCode;
 use axum::response::Json;
 use serde::{Deserialize, Serialize};
 use symbolicator_service::services::symbolication::SymbolicateJsStacktraces;
 use symbolicator_service::types::RawObjectInfo;
 use symbolicator_sources::SentrySourceConfig;
 
-use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
+use crate::endpoints::symbolicate::{request_sentry_source, SymbolicationRequestQueryParams};
 use crate::service::{JsStacktrace, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
@@ -48,9 +47,14 @@ pub async fn handle_symbolication_request(
         allow_scraping,
     } = body;
 
+    let Some(source) = source else {
+        return Err((StatusCode::BAD_REQUEST, "missing Sentry source").into());
+    };
+    let source = request_sentry_source(source)?;
+
     let request_id = service.symbolicate_js_stacktraces(SymbolicateJsStacktraces {
         scope: params.scope,
-        source: Arc::new(source.unwrap()),
+        source,
         stacktraces,
         modules,
         dist,
@@ -62,3 +66,37 @@ pub async fn handle_symbolication_request(
         None => Err("symbolication request did not start".into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_secret_reference_in_source() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = json!({
+            "source": {
+                "id": "attacker",
+                "url": "https://attacker.invalid/",
+                "token": "${env:SYMBOLICATOR_TOKEN}",
+            },
+            "stacktraces": [],
+            "modules": [],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-js"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
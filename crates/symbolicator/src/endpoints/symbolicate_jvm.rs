@@ -0,0 +1,106 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    JvmException, JvmModule, JvmStacktrace, RequestOptions, RequestService,
+    SymbolicateJvmStacktraces, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the JVM symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct JvmSymbolicationRequestBody {
+    /// The sources to fetch ProGuard mapping files from.
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub exceptions: Vec<JvmException>,
+    #[serde(default)]
+    pub stacktraces: Vec<JvmStacktrace>,
+    /// The ProGuard mapping files to deobfuscate with.
+    #[serde(default)]
+    pub modules: Vec<JvmModule>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn handle_symbolication_request(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<JvmSymbolicationRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let JvmSymbolicationRequestBody {
+        sources,
+        exceptions,
+        stacktraces,
+        modules,
+        options,
+    } = body;
+
+    let sources = match sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.symbolicate_jvm_stacktraces(
+        SymbolicateJvmStacktraces {
+            scope: params.scope,
+            sources,
+            exceptions,
+            stacktraces,
+            modules,
+        },
+        options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_missing_mapping() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = json!({
+            "sources": [],
+            "exceptions": [{"type": "b", "module": "a"}],
+            "stacktraces": [{"frames": [{"function": "a", "module": "a.b", "lineno": 4}]}],
+            "modules": [{"uuid": "246fb328-fc4e-406a-87ff-fc35f6149d8f"}],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-jvm"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(response["status"], "completed");
+        assert_eq!(response["exceptions"][0]["type"], "b");
+        assert_eq!(response["stacktraces"][0]["frames"][0]["module"], "a.b");
+        assert_eq!(response["errors"][0]["type"], "missing");
+    }
+}
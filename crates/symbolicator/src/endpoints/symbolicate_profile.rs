@@ -0,0 +1,100 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    ProfileFormat, RawObjectInfo, RequestOptions, RequestService, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the profile symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct ProfileSymbolicationRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The format of the `profile`.
+    #[serde(default)]
+    pub format: ProfileFormat,
+    /// The textual profile to symbolicate.
+    pub profile: String,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn symbolicate_profile(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<ProfileSymbolicationRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.symbolicate_profile(
+        params.scope,
+        body.format,
+        body.profile,
+        sources,
+        body.modules,
+        body.options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use symbolicator_service::types::CompletedResponse;
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_folded() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [],
+            "modules": [{"type": "elf", "code_file": "/usr/bin/prog", "image_addr": "0x1000"}],
+            "profile": "prog;prog+0x10;prog+0x20 3\nprog;prog+0x10;prog+0x20 2\n",
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-profile"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = response.json::<SymbolicationResponse>().await.unwrap();
+        let SymbolicationResponse::Completed(response) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        let CompletedResponse::ProfileSymbolication(response) = *response else {
+            panic!("unexpected response: {response:?}");
+        };
+
+        // Without debug files, the frames are returned as they are, with their counts merged.
+        assert_eq!(response.folded, "prog;prog+0x10;prog+0x20 5\n");
+    }
+}
@@ -0,0 +1,146 @@
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_service::types::RawFrame;
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    RawObjectInfo, RequestOptions, RequestService, SymbolicateProfileChunk, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the profile chunk symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct ProfileChunkRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    /// The table of unique frames.
+    #[serde(default)]
+    pub frames: Vec<RawFrame>,
+    /// The stacks, as lists of indices into `frames`.
+    #[serde(default)]
+    pub stacks: Vec<Vec<usize>>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn symbolicate_profile_chunk(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<ProfileChunkRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    if let Some(index) = body
+        .stacks
+        .iter()
+        .flatten()
+        .find(|&&index| index >= body.frames.len())
+    {
+        let err = anyhow::anyhow!("stack references unknown frame {index}");
+        return Err((StatusCode::BAD_REQUEST, err).into());
+    }
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.symbolicate_profile_chunk(
+        SymbolicateProfileChunk {
+            scope: params.scope,
+            sources,
+            modules: body.modules.into_iter().map(From::from).collect(),
+            frames: body.frames,
+            stacks: body.stacks,
+        },
+        body.options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use symbolicator_service::types::CompletedResponse;
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_basic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [],
+            "frames": [
+                {"instruction_addr": "0x1010"},
+                {"instruction_addr": "0x1020"},
+            ],
+            "stacks": [[0, 1], [1], [0, 1]],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-profile-chunk"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = response.json::<SymbolicationResponse>().await.unwrap();
+        let SymbolicationResponse::Completed(response) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        let CompletedResponse::ProfileChunkSymbolication(response) = *response else {
+            panic!("unexpected response: {response:?}");
+        };
+
+        // The second frame is symbolicated once as a caller, and once as a leaf.
+        let frames: Vec<_> = response
+            .frames
+            .iter()
+            .map(|frame| frame.original_index)
+            .collect();
+        assert_eq!(frames, [Some(0), Some(1), Some(1)]);
+        assert_eq!(response.stacks, [vec![0, 1], vec![2], vec![0, 1]]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_frame_index() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [],
+            "frames": [{"instruction_addr": "0x1010"}],
+            "stacks": [[0, 1]],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-profile-chunk"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
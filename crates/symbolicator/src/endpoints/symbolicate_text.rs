@@ -0,0 +1,102 @@
+use axum::extract;
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_sources::SourceConfig;
+
+use crate::endpoints::symbolicate::{request_sources, SymbolicationRequestQueryParams};
+use crate::service::{
+    RawObjectInfo, RequestOptions, RequestService, Signal, SymbolicationResponse,
+};
+use crate::utils::sentry::ConfigureScope;
+
+use super::ResponseError;
+
+/// JSON body of the text backtrace symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct TextSymbolicationRequestBody {
+    #[serde(default)]
+    pub signal: Option<Signal>,
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The unstructured backtrace to extract frames from.
+    pub backtrace: String,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn symbolicate_text(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<TextSymbolicationRequestBody>,
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    let sources = match body.sources {
+        Some(sources) => request_sources(sources)?,
+        None => params.default_sources(&service)?,
+    };
+
+    let request_id = service.symbolicate_text(
+        params.scope,
+        body.signal,
+        body.backtrace,
+        sources,
+        body.modules,
+        body.options,
+    )?;
+
+    match service.get_response(request_id, params.timeout).await {
+        Some(response) => Ok(Json(response)),
+        None => Err("symbolication request did not start".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use symbolicator_service::types::CompletedResponse;
+
+    use crate::service::SymbolicationResponse;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_basic() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let body = serde_json::json!({
+            "sources": [],
+            "modules": [{"type": "elf", "code_file": "/usr/bin/prog", "image_addr": "0x1000"}],
+            "backtrace": "./prog(+0x8e5) [0x18e5]\n./prog(main+0x21) [0x1901]\n",
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate-text"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = response.json::<SymbolicationResponse>().await.unwrap();
+        let SymbolicationResponse::Completed(response) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        let CompletedResponse::NativeSymbolication(response) = *response else {
+            panic!("unexpected response: {response:?}");
+        };
+
+        assert_eq!(response.stacktraces.len(), 1);
+        let frames = &response.stacktraces[0].frames;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].raw.instruction_addr.0, 0x8e5);
+        assert_eq!(frames[1].raw.symbol.as_deref(), Some("main"));
+    }
+}
@@ -0,0 +1,601 @@
+//! The gRPC interface of Symbolicator.
+//!
+//! If `bind_grpc` is configured, Symbolicator serves the `Symbolicator` service defined in
+//! `proto/symbolicator.proto` next to the HTTP endpoints. Its RPCs are thin wrappers around the
+//! [`RequestService`], so requests are processed, queued and polled exactly like HTTP requests,
+//! and the request IDs of either interface can be polled with the other one.
+//!
+//! Responses are converted from the JSON API types into their protobuf counterparts. Only native
+//! symbolication responses have one, polling any other response fails with `FAILED_PRECONDITION`.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::fs::File;
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+use symbolicator_service::services::download::contains_secret_references;
+use symbolicator_service::services::symbolication::MinidumpUploadError;
+use symbolicator_service::types::{
+    CompleteObjectInfo, CompleteStacktrace, CompletedResponse, CompletedSymbolicationResponse,
+    RawFrame, SymbolicatedFrame,
+};
+use symbolicator_service::utils::addr::KernelSpace;
+use symbolicator_service::utils::hex::HexValue;
+use symbolicator_sources::SourceConfig;
+
+use crate::service::{
+    MaxRequestsError, RawObjectInfo, RawStacktrace, RequestId, RequestOptions, RequestService,
+    Scope, Signal, StacktraceOrigin, SymbolicateStacktraces, SymbolicationResponse,
+};
+
+use self::proto::process_minidump_request::Chunk;
+use self::proto::symbolicator_server::{Symbolicator, SymbolicatorServer};
+
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("symbolicator");
+}
+
+/// Creates the gRPC service, which processes requests with the given [`RequestService`].
+pub fn create_service(service: RequestService) -> SymbolicatorServer<GrpcService> {
+    SymbolicatorServer::new(GrpcService { service })
+}
+
+/// Implements the RPCs of the `Symbolicator` gRPC service.
+#[derive(Clone)]
+pub struct GrpcService {
+    service: RequestService,
+}
+
+impl GrpcService {
+    /// Rejects new requests while the server is draining, like the HTTP endpoints do.
+    fn check_draining(&self) -> Result<(), Status> {
+        if self.service.is_draining() {
+            return Err(Status::unavailable("draining"));
+        }
+        Ok(())
+    }
+
+    /// Parses the `sources` of a request, or returns the configured sources if there are none.
+    fn sources(&self, sources: Option<String>) -> Result<Arc<[SourceConfig]>, Status> {
+        match sources {
+            Some(sources) => {
+                let sources: Vec<SourceConfig> = parse_json(&sources, "sources")?;
+                if contains_secret_references(&sources) {
+                    return Err(Status::invalid_argument(
+                        "sources of requests must not reference secrets",
+                    ));
+                }
+                Ok(sources.into())
+            }
+            None => Ok(self.service.config().default_sources()),
+        }
+    }
+
+    /// Waits for the response to a request, and converts it to protobuf.
+    async fn respond(
+        &self,
+        request_id: RequestId,
+        timeout: Option<u64>,
+    ) -> Result<Response<proto::SymbolicationResponse>, Status> {
+        match self.service.get_response(request_id, timeout).await {
+            Some(response) => Ok(Response::new(response.try_into()?)),
+            None => Err(Status::not_found("unknown request")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Symbolicator for GrpcService {
+    async fn symbolicate_stacktraces(
+        &self,
+        request: Request<proto::SymbolicateStacktracesRequest>,
+    ) -> Result<Response<proto::SymbolicationResponse>, Status> {
+        self.check_draining()?;
+        let request = request.into_inner();
+
+        let sources = self.sources(request.sources)?;
+        let options = parse_options(request.options)?;
+        let stacktraces = request
+            .stacktraces
+            .into_iter()
+            .map(RawStacktrace::try_from)
+            .collect::<Result<_, _>>()?;
+        let modules = request
+            .modules
+            .into_iter()
+            .map(|module| RawObjectInfo::try_from(module).map(From::from))
+            .collect::<Result<_, _>>()?;
+
+        let request_id = self
+            .service
+            .symbolicate_stacktraces(
+                SymbolicateStacktraces {
+                    scope: parse_scope(request.scope),
+                    signal: request.signal.map(Signal),
+                    sources,
+                    origin: StacktraceOrigin::Symbolicate,
+                    stacktraces,
+                    modules,
+                    kernel_space: KernelSpace::Unknown,
+                },
+                options,
+            )
+            .map_err(max_requests_status)?;
+
+        self.respond(request_id, request.timeout).await
+    }
+
+    async fn process_minidump(
+        &self,
+        request: Request<Streaming<proto::ProcessMinidumpRequest>>,
+    ) -> Result<Response<proto::SymbolicationResponse>, Status> {
+        self.check_draining()?;
+        let mut stream = request.into_inner();
+
+        let metadata = match stream.message().await? {
+            Some(proto::ProcessMinidumpRequest {
+                chunk: Some(Chunk::Metadata(metadata)),
+            }) => metadata,
+            _ => return Err(Status::invalid_argument("expected minidump metadata first")),
+        };
+        let scope = parse_scope(metadata.scope);
+        let sources = self.sources(metadata.sources)?;
+        let options = parse_options(metadata.options)?;
+
+        let mut minidump_file = tempfile::Builder::new();
+        minidump_file.prefix("minidump").suffix(".dmp");
+        let minidump_file = match self.service.config().cache_dir("tmp") {
+            Some(tmp_dir) => minidump_file.tempfile_in(tmp_dir),
+            None => minidump_file.tempfile(),
+        }?;
+        let (file, temp_path) = minidump_file.into_parts();
+        let mut file = File::from_std(file);
+
+        let chunks = stream.map(|message| match message?.chunk {
+            Some(Chunk::Data(data)) => Ok(data),
+            _ => Err(Status::invalid_argument("expected minidump data")),
+        });
+        let spooled = self
+            .service
+            .spool_minidump(&scope, Some(sources.clone()), chunks, &mut file)
+            .await
+            .map_err(|err| match err {
+                MinidumpUploadError::TooLarge(_) => Status::resource_exhausted(err.to_string()),
+                MinidumpUploadError::Stream(ref source) => match source.downcast_ref::<Status>() {
+                    Some(status) => status.clone(),
+                    None => Status::aborted(err.to_string()),
+                },
+                MinidumpUploadError::Io(err) => err.into(),
+            })?;
+
+        let request_id = self
+            .service
+            .process_minidump(scope, temp_path, sources, options)
+            .map_err(max_requests_status)?;
+        spooled.keep_prefetching();
+
+        self.respond(request_id, metadata.timeout).await
+    }
+
+    async fn get_response(
+        &self,
+        request: Request<proto::GetResponseRequest>,
+    ) -> Result<Response<proto::SymbolicationResponse>, Status> {
+        let request = request.into_inner();
+        let uuid = Uuid::parse_str(&request.request_id)
+            .map_err(|_| Status::invalid_argument("invalid request ID"))?;
+
+        self.respond(RequestId::new(uuid), request.timeout).await
+    }
+}
+
+fn max_requests_status(err: MaxRequestsError) -> Status {
+    Status::unavailable(err.to_string())
+}
+
+fn parse_scope(scope: String) -> Scope {
+    match scope.as_str() {
+        "" | "global" => Scope::Global,
+        _ => Scope::Scoped(scope),
+    }
+}
+
+fn parse_options(options: Option<String>) -> Result<RequestOptions, Status> {
+    match options {
+        Some(options) => parse_json(&options, "options"),
+        None => Ok(RequestOptions::default()),
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(json: &str, field: &str) -> Result<T, Status> {
+    serde_json::from_str(json).map_err(|err| Status::invalid_argument(format!("{field}: {err}")))
+}
+
+/// Returns the name of an enum value, as it is serialized in the JSON API.
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// Parses an enum value from the name it has in the JSON API.
+fn parse_name<T: DeserializeOwned>(name: String, field: &str) -> Result<T, Status> {
+    serde_json::from_value(serde_json::Value::String(name))
+        .map_err(|err| Status::invalid_argument(format!("{field}: {err}")))
+}
+
+fn parse_optional_name<T: DeserializeOwned>(
+    name: Option<String>,
+    field: &str,
+) -> Result<Option<T>, Status> {
+    name.map(|name| parse_name(name, field)).transpose()
+}
+
+impl TryFrom<SymbolicationResponse> for proto::SymbolicationResponse {
+    type Error = Status;
+
+    fn try_from(response: SymbolicationResponse) -> Result<Self, Status> {
+        use proto::symbolication_response::Status as S;
+
+        let status = match response {
+            SymbolicationResponse::Pending {
+                request_id,
+                retry_after,
+                affinity,
+                ..
+            } => S::Pending(proto::Pending {
+                request_id: request_id.to_string(),
+                retry_after: retry_after as u64,
+                affinity,
+            }),
+            SymbolicationResponse::Completed(completed) => match *completed {
+                CompletedResponse::NativeSymbolication(completed) => S::Completed(completed.into()),
+                _ => {
+                    return Err(Status::failed_precondition(
+                        "only native symbolication responses are available via gRPC",
+                    ))
+                }
+            },
+            SymbolicationResponse::Failed { message } => S::Failed(proto::Failed { message }),
+            SymbolicationResponse::Timeout => S::Timeout(proto::Timeout {}),
+            SymbolicationResponse::Cancelled => S::Cancelled(proto::Cancelled {}),
+            SymbolicationResponse::InternalError { reference_id } => {
+                S::InternalError(proto::InternalError { reference_id })
+            }
+        };
+
+        Ok(Self {
+            status: Some(status),
+        })
+    }
+}
+
+impl From<CompletedSymbolicationResponse> for proto::CompletedSymbolicationResponse {
+    fn from(response: CompletedSymbolicationResponse) -> Self {
+        Self {
+            timestamp: response.timestamp.map(|timestamp| timestamp.timestamp()),
+            signal: response.signal.map(|signal| signal.0),
+            system_info: response.system_info.map(|info| proto::SystemInfo {
+                os_name: info.os_name,
+                os_version: info.os_version,
+                os_build: info.os_build,
+                cpu_arch: name(&info.cpu_arch),
+                device_model: info.device_model,
+            }),
+            crashed: response.crashed,
+            crash_reason: response.crash_reason,
+            crash_details: response.crash_details,
+            assertion: response.assertion,
+            partial: response.partial.as_ref().map(name),
+            truncated: response.truncated.map(|truncated| proto::Truncation {
+                threads: truncated.threads as u64,
+                modules: truncated.modules as u64,
+                memory_regions: truncated.memory_regions as u64,
+            }),
+            stacktraces: response.stacktraces.into_iter().map(From::from).collect(),
+            modules: response.modules.into_iter().map(From::from).collect(),
+        }
+    }
+}
+
+impl From<CompleteStacktrace> for proto::CompleteStacktrace {
+    fn from(stacktrace: CompleteStacktrace) -> Self {
+        Self {
+            thread_id: stacktrace.thread_id,
+            thread_name: stacktrace.thread_name,
+            is_requesting: stacktrace.is_requesting,
+            registers: stacktrace
+                .registers
+                .into_iter()
+                .map(|(register, value)| (register, value.0))
+                .collect(),
+            frames: stacktrace.frames.into_iter().map(From::from).collect(),
+        }
+    }
+}
+
+impl From<SymbolicatedFrame> for proto::SymbolicatedFrame {
+    fn from(frame: SymbolicatedFrame) -> Self {
+        Self {
+            status: name(&frame.status),
+            original_index: frame.original_index.map(|index| index as u64),
+            frame: Some(frame.raw.into()),
+        }
+    }
+}
+
+impl From<RawFrame> for proto::Frame {
+    fn from(frame: RawFrame) -> Self {
+        Self {
+            addr_mode: frame.addr_mode.to_string(),
+            instruction_addr: frame.instruction_addr.0,
+            adjust_instruction_addr: frame.adjust_instruction_addr,
+            function_id: frame.function_id.map(|id| id.0),
+            package: frame.package,
+            lang: frame.lang.as_ref().map(name),
+            symbol: frame.symbol,
+            sym_addr: frame.sym_addr.map(|addr| addr.0),
+            sym_size: frame.sym_size,
+            function: frame.function,
+            filename: frame.filename,
+            abs_path: frame.abs_path,
+            lineno: frame.lineno,
+            pre_context: frame.pre_context,
+            context_line: frame.context_line,
+            post_context: frame.post_context,
+            source_link: frame.source_link,
+            in_app: frame.in_app,
+            package_kind: frame.package_kind.as_ref().map(name),
+            trust: name(&frame.trust),
+        }
+    }
+}
+
+impl TryFrom<proto::Frame> for RawFrame {
+    type Error = Status;
+
+    fn try_from(frame: proto::Frame) -> Result<Self, Status> {
+        let addr_mode = match frame.addr_mode.as_str() {
+            "" => Default::default(),
+            addr_mode => addr_mode
+                .parse()
+                .map_err(|_| Status::invalid_argument("addr_mode: invalid address mode"))?,
+        };
+        let trust = match frame.trust.as_str() {
+            "" => Default::default(),
+            _ => parse_name(frame.trust, "trust")?,
+        };
+
+        Ok(Self {
+            addr_mode,
+            instruction_addr: HexValue(frame.instruction_addr),
+            adjust_instruction_addr: frame.adjust_instruction_addr,
+            function_id: frame.function_id.map(HexValue),
+            package: frame.package,
+            lang: parse_optional_name(frame.lang, "lang")?,
+            symbol: frame.symbol,
+            sym_addr: frame.sym_addr.map(HexValue),
+            sym_size: frame.sym_size,
+            function: frame.function,
+            filename: frame.filename,
+            abs_path: frame.abs_path,
+            lineno: frame.lineno,
+            pre_context: frame.pre_context,
+            context_line: frame.context_line,
+            post_context: frame.post_context,
+            source_link: frame.source_link,
+            in_app: frame.in_app,
+            package_kind: parse_optional_name(frame.package_kind, "package_kind")?,
+            trust,
+        })
+    }
+}
+
+impl TryFrom<proto::RawStacktrace> for RawStacktrace {
+    type Error = Status;
+
+    fn try_from(stacktrace: proto::RawStacktrace) -> Result<Self, Status> {
+        Ok(Self {
+            thread_id: stacktrace.thread_id,
+            thread_name: stacktrace.thread_name,
+            is_requesting: stacktrace.is_requesting,
+            registers: stacktrace
+                .registers
+                .into_iter()
+                .map(|(register, value)| (register, HexValue(value)))
+                .collect(),
+            frames: stacktrace
+                .frames
+                .into_iter()
+                .map(RawFrame::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<RawObjectInfo> for proto::ObjectInfo {
+    fn from(info: RawObjectInfo) -> Self {
+        Self {
+            r#type: name(&info.ty),
+            code_id: info.code_id,
+            code_file: info.code_file,
+            debug_id: info.debug_id,
+            debug_file: info.debug_file,
+            debug_checksum: info.debug_checksum,
+            image_addr: info.image_addr.0,
+            slide: info.slide.map(|slide| slide.0),
+            image_size: info.image_size,
+        }
+    }
+}
+
+impl TryFrom<proto::ObjectInfo> for RawObjectInfo {
+    type Error = Status;
+
+    fn try_from(info: proto::ObjectInfo) -> Result<Self, Status> {
+        Ok(Self {
+            ty: parse_name(info.r#type, "type")?,
+            code_id: info.code_id,
+            code_file: info.code_file,
+            debug_id: info.debug_id,
+            debug_file: info.debug_file,
+            debug_checksum: info.debug_checksum,
+            image_addr: HexValue(info.image_addr),
+            slide: info.slide.map(HexValue),
+            image_size: info.image_size,
+        })
+    }
+}
+
+impl From<CompleteObjectInfo> for proto::CompleteObjectInfo {
+    fn from(info: CompleteObjectInfo) -> Self {
+        Self {
+            debug_status: info.debug_status.name().to_owned(),
+            unwind_status: info.unwind_status.map(|status| status.name().to_owned()),
+            features: Some(proto::ObjectFeatures {
+                has_debug_info: info.features.has_debug_info,
+                has_unwind_info: info.features.has_unwind_info,
+                has_symbols: info.features.has_symbols,
+                has_sources: info.features.has_sources,
+            }),
+            arch: name(&info.arch),
+            object: Some(info.raw.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use symbolicator_service::types::FrameStatus;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::test;
+
+    fn grpc_service() -> GrpcService {
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+        GrpcService { service }
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = RawFrame {
+            addr_mode: "rel:1".parse().unwrap(),
+            instruction_addr: HexValue(0x1000),
+            function: Some("main".into()),
+            lang: Some(symbolic::common::Language::Rust),
+            trust: symbolicator_service::types::FrameTrust::Cfi,
+            ..Default::default()
+        };
+
+        let encoded = proto::Frame::from(frame.clone());
+        assert_eq!(encoded.addr_mode, "rel:1");
+        assert_eq!(encoded.trust, "cfi");
+        assert_eq!(RawFrame::try_from(encoded).unwrap(), frame);
+
+        let invalid = proto::Frame {
+            trust: "sideways".into(),
+            ..Default::default()
+        };
+        let status = RawFrame::try_from(invalid).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_stacktraces() {
+        test::setup();
+
+        let service = grpc_service();
+        let request = proto::SymbolicateStacktracesRequest {
+            sources: Some("[]".into()),
+            stacktraces: vec![proto::RawStacktrace {
+                frames: vec![proto::Frame {
+                    instruction_addr: 0x1_0000_0fa0,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            modules: vec![proto::ObjectInfo {
+                r#type: "macho".into(),
+                debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".into()),
+                image_addr: 0x1_0000_0000,
+                image_size: Some(4096),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let response = service
+            .symbolicate_stacktraces(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        let Some(proto::symbolication_response::Status::Completed(completed)) = response.status
+        else {
+            panic!("Not a complete response: {response:?}");
+        };
+
+        let frame = &completed.stacktraces[0].frames[0];
+        assert_eq!(frame.status, name(&FrameStatus::Missing));
+        assert_eq!(completed.modules[0].debug_status, "missing");
+    }
+
+    #[tokio::test]
+    async fn test_process_minidump_too_large() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let mut config = Config::default();
+        config.minidump.max_size = Some(1024);
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let messages = [
+            Chunk::Metadata(proto::MinidumpMetadata {
+                sources: Some("[]".into()),
+                ..Default::default()
+            }),
+            Chunk::Data(test::read_fixture("windows.dmp")),
+        ];
+        // Every message of the stream is framed with a compression flag and its length.
+        let mut body = vec![];
+        for chunk in messages {
+            let message = proto::ProcessMinidumpRequest { chunk: Some(chunk) }.encode_to_vec();
+            body.push(0);
+            body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+            body.extend_from_slice(&message);
+        }
+
+        let request = axum::http::Request::post("/symbolicator.Symbolicator/ProcessMinidump")
+            .header("content-type", "application/grpc")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let response = create_service(service).oneshot(request).await.unwrap();
+
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn test_get_unknown_response() {
+        test::setup();
+
+        let request = proto::GetResponseRequest {
+            request_id: Uuid::new_v4().to_string(),
+            timeout: None,
+        };
+        let status = grpc_service()
+            .get_response(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
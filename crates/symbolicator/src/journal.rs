@@ -0,0 +1,372 @@
+//! Journaling of accepted requests, so that they survive a crash of the process.
+//!
+//! If a `journal` directory is configured, an entry is written for every accepted minidump and
+//! stacktrace request before it starts processing, and removed once its response has been sent
+//! and stored. Entries which are left behind when the process dies are read on startup, and
+//! their requests are restarted under their original request ID.
+//!
+//! Every restart is counted in the entry before the request starts again. Requests which were
+//! restarted [`MAX_RESTARTS`] times without completing are dropped, as they likely crash the
+//! process themselves.
+//!
+//! In contrast to recordings, entries contain the sources of the request, which may include
+//! credentials. If `encryption` is configured, the credentials are encrypted in the entries like
+//! in the stored sources. Either way, entries are only readable by the user running symbolicator.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use symbolicator_service::metric;
+use symbolicator_service::utils::encryption::MasterKey;
+use symbolicator_sources::SourceConfig;
+
+use crate::recording::RecordedPayload;
+use crate::service::{RequestId, RequestOptions, Scope};
+use crate::stored_sources::CREDENTIAL_FIELDS;
+
+/// The file extension of the journal entries.
+const ENTRY_EXTENSION: &str = "json";
+/// The file extension of the minidump referenced by an entry.
+const MINIDUMP_EXTENSION: &str = "dmp";
+
+/// How often a request is restarted from the journal before its entry is dropped.
+pub const MAX_RESTARTS: u32 = 3;
+
+/// An accepted request, with everything needed to restart it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournaledRequest {
+    /// The ID under which the request was accepted.
+    pub request_id: RequestId,
+    /// The scope of the request.
+    pub scope: Scope,
+    /// The sources of the request.
+    pub sources: Arc<[SourceConfig]>,
+    /// The options of the request.
+    pub options: RequestOptions,
+    /// The payload of the request, where minidumps are stored next to the entry.
+    pub payload: RecordedPayload,
+    /// How often the request was restarted from the journal.
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+/// The directory journaling the requests which are being processed.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+    /// The key encrypting the credentials in the entries, if any.
+    key: Option<Arc<MasterKey>>,
+}
+
+impl Journal {
+    /// Creates the journal in the given directory.
+    ///
+    /// With a `key`, the credentials of the journaled sources are encrypted.
+    pub fn new(dir: PathBuf, key: Option<Arc<MasterKey>>) -> Self {
+        Self { dir, key }
+    }
+
+    /// Writes the entry of an accepted request, along with its `minidump`, if any.
+    ///
+    /// This happens synchronously, so that the request is journaled before it is acknowledged.
+    /// The minidump is hard-linked into the journal if possible, and copied otherwise, which both
+    /// keep the permissions of the temporary file it was uploaded to.
+    pub fn write(
+        &self,
+        request: &JournaledRequest,
+        minidump: Option<&Path>,
+    ) -> Result<JournalEntry> {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&self.dir)?;
+
+        let entry = JournalEntry {
+            path: self.dir.join(request.request_id.to_string()),
+            has_minidump: minidump.is_some(),
+        };
+
+        if let Some(minidump) = minidump {
+            let destination = entry.minidump_path();
+            if std::fs::hard_link(minidump, &destination).is_err() {
+                std::fs::copy(minidump, &destination)?;
+            }
+        }
+
+        self.write_entry(request, &entry)?;
+
+        metric!(counter("requests.journaled") += 1);
+        Ok(entry)
+    }
+
+    /// Counts a restart of the `request` in its `entry`, before the request is started again.
+    pub fn record_restart(
+        &self,
+        request: &mut JournaledRequest,
+        entry: &JournalEntry,
+    ) -> Result<()> {
+        request.restarts += 1;
+        self.write_entry(request, entry)
+    }
+
+    /// Writes the `request` to its `entry`, encrypting the credentials of its sources.
+    fn write_entry(&self, request: &JournaledRequest, entry: &JournalEntry) -> Result<()> {
+        // Write to a temporary file first, so that a recovery never picks up a partial entry.
+        let mut value = serde_json::to_value(request)?;
+        if let Some(ref key) = self.key {
+            key.encrypt_json_fields(&mut value, CREDENTIAL_FIELDS)?;
+        }
+        let temp_path = entry.path.with_extension("tmp");
+        write_private(&temp_path, &serde_json::to_vec(&value)?)?;
+        std::fs::rename(&temp_path, entry.path.with_extension(ENTRY_EXTENSION))?;
+        Ok(())
+    }
+
+    /// Reads the entries of all requests which did not complete before the process died.
+    ///
+    /// Entries which cannot be read are logged and skipped, and entries of requests which were
+    /// already restarted [`MAX_RESTARTS`] times are logged and removed.
+    pub fn recover(&self) -> Vec<(JournaledRequest, JournalEntry)> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return vec![],
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Failed to read the request journal");
+                return vec![];
+            }
+        };
+
+        let mut recovered = vec![];
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != ENTRY_EXTENSION) {
+                continue;
+            }
+
+            let request = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| self.parse_entry(&data));
+            match request {
+                Ok(request) => {
+                    let entry = JournalEntry {
+                        has_minidump: matches!(request.payload, RecordedPayload::Minidump),
+                        path: path.with_extension(""),
+                    };
+                    if request.restarts >= MAX_RESTARTS {
+                        tracing::error!(
+                            "Dropping journaled request {} after {} restarts",
+                            request.request_id,
+                            request.restarts,
+                        );
+                        metric!(counter("requests.journal_dropped") += 1);
+                        entry.discard();
+                        continue;
+                    }
+                    recovered.push((request, entry));
+                }
+                Err(err) => {
+                    let stderr: &dyn std::error::Error = &*err;
+                    tracing::error!(stderr, "Failed to recover {}", path.display());
+                }
+            }
+        }
+
+        metric!(counter("requests.recovered") += recovered.len() as i64);
+        recovered
+    }
+
+    /// Parses a journal entry, decrypting the credentials of its sources.
+    fn parse_entry(&self, data: &[u8]) -> Result<JournaledRequest> {
+        let mut value: serde_json::Value = serde_json::from_slice(data)?;
+        if let Some(ref key) = self.key {
+            key.decrypt_json(&mut value)?;
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Writes `data` to a file at `path`, which is only readable by its owner.
+fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)
+}
+
+/// The files of a journaled request, which are removed once the request completes.
+#[derive(Debug)]
+pub struct JournalEntry {
+    /// The path of the entry, without an extension.
+    path: PathBuf,
+    has_minidump: bool,
+}
+
+impl JournalEntry {
+    /// The path of the minidump stored with the entry.
+    pub fn minidump_path(&self) -> PathBuf {
+        self.path.with_extension(MINIDUMP_EXTENSION)
+    }
+
+    /// Removes the entry synchronously, for requests which were rejected.
+    pub fn discard(self) {
+        std::fs::remove_file(self.path.with_extension(ENTRY_EXTENSION)).ok();
+        if self.has_minidump {
+            std::fs::remove_file(self.minidump_path()).ok();
+        }
+    }
+
+    /// Removes the entry of a completed request.
+    pub async fn complete(self) {
+        if let Err(err) = tokio::fs::remove_file(self.path.with_extension(ENTRY_EXTENSION)).await {
+            let stderr: &dyn std::error::Error = &err;
+            tracing::error!(stderr, "Failed to remove journal entry");
+        }
+        if self.has_minidump {
+            tokio::fs::remove_file(self.minidump_path()).await.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::service::{RequestService, SymbolicationResponse};
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_recover_minidump() {
+        test::setup();
+
+        // An entry left behind by a previous process, which died while processing it.
+        let dir = test::tempdir();
+        let journal = Journal::new(dir.path().to_owned(), None);
+        let request = JournaledRequest {
+            request_id: RequestId::new(uuid::Uuid::new_v4()),
+            scope: Scope::Global,
+            sources: Arc::new([]),
+            options: RequestOptions::default(),
+            payload: RecordedPayload::Minidump,
+            restarts: 0,
+        };
+        let minidump = test::fixture("windows.dmp");
+        let entry = journal.write(&request, Some(&minidump)).unwrap();
+        let entry_path = entry.path.with_extension(ENTRY_EXTENSION);
+        assert!(entry_path.exists());
+        assert!(entry.minidump_path().exists());
+
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            journal: Some(dir.path().to_owned()),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let response = loop {
+            match service.get_response(request.request_id, None).await {
+                Some(SymbolicationResponse::Pending { .. }) => continue,
+                response => break response,
+            }
+        };
+        assert!(matches!(
+            response,
+            Some(SymbolicationResponse::Completed(_))
+        ));
+
+        // The entry is removed after the response has been sent.
+        for _ in 0..100 {
+            if !entry_path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!entry_path.exists());
+        assert!(!entry.minidump_path().exists());
+        // The fixture itself was only linked or copied into the journal.
+        assert!(minidump.exists());
+    }
+
+    #[test]
+    fn test_encrypted_entry() {
+        let dir = test::tempdir();
+        let key = MasterKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap();
+        let journal = Journal::new(dir.path().to_owned(), Some(Arc::new(key)));
+
+        let source: SourceConfig = serde_json::from_value(serde_json::json!({
+            "type": "sentry",
+            "id": "sentry",
+            "url": "https://sentry.example.com/",
+            "token": "hunter2",
+        }))
+        .unwrap();
+        let request = JournaledRequest {
+            request_id: RequestId::new(uuid::Uuid::new_v4()),
+            scope: Scope::Global,
+            sources: Arc::from(vec![source]),
+            options: RequestOptions::default(),
+            payload: RecordedPayload::Minidump,
+            restarts: 0,
+        };
+        let entry = journal
+            .write(&request, Some(&test::fixture("windows.dmp")))
+            .unwrap();
+
+        let entry_path = entry.path.with_extension(ENTRY_EXTENSION);
+        let persisted = std::fs::read_to_string(&entry_path).unwrap();
+        assert!(persisted.contains("https://sentry.example.com/"));
+        assert!(!persisted.contains("hunter2"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&entry_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let recovered = journal.recover();
+        assert_eq!(recovered.len(), 1);
+        match &recovered[0].0.sources[0] {
+            SourceConfig::Sentry(source) => assert_eq!(source.token, "hunter2"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_drop_after_max_restarts() {
+        let dir = test::tempdir();
+        let journal = Journal::new(dir.path().to_owned(), None);
+
+        let mut request = JournaledRequest {
+            request_id: RequestId::new(uuid::Uuid::new_v4()),
+            scope: Scope::Global,
+            sources: Arc::new([]),
+            options: RequestOptions::default(),
+            payload: RecordedPayload::Minidump,
+            restarts: 0,
+        };
+        let entry = journal
+            .write(&request, Some(&test::fixture("windows.dmp")))
+            .unwrap();
+
+        for restarts in 1..=MAX_RESTARTS {
+            journal.record_restart(&mut request, &entry).unwrap();
+
+            let recovered = journal.recover();
+            if restarts < MAX_RESTARTS {
+                assert_eq!(recovered.len(), 1);
+                assert_eq!(recovered[0].0.restarts, restarts);
+            } else {
+                assert!(recovered.is_empty());
+            }
+        }
+
+        assert!(!entry.path.with_extension(ENTRY_EXTENSION).exists());
+        assert!(!entry.minidump_path().exists());
+    }
+}
@@ -0,0 +1,331 @@
+//! Generating load against a running Symbolicator, for capacity and soak testing.
+//!
+//! `symbolicator load-test` sends requests to a target instance at a fixed rate, and reports the
+//! latency percentiles and error rate of the responses. The requests are either taken from a
+//! directory of [recordings](crate::recording), which are replayed in a loop, or synthesized from
+//! a debug file, with stack traces pointing into its symbols.
+//!
+//! Requests are sent without retries, so that every rejection of an overloaded target shows up
+//! in the error rate.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use structopt::StructOpt;
+use symbolic::common::ByteView;
+use symbolic::debuginfo::{Archive, FileFormat};
+use tokio::task::JoinSet;
+
+use symbolicator_client::{self as client, Client, ClientOptions, Url};
+use symbolicator_service::types::RawFrame;
+use symbolicator_service::utils::hex::HexValue;
+use symbolicator_sources::ObjectType;
+
+use crate::recording::{self, Record, RecordedPayload};
+use crate::service::{RawObjectInfo, RawStacktrace, Scope};
+
+/// The address at which the module of synthesized stack traces is loaded.
+const SYNTHESIZED_IMAGE_ADDR: u64 = 0x10000;
+/// The maximum number of frames of synthesized stack traces.
+const SYNTHESIZED_FRAMES: usize = 32;
+
+/// Command line options of the load test.
+#[derive(Debug, StructOpt)]
+pub struct LoadTestOptions {
+    /// The URL of the Symbolicator to send the requests to.
+    #[structopt(value_name = "URL")]
+    pub target: Url,
+
+    /// A directory of recorded requests, which are replayed in a loop.
+    #[structopt(
+        long = "records",
+        value_name = "DIR",
+        required_unless = "object",
+        conflicts_with = "object"
+    )]
+    pub records: Option<PathBuf>,
+
+    /// A debug file to synthesize stack traces against.
+    ///
+    /// The target needs to be able to find the file on its configured sources.
+    #[structopt(long = "object", value_name = "FILE")]
+    pub object: Option<PathBuf>,
+
+    /// The number of requests sent per second.
+    #[structopt(long = "rate", default_value = "10")]
+    pub rate: f64,
+
+    /// For how many seconds requests are sent.
+    #[structopt(long = "duration", default_value = "60")]
+    pub duration: u64,
+
+    /// Fail if the fraction of failed requests exceeds this, from `0` to `1`.
+    #[structopt(long = "max-error-rate")]
+    pub max_error_rate: Option<f64>,
+}
+
+/// A request sent during the load test.
+#[derive(Debug)]
+enum LoadRequest {
+    Stacktraces(Scope, client::SymbolicateStacktraces),
+    Minidump(Scope, Vec<u8>, client::RequestOptions),
+}
+
+impl LoadRequest {
+    async fn send(&self, client: &Client) -> Result<client::CompletedResponse, client::Error> {
+        match self {
+            LoadRequest::Stacktraces(scope, request) => client.symbolicate(scope, request).await,
+            LoadRequest::Minidump(scope, minidump, options) => {
+                client
+                    .upload_minidump(scope, minidump.clone(), None, options)
+                    .await
+            }
+        }
+    }
+}
+
+/// Reads the recorded requests in `dir`.
+///
+/// The requests are sent without sources, so the target uses the ones it is configured with.
+async fn read_records(dir: &Path) -> Result<Vec<LoadRequest>> {
+    let mut requests = vec![];
+    for path in recording::list_records(dir).await? {
+        let record: Record = serde_json::from_slice(&tokio::fs::read(&path).await?)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        // The options of the client are the same as those of the service on the wire.
+        let options = serde_json::from_value(serde_json::to_value(&record.options)?)?;
+
+        let request = match record.payload {
+            RecordedPayload::Stacktraces {
+                signal,
+                stacktraces,
+                modules,
+            } => LoadRequest::Stacktraces(
+                record.scope,
+                client::SymbolicateStacktraces {
+                    signal,
+                    sources: None,
+                    stacktraces,
+                    modules,
+                    options,
+                },
+            ),
+            RecordedPayload::Minidump => {
+                let minidump = tokio::fs::read(recording::minidump_path(&path)).await?;
+                LoadRequest::Minidump(record.scope, minidump, options)
+            }
+        };
+        requests.push(request);
+    }
+
+    if requests.is_empty() {
+        bail!("no records found in {}", dir.display());
+    }
+    Ok(requests)
+}
+
+/// Synthesizes a request with a stack trace through the symbols of the debug file at `path`.
+fn synthesize_request(path: &Path) -> Result<LoadRequest> {
+    let data = ByteView::open(path)?;
+    let archive = Archive::parse(&data)?;
+    let object = archive
+        .objects()
+        .next()
+        .context("debug file contains no objects")??;
+
+    let ty = match object.file_format() {
+        FileFormat::Elf => ObjectType::Elf,
+        FileFormat::MachO => ObjectType::Macho,
+        FileFormat::Pe | FileFormat::Pdb => ObjectType::Pe,
+        FileFormat::Wasm => ObjectType::Wasm,
+        format => bail!("unsupported debug file format {format}"),
+    };
+
+    let symbols: Vec<_> = object.symbols().collect();
+    if symbols.is_empty() {
+        bail!("debug file contains no symbols");
+    }
+    // Spread the frames over the symbols, so that lookups are not all served by the same function.
+    let step = (symbols.len() / SYNTHESIZED_FRAMES).max(1);
+    let frames = symbols
+        .iter()
+        .step_by(step)
+        .take(SYNTHESIZED_FRAMES)
+        .map(|symbol| {
+            let offset = symbol.address.saturating_sub(object.load_address());
+            RawFrame {
+                instruction_addr: HexValue(SYNTHESIZED_IMAGE_ADDR + offset),
+                ..RawFrame::default()
+            }
+        })
+        .collect();
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    let module = RawObjectInfo {
+        ty,
+        code_id: object.code_id().map(|id| id.to_string()),
+        code_file: file_name.clone(),
+        debug_id: Some(object.debug_id().to_string()),
+        debug_file: file_name,
+        debug_checksum: None,
+        image_addr: HexValue(SYNTHESIZED_IMAGE_ADDR),
+        slide: None,
+        image_size: None,
+    };
+
+    let request = client::SymbolicateStacktraces {
+        stacktraces: vec![RawStacktrace {
+            is_requesting: Some(true),
+            frames,
+            ..RawStacktrace::default()
+        }],
+        modules: vec![module],
+        ..client::SymbolicateStacktraces::default()
+    };
+    Ok(LoadRequest::Stacktraces(Scope::Global, request))
+}
+
+/// The outcome of a load test.
+#[derive(Debug, Default)]
+pub struct LoadTestReport {
+    /// The latencies of the successful requests, in ascending order.
+    latencies: Vec<Duration>,
+    /// The number of failed requests, by the kind of error.
+    errors: BTreeMap<String, usize>,
+}
+
+impl LoadTestReport {
+    /// The total number of requests sent.
+    pub fn total(&self) -> usize {
+        self.latencies.len() + self.errors.values().sum::<usize>()
+    }
+
+    /// The fraction of requests which failed.
+    pub fn error_rate(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => (total - self.latencies.len()) as f64 / total as f64,
+        }
+    }
+
+    /// The latency below which the given fraction of successful requests completed.
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let index = (last as f64 * fraction).round() as usize;
+        self.latencies.get(index).copied()
+    }
+}
+
+impl fmt::Display for LoadTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} requests, {:.2}% failed",
+            self.total(),
+            self.error_rate() * 100.0
+        )?;
+        for (name, fraction) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+            if let Some(latency) = self.percentile(fraction) {
+                writeln!(f, "{name}: {latency:?}")?;
+            }
+        }
+        for (kind, count) in &self.errors {
+            writeln!(f, "{kind}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Classifies an error of the client for the report.
+fn error_kind(error: &client::Error) -> String {
+    match error {
+        client::Error::Http(_) => "http error".into(),
+        client::Error::Status(status) => format!("status {}", status.as_u16()),
+        client::Error::NotFound(_) => "not found".into(),
+        client::Error::Failed(_) => "failed".into(),
+        client::Error::Timeout => "timeout".into(),
+        client::Error::InternalError { .. } => "internal error".into(),
+    }
+}
+
+/// Runs the load test described by the `options`.
+pub async fn run(options: &LoadTestOptions) -> Result<LoadTestReport> {
+    if options.rate.is_nan() || options.rate <= 0.0 {
+        bail!("the rate must be positive");
+    }
+    let requests = match (&options.records, &options.object) {
+        (Some(dir), _) => read_records(dir).await?,
+        (None, Some(object)) => vec![synthesize_request(object)?],
+        (None, None) => bail!("either records or an object are required"),
+    };
+
+    let client_options = ClientOptions {
+        max_retries: 0,
+        ..ClientOptions::default()
+    };
+    let client = Client::with_options(options.target.clone(), client_options);
+    let requests: Arc<[LoadRequest]> = requests.into();
+
+    let deadline = Instant::now() + Duration::from_secs(options.duration);
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / options.rate));
+    let mut tasks = JoinSet::new();
+    for index in 0.. {
+        interval.tick().await;
+        if Instant::now() >= deadline {
+            break;
+        }
+
+        let client = client.clone();
+        let requests = requests.clone();
+        tasks.spawn(async move {
+            let start = Instant::now();
+            let result = requests[index % requests.len()].send(&client).await;
+            (start.elapsed(), result.map(|_| ()))
+        });
+    }
+
+    let mut report = LoadTestReport::default();
+    while let Some(task) = tasks.join_next().await {
+        match task? {
+            (latency, Ok(())) => report.latencies.push(latency),
+            (_, Err(err)) => *report.errors.entry(error_kind(&err)).or_default() += 1,
+        }
+    }
+    report.latencies.sort();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_synthesized_load() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let options = LoadTestOptions {
+            target: server.url("/"),
+            records: None,
+            object: Some(test::fixture(
+                "symbols/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb",
+            )),
+            rate: 20.0,
+            duration: 1,
+            max_error_rate: None,
+        };
+
+        let report = run(&options).await.unwrap();
+        assert!(report.total() > 0);
+        assert_eq!(report.error_rate(), 0.0);
+        assert!(report.percentile(0.5) <= report.percentile(1.0));
+    }
+}
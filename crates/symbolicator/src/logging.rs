@@ -1,10 +1,10 @@
 use std::env;
 
 use tracing::level_filters::LevelFilter;
-use tracing_subscriber::fmt::fmt;
 use tracing_subscriber::fmt::time::UtcTime;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
 
 use crate::config::{Config, LogFormat};
 
@@ -47,36 +47,61 @@ pub fn init_logging(config: &Config) {
     let rust_log =
         env::var("RUST_LOG").unwrap_or_else(|_| get_rust_log(config.logging.level).to_string());
 
-    let subscriber = fmt()
+    let format = tracing_subscriber::fmt::layer()
         .with_timer(UtcTime::rfc_3339())
-        .with_target(true)
-        .with_env_filter(rust_log);
+        .with_target(true);
 
-    match (config.logging.format, console::user_attended()) {
-        (LogFormat::Auto, true) | (LogFormat::Pretty, _) => subscriber
-            .pretty()
-            .finish()
-            .with(sentry::integrations::tracing::layer())
-            .init(),
-        (LogFormat::Auto, false) | (LogFormat::Simplified, _) => subscriber
-            .compact()
-            .with_ansi(false)
-            .finish()
-            .with(sentry::integrations::tracing::layer())
-            .init(),
-        (LogFormat::Json, _) => subscriber
+    let format = match (config.logging.format, console::user_attended()) {
+        (LogFormat::Auto, true) | (LogFormat::Pretty, _) => format.pretty().boxed(),
+        (LogFormat::Auto, false) | (LogFormat::Simplified, _) => {
+            format.compact().with_ansi(false).boxed()
+        }
+        (LogFormat::Json, _) => format
             .json()
             .flatten_event(true)
             .with_current_span(true)
             .with_span_list(true)
             .with_file(true)
             .with_line_number(true)
-            .finish()
-            .with(sentry::integrations::tracing::layer())
-            .init(),
+            .boxed(),
+    };
+
+    // The filter only applies to logs and Sentry, since tokio-console relies on the trace-level
+    // instrumentation of the runtimes.
+    let logs = format
+        .and_then(sentry::integrations::tracing::layer())
+        .with_filter(EnvFilter::new(rust_log));
+
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(tokio_console_layer(config))
+        .init();
+
+    if config.logging.tokio_console && cfg!(not(feature = "tokio-console")) {
+        tracing::warn!(
+            "tokio-console is enabled, but symbolicator was built without support for it"
+        );
     }
 }
 
+/// Creates the layer serving the instrumentation of all runtimes to tokio-console, if enabled.
+///
+/// The server listens on `127.0.0.1:6669` by default, which can be changed with the
+/// `TOKIO_CONSOLE_BIND` environment variable.
+#[cfg(feature = "tokio-console")]
+fn tokio_console_layer(config: &Config) -> Option<console_subscriber::ConsoleLayer> {
+    config.logging.tokio_console.then(|| {
+        console_subscriber::ConsoleLayer::builder()
+            .with_default_env()
+            .spawn()
+    })
+}
+
+#[cfg(not(feature = "tokio-console"))]
+fn tokio_console_layer(_config: &Config) -> Option<tracing_subscriber::layer::Identity> {
+    None
+}
+
 /// Logs an error to the configured logger or `stderr` if not yet configured.
 pub fn ensure_log_error(error: &anyhow::Error) {
     if tracing::Level::ERROR <= tracing::level_filters::STATIC_MAX_LEVEL
@@ -21,24 +21,37 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 pub use symbolicator_service::{config, metric, utils};
 
+mod callbacks;
 mod cli;
+mod comparison;
 mod endpoints;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod journal;
+mod load_test;
 mod logging;
+mod queue;
+mod recording;
 mod server;
 mod service;
+mod stored_sources;
 
 #[cfg(test)]
 mod test {
-    use crate::config::Config;
+    use crate::config::{Config, ReservedIpsPolicy};
     use crate::service::RequestService;
     pub use symbolicator_test::*;
 
     use crate::endpoints;
 
+    /// The `admin_token` configured for [`server_with_default_service`].
+    pub const ADMIN_TOKEN: &str = "admin-secret";
+
     pub fn server_with_default_service() -> Server {
         let handle = tokio::runtime::Handle::current();
         let config = Config {
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            admin_token: Some(ADMIN_TOKEN.into()),
             ..Config::default()
         };
         let service = RequestService::create(config, handle.clone(), handle).unwrap();
@@ -0,0 +1,231 @@
+//! Scheduling of accepted requests by their priority.
+//!
+//! If `max_running_requests` is configured, only that many requests are symbolicated at the same
+//! time. Requests beyond that wait in the [`RequestQueue`] until a running request finishes, and
+//! the waiting request with the highest [`RequestPriority`] is started next. This keeps bulk
+//! reprocessing from holding up the requests of users who are waiting for their results.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+
+/// The priority of a request, which decides the order in which waiting requests are started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    /// Requests which nobody is waiting for, such as reprocessing.
+    Low,
+    /// Regular requests.
+    #[default]
+    Normal,
+    /// Requests which someone is actively waiting for.
+    High,
+}
+
+impl RequestPriority {
+    /// Returns the name of the priority, for metrics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestPriority::Low => "low",
+            RequestPriority::Normal => "normal",
+            RequestPriority::High => "high",
+        }
+    }
+}
+
+/// A request waiting to be started.
+#[derive(Debug)]
+struct Waiter {
+    priority: RequestPriority,
+    /// The position of the request in the queue, which orders requests of the same priority.
+    position: u64,
+    sender: oneshot::Sender<RunPermit>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The heap pops the greatest element, which is the earliest request of highest priority.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.position.cmp(&self.position))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    running: usize,
+    next_position: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+/// Limits the number of running requests, and starts waiting requests by priority.
+#[derive(Debug, Clone)]
+pub struct RequestQueue {
+    max_running: Option<usize>,
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl RequestQueue {
+    /// Creates a queue which runs up to `max_running` requests at the same time.
+    ///
+    /// Without a limit, requests never wait.
+    pub fn new(max_running: Option<usize>) -> Self {
+        Self {
+            max_running,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns the number of requests which are waiting to be started.
+    pub fn waiting(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Waits until a request of the given `priority` may run.
+    ///
+    /// The request keeps running for as long as the returned permit is held.
+    pub async fn acquire(&self, priority: RequestPriority) -> RunPermit {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            let has_capacity = self.max_running.map_or(true, |max| state.running < max);
+            if has_capacity && state.waiting.is_empty() {
+                state.running += 1;
+                return RunPermit {
+                    state: Some(self.state.clone()),
+                };
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let position = state.next_position;
+            state.next_position += 1;
+            state.waiting.push(Waiter {
+                priority,
+                position,
+                sender,
+            });
+            receiver
+        };
+
+        // The sender is only dropped along with the queue, in which case nothing is limited.
+        receiver.await.unwrap_or(RunPermit { state: None })
+    }
+}
+
+/// Allows a request to run, and passes its slot on to the next waiting request when dropped.
+#[derive(Debug)]
+pub struct RunPermit {
+    state: Option<Arc<Mutex<QueueState>>>,
+}
+
+impl Drop for RunPermit {
+    fn drop(&mut self) {
+        let Some(ref state) = self.state else {
+            return;
+        };
+
+        let mut locked = state.lock().unwrap();
+        while let Some(waiter) = locked.waiting.pop() {
+            let permit = RunPermit {
+                state: Some(state.clone()),
+            };
+            match waiter.sender.send(permit) {
+                Ok(()) => return,
+                // The waiting request was dropped, so its permit must not hand off again while
+                // the state is locked.
+                Err(mut permit) => permit.state = None,
+            }
+        }
+        locked.running -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unlimited() {
+        let queue = RequestQueue::new(None);
+        let _permits: Vec<_> =
+            futures::future::join_all((0..10).map(|_| queue.acquire(RequestPriority::Normal)))
+                .await;
+        assert_eq!(queue.waiting(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_priority_order() {
+        let queue = RequestQueue::new(Some(1));
+        let running = queue.acquire(RequestPriority::Low).await;
+
+        let started = Arc::new(Mutex::new(vec![]));
+        let mut tasks = vec![];
+        for (name, priority) in [
+            ("low", RequestPriority::Low),
+            ("normal", RequestPriority::Normal),
+            ("high", RequestPriority::High),
+            ("second normal", RequestPriority::Normal),
+        ] {
+            let queue = queue.clone();
+            let started = started.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await;
+                started.lock().unwrap().push(name);
+            }));
+            // Let the task enter the queue before the next one.
+            while queue.waiting() < tasks.len() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+
+        drop(running);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            *started.lock().unwrap(),
+            ["high", "normal", "second normal", "low"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter() {
+        let queue = RequestQueue::new(Some(1));
+        let running = queue.acquire(RequestPriority::Normal).await;
+
+        // A request which stops waiting does not take up a slot.
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(10),
+            queue.acquire(RequestPriority::High),
+        )
+        .await;
+        assert!(waiting.is_err());
+        assert_eq!(queue.waiting(), 1);
+
+        drop(running);
+        assert_eq!(queue.waiting(), 0);
+        let _permit =
+            tokio::time::timeout(Duration::from_secs(1), queue.acquire(RequestPriority::Low))
+                .await
+                .unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+//! Recording of symbolication requests, and replaying them offline.
+//!
+//! If `recording` is configured, a sample of the incoming requests is archived to disk together
+//! with their response, and any input files they reference. `symbolicator replay` runs the
+//! archived requests through a fresh service and compares the responses, which allows testing
+//! changes against real traffic.
+//!
+//! Records are sanitized: the sources of a request are not archived, as they may contain
+//! credentials. Requests are replayed against the sources configured for the replaying instance.
+//! Callback URLs and comparisons with other instances are dropped as well, so that replays do not
+//! reach out to other services.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use symbolicator_service::config::RecordingConfig;
+use symbolicator_service::metric;
+
+use crate::service::{
+    KernelSpace, RawObjectInfo, RawStacktrace, RequestOptions, RequestService, Scope, Signal,
+    StacktraceOrigin, SymbolicateStacktraces, SymbolicationResponse,
+};
+
+/// The file extension of the records.
+const RECORD_EXTENSION: &str = "json";
+/// The file extension of the minidump referenced by a record.
+const MINIDUMP_EXTENSION: &str = "dmp";
+
+/// The payload of a recorded request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedPayload {
+    /// A request to symbolicate stacktraces.
+    Stacktraces {
+        signal: Option<Signal>,
+        stacktraces: Vec<RawStacktrace>,
+        modules: Vec<RawObjectInfo>,
+    },
+    /// A request to process a minidump, which is stored next to the record.
+    Minidump,
+}
+
+/// A recorded request, along with the response it received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// The scope of the request.
+    pub scope: Scope,
+    /// The options of the request.
+    pub options: RequestOptions,
+    /// The payload of the request.
+    pub payload: RecordedPayload,
+    /// The serialized response to the request.
+    #[serde(default)]
+    pub response: serde_json::Value,
+}
+
+/// Archives a sample of symbolication requests to disk.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    config: RecordingConfig,
+}
+
+impl Recorder {
+    /// Creates a recorder from its configuration.
+    pub fn new(config: RecordingConfig) -> Self {
+        Self { config }
+    }
+
+    /// Decides whether to record a request, and starts the recording if so.
+    ///
+    /// The `payload` is only computed for sampled requests.
+    pub fn sample<P>(
+        &self,
+        scope: &Scope,
+        options: &RequestOptions,
+        payload: P,
+    ) -> Option<PendingRecord>
+    where
+        P: FnOnce() -> RecordedPayload,
+    {
+        let id = Uuid::new_v4();
+        // The low bits of a random UUID are uniformly distributed.
+        let (_, low) = id.as_u64_pair();
+        let fraction = (low & ((1 << 53) - 1)) as f64 / (1u64 << 53) as f64;
+        if fraction >= self.config.sample_rate {
+            return None;
+        }
+
+        Some(PendingRecord {
+            path: self.config.dir.join(id.simple().to_string()),
+            record: Record {
+                scope: scope.clone(),
+                options: RequestOptions {
+                    callback_url: None,
+                    compare_with: None,
+                    ..options.clone()
+                },
+                payload: payload(),
+                response: serde_json::Value::Null,
+            },
+        })
+    }
+}
+
+/// A request which is being recorded, and written to disk once it completes.
+#[derive(Debug)]
+pub struct PendingRecord {
+    /// The path of the record, without an extension.
+    path: PathBuf,
+    record: Record,
+}
+
+impl PendingRecord {
+    /// Returns a future archiving the minidump file referenced by the request.
+    ///
+    /// This needs to complete before processing the minidump, which consumes the file.
+    pub fn store_minidump(&self, minidump: &Path) -> impl Future<Output = ()> {
+        let source = minidump.to_owned();
+        let destination = self.path.with_extension(MINIDUMP_EXTENSION);
+
+        async move {
+            let copy = async {
+                tokio::fs::create_dir_all(destination.parent().unwrap_or(&destination)).await?;
+                tokio::fs::copy(&source, &destination).await
+            };
+            if let Err(err) = copy.await {
+                let stderr: &dyn std::error::Error = &err;
+                tracing::error!(stderr, "Failed to record minidump");
+            }
+        }
+    }
+
+    /// Completes the record with the `response`, and returns a future writing it to disk.
+    pub fn finish(mut self, response: &SymbolicationResponse) -> impl Future<Output = ()> {
+        self.record.response = serde_json::to_value(response).unwrap_or_default();
+
+        async move {
+            match self.write().await {
+                Ok(()) => metric!(counter("requests.recorded") += 1),
+                Err(err) => {
+                    let stderr: &dyn std::error::Error = &*err;
+                    tracing::error!(stderr, "Failed to record request");
+                }
+            }
+        }
+    }
+
+    async fn write(&self) -> Result<()> {
+        tokio::fs::create_dir_all(self.path.parent().unwrap_or(&self.path)).await?;
+        let record = serde_json::to_vec(&self.record)?;
+
+        // Write to a temporary file first, so that a replay never picks up a partial record.
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, record).await?;
+        tokio::fs::rename(&temp_path, self.path.with_extension(RECORD_EXTENSION)).await?;
+        Ok(())
+    }
+}
+
+/// The outcome of replaying recorded requests.
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    /// The number of requests whose response matched the recorded one.
+    pub identical: usize,
+    /// The records of requests whose response differed from the recorded one.
+    pub differing: Vec<PathBuf>,
+    /// The records which could not be replayed.
+    pub failed: Vec<PathBuf>,
+}
+
+/// Lists the paths of all records in `dir`, sorted by name.
+pub async fn list_records(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut records = vec![];
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("failed to read {}", dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path
+            .extension()
+            .map_or(false, |ext| ext == RECORD_EXTENSION)
+        {
+            records.push(path);
+        }
+    }
+    records.sort();
+    Ok(records)
+}
+
+/// Returns the path of the minidump archived along with the record at `path`.
+pub fn minidump_path(path: &Path) -> PathBuf {
+    path.with_extension(MINIDUMP_EXTENSION)
+}
+
+/// Replays all requests recorded in `dir` one after another, and compares their responses.
+pub async fn replay(service: &RequestService, dir: &Path) -> Result<ReplaySummary> {
+    let records = list_records(dir).await?;
+
+    let mut summary = ReplaySummary::default();
+    for path in records {
+        match replay_record(service, &path).await {
+            Ok(true) => summary.identical += 1,
+            Ok(false) => {
+                tracing::warn!("Response differs for {}", path.display());
+                summary.differing.push(path);
+            }
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(stderr, "Failed to replay {}", path.display());
+                summary.failed.push(path);
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Replays a single record, returning whether the response matches the recorded one.
+async fn replay_record(service: &RequestService, path: &Path) -> Result<bool> {
+    let record: Record = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let sources = service.config().default_sources();
+
+    let request_id = match record.payload {
+        RecordedPayload::Stacktraces {
+            signal,
+            stacktraces,
+            modules,
+        } => service.symbolicate_stacktraces(
+            SymbolicateStacktraces {
+                scope: record.scope,
+                signal,
+                sources,
+                origin: StacktraceOrigin::Symbolicate,
+                stacktraces,
+                modules: modules.into_iter().map(From::from).collect(),
+                kernel_space: KernelSpace::Unknown,
+            },
+            record.options,
+        )?,
+        RecordedPayload::Minidump => {
+            // Processing consumes the minidump, so it works on a copy of the archived one.
+            let minidump_file = tempfile::NamedTempFile::new()?.into_temp_path();
+            tokio::fs::copy(minidump_path(path), &minidump_file).await?;
+            service.process_minidump(record.scope, minidump_file, sources, record.options)?
+        }
+    };
+
+    let response = loop {
+        match service.get_response(request_id, None).await {
+            Some(SymbolicationResponse::Pending { .. }) => continue,
+            Some(response) => break response,
+            None => bail!("replayed request did not start"),
+        }
+    };
+
+    Ok(serde_json::to_value(&response)? == record.response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        test::setup();
+
+        let dir = test::tempdir();
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            recording: Some(RecordingConfig {
+                dir: dir.path().to_owned(),
+                sample_rate: 1.0,
+            }),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: service.config().default_sources(),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![],
+            kernel_space: KernelSpace::Unknown,
+        };
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(matches!(response, SymbolicationResponse::Completed(_)));
+
+        // The record is written after the response has been sent.
+        let mut records = vec![];
+        for _ in 0..100 {
+            records = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |ext| ext == RECORD_EXTENSION)
+                })
+                .collect();
+            if !records.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(records.len(), 1);
+
+        let summary = replay(&service, dir.path()).await.unwrap();
+        assert_eq!(summary.identical, 1);
+        assert!(summary.differing.is_empty());
+        assert!(summary.failed.is_empty());
+    }
+}
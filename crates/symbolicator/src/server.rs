@@ -2,19 +2,25 @@ use axum_server::Handle;
 #[cfg(feature = "https")]
 use std::fs::read;
 use std::net::SocketAddr;
+use std::path::Path;
 #[cfg(feature = "https")]
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 #[cfg(feature = "https")]
 use axum_server::tls_rustls::RustlsConfig;
 use futures::future::try_join_all;
 use futures::future::BoxFuture;
+use symbolicator_service::services::download::DownloadService;
 
 use crate::config::Config;
-use crate::endpoints;
+use crate::endpoints::{self, DownloaderState};
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::load_test::{self, LoadTestOptions};
 use crate::metric;
+use crate::recording;
 use crate::service::RequestService;
 
 #[cfg(feature = "https")]
@@ -22,7 +28,12 @@ fn read_pem_file(path: &PathBuf) -> Result<Vec<u8>> {
     read(path).context(format!("unable to read file: {}", path.display()))
 }
 
-/// Starts all actors and HTTP (and optionally HTTPS) server based on loaded config.
+/// The number of worker threads of each of the runtimes.
+pub fn worker_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Starts all actors and HTTP (and optionally HTTPS and gRPC) server based on loaded config.
 pub fn run(config: Config) -> Result<()> {
     // Log this metric before actually starting the server. This allows to see restarts even if
     // service creation fails.
@@ -31,16 +42,19 @@ pub fn run(config: Config) -> Result<()> {
     let megs = 1024 * 1024;
     let io_pool = tokio::runtime::Builder::new_multi_thread()
         .thread_name("sym-io")
+        .worker_threads(worker_threads())
         .enable_all()
         .thread_stack_size(8 * megs)
         .build()?;
     let cpu_pool = tokio::runtime::Builder::new_multi_thread()
         .thread_name("sym-cpu")
+        .worker_threads(worker_threads())
         .enable_all()
         .thread_stack_size(8 * megs)
         .build()?;
     let web_pool = tokio::runtime::Builder::new_multi_thread()
         .thread_name("sym-web")
+        .worker_threads(worker_threads())
         .enable_all()
         .thread_stack_size(8 * megs)
         .build()?;
@@ -54,6 +68,8 @@ pub fn run(config: Config) -> Result<()> {
     )
     .context("failed to create service state")?;
 
+    #[cfg(feature = "grpc")]
+    let grpc_service = service.clone();
     let svc = endpoints::create_app(service).into_make_service();
 
     let handle_http = Handle::new();
@@ -102,8 +118,141 @@ pub fn run(config: Config) -> Result<()> {
         servers.push(Box::pin(listening_https));
     }
 
+    #[cfg(feature = "grpc")]
+    if let Some(ref bind_str) = config.bind_grpc {
+        let socket_grpc = bind_str.parse::<SocketAddr>()?;
+        let server_grpc = async move {
+            tracing::info!("Starting gRPC server on {}", socket_grpc);
+            tonic::transport::Server::builder()
+                .add_service(grpc::create_service(grpc_service))
+                .serve(socket_grpc)
+                .await
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+        };
+        servers.push(Box::pin(server_grpc));
+    }
+
     web_pool.block_on(try_join_all(servers))?;
     tracing::info!("System shutdown complete");
 
     Ok(())
 }
+
+/// Starts the downloader process, which downloads files on behalf of other instances.
+///
+/// Instances delegate their downloads to this process if it is configured as their
+/// `downloader_url`. This only serves HTTP on `bind_downloader`, as it is meant to be reachable
+/// locally, and requires the `downloader_token`.
+pub fn run_downloader(config: Config) -> Result<()> {
+    if config.downloader_url.is_some() {
+        bail!("the downloader process cannot delegate downloads itself");
+    }
+    if config.downloader_token.is_none() {
+        bail!("the downloader process requires a downloader_token");
+    }
+
+    metric!(counter("downloader.starting") += 1);
+
+    let megs = 1024 * 1024;
+    let io_pool = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-io")
+        .worker_threads(worker_threads())
+        .enable_all()
+        .thread_stack_size(8 * megs)
+        .build()?;
+    let web_pool = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-web")
+        .worker_threads(worker_threads())
+        .enable_all()
+        .thread_stack_size(8 * megs)
+        .build()?;
+
+    let state = DownloaderState {
+        downloader: DownloadService::new(&config, io_pool.handle().to_owned()),
+        tmp_dir: config.cache_dir("tmp"),
+        token: config.downloader_token.clone(),
+    };
+    let svc = endpoints::create_downloader_app(state).into_make_service();
+
+    let handle = Handle::new();
+    let socket = config.bind_downloader.parse::<SocketAddr>()?;
+    let server = axum_server::bind(socket).handle(handle.clone()).serve(svc);
+
+    let listening = async move {
+        if let Some(local_addr) = handle.listening().await {
+            tracing::info!("Starting downloader on {}", local_addr);
+        } else {
+            panic!("Unable to listen on HTTP port");
+        }
+        Ok(())
+    };
+
+    let servers: Vec<BoxFuture<_>> = vec![Box::pin(server), Box::pin(listening)];
+    web_pool.block_on(try_join_all(servers))?;
+    tracing::info!("Downloader shutdown complete");
+
+    Ok(())
+}
+
+/// Replays the requests recorded in `dir`, and compares their responses to the recorded ones.
+///
+/// Fails if any of the responses differ, or any of the requests could not be replayed.
+pub fn run_replay(mut config: Config, dir: &Path) -> Result<()> {
+    // Do not record the replayed requests again.
+    config.recording = None;
+
+    let megs = 1024 * 1024;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-replay")
+        .worker_threads(worker_threads())
+        .enable_all()
+        .thread_stack_size(8 * megs)
+        .build()?;
+
+    let service = RequestService::create(
+        config,
+        runtime.handle().to_owned(),
+        runtime.handle().to_owned(),
+    )
+    .context("failed to create service state")?;
+
+    let summary = runtime.block_on(recording::replay(&service, dir))?;
+    for path in &summary.differing {
+        println!("differs: {}", path.display());
+    }
+    for path in &summary.failed {
+        println!("failed: {}", path.display());
+    }
+    println!(
+        "{} identical, {} differing, {} failed",
+        summary.identical,
+        summary.differing.len(),
+        summary.failed.len()
+    );
+
+    if !summary.differing.is_empty() || !summary.failed.is_empty() {
+        bail!("replayed responses do not match the recorded ones");
+    }
+
+    Ok(())
+}
+
+/// Runs a load test against another Symbolicator, and prints its report.
+pub fn run_load_test(options: &LoadTestOptions) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-load-test")
+        .worker_threads(worker_threads())
+        .enable_all()
+        .build()?;
+
+    let report = runtime.block_on(load_test::run(options))?;
+    print!("{report}");
+
+    if let Some(max_error_rate) = options.max_error_rate {
+        if report.error_rate() > max_error_rate {
+            bail!("the error rate exceeds {max_error_rate}");
+        }
+    }
+
+    Ok(())
+}
@@ -10,41 +10,67 @@
 //! is primarily synchronous work in the best case (everything is cached).
 //! When file fetching is needed, that fetching will happen on the `io_pool`.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
 use std::fs::File;
 use std::future::Future;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use futures::future;
 use futures::{channel::oneshot, FutureExt as _};
-use sentry::protocol::SessionStatus;
+use sentry::protocol::{Context, SessionStatus};
 use sentry::SentryFutureExt;
 use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use tempfile::TempPath;
 use thiserror::Error;
+use url::Url;
 use uuid::Uuid;
 
-use symbolicator_service::caching::CacheEntry;
+use symbolicator_service::caching::{
+    CacheEntry, CacheName, CacheReport, ResultStore, ResultStoreRef,
+};
 use symbolicator_service::config::Config;
 use symbolicator_service::metric;
+use symbolicator_service::services::download::SourceHealth;
 use symbolicator_service::services::objects::ObjectsActor;
-use symbolicator_service::services::symbolication::SymbolicationActor;
-use symbolicator_service::types::{CompletedResponse, CompletedSymbolicationResponse};
+use symbolicator_service::services::symbolication::{
+    MinidumpUploadError, ProgressTracker, SpooledMinidump, SymbolicationActor,
+    SymbolicationProgress, TraceRecorder,
+};
+use symbolicator_service::types::{
+    CompleteObjectInfo, CompletedResponse, SymbolicationOptions, TaggedCompletedResponse,
+};
+use symbolicator_service::utils::encryption::MasterKey;
 use symbolicator_service::utils::futures::CallOnDrop;
 use symbolicator_service::utils::futures::{m, measure};
-use symbolicator_sources::SourceConfig;
+use symbolicator_service::utils::hex::HexValue;
+use symbolicator_sources::{ObjectId, SourceConfig, SourceId};
+
+use crate::callbacks::CallbackSender;
+use crate::comparison::{self, Comparator, ComparedRequest};
+use crate::journal::{Journal, JournalEntry, JournaledRequest};
+use crate::queue::{RequestPriority, RequestQueue};
+use crate::recording::{PendingRecord, RecordedPayload, Recorder};
+use crate::stored_sources::SourceStore;
 
 pub use symbolicator_service::services::objects::{
     FindObject, FindResult, ObjectHandle, ObjectMetaHandle, ObjectPurpose,
 };
 pub use symbolicator_service::services::symbolication::{
-    StacktraceOrigin, SymbolicateJsStacktraces, SymbolicateStacktraces,
+    PrefetchDifs, ProfileFormat, StacktraceOrigin, SymbolicateHybridStacktraces,
+    SymbolicateJsStacktraces, SymbolicateJvmStacktraces, SymbolicateProfileChunk,
+    SymbolicateStacktraces,
 };
-pub use symbolicator_service::types::{JsStacktrace, RawObjectInfo, RawStacktrace, Scope, Signal};
+pub use symbolicator_service::types::{
+    HybridStacktrace, JsStacktrace, JvmException, JvmModule, JvmStacktrace, RawObjectInfo,
+    RawStacktrace, Scope, Signal,
+};
+pub use symbolicator_service::utils::addr::KernelSpace;
 
 /// Symbolication task identifier.
 #[derive(Debug, Clone, Copy, Serialize, Ord, PartialOrd, Eq, PartialEq)]
@@ -81,6 +107,8 @@ impl<'de> Deserialize<'de> for RequestId {
 /// This is primarily a wrapper around [`CompletedSymbolicationResponse`] which is publicly
 /// documented at <https://getsentry.github.io/symbolicator/api/response/>.
 ///
+/// [`CompletedSymbolicationResponse`]: symbolicator_service::types::CompletedSymbolicationResponse
+///
 /// For the actual HTTP response this is further wrapped to also allow a pending or failed state etc
 /// instead of a result.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -92,13 +120,30 @@ pub enum SymbolicationResponse {
         request_id: RequestId,
         /// An indication when the next poll would be suitable.
         retry_after: usize,
+        /// The progress of the running symbolication, if it is reported by the endpoint.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        progress: Option<SymbolicationProgress>,
+        /// A token derived from the scope of the request, see [`affinity_token`].
+        ///
+        /// Load balancers can route polls carrying this token to the instance that is
+        /// processing the request.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        affinity: Option<String>,
     },
     Completed(Box<CompletedResponse>),
     Failed {
         message: String,
     },
     Timeout,
-    InternalError,
+    /// The request was cancelled before it finished.
+    Cancelled,
+    InternalError {
+        /// An id identifying the error in the logs and the captured Sentry event.
+        ///
+        /// This can be used to correlate a reported failure with the corresponding crash report.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reference_id: Option<String>,
+    },
 }
 
 /// Errors during symbolication.
@@ -107,6 +152,15 @@ pub enum SymbolicationError {
     #[error("symbolication took too long")]
     Timeout,
 
+    #[error("symbolication was cancelled")]
+    Cancelled,
+
+    #[error("symbolication panicked")]
+    Panic {
+        /// The id under which the panic was reported.
+        reference_id: String,
+    },
+
     #[error(transparent)]
     Failed(#[from] anyhow::Error),
 }
@@ -115,6 +169,10 @@ impl From<&SymbolicationError> for SymbolicationResponse {
     fn from(error: &SymbolicationError) -> Self {
         match error {
             SymbolicationError::Timeout => SymbolicationResponse::Timeout,
+            SymbolicationError::Cancelled => SymbolicationResponse::Cancelled,
+            SymbolicationError::Panic { reference_id } => SymbolicationResponse::InternalError {
+                reference_id: Some(reference_id.clone()),
+            },
             SymbolicationError::Failed(_) => SymbolicationResponse::Failed {
                 message: error.to_string(),
             },
@@ -138,14 +196,124 @@ pub struct RequestOptions {
     /// for which extra information is returned for DIF objects.
     #[serde(default)]
     pub dif_candidates: bool,
+
+    /// Whether to return a trace of the decisions made while processing the request.
+    ///
+    /// The trace lists the phases of the request with their timings, the files probed on the
+    /// sources for every module along with the reasons they were rejected, and the frames which
+    /// could not be symbolicated. This only applies to native symbolication, and is meant for
+    /// debugging why frames are not symbolicated.
+    #[serde(default)]
+    pub capture_trace: bool,
+
+    /// For how many seconds the result can be polled after the request finished.
+    ///
+    /// This is capped to the configured `max_result_ttl`, and defaults to
+    /// [`DEFAULT_RESULT_TTL`] if not given.
+    #[serde(default)]
+    pub result_ttl: Option<u64>,
+
+    /// The format of the response to a completed request.
+    ///
+    /// This only applies to native symbolication, and is ignored by other requests.
+    #[serde(default)]
+    pub output: OutputFormat,
+
+    /// A URL to which the response is sent once the request has finished.
+    ///
+    /// The response is sent as a JSON `POST` request, and retried if delivery fails. It can be
+    /// polled regardless, for as long as the `result_ttl` allows.
+    #[serde(default)]
+    pub callback_url: Option<Url>,
+
+    /// The priority with which the request is started, if it has to wait for others to finish.
+    ///
+    /// This only makes a difference if `max_running_requests` is configured.
+    #[serde(default)]
+    pub priority: RequestPriority,
+
+    /// The URL of another Symbolicator to compare the response with.
+    ///
+    /// The URL must be one of the configured `comparison_targets`. The request is also sent to
+    /// that Symbolicator, and the frames which it symbolicated differently are reported in
+    /// metrics and logs. This only applies to native stack traces sent to `/symbolicate`, and is
+    /// ignored by other requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compare_with: Option<Url>,
+
+    /// Options which control how frames are symbolicated.
+    #[serde(flatten)]
+    pub symbolication: SymbolicationOptions,
+}
+
+/// The format in which native symbolication responses are returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// The symbolication response, as documented in the API.
+    #[default]
+    Symbolication,
+    /// A Sentry event payload, with the exception, threads, contexts and debug images extracted
+    /// from the response, which can be sent to Sentry as is.
+    SentryEvent,
+}
+
+/// Information about a symbolication request which is attached to the Sentry event that is
+/// reported in case the request fails.
+#[derive(Debug, Default)]
+struct RequestMetadata {
+    /// The ID of the request, if it was assigned before the request was created.
+    request_id: Option<RequestId>,
+    /// The recording of the request, if it was sampled by the [`Recorder`].
+    recording: Option<PendingRecord>,
+    /// The entry of the request in the [`Journal`], which is removed once it completes.
+    journal: Option<JournalEntry>,
+    /// The scope of the request.
+    scope: Scope,
+    /// The number of modules in the request, if known upfront.
+    num_modules: Option<usize>,
+    /// The number of frames in the request, if known upfront.
+    num_frames: Option<usize>,
+    /// The ids of all the sources the request may fetch files from.
+    sources: Vec<SourceId>,
+    /// The trace of the request, which is disabled unless it was requested.
+    trace: TraceRecorder,
+}
+
+impl RequestMetadata {
+    /// Creates metadata for a request whose modules and frames are not known upfront.
+    fn new(scope: Scope, sources: &[SourceConfig]) -> Self {
+        Self {
+            scope,
+            sources: sources.iter().map(|source| source.id().clone()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn to_scope(&self, request_id: RequestId, endpoint: &str, scope: &mut sentry::Scope) {
+        scope.set_tag("request_id", request_id);
+        scope.set_tag("endpoint", endpoint);
+        scope.set_tag("scope", &self.scope);
+
+        let mut map = BTreeMap::new();
+        if let Some(num_modules) = self.num_modules {
+            map.insert("num_modules".to_string(), num_modules.into());
+        }
+        if let Some(num_frames) = self.num_frames {
+            map.insert("num_frames".to_string(), num_frames.into());
+        }
+        let sources: Vec<_> = self.sources.iter().map(ToString::to_string).collect();
+        map.insert("sources".to_string(), sources.into());
+        scope.set_context("Symbolication Request", Context::Other(map));
+    }
 }
 
 /// Clears out all the information about the DIF object candidates in the modules list.
 ///
 /// This will avoid this from being serialised as the DIF object candidates list is not
 /// serialised when it is empty.
-fn clear_dif_candidates(response: &mut CompletedSymbolicationResponse) {
-    for module in response.modules.iter_mut() {
+fn clear_dif_candidates(modules: &mut [CompleteObjectInfo]) {
+    for module in modules.iter_mut() {
         module.candidates.clear()
     }
 }
@@ -158,7 +326,18 @@ pub struct RequestService {
 // We want a shared future here because otherwise polling for a response would hold the global lock.
 type ComputationChannel = future::Shared<oneshot::Receiver<(Instant, SymbolicationResponse)>>;
 
-type ComputationMap = Arc<Mutex<BTreeMap<RequestId, ComputationChannel>>>;
+/// A running or finished computation that can be polled for its response.
+#[derive(Clone)]
+struct Computation {
+    channel: ComputationChannel,
+    progress: Option<ProgressTracker>,
+    affinity: String,
+    abort: future::AbortHandle,
+    /// Set once the computation either finished or was cancelled, whichever happened first.
+    settled: Arc<AtomicBool>,
+}
+
+type ComputationMap = Arc<Mutex<BTreeMap<RequestId, Computation>>>;
 
 struct RequestServiceInner {
     config: Config,
@@ -170,7 +349,17 @@ struct RequestServiceInner {
     requests: ComputationMap,
     max_concurrent_requests: Option<usize>,
     current_requests: Arc<AtomicUsize>,
+    /// Notified whenever a request stops counting as in flight.
+    request_finished: Arc<tokio::sync::Notify>,
+    draining: AtomicBool,
     symbolication_taskmon: tokio_metrics::TaskMonitor,
+    recorder: Option<Recorder>,
+    stored_sources: SourceStore,
+    result_store: ResultStoreRef,
+    journal: Option<Journal>,
+    callbacks: CallbackSender,
+    comparator: Comparator,
+    queue: RequestQueue,
 }
 
 impl RequestService {
@@ -202,6 +391,23 @@ impl RequestService {
         }
 
         let max_concurrent_requests = config.max_concurrent_requests;
+        let recorder = config.recording.clone().map(Recorder::new);
+        let master_key = config
+            .encryption
+            .as_ref()
+            .map(MasterKey::load)
+            .transpose()?
+            .map(Arc::new);
+        let stored_sources =
+            SourceStore::load(config.stored_sources.as_deref(), master_key.clone())?;
+        let result_store = ResultStore::new(config.result_storage.clone(), &io_pool);
+        let journal = config
+            .journal
+            .clone()
+            .map(|dir| Journal::new(dir, master_key));
+        let callbacks = CallbackSender::new(&config);
+        let comparator = Comparator::new(&config);
+        let queue = RequestQueue::new(config.max_running_requests);
 
         let inner = RequestServiceInner {
             config,
@@ -213,12 +419,146 @@ impl RequestService {
             requests: Arc::new(Mutex::new(BTreeMap::new())),
             max_concurrent_requests,
             current_requests: Arc::new(AtomicUsize::new(0)),
+            request_finished: Arc::new(tokio::sync::Notify::new()),
+            draining: AtomicBool::new(false),
             symbolication_taskmon,
+            recorder,
+            stored_sources,
+            result_store,
+            journal,
+            callbacks,
+            comparator,
+            queue,
         };
 
-        Ok(Self {
+        let service = Self {
             inner: Arc::new(inner),
-        })
+        };
+        service.recover_journal(&io_pool);
+        Ok(service)
+    }
+
+    /// Restarts the requests which a previous process journaled, but did not complete.
+    ///
+    /// Requests which exceed `max_concurrent_requests` are deferred, and restarted on `io_pool`
+    /// as soon as running requests finish.
+    fn recover_journal(&self, io_pool: &tokio::runtime::Handle) {
+        let Some(ref journal) = self.inner.journal else {
+            return;
+        };
+
+        let mut recovered = VecDeque::from(journal.recover());
+        while self.has_capacity(1) {
+            let Some((request, entry)) = recovered.pop_front() else {
+                return;
+            };
+            self.restart_journaled(request, entry);
+        }
+        if recovered.is_empty() {
+            return;
+        }
+
+        tracing::warn!(
+            "Too many journaled requests, deferring {} of them",
+            recovered.len()
+        );
+        let service = self.clone();
+        io_pool.spawn(async move {
+            while let Some((request, entry)) = recovered.pop_front() {
+                while !service.has_capacity(1) {
+                    service.inner.request_finished.notified().await;
+                }
+                service.restart_journaled(request, entry);
+            }
+        });
+    }
+
+    /// Returns whether the given number of new requests would be admitted, given
+    /// `max_concurrent_requests`.
+    pub fn has_capacity(&self, requests: usize) -> bool {
+        self.inner
+            .max_concurrent_requests
+            .map_or(true, |max| self.in_flight_requests() + requests <= max)
+    }
+
+    /// Restarts a single journaled request, after counting the restart in its entry.
+    fn restart_journaled(&self, mut request: JournaledRequest, entry: JournalEntry) {
+        let request_id = request.request_id;
+        if let Some(ref journal) = self.inner.journal {
+            if let Err(err) = journal.record_restart(&mut request, &entry) {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(stderr, "Failed to count restart of journaled request");
+            }
+        }
+
+        tracing::info!("Restarting journaled request {request_id}");
+        let result = match request.payload {
+            RecordedPayload::Minidump => {
+                // Processing consumes the minidump, so it works on a copy of the journaled one.
+                let copy = tempfile::NamedTempFile::new().and_then(|file| {
+                    let minidump_file = file.into_temp_path();
+                    std::fs::copy(entry.minidump_path(), &minidump_file)?;
+                    Ok(minidump_file)
+                });
+                let minidump_file = match copy {
+                    Ok(minidump_file) => minidump_file,
+                    Err(err) => {
+                        let stderr: &dyn std::error::Error = &err;
+                        tracing::error!(stderr, "Failed to restart journaled request");
+                        return;
+                    }
+                };
+                self.start_minidump_processing(
+                    request_id,
+                    Some(entry),
+                    request.scope,
+                    minidump_file,
+                    request.sources,
+                    request.options,
+                )
+            }
+            RecordedPayload::Stacktraces {
+                signal,
+                stacktraces,
+                modules,
+            } => self.start_stacktrace_symbolication(
+                request_id,
+                Some(entry),
+                SymbolicateStacktraces {
+                    scope: request.scope,
+                    signal,
+                    sources: request.sources,
+                    origin: StacktraceOrigin::Symbolicate,
+                    stacktraces,
+                    modules: modules.into_iter().map(From::from).collect(),
+                    kernel_space: KernelSpace::Unknown,
+                },
+                request.options,
+            ),
+        };
+        if let Err(err) = result {
+            let stderr: &dyn std::error::Error = &err;
+            tracing::error!(stderr, "Failed to restart journaled request");
+        }
+    }
+
+    /// Writes the journal entry of a newly accepted request, if a [`Journal`] is configured.
+    ///
+    /// The `request` is only created if it is journaled. Failures to write the entry are logged,
+    /// but do not fail the request.
+    fn journal_request<R>(&self, minidump: Option<&Path>, request: R) -> Option<JournalEntry>
+    where
+        R: FnOnce() -> JournaledRequest,
+    {
+        let journal = self.inner.journal.as_ref()?;
+        match journal.write(&request(), minidump) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                let stderr: &dyn std::error::Error = &*err;
+                tracing::error!(stderr, "Failed to journal request");
+                None
+            }
+        }
     }
 
     /// Gives access to the [`Config`].
@@ -226,6 +566,94 @@ impl RequestService {
         &self.inner.config
     }
 
+    /// Gives access to the source configurations stored on the server.
+    pub fn stored_sources(&self) -> &SourceStore {
+        &self.inner.stored_sources
+    }
+
+    /// Switches the service into drain mode.
+    ///
+    /// While draining, the healthcheck fails and new symbolication requests are rejected, but
+    /// requests that are already in flight keep running and can still be polled.
+    pub fn start_draining(&self) {
+        if !self.inner.draining.swap(true, Ordering::Relaxed) {
+            tracing::info!("Draining, no longer accepting new requests");
+            metric!(counter("requests.draining") += 1);
+        }
+    }
+
+    /// Returns `true` if the service has been switched into drain mode.
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of symbolication requests that are currently being processed.
+    pub fn in_flight_requests(&self) -> usize {
+        self.inner.current_requests.load(Ordering::Relaxed)
+    }
+
+    /// Counts a request which is not tracked as a symbolication request as in flight.
+    ///
+    /// The request is rejected like symbolication requests once `max_concurrent_requests` are in
+    /// flight, and stops counting when the returned guard is dropped.
+    pub fn admit_request(&self) -> Result<CallOnDrop, MaxRequestsError> {
+        let current_requests = Arc::clone(&self.inner.current_requests);
+        let request_finished = Arc::clone(&self.inner.request_finished);
+        let num_requests = current_requests.fetch_add(1, Ordering::Relaxed);
+        let guard = CallOnDrop::new(move || {
+            current_requests.fetch_sub(1, Ordering::Relaxed);
+            request_finished.notify_one();
+        });
+
+        if let Some(max_concurrent_requests) = self.inner.max_concurrent_requests {
+            if num_requests >= max_concurrent_requests {
+                metric!(counter("requests.rejected") += 1);
+                return Err(MaxRequestsError);
+            }
+        }
+        Ok(guard)
+    }
+
+    /// Returns the health of the configured sources.
+    pub fn source_health(&self) -> Vec<SourceHealth> {
+        self.inner.objects.source_health()
+    }
+
+    /// Returns the statistics of the file-system caches, as gathered by the background scanner.
+    pub fn cache_stats(&self) -> Vec<CacheReport> {
+        self.inner.symbolication.cache_stats()
+    }
+
+    /// Invalidates the negative cache entries of the given object in all caches used for native
+    /// symbolication.
+    ///
+    /// This is meant to be called once a previously missing debug file was uploaded, so that it
+    /// is picked up right away instead of after `retry_misses_after`. Returns the number of
+    /// entries removed from each file system cache.
+    pub async fn invalidate_negative_caches(
+        &self,
+        object_id: &ObjectId,
+    ) -> Result<Vec<(CacheName, usize)>> {
+        self.inner
+            .symbolication
+            .invalidate_negative_caches(object_id)
+            .await
+    }
+
+    /// Estimates how many seconds it takes until a pending request is worth polling again.
+    ///
+    /// Pending requests are mostly waiting for downloads, so this follows the time downloads
+    /// from the configured sources currently take.
+    fn retry_after(&self) -> usize {
+        match self.inner.objects.expected_download_latency() {
+            Some(latency) => {
+                let seconds = latency.as_secs_f64().ceil() as usize;
+                seconds.clamp(MIN_RETRY_AFTER, MAX_RETRY_AFTER)
+            }
+            None => DEFAULT_RETRY_AFTER,
+        }
+    }
+
     /// Looks up the object according to the [`FindObject`] request.
     pub async fn find_object(&self, request: FindObject) -> FindResult {
         self.inner.objects.find(request).await
@@ -248,26 +676,108 @@ impl RequestService {
         request: SymbolicateStacktraces,
         options: RequestOptions,
     ) -> Result<RequestId, MaxRequestsError> {
-        let slf = self.inner.clone();
+        let request_id = RequestId::new(Uuid::new_v4());
+        let journal = self.journal_request(None, || JournaledRequest {
+            request_id,
+            scope: request.scope.clone(),
+            sources: request.sources.clone(),
+            options: options.clone(),
+            payload: RecordedPayload::Stacktraces {
+                signal: request.signal,
+                stacktraces: request.stacktraces.clone(),
+                modules: request.modules.iter().map(|m| m.raw.clone()).collect(),
+            },
+            restarts: 0,
+        });
+        self.start_stacktrace_symbolication(request_id, journal, request, options)
+    }
+
+    fn start_stacktrace_symbolication(
+        &self,
+        request_id: RequestId,
+        journal: Option<JournalEntry>,
+        request: SymbolicateStacktraces,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let recording = self.inner.recorder.as_ref().and_then(|recorder| {
+            recorder.sample(&request.scope, &options, || RecordedPayload::Stacktraces {
+                signal: request.signal,
+                stacktraces: request.stacktraces.clone(),
+                modules: request.modules.iter().map(|m| m.raw.clone()).collect(),
+            })
+        });
+        let metadata = RequestMetadata {
+            request_id: Some(request_id),
+            recording,
+            journal,
+            num_modules: Some(request.modules.len()),
+            num_frames: Some(request.stacktraces.iter().map(|st| st.frames.len()).sum()),
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(request.scope.clone(), &request.sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let comparator = &self.inner.comparator;
+        let other_response = options
+            .compare_with
+            .clone()
+            .filter(|url| comparator.allows(url))
+            .map(|url| {
+                let comparator = comparator.clone();
+                let scope = request.scope.clone();
+                let compared = ComparedRequest::new(&request, &options);
+                async move { comparator.fetch(&url, &scope, &compared).await }
+            });
         let span = sentry::configure_scope(|scope| scope.get_span());
         let ctx = sentry::TransactionContext::continue_from_span(
             "symbolicate_stacktraces",
             "symbolicate_stacktraces",
             span,
         );
-        self.create_symbolication_request("symbolicate", options, async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf.symbolication.symbolicate(request).await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        self.create_symbolication_request(
+            "symbolicate",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                // The comparison runs detached, so that it never holds back our response.
+                let theirs = other_response.map(tokio::spawn);
+                let res = symbolication.symbolicate(request).await;
+                transaction.finish();
+                let res = res?;
+                if let Some(theirs) = theirs {
+                    let ours = res.clone();
+                    tokio::spawn(async move {
+                        let theirs = theirs.await.unwrap_or_else(|err| Err(err.into()));
+                        comparison::compare(&ours, theirs).ok();
+                    });
+                }
+                Ok(res.into())
+            },
+        )
     }
 
     pub fn symbolicate_js_stacktraces(
         &self,
         request: SymbolicateJsStacktraces,
     ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            scope: request.scope.clone(),
+            num_modules: Some(request.modules.len()),
+            num_frames: Some(request.stacktraces.iter().map(|st| st.frames.len()).sum()),
+            sources: vec![request.source.id.clone()],
+            ..Default::default()
+        };
+
         let slf = self.inner.clone();
         let span = sentry::configure_scope(|scope| scope.get_span());
         let ctx = sentry::TransactionContext::continue_from_span(
@@ -275,140 +785,672 @@ impl RequestService {
             "symbolicate_js_stacktraces",
             span,
         );
-        self.create_symbolication_request("symbolicate_js", RequestOptions::default(), async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf.symbolication.symbolicate_js(request).await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        let options = RequestOptions::default();
+        self.create_symbolication_request(
+            "symbolicate_js",
+            options,
+            metadata,
+            None,
+            move |_| async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf.symbolication.symbolicate_js(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
-    /// Creates a new request to process a minidump.
+    /// Creates a new request to deobfuscate JVM exceptions and stack traces.
     ///
-    /// Returns an `Err` if the [`RequestService`] is already processing the
-    /// maximum number of requests, as configured by the `max_concurrent_requests` option.
-    pub fn process_minidump(
+    /// The ProGuard mapping files listed in the `modules` of the request are fetched from its
+    /// `sources`.
+    pub fn symbolicate_jvm_stacktraces(
         &self,
-        scope: Scope,
-        minidump_file: TempPath,
-        sources: Arc<[SourceConfig]>,
+        request: SymbolicateJvmStacktraces,
         options: RequestOptions,
     ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(request.modules.len()),
+            num_frames: Some(request.stacktraces.iter().map(|st| st.frames.len()).sum()),
+            ..RequestMetadata::new(request.scope.clone(), &request.sources)
+        };
+
         let slf = self.inner.clone();
         let span = sentry::configure_scope(|scope| scope.get_span());
         let ctx = sentry::TransactionContext::continue_from_span(
-            "process_minidump",
-            "process_minidump",
+            "symbolicate_jvm_stacktraces",
+            "symbolicate_jvm_stacktraces",
             span,
         );
-        self.create_symbolication_request("minidump_stackwalk", options, async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf
-                .symbolication
-                .process_minidump(scope, minidump_file, sources)
-                .await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        self.create_symbolication_request(
+            "symbolicate_jvm",
+            options,
+            metadata,
+            None,
+            move |_| async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf.symbolication.symbolicate_jvm(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
-    /// Creates a new request to process an Apple crash report.
+    /// Creates a new request to fetch and convert the debug information files of the given
+    /// modules ahead of time.
     ///
-    /// Returns an `Err` if the [`RequestService`] is already processing the
-    /// maximum number of requests, as configured by the `max_concurrent_requests` option.
-    pub fn process_apple_crash_report(
+    /// No frames are symbolicated. This allows warming the caches right after new builds were
+    /// uploaded, so that the first crashes of those builds are symbolicated quickly.
+    pub fn prefetch_difs(
         &self,
-        scope: Scope,
-        apple_crash_report: File,
-        sources: Arc<[SourceConfig]>,
+        request: PrefetchDifs,
         options: RequestOptions,
     ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(request.modules.len()),
+            ..RequestMetadata::new(request.scope.clone(), &request.sources)
+        };
+
         let slf = self.inner.clone();
         let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx =
+            sentry::TransactionContext::continue_from_span("prefetch_difs", "prefetch_difs", span);
+        self.create_symbolication_request(
+            "prefetch_difs",
+            options,
+            metadata,
+            None,
+            move |_| async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf.symbolication.prefetch_difs(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
+
+    /// Creates a new request to symbolicate stack traces mixing native and JavaScript frames.
+    ///
+    /// Native frames are resolved using the debug files from `sources`, and JavaScript frames
+    /// using the source maps from the Sentry `source`.
+    pub fn symbolicate_hybrid_stacktraces(
+        &self,
+        request: SymbolicateHybridStacktraces,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let mut metadata = RequestMetadata {
+            num_modules: Some(request.modules.len()),
+            num_frames: Some(request.stacktraces.iter().map(|st| st.frames.len()).sum()),
+            ..RequestMetadata::new(request.scope.clone(), &request.sources)
+        };
+        if let Some(source) = &request.source {
+            metadata.sources.push(source.id.clone());
+        }
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
         let ctx = sentry::TransactionContext::continue_from_span(
-            "process_apple_crash_report",
-            "process_apple_crash_report",
+            "symbolicate_hybrid_stacktraces",
+            "symbolicate_hybrid_stacktraces",
             span,
         );
-        self.create_symbolication_request("parse_apple_crash_report", options, async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf
-                .symbolication
-                .process_apple_crash_report(scope, apple_crash_report, sources)
-                .await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        self.create_symbolication_request(
+            "symbolicate_hybrid",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication.symbolicate_hybrid(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
-    /// Polls the status for a started symbolication task.
-    ///
-    /// If the timeout is set and no result is ready within the given time,
-    /// [`SymbolicationResponse::Pending`] is returned.
-    pub async fn get_response(
+    /// Creates a new request to symbolicate an unstructured, textual backtrace.
+    pub fn symbolicate_text(
         &self,
-        request_id: RequestId,
-        timeout: Option<u64>,
-    ) -> Option<SymbolicationResponse> {
-        let channel_opt = self
+        scope: Scope,
+        signal: Option<Signal>,
+        backtrace: String,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(modules.len()),
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
             .inner
-            .requests
-            .lock()
-            .unwrap()
-            .get(&request_id)
-            .cloned();
-        match channel_opt {
-            Some(channel) => Some(wrap_response_channel(request_id, timeout, channel).await),
-            None => {
-                // This is okay to occur during deploys, but if it happens all the time we have a state
-                // bug somewhere. Could be a misconfigured load balancer (supposed to be pinned to
-                // scopes).
-                metric!(counter("symbolication.request_id_unknown") += 1);
-                None
-            }
-        }
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "symbolicate_text",
+            "symbolicate_text",
+            span,
+        );
+        self.create_symbolication_request(
+            "symbolicate_text",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .symbolicate_text(scope, signal, &backtrace, sources, modules)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
-    /// Creates a new request to compute the given future.
-    ///
-    /// Returns `None` if the `SymbolicationActor` is already processing the
-    /// maximum number of requests, as given by `max_concurrent_requests`.
-    fn create_symbolication_request<F>(
+    /// Creates a new request to symbolicate raw addresses of a bare-metal firmware image.
+    pub fn symbolicate_firmware(
         &self,
-        task_name: &'static str,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        firmware: RawObjectInfo,
+        load_offset: u64,
+        addresses: Vec<Vec<HexValue>>,
         options: RequestOptions,
-        f: F,
-    ) -> Result<RequestId, MaxRequestsError>
-    where
-        F: Future<Output = Result<CompletedResponse, anyhow::Error>> + Send + 'static,
-    {
-        let (sender, receiver) = oneshot::channel();
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(1),
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
 
-        let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "symbolicate_firmware",
+            "symbolicate_firmware",
+            span,
+        );
+        self.create_symbolication_request(
+            "symbolicate_firmware",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .symbolicate_firmware(scope, sources, firmware, load_offset, addresses)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
 
-        // Assume that there are no UUID4 collisions in practice.
-        let requests = Arc::clone(&self.inner.requests);
-        let current_requests = Arc::clone(&self.inner.current_requests);
+    /// Creates a new request to symbolicate a textual profile.
+    pub fn symbolicate_profile(
+        &self,
+        scope: Scope,
+        format: ProfileFormat,
+        profile: String,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(modules.len()),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
 
-        let num_requests = current_requests.load(Ordering::Relaxed);
-        metric!(gauge("requests.in_flight") = num_requests as u64);
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "symbolicate_profile",
+            "symbolicate_profile",
+            span,
+        );
+        self.create_symbolication_request(
+            "symbolicate_profile",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .symbolicate_profile(scope, format, &profile, sources, modules)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
 
-        // Reject the request if `requests` already contains `max_concurrent_requests` elements.
+    /// Creates a new request to symbolicate a chunk of profiler samples.
+    pub fn symbolicate_profile_chunk(
+        &self,
+        request: SymbolicateProfileChunk,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(request.modules.len()),
+            num_frames: Some(request.frames.len()),
+            ..RequestMetadata::new(request.scope.clone(), &request.sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "symbolicate_profile_chunk",
+            "symbolicate_profile_chunk",
+            span,
+        );
+        self.create_symbolication_request(
+            "symbolicate_profile_chunk",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication.symbolicate_profile_chunk(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
+
+    /// Writes a minidump which is uploaded as a `stream` of chunks to `file`.
+    ///
+    /// If the `sources` of the request are already known, unwind information for the modules of
+    /// the minidump is fetched while the rest of it is still being uploaded. This is skipped if
+    /// the request would be rejected, as `max_concurrent_requests` are already being processed.
+    /// Fetching ahead stops when the returned [`SpooledMinidump`] is dropped, unless the request
+    /// was admitted and [`SpooledMinidump::keep_prefetching`] was called.
+    pub async fn spool_minidump<S, B, E>(
+        &self,
+        scope: &Scope,
+        sources: Option<Arc<[SourceConfig]>>,
+        stream: S,
+        file: &mut tokio::fs::File,
+    ) -> Result<SpooledMinidump, MinidumpUploadError>
+    where
+        S: futures::Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<anyhow::Error>,
+    {
+        let at_capacity = self
+            .inner
+            .max_concurrent_requests
+            .map_or(false, |max| self.in_flight_requests() >= max);
+        let sources = sources.filter(|_| !at_capacity);
+
+        self.inner
+            .symbolication
+            .spool_minidump(scope, sources, stream, file)
+            .await
+    }
+
+    /// Creates a new request to process a minidump.
+    ///
+    /// Returns an `Err` if the [`RequestService`] is already processing the
+    /// maximum number of requests, as configured by the `max_concurrent_requests` option.
+    pub fn process_minidump(
+        &self,
+        scope: Scope,
+        minidump_file: TempPath,
+        sources: Arc<[SourceConfig]>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let request_id = RequestId::new(Uuid::new_v4());
+        let journal = self.journal_request(Some(&*minidump_file), || JournaledRequest {
+            request_id,
+            scope: scope.clone(),
+            sources: sources.clone(),
+            options: options.clone(),
+            payload: RecordedPayload::Minidump,
+            restarts: 0,
+        });
+        self.start_minidump_processing(request_id, journal, scope, minidump_file, sources, options)
+    }
+
+    fn start_minidump_processing(
+        &self,
+        request_id: RequestId,
+        journal: Option<JournalEntry>,
+        scope: Scope,
+        minidump_file: TempPath,
+        sources: Arc<[SourceConfig]>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let recording =
+            self.inner.recorder.as_ref().and_then(|recorder| {
+                recorder.sample(&scope, &options, || RecordedPayload::Minidump)
+            });
+        let minidump_recording = recording
+            .as_ref()
+            .map(|recording| recording.store_minidump(&minidump_file));
+        let metadata = RequestMetadata {
+            request_id: Some(request_id),
+            recording,
+            journal,
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "process_minidump",
+            "process_minidump",
+            span,
+        );
+        self.create_symbolication_request(
+            "minidump_stackwalk",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                if let Some(minidump_recording) = minidump_recording {
+                    minidump_recording.await;
+                }
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .process_minidump(scope, minidump_file, sources)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
+
+    /// Creates a new request to process an Apple crash report.
+    ///
+    /// Returns an `Err` if the [`RequestService`] is already processing the
+    /// maximum number of requests, as configured by the `max_concurrent_requests` option.
+    pub fn process_apple_crash_report(
+        &self,
+        scope: Scope,
+        apple_crash_report: File,
+        sources: Arc<[SourceConfig]>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "process_apple_crash_report",
+            "process_apple_crash_report",
+            span,
+        );
+        self.create_symbolication_request(
+            "parse_apple_crash_report",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .process_apple_crash_report(scope, apple_crash_report, sources)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
+
+    /// Creates a new request to process a Go panic.
+    ///
+    /// Returns an `Err` if the [`RequestService`] is already processing the
+    /// maximum number of requests, as configured by the `max_concurrent_requests` option.
+    pub fn process_go_panic(
+        &self,
+        scope: Scope,
+        go_panic: File,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+        options: RequestOptions,
+    ) -> Result<RequestId, MaxRequestsError> {
+        let metadata = RequestMetadata {
+            num_modules: Some(modules.len()),
+            trace: TraceRecorder::new(options.capture_trace),
+            ..RequestMetadata::new(scope.clone(), &sources)
+        };
+
+        let progress = ProgressTracker::default();
+        let symbolication = self
+            .inner
+            .symbolication
+            .with_progress(progress.clone())
+            .with_trace(metadata.trace.clone())
+            .with_options(options.symbolication.clone());
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "process_go_panic",
+            "process_go_panic",
+            span,
+        );
+        self.create_symbolication_request(
+            "parse_go_panic",
+            options,
+            metadata,
+            Some(progress),
+            move |deadline| async move {
+                let symbolication = symbolication.with_deadline(deadline);
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = symbolication
+                    .process_go_panic(scope, go_panic, sources, modules)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
+    }
+
+    /// Polls the status for a started symbolication task.
+    ///
+    /// If the timeout is set and no result is ready within the given time,
+    /// [`SymbolicationResponse::Pending`] is returned.
+    ///
+    /// The timeout is capped to the configured `max_poll_timeout`, which also applies if no
+    /// timeout is given.
+    pub async fn get_response(
+        &self,
+        request_id: RequestId,
+        timeout: Option<u64>,
+    ) -> Option<SymbolicationResponse> {
+        let timeout = timeout.map(Duration::from_secs);
+        let timeout = match self.inner.config.max_poll_timeout {
+            Some(max_timeout) => Some(timeout.map_or(max_timeout, |t| t.min(max_timeout))),
+            None => timeout,
+        };
+
+        let computation_opt = self
+            .inner
+            .requests
+            .lock()
+            .unwrap()
+            .get(&request_id)
+            .cloned();
+        match computation_opt {
+            Some(computation) => {
+                let retry_after = self.retry_after();
+                Some(wrap_response_channel(request_id, timeout, retry_after, computation).await)
+            }
+            None => {
+                // Requests which finished long ago, or on an instance which has since been
+                // restarted, may still be found in the result storage.
+                if let Some(store) = self.inner.result_store.get() {
+                    if let Some(response) = load_stored_response(store, request_id).await {
+                        metric!(counter("symbolication.request_id_stored") += 1);
+                        return Some(response);
+                    }
+                }
+
+                // This is okay to occur during deploys, but if it happens all the time we have a state
+                // bug somewhere. Could be a misconfigured load balancer (supposed to be pinned to
+                // scopes).
+                metric!(counter("symbolication.request_id_unknown") += 1);
+                None
+            }
+        }
+    }
+
+    /// Cancels a request which is still running or waiting to be started.
+    ///
+    /// The request stops right away, and everyone polling it receives a
+    /// [`SymbolicationResponse::Cancelled`]. The request is forgotten, so later polls do not find
+    /// it unless its response is kept in the result storage.
+    ///
+    /// Returns `false` if the request is not known, or has already finished.
+    pub fn cancel(&self, request_id: RequestId) -> bool {
+        let mut requests = self.inner.requests.lock().unwrap();
+        let Some(computation) = requests.get(&request_id) else {
+            return false;
+        };
+        // The computation may finish concurrently, in which case its response is kept.
+        if computation.settled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+
+        computation.abort.abort();
+        requests.remove(&request_id);
+        metric!(counter("requests.cancelled") += 1);
+        true
+    }
+
+    /// Creates a new request to compute the future returned by `f`.
+    ///
+    /// `f` is called once the request is admitted by the queue, with the deadline after which
+    /// native symbolication returns partial results.
+    /// The given [`RequestMetadata`] is attached to the Sentry event reported if the request fails,
+    /// and its recording is written to disk together with the response.
+    /// If a [`ProgressTracker`] is given, its progress is reported in pending responses.
+    ///
+    /// Returns `None` if the `SymbolicationActor` is already processing the
+    /// maximum number of requests, as given by `max_concurrent_requests`.
+    fn create_symbolication_request<F, Fut>(
+        &self,
+        task_name: &'static str,
+        options: RequestOptions,
+        mut metadata: RequestMetadata,
+        progress: Option<ProgressTracker>,
+        f: F,
+    ) -> Result<RequestId, MaxRequestsError>
+    where
+        F: FnOnce(Instant) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<CompletedResponse, anyhow::Error>> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+
+        // Tag all events of this request, including the one captured for a panic, so that a
+        // failed response can be correlated with its event.
+        let reference_id = Uuid::new_v4().simple().to_string();
+        hub.configure_scope(|scope| scope.set_tag("reference_id", &reference_id));
+
+        // Assume that there are no UUID4 collisions in practice.
+        let requests = Arc::clone(&self.inner.requests);
+        let current_requests = Arc::clone(&self.inner.current_requests);
+        let request_finished = Arc::clone(&self.inner.request_finished);
+        let result_store = Arc::clone(&self.inner.result_store);
+        let journal = metadata.journal.take();
+        let callbacks = self.inner.callbacks.clone();
+        let queue = self.inner.queue.clone();
+
+        let num_requests = current_requests.load(Ordering::Relaxed);
+        metric!(gauge("requests.in_flight") = num_requests as u64);
+        metric!(gauge("requests.waiting") = queue.waiting() as u64);
+
+        // Reject the request if `requests` already contains `max_concurrent_requests` elements.
         if let Some(max_concurrent_requests) = self.inner.max_concurrent_requests {
             if num_requests >= max_concurrent_requests {
                 metric!(counter("requests.rejected") += 1);
+                // The client is told to retry, so the request must not be recovered.
+                if let Some(journal) = journal {
+                    journal.discard();
+                }
                 return Err(MaxRequestsError);
             }
         }
 
-        let request_id = RequestId::new(uuid::Uuid::new_v4());
-        requests
-            .lock()
-            .unwrap()
-            .insert(request_id, receiver.shared());
+        let result_ttl = options
+            .result_ttl
+            .map_or(DEFAULT_RESULT_TTL, Duration::from_secs)
+            .min(self.inner.config.max_result_ttl);
+
+        let request_id = metadata
+            .request_id
+            .unwrap_or_else(|| RequestId::new(Uuid::new_v4()));
+        let (abort, abort_registration) = future::AbortHandle::new_pair();
+        let settled = Arc::new(AtomicBool::new(false));
+        let computation = Computation {
+            channel: receiver.shared(),
+            progress,
+            affinity: affinity_token(&metadata.scope),
+            abort,
+            settled: Arc::clone(&settled),
+        };
+        requests.lock().unwrap().insert(request_id, computation);
         current_requests.fetch_add(1, Ordering::Relaxed);
         let drop_hub = hub.clone();
         let token = CallOnDrop::new(move || {
@@ -422,26 +1464,90 @@ impl RequestService {
         let request_future = async move {
             metric!(timer("symbolication.create_request.first_poll") = spawn_time.elapsed());
 
-            let f = tokio::time::timeout(Duration::from_secs(3600), f);
-            let f = measure(task_name, m::timed_result, f);
+            let priority = options.priority;
+            let run = async move {
+                // The permit is released once the computation is done, so the next waiting
+                // request can start while this one is wrapped up.
+                let _permit = queue.acquire(priority).await;
+                metric!(
+                    timer("requests.queued") = spawn_time.elapsed(),
+                    "priority" => priority.as_str(),
+                );
+
+                // Like the timeout, the deadline for partial results starts once the request
+                // runs, so that the time spent waiting in the queue does not count against it.
+                let f = f(Instant::now() + PARTIAL_RESULTS_AFTER);
+
+                // Panics are isolated to this request, and turned into an internal error response.
+                let f = std::panic::AssertUnwindSafe(f).catch_unwind();
+                let f = tokio::time::timeout(REQUEST_TIMEOUT, f);
+                measure(task_name, m::timed_result, f).await
+            };
 
-            // This flattens the `Result<Result<_, Error>, Timeout>` into a
-            // `Result<_, SymbolicationError>` so we can match on it more easily.
-            let result = f
-                .await
-                .map(|inner| inner.map_err(SymbolicationError::from))
-                .unwrap_or(Err(SymbolicationError::Timeout));
+            // This flattens the `Result<Result<Result<Result<_, Error>, Panic>, Timeout>, Aborted>`
+            // into a `Result<_, SymbolicationError>` so we can match on it more easily.
+            // A request cancelled right as it finished is still reported as cancelled, as it has
+            // already been removed from `requests`.
+            let result = match future::Abortable::new(run, abort_registration).await {
+                _ if settled.swap(true, Ordering::AcqRel) => Err(SymbolicationError::Cancelled),
+                Ok(Ok(Ok(inner))) => inner.map_err(SymbolicationError::from),
+                Ok(Ok(Err(_panic))) => Err(SymbolicationError::Panic { reference_id }),
+                Ok(Err(_elapsed)) => Err(SymbolicationError::Timeout),
+                Err(future::Aborted) => Err(SymbolicationError::Cancelled),
+            };
+            let cancelled = matches!(result, Err(SymbolicationError::Cancelled));
+
+            // Report the end-to-end duration, including the time the request spent waiting to
+            // be scheduled, broken down by endpoint and outcome.
+            let outcome = match &result {
+                Ok(_) => "completed",
+                Err(SymbolicationError::Timeout) => "timeout",
+                Err(SymbolicationError::Cancelled) => "cancelled",
+                Err(SymbolicationError::Failed(_)) => "failed",
+                Err(SymbolicationError::Panic { .. }) => "panicked",
+            };
+            metric!(
+                histogram("requests.duration") = spawn_time.elapsed().as_millis() as u64,
+                "endpoint" => task_name,
+                "outcome" => outcome,
+            );
 
             let response = match result {
                 Ok(mut response) => {
                     if !options.dif_candidates {
-                        if let CompletedResponse::NativeSymbolication(ref mut res) = response {
-                            clear_dif_candidates(res)
+                        match response {
+                            CompletedResponse::NativeSymbolication(ref mut res) => {
+                                clear_dif_candidates(&mut res.modules)
+                            }
+                            CompletedResponse::HybridSymbolication(ref mut res) => {
+                                clear_dif_candidates(&mut res.modules)
+                            }
+                            CompletedResponse::ProfileSymbolication(ref mut res) => {
+                                clear_dif_candidates(&mut res.modules)
+                            }
+                            CompletedResponse::ProfileChunkSymbolication(ref mut res) => {
+                                clear_dif_candidates(&mut res.modules)
+                            }
+                            CompletedResponse::JsSymbolication(_)
+                            | CompletedResponse::JvmSymbolication(_)
+                            | CompletedResponse::SentryEvent(_) => {}
+                        }
+                    }
+                    if let CompletedResponse::NativeSymbolication(ref mut res) = response {
+                        res.trace = metadata.trace.events();
+                    }
+                    if options.output == OutputFormat::SentryEvent {
+                        if let CompletedResponse::NativeSymbolication(res) = response {
+                            response = CompletedResponse::SentryEvent(res.into_sentry_event());
                         }
                     }
                     sentry::end_session_with_status(SessionStatus::Exited);
                     SymbolicationResponse::Completed(Box::new(response))
                 }
+                Err(SymbolicationError::Cancelled) => {
+                    sentry::end_session_with_status(SessionStatus::Exited);
+                    SymbolicationResponse::Cancelled
+                }
                 Err(error) => {
                     // a timeout is an abnormal session exit, all other errors are considered "crashed"
                     let status = match &error {
@@ -452,20 +1558,51 @@ impl RequestService {
 
                     let response = SymbolicationResponse::from(&error);
                     let error = anyhow::Error::new(error);
-                    tracing::error!("Symbolication error: {:?}", error);
+                    sentry::with_scope(
+                        |scope| metadata.to_scope(request_id, task_name, scope),
+                        || tracing::error!("Symbolication error: {:?}", error),
+                    );
                     response
                 }
             };
 
+            let recording = metadata
+                .recording
+                .map(|recording| recording.finish(&response));
+            let stored_response = result_store.get().and_then(|store| {
+                let content = serialize_stored_response(store.ttl(), &response)?;
+                Some((store, content))
+            });
+            let callback = options.callback_url.and_then(|url| {
+                let content = serde_json::to_vec(&response).ok()?;
+                Some((url, content))
+            });
+
             sender.send((Instant::now(), response)).ok();
 
             // We stop counting the request as an in-flight request at this point, even though
-            // it will stay in the `requests` map for another 90s.
+            // it will stay in the `requests` map for another `result_ttl`.
             current_requests.fetch_sub(1, Ordering::Relaxed);
+            request_finished.notify_one();
+
+            if let Some(recording) = recording {
+                recording.await;
+            }
+            if let Some((store, content)) = stored_response {
+                store.store(&request_id.to_string(), content).await;
+            }
+            if let Some(journal) = journal {
+                journal.complete().await;
+            }
+            if let Some((url, content)) = callback {
+                callbacks.deliver(&url, request_id, content).await;
+            }
 
             // Wait before removing the channel from the computation map to allow clients to
-            // poll the status.
-            tokio::time::sleep(MAX_POLL_DELAY).await;
+            // poll the status. Cancelled requests have already been removed.
+            if !cancelled {
+                tokio::time::sleep(result_ttl).await;
+            }
 
             drop(token);
         }
@@ -479,8 +1616,102 @@ impl RequestService {
     }
 }
 
-/// The maximum delay we allow for polling a finished request before dropping it.
-const MAX_POLL_DELAY: Duration = Duration::from_secs(90);
+/// The maximum time a request may take before it is aborted with a [`SymbolicationResponse::Timeout`].
+///
+/// This starts once the request runs, and does not include the time it waited in the queue for
+/// `max_running_requests`.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// The time after which native symbolication stops waiting for outstanding downloads.
+///
+/// This leaves some headroom before [`REQUEST_TIMEOUT`] to symbolicate with whatever was fetched
+/// until then, and return a partial response instead of throwing away all the work done so far.
+const PARTIAL_RESULTS_AFTER: Duration = Duration::from_secs(3300);
+
+/// The delay we allow for polling a finished request before dropping it, unless the request
+/// asks for a different [`result_ttl`](RequestOptions::result_ttl).
+const DEFAULT_RESULT_TTL: Duration = Duration::from_secs(90);
+
+/// The seconds after which a pending request should be polled again, as long as nothing has
+/// been downloaded from the configured sources.
+const DEFAULT_RETRY_AFTER: usize = 30;
+
+/// The fewest seconds after which a pending request should be polled again.
+const MIN_RETRY_AFTER: usize = 1;
+
+/// The most seconds after which a pending request should be polled again.
+const MAX_RETRY_AFTER: usize = 60;
+
+/// A response in the [`ResultStore`], along with the time it can be polled until.
+#[derive(Serialize, Deserialize)]
+struct StoredResponse<R> {
+    /// The time after which the response is no longer returned, in seconds since the UNIX epoch.
+    expires: u64,
+    response: R,
+}
+
+/// A [`SymbolicationResponse`] as written to the [`ResultStore`].
+///
+/// Completed responses are stored along with their type, so that they are loaded as the same
+/// variant of [`CompletedResponse`] again.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data", rename_all = "snake_case")]
+enum StoredResult {
+    Completed(#[serde(with = "TaggedCompletedResponse")] CompletedResponse),
+    Other(SymbolicationResponse),
+}
+
+impl From<SymbolicationResponse> for StoredResult {
+    fn from(response: SymbolicationResponse) -> Self {
+        match response {
+            SymbolicationResponse::Completed(completed) => Self::Completed(*completed),
+            response => Self::Other(response),
+        }
+    }
+}
+
+impl From<StoredResult> for SymbolicationResponse {
+    fn from(stored: StoredResult) -> Self {
+        match stored {
+            StoredResult::Completed(completed) => Self::Completed(Box::new(completed)),
+            StoredResult::Other(response) => response,
+        }
+    }
+}
+
+/// Serializes a response to be written to the [`ResultStore`].
+fn serialize_stored_response(ttl: Duration, response: &SymbolicationResponse) -> Option<Vec<u8>> {
+    let expires = (SystemTime::now() + ttl).duration_since(UNIX_EPOCH).ok()?;
+    let stored = StoredResponse {
+        expires: expires.as_secs(),
+        response: StoredResult::from(response.clone()),
+    };
+    serde_json::to_vec(&stored).ok()
+}
+
+/// Loads the response of a request from the [`ResultStore`], unless it has expired.
+///
+/// The storage backends do not expire responses themselves, so this has to be checked here.
+async fn load_stored_response(
+    store: &ResultStore,
+    request_id: RequestId,
+) -> Option<SymbolicationResponse> {
+    let content = store.fetch(&request_id.to_string()).await?;
+    let stored: StoredResponse<StoredResult> = serde_json::from_slice(&content).ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    (stored.expires > now.as_secs()).then(|| stored.response.into())
+}
+
+/// Returns the affinity token for requests of the given `scope`.
+///
+/// The token is a stable hash of the scope, so it is the same across all instances and
+/// restarts, without revealing the scope itself.
+pub fn affinity_token(scope: &Scope) -> String {
+    let digest = Sha256::digest(scope.as_ref().as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    format!("{:016x}", u64::from_be_bytes(bytes))
+}
 
 /// An error returned when symbolicator receives a request while already processing
 /// the maximum number of requests.
@@ -490,20 +1721,26 @@ pub struct MaxRequestsError;
 
 async fn wrap_response_channel(
     request_id: RequestId,
-    timeout: Option<u64>,
-    channel: ComputationChannel,
+    timeout: Option<Duration>,
+    retry_after: usize,
+    computation: Computation,
 ) -> SymbolicationResponse {
+    let Computation {
+        channel,
+        progress,
+        affinity,
+        ..
+    } = computation;
+
     let channel_result = if let Some(timeout) = timeout {
-        match tokio::time::timeout(Duration::from_secs(timeout), channel).await {
+        match tokio::time::timeout(timeout, channel).await {
             Ok(outcome) => outcome,
             Err(_elapsed) => {
                 return SymbolicationResponse::Pending {
                     request_id,
-                    // We should estimate this better, but at some point the
-                    // architecture will probably change to pushing results on a
-                    // queue instead of polling so it's unlikely we'll ever do
-                    // better here.
-                    retry_after: 30,
+                    retry_after,
+                    progress: progress.map(|progress| progress.snapshot()),
+                    affinity: Some(affinity),
                 };
             }
         }
@@ -516,9 +1753,9 @@ async fn wrap_response_channel(
             metric!(timer("requests.response_idling") = finished_at.elapsed());
             response
         }
-        // If the sender is dropped, this is likely due to a panic that is captured at the source.
-        // Therefore, we do not need to capture an error at this point.
-        Err(_canceled) => SymbolicationResponse::InternalError,
+        // Panics are caught within the request, so the sender is only dropped if the request was
+        // aborted, for example during shutdown. There is no event to refer to in that case.
+        Err(_canceled) => SymbolicationResponse::InternalError { reference_id: None },
     }
 }
 
@@ -549,7 +1786,11 @@ pub fn record_task_metrics(name: &str, metrics: &tokio_metrics::TaskMetrics) {
 
 #[cfg(test)]
 mod tests {
-    use symbolicator_service::types::{CompleteObjectInfo, RawFrame};
+    use symbolicator_service::config::ReservedIpsPolicy;
+    use symbolicator_service::types::{
+        CompleteObjectInfo, CompletedHybridSymbolicationResponse, FrameStatus, RawFrame,
+        SymbolicatedFrame, SymbolicatedHybridFrame, SymbolicatedHybridStacktrace,
+    };
     use symbolicator_service::utils::hex::HexValue;
     use symbolicator_sources::ObjectType;
 
@@ -585,6 +1826,7 @@ mod tests {
             origin: StacktraceOrigin::Symbolicate,
             sources: Arc::new([]),
             scope: Default::default(),
+            kernel_space: KernelSpace::Unknown,
         };
 
         let request_id = service
@@ -601,6 +1843,490 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_result_ttl() {
+        test::setup();
+
+        let config = Config {
+            max_result_ttl: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        // The requested TTL is capped to the configured `max_result_ttl`.
+        let options = RequestOptions {
+            result_ttl: Some(60),
+            ..Default::default()
+        };
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), options)
+            .unwrap();
+
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(
+            matches!(&response, SymbolicationResponse::Completed(_)),
+            "Not a complete response: {response:#?}"
+        );
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(service.get_response(request_id, None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compare_with() {
+        use axum::routing::post;
+        use axum::Router;
+
+        test::setup();
+
+        // The other Symbolicator receives the request, but never responds.
+        let (sender, receiver) = oneshot::channel();
+        let sender = Arc::new(Mutex::new(Some(sender)));
+        let handler = move |body: String| async move {
+            if let Some(sender) = sender.lock().unwrap().take() {
+                sender.send(body).ok();
+            }
+            future::pending::<()>().await
+        };
+        let other = test::Server::with_router(Router::new().route("/symbolicate", post(handler)));
+
+        let config = Config {
+            comparison_targets: vec![other.url("/")],
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let options = RequestOptions {
+            compare_with: Some(other.url("/")),
+            ..Default::default()
+        };
+        let sources = vec![test::local_source()];
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(sources), options)
+            .unwrap();
+
+        // Our response does not wait for the comparison.
+        let response = tokio::time::timeout(
+            Duration::from_secs(5),
+            service.get_response(request_id, None),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            response,
+            Some(SymbolicationResponse::Completed(_))
+        ));
+
+        // The other Symbolicator is sent the same sources.
+        let forwarded = tokio::time::timeout(Duration::from_secs(5), receiver)
+            .await
+            .unwrap()
+            .unwrap();
+        let forwarded: serde_json::Value = serde_json::from_str(&forwarded).unwrap();
+        assert_eq!(forwarded["sources"][0]["id"], "local");
+    }
+
+    #[tokio::test]
+    async fn test_capture_trace() {
+        use symbolicator_service::services::symbolication::TraceEventKind;
+        use symbolicator_service::types::{FrameStatus, ObjectFileStatus};
+
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+
+        let options = RequestOptions {
+            capture_trace: true,
+            ..Default::default()
+        };
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), options)
+            .unwrap();
+        let response = match service.get_response(request_id, None).await.unwrap() {
+            SymbolicationResponse::Completed(response) => *response,
+            response => panic!("Not a complete response: {response:#?}"),
+        };
+        let CompletedResponse::NativeSymbolication(response) = response else {
+            panic!("Not a native response");
+        };
+
+        let trace = response.trace.unwrap();
+        assert!(trace.iter().any(|event| matches!(
+            event.kind,
+            TraceEventKind::DebugInfo {
+                module: 0,
+                status: ObjectFileStatus::Missing,
+                ..
+            }
+        )));
+        assert!(trace.iter().any(|event| matches!(
+            event.kind,
+            TraceEventKind::UnsymbolicatedFrame {
+                thread: 0,
+                frame: 0,
+                status: FrameStatus::Missing,
+                module: Some(0),
+                ..
+            }
+        )));
+
+        // Without the option, no trace is returned.
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), Default::default())
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(matches!(
+            response,
+            SymbolicationResponse::Completed(ref res) if matches!(
+                **res,
+                CompletedResponse::NativeSymbolication(ref res) if res.trace.is_none()
+            )
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_callback_url() {
+        use axum::routing::post;
+        use axum::Router;
+
+        test::setup();
+
+        let (sender, receiver) = oneshot::channel();
+        let sender = Arc::new(Mutex::new(Some(sender)));
+        let handler = move |body: String| async move {
+            if let Some(sender) = sender.lock().unwrap().take() {
+                sender.send(body).ok();
+            }
+        };
+        let server = test::Server::with_router(Router::new().route("/callback", post(handler)));
+
+        let config = Config {
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let options = RequestOptions {
+            callback_url: Some(server.url("/callback")),
+            ..Default::default()
+        };
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), options)
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+
+        let delivered = tokio::time::timeout(Duration::from_secs(5), receiver)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivered, serde_json::to_string(&response).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_result_storage() {
+        test::setup();
+
+        let storage_dir = test::tempdir();
+        let result_storage = serde_json::json!({
+            "filesystem": {"path": storage_dir.path()},
+            "ttl": "1h",
+        });
+        let config = Config {
+            max_result_ttl: Duration::ZERO,
+            result_storage: Some(serde_json::from_value(result_storage).unwrap()),
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        // The storage backend is initialized in the background.
+        while service.inner.result_store.get().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), Default::default())
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(
+            matches!(&response, SymbolicationResponse::Completed(_)),
+            "Not a complete response: {response:#?}"
+        );
+
+        // Once the request is dropped from memory, its response is loaded from the storage.
+        let requests = &service.inner.requests;
+        while requests.lock().unwrap().contains_key(&request_id) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let response = service.get_response(request_id, None).await;
+        assert!(
+            matches!(&response, Some(SymbolicationResponse::Completed(_))),
+            "Not a stored response: {response:#?}"
+        );
+    }
+
+    #[test]
+    fn test_stored_hybrid_response() {
+        // A hybrid response with only native frames, which is indistinguishable from a native
+        // response by its contents.
+        let frame = SymbolicatedFrame {
+            status: FrameStatus::Symbolicated,
+            original_index: Some(0),
+            raw: RawFrame {
+                instruction_addr: HexValue(0x1000),
+                ..Default::default()
+            },
+        };
+        let completed = CompletedHybridSymbolicationResponse {
+            stacktraces: vec![SymbolicatedHybridStacktrace {
+                frames: vec![SymbolicatedHybridFrame::Native(frame)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let response = SymbolicationResponse::Completed(Box::new(completed.into()));
+
+        let content = serialize_stored_response(Duration::from_secs(60), &response).unwrap();
+        let stored: StoredResponse<StoredResult> = serde_json::from_slice(&content).unwrap();
+        let response = SymbolicationResponse::from(stored.response);
+
+        let SymbolicationResponse::Completed(completed) = response else {
+            panic!("Not a complete response: {response:#?}");
+        };
+        let CompletedResponse::HybridSymbolication(completed) = *completed else {
+            panic!("Not a hybrid response: {completed:#?}");
+        };
+        assert_eq!(completed.stacktraces[0].frames.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_poll_timeout() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            max_poll_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let hitcounter = test::Server::new();
+        let source = hitcounter.source("pending", "/delay/1h/");
+
+        let request = get_symbolication_request(vec![source]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+
+        // Polls without a timeout or with a larger one are capped by `max_poll_timeout`.
+        for timeout in [None, Some(3600)] {
+            let response = service.get_response(request_id, timeout).await.unwrap();
+            assert!(
+                matches!(&response, SymbolicationResponse::Pending { .. }),
+                "Not a pending response: {response:#?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_affinity() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            ..Default::default()
+        };
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let hitcounter = test::Server::new();
+        let source = hitcounter.source("pending", "/delay/1h/");
+
+        let request = get_symbolication_request(vec![source]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+
+        let response = service.get_response(request_id, Some(0)).await.unwrap();
+        let SymbolicationResponse::Pending { affinity, .. } = response else {
+            panic!("Not a pending response: {response:#?}");
+        };
+        assert_eq!(affinity, Some(affinity_token(&Scope::Global)));
+    }
+
+    #[tokio::test]
+    async fn test_panic_isolation() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+
+        let request_id = service
+            .create_symbolication_request(
+                "panic",
+                RequestOptions::default(),
+                RequestMetadata::default(),
+                None,
+                |_| future::lazy(|_| -> Result<CompletedResponse> { panic!("oops") }),
+            )
+            .unwrap();
+
+        let response = service.get_response(request_id, None).await.unwrap();
+        let SymbolicationResponse::InternalError { reference_id } = response else {
+            panic!("Not an internal error: {response:#?}");
+        };
+        assert_eq!(reference_id.map(|id| id.len()), Some(32));
+
+        // The service keeps processing other requests.
+        let request_id = service
+            .symbolicate_stacktraces(get_symbolication_request(vec![]), RequestOptions::default())
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(
+            matches!(&response, SymbolicationResponse::Completed(_)),
+            "Not a complete response: {response:#?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deadline_excludes_queue_time() {
+        test::setup();
+
+        let config = Config {
+            max_running_requests: Some(1),
+            ..Config::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        // The first request keeps the only running slot until it is released.
+        let (release, released) = oneshot::channel::<()>();
+        service
+            .create_symbolication_request(
+                "blocking",
+                RequestOptions::default(),
+                RequestMetadata::default(),
+                None,
+                |_| async move {
+                    released.await.ok();
+                    Err(anyhow::anyhow!("released"))
+                },
+            )
+            .unwrap();
+
+        let (sender, receiver) = oneshot::channel();
+        service
+            .create_symbolication_request(
+                "queued",
+                RequestOptions::default(),
+                RequestMetadata::default(),
+                None,
+                |deadline| async move {
+                    sender.send(deadline).ok();
+                    Err(anyhow::anyhow!("queued"))
+                },
+            )
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let released_at = Instant::now();
+        release.send(()).unwrap();
+
+        let deadline = receiver.await.unwrap();
+        assert!(deadline >= released_at + PARTIAL_RESULTS_AFTER);
+    }
+
+    /// Serializes `value`, reads it back through the client's type `C`, and returns the value
+    /// serialized by the client.
+    fn client_roundtrip<T, C>(value: &T) -> serde_json::Value
+    where
+        T: Serialize,
+        C: Serialize + serde::de::DeserializeOwned,
+    {
+        let json = serde_json::to_value(value).unwrap();
+        let client: C = serde_json::from_value(json).unwrap();
+        serde_json::to_value(client).unwrap()
+    }
+
+    #[test]
+    fn test_client_request_options() {
+        let url: Url = "https://symbolicator.example.com/".parse().unwrap();
+        let options = RequestOptions {
+            dif_candidates: true,
+            capture_trace: true,
+            result_ttl: Some(60),
+            output: OutputFormat::SentryEvent,
+            callback_url: Some(url.clone()),
+            priority: RequestPriority::High,
+            compare_with: Some(url),
+            symbolication: SymbolicationOptions {
+                function_sizes: true,
+                demangle_parameters: Some(false),
+                ..SymbolicationOptions::default()
+            },
+        };
+        let json = client_roundtrip::<_, symbolicator_client::RequestOptions>(&options);
+        assert_eq!(json, serde_json::to_value(&options).unwrap());
+
+        // The client leaves out unset options, which the server reads as their defaults.
+        let json =
+            client_roundtrip::<_, symbolicator_client::RequestOptions>(&RequestOptions::default());
+        let options: RequestOptions = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            serde_json::to_value(RequestOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_client_responses() {
+        let responses = [
+            SymbolicationResponse::Pending {
+                request_id: RequestId::new(Uuid::new_v4()),
+                retry_after: 30,
+                progress: Some(SymbolicationProgress::default()),
+                affinity: Some(affinity_token(&Scope::Global)),
+            },
+            SymbolicationResponse::Completed(Box::new(CompletedResponse::NativeSymbolication(
+                Default::default(),
+            ))),
+            SymbolicationResponse::Failed {
+                message: "failed".into(),
+            },
+            SymbolicationResponse::Timeout,
+            SymbolicationResponse::Cancelled,
+            SymbolicationResponse::InternalError {
+                reference_id: Some("abc".into()),
+            },
+        ];
+        for response in responses {
+            let json = client_roundtrip::<_, symbolicator_client::SymbolicationResponse>(&response);
+            assert_eq!(json, serde_json::to_value(&response).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_affinity_token() {
+        let token = affinity_token(&Scope::Scoped("1234".into()));
+        assert_eq!(token.len(), 16);
+        assert_eq!(token, affinity_token(&Scope::Scoped("1234".into())));
+        assert_ne!(token, affinity_token(&Scope::Scoped("1235".into())));
+        assert_ne!(token, affinity_token(&Scope::Global));
+    }
+
     fn get_symbolication_request(sources: Vec<SourceConfig>) -> SymbolicateStacktraces {
         SymbolicateStacktraces {
             scope: Scope::Global,
@@ -619,11 +2345,13 @@ mod tests {
                 code_id: Some("502fc0a51ec13e479998684fa139dca7".to_owned().to_lowercase()),
                 debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".to_owned()),
                 image_addr: HexValue(0x1_0000_0000),
+                slide: None,
                 image_size: Some(4096),
                 code_file: None,
                 debug_file: None,
                 debug_checksum: None,
             })],
+            kernel_space: KernelSpace::Unknown,
         }
     }
 
@@ -635,7 +2363,7 @@ mod tests {
 
         let config = Config {
             cache_dir: Some(cache_dir.path().to_owned()),
-            connect_to_reserved_ips: true,
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
             max_concurrent_requests: Some(2),
             ..Default::default()
         };
@@ -663,4 +2391,62 @@ mod tests {
             .symbolicate_stacktraces(request, RequestOptions::default())
             .is_err());
     }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        test::setup();
+
+        let config = Config {
+            connect_to_reserved_ips: ReservedIpsPolicy::Allow,
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let hitcounter = test::Server::new();
+        let source = hitcounter.source("pending", "/delay/1h/");
+
+        let request = get_symbolication_request(vec![source.clone()]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+        let poll = tokio::spawn({
+            let service = service.clone();
+            async move { service.get_response(request_id, None).await }
+        });
+
+        assert!(service.cancel(request_id));
+        let response = poll.await.unwrap().unwrap();
+        assert!(matches!(response, SymbolicationResponse::Cancelled));
+
+        // The request is gone, and no longer counts towards `max_concurrent_requests`.
+        assert!(!service.cancel(request_id));
+        assert!(service.get_response(request_id, Some(0)).await.is_none());
+        assert_eq!(service.in_flight_requests(), 0);
+        let request = get_symbolication_request(vec![source]);
+        assert!(service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_finished() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+
+        let request = get_symbolication_request(vec![]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+        let response = service.get_response(request_id, None).await.unwrap();
+        assert!(matches!(response, SymbolicationResponse::Completed(_)));
+
+        // Finished requests are not cancelled, and their response can still be polled.
+        assert!(!service.cancel(request_id));
+        let response = service.get_response(request_id, Some(0)).await.unwrap();
+        assert!(matches!(response, SymbolicationResponse::Completed(_)));
+    }
 }
@@ -0,0 +1,299 @@
+//! Named source configurations which are stored on the server.
+//!
+//! Source configurations are stored per [`Scope`] through the admin API, and requests of that
+//! scope can reference them by name instead of sending the full list of sources every time.
+//!
+//! If `stored_sources` is configured, all stored configurations are persisted to that file and
+//! loaded again on startup. If `encryption` is configured as well, the credentials of the
+//! sources are encrypted in the file.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::{Context, Result};
+use symbolicator_service::utils::encryption::MasterKey;
+use symbolicator_sources::SourceConfig;
+
+use crate::service::Scope;
+
+/// The fields of sources which contain credentials.
+///
+/// These are encrypted when persisted or journaled, and redacted in the responses of the admin API.
+pub const CREDENTIAL_FIELDS: &[&str] = &[
+    "headers",
+    "token",
+    "access_key",
+    "secret_key",
+    "sse_customer_key",
+    "password",
+    "private_key",
+];
+
+/// The placeholder replacing credentials in the responses of the admin API.
+pub const REDACTED: &str = "[redacted]";
+
+/// The stored source configurations, keyed by scope and name.
+type StoredSourcesMap = BTreeMap<String, BTreeMap<String, Arc<[SourceConfig]>>>;
+
+/// A store of named source configurations per scope.
+#[derive(Debug, Default)]
+pub struct SourceStore {
+    /// The file persisting the stored configurations, if any.
+    path: Option<PathBuf>,
+    /// The key encrypting the credentials in the file, if any.
+    key: Option<Arc<MasterKey>>,
+    sources: RwLock<StoredSourcesMap>,
+    /// Serializes modifications, so that the file is always written in the order of updates.
+    write_lock: Mutex<()>,
+}
+
+impl SourceStore {
+    /// Creates a store persisted to `path`, loading the configurations already stored there.
+    ///
+    /// Without a `path`, configurations are kept in memory only. With a `key`, credentials are
+    /// encrypted in the file.
+    pub fn load(path: Option<&Path>, key: Option<Arc<MasterKey>>) -> Result<Self> {
+        let sources = match path {
+            Some(path) if path.exists() => {
+                let data = std::fs::read(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let mut value: serde_json::Value = serde_json::from_slice(&data)
+                    .with_context(|| format!("failed to parse {}", path.display()))?;
+                if let Some(ref key) = key {
+                    key.decrypt_json(&mut value)
+                        .with_context(|| format!("failed to decrypt {}", path.display()))?;
+                }
+                serde_json::from_value(value)
+                    .with_context(|| format!("failed to parse {}", path.display()))?
+            }
+            _ => StoredSourcesMap::new(),
+        };
+
+        Ok(Self {
+            path: path.map(Path::to_owned),
+            key,
+            sources: RwLock::new(sources),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Returns the names of the configurations stored for `scope`.
+    pub fn names(&self, scope: &Scope) -> Vec<String> {
+        let sources = self.sources.read().unwrap();
+        sources
+            .get(scope.as_ref())
+            .map(|stored| stored.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the configuration stored for `scope` under `name`.
+    pub fn get(&self, scope: &Scope, name: &str) -> Option<Arc<[SourceConfig]>> {
+        let sources = self.sources.read().unwrap();
+        sources.get(scope.as_ref())?.get(name).cloned()
+    }
+
+    /// Stores a configuration for `scope` under `name`, replacing any previous one.
+    pub fn put(&self, scope: &Scope, name: &str, config: Arc<[SourceConfig]>) -> Result<()> {
+        self.modify(|sources| {
+            sources
+                .entry(scope.as_ref().to_owned())
+                .or_default()
+                .insert(name.to_owned(), config);
+            true
+        })
+        .map(|_| ())
+    }
+
+    /// Removes the configuration stored for `scope` under `name`.
+    ///
+    /// Returns `false` if no such configuration was stored.
+    pub fn delete(&self, scope: &Scope, name: &str) -> Result<bool> {
+        self.modify(|sources| {
+            let Some(stored) = sources.get_mut(scope.as_ref()) else {
+                return false;
+            };
+            let removed = stored.remove(name).is_some();
+            if stored.is_empty() {
+                sources.remove(scope.as_ref());
+            }
+            removed
+        })
+    }
+
+    /// Applies a modification, and persists the result if `modify` reports a change.
+    ///
+    /// If persisting fails, the modification is rolled back.
+    fn modify<F>(&self, modify: F) -> Result<bool>
+    where
+        F: FnOnce(&mut StoredSourcesMap) -> bool,
+    {
+        let _guard = self.write_lock.lock().unwrap();
+
+        let mut updated = self.sources.read().unwrap().clone();
+        if !modify(&mut updated) {
+            return Ok(false);
+        }
+        if let Some(ref path) = self.path {
+            persist(path, &updated, self.key.as_ref())?;
+        }
+
+        *self.sources.write().unwrap() = updated;
+        Ok(true)
+    }
+}
+
+/// Serializes `sources`, replacing all of their non-empty credentials with [`REDACTED`].
+pub fn redact_credentials(sources: &[SourceConfig]) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(sources)?;
+    redact_json_fields(&mut value);
+    Ok(value)
+}
+
+/// Redacts all [`CREDENTIAL_FIELDS`] within a JSON `value`.
+fn redact_json_fields(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_json_fields),
+        serde_json::Value::Object(object) => {
+            for (name, value) in object.iter_mut() {
+                if CREDENTIAL_FIELDS.contains(&name.as_str()) {
+                    redact_json(value);
+                } else {
+                    redact_json_fields(value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Redacts all strings contained in a JSON `value`.
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) if !s.is_empty() => *s = REDACTED.to_owned(),
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_json),
+        serde_json::Value::Object(object) => object.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Writes the stored configurations to `path`, encrypting their credentials with `key`.
+fn persist(path: &Path, sources: &StoredSourcesMap, key: Option<&MasterKey>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut value = serde_json::to_value(sources)?;
+    if let Some(key) = key {
+        key.encrypt_json_fields(&mut value, CREDENTIAL_FIELDS)?;
+    }
+
+    // Write to a temporary file first, so that a crash never leaves a partial file behind.
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, serde_json::to_vec(&value)?)
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use symbolicator_sources::{CommonSourceConfig, FilesystemSourceConfig, SourceId};
+
+    use super::*;
+    use crate::test;
+
+    fn sources(id: &str) -> Arc<[SourceConfig]> {
+        let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+            id: SourceId::new(id),
+            path: PathBuf::from("/symbols"),
+            index_ttl: None,
+            files: CommonSourceConfig::default(),
+        }));
+        Arc::from(vec![source])
+    }
+
+    #[test]
+    fn test_source_store() {
+        let dir = test::tempdir();
+        let path = dir.path().join("sources.json");
+        let scope = Scope::Scoped("1".into());
+
+        let store = SourceStore::load(Some(&path), None).unwrap();
+        store.put(&scope, "builds", sources("builds")).unwrap();
+        store.put(&scope, "nightly", sources("nightly")).unwrap();
+        assert_eq!(store.names(&scope), ["builds", "nightly"]);
+        assert!(store.names(&Scope::Global).is_empty());
+        assert!(store.get(&Scope::Global, "builds").is_none());
+
+        // The configurations are loaded again from disk.
+        let store = SourceStore::load(Some(&path), None).unwrap();
+        let stored = store.get(&scope, "builds").unwrap();
+        assert_eq!(stored[0].id().as_str(), "builds");
+
+        assert!(store.delete(&scope, "builds").unwrap());
+        assert!(!store.delete(&scope, "builds").unwrap());
+
+        let store = SourceStore::load(Some(&path), None).unwrap();
+        assert_eq!(store.names(&scope), ["nightly"]);
+    }
+
+    #[test]
+    fn test_source_store_encrypted() {
+        let dir = test::tempdir();
+        let path = dir.path().join("sources.json");
+        let scope = Scope::Scoped("1".into());
+        let key = || {
+            MasterKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=")
+                .ok()
+                .map(Arc::new)
+        };
+
+        let source: SourceConfig = serde_json::from_value(serde_json::json!({
+            "type": "sentry",
+            "id": "sentry",
+            "url": "https://sentry.example.com/",
+            "token": "hunter2",
+        }))
+        .unwrap();
+
+        let store = SourceStore::load(Some(&path), key()).unwrap();
+        store
+            .put(&scope, "sentry", Arc::from(vec![source]))
+            .unwrap();
+
+        let persisted = std::fs::read_to_string(&path).unwrap();
+        assert!(persisted.contains("https://sentry.example.com/"));
+        assert!(!persisted.contains("hunter2"));
+
+        let store = SourceStore::load(Some(&path), key()).unwrap();
+        match &store.get(&scope, "sentry").unwrap()[0] {
+            SourceConfig::Sentry(source) => assert_eq!(source.token, "hunter2"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_redact_credentials() {
+        let sources: Vec<SourceConfig> = serde_json::from_value(serde_json::json!([{
+            "type": "sentry",
+            "id": "sentry",
+            "url": "https://sentry.example.com/",
+            "token": "hunter2",
+        }, {
+            "type": "http",
+            "id": "ntlm",
+            "url": "https://symbols.example.com/",
+            "auth": {"type": "ntlm", "username": "CORP\\builder", "password": "hunter2"},
+        }]))
+        .unwrap();
+
+        let redacted = redact_credentials(&sources).unwrap();
+        assert!(!redacted.to_string().contains("hunter2"));
+        assert_eq!(redacted[0]["url"], "https://sentry.example.com/");
+        assert_eq!(redacted[0]["token"], REDACTED);
+        assert_eq!(redacted[1]["auth"]["username"], "CORP\\builder");
+        assert_eq!(redacted[1]["auth"]["password"], REDACTED);
+    }
+}
@@ -58,6 +58,7 @@ async fn main() -> anyhow::Result<()> {
             url: base_url
                 .join(&format!("projects/{org}/{project}/files/dsyms/"))
                 .unwrap(),
+            index_ttl: None,
         }));
 
         sources.push(project_source);
@@ -241,7 +242,7 @@ mod output {
             let instruction_addr = frame.raw.instruction_addr.0;
 
             let module = frame.raw.package.map(|module_file| {
-                let module_addr = self.modules[&module_file].raw.image_addr.0;
+                let module_addr = self.modules[&module_file].base_addr();
                 let module_file = split_path(&module_file).1.into();
                 let module_rel_addr = instruction_addr - module_addr;
 
@@ -545,7 +546,8 @@ mod event {
     use symbolicator_service::types::{
         CompleteObjectInfo, FrameTrust, RawFrame, RawObjectInfo, RawStacktrace, Scope, Signal,
     };
-    use symbolicator_service::utils::{addr::AddrMode, hex::HexValue};
+    use symbolicator_service::utils::addr::{AddrMode, KernelSpace};
+    use symbolicator_service::utils::hex::HexValue;
     use symbolicator_sources::SourceConfig;
 
     pub fn create_symbolication_request(
@@ -600,6 +602,7 @@ mod event {
             origin: StacktraceOrigin::Symbolicate,
             stacktraces,
             modules,
+            kernel_space: KernelSpace::Unknown,
         })
     }
 
@@ -710,6 +713,7 @@ mod event {
             lang: value.lang,
             symbol: value.symbol,
             sym_addr: value.sym_addr,
+            sym_size: None,
             function: value.function,
             filename: value.filename,
             abs_path: value.abs_path,
@@ -717,6 +721,9 @@ mod event {
             pre_context: value.pre_context,
             context_line: value.context_line,
             post_context: value.post_context,
+            source_link: None,
+            in_app: None,
+            package_kind: None,
             trust: value.trust,
         })
     }
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use anyhow::{anyhow, Context, Result};
@@ -17,6 +17,7 @@ use zip::ZipArchive;
 use zstd::stream::copy_encode;
 
 use crate::config::{RunConfig, SortConfig};
+use crate::dyld_cache;
 use crate::utils::{
     create_source_bundle, get_target_filename, get_unified_id, is_bundle_id, make_bundle_id,
 };
@@ -165,6 +166,48 @@ fn process_file(
     Ok(rv)
 }
 
+/// Writes the symbols of all images in a dyld shared cache, and returns their unified IDs.
+fn sort_dyld_cache(
+    sort_config: &SortConfig,
+    path: &Path,
+    bv: ByteView<'static>,
+) -> Result<Vec<String>> {
+    let ignore_error = |err: anyhow::Error| {
+        if RunConfig::get().ignore_errors {
+            eprintln!(
+                "{}: ignored error {:#} ({})",
+                style("error").red().bold(),
+                err,
+                style(path.display()).cyan(),
+            );
+            Ok(())
+        } else {
+            Err(err).context(format!("failed to process file {}", path.display()))
+        }
+    };
+
+    let mut unified_ids = vec![];
+    let images = match dyld_cache::read_images(path, &bv) {
+        Ok(Some(images)) => images,
+        // Subcaches are read along with their main cache.
+        Ok(None) => return Ok(unified_ids),
+        Err(err) => return ignore_error(err).map(|_| unified_ids),
+    };
+
+    for image in images {
+        match image {
+            Ok(image) => {
+                let symbols = ByteView::from_vec(image.to_breakpad().into_bytes());
+                let processed_objects = process_file(sort_config, symbols, image.path)?;
+                unified_ids.extend(processed_objects.into_iter().map(|x| x.0));
+            }
+            Err(err) => ignore_error(err)?,
+        }
+    }
+
+    Ok(unified_ids)
+}
+
 fn sort_files(sort_config: &SortConfig, paths: Vec<PathBuf>) -> Result<(usize, usize)> {
     let mut source_bundles_created = 0;
     let source_candidates = Mutex::new(HashMap::<String, Option<PathBuf>>::new());
@@ -201,6 +244,11 @@ fn sort_files(sort_config: &SortConfig, paths: Vec<PathBuf>) -> Result<(usize, u
                     }
                 }
 
+            // dyld shared cache, whose images are written as breakpad symbols
+            } else if dyld_cache::is_dyld_cache(&bv) {
+                let unified_ids = sort_dyld_cache(sort_config, path, bv)?;
+                debug_ids.lock().unwrap().extend(unified_ids);
+
             // object file directly
             } else if Archive::peek(&bv) != FileFormat::Unknown {
                 for (unified_id, object_kind) in process_file(
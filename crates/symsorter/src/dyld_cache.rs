@@ -0,0 +1,182 @@
+//! Reading the images of dyld shared caches.
+//!
+//! Since macOS 11 and iOS 14, system libraries only exist inside the dyld shared cache and no
+//! longer as standalone files. The cache is not an object file that symbolicator can read, so
+//! the symbols of every image in the cache are written to a Breakpad symbol file instead.
+//!
+//! Recent caches are split into a main cache containing the list of images, several subcaches
+//! named `<main>.1`, `<main>.2`, …, and a `<main>.symbols` file, which all need to be next to each
+//! other. The cache is parsed with [`object`], which reads the symbol tables of the images, but
+//! not the local symbols that were moved out of them into the `.symbols` file. Only 64-bit images
+//! are supported.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use object::macho::{DyldCacheHeader, MachHeader64};
+use object::read::macho::{DyldCache, DyldCacheImage, MachHeader};
+use object::{Endianness, Object, ObjectSegment, ObjectSymbol, SymbolKind};
+use symbolic::common::{Arch, ByteView, CodeId, DebugId, Uuid};
+
+/// The start of the magic of all dyld shared caches, which is followed by the architecture.
+const MAGIC: &[u8] = b"dyld_v1 ";
+
+/// The name of symbols which were moved to the local symbols of the cache.
+const REDACTED: &str = "<redacted>";
+
+/// Returns whether `data` starts like a dyld shared cache.
+pub fn is_dyld_cache(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Reads all images of the cache at `path`, whose contents are given as `data`, along with its
+/// subcaches.
+///
+/// Returns `None` if `path` is a subcache itself, whose images are read via the main cache.
+pub fn read_images(path: &Path, data: &[u8]) -> Result<Option<Vec<Result<Image>>>> {
+    let header = DyldCacheHeader::<Endianness>::parse(data).context("not a dyld shared cache")?;
+    let (_arch, endian) = header.parse_magic()?;
+    if header.images(endian, data)?.is_empty() {
+        return Ok(None);
+    }
+
+    let subcaches = header.subcaches(endian, data)?.map_or(0, <[_]>::len);
+    let mut suffixes: Vec<_> = (1..=subcaches).map(|index| format!(".{index}")).collect();
+    if header.symbols_subcache_uuid(endian).is_some() {
+        suffixes.push(".symbols".to_owned());
+    }
+
+    let subcache_data = suffixes
+        .iter()
+        .map(|suffix| {
+            let path = with_suffix(path, suffix);
+            ByteView::open(&path)
+                .with_context(|| format!("failed to open subcache {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let subcache_data: Vec<&[u8]> = subcache_data.iter().map(|data| &data[..]).collect();
+
+    let cache = DyldCache::<Endianness>::parse(data, &subcache_data)?;
+    let images = cache
+        .images()
+        .map(|image| {
+            let path = image.path()?;
+            read_image(path, &image)
+                .with_context(|| format!("failed to read {path} from the dyld shared cache"))
+        })
+        .collect();
+
+    Ok(Some(images))
+}
+
+fn read_image(path: &str, image: &DyldCacheImage<'_, '_>) -> Result<Image> {
+    let (data, offset) = image.image_data_and_offset()?;
+    let header = MachHeader64::<Endianness>::parse(data, offset).context("not a 64-bit image")?;
+    let endian = header.endian()?;
+
+    let object = image.parse_object()?;
+    let uuid = object
+        .mach_uuid()?
+        .ok_or_else(|| anyhow!("missing LC_UUID"))?;
+    let text = object
+        .segments()
+        .find(|segment| matches!(segment.name(), Ok(Some("__TEXT"))))
+        .ok_or_else(|| anyhow!("missing __TEXT segment"))?;
+    let text = text.address()..text.address() + text.size();
+
+    let mut symbols = BTreeMap::new();
+    for symbol in object.symbols() {
+        if symbol.kind() != SymbolKind::Text
+            || !symbol.is_definition()
+            || !text.contains(&symbol.address())
+        {
+            continue;
+        }
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        // C symbols carry a leading underscore in Mach-O files.
+        let name = name.strip_prefix('_').unwrap_or(name);
+        if !name.is_empty() && name != REDACTED {
+            symbols
+                .entry(symbol.address() - text.start)
+                .or_insert_with(|| name.to_owned());
+        }
+    }
+
+    Ok(Image {
+        path: path.to_owned(),
+        uuid: Uuid::from_bytes(uuid),
+        arch: Arch::from_mach(
+            header.cputype(endian),
+            header.cpusubtype(endian) & 0x00ff_ffff,
+        ),
+        symbols,
+    })
+}
+
+/// An image of the dyld shared cache along with its symbols.
+#[derive(Debug)]
+pub struct Image {
+    /// The install path of the image, such as `/usr/lib/libobjc.A.dylib`.
+    pub path: String,
+    uuid: Uuid,
+    arch: Arch,
+    /// The names of the functions in the image, keyed by their offset from the image base.
+    symbols: BTreeMap<u64, String>,
+}
+
+impl Image {
+    /// Writes the symbols of the image as a Breakpad symbol file.
+    pub fn to_breakpad(&self) -> String {
+        let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        let arch = self.arch;
+        let debug_id = DebugId::from_uuid(self.uuid).breakpad();
+        let code_id = CodeId::from_binary(self.uuid.as_bytes());
+
+        let mut out = String::new();
+        writeln!(out, "MODULE mac {arch} {debug_id} {name}").ok();
+        writeln!(out, "INFO CODE_ID {code_id} {}", self.path).ok();
+        for (offset, name) in &self.symbols {
+            writeln!(out, "PUBLIC {offset:x} 0 {name}").ok();
+        }
+        out
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_images() {
+        // A single-file cache of one x86_64 image, whose symbol table contains two functions, a
+        // redacted one and an undefined one.
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/fixtures/dyld/dyld_shared_cache_x86_64");
+        let data = ByteView::open(&path).unwrap();
+        assert!(is_dyld_cache(&data));
+
+        let images = read_images(&path, &data).unwrap().unwrap();
+        assert_eq!(images.len(), 1);
+
+        let image = images.into_iter().next().unwrap().unwrap();
+        assert_eq!(image.path, "/usr/lib/libfixture.dylib");
+        assert_eq!(
+            image.to_breakpad(),
+            "MODULE mac x86_64 0123456789ABCDEF0123456789ABCDEF0 libfixture.dylib\n\
+             INFO CODE_ID 0123456789abcdef0123456789abcdef /usr/lib/libfixture.dylib\n\
+             PUBLIC 400 0 fixture_main\n\
+             PUBLIC 480 0 fixture_helper\n"
+        );
+    }
+}
@@ -12,6 +12,7 @@ mod utils;
 
 mod app;
 mod config;
+mod dyld_cache;
 
 fn main() {
     app::main();
@@ -44,6 +44,7 @@ pub fn get_target_filename(obj: &Object) -> Result<PathBuf> {
     let id = get_unified_id(obj)?;
     // match the unified format here.
     let suffix = match obj.kind() {
+        ObjectKind::Debug if obj.file_format() == FileFormat::Breakpad => "breakpad",
         ObjectKind::Debug => "debuginfo",
         ObjectKind::Sources if obj.file_format() == FileFormat::SourceBundle => "sourcebundle",
         ObjectKind::Relocatable | ObjectKind::Library | ObjectKind::Executable => "executable",